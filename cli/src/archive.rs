@@ -0,0 +1,325 @@
+//! Portable archives containing all the files of an inventory.
+//!
+//! An archive is a gzip compressed tar file. It contains a `manifest.json` file
+//! and the parts of the inventory that existed when it was created, stored without
+//! the inventory name prefix (`types.json`, `instances.json`, `snapshots/...`). The files of
+//! an encrypted inventory are archived as they are, encrypted.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, remove_dir_all, remove_file, File};
+use std::io::{Error, ErrorKind, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// The version of the archive format written by this program.
+pub const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+
+/// The files and directories making up an inventory.
+/// They are stored in the working directory as `<inventory name>_<part>`.
+pub const PARTS: &[&str] = &[
+    "types.json",
+    "instances.json",
+    "types.json.enc",
+    "instances.json.enc",
+    "classes.json",
+    "config.json",
+    "events.json",
+    "events.json.enc",
+    "snapshots",
+    "attachments",
+];
+
+/// Describes the content of an archive.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub format_version: u32,
+    pub inventory_name: String,
+    /// RFC 3339 timestamp of the creation of the archive.
+    pub created_at: String,
+}
+
+/// Writes the existing parts of the inventory into a new archive.
+/// Returns the parts that were included.
+pub fn export(
+    workdir: &Path,
+    name: &str,
+    archive: &Path,
+    now: SystemTime,
+) -> Result<Vec<&'static str>, Error> {
+    let file = File::create(archive).map_err(|e| with_path(e, archive))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    let manifest = serde_json::to_vec_pretty(&Manifest {
+        format_version: FORMAT_VERSION,
+        inventory_name: name.to_string(),
+        created_at: humantime::format_rfc3339(now).to_string(),
+    })?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST, manifest.as_slice())?;
+
+    let mut included = vec![];
+    for part in PARTS {
+        let path = part_path(workdir, name, part);
+        if path.is_dir() {
+            builder.append_dir_all(part, &path)?;
+        } else if path.is_file() {
+            builder.append_path_with_name(&path, part)?;
+        } else {
+            continue;
+        }
+        included.push(*part);
+    }
+    builder.into_inner()?.finish()?;
+    Ok(included)
+}
+
+/// Reads the manifest of an archive and the parts it contains.
+pub fn inspect(archive: &Path) -> Result<(Manifest, Vec<&'static str>), Error> {
+    let mut manifest = None;
+    let mut parts = vec![];
+    let in_archive = |e| with_path(e, archive);
+    let mut tar = open(archive)?;
+    for entry in tar.entries().map_err(in_archive)? {
+        let mut entry = entry.map_err(in_archive)?;
+        let path = entry.path().map_err(in_archive)?.into_owned();
+        if path == Path::new(MANIFEST) {
+            let mut data = vec![];
+            entry.read_to_end(&mut data).map_err(in_archive)?;
+            manifest =
+                Some(serde_json::from_slice::<Manifest>(&data).map_err(|e| in_archive(e.into()))?);
+        } else if let Some(part) = part_of(&path) {
+            if !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+    }
+    let manifest = manifest.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{}: the archive has no manifest", archive.display()),
+        )
+    })?;
+    if manifest.format_version > FORMAT_VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{}: unsupported archive format version {}, the latest supported version is {}",
+                archive.display(),
+                manifest.format_version,
+                FORMAT_VERSION
+            ),
+        ));
+    }
+    Ok((manifest, parts))
+}
+
+/// Extracts the parts contained in the archive into the inventory with the specified name.
+/// Refuses to replace existing parts of the inventory unless `force` is set.
+/// Returns the parts that were imported.
+pub fn import(
+    workdir: &Path,
+    name: &str,
+    archive: &Path,
+    force: bool,
+) -> Result<Vec<&'static str>, Error> {
+    let (_, parts) = inspect(archive)?;
+    let existing = parts
+        .iter()
+        .flat_map(|part| std::iter::once(*part).chain(counterpart(part)))
+        .map(|part| part_path(workdir, name, part))
+        .filter(|path| path.exists())
+        .collect::<Vec<_>>();
+    if !existing.is_empty() {
+        if !force {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "the inventory \"{}\" already exists, use --force to replace it",
+                    name
+                ),
+            ));
+        }
+        for path in existing {
+            if path.is_dir() {
+                remove_dir_all(&path).map_err(|e| with_path(e, &path))?;
+            } else {
+                remove_file(&path).map_err(|e| with_path(e, &path))?;
+            }
+        }
+    }
+
+    let mut tar = open(archive)?;
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let part = match part_of(&path) {
+            Some(part) => part,
+            None => {
+                if path != Path::new(MANIFEST) {
+                    warn!("Ignoring unknown archive entry {}", path.display());
+                }
+                continue;
+            }
+        };
+        let rest = path
+            .strip_prefix(part)
+            .expect("Entry path starts with its part");
+        let mut target = part_path(workdir, name, part);
+        if !rest.as_os_str().is_empty() {
+            target.push(rest);
+        }
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent).map_err(|e| with_path(e, parent))?;
+        }
+        entry.unpack(&target).map_err(|e| with_path(e, &target))?;
+    }
+    Ok(parts)
+}
+
+fn open(archive: &Path) -> Result<tar::Archive<GzDecoder<File>>, Error> {
+    let file = File::open(archive).map_err(|e| with_path(e, archive))?;
+    Ok(tar::Archive::new(GzDecoder::new(file)))
+}
+
+/// The encrypted version of a plaintext part, or the other way around, which an imported
+/// part replaces too.
+fn counterpart(part: &str) -> Option<&'static str> {
+    match part {
+        "types.json" => Some("types.json.enc"),
+        "instances.json" => Some("instances.json.enc"),
+        "types.json.enc" => Some("types.json"),
+        "instances.json.enc" => Some("instances.json"),
+        "events.json" => Some("events.json.enc"),
+        "events.json.enc" => Some("events.json"),
+        _ => None,
+    }
+}
+
+fn part_path(workdir: &Path, name: &str, part: &str) -> PathBuf {
+    workdir.join(format!("{}_{}", name, part))
+}
+
+/// Returns the part an archive entry belongs to.
+/// Entries escaping their part directory are never part of the inventory.
+fn part_of(path: &Path) -> Option<&'static str> {
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    let first = path.components().next()?.as_os_str();
+    PARTS.iter().find(|part| first == **part).copied()
+}
+
+fn with_path(e: Error, path: &Path) -> Error {
+    Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{read_to_string, write};
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("inv_archive_{}_{}", test, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = temp_dir("round_trip");
+        let source = dir.join("source");
+        create_dir_all(source.join("home_snapshots")).unwrap();
+        write(source.join("home_types.json"), "[]").unwrap();
+        write(source.join("home_instances.json"), "[1]").unwrap();
+        write(source.join("home_snapshots/a.json"), "snapshot").unwrap();
+        write(source.join("other_types.json"), "other").unwrap();
+        let archive = dir.join("home.tar.gz");
+
+        let exported = export(&source, "home", &archive, SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!(exported, vec!["types.json", "instances.json", "snapshots"]);
+        let (manifest, parts) = inspect(&archive).unwrap();
+        assert_eq!(manifest.format_version, FORMAT_VERSION);
+        assert_eq!(manifest.inventory_name, "home");
+        assert_eq!(manifest.created_at, "1970-01-01T00:00:00Z");
+        assert_eq!(parts, exported);
+
+        let target = dir.join("target");
+        create_dir_all(&target).unwrap();
+        import(&target, "copy", &archive, false).unwrap();
+        assert_eq!(
+            read_to_string(target.join("copy_types.json")).unwrap(),
+            "[]"
+        );
+        assert_eq!(
+            read_to_string(target.join("copy_instances.json")).unwrap(),
+            "[1]"
+        );
+        assert_eq!(
+            read_to_string(target.join("copy_snapshots/a.json")).unwrap(),
+            "snapshot"
+        );
+        assert!(!target.join("copy_other_types.json").exists());
+    }
+
+    #[test]
+    fn import_refuses_to_overwrite_without_force() {
+        let dir = temp_dir("overwrite");
+        write(dir.join("home_types.json"), "[]").unwrap();
+        let archive = dir.join("home.tar.gz");
+        export(&dir, "home", &archive, inv_manager::current_time()).unwrap();
+        write(dir.join("home_types.json"), "[\"changed\"]").unwrap();
+        write(dir.join("home_types.json.enc"), "encrypted").unwrap();
+
+        let err = import(&dir, "home", &archive, false).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        import(&dir, "home", &archive, true).unwrap();
+        assert_eq!(read_to_string(dir.join("home_types.json")).unwrap(), "[]");
+        assert!(!dir.join("home_types.json.enc").exists());
+    }
+
+    #[test]
+    fn newer_format_versions_are_rejected() {
+        let dir = temp_dir("version");
+        let archive = dir.join("future.tar.gz");
+        let file = File::create(&archive).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let manifest = serde_json::to_vec(&Manifest {
+            format_version: FORMAT_VERSION + 1,
+            inventory_name: "home".to_string(),
+            created_at: "2020-01-01T00:00:00Z".to_string(),
+        })
+        .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, MANIFEST, manifest.as_slice())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let err = import(&dir, "home", &archive, true).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn entries_outside_of_parts_are_ignored() {
+        assert_eq!(part_of(Path::new("types.json")), Some("types.json"));
+        assert_eq!(part_of(Path::new("snapshots/a.json")), Some("snapshots"));
+        assert_eq!(part_of(Path::new("snapshots/../../etc")), None);
+        assert_eq!(part_of(Path::new("/types.json")), None);
+        assert_eq!(part_of(Path::new("unknown.json")), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Saving for modes that keep the inventory in memory and change it many times in a row,
+//! like the repl subcommand.
+//!
+//! Saving writes every file of the inventory, so such modes save at most once per interval
+//! instead of after every change. They call `tick` regularly, even while waiting for input,
+//! so a change reaches the disk at most one interval after it was made: that is what can be
+//! lost if the process crashes or is killed with SIGKILL. On a clean exit, including SIGINT
+//! and SIGTERM, which the mode catches, see `repl::stop_on_signals`, it calls `shutdown`,
+//! which saves what is pending.
+
+use crate::error::CliError;
+use inv_manager::Inventory;
+use std::time::{Duration, Instant};
+
+/// How long changes wait to be saved by default.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Saves the inventory with `save` at most once per interval, measured with the monotonic
+/// clock of the system rather than the clock of the inventory, which can be frozen.
+pub struct SaveManager<F> {
+    interval: Duration,
+    save: F,
+    last_save: Option<Instant>,
+    pending: bool,
+}
+
+impl<F: FnMut(&Inventory) -> Result<(), CliError>> SaveManager<F> {
+    pub fn new(interval: Duration, save: F) -> Self {
+        SaveManager {
+            interval,
+            save,
+            last_save: None,
+            pending: false,
+        }
+    }
+
+    /// Whether some changes are not saved yet.
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Records that the inventory changed, and saves it if the last save is at least an
+    /// interval old. Returns whether it saved.
+    pub fn changed(&mut self, inventory: &Inventory) -> Result<bool, CliError> {
+        self.changed_at(inventory, Instant::now())
+    }
+
+    /// Saves the pending changes once an interval passed since the last save. Returns
+    /// whether it saved.
+    pub fn tick(&mut self, inventory: &Inventory) -> Result<bool, CliError> {
+        self.tick_at(inventory, Instant::now())
+    }
+
+    /// Saves right away, for example when the user asks to. The changes stay pending if
+    /// the save fails.
+    pub fn save_now(&mut self, inventory: &Inventory) -> Result<(), CliError> {
+        self.save_at(inventory, Instant::now())
+    }
+
+    fn changed_at(&mut self, inventory: &Inventory, now: Instant) -> Result<bool, CliError> {
+        self.pending = true;
+        self.tick_at(inventory, now)
+    }
+
+    fn tick_at(&mut self, inventory: &Inventory, now: Instant) -> Result<bool, CliError> {
+        if !self.pending {
+            return Ok(false);
+        }
+        let due = self
+            .last_save
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.interval);
+        if due {
+            self.save_at(inventory, now)?;
+        }
+        Ok(due)
+    }
+
+    fn save_at(&mut self, inventory: &Inventory, now: Instant) -> Result<(), CliError> {
+        (self.save)(inventory)?;
+        self.pending = false;
+        self.last_save = Some(now);
+        Ok(())
+    }
+
+    /// Forgets the pending changes, for example after loading the inventory again.
+    pub fn discard(&mut self) {
+        self.pending = false;
+    }
+
+    /// Saves the pending changes before exiting.
+    pub fn shutdown(mut self, inventory: &Inventory) -> Result<(), CliError> {
+        if self.pending {
+            self.save_now(inventory)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Operation;
+    use inv_manager::FixedClock;
+    use std::cell::Cell;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    /// An inventory whose clock is frozen, like with IMGOAT_FAKE_NOW, which doesn't delay
+    /// the saves.
+    fn inventory() -> Inventory {
+        let frozen = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(frozen)));
+        inventory
+    }
+
+    #[test]
+    fn saves_are_debounced() {
+        let inventory = inventory();
+        let start = Instant::now();
+        let saves = Cell::new(0);
+        let mut saver = SaveManager::new(DEFAULT_INTERVAL, |_: &Inventory| {
+            saves.set(saves.get() + 1);
+            Ok(())
+        });
+
+        assert!(!saver.tick_at(&inventory, start).unwrap());
+        assert!(saver.changed_at(&inventory, start).unwrap());
+        let later = start + Duration::from_millis(500);
+        assert!(!saver.changed_at(&inventory, later).unwrap());
+        assert!(!saver.changed_at(&inventory, later).unwrap());
+        assert!(saver.is_pending());
+        let later = start + Duration::from_millis(1999);
+        assert!(!saver.tick_at(&inventory, later).unwrap());
+        assert!(saver.tick_at(&inventory, start + DEFAULT_INTERVAL).unwrap());
+        assert!(!saver.is_pending());
+        assert!(!saver.tick_at(&inventory, start + DEFAULT_INTERVAL).unwrap());
+        assert_eq!(saves.get(), 2);
+
+        let later = start + DEFAULT_INTERVAL * 2;
+        assert!(saver.changed_at(&inventory, later).unwrap());
+        assert!(!saver.changed_at(&inventory, later).unwrap());
+        saver.save_now(&inventory).unwrap();
+        assert_eq!(saves.get(), 4);
+    }
+
+    #[test]
+    fn pending_changes_are_saved_on_shutdown() {
+        let inventory = inventory();
+        let saves = Cell::new(0);
+        let save = |_: &Inventory| {
+            saves.set(saves.get() + 1);
+            Ok(())
+        };
+        let mut saver = SaveManager::new(DEFAULT_INTERVAL, save);
+        saver.changed(&inventory).unwrap();
+        saver.changed(&inventory).unwrap();
+        saver.shutdown(&inventory).unwrap();
+        assert_eq!(saves.get(), 2);
+
+        let saver = SaveManager::new(DEFAULT_INTERVAL, save);
+        saver.shutdown(&inventory).unwrap();
+        assert_eq!(saves.get(), 2);
+    }
+
+    #[test]
+    fn failed_saves_stay_pending() {
+        let inventory = inventory();
+        let fail = Cell::new(true);
+        let mut saver = SaveManager::new(DEFAULT_INTERVAL, |_: &Inventory| {
+            if fail.get() {
+                Err(CliError::io(
+                    Operation::Write,
+                    Path::new("home_types.json"),
+                    std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+                ))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(saver.changed(&inventory).is_err());
+        assert!(saver.is_pending());
+        fail.set(false);
+        assert!(saver.tick(&inventory).unwrap());
+        assert!(!saver.is_pending());
+    }
+}
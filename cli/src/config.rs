@@ -0,0 +1,92 @@
+//! The settings of an inventory, `<name>_config.json`, a json object whose fields are all
+//! optional. It holds the weights of the health score, the keyfile of an encrypted
+//! inventory and the hook run after the changes, see `hooks`:
+//!
+//! ```json
+//! {"health_weights": {"type_unit": 0, "instance_value": 2}, "keyfile": "home.key",
+//!  "post_mutation_hook": "commit.sh", "hook_failure": "error"}
+//! ```
+
+use crate::error::{CliError, Operation};
+use crate::hooks::{Hook, HookFailure, DEFAULT_TIMEOUT_SECS};
+use inv_manager::HealthWeights;
+use serde::{Deserialize, Serialize};
+use std::fs::read;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// How much each measure counts in the score of `health`, see `HealthWeights`.
+    pub health_weights: HealthWeights,
+    /// The file holding the passphrase of the encrypted inventory, relative to the working
+    /// directory, see `crypto`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyfile: Option<PathBuf>,
+    /// The program run after the commands changing the inventory, relative to the working
+    /// directory, see `hooks`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_mutation_hook: Option<PathBuf>,
+    /// Whether a failing hook is a warning or makes the command fail.
+    pub hook_failure: HookFailure,
+    /// How many seconds the hook can run before it is killed, 30 when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook_timeout_secs: Option<u64>,
+}
+
+impl Config {
+    /// The hook to run after the commands changing the inventory, if any.
+    pub fn hook(&self, workdir: &Path) -> Option<Hook> {
+        self.post_mutation_hook.as_ref().map(|program| Hook {
+            program: workdir.join(program),
+            dir: workdir.to_path_buf(),
+            timeout: Duration::from_secs(self.hook_timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS)),
+        })
+    }
+}
+
+/// The settings file of the inventory with this name.
+pub fn config_path(workdir: &Path, name: &str) -> PathBuf {
+    workdir.join(format!("{}_config.json", name))
+}
+
+/// Reads the settings, which are all the defaults when the file doesn't exist.
+pub fn load(path: &Path) -> Result<Config, CliError> {
+    match read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| CliError::io(Operation::Parse, path, e.into())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Config::default()),
+        Err(e) => Err(CliError::io(Operation::Read, path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{remove_file, write};
+
+    #[test]
+    fn missing_settings_take_their_default() {
+        let path = std::env::temp_dir().join(format!("inv_config_{}.json", std::process::id()));
+        assert_eq!(load(&path).unwrap(), Config::default());
+        write(&path, r#"{"health_weights": {"type_unit": 0}}"#).unwrap();
+        let config = load(&path).unwrap();
+        assert_eq!(config.health_weights.type_unit, 0.0);
+        assert_eq!(config.health_weights.type_ttl, 1.0);
+        write(
+            &path,
+            r#"{"post_mutation_hook": "sync.sh", "hook_failure": "error"}"#,
+        )
+        .unwrap();
+        let config = load(&path).unwrap();
+        assert_eq!(config.hook_failure, HookFailure::Error);
+        let hook = config.hook(Path::new("/srv/inventory")).unwrap();
+        assert_eq!(hook.program, PathBuf::from("/srv/inventory/sync.sh"));
+        assert_eq!(hook.timeout, Duration::from_secs(DEFAULT_TIMEOUT_SECS));
+        write(&path, r#"{"health_weights": {"type_unit": "none"}}"#).unwrap();
+        assert!(load(&path).is_err());
+        remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,379 @@
+//! Encryption at rest of the files of an inventory: the types and instances files,
+//! `<name>_types.json.enc` and `<name>_instances.json.enc`, the event log,
+//! `<name>_events.json.enc`, and the state file, `<name>_state.json.enc`.
+//!
+//! An encrypted file is a line naming the format followed by a line with, in hexadecimal,
+//! the salt of the key, the nonce and the encrypted json with its authentication tag:
+//!
+//! ```text
+//! inventory-managoat encrypted 1
+//! 5f0c...
+//! ```
+//!
+//! The key is derived from a passphrase with Argon2id, and the json is encrypted with
+//! XChaCha20-Poly1305, so a wrong passphrase or a damaged file is detected before anything
+//! is read. The files are text so that they go through the journal of `storage` as they are:
+//! the temporary files and the journal of an encrypted inventory are encrypted too.
+//!
+//! The event log, which is only appended to, is encrypted line by line: after the first
+//! line, each line is an event encrypted like the json of the other files.
+//!
+//! The passphrase is the value of `INVENTORY_MANAGOAT_PASSPHRASE`, otherwise the content of
+//! the `keyfile` of the settings, otherwise it is asked on the terminal. It is asked once
+//! per run.
+
+use crate::config;
+use crate::error::{CliError, Operation};
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// The suffix added to the name of the encrypted files.
+pub const EXTENSION: &str = ".enc";
+
+/// The environment variable holding the passphrase.
+pub const PASSPHRASE_VAR: &str = "INVENTORY_MANAGOAT_PASSPHRASE";
+
+/// The first line of the encrypted files.
+pub const MAGIC: &str = "inventory-managoat encrypted 1";
+
+/// Whether the file is an encrypted one, going by its name.
+pub fn is_encrypted(path: &Path) -> bool {
+    path.to_str().is_some_and(|p| p.ends_with(EXTENSION))
+}
+
+/// The encrypted version of a plaintext file.
+pub fn encrypted_path(path: &Path) -> PathBuf {
+    let mut encrypted = path.as_os_str().to_owned();
+    encrypted.push(EXTENSION);
+    PathBuf::from(encrypted)
+}
+
+/// The plaintext version of an encrypted file.
+pub fn plaintext_path(path: &Path) -> PathBuf {
+    match path.to_str().and_then(|p| p.strip_suffix(EXTENSION)) {
+        Some(plaintext) => PathBuf::from(plaintext),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Whether the content of a file is encrypted, whatever its name.
+pub fn looks_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC.as_bytes())
+}
+
+static CIPHER: OnceLock<Cipher> = OnceLock::new();
+
+/// The cipher of the inventory whose settings are at this path, getting the passphrase the
+/// first time. A new passphrase is asked twice when `confirm` is set.
+pub fn cipher(config_path: &Path, confirm: bool) -> Result<&'static Cipher, CliError> {
+    if let Some(cipher) = CIPHER.get() {
+        return Ok(cipher);
+    }
+    let cipher = Cipher::new(passphrase(config_path, confirm)?)?;
+    Ok(CIPHER.get_or_init(|| cipher))
+}
+
+/// Finds the passphrase, see the module documentation.
+fn passphrase(config_path: &Path, confirm: bool) -> Result<String, CliError> {
+    if let Some(passphrase) = std::env::var(PASSPHRASE_VAR).ok().filter(|p| !p.is_empty()) {
+        return Ok(passphrase);
+    }
+    if let Some(keyfile) = config::load(config_path)?.keyfile {
+        let keyfile = match config_path.parent() {
+            Some(workdir) => workdir.join(keyfile),
+            None => keyfile,
+        };
+        let passphrase = read_to_string(&keyfile)
+            .map_err(|e| CliError::io(Operation::Read, &keyfile, e))?
+            .trim_end_matches(['\r', '\n'])
+            .to_string();
+        if passphrase.is_empty() {
+            return Err(CliError::Encryption(format!(
+                "the keyfile {} is empty",
+                keyfile.display()
+            )));
+        }
+        return Ok(passphrase);
+    }
+    if !crate::prompt::is_interactive() {
+        return Err(CliError::Encryption(format!(
+            "the inventory is encrypted, set {} or a keyfile in {} to give its passphrase",
+            PASSPHRASE_VAR,
+            config_path.display()
+        )));
+    }
+    ask_passphrase(confirm)
+}
+
+#[cfg(feature = "encryption")]
+fn ask_passphrase(confirm: bool) -> Result<String, CliError> {
+    let ask = |prompt: &str| {
+        rpassword::prompt_password(prompt)
+            .map_err(|e| CliError::io(Operation::Read, Path::new("<terminal>"), e))
+    };
+    let passphrase = ask("Passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(CliError::Encryption("the passphrase is empty".to_string()));
+    }
+    if confirm && ask("Passphrase again: ")? != passphrase {
+        return Err(CliError::Encryption(
+            "the passphrases are different".to_string(),
+        ));
+    }
+    Ok(passphrase)
+}
+
+#[cfg(not(feature = "encryption"))]
+fn ask_passphrase(_confirm: bool) -> Result<String, CliError> {
+    Err(unsupported())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn unsupported() -> CliError {
+    CliError::Encryption(
+        "this build of inv can't read nor write encrypted inventories, it was built without the encryption feature"
+            .to_string(),
+    )
+}
+
+#[cfg(feature = "encryption")]
+pub use self::sealed::Cipher;
+
+#[cfg(feature = "encryption")]
+mod sealed {
+    use super::MAGIC;
+    use crate::error::CliError;
+    use argon2::Argon2;
+    use chacha20poly1305::aead::rand_core::RngCore;
+    use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+    use std::fmt::Write;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    const SALT_LEN: usize = 16;
+    const NONCE_LEN: usize = 24;
+
+    /// Encrypts and decrypts files with the key derived from a passphrase.
+    pub struct Cipher {
+        passphrase: String,
+        /// The last salt used and its key, so that the key is derived once per run.
+        key: Mutex<Option<([u8; SALT_LEN], XChaCha20Poly1305)>>,
+    }
+
+    impl Cipher {
+        pub fn new(passphrase: String) -> Result<Self, CliError> {
+            Ok(Cipher {
+                passphrase,
+                key: Mutex::new(None),
+            })
+        }
+
+        /// Runs `f` with the key of the salt, deriving it if it isn't the last one used.
+        fn with_key<T>(
+            &self,
+            salt: Option<[u8; SALT_LEN]>,
+            f: impl FnOnce(&[u8; SALT_LEN], &XChaCha20Poly1305) -> T,
+        ) -> Result<T, CliError> {
+            let mut key = self.key.lock().expect("The key is never poisoned");
+            let reuse = match (&*key, salt) {
+                (Some((last, _)), Some(salt)) => *last == salt,
+                (Some(_), None) => true,
+                (None, _) => false,
+            };
+            if !reuse {
+                let salt = salt.unwrap_or_else(|| {
+                    let mut salt = [0; SALT_LEN];
+                    OsRng.fill_bytes(&mut salt);
+                    salt
+                });
+                let mut bytes = [0; 32];
+                Argon2::default()
+                    .hash_password_into(self.passphrase.as_bytes(), &salt, &mut bytes)
+                    .map_err(|e| CliError::Encryption(e.to_string()))?;
+                *key = Some((salt, XChaCha20Poly1305::new(&bytes.into())));
+            }
+            let (salt, cipher) = key.as_ref().expect("The key was just derived");
+            Ok(f(salt, cipher))
+        }
+
+        /// Encrypts the content of a file.
+        pub fn seal(&self, plaintext: &str) -> Result<String, CliError> {
+            Ok(format!("{}\n{}\n", MAGIC, self.seal_line(plaintext, None)?))
+        }
+
+        /// Encrypts a line of a file encrypted line by line, like the event log, giving the
+        /// hexadecimal line without the newline. The key is the one of `like`, another line
+        /// of the file, so that it is derived once for the whole file.
+        pub fn seal_line(&self, plaintext: &str, like: Option<&str>) -> Result<String, CliError> {
+            let like = like
+                .and_then(|line| hex_bytes(line.get(..2 * SALT_LEN)?))
+                .map(|salt| {
+                    let mut salt_bytes = [0; SALT_LEN];
+                    salt_bytes.copy_from_slice(&salt);
+                    salt_bytes
+                });
+            let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let (salt, ciphertext) = self.with_key(like, |salt, cipher| {
+                (*salt, cipher.encrypt(&nonce, plaintext.as_bytes()))
+            })?;
+            let ciphertext =
+                ciphertext.map_err(|_| CliError::Encryption("failed to encrypt".to_string()))?;
+            let mut sealed = String::new();
+            for byte in salt.iter().chain(&nonce).chain(&ciphertext) {
+                write!(sealed, "{:02x}", byte).expect("Writing to a string never fails");
+            }
+            Ok(sealed)
+        }
+
+        /// Decrypts the content of the file at the path, failing when the passphrase is wrong
+        /// or the file was changed.
+        pub fn open(&self, path: &Path, data: &[u8]) -> Result<String, CliError> {
+            let text = std::str::from_utf8(data)
+                .map_err(|_| CliError::WrongPassphrase(path.to_path_buf()))?;
+            let hex = text.strip_prefix(MAGIC).ok_or_else(|| {
+                CliError::Encryption(format!("{} is not an encrypted file", path.display()))
+            })?;
+            self.open_line(path, hex.trim())
+        }
+
+        /// Decrypts a line of a file encrypted line by line, see `seal_line`.
+        pub fn open_line(&self, path: &Path, line: &str) -> Result<String, CliError> {
+            let wrong = || CliError::WrongPassphrase(path.to_path_buf());
+            if line.len() < 2 * (SALT_LEN + NONCE_LEN) {
+                return Err(wrong());
+            }
+            let bytes = hex_bytes(line).ok_or_else(wrong)?;
+            let (salt, rest) = bytes.split_at(SALT_LEN);
+            let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+            let mut salt_bytes = [0; SALT_LEN];
+            salt_bytes.copy_from_slice(salt);
+            let plaintext = self
+                .with_key(Some(salt_bytes), |_, cipher| {
+                    cipher.decrypt(XNonce::from_slice(nonce), ciphertext)
+                })?
+                .map_err(|_| wrong())?;
+            String::from_utf8(plaintext).map_err(|_| wrong())
+        }
+    }
+
+    /// The bytes written in hexadecimal, `None` if it isn't hexadecimal.
+    fn hex_bytes(hex: &str) -> Option<Vec<u8>> {
+        if !hex.len().is_multiple_of(2) {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+}
+
+#[cfg(not(feature = "encryption"))]
+pub use self::unsupported::Cipher;
+
+#[cfg(not(feature = "encryption"))]
+mod unsupported {
+    use crate::error::CliError;
+    use std::path::Path;
+
+    /// Encrypts and decrypts files, which this build can't do.
+    pub struct Cipher;
+
+    impl Cipher {
+        pub fn new(_passphrase: String) -> Result<Self, CliError> {
+            Err(super::unsupported())
+        }
+
+        pub fn seal(&self, _plaintext: &str) -> Result<String, CliError> {
+            Err(super::unsupported())
+        }
+
+        pub fn seal_line(&self, _plaintext: &str, _like: Option<&str>) -> Result<String, CliError> {
+            Err(super::unsupported())
+        }
+
+        pub fn open(&self, _path: &Path, _data: &[u8]) -> Result<String, CliError> {
+            Err(super::unsupported())
+        }
+
+        pub fn open_line(&self, _path: &Path, _line: &str) -> Result<String, CliError> {
+            Err(super::unsupported())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_sealed_and_opened_with_the_same_passphrase() {
+        let path = Path::new("home_types.json.enc");
+        let cipher = Cipher::new("correct horse".to_string()).unwrap();
+        let sealed = cipher.seal("{\"items\": []}").unwrap();
+        assert!(looks_encrypted(sealed.as_bytes()));
+        assert!(!sealed.contains("items"));
+        assert_ne!(cipher.seal("{\"items\": []}").unwrap(), sealed);
+        assert_eq!(
+            cipher.open(path, sealed.as_bytes()).unwrap(),
+            "{\"items\": []}"
+        );
+
+        let other = Cipher::new("correct horse".to_string()).unwrap();
+        assert_eq!(
+            other.open(path, sealed.as_bytes()).unwrap(),
+            "{\"items\": []}"
+        );
+
+        let wrong = Cipher::new("battery staple".to_string()).unwrap();
+        assert!(matches!(
+            wrong.open(path, sealed.as_bytes()),
+            Err(CliError::WrongPassphrase(_))
+        ));
+        let mut damaged = sealed.into_bytes();
+        let last = damaged.len() - 2;
+        damaged[last] = if damaged[last] == b'0' { b'1' } else { b'0' };
+        assert!(matches!(
+            cipher.open(path, &damaged),
+            Err(CliError::WrongPassphrase(_))
+        ));
+        assert!(matches!(
+            cipher.open(path, b"{}"),
+            Err(CliError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn lines_are_sealed_with_the_key_of_another_line() {
+        let path = Path::new("home_events.json.enc");
+        let cipher = Cipher::new("correct horse".to_string()).unwrap();
+        let first = cipher.seal_line("{\"kind\":\"checkpoint\"}", None).unwrap();
+        let other = Cipher::new("correct horse".to_string()).unwrap();
+        let second = other
+            .seal_line("{\"kind\":\"changes\"}", Some(&first))
+            .unwrap();
+        assert!(!first.contains('\n') && !second.contains("changes"));
+        assert_eq!(first[..32], second[..32]);
+        assert_eq!(
+            cipher.open_line(path, &second).unwrap(),
+            "{\"kind\":\"changes\"}"
+        );
+        assert!(matches!(
+            cipher.open_line(path, &second[..second.len() - 3]),
+            Err(CliError::WrongPassphrase(_))
+        ));
+    }
+
+    #[test]
+    fn encrypted_files_are_named_after_the_plaintext_ones() {
+        let plaintext = Path::new("/inv/home_types.json");
+        let encrypted = encrypted_path(plaintext);
+        assert_eq!(encrypted, Path::new("/inv/home_types.json.enc"));
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(plaintext));
+        assert_eq!(plaintext_path(&encrypted), plaintext);
+        assert_eq!(plaintext_path(plaintext), plaintext);
+    }
+}
@@ -0,0 +1,212 @@
+//! Errors of the command line interface, naming the file they happened on.
+
+use crate::migrations::MigrationError;
+use inv_manager::{InstanceId, TypeId};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// What was being done to a file when an error happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    CreateDir,
+    Read,
+    Parse,
+    Write,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    /// The working directory exists but is not a directory.
+    NotADirectory(PathBuf),
+    /// The inventory files use some ids more than once.
+    DuplicateIds {
+        type_ids: Vec<TypeId>,
+        instance_ids: Vec<InstanceId>,
+    },
+    /// The inventory file can not be upgraded to the current format.
+    Format(PathBuf, MigrationError),
+    /// A save was interrupted and its journal can not be used to finish it.
+    InterruptedSave { journal: PathBuf, reason: String },
+    /// The command would change an inventory read from a published file.
+    Published(PathBuf),
+    /// An encrypted file can not be decrypted with the passphrase, see `crypto`.
+    WrongPassphrase(PathBuf),
+    /// The inventory has both plaintext and encrypted files.
+    MixedEncryption {
+        plaintext: Vec<PathBuf>,
+        encrypted: Vec<PathBuf>,
+    },
+    /// The inventory can not be encrypted or decrypted.
+    Encryption(String),
+    /// The chosen profile is not in the profiles file, see `profiles`.
+    UnknownProfile { name: String, known: Vec<String> },
+    /// `IMGOAT_FAKE_NOW` is set to something else than a time.
+    FakeNow(String),
+    /// The hook run after the changes failed, with `"hook_failure": "error"`, see `hooks`.
+    Hook { program: PathBuf, reason: String },
+    /// An archive can not be exported or imported, see `archive`. The error names the file.
+    Archive(io::Error),
+    Io {
+        operation: Operation,
+        path: PathBuf,
+        source: io::Error,
+    },
+}
+
+impl CliError {
+    pub fn io(operation: Operation, path: &Path, source: io::Error) -> Self {
+        CliError::Io {
+            operation,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Whether the error comes from a file or directory that can not be written to.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            CliError::Published(_) => true,
+            CliError::Io {
+                operation: Operation::CreateDir | Operation::Write,
+                source,
+                ..
+            } => {
+                // EROFS has no ErrorKind on the supported rust versions.
+                source.kind() == io::ErrorKind::PermissionDenied
+                    || (cfg!(unix) && source.raw_os_error() == Some(30))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::NotADirectory(path) => write!(
+                f,
+                "{} is not a directory, use --workdir to choose another working directory",
+                path.display()
+            ),
+            CliError::DuplicateIds {
+                type_ids,
+                instance_ids,
+            } => {
+                fn list<I: ToString>(ids: &[I]) -> String {
+                    ids.iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                }
+                write!(f, "duplicate ids in the inventory files:")?;
+                if !type_ids.is_empty() {
+                    write!(f, " item types {}", list(type_ids))?;
+                }
+                if !instance_ids.is_empty() {
+                    if !type_ids.is_empty() {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " item instances {}", list(instance_ids))?;
+                }
+                write!(f, ". Use --fix-ids to give new ids to the later duplicates")
+            }
+            CliError::Format(path, e) => write!(f, "{}: {}", path.display(), e),
+            CliError::InterruptedSave { journal, reason } => write!(
+                f,
+                "the last save was interrupted and {} can not be used to finish it: {}. \
+                 Check that the inventory files are consistent, restoring them from an archive \
+                 if needed, then delete it",
+                journal.display(),
+                reason
+            ),
+            CliError::Published(path) => write!(
+                f,
+                "{} is a published inventory, which can only be read. Use the files of the inventory to change it",
+                path.display()
+            ),
+            CliError::WrongPassphrase(path) => write!(
+                f,
+                "{} can not be decrypted: the passphrase is wrong or the file was damaged. Nothing was changed",
+                path.display()
+            ),
+            CliError::MixedEncryption {
+                plaintext,
+                encrypted,
+            } => {
+                let list = |paths: &[PathBuf]| {
+                    paths
+                        .iter()
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                };
+                write!(
+                    f,
+                    "the inventory has both plaintext files ({}) and encrypted files ({}), \
+                     probably because encrypt or decrypt was interrupted. Check which ones are \
+                     current and delete the others",
+                    list(plaintext),
+                    list(encrypted)
+                )
+            }
+            CliError::Encryption(reason) => write!(f, "{}", reason),
+            CliError::UnknownProfile { name, known } if known.is_empty() => write!(
+                f,
+                "there is no profile named {}, no profile is defined",
+                name
+            ),
+            CliError::UnknownProfile { name, known } => write!(
+                f,
+                "there is no profile named {}, the profiles are: {}",
+                name,
+                known.join(", ")
+            ),
+            CliError::FakeNow(value) => write!(
+                f,
+                "{} is set to {}, which is not a time like 2024-01-15T12:00:00Z",
+                inv_manager::clock::FAKE_NOW_VAR,
+                value
+            ),
+            CliError::Hook { program, reason } => write!(
+                f,
+                "the changes were saved but the hook {} failed: {}",
+                program.display(),
+                reason
+            ),
+            CliError::Archive(e) => write!(f, "{}", e),
+            CliError::Io {
+                operation,
+                path,
+                source,
+            } => {
+                let operation = match operation {
+                    Operation::CreateDir => "create directory",
+                    Operation::Read => "read",
+                    Operation::Parse => "parse",
+                    Operation::Write => "write",
+                };
+                write!(f, "failed to {} {}: {}", operation, path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CliError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CliError::NotADirectory(_)
+            | CliError::DuplicateIds { .. }
+            | CliError::Format(..)
+            | CliError::InterruptedSave { .. }
+            | CliError::Published(_)
+            | CliError::WrongPassphrase(_)
+            | CliError::MixedEncryption { .. }
+            | CliError::Encryption(_)
+            | CliError::UnknownProfile { .. }
+            | CliError::FakeNow(_)
+            | CliError::Hook { .. } => None,
+            CliError::Archive(source) | CliError::Io { source, .. } => Some(source),
+        }
+    }
+}
@@ -0,0 +1,349 @@
+//! The event log of an inventory, `<name>_events.json`, with one json event per line.
+//!
+//! Every command changing the saved inventory appends the items it changed. The log starts
+//! with a checkpoint of the inventory before the first logged command, which is the first
+//! one loading both the item types and the item instances, and a checkpoint follows every
+//! `CHECKPOINT_INTERVAL` commands so that replays don't start from the beginning.
+//! `update-state` appends the item instances which became expired, which replays ignore.
+//!
+//! The log of an encrypted inventory is `<name>_events.json.enc`, encrypted line by line
+//! with the cipher of its other files, see `crypto`.
+
+use crate::crypto::{self, Cipher};
+use crate::error::{CliError, Operation};
+use crate::result::{Change, CommandResult};
+use crate::storage;
+use inv_manager::{Edit, Event, EventLog, Identified, Inventory};
+use log::warn;
+use serde::Deserialize;
+use std::fs::{read, remove_file, rename, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The number of logged commands after which a checkpoint is written.
+pub const CHECKPOINT_INTERVAL: usize = 100;
+
+/// The event log of the inventory with this name, the encrypted one for an encrypted
+/// inventory.
+pub fn events_path(workdir: &Path, name: &str, encrypted: bool) -> PathBuf {
+    let path = workdir.join(format!("{}_events.json", name));
+    if encrypted {
+        crypto::encrypted_path(&path)
+    } else {
+        path
+    }
+}
+
+/// Only the kind of an event, to count events without reading whole checkpoints.
+#[derive(Deserialize)]
+struct Kind {
+    kind: String,
+}
+
+/// Appends the changes made by a command to the log, preceded by a checkpoint of the
+/// inventory before them when the log has none. `whole` tells whether both the item types
+/// and the item instances were loaded, which checkpoints need: without them, checkpoints
+/// are written by a later command, and nothing is logged until the first one.
+pub fn record(
+    path: &Path,
+    cipher: Option<&Cipher>,
+    result: &CommandResult,
+    before: &Inventory,
+    after: &Inventory,
+    whole: bool,
+) -> Result<(), CliError> {
+    let logged = read_lines(path, cipher)?;
+    let since_checkpoint = logged.iter().rev().position(|(_, line)| {
+        line.as_deref()
+            .and_then(|line| serde_json::from_str::<Kind>(line).ok())
+            .is_some_and(|k| k.kind == "checkpoint")
+    });
+
+    let mut events = vec![];
+    match since_checkpoint {
+        None if whole => events.push(Event::checkpoint(before)),
+        None => return Ok(()),
+        Some(_) => {}
+    }
+    events.push(Event::Changes {
+        at: after.now(),
+        command: result.command.to_string(),
+        types: edits(&result.type_changes),
+        instances: edits(&result.instance_changes),
+    });
+    if since_checkpoint.is_some_and(|n| n + 1 >= CHECKPOINT_INTERVAL) && whole {
+        events.push(Event::checkpoint(after));
+    }
+    append(path, cipher, &events)
+}
+
+/// Appends events to the log, like the expiries recorded by `update-state`, in a single
+/// write, encrypting them with the cipher of an encrypted log. A last line cut short by an
+/// interrupted append is ended first, so that the events don't continue it.
+pub fn append(path: &Path, cipher: Option<&Cipher>, events: &[Event]) -> Result<(), CliError> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| CliError::io(Operation::Write, path, e))?;
+    let mut logged = vec![];
+    file.read_to_end(&mut logged)
+        .map_err(|e| CliError::io(Operation::Read, path, e))?;
+    let logged = String::from_utf8_lossy(&logged);
+    let mut lines = String::new();
+    if !logged.is_empty() && !logged.ends_with('\n') {
+        lines.push('\n');
+    }
+    if cipher.is_some() && logged.is_empty() {
+        lines.push_str(crypto::MAGIC);
+        lines.push('\n');
+    }
+    // Encrypted with the key of the first event, derived once for the whole log.
+    let like = logged.lines().nth(1);
+    for event in events {
+        let json = serde_json::to_string(event).expect("Failed to serialize the event");
+        match cipher {
+            Some(cipher) => lines.push_str(&cipher.seal_line(&json, like)?),
+            None => lines.push_str(&json),
+        }
+        lines.push('\n');
+    }
+    file.write_all(lines.as_bytes())
+        .map_err(|e| CliError::io(Operation::Write, path, e))
+}
+
+/// Writes the log at `from` to `to`, decrypting it with `from_cipher` and encrypting it
+/// with `to_cipher`, then removes it, for `encrypt` and `decrypt`. Lines cut short by an
+/// interrupted append are left out. Returns whether there was a log.
+pub fn convert(
+    from: &Path,
+    from_cipher: Option<&Cipher>,
+    to: &Path,
+    to_cipher: Option<&Cipher>,
+) -> Result<bool, CliError> {
+    if !from.exists() {
+        return Ok(false);
+    }
+    let mut text = String::new();
+    if to_cipher.is_some() {
+        text.push_str(crypto::MAGIC);
+        text.push('\n');
+    }
+    for (_, line) in read_lines(from, from_cipher)? {
+        let line = match line {
+            Some(line) if serde_json::from_str::<Kind>(&line).is_ok() => line,
+            _ => continue,
+        };
+        match to_cipher {
+            Some(cipher) => text.push_str(&cipher.seal_line(&line, None)?),
+            None => text.push_str(&line),
+        }
+        text.push('\n');
+    }
+    let tmp = storage::write_tmp(to, text.as_bytes())?;
+    rename(&tmp, to).map_err(|e| CliError::io(Operation::Write, to, e))?;
+    remove_file(from).map_err(|e| CliError::io(Operation::Write, from, e))?;
+    Ok(true)
+}
+
+fn edits<T: Clone + Identified>(changes: &[Change<T>]) -> Vec<Edit<T>> {
+    changes
+        .iter()
+        .filter_map(|c| Edit::between(c.id, c.before.as_ref(), c.after.clone()))
+        .collect()
+}
+
+/// Reads the event log, which is empty when the file doesn't exist. Lines cut short by an
+/// interrupted append are skipped with a warning.
+pub fn load(path: &Path, cipher: Option<&Cipher>) -> Result<EventLog, CliError> {
+    let mut events = vec![];
+    for (number, line) in read_lines(path, cipher)? {
+        let cut = || {
+            warn!(
+                "Skipping the line {} of {}, which was cut short by an interrupted write",
+                number,
+                path.display()
+            )
+        };
+        let line = match line {
+            Some(line) => line,
+            None => {
+                cut();
+                continue;
+            }
+        };
+        match serde_json::from_str(&line) {
+            Ok(event) => events.push(event),
+            Err(e) if e.is_eof() => cut(),
+            Err(e) => return Err(CliError::io(Operation::Parse, path, e.into())),
+        }
+    }
+    Ok(EventLog { events })
+}
+
+/// The numbers and the text of the lines of the log which are not blank, decrypted with
+/// the cipher of an encrypted log. The lines of an encrypted log that can not be decrypted,
+/// cut short by an interrupted append, are `None`, unless none can, which means that the
+/// passphrase is wrong.
+fn read_lines(
+    path: &Path,
+    cipher: Option<&Cipher>,
+) -> Result<Vec<(usize, Option<String>)>, CliError> {
+    let data = match read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(CliError::io(Operation::Read, path, e)),
+    };
+    // An interrupted append can cut a character in two.
+    let text = String::from_utf8_lossy(&data);
+    let lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty());
+    let cipher = match cipher {
+        Some(cipher) => cipher,
+        None => return Ok(lines.map(|(i, line)| (i, Some(line.to_string()))).collect()),
+    };
+    let lines = lines
+        .filter(|(_, line)| *line != crypto::MAGIC)
+        .map(|(i, line)| (i, cipher.open_line(path, line).ok()))
+        .collect::<Vec<_>>();
+    if !lines.is_empty() && lines.iter().all(|(_, line)| line.is_none()) {
+        return Err(CliError::WrongPassphrase(path.to_path_buf()));
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use inv_manager::{FixedClock, ItemInstance, ItemType};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    fn log_command(path: &Path, before: &Inventory, after: &Inventory) {
+        let mut result = CommandResult {
+            command: "test",
+            ..CommandResult::default()
+        };
+        result.record_changes(before, after);
+        record(path, None, &result, before, after, true).unwrap();
+    }
+
+    #[test]
+    fn logged_commands_are_replayed_with_checkpoints() {
+        let dir = std::env::temp_dir().join(format!("inv_events_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = events_path(&dir, "log", false);
+        let _ = std::fs::remove_file(&path);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let hour = Duration::from_secs(3600);
+
+        let mut inventory = Inventory::default();
+        let rice = inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            ..ItemType::default()
+        });
+        let mut states = vec![];
+        for i in 0..CHECKPOINT_INTERVAL as u32 + 5 {
+            inventory.set_clock(Arc::new(FixedClock::new(start + hour * i)));
+            let before = inventory.clone();
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: rice,
+                    quantity: i as f32,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+            log_command(&path, &before, &inventory);
+            states.push(inventory.clone());
+        }
+
+        let log = load(&path, None).unwrap();
+        let checkpoints = log
+            .events
+            .iter()
+            .filter(|e| matches!(e, Event::Checkpoint { .. }))
+            .count();
+        assert_eq!(checkpoints, 2);
+        assert_eq!(log.events.len(), CHECKPOINT_INTERVAL + 5 + 2);
+        for (i, state) in states.iter().enumerate() {
+            let (replayed, skipped) = log.replay_checked(start + hour * i as u32);
+            assert!(skipped.is_empty());
+            assert_eq!(replayed.item_types, state.item_types);
+            assert_eq!(replayed.item_instances, state.item_instances);
+        }
+        assert_eq!(log.start(), Some(start));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn appends_after_an_interrupted_one_start_on_a_new_line() {
+        let dir = std::env::temp_dir().join(format!("inv_events_cut_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = events_path(&dir, "log", false);
+        std::fs::write(&path, "{\"kind\":\"chan").unwrap();
+        let before = Inventory::default();
+        let mut after = before.clone();
+        after.add_item_type(ItemType {
+            name: "rice".to_string(),
+            ..ItemType::default()
+        });
+        log_command(&path, &before, &after);
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("{\"kind\":\"chan\n{"), "{}", text);
+        let log = load(&path, None).unwrap();
+        assert_eq!(log.events.len(), 2);
+        assert!(matches!(log.events[0], Event::Checkpoint { .. }));
+        // Other errors are not skipped.
+        std::fs::write(&path, "{\"kind\":\"nothing\"}\n").unwrap();
+        assert!(load(&path, None).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn encrypted_logs_are_encrypted_line_by_line() {
+        let dir = std::env::temp_dir().join(format!("inv_events_enc_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = events_path(&dir, "log", true);
+        let _ = std::fs::remove_file(&path);
+        let cipher = Cipher::new("correct horse".to_string()).unwrap();
+        let before = Inventory::default();
+        let mut after = before.clone();
+        after.add_item_type(ItemType {
+            name: "rice".to_string(),
+            ..ItemType::default()
+        });
+        let mut result = CommandResult {
+            command: "test",
+            ..CommandResult::default()
+        };
+        result.record_changes(&before, &after);
+        record(&path, Some(&cipher), &result, &before, &after, true).unwrap();
+        // Cut short by a crash.
+        let mut text = std::fs::read_to_string(&path).unwrap();
+        text.truncate(text.len() - 10);
+        std::fs::write(&path, &text).unwrap();
+        record(&path, Some(&cipher), &result, &before, &after, true).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(crypto::MAGIC) && !text.contains("rice"));
+        let log = load(&path, Some(&cipher)).unwrap();
+        assert_eq!(log.events.len(), 2);
+        let wrong = Cipher::new("battery staple".to_string()).unwrap();
+        assert!(matches!(
+            load(&path, Some(&wrong)),
+            Err(CliError::WrongPassphrase(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_missing_log_is_empty() {
+        let log = load(Path::new("/nonexistent/inventory_events.json"), None).unwrap();
+        assert!(log.events.is_empty());
+    }
+}
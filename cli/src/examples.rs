@@ -0,0 +1,729 @@
+//! Worked examples of every subcommand, printed by `inv examples`.
+//!
+//! The tests parse every example like the command line would, so that they can't drift from
+//! the options of the subcommands, and check that every subcommand has some.
+
+/// A command line and what it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Example {
+    /// The name of the subcommand, as returned by `Command::name`.
+    pub command: &'static str,
+    /// The whole command line. Arguments containing spaces are between double quotes, or
+    /// between single quotes when they contain a $ the shell must not expand.
+    pub cmdline: &'static str,
+    pub description: &'static str,
+}
+
+const fn example(
+    command: &'static str,
+    cmdline: &'static str,
+    description: &'static str,
+) -> Example {
+    Example {
+        command,
+        cmdline,
+        description,
+    }
+}
+
+/// The examples, in the order of the subcommands in `Command`.
+pub const EXAMPLES: &[Example] = &[
+    example(
+        "ct",
+        "inv ct rice --unit kg -m 2",
+        "Create the item type rice, counted in kg, and keep at least 2 kg of it.",
+    ),
+    example(
+        "ct",
+        "inv ct milk --ttl 5days -o true --with-instance --quantity 2 --location fridge",
+        "Create milk, which keeps 5 days once opened, with 2 of it in the fridge.",
+    ),
+    example(
+        "ct",
+        "inv ct milk --icon 🥛",
+        "Create milk, shown with a glass of milk by frontends and in the table of rt.",
+    ),
+    example(
+        "ct",
+        "inv ct drill --depreciation-years 5",
+        "Create drills, which lose all their value in 5 years.",
+    ),
+    example(
+        "ct",
+        "inv ct flour --unit kg --category Food/Baking",
+        "Create flour in the category Baking of the category Food.",
+    ),
+    example(
+        "ct",
+        "inv ct eggs --unit pieces --integer-only true",
+        "Create eggs, counted in whole pieces, refusing quantities like 0.5.",
+    ),
+    example(
+        "ct",
+        "inv ct coffee -m 1 --vendor market",
+        "Create coffee, usually bought at the market, and keep at least 1 of it.",
+    ),
+    example(
+        "ct",
+        "inv ct rice --unit kg -m 2 --package-size 1",
+        "Create rice bought in bags of 1 kg, so that the shopping list asks for whole bags.",
+    ),
+    example(
+        "ct",
+        "inv ct milk -t 5days --auto-trash-on-expiry true",
+        "Create milk, put in the trash by update-state once it expires.",
+    ),
+    example(
+        "ct",
+        "inv ct yogurt --class fresh --min-at fridge=4",
+        "Create yogurt with the defaults of the class fresh, keeping 4 in the fridge.",
+    ),
+    example(
+        "ct",
+        "inv ct \"sourdough starter\" --remind-after-open 5days",
+        "Have the digest remind to feed the sourdough starter 5 days after opening it.",
+    ),
+    example(
+        "rt",
+        "inv rt",
+        "Print every item type with its total quantity.",
+    ),
+    example(
+        "rt",
+        "inv rt -n rice --exact",
+        "Print the item type named rice, but not brown rice.",
+    ),
+    example(
+        "rt",
+        "inv rt --unused",
+        "Print the item types without any item instance, for example to delete them.",
+    ),
+    example(
+        "ut",
+        "inv ut 1 -m 3 --min-at pantry=1",
+        "Keep at least 3 of item type 1, one of them in the pantry.",
+    ),
+    example(
+        "ut",
+        "inv ut 4 --seasonal 05-01..09-30=2 --seasonal 10-01..04-30=0",
+        "Keep 2 of item type 4 from May to September and none the rest of the year.",
+    ),
+    example("ut", "inv ut 2 --unit", "Remove the unit of item type 2."),
+    example(
+        "ut",
+        "inv ut 2 --category \"Household/Cleaning\"",
+        "Move item type 2 to the category Cleaning of Household.",
+    ),
+    example(
+        "ut",
+        "inv ut 3 -n \"Oat drink\"",
+        "Rename item type 3, which its former name still finds.",
+    ),
+    example(
+        "ut",
+        "inv ut 5 --counted",
+        "Record that item type 5 was counted again, clearing its recount flag.",
+    ),
+    example(
+        "ut",
+        "inv ut 6 --add-field chemistry:text --add-field voltage:number",
+        "Give the item instances of item type 6, batteries, a chemistry and a voltage.",
+    ),
+    example(
+        "dt",
+        "inv dt 3",
+        "Delete item type 3 and its item instances.",
+    ),
+    example(
+        "dt",
+        "inv dt 5 --reassign-to 2",
+        "Delete item type 5, a duplicate of item type 2, moving its item instances to it.",
+    ),
+    example(
+        "recompute-expiry",
+        "inv recompute-expiry 2 --dry-run",
+        "Print the expiries of the opened item instances of type 2 that its ttl would change.",
+    ),
+    example(
+        "bulk-update-types",
+        "inv bulk-update-types --name-contains cheese --no-ttl --set-ttl 2weeks --recompute",
+        "Give a ttl of 2 weeks to the cheeses without one and update their opened instances.",
+    ),
+    example(
+        "set-icons-from-map",
+        "inv set-icons-from-map icons.json --dry-run",
+        "Print the icons that the patterns of icons.json, like {\"*milk*\": \"🥛\"}, would set.",
+    ),
+    example(
+        "ci",
+        "inv ci rice -q 500g -l pantry -v 1.5",
+        "Add 500 g of rice, converted to the unit of the item type, worth 1.5, to the pantry.",
+    ),
+    example(
+        "ci",
+        "inv ci rice -q 1,5kg -v 2,40",
+        "Add 1.5 kg of rice worth 2.40, written with decimal commas.",
+    ),
+    example(
+        "ci",
+        "inv ci 2 -e \"2030-06-01 00:00:00\"",
+        "Add an item instance of type 2 expiring on June 1st 2030.",
+    ),
+    example(
+        "ci",
+        "inv ci milk -e friday",
+        "Add milk expiring at the start of next friday, in UTC.",
+    ),
+    example(
+        "ci",
+        "inv ci milk --opened-at 3d",
+        "Add milk opened 3 days ago, expiring after the ttl of its item type counted from then.",
+    ),
+    example(
+        "ci",
+        "inv ci drill --ref ORDER-1234",
+        "Add a drill, recording the number of the order it came with.",
+    ),
+    example(
+        "ci",
+        "inv ci batteries -q 4 --set chemistry=LiIon --set voltage=3.7",
+        "Add 4 lithium-ion batteries, filling in the custom fields of their item type.",
+    ),
+    example(
+        "ri",
+        "inv ri",
+        "Print every item instance that is not in the trash.",
+    ),
+    example(
+        "ri",
+        "inv --timestamps ri --added-since \"1 week\"",
+        "Print what was added during the last week, with when it was added.",
+    ),
+    example(
+        "ri",
+        "inv --no-shelf-life ri",
+        "Print the item instances without the bars of their shelf life, on a narrow terminal.",
+    ),
+    example(
+        "ri",
+        "inv ri --type-name milk -e",
+        "Print the expired item instances of the item types whose name contains milk.",
+    ),
+    example(
+        "ri",
+        "inv ri -g location",
+        "Print the item instances by location, with subtotals.",
+    ),
+    example(
+        "ri",
+        "inv ri -g home-location",
+        "Print the item instances by the location where they belong, with subtotals.",
+    ),
+    example(
+        "ri",
+        "inv ri --ref ORDER-12",
+        "Print the item instances whose external reference contains ORDER-12.",
+    ),
+    example(
+        "ri",
+        "inv ri --where chemistry=liion --where \"voltage>=3\"",
+        "Print the lithium-ion batteries of at least 3 volts.",
+    ),
+    example(
+        "ri",
+        "inv -n home ri -i 42 --qr-string",
+        "Print item instance 42 of the inventory home as a compact string for a QR code.",
+    ),
+    example(
+        "ui",
+        "inv ui 7 -q 0.5 -l fridge",
+        "Set the quantity of item instance 7 to 0.5 and move it to the fridge.",
+    ),
+    example(
+        "ui",
+        "inv ui drill-01 -l kitchen --home-location garage",
+        "Record that the drill, which belongs in the garage, is in the kitchen for now.",
+    ),
+    example(
+        "ui",
+        "inv ui 12 --set voltage=",
+        "Remove the voltage of item instance 12.",
+    ),
+    example(
+        "di",
+        "inv di 7 --reason broken",
+        "Remove item instance 7, which broke.",
+    ),
+    example(
+        "list-expired",
+        "inv list-expired --include-best-before",
+        "Print the item instances past their use-by or best-before date.",
+    ),
+    example(
+        "list-returnables",
+        "inv list-returnables",
+        "Print the trashed item instances whose container can be returned.",
+    ),
+    example(
+        "return-deposit",
+        "inv return-deposit 12 13",
+        "Mark the containers of item instances 12 and 13 as returned.",
+    ),
+    example(
+        "list-missing",
+        "inv list-missing --with-prices",
+        "Print what to buy, with the last prices paid.",
+    ),
+    example(
+        "list-missing",
+        "inv --minimal shopping-list",
+        "Print what to buy as tab separated lines.",
+    ),
+    example(
+        "list-missing",
+        "inv shopping-list --group-by vendor",
+        "Print what to buy with one section per store, item types without a store last.",
+    ),
+    example(
+        "list-missing",
+        "inv shopping-list --vendor market",
+        "Print what to buy at the market.",
+    ),
+    example(
+        "digest",
+        "inv digest -p 14days",
+        "Print what expired or expires within 14 days, and what to buy.",
+    ),
+    example(
+        "digest",
+        "inv digest --mark-reminded",
+        "Print the digest to send it, reminding about each opened item instance only once.",
+    ),
+    example(
+        "update-state",
+        "inv update-state",
+        "Record what expired since the last run, for a cron job running before the digest.",
+    ),
+    example(
+        "update-state",
+        "inv update-state --dry-run",
+        "Print what expired since the last run without recording it.",
+    ),
+    example(
+        "snooze",
+        "inv snooze 12 --forever",
+        "Stop reporting the expired bottle of vinegar 12, which is kept on purpose.",
+    ),
+    example(
+        "snooze",
+        "inv snooze --type-id 4 --for 30days",
+        "Leave the item instances of the item type 4 out of the reports for 30 days.",
+    ),
+    example(
+        "unsnooze",
+        "inv unsnooze 12",
+        "Report the item instance 12 again.",
+    ),
+    example(
+        "prices",
+        "inv prices 1",
+        "Print the prices paid per unit for item type 1.",
+    ),
+    example("use", "inv use milk", "Use 1 of the item type named milk."),
+    example(
+        "use",
+        "inv use rice 250g",
+        "Use 250 g of rice, converted to the unit of the item type.",
+    ),
+    example(
+        "use",
+        "inv use 3 --all",
+        "Use everything left in the item instance of type 3 that would be used from.",
+    ),
+    example(
+        "use",
+        "inv use 3:1 7:0.5 flour:250g",
+        "Use from three item types at once, or from none if one of them has not enough.",
+    ),
+    example(
+        "use",
+        "inv use --item 3=1 --item 7=0.5 --best-effort",
+        "Use from two item types, using what there is of those that have not enough.",
+    ),
+    example(
+        "use",
+        "inv use salt 2 --allow-overdraft",
+        "Use 2 of salt even if less is left, flagging it for a recount.",
+    ),
+    example(
+        "finish",
+        "inv finish rice-01",
+        "Use everything left in the item instance labelled rice-01.",
+    ),
+    example(
+        "parse-qr",
+        "inv -n home parse-qr \"IMG1|inv=home|i=42|t=rice|exp=2030-06-01\"",
+        "Print the item instance designated by a scanned QR code.",
+    ),
+    example(
+        "find-ref",
+        "inv find-ref ORDER-1234",
+        "Print every field of the item instances of order ORDER-1234, trashed ones included.",
+    ),
+    example(
+        "trash",
+        "inv trash 7 --reason expired",
+        "Put item instance 7 in the trash, recording that it expired.",
+    ),
+    example(
+        "trash",
+        "inv trash 7 --quantity 200g --reason expired",
+        "Throw out 200g of item instance 7, keeping the rest.",
+    ),
+    example(
+        "list-trashed",
+        "inv list-trashed --removed-since 7days",
+        "Print what was thrown away during the last week, and when.",
+    ),
+    example(
+        "trash-where",
+        "inv trash-where --expired --location fridge",
+        "Put the expired item instances of the fridge in the trash, after confirming.",
+    ),
+    example(
+        "trash-where",
+        "inv trash-where --empty --reason used --yes",
+        "Put every empty item instance in the trash without asking.",
+    ),
+    example(
+        "replace",
+        "inv replace --field model --from Bosh --to Bosch --dry-run",
+        "Preview fixing a misspelled brand in the models of the item instances.",
+    ),
+    example(
+        "replace",
+        "inv replace --field location --regex --from '^Shed (\\d)' --to 'Garden shed $1'",
+        "Rename the numbered shelves of the shed, keeping their numbers.",
+    ),
+    example(
+        "give",
+        "inv give 8 --to Sam -q 250g",
+        "Give 250 g of item instance 8 to Sam.",
+    ),
+    example(
+        "list-given",
+        "inv list-given --to sam",
+        "Print what was given to Sam.",
+    ),
+    example(
+        "list-recount",
+        "inv list-recount",
+        "Print the item types to count again after using more than what was left.",
+    ),
+    example(
+        "misplaced",
+        "inv misplaced",
+        "Print what is not where it belongs.",
+    ),
+    example(
+        "put-back",
+        "inv put-back drill-01",
+        "Record that the item instance labelled drill-01 is back at its home location.",
+    ),
+    example(
+        "waste-report",
+        "inv waste-report --months 12",
+        "Print what was thrown away each month of the last year, per reason.",
+    ),
+    example(
+        "tco",
+        "inv tco rice --months 24",
+        "Print what the rice bought during the last two years cost per kilo used, waste included.",
+    ),
+    example(
+        "tco",
+        "inv tco --all",
+        "Print the item types wasting the largest part of what was paid for them first.",
+    ),
+    example(
+        "health",
+        "inv health --top 20",
+        "Print how complete the data is and the 20 records missing the most.",
+    ),
+    example(
+        "list-idle",
+        "inv list-idle --for 6months --delete",
+        "Delete the item types nobody used in 6 months, after confirming.",
+    ),
+    example(
+        "top-up",
+        "inv top-up rice-01 2kg --expires-at 2031-03-01",
+        "Pour a new bag of rice into the jar rice-01, created with ci --continuous.",
+    ),
+    example(
+        "categories",
+        "inv categories",
+        "Print the tree of categories with their totals.",
+    ),
+    example(
+        "value-report",
+        "inv value-report --depreciated",
+        "Print what the inventory cost and what it is worth now, per item type.",
+    ),
+    example(
+        "generate-labels",
+        "inv generate-labels",
+        "Give a label to the item instances that have none.",
+    ),
+    example(
+        "generate-demo",
+        "inv -n demo generate-demo --types 30 --instances 200 --seed 7",
+        "Fill the empty inventory demo with 30 fake item types and 200 item instances.",
+    ),
+    example(
+        "trash-empty",
+        "inv trash-empty -t 2",
+        "Put the empty item instances of type 2 in the trash.",
+    ),
+    example(
+        "compact-ids",
+        "inv compact-ids --instances -o ids.json",
+        "Renumber the item instances from 1 and write the old and new ids to ids.json.",
+    ),
+    example(
+        "classes",
+        "inv classes set fresh --ttl 3days -o true",
+        "Create the class fresh, for item types keeping 3 days and opened by default.",
+    ),
+    example(
+        "classes",
+        "inv classes list",
+        "Print the classes of item types.",
+    ),
+    example(
+        "archive",
+        "inv archive export backup.tar.gz",
+        "Write every file of the inventory to backup.tar.gz.",
+    ),
+    example(
+        "archive",
+        "inv archive import --force backup.tar.gz",
+        "Replace the inventory with the files of backup.tar.gz.",
+    ),
+    example(
+        "migrate",
+        "inv migrate --check",
+        "Print the format version of the inventory files.",
+    ),
+    example(
+        "encrypt",
+        "inv encrypt",
+        "Encrypt the inventory files with the passphrase of INVENTORY_MANAGOAT_PASSPHRASE, or one asked twice.",
+    ),
+    example(
+        "decrypt",
+        "inv decrypt",
+        "Decrypt the inventory files, asking for their passphrase.",
+    ),
+    example(
+        "profiles",
+        "inv profiles",
+        "List the profiles of the profiles file, the default one marked with *.",
+    ),
+    example(
+        "link",
+        "inv -w ~/lab/inventory -n consumables link",
+        "Use the consumables inventory by default in the current directory and below.",
+    ),
+    example(
+        "export-ical",
+        "inv export-ical --within 30days -a 2 -o expiries.ics",
+        "Write the expiries of the next 30 days as a calendar, with alarms 2 days before.",
+    ),
+    example(
+        "export",
+        "inv export -g type-class -o inventory.md",
+        "Write the inventory as a Markdown document with a section per class.",
+    ),
+    #[cfg(feature = "xlsx")]
+    example(
+        "export",
+        "inv export -f xlsx -o inventory.xlsx",
+        "Write a spreadsheet of the item types, the item instances and the digest numbers.",
+    ),
+    example(
+        "graph-locations",
+        "inv graph-locations -o locations.dot",
+        "Write a Graphviz graph of the locations, to draw with dot -Tsvg locations.dot.",
+    ),
+    example(
+        "graph-locations",
+        "inv graph-locations -f mermaid",
+        "Print the graph as a Mermaid flowchart to paste in Markdown.",
+    ),
+    example(
+        "publish",
+        "inv publish -o ~/Sync/pantry.json --redact value,serial",
+        "Share the inventory without its values and serial numbers, read with --from-published.",
+    ),
+    example(
+        "metrics",
+        "inv metrics --include-deposits -o /var/lib/node_exporter/inventory.prom",
+        "Write the metrics, with the outstanding deposits, for the node_exporter.",
+    ),
+    example(
+        "state-at",
+        "inv state-at 2021-06-01",
+        "Print the item types and item instances as they were at the end of June 1st 2021.",
+    ),
+    example(
+        "state-at",
+        "inv state-at now --verify",
+        "Check that the event log gives the inventory of the files.",
+    ),
+    example(
+        "normalize-locations",
+        "inv normalize-locations",
+        "Rename the location names written in several ways to a single name, asking for each.",
+    ),
+    example(
+        "normalize-locations",
+        "inv normalize-locations --dry-run",
+        "Print the location names, how much they are used and the proposed renames.",
+    ),
+    example(
+        "normalize-locations",
+        "inv normalize-locations --apply renames.json",
+        "Rename the locations as mapped in renames.json, like {\"Pantry\": \"pantry\"}.",
+    ),
+    example(
+        "location-add",
+        "inv location-add Freezer -c 30 -u l",
+        "Register the freezer, which holds 30 litres.",
+    ),
+    example(
+        "location-set",
+        "inv location-set freezer -c 40",
+        "Change the capacity of the freezer to 40 litres.",
+    ),
+    example(
+        "location-list",
+        "inv location-list",
+        "Print how full each registered location is, in red when over its capacity.",
+    ),
+    #[cfg(feature = "grocy")]
+    example(
+        "import-grocy",
+        "inv import-grocy grocy-export",
+        "Import the products and stock of the Grocy export in the directory grocy-export.",
+    ),
+    example(
+        "import-legacy",
+        "inv import-legacy types.txt instances.txt",
+        "Import the item types and item instances printed by the minimal mode of the first versions.",
+    ),
+    example(
+        "repl",
+        "inv -w /mnt/nas/inventory repl",
+        "Load the inventory on the NAS once, then type commands like ri -e until quit.",
+    ),
+    example("examples", "inv examples use", "Print the examples of use."),
+];
+
+/// The examples of a subcommand, or of every subcommand.
+pub fn examples_of(command: Option<&str>) -> Vec<&'static Example> {
+    EXAMPLES
+        .iter()
+        .filter(|e| command.map(|c| c == e.command).unwrap_or(true))
+        .collect()
+}
+
+/// Renders examples with their descriptions indented below them.
+pub fn render(examples: &[&Example]) -> String {
+    examples
+        .iter()
+        .map(|e| format!("{}\n    {}\n", e.cmdline, e.description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Manager;
+    use structopt::StructOpt;
+
+    /// Splits a command line into arguments like a shell, for the quotes of examples.
+    fn arguments(cmdline: &str) -> Vec<String> {
+        let mut arguments = vec![];
+        let mut current = String::new();
+        let mut quote = None;
+        let mut started = false;
+        for c in cmdline.chars() {
+            match c {
+                '"' | '\'' if quote.is_none() => {
+                    quote = Some(c);
+                    started = true;
+                }
+                c if quote == Some(c) => quote = None,
+                ' ' if quote.is_none() => {
+                    if started {
+                        arguments.push(std::mem::take(&mut current));
+                    }
+                    started = false;
+                }
+                c => {
+                    current.push(c);
+                    started = true;
+                }
+            }
+        }
+        assert!(quote.is_none(), "unbalanced quotes in {}", cmdline);
+        if started {
+            arguments.push(current);
+        }
+        arguments
+    }
+
+    #[test]
+    fn examples_are_valid_command_lines() {
+        for example in EXAMPLES {
+            let arguments = arguments(example.cmdline);
+            assert_eq!(arguments[0], "inv", "{}", example.cmdline);
+            let manager = Manager::from_iter_safe(&arguments)
+                .unwrap_or_else(|e| panic!("{}: {}", example.cmdline, e));
+            assert_eq!(
+                manager.command.unwrap().name(),
+                example.command,
+                "{}",
+                example.cmdline
+            );
+        }
+    }
+
+    #[test]
+    fn every_subcommand_has_examples() {
+        let app = Manager::clap();
+        for subcommand in app.p.subcommands.iter().map(|s| s.p.meta.name.as_str()) {
+            if subcommand != "help" {
+                assert!(
+                    !examples_of(Some(subcommand)).is_empty(),
+                    "{} has no example",
+                    subcommand
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn quoted_arguments_are_kept_together() {
+        assert_eq!(
+            arguments("inv ci 2 -e \"2030-06-01 00:00:00\" -l \"\""),
+            vec!["inv", "ci", "2", "-e", "2030-06-01 00:00:00", "-l", ""]
+        );
+        assert_eq!(
+            arguments("inv replace --to 'say \"$1\"' --from \"it's\""),
+            vec!["inv", "replace", "--to", "say \"$1\"", "--from", "it's"]
+        );
+    }
+}
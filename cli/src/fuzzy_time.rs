@@ -0,0 +1,303 @@
+//! Durations and times as people type them, like `2 weeks`, `3 months`, `tomorrow` or
+//! `friday`, for every time argument.
+//!
+//! Arguments are parsed when the command line is read, and resolved against the clock of
+//! the inventory once it is loaded. Days start at midnight UTC, like the dates of
+//! `2030-06-01`.
+
+use inv_manager::date::{civil_date, days_in_month, from_civil_date, weekday};
+use inv_manager::time_math::{checked_add_duration, LATEST_YEAR};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// A resolved time argument: a duration, or a time for the words and dates that designate one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeSpec {
+    Duration(Duration),
+    At(SystemTime),
+}
+
+impl TimeSpec {
+    /// The duration from `now`, nothing for a time already past.
+    pub fn duration(self, now: SystemTime) -> Duration {
+        match self {
+            TimeSpec::Duration(duration) => duration,
+            TimeSpec::At(at) => at.duration_since(now).unwrap_or_default(),
+        }
+    }
+
+    /// The time, a duration counting from `now`. A duration too long to be represented
+    /// ends after `LATEST_YEAR`, later than any plausible date.
+    pub fn time(self, now: SystemTime) -> SystemTime {
+        match self {
+            TimeSpec::Duration(duration) => checked_add_duration(now, duration)
+                .unwrap_or_else(|| from_civil_date(LATEST_YEAR + 1, 1, 1)),
+            TimeSpec::At(at) => at,
+        }
+    }
+}
+
+/// A time argument of the command line, before it is resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeArg {
+    /// A duration like `2days 3h` or `2 weeks`.
+    Duration(Duration),
+    /// Calendar months, like `3 months` or `1 year`, keeping the day of the month when
+    /// it exists and taking the last one otherwise.
+    Months(u32),
+    /// The start of the day this many days after today, like `tomorrow` or `next week`.
+    Days(u32),
+    /// The start of the next day of the week, from 0 for Monday, a week ahead on that day.
+    Weekday(u8),
+    /// The start of the first day of the next month.
+    EndOfMonth,
+    /// A date, or a date and time, in UTC.
+    At(SystemTime),
+}
+
+impl TimeArg {
+    pub fn resolve(self, now: SystemTime) -> TimeSpec {
+        let today = || {
+            let (year, month, day) = civil_date(now);
+            from_civil_date(year, month, day)
+        };
+        match self {
+            TimeArg::Duration(duration) => TimeSpec::Duration(duration),
+            TimeArg::Months(months) => TimeSpec::At(add_months(now, i64::from(months))),
+            TimeArg::Days(days) => TimeSpec::At(today() + DAY * days),
+            TimeArg::Weekday(day) => {
+                let ahead = (i32::from(day) - i32::from(weekday(now))).rem_euclid(7);
+                let ahead = if ahead == 0 { 7 } else { ahead as u32 };
+                TimeSpec::At(today() + DAY * ahead)
+            }
+            TimeArg::EndOfMonth => {
+                let (year, month, _) = civil_date(now);
+                TimeSpec::At(match month {
+                    12 => from_civil_date(year + 1, 1, 1),
+                    month => from_civil_date(year, month + 1, 1),
+                })
+            }
+            TimeArg::At(at) => TimeSpec::At(at),
+        }
+    }
+
+    /// The duration from `now`, for the options that take one.
+    pub fn duration(self, now: SystemTime) -> Duration {
+        self.resolve(now).duration(now)
+    }
+
+    /// The time, for the options that take one.
+    pub fn time(self, now: SystemTime) -> SystemTime {
+        self.resolve(now).time(now)
+    }
+
+    /// The time, a duration or calendar months counting back from `now`, for the options
+    /// that take a time in the past like --added-since.
+    pub fn ago(self, now: SystemTime) -> SystemTime {
+        match self {
+            TimeArg::Duration(duration) => now.checked_sub(duration).unwrap_or(UNIX_EPOCH),
+            TimeArg::Months(months) => add_months(now, -i64::from(months)),
+            arg => arg.time(now),
+        }
+    }
+}
+
+/// The same time of the day some calendar months later, or earlier, on the last day of the
+/// month when the day doesn't exist in it.
+fn add_months(now: SystemTime, months: i64) -> SystemTime {
+    let (year, month, day) = civil_date(now);
+    let start = from_civil_date(year, month, day);
+    let time_of_day = now.duration_since(start).unwrap_or_default();
+    let index = i64::from(year) * 12 + i64::from(month) - 1 + months;
+    let (year, month) = (index.div_euclid(12) as i32, index.rem_euclid(12) as u8 + 1);
+    from_civil_date(year, month, day.min(days_in_month(year, month))) + time_of_day
+}
+
+impl FromStr for TimeArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let input = s.split_whitespace().collect::<Vec<_>>().join(" ");
+        let input = input.to_lowercase();
+        let input = input.strip_prefix("in ").unwrap_or(&input);
+        let words = input.split(' ').collect::<Vec<_>>();
+        let count = |word: &str| match word {
+            "a" | "an" | "one" => Some(1),
+            word => word.parse::<u32>().ok(),
+        };
+        let fixed = match words.as_slice() {
+            ["now"] => Some(TimeArg::Duration(Duration::from_secs(0))),
+            ["today"] => Some(TimeArg::Days(0)),
+            ["tomorrow"] => Some(TimeArg::Days(1)),
+            ["next", "week"] => Some(TimeArg::Days(7)),
+            ["next", "month"] => Some(TimeArg::Months(1)),
+            ["next", "year"] => Some(TimeArg::Months(12)),
+            ["end", "of", "month"] | ["end", "of", "the", "month"] => Some(TimeArg::EndOfMonth),
+            [day] | ["next", day] => WEEKDAYS
+                .iter()
+                .position(|w| w == day || (day.len() >= 3 && w.starts_with(day)))
+                .map(|i| TimeArg::Weekday(i as u8)),
+            [n, "month"] | [n, "months"] => count(n).map(TimeArg::Months),
+            [n, "year"] | [n, "years"] => count(n)
+                .and_then(|n| n.checked_mul(12))
+                .map(TimeArg::Months),
+            _ => None,
+        };
+        if let Some(fixed) = fixed {
+            return Ok(fixed);
+        }
+        if let Some(months) = input
+            .strip_suffix("months")
+            .or_else(|| input.strip_suffix("month"))
+            .and_then(|n| n.parse::<u32>().ok())
+        {
+            return Ok(TimeArg::Months(months));
+        }
+        let date = format!("{} 00:00:00", s.trim());
+        humantime::parse_duration(input)
+            .map(TimeArg::Duration)
+            .or_else(|_| humantime::parse_rfc3339_weak(s.trim()).map(TimeArg::At))
+            .or_else(|_| humantime::parse_rfc3339_weak(&date).map(TimeArg::At))
+            .map_err(|_| {
+                format!(
+                    "invalid duration or time \"{}\", expected for example 2weeks, \"3 months\", tomorrow, \"next week\", friday, \"end of month\", 2030-06-01 or \"2030-06-01 18:00:00\"",
+                    s
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2020-09-13 12:26:40 UTC, a Sunday.
+    fn now() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    fn parse_fuzzy_time(input: &str, now: SystemTime) -> Result<TimeSpec, String> {
+        Ok(input.parse::<TimeArg>()?.resolve(now))
+    }
+
+    fn at(date: &str) -> TimeSpec {
+        TimeSpec::At(humantime::parse_rfc3339_weak(date).unwrap())
+    }
+
+    #[test]
+    fn accepted_inputs() {
+        let cases = [
+            ("7days", TimeSpec::Duration(DAY * 7)),
+            (
+                "2days 3h",
+                TimeSpec::Duration(DAY * 2 + Duration::from_secs(3 * 3600)),
+            ),
+            ("2 weeks", TimeSpec::Duration(DAY * 14)),
+            ("In 2 Weeks", TimeSpec::Duration(DAY * 14)),
+            ("now", TimeSpec::Duration(Duration::from_secs(0))),
+            ("today", at("2020-09-13 00:00:00")),
+            ("tomorrow", at("2020-09-14 00:00:00")),
+            ("next week", at("2020-09-20 00:00:00")),
+            ("friday", at("2020-09-18 00:00:00")),
+            ("next Fri", at("2020-09-18 00:00:00")),
+            ("monday", at("2020-09-14 00:00:00")),
+            ("sunday", at("2020-09-20 00:00:00")),
+            ("3 months", at("2020-12-13 12:26:40")),
+            ("3months", at("2020-12-13 12:26:40")),
+            ("a month", at("2020-10-13 12:26:40")),
+            ("next month", at("2020-10-13 12:26:40")),
+            ("5 months", at("2021-02-13 12:26:40")),
+            ("2 years", at("2022-09-13 12:26:40")),
+            ("end of month", at("2020-10-01 00:00:00")),
+            ("end of the month", at("2020-10-01 00:00:00")),
+            ("2030-06-01", at("2030-06-01 00:00:00")),
+            ("2030-06-01 18:00:00", at("2030-06-01 18:00:00")),
+            ("2030-06-01T18:00:00Z", at("2030-06-01 18:00:00")),
+        ];
+        for (input, expected) in cases.iter() {
+            assert_eq!(parse_fuzzy_time(input, now()), Ok(*expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn rejected_inputs_list_examples() {
+        for input in [
+            "",
+            "soon",
+            "next",
+            "2 fortnights",
+            "-3 months",
+            "frid4y",
+            "fr",
+        ] {
+            let error = parse_fuzzy_time(input, now()).unwrap_err();
+            assert!(error.contains("tomorrow"), "{}: {}", input, error);
+        }
+    }
+
+    #[test]
+    fn calendar_months_keep_the_day_when_they_can() {
+        let jan_31 = humantime::parse_rfc3339_weak("2024-01-31 08:00:00").unwrap();
+        assert_eq!(
+            TimeArg::Months(1).time(jan_31),
+            humantime::parse_rfc3339_weak("2024-02-29 08:00:00").unwrap()
+        );
+        assert_eq!(
+            TimeArg::Months(13).time(jan_31),
+            humantime::parse_rfc3339_weak("2025-02-28 08:00:00").unwrap()
+        );
+        let dec_15 = humantime::parse_rfc3339_weak("2024-12-15 00:00:00").unwrap();
+        assert_eq!(
+            TimeArg::EndOfMonth.time(dec_15),
+            humantime::parse_rfc3339_weak("2025-01-01 00:00:00").unwrap()
+        );
+    }
+
+    #[test]
+    fn past_times_count_back_from_now() {
+        assert_eq!(TimeArg::Duration(DAY * 2).ago(now()), now() - DAY * 2);
+        assert_eq!(
+            TimeArg::Months(1).ago(now()),
+            humantime::parse_rfc3339_weak("2020-08-13 12:26:40").unwrap()
+        );
+        assert_eq!(
+            TimeArg::Days(0).ago(now()),
+            humantime::parse_rfc3339_weak("2020-09-13 00:00:00").unwrap()
+        );
+    }
+
+    #[test]
+    fn durations_and_times_convert_into_each_other() {
+        assert_eq!(TimeArg::Months(1).duration(now()), DAY * 30);
+        assert_eq!(TimeArg::Duration(DAY).time(now()), now() + DAY);
+        assert_eq!(
+            TimeArg::At(now() - DAY).duration(now()),
+            Duration::from_secs(0)
+        );
+    }
+
+    #[test]
+    fn absurd_durations_do_not_panic() {
+        let forever = TimeArg::Duration(Duration::from_secs(u64::MAX));
+        assert_eq!(forever.time(now()), from_civil_date(LATEST_YEAR + 1, 1, 1));
+        assert_eq!(forever.ago(now()), UNIX_EPOCH);
+        // Too many months, left to humantime.
+        assert!(!matches!(
+            "4294967295 years".parse::<TimeArg>(),
+            Ok(TimeArg::Months(_))
+        ));
+        assert!(TimeArg::Months(u32::MAX).time(now()) > now());
+    }
+}
@@ -0,0 +1,344 @@
+//! Graphviz DOT and Mermaid export of the locations and of the item types stored there.
+//!
+//! Locations written as paths, like `Kitchen/Pantry`, are nested in their parent locations.
+//! Each item type stored at a location is linked to it, in red when an item instance there
+//! is expired, and the node of an item type grows with its quantity.
+
+use inv_manager::path::segments;
+use inv_manager::*;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The formats `graph-locations` can write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Dot,
+    /// A flowchart to paste in Markdown.
+    Mermaid,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "mermaid" => Ok(Format::Mermaid),
+            _ => Err(format!("unknown format {}, expected dot or mermaid", s)),
+        }
+    }
+}
+
+/// A location, named by its last segment, under its parent location when it has one.
+struct Location {
+    name: String,
+    parent: Option<usize>,
+}
+
+/// What is stored of an item type at a location.
+struct Stored {
+    location: usize,
+    type_id: TypeId,
+    quantity: f32,
+    expired: bool,
+}
+
+/// The locations and the item types of the item instances that are not removed, in the
+/// order of their nodes.
+struct Graph {
+    locations: Vec<Location>,
+    /// The item types stored somewhere, with their total quantity.
+    types: Vec<(TypeId, f32)>,
+    stored: Vec<Stored>,
+}
+
+impl Graph {
+    fn new(inventory: &Inventory, now: SystemTime) -> Self {
+        // The folded path of each location, ordered, with its index.
+        let mut paths = BTreeMap::<Vec<String>, usize>::new();
+        let mut locations = Vec::<Location>::new();
+        let mut stored = BTreeMap::<(usize, TypeId), (f32, bool)>::new();
+        let mut located = inventory
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+            .filter_map(|ii| ii.location.as_ref().map(|l| (ii, segments(l))))
+            .filter(|(_, path)| !path.is_empty())
+            .collect::<Vec<_>>();
+        located.sort_by_cached_key(|(ii, path)| {
+            (path.iter().map(|s| fold_case(s)).collect::<Vec<_>>(), ii.id)
+        });
+        for (ii, path) in located {
+            let mut parent = None;
+            for depth in 1..=path.len() {
+                let key = path[..depth].iter().map(|s| fold_case(s)).collect();
+                let index = *paths.entry(key).or_insert_with(|| {
+                    locations.push(Location {
+                        name: path[depth - 1].clone(),
+                        parent,
+                    });
+                    locations.len() - 1
+                });
+                parent = Some(index);
+            }
+            let location = parent.expect("a location has at least one segment");
+            let entry = stored
+                .entry((location, ii.item_type))
+                .or_insert((0.0, false));
+            entry.0 += ii.quantity;
+            entry.1 |= ii.expires_at.map(|exp| exp <= now).unwrap_or(false);
+        }
+
+        let mut types = BTreeMap::<TypeId, f32>::new();
+        for ((_, type_id), (quantity, _)) in &stored {
+            *types.entry(*type_id).or_insert(0.0) += quantity;
+        }
+        Graph {
+            locations,
+            types: types.into_iter().collect(),
+            stored: stored
+                .into_iter()
+                .map(|((location, type_id), (quantity, expired))| Stored {
+                    location,
+                    type_id,
+                    quantity,
+                    expired,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Renders the graph of the locations of the item instances that are not removed.
+pub fn render(inventory: &Inventory, now: SystemTime, format: Format) -> String {
+    let graph = Graph::new(inventory, now);
+    match format {
+        Format::Dot => dot(inventory, &graph),
+        Format::Mermaid => mermaid(inventory, &graph),
+    }
+}
+
+/// The name and the quantity of an item type, with its unit.
+fn type_label(inventory: &Inventory, type_id: TypeId, quantity: f32) -> (String, String) {
+    let unit = inventory
+        .item_types
+        .iter()
+        .find(|t| t.id == type_id)
+        .and_then(|t| t.unit.as_ref());
+    let quantity = match unit {
+        Some(unit) => format!("{} {}", quantity, unit),
+        None => quantity.to_string(),
+    };
+    (inventory.type_name(type_id), quantity)
+}
+
+/// The width of the node of an item type in inches, growing slowly with its quantity.
+fn type_width(quantity: f32) -> f32 {
+    0.75 + 0.5 * quantity.max(0.0).ln_1p()
+}
+
+fn dot(inventory: &Inventory, graph: &Graph) -> String {
+    let mut out = String::from("digraph locations {\n    rankdir=LR;\n");
+    out.push_str("    node [fontname=\"sans-serif\"];\n");
+    for (i, location) in graph.locations.iter().enumerate() {
+        out.push_str(&format!(
+            "    loc{} [label=\"{}\", shape=folder];\n",
+            i,
+            escape_dot(&location.name)
+        ));
+    }
+    for (type_id, quantity) in &graph.types {
+        let (name, quantity_label) = type_label(inventory, *type_id, *quantity);
+        out.push_str(&format!(
+            "    type{} [label=\"{}\\n{}\", shape=ellipse, width={:.2}];\n",
+            type_id,
+            escape_dot(&name),
+            escape_dot(&quantity_label),
+            type_width(*quantity)
+        ));
+    }
+    for (i, location) in graph.locations.iter().enumerate() {
+        if let Some(parent) = location.parent {
+            out.push_str(&format!("    loc{} -> loc{} [style=dashed];\n", parent, i));
+        }
+    }
+    for s in &graph.stored {
+        let color = if s.expired { ", color=red" } else { "" };
+        out.push_str(&format!(
+            "    loc{} -> type{} [label=\"{}\"{}];\n",
+            s.location, s.type_id, s.quantity, color
+        ));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn mermaid(inventory: &Inventory, graph: &Graph) -> String {
+    let mut out = String::from("flowchart LR\n");
+    for (i, location) in graph.locations.iter().enumerate() {
+        out.push_str(&format!(
+            "    loc{}[\"{}\"]\n",
+            i,
+            escape_mermaid(&location.name)
+        ));
+    }
+    for (type_id, quantity) in &graph.types {
+        let (name, quantity_label) = type_label(inventory, *type_id, *quantity);
+        out.push_str(&format!(
+            "    type{}([\"{} ({})\"])\n",
+            type_id,
+            escape_mermaid(&name),
+            escape_mermaid(&quantity_label)
+        ));
+    }
+    // Links are styled by their index, in the order they are written.
+    let mut links = 0;
+    for (i, location) in graph.locations.iter().enumerate() {
+        if let Some(parent) = location.parent {
+            out.push_str(&format!("    loc{} -.-> loc{}\n", parent, i));
+            links += 1;
+        }
+    }
+    let mut expired = Vec::new();
+    for s in &graph.stored {
+        out.push_str(&format!(
+            "    loc{} -->|{}| type{}\n",
+            s.location, s.quantity, s.type_id
+        ));
+        if s.expired {
+            expired.push(links.to_string());
+        }
+        links += 1;
+    }
+    if !expired.is_empty() {
+        out.push_str(&format!(
+            "    linkStyle {} stroke:red,color:red\n",
+            expired.join(",")
+        ));
+    }
+    out
+}
+
+/// Escapes the characters that end or have a meaning in a DOT quoted string.
+fn escape_dot(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes the characters that end a Mermaid quoted label or are read as markup, as
+/// entity codes.
+fn escape_mermaid(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("#quot;"),
+            '#' => out.push_str("#35;"),
+            '<' => out.push_str("#lt;"),
+            '>' => out.push_str("#gt;"),
+            '\n' | '\r' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Rice in the pantry of the kitchen and in the cellar, some of it expired, and milk
+    /// in the fridge of the kitchen, with names that need escaping.
+    fn inventory(start: SystemTime) -> Inventory {
+        let mut inventory = Inventory::default();
+        let rice = inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("rice".to_string())
+                .unit(Some("kg".to_string()))
+                .build()
+                .unwrap(),
+        );
+        let milk = inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("\"Oat\" milk <#1>".to_string())
+                .build()
+                .unwrap(),
+        );
+        let instances: &[(TypeId, f32, &str, Option<SystemTime>)] = &[
+            (rice, 2.0, "Kitchen/Pantry", Some(start + DAY * 30)),
+            (rice, 1.5, "kitchen / pantry", Some(start - DAY)),
+            (rice, 5.0, "Cellar", None),
+            (milk, 1.0, "Kitchen/Fridge \\ top", Some(start + DAY)),
+            (milk, 3.0, "Cellar", None),
+        ];
+        for (type_id, quantity, location, expires_at) in instances {
+            inventory
+                .add_item_instance(
+                    ItemInstanceBuilder::default()
+                        .item_type(*type_id)
+                        .quantity(*quantity)
+                        .location(Some(location.to_string()))
+                        .expires_at(*expires_at)
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+        // Removed and unplaced item instances are left out.
+        let trashed = inventory
+            .add_item_instance(
+                ItemInstanceBuilder::default()
+                    .item_type(rice)
+                    .quantity(9.0)
+                    .location(Some("Attic".to_string()))
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        inventory.trash(trashed);
+        inventory
+            .add_item_instance(
+                ItemInstanceBuilder::default()
+                    .item_type(milk)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        inventory
+    }
+
+    #[test]
+    fn matches_golden_files() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let inventory = inventory(start);
+        assert_eq!(
+            render(&inventory, start, Format::Dot),
+            include_str!("../fixtures/graph/locations.dot")
+        );
+        assert_eq!(
+            render(&inventory, start, Format::Mermaid),
+            include_str!("../fixtures/graph/locations.mmd")
+        );
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        assert_eq!(escape_dot("a \"b\" \\c\nd"), "a \\\"b\\\" \\\\c\\nd");
+        assert_eq!(
+            escape_mermaid("a \"b\" <#c>"),
+            "a #quot;b#quot; #lt;#35;c#gt;"
+        );
+    }
+}
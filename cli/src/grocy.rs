@@ -0,0 +1,356 @@
+//! Import of the data exported from Grocy.
+//!
+//! The export directory contains the responses of the Grocy API `objects` endpoints:
+//! `products.json`, `stock.json` and optionally `locations.json`.
+//! Each Grocy field is converted using the rule tables below. Fields that have no rule
+//! are counted and reported as unmapped.
+
+use inv_manager::decimal::parse_decimal;
+use inv_manager::*;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::read;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Grocy uses this best before date for products that never expire.
+const NEVER_EXPIRES: &str = "2999-12-31";
+
+type Setter<T> = fn(&mut T, &Value);
+
+/// A Grocy product, to be converted into an item type.
+#[derive(Debug, Default)]
+struct Product {
+    id: Option<String>,
+    name: Option<String>,
+    min_stock: f32,
+    ttl: Option<Duration>,
+    location_id: Option<String>,
+}
+
+/// A Grocy stock entry, to be converted into an item instance.
+#[derive(Debug, Default)]
+struct StockEntry {
+    product_id: Option<String>,
+    amount: Option<f32>,
+    best_before: Option<SystemTime>,
+    purchased: Option<SystemTime>,
+    price: Option<f32>,
+    opened: bool,
+    opened_date: Option<SystemTime>,
+    location_id: Option<String>,
+}
+
+/// A Grocy location, used to fill the location of item instances.
+#[derive(Debug, Default)]
+struct Location {
+    id: Option<String>,
+    name: Option<String>,
+}
+
+const PRODUCT_RULES: &[(&str, Setter<Product>)] = &[
+    ("id", |p, v| p.id = string(v)),
+    ("name", |p, v| p.name = string(v)),
+    ("min_stock_amount", |p, v| {
+        p.min_stock = number(v).unwrap_or(0.0)
+    }),
+    ("default_best_before_days", |p, v| p.ttl = days(v)),
+    ("location_id", |p, v| p.location_id = string(v)),
+];
+
+const STOCK_RULES: &[(&str, Setter<StockEntry>)] = &[
+    ("product_id", |s, v| s.product_id = string(v)),
+    ("amount", |s, v| s.amount = number(v)),
+    ("best_before_date", |s, v| s.best_before = date(v)),
+    ("purchased_date", |s, v| s.purchased = date(v)),
+    ("price", |s, v| s.price = number(v)),
+    ("open", |s, v| {
+        s.opened = number(v).map(|n| n != 0.0).unwrap_or(false)
+    }),
+    ("opened_date", |s, v| s.opened_date = date(v)),
+    ("location_id", |s, v| s.location_id = string(v)),
+];
+
+const LOCATION_RULES: &[(&str, Setter<Location>)] = &[
+    ("id", |l, v| l.id = string(v)),
+    ("name", |l, v| l.name = string(v)),
+];
+
+/// The result of an import.
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    /// The number of item types created.
+    pub types_created: usize,
+    /// The number of products mapped to an item type that already existed with the same name.
+    pub types_reused: usize,
+    /// The number of item instances created.
+    pub instances_created: usize,
+    /// The number of stock entries that could not be imported.
+    pub skipped: usize,
+    /// The number of occurences of each field that was not imported, by "file.field" name.
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    /// Describes the import for humans.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!(
+            "Created {} item types, reused {} existing item types and created {} item instances.",
+            self.types_created, self.types_reused, self.instances_created
+        )];
+        if self.skipped > 0 {
+            lines.push(format!("Skipped {} stock entries.", self.skipped));
+        }
+        if !self.unmapped.is_empty() {
+            lines.push("Unmapped fields:".to_string());
+            self.unmapped
+                .iter()
+                .for_each(|(field, count)| lines.push(format!("  {} ({})", field, count)));
+        }
+        lines
+    }
+}
+
+/// Imports the Grocy export located in the specified directory into the inventory.
+pub fn import(dir: &Path, inventory: &mut Inventory) -> Result<Summary, Error> {
+    let products = read_records(&dir.join("products.json"))?;
+    let stock = read_records(&dir.join("stock.json"))?;
+    let locations_path = dir.join("locations.json");
+    let locations = if locations_path.exists() {
+        read_records(&locations_path)?
+    } else {
+        vec![]
+    };
+    Ok(import_records(&products, &stock, &locations, inventory))
+}
+
+/// Maps the raw Grocy records into the inventory.
+fn import_records(
+    products: &[Map<String, Value>],
+    stock: &[Map<String, Value>],
+    locations: &[Map<String, Value>],
+    inventory: &mut Inventory,
+) -> Summary {
+    let mut summary = Summary::default();
+
+    let location_names = locations
+        .iter()
+        .map(|r| apply(r, LOCATION_RULES, "locations", &mut summary))
+        .filter_map(|l: Location| Some((l.id?, l.name?)))
+        .collect::<BTreeMap<_, _>>();
+
+    // Grocy product id -> (item type id, default location)
+    let mut type_ids = BTreeMap::new();
+    for record in products {
+        let product: Product = apply(record, PRODUCT_RULES, "products", &mut summary);
+        let (id, name) = match (product.id, product.name) {
+            (Some(id), Some(name)) => (id, name),
+            _ => continue,
+        };
+        let existing = inventory
+            .item_types
+            .iter()
+            .find(|t| MatchMode::Exact.matches(&t.name, &name))
+            .map(|t| t.id);
+        let type_id = if let Some(type_id) = existing {
+            summary.types_reused += 1;
+            type_id
+        } else {
+            summary.types_created += 1;
+            inventory.add_item_type(
+                ItemTypeBuilder::default()
+                    .name(name)
+                    .minimum_quantity(product.min_stock)
+                    .ttl(product.ttl)
+                    .build()
+                    .expect("Failed to build item type"),
+            )
+        };
+        let location = product
+            .location_id
+            .and_then(|l| location_names.get(&l).cloned());
+        type_ids.insert(id, (type_id, location));
+    }
+
+    for record in stock {
+        let entry: StockEntry = apply(record, STOCK_RULES, "stock", &mut summary);
+        let (type_id, default_location) =
+            match entry.product_id.as_ref().and_then(|p| type_ids.get(p)) {
+                Some(t) => t.clone(),
+                None => {
+                    summary.skipped += 1;
+                    continue;
+                }
+            };
+        let quantity = entry.amount.unwrap_or(1.0);
+        let location = entry
+            .location_id
+            .and_then(|l| location_names.get(&l).cloned())
+            .or(default_location);
+        let mut item_instance = ItemInstanceBuilder::default()
+            .item_type(type_id)
+            .quantity(quantity)
+            .location(location)
+            // Grocy prices are per unit of stock.
+            .value(entry.price.map(|p| p * quantity))
+            .expires_at(entry.best_before)
+            .opened_at(if entry.opened {
+                entry.opened_date.or(entry.purchased)
+            } else {
+                None
+            })
+            .build()
+            .expect("Failed to build item instance");
+        item_instance.added_at = entry.purchased;
+        inventory
+            .insert_item_instance(item_instance)
+            .expect("Failed to insert imported item instance");
+        summary.instances_created += 1;
+    }
+    summary
+}
+
+/// Reads a json file containing an array of objects.
+fn read_records(path: &Path) -> Result<Vec<Map<String, Value>>, Error> {
+    let data =
+        read(path).map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+    serde_json::from_slice(&data)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+}
+
+/// Converts a record using the rules, counting the fields without a rule as unmapped.
+fn apply<T: Default>(
+    record: &Map<String, Value>,
+    rules: &[(&str, Setter<T>)],
+    file: &str,
+    summary: &mut Summary,
+) -> T {
+    let mut out = T::default();
+    for (field, value) in record {
+        if let Some((_, setter)) = rules.iter().find(|(name, _)| name == field) {
+            setter(&mut out, value);
+        } else {
+            *summary
+                .unmapped
+                .entry(format!("{}.{}", file, field))
+                .or_insert(0) += 1;
+        }
+    }
+    out
+}
+
+/// Grocy returns most values as strings, even numbers.
+fn string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn number(value: &Value) -> Option<f32> {
+    match value {
+        Value::Number(n) => n.as_f64().map(|n| n as f32),
+        Value::String(s) => parse_decimal(s).ok(),
+        _ => None,
+    }
+}
+
+/// A number of days, where zero or less means that the product never expires.
+fn days(value: &Value) -> Option<Duration> {
+    number(value)
+        .filter(|d| *d > 0.0)
+        .map(|d| Duration::from_secs((d * 24.0 * 60.0 * 60.0) as u64))
+}
+
+/// A date with an optional time, in the local time of the Grocy server.
+fn date(value: &Value) -> Option<SystemTime> {
+    let s = string(value)?;
+    if s.starts_with(NEVER_EXPIRES) {
+        return None;
+    }
+    humantime::parse_rfc3339_weak(&s)
+        .or_else(|_| humantime::parse_rfc3339_weak(&format!("{} 00:00:00", s)))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/grocy")
+    }
+
+    fn type_named<'a>(inventory: &'a Inventory, name: &str) -> &'a ItemType {
+        inventory
+            .item_types
+            .iter()
+            .find(|t| t.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn imports_fixture_export() {
+        let mut inventory = Inventory::default();
+        let summary = import(&fixture(), &mut inventory).unwrap();
+        assert_eq!(summary.types_created, 3);
+        assert_eq!(summary.types_reused, 0);
+        assert_eq!(summary.instances_created, 4);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.unmapped.get("products.description"), Some(&3));
+        assert_eq!(summary.unmapped.get("stock.stock_id"), Some(&5));
+
+        let milk = type_named(&inventory, "Milk");
+        assert_eq!(milk.minimum_quantity, 2.0);
+        assert_eq!(milk.ttl, Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        let salt = type_named(&inventory, "Salt");
+        assert_eq!(salt.ttl, None);
+
+        let milk_instances = inventory.get_instances_for_type(milk.id).unwrap();
+        assert_eq!(milk_instances.len(), 2);
+        let opened = milk_instances
+            .iter()
+            .find(|ii| ii.opened_at.is_some())
+            .unwrap();
+        assert_eq!(opened.quantity, 0.5);
+        assert_eq!(opened.value, Some(0.75));
+        assert_eq!(opened.location.as_deref(), Some("Fridge"));
+        assert_eq!(
+            opened.expires_at,
+            Some(humantime::parse_rfc3339_weak("2020-01-20 00:00:00").unwrap())
+        );
+        assert_eq!(
+            opened.added_at,
+            Some(humantime::parse_rfc3339_weak("2020-01-10 00:00:00").unwrap())
+        );
+
+        let salt_instances = inventory.get_instances_for_type(salt.id).unwrap();
+        assert_eq!(salt_instances[0].expires_at, None);
+        assert_eq!(salt_instances[0].location.as_deref(), Some("Pantry"));
+    }
+
+    #[test]
+    fn reuses_types_with_the_same_name() {
+        let mut inventory = Inventory::default();
+        let existing = inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("milk".to_string())
+                .build()
+                .unwrap(),
+        );
+        let summary = import(&fixture(), &mut inventory).unwrap();
+        assert_eq!(summary.types_created, 2);
+        assert_eq!(summary.types_reused, 1);
+        assert_eq!(inventory.item_types.len(), 3);
+        assert_eq!(inventory.get_instances_for_type(existing).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn missing_export_is_an_error() {
+        let mut inventory = Inventory::default();
+        assert!(import(&fixture().join("missing"), &mut inventory).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! The program run after every command that changed the inventory files, set by
+//! `post_mutation_hook` in the settings, like a script committing the working directory to
+//! git or syncing it to a server.
+//!
+//! It runs in the working directory once the files are saved, with the machine mode json
+//! result of the command on stdin and these environment variables:
+//! - `IMGOAT_COMMAND`: the name of the subcommand, like `ci`.
+//! - `IMGOAT_AFFECTED_IDS`: the ids of the item instances changed, separated by commas.
+//! - `IMGOAT_AFFECTED_TYPE_IDS`: the ids of the item types changed, separated by commas.
+//! - `IMGOAT_INVENTORY`: the name of the inventory.
+//! - `IMGOAT_WORKDIR`: the working directory.
+//!
+//! Its output goes to stderr, so that it doesn't mix with the output of the command. It is
+//! killed after `hook_timeout_secs`. A failure never undoes the save: it is a warning, or an
+//! error making inv exit with a failure with `"hook_failure": "error"`.
+
+use crate::result::CommandResult;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// How long a hook can run before it is killed, by default.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// What a failing hook does to the outcome of the command.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    /// Prints a warning, the command still succeeds.
+    #[default]
+    Warn,
+    /// The command fails, although its changes are saved.
+    Error,
+}
+
+/// Why a hook failed.
+#[derive(Debug)]
+pub enum HookError {
+    /// The program could not be started or fed its input.
+    Spawn(io::Error),
+    /// The program exited with a failure.
+    Failed(ExitStatus),
+    /// The program was still running after the timeout, and was killed.
+    TimedOut(Duration),
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookError::Spawn(e) => write!(f, "it could not be run: {}", e),
+            HookError::Failed(status) => write!(f, "it exited with {}", status),
+            HookError::TimedOut(timeout) => write!(
+                f,
+                "it was killed after running for {}",
+                humantime::format_duration(*timeout)
+            ),
+        }
+    }
+}
+
+/// A hook to run, with what it is told about the command.
+#[derive(Debug, Clone)]
+pub struct Hook {
+    pub program: PathBuf,
+    /// The directory the hook runs in, the working directory.
+    pub dir: PathBuf,
+    pub timeout: Duration,
+}
+
+impl Hook {
+    /// The environment variables describing a command to the hook.
+    pub fn env(result: &CommandResult, inventory: &str, workdir: &Path) -> Vec<(String, String)> {
+        let ids = |ids: Vec<u32>| {
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        vec![
+            ("IMGOAT_COMMAND".to_string(), result.command.to_string()),
+            (
+                "IMGOAT_AFFECTED_IDS".to_string(),
+                ids(result
+                    .instance_changes
+                    .iter()
+                    .map(|c| c.id.into())
+                    .collect()),
+            ),
+            (
+                "IMGOAT_AFFECTED_TYPE_IDS".to_string(),
+                ids(result.type_changes.iter().map(|c| c.id.into()).collect()),
+            ),
+            ("IMGOAT_INVENTORY".to_string(), inventory.to_string()),
+            ("IMGOAT_WORKDIR".to_string(), workdir.display().to_string()),
+        ]
+    }
+
+    /// Runs the hook with the environment variables and `input` on stdin, waiting for it to
+    /// exit until the timeout.
+    pub fn run(&self, env: &[(String, String)], input: &str) -> Result<(), HookError> {
+        let mut child = Command::new(&self.program)
+            .current_dir(&self.dir)
+            .envs(env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::from(io::stderr()))
+            .spawn()
+            .map_err(HookError::Spawn)?;
+        // Written from a thread, so that a hook not reading its input is still timed out.
+        // One exiting without reading it closes the pipe, which isn't a failure.
+        let writer = child.stdin.take().map(|mut stdin| {
+            let input = input.to_string();
+            std::thread::spawn(move || match stdin.write_all(input.as_bytes()) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+                _ => Ok(()),
+            })
+        });
+        let started = Instant::now();
+        let status = loop {
+            match child.try_wait().map_err(HookError::Spawn)? {
+                Some(status) => break status,
+                None if started.elapsed() >= self.timeout => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(HookError::TimedOut(self.timeout));
+                }
+                None => sleep(Duration::from_millis(10)),
+            }
+        };
+        if let Some(writer) = writer {
+            writer
+                .join()
+                .expect("The input of the hook could not be written")
+                .map_err(HookError::Spawn)?;
+        }
+        if status.success() {
+            Ok(())
+        } else {
+            Err(HookError::Failed(status))
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all, set_permissions, write};
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A stub hook in a new directory, a shell script whose body is `script`.
+    fn stub(test: &str, script: &str) -> (PathBuf, Hook) {
+        let dir = std::env::temp_dir().join(format!("inv_hooks_{}_{}", test, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        let program = dir.join("hook.sh");
+        write(&program, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        set_permissions(&program, PermissionsExt::from_mode(0o755)).unwrap();
+        let hook = Hook {
+            program,
+            dir: dir.clone(),
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECS),
+        };
+        (dir, hook)
+    }
+
+    #[test]
+    fn hooks_get_the_environment_and_the_result() {
+        let (dir, hook) = stub(
+            "env",
+            "cat > \"$IMGOAT_WORKDIR/input.json\"\n\
+             echo \"$IMGOAT_COMMAND $IMGOAT_AFFECTED_IDS $IMGOAT_INVENTORY\" > \"$IMGOAT_WORKDIR/env\"",
+        );
+        let env = vec![
+            ("IMGOAT_COMMAND".to_string(), "ci".to_string()),
+            ("IMGOAT_AFFECTED_IDS".to_string(), "3,4".to_string()),
+            ("IMGOAT_INVENTORY".to_string(), "home".to_string()),
+            ("IMGOAT_WORKDIR".to_string(), dir.display().to_string()),
+        ];
+        hook.run(&env, "{\"command\":\"ci\"}").unwrap();
+        assert_eq!(read_to_string(dir.join("env")).unwrap(), "ci 3,4 home\n");
+        assert_eq!(
+            read_to_string(dir.join("input.json")).unwrap(),
+            "{\"command\":\"ci\"}"
+        );
+        remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failures_and_timeouts_are_errors() {
+        let (dir, hook) = stub("exit", "exit 3");
+        assert!(
+            matches!(hook.run(&[], ""), Err(HookError::Failed(status)) if status.code() == Some(3))
+        );
+
+        let (sleeping, mut hook) = stub("sleep", "sleep 5");
+        hook.timeout = Duration::from_millis(100);
+        let started = Instant::now();
+        assert!(matches!(hook.run(&[], ""), Err(HookError::TimedOut(_))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        remove_dir_all(&sleeping).unwrap();
+
+        let missing = Hook {
+            program: dir.join("missing.sh"),
+            dir: dir.clone(),
+            timeout: Duration::from_secs(1),
+        };
+        assert!(matches!(missing.run(&[], ""), Err(HookError::Spawn(_))));
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! iCalendar (RFC 5545) export of the expiry dates.
+
+use inv_manager::*;
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The maximum length of a content line in octets, excluding the line break.
+const MAX_LINE: usize = 75;
+
+/// Renders a calendar with one all-day event per item instance that is not removed
+/// and that expires before `now + within`, or at any time when `within` is not set.
+/// When `alarm_days` is set, each event has an alarm that many days before the expiry.
+pub fn render(
+    inventory: &Inventory,
+    inventory_name: &str,
+    now: SystemTime,
+    within: Option<Duration>,
+    alarm_days: Option<u32>,
+) -> String {
+    let mut instances = inventory
+        .item_instances
+        .iter()
+        .filter(|ii| ii.removed_at.is_none())
+        .filter_map(|ii| ii.expires_at.map(|exp| (ii, exp)))
+        .filter(|(_, exp)| {
+            within
+                .and_then(|w| time_math::checked_add_duration(now, w))
+                .is_none_or(|until| *exp <= until)
+        })
+        .collect::<Vec<_>>();
+    instances.sort_by(|(a, _), (b, _)| a.cmp_expiry(b));
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Inventory Managoat//inv//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for (ii, exp) in instances {
+        let type_name = inventory.type_name(ii.item_type);
+        let summary = format!("Expires: {} ({})", type_name, ii.quantity);
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!(
+            "UID:{}-{}@inventory-managoat",
+            escape_text(inventory_name),
+            ii.id
+        ));
+        lines.push(format!("DTSTAMP:{}", date_time(now)));
+        lines.push(format!("DTSTART;VALUE=DATE:{}", date(exp)));
+        lines.push(format!("DTEND;VALUE=DATE:{}", date(exp + DAY)));
+        lines.push(format!("SUMMARY:{}", escape_text(&summary)));
+        if let Some(days) = alarm_days {
+            lines.push("BEGIN:VALARM".to_string());
+            lines.push("ACTION:DISPLAY".to_string());
+            lines.push(format!("TRIGGER:-P{}D", days));
+            lines.push(format!("DESCRIPTION:{}", escape_text(&summary)));
+            lines.push("END:VALARM".to_string());
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|l| fold(l)).collect::<String>()
+}
+
+/// Formats the UTC date as `YYYYMMDD`.
+fn date(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()[..10].replace('-', "")
+}
+
+/// Formats the UTC date and time as `YYYYMMDDTHHMMSSZ`.
+fn date_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time)
+        .to_string()
+        .replace(['-', ':'], "")
+}
+
+/// Escapes the characters that have a meaning in TEXT values.
+fn escape_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\;"),
+            ',' => out.push_str("\\,"),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Splits a content line into lines of at most 75 octets, each continuation line
+/// starting with a space, and terminates it with CRLF. Never splits a UTF-8 character.
+fn fold(line: &str) -> String {
+    let mut out = String::with_capacity(line.len() + 8);
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE {
+            out.push_str("\r\n ");
+            // The leading space counts towards the length of the continuation line.
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Reverts the folding and splits the calendar into content lines.
+    fn unfold(calendar: &str) -> Vec<String> {
+        calendar
+            .replace("\r\n ", "")
+            .split("\r\n")
+            .filter(|l| !l.is_empty())
+            .map(|l| l.to_string())
+            .collect()
+    }
+
+    fn inventory(start: SystemTime) -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(start)));
+        let rice = inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("rice".to_string())
+                .build()
+                .unwrap(),
+        );
+        let long = inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("Extra virgin olive oil, cold pressed; from the small farm down the road — ünïcödé".to_string())
+                .build()
+                .unwrap(),
+        );
+        for (type_id, quantity, days) in &[(rice, 2.0, 3), (long, 0.5, 1), (rice, 1.0, 200)] {
+            inventory
+                .add_item_instance(
+                    ItemInstanceBuilder::default()
+                        .item_type(*type_id)
+                        .quantity(*quantity)
+                        .expires_at(Some(start + DAY * *days))
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+        inventory
+    }
+
+    #[test]
+    fn matches_golden_file() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let calendar = render(&inventory(start), "home", start, Some(DAY * 90), Some(2));
+        let golden = include_str!("../fixtures/ical/expiries.ics");
+        assert_eq!(calendar, golden);
+    }
+
+    #[test]
+    fn lines_are_folded_and_round_trip() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let calendar = render(&inventory(start), "home", start, None, None);
+        assert!(calendar.ends_with("\r\n"));
+        for line in calendar.split("\r\n") {
+            assert!(line.len() <= MAX_LINE, "line too long: {:?}", line);
+        }
+        let lines = unfold(&calendar);
+        assert_eq!(lines.iter().filter(|l| *l == "BEGIN:VEVENT").count(), 3);
+        assert!(lines.contains(
+            &"SUMMARY:Expires: Extra virgin olive oil\\, cold pressed\\; from the small farm down the road — ünïcödé (0.5)".to_string()
+        ));
+    }
+
+    #[test]
+    fn text_is_escaped() {
+        assert_eq!(escape_text("a,b;c\\d\ne"), "a\\,b\\;c\\\\d\\ne");
+    }
+}
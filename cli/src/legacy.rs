@@ -0,0 +1,480 @@
+//! Import of the item types and item instances printed by the minimal mode of the first
+//! versions, before the tab separated records of `record`.
+//!
+//! Each line is a record whose fields are joined by `;`, without escaping. Item types are
+//!
+//! ```text
+//! id;name;minimum quantity;ttl;opened by default
+//! ```
+//!
+//! and item instances are
+//!
+//! ```text
+//! id;item type id;quantity;model;serial;extra;location;value;opened at;expires at
+//! ```
+//!
+//! with durations like `5days 2h` and times in RFC 3339, like humantime prints them. Missing
+//! trailing fields and empty fields are not set, blank lines are ignored. A name can contain
+//! `;`, since the fields after it are known, but the text fields of item instances can't.
+
+use crate::error::{CliError, Operation};
+use inv_manager::decimal::parse_decimal;
+use inv_manager::*;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::read_to_string;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The separator of the fields of the legacy records.
+pub const SEPARATOR: char = ';';
+
+const TYPE_FIELDS: usize = 5;
+const INSTANCE_FIELDS: usize = 10;
+
+/// What became of a line of the legacy files.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportedRecord {
+    /// `type` or `instance`.
+    pub kind: &'static str,
+    /// The line in its file, from 1.
+    pub line: usize,
+    /// The id in the legacy file, None when it could not be read.
+    pub legacy_id: Option<u32>,
+    /// The id in the inventory, None when the line was skipped.
+    pub id: Option<u32>,
+    /// The name of the item type, or of the item type of the item instance.
+    pub name: String,
+    /// `kept` when the legacy id was kept, `remapped` when it was taken, `reused` for an item
+    /// type with the name of an existing one, otherwise why the line was skipped.
+    pub result: String,
+}
+
+/// Parses a legacy item type. Fields beyond the 5 expected are part of the name.
+pub fn parse_type(line: &str) -> Result<ItemType, String> {
+    let mut fields = line.split(SEPARATOR).collect::<Vec<_>>();
+    let name;
+    if fields.len() > TYPE_FIELDS {
+        let end = fields.len() + 2 - TYPE_FIELDS;
+        name = fields[1..end].join(";");
+        fields.splice(1..end, [name.as_str()]);
+    }
+    type_from(&fields)
+}
+
+fn type_from(fields: &[&str]) -> Result<ItemType, String> {
+    let field = |i: usize| fields.get(i).map(|f| f.trim()).filter(|f| !f.is_empty());
+    let name = fields.get(1).map(|n| n.trim()).unwrap_or_default();
+    if name.is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    Ok(ItemType {
+        id: id(field(0))?,
+        name: name.to_string(),
+        minimum_quantity: field(2)
+            .map(|m| number(m, "minimum quantity"))
+            .transpose()?
+            .unwrap_or(0.0),
+        ttl: field(3)
+            .map(|t| humantime::parse_duration(t).map_err(|e| format!("invalid ttl {}: {}", t, e)))
+            .transpose()?,
+        opened_by_default: field(4)
+            .map(|o| o.parse::<bool>().map_err(|_| format!("invalid bool {}", o)))
+            .transpose()?
+            .unwrap_or(false),
+        ..ItemType::default()
+    })
+}
+
+/// Parses a legacy item instance, whose `item_type` is the legacy id of its item type.
+pub fn parse_instance(line: &str) -> Result<ItemInstance, String> {
+    let fields = line.split(SEPARATOR).collect::<Vec<_>>();
+    if fields.len() > INSTANCE_FIELDS {
+        return Err(format!(
+            "{} fields instead of at most {}, a text field probably contains ;",
+            fields.len(),
+            INSTANCE_FIELDS
+        ));
+    }
+    let field = |i: usize| fields.get(i).map(|f| f.trim()).filter(|f| !f.is_empty());
+    let text = |i: usize| field(i).map(str::to_string);
+    let quantity = field(2)
+        .map(|q| number(q, "quantity"))
+        .transpose()?
+        .unwrap_or(1.0);
+    if !quantity.is_finite() || quantity < 0.0 {
+        return Err(format!("invalid quantity {}", quantity));
+    }
+    let item_type = field(1).ok_or("the item type is missing")?;
+    Ok(ItemInstance {
+        id: id(field(0))?,
+        item_type: item_type
+            .parse()
+            .map_err(|_| format!("invalid item type id {}", item_type))?,
+        quantity,
+        model: text(3),
+        serial: text(4),
+        extra: text(5),
+        location: text(6),
+        value: field(7).map(|v| number(v, "value")).transpose()?,
+        opened_at: field(8).map(time).transpose()?,
+        expires_at: field(9).map(time).transpose()?,
+        ..ItemInstance::default()
+    })
+}
+
+fn id<I: FromStr>(field: Option<&str>) -> Result<I, String> {
+    let field = field.ok_or("the id is missing")?;
+    field.parse().map_err(|_| format!("invalid id {}", field))
+}
+
+fn number(field: &str, what: &str) -> Result<f32, String> {
+    parse_decimal(field).map_err(|_| format!("invalid {} {}", what, field))
+}
+
+fn time(field: &str) -> Result<SystemTime, String> {
+    humantime::parse_rfc3339_weak(field).map_err(|e| format!("invalid time {}: {}", field, e))
+}
+
+/// Reads the legacy files and imports them, see `import_lines`.
+pub fn import(
+    types_path: &Path,
+    instances_path: &Path,
+    inventory: &mut Inventory,
+) -> Result<Vec<ImportedRecord>, CliError> {
+    let read =
+        |path: &Path| read_to_string(path).map_err(|e| CliError::io(Operation::Read, path, e));
+    Ok(import_lines(
+        &read(types_path)?,
+        &read(instances_path)?,
+        inventory,
+    ))
+}
+
+/// How an id of the legacy files is brought into the inventory.
+enum Placement {
+    Keep,
+    Remap,
+    Reuse(TypeId),
+}
+
+/// Imports legacy item types and item instances, returning what became of every line.
+///
+/// An item type with the name of an existing one, ignoring case, is reused. Otherwise a
+/// legacy id is kept when it is above every id ever given in the inventory and not used
+/// twice in the file, so that the ids of deleted item types and item instances are not
+/// given again, and the others get new ids after them. Item instances are added now, and
+/// those whose item type is unknown are skipped.
+pub fn import_lines(
+    types: &str,
+    instances: &str,
+    inventory: &mut Inventory,
+) -> Vec<ImportedRecord> {
+    let (next_type_id, next_instance_id) = inventory.next_ids();
+    let mut records = vec![];
+
+    let mut claimed = HashSet::new();
+    let mut placed_types = vec![];
+    for (line, text) in lines(types) {
+        let item_type = match parse_type(text) {
+            Ok(item_type) => item_type,
+            Err(e) => {
+                records.push(skipped("type", line, text, e));
+                continue;
+            }
+        };
+        let existing = inventory
+            .item_types
+            .iter()
+            .find(|t| MatchMode::Exact.matches(&t.name, &item_type.name));
+        let placement = match existing {
+            Some(t) => Placement::Reuse(t.id),
+            None if item_type.id >= next_type_id && claimed.insert(item_type.id) => Placement::Keep,
+            None => Placement::Remap,
+        };
+        placed_types.push((records.len(), item_type, placement));
+        records.push(ImportedRecord {
+            kind: "type",
+            line,
+            legacy_id: None,
+            id: None,
+            name: String::new(),
+            result: String::new(),
+        });
+    }
+    // The kept ids first, so that the new ids come after them.
+    placed_types.sort_by_key(|(_, _, placement)| !matches!(placement, Placement::Keep));
+    let mut type_ids = HashMap::new();
+    for (index, item_type, placement) in placed_types {
+        let legacy_id = item_type.id;
+        let name = item_type.name.clone();
+        let (id, result) = match placement {
+            Placement::Keep => {
+                inventory.item_types.push(item_type);
+                (legacy_id, "kept")
+            }
+            Placement::Remap => (inventory.add_item_type(item_type), "remapped"),
+            Placement::Reuse(id) => (id, "reused"),
+        };
+        type_ids.entry(legacy_id).or_insert(id);
+        records[index] = ImportedRecord {
+            kind: "type",
+            line: records[index].line,
+            legacy_id: Some(legacy_id.into()),
+            id: Some(id.into()),
+            name,
+            result: result.to_string(),
+        };
+    }
+
+    let now = inventory.now();
+    let mut claimed = HashSet::new();
+    let mut placed_instances = vec![];
+    for (line, text) in lines(instances) {
+        let mut item_instance = match parse_instance(text) {
+            Ok(item_instance) => item_instance,
+            Err(e) => {
+                records.push(skipped("instance", line, text, e));
+                continue;
+            }
+        };
+        let legacy_type = item_instance.item_type;
+        item_instance.item_type = match type_ids.get(&legacy_type) {
+            Some(id) => *id,
+            None => {
+                records.push(ImportedRecord {
+                    legacy_id: Some(item_instance.id.into()),
+                    ..skipped(
+                        "instance",
+                        line,
+                        "",
+                        format!("unknown item type {}", legacy_type),
+                    )
+                });
+                continue;
+            }
+        };
+        let keep = item_instance.id >= next_instance_id && claimed.insert(item_instance.id);
+        placed_instances.push((records.len(), item_instance, keep));
+        records.push(ImportedRecord {
+            kind: "instance",
+            line,
+            legacy_id: None,
+            id: None,
+            name: String::new(),
+            result: String::new(),
+        });
+    }
+    placed_instances.sort_by_key(|(_, _, keep)| !keep);
+    for (index, mut item_instance, keep) in placed_instances {
+        let legacy_id = item_instance.id;
+        let name = inventory.type_name(item_instance.item_type);
+        let (id, result) = if keep {
+            item_instance.added_at = Some(now);
+            item_instance.initial_quantity = Some(item_instance.quantity);
+            item_instance.label = Some(inventory.generate_label(item_instance.item_type));
+            inventory.item_instances.push(item_instance);
+            (legacy_id, "kept")
+        } else {
+            let id = inventory
+                .insert_item_instance(item_instance)
+                .expect("The item type of an imported item instance exists");
+            (id, "remapped")
+        };
+        records[index] = ImportedRecord {
+            kind: "instance",
+            line: records[index].line,
+            legacy_id: Some(legacy_id.into()),
+            id: Some(id.into()),
+            name,
+            result: result.to_string(),
+        };
+    }
+    records
+}
+
+/// The lines that are not blank, numbered from 1.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.trim().is_empty())
+}
+
+fn skipped(
+    kind: &'static str,
+    line: usize,
+    text: &str,
+    reason: impl Into<String>,
+) -> ImportedRecord {
+    ImportedRecord {
+        kind,
+        line,
+        legacy_id: text
+            .split(SEPARATOR)
+            .next()
+            .and_then(|id| id.trim().parse().ok()),
+        id: None,
+        name: String::new(),
+        result: format!("skipped: {}", reason.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    const TYPES: &str = include_str!("../fixtures/legacy/types.txt");
+    const INSTANCES: &str = include_str!("../fixtures/legacy/instances.txt");
+
+    /// The kind, line, legacy id, id and result of each record.
+    type Results<'a> = Vec<(&'a str, usize, Option<u32>, Option<u32>, &'a str)>;
+
+    fn results(records: &[ImportedRecord]) -> Results<'_> {
+        records
+            .iter()
+            .map(|r| (r.kind, r.line, r.legacy_id, r.id, r.result.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn fields_follow_the_legacy_display_format() {
+        let rice = parse_type("3;rice;2.5;5days 2h;true").unwrap();
+        assert_eq!(rice.id, TypeId(3));
+        assert_eq!(rice.minimum_quantity, 2.5);
+        assert_eq!(
+            rice.ttl,
+            Some(Duration::from_secs(5 * 24 * 3600 + 2 * 3600))
+        );
+        assert!(rice.opened_by_default);
+
+        let short = parse_type("4;salt").unwrap();
+        assert_eq!((short.minimum_quantity, short.ttl), (0.0, None));
+        assert_eq!(parse_type("5;a;b;c;1;;false").unwrap().name, "a;b;c");
+        assert!(parse_type("6;;1;;false").is_err());
+        assert!(parse_type("x;salt").is_err());
+
+        let bag = parse_instance("7;3;0.5;;SN-1;;pantry;;2020-01-10T00:00:00Z").unwrap();
+        assert_eq!(
+            (bag.id, bag.item_type, bag.quantity),
+            (InstanceId(7), TypeId(3), 0.5)
+        );
+        assert_eq!((bag.model, bag.serial.as_deref()), (None, Some("SN-1")));
+        assert_eq!(bag.location.as_deref(), Some("pantry"));
+        assert_eq!(
+            bag.opened_at,
+            Some(humantime::parse_rfc3339("2020-01-10T00:00:00Z").unwrap())
+        );
+        assert_eq!(bag.expires_at, None);
+        assert_eq!(parse_instance("8;3").unwrap().quantity, 1.0);
+        assert!(parse_instance("9;3;1;a;b;c;d;1;;;extra").is_err());
+        assert!(parse_instance("9;3;-1").is_err());
+    }
+
+    #[test]
+    fn fixture_lines_are_imported_or_reported() {
+        let mut inventory = Inventory::default();
+        let records = import_lines(TYPES, INSTANCES, &mut inventory);
+        assert_eq!(
+            results(&records),
+            vec![
+                ("type", 1, Some(1), Some(1), "kept"),
+                ("type", 2, Some(2), Some(2), "kept"),
+                ("type", 4, Some(5), Some(5), "kept"),
+                ("type", 5, Some(2), Some(8), "remapped"),
+                ("type", 6, None, None, "skipped: invalid id abc"),
+                ("type", 7, Some(7), Some(7), "kept"),
+                ("instance", 1, Some(1), Some(1), "kept"),
+                ("instance", 2, Some(4), Some(4), "kept"),
+                ("instance", 3, Some(2), Some(2), "kept"),
+                ("instance", 4, Some(3), None, "skipped: unknown item type 9"),
+                (
+                    "instance",
+                    5,
+                    Some(6),
+                    None,
+                    "skipped: 11 fields instead of at most 10, a text field probably contains ;"
+                ),
+                ("instance", 6, Some(4), Some(5), "remapped"),
+            ]
+        );
+        let names = inventory
+            .item_types
+            .iter()
+            .map(|t| (t.id.0, t.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                (1, "rice"),
+                (2, "Crème fraîche"),
+                (5, "nuts; bolts"),
+                (7, "spaced"),
+                (8, "duplicate id")
+            ]
+        );
+        let milk = &inventory.item_instances[1];
+        assert_eq!(milk.item_type, TypeId(2));
+        assert_eq!(milk.extra.as_deref(), Some("half \"full\""));
+        assert_eq!(
+            milk.expires_at,
+            Some(humantime::parse_rfc3339("2020-01-20T10:30:00.250Z").unwrap())
+        );
+        assert_eq!(
+            inventory.item_instances[0].label.as_deref(),
+            Some("rice-01")
+        );
+        assert!(inventory
+            .item_instances
+            .iter()
+            .all(|ii| ii.added_at.is_some()));
+    }
+
+    #[test]
+    fn taken_ids_are_remapped_and_names_reused() {
+        let mut inventory = Inventory::default();
+        inventory.add_item_type(ItemType {
+            name: "Rice".to_string(),
+            ..ItemType::default()
+        });
+        inventory.add_item_type(ItemType {
+            name: "flour".to_string(),
+            ..ItemType::default()
+        });
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        let records = import_lines("1;rice;1\n2;oil\n3;salt", "1;1;2\n2;2\n", &mut inventory);
+        assert_eq!(
+            results(&records),
+            vec![
+                ("type", 1, Some(1), Some(1), "reused"),
+                ("type", 2, Some(2), Some(4), "remapped"),
+                ("type", 3, Some(3), Some(3), "kept"),
+                ("instance", 1, Some(1), Some(3), "remapped"),
+                ("instance", 2, Some(2), Some(2), "kept"),
+            ]
+        );
+        assert_eq!(inventory.item_instances[1].item_type, TypeId(4));
+        assert_eq!(inventory.item_instances[2].item_type, TypeId(1));
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/legacy");
+        let mut inventory = Inventory::default();
+        assert!(import(
+            &dir.join("types.txt"),
+            &dir.join("missing.txt"),
+            &mut inventory
+        )
+        .is_err());
+        assert!(inventory.item_types.is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+//! The inventory of a directory, like the consumables of a project, named by a
+//! `.inventory-managoat.toml` file in the current directory or in one of its parents, the
+//! nearest one winning:
+//!
+//! ```toml
+//! name = "lab"
+//! workdir = "inventory"
+//! format = "minimal"
+//! ```
+//!
+//! It has the fields of a profile, see `profiles`, and a relative workdir is relative to the
+//! directory of the file. Its values apply to the options that are not given, before those
+//! of the profile. `inv link` writes one, and `--no-local-config` leaves it out.
+
+use crate::error::{CliError, Operation};
+use crate::profiles::Profile;
+use std::fs::{read_to_string, write};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the local configuration file.
+pub const FILE_NAME: &str = ".inventory-managoat.toml";
+
+/// The nearest local configuration file, in `dir` or one of its parents.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|path| path.is_file())
+}
+
+/// Reads a local configuration file, with its workdir resolved against its directory.
+pub fn load(path: &Path) -> Result<Profile, CliError> {
+    let text = read_to_string(path).map_err(|e| CliError::io(Operation::Read, path, e))?;
+    let mut config: Profile = toml::from_str(&text).map_err(|e| {
+        CliError::io(
+            Operation::Parse,
+            path,
+            io::Error::new(io::ErrorKind::InvalidData, e),
+        )
+    })?;
+    if let (Some(workdir), Some(dir)) = (&config.workdir, path.parent()) {
+        config.workdir = Some(dir.join(workdir));
+    }
+    Ok(config)
+}
+
+/// Writes the local configuration file of `dir`, both absolute paths. The workdir is
+/// written relative to `dir` when it is inside it, so that the directory can be moved with
+/// its inventory.
+pub fn save(dir: &Path, config: &Profile) -> Result<PathBuf, CliError> {
+    let path = dir.join(FILE_NAME);
+    let mut config = config.clone();
+    if let Some(relative) = config
+        .workdir
+        .as_deref()
+        .and_then(|w| w.strip_prefix(dir).ok())
+    {
+        config.workdir = Some(if relative.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            relative.to_path_buf()
+        });
+    }
+    let text = toml::to_string(&config).map_err(|e| {
+        CliError::io(
+            Operation::Write,
+            &path,
+            io::Error::new(io::ErrorKind::InvalidData, e),
+        )
+    })?;
+    write(&path, text).map_err(|e| CliError::io(Operation::Write, &path, e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::Format;
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("inv_local_config_{}_{}", test, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn the_nearest_file_wins() {
+        let root = temp_dir("nearest");
+        let nested = root.join("project").join("src").join("deep");
+        create_dir_all(&nested).unwrap();
+        assert_eq!(find(&nested), None);
+
+        write(root.join(FILE_NAME), "name = \"home\"\n").unwrap();
+        assert_eq!(find(&nested), Some(root.join(FILE_NAME)));
+        let project = root.join("project");
+        write(project.join(FILE_NAME), "name = \"lab\"\n").unwrap();
+        assert_eq!(find(&nested), Some(project.join(FILE_NAME)));
+        assert_eq!(find(&root), Some(root.join(FILE_NAME)));
+        assert_eq!(
+            load(&project.join(FILE_NAME)).unwrap().name.as_deref(),
+            Some("lab")
+        );
+    }
+
+    #[test]
+    fn relative_workdirs_are_relative_to_the_file() {
+        let root = temp_dir("relative");
+        let path = root.join(FILE_NAME);
+        write(
+            &path,
+            "name = \"lab\"\nworkdir = \"../shared\"\nformat = \"machine\"\n",
+        )
+        .unwrap();
+        let config = load(&path).unwrap();
+        assert_eq!(config.workdir, Some(root.join("../shared")));
+        assert_eq!(config.format, Some(Format::Machine));
+
+        write(&path, "workdir = \"/srv/inventory\"\n").unwrap();
+        assert_eq!(load(&path).unwrap().workdir, Some("/srv/inventory".into()));
+
+        write(&path, "name = 3\n").unwrap();
+        assert!(matches!(
+            load(&path),
+            Err(CliError::Io {
+                operation: Operation::Parse,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn saved_workdirs_inside_the_directory_are_relative() {
+        let root = temp_dir("save");
+        let config = Profile {
+            workdir: Some(root.join("inventory")),
+            name: Some("lab".to_string()),
+            format: None,
+        };
+        let path = save(&root, &config).unwrap();
+        assert_eq!(
+            read_to_string(&path).unwrap(),
+            "workdir = \"inventory\"\nname = \"lab\"\n"
+        );
+        assert_eq!(load(&path).unwrap(), config);
+
+        let outside = Profile {
+            workdir: Some(PathBuf::from("/srv/inventory")),
+            ..config
+        };
+        save(&root, &outside).unwrap();
+        assert_eq!(load(&path).unwrap(), outside);
+    }
+}
@@ -1,9 +1,55 @@
+mod archive;
+mod autosave;
+mod config;
+mod crypto;
+mod error;
+mod events;
+mod examples;
+mod fuzzy_time;
+mod graph;
+#[cfg(feature = "grocy")]
+mod grocy;
+mod hooks;
+mod ical;
+mod legacy;
+mod local_config;
+mod metrics;
+mod migrations;
+mod profiles;
+mod prompt;
+mod publish;
+mod quantity;
+mod repl;
+#[macro_use]
+mod render;
+mod report;
+mod result;
+mod state;
+mod storage;
+mod uses;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+use error::{CliError, Operation};
+use fuzzy_time::TimeArg;
+use hooks::{Hook, HookFailure};
+use inv_manager::decimal::parse_decimal;
 use inv_manager::*;
-use prettytable::*;
+use legacy::ImportedRecord;
+use log::{debug, error, info, trace, warn};
+use quantity::QuantityArg;
+use render::{Column, Field, Grid, Layout, RendererKind, Style};
+use result::{
+    diff, Change, CommandResult, Dashboard, LocationMapping, MinimumCheck, PastState, Prices,
+    Replacement, ShoppingItem, UseSummary, ValueReport,
+};
 use std::fs::*;
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use storage::Recovery;
 use structopt::StructOpt;
+use uses::{UseArg, UseItem};
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -23,12 +69,132 @@ pub struct Manager {
     /// Showing the total quantity of each item type will be disabled.
     #[structopt(short, long)]
     pub minimal: bool,
+    /// Prints a header row naming the fields before the data in minimal mode.
+    /// Fields are separated by tabs, and tabs, newlines and backslashes inside of fields are escaped.
+    #[structopt(long)]
+    pub header: bool,
+    /// Prints the result of the command as a single json object instead of human readable text.
+    /// See the man page for the schema.
+    #[structopt(long)]
+    pub machine: bool,
+    /// How tables are printed: classic draws them with borders, compact fits them to the width
+    /// of the terminal, dropping less important columns and shortening long fields.
+    /// Defaults to compact on terminals narrower than 100 columns.
+    #[structopt(long, possible_values = &["classic", "compact"])]
+    pub renderer: Option<RendererKind>,
+    /// Adds the columns of when the item instances were added and removed to their tables.
+    /// They are always in the minimal and machine outputs.
+    #[structopt(long)]
+    pub timestamps: bool,
+    /// Removes the shelf life column, a bar of the part of their shelf life the item
+    /// instances have left, from their tables.
+    #[structopt(long)]
+    pub no_shelf_life: bool,
+    /// Gives new ids to the item types and item instances whose id is already used by an
+    /// earlier one, for example after editing the files by hand. Otherwise duplicate ids are an error.
+    #[structopt(long)]
+    pub fix_ids: bool,
+    /// Never asks questions, for example which item type is meant when several match a name.
+    /// Questions are only asked when stdin is a terminal.
+    #[structopt(long)]
+    pub no_interactive: bool,
+    /// Reads the inventory as a single json document from stdin instead of its files.
+    /// See the man page for the format.
+    #[structopt(long)]
+    pub stdin: bool,
+    /// Prints the inventory after the command as a single json document on stdout instead of
+    /// saving its files. Everything else is printed on stderr.
+    #[structopt(long)]
+    pub stdout: bool,
+    /// Reads the inventory from a file written by publish instead of its files. Only the
+    /// subcommands that read the inventory are accepted.
+    #[structopt(long, conflicts_with_all = &["stdin", "stdout", "fix-ids"])]
+    pub from_published: Option<PathBuf>,
+    /// Takes the options that are not given from this profile of the profiles file, see the
+    /// man page. Defaults to IMGOAT_PROFILE, then to the default profile.
+    #[structopt(long)]
+    pub profile: Option<String>,
+    /// Ignores the .inventory-managoat.toml file of the current directory or of its parents,
+    /// see link.
+    #[structopt(long)]
+    pub no_local_config: bool,
+    /// Requires the inventory files to be encrypted, creating a new inventory encrypted.
+    /// Encrypted inventories are otherwise recognized by their files, see encrypt.
+    // Named apart from the subcommand, which clap would otherwise take for the flag.
+    #[structopt(name = "encrypted", long = "encrypt")]
+    pub encrypt: bool,
+    /// Prints what inv does on stderr: once for how long loading, running the command,
+    /// saving and printing take, twice for the files read and written and the steps of the
+    /// listings too.
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u8,
     /// The action to execute on the inventory.
+    /// Without one, a summary of the inventory is printed.
     #[structopt(subcommand)]
-    pub command: Command,
+    pub command: Option<Command>,
 }
 
 impl Manager {
+    /// Takes the options that were not given from the chosen profile, see `profiles`.
+    /// `name_given` tells whether --name was given, which has a default.
+    pub fn apply_profile(&mut self, name_given: bool) -> std::result::Result<(), CliError> {
+        let profiles = match profiles::profiles_path() {
+            Some(path) => profiles::load(&path)?,
+            None => profiles::Profiles::default(),
+        };
+        let env = std::env::var(profiles::PROFILE_VAR)
+            .ok()
+            .filter(|p| !p.is_empty());
+        if let Some((name, profile)) = profiles.select(self.profile.as_deref(), env.as_deref())? {
+            self.use_profile(name, profile, name_given);
+        }
+        Ok(())
+    }
+
+    /// Takes the options that were not given from the local configuration file of a
+    /// directory or of its parents, see `local_config`. Returns whether the name of the
+    /// inventory is now chosen, which the profile doesn't override.
+    pub fn apply_local_config(
+        &mut self,
+        dir: &Path,
+        name_given: bool,
+    ) -> std::result::Result<bool, CliError> {
+        if self.no_local_config {
+            return Ok(name_given);
+        }
+        let path = match local_config::find(dir) {
+            Some(path) => path,
+            None => return Ok(name_given),
+        };
+        debug!("reading {}", path.display());
+        let config = local_config::load(&path)?;
+        self.fill_options(&config, name_given);
+        Ok(name_given || config.name.is_some())
+    }
+
+    /// Takes the options that were not given from a profile.
+    fn use_profile(&mut self, name: &str, profile: &profiles::Profile, name_given: bool) {
+        self.profile = Some(name.to_string());
+        self.fill_options(profile, name_given);
+    }
+
+    /// Takes the options that were not given from a profile or a local configuration file.
+    fn fill_options(&mut self, profile: &profiles::Profile, name_given: bool) {
+        if self.workdir.is_none() {
+            self.workdir = profile.workdir.clone();
+        }
+        if let (false, Some(inventory_name)) = (name_given, &profile.name) {
+            self.inventory_name = inventory_name.clone();
+        }
+        if !self.minimal && !self.machine {
+            match profile.format {
+                Some(profiles::Format::Minimal) => self.minimal = true,
+                Some(profiles::Format::Machine) => self.machine = true,
+                Some(profiles::Format::Table) | None => {}
+            }
+        }
+    }
+
     /// Assign a default working directory if none is specified.
     pub fn fix_workdir(&mut self) {
         if self.workdir.is_none() {
@@ -36,21 +202,1089 @@ impl Manager {
         }
     }
 
+    /// The printing options selected for listings.
+    pub fn output(&self) -> Output {
+        Output {
+            minimal: self.minimal,
+            header: self.header,
+            to_stderr: self.stdout,
+            layout: Layout::choose(self.renderer, render::terminal_width()),
+            timestamps: self.timestamps
+                || matches!(self.command, Some(Command::ListTrashed { .. })),
+            shelf_life: !self.no_shelf_life,
+        }
+    }
+
+    /// Whether questions can be asked on the terminal.
+    pub fn interactive(&self) -> bool {
+        !self.no_interactive && !self.stdin && prompt::is_interactive()
+    }
+
+    /// Exports or imports an archive of the inventory files.
+    /// After an import, the inventory instance is reloaded from the imported files.
+    fn archive(
+        &self,
+        cmd: &ArchiveCommand,
+        inventory: &mut Inventory,
+    ) -> std::result::Result<CommandResult, CliError> {
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        match cmd {
+            ArchiveCommand::Export { path } => {
+                let parts = archive::export(workdir, &self.inventory_name, path, inventory.now())
+                    .map_err(CliError::Archive)?;
+                Ok(CommandResult::messages(vec![format!(
+                    "Exported {}",
+                    parts.join(", ")
+                )]))
+            }
+            ArchiveCommand::Import { path, force } => {
+                let parts = archive::import(workdir, &self.inventory_name, path, *force)
+                    .map_err(CliError::Archive)?;
+                *inventory =
+                    load_inventory(workdir, &self.inventory_name, Files::Both, self.fix_ids)?.0;
+                Ok(CommandResult::messages(vec![format!(
+                    "Imported {}",
+                    parts.join(", ")
+                )]))
+            }
+        }
+    }
+
+    /// Upgrades the types and instances files to a format version, or reports their version.
+    /// The files are rewritten without going through the inventory, so that they can be
+    /// upgraded to a version older than the latest.
+    fn migrate(
+        &self,
+        to: Option<u32>,
+        check: bool,
+    ) -> std::result::Result<CommandResult, CliError> {
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        let to = to.unwrap_or(migrations::CURRENT_VERSION);
+        let mut messages = vec![];
+        let mut upgraded = vec![];
+        let (types_path, instances_path) = inventory_paths(workdir, &self.inventory_name)?;
+        for path in [types_path.clone(), instances_path] {
+            let file = match read_value(&path)? {
+                Some(file) => file,
+                None => continue,
+            };
+            let format_error = |e| CliError::Format(path.clone(), e);
+            let version = migrations::version_of(&file).map_err(format_error)?;
+            if check || version == to {
+                messages.push(format!("{}: format version {}", path.display(), version));
+                continue;
+            }
+            let file = migrations::migrate(file, to).map_err(format_error)?;
+            messages.push(format!(
+                "{}: upgraded from format version {} to {}",
+                path.display(),
+                version,
+                to
+            ));
+            let content = file_content(&path, to_json(&file))?;
+            upgraded.push((path, content));
+        }
+        if !upgraded.is_empty() {
+            let files = upgraded
+                .iter()
+                .map(|(path, content)| (path.as_path(), content.clone()))
+                .collect::<Vec<_>>();
+            storage::save_files(&journal_path(&types_path), &files)?;
+        }
+        Ok(CommandResult::messages(messages))
+    }
+
+    /// Saves the inventory to encrypted files, or to plaintext ones, and removes the files it
+    /// was loaded from. The new files are complete before the old ones are removed.
+    fn set_encryption(
+        &self,
+        inventory: &Inventory,
+        encrypt: bool,
+    ) -> std::result::Result<CommandResult, CliError> {
+        if self.stdin || self.stdout {
+            warn!(
+                "{} changes the inventory files, it can't be used with --stdin or --stdout",
+                if encrypt { "encrypt" } else { "decrypt" }
+            );
+            return Ok(CommandResult::default());
+        }
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        let (types_path, instances_path) = inventory_paths(workdir, &self.inventory_name)?;
+        if crypto::is_encrypted(&types_path) == encrypt {
+            return Ok(CommandResult::messages(vec![format!(
+                "The inventory {} is already {}",
+                self.inventory_name,
+                if encrypt {
+                    "encrypted"
+                } else {
+                    "not encrypted"
+                }
+            )]));
+        }
+        let convert = |path: &Path| {
+            if encrypt {
+                crypto::encrypted_path(path)
+            } else {
+                crypto::plaintext_path(path)
+            }
+        };
+        let (new_types, new_instances) = (convert(&types_path), convert(&instances_path));
+        save_inventory(inventory, &new_types, &new_instances, Files::Both)?;
+        for path in [&types_path, &instances_path] {
+            match remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(CliError::io(Operation::Write, path, e));
+                }
+                _ => {}
+            }
+        }
+        // The event log and the state file follow, with the cipher just used.
+        let cipher = cipher_for(if encrypt { &new_types } else { &types_path }, false)?;
+        let (from, to) = if encrypt {
+            (None, Some(cipher))
+        } else {
+            (Some(cipher), None)
+        };
+        let name = &self.inventory_name;
+        let new_events = events::events_path(workdir, name, encrypt);
+        let new_state = state::state_path(workdir, name, encrypt);
+        let mut written = vec![new_types, new_instances];
+        if events::convert(
+            &events::events_path(workdir, name, !encrypt),
+            from,
+            &new_events,
+            to,
+        )? {
+            written.push(new_events);
+        }
+        if state::convert(
+            &state::state_path(workdir, name, !encrypt),
+            from,
+            &new_state,
+            to,
+        )? {
+            written.push(new_state);
+        }
+        Ok(CommandResult::messages(
+            written
+                .iter()
+                .map(|path| format!("Wrote {}", path.display()))
+                .collect(),
+        ))
+    }
+
+    /// The options of the health report: the weights of the settings file and, unless the
+    /// inventory is a published one, the last changes of the event log.
+    fn health_options(&self, top: usize) -> std::result::Result<HealthOptions, CliError> {
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        let config = config::load(&config::config_path(workdir, &self.inventory_name))?;
+        let last_changes = match self.from_published {
+            Some(_) => Default::default(),
+            None => self.load_events()?.last_changes(),
+        };
+        Ok(HealthOptions {
+            weights: config.health_weights,
+            last_changes,
+            top,
+            ..HealthOptions::default()
+        })
+    }
+
+    /// Whether the files of the inventory are encrypted, see `crypto`.
+    fn encrypted(&self) -> std::result::Result<bool, CliError> {
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        let (types_path, _) = inventory_paths(workdir, &self.inventory_name)?;
+        Ok(crypto::is_encrypted(&types_path))
+    }
+
+    /// The event log of the inventory, see `events`.
+    fn events_path(&self) -> std::result::Result<PathBuf, CliError> {
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        Ok(events::events_path(
+            workdir,
+            &self.inventory_name,
+            self.encrypted()?,
+        ))
+    }
+
+    /// Reads the event log of the inventory, decrypting it if the inventory is encrypted.
+    fn load_events(&self) -> std::result::Result<EventLog, CliError> {
+        let path = self.events_path()?;
+        cipher_of(&path).and_then(|cipher| events::load(&path, cipher))
+    }
+
+    fn classes_path(&self) -> PathBuf {
+        self.workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.")
+            .join(format!("{}_classes.json", self.inventory_name))
+    }
+
+    fn load_classes(&self) -> std::result::Result<Vec<ItemClass>, CliError> {
+        read_json(&self.classes_path())
+    }
+
+    fn classes(
+        &self,
+        cmd: &ClassesCommand,
+        inventory: &Inventory,
+    ) -> std::result::Result<CommandResult, CliError> {
+        let mut classes = self.load_classes()?;
+        let message = match cmd {
+            ClassesCommand::List => return Ok(CommandResult::classes(classes)),
+            ClassesCommand::Set {
+                name,
+                ttl,
+                open_by_default,
+                auto_trash_empty,
+                minimum_counts_sealed_only,
+            } => {
+                let index = match classes
+                    .iter()
+                    .position(|c| MatchMode::Exact.matches(&c.name, name))
+                {
+                    Some(index) => index,
+                    None => {
+                        classes.push(ItemClass::new(name));
+                        classes.len() - 1
+                    }
+                };
+                let class = &mut classes[index];
+                if let Some(ttl) = ttl {
+                    class.ttl = ttl.map(|t| t.duration(inventory.now()));
+                }
+                if let Some(open_by_default) = open_by_default {
+                    class.opened_by_default = *open_by_default;
+                }
+                if let Some(auto_trash_empty) = auto_trash_empty {
+                    class.auto_trash_empty = *auto_trash_empty;
+                }
+                if let Some(sealed_only) = minimum_counts_sealed_only {
+                    class.minimum_counts_sealed_only = *sealed_only;
+                }
+                format!("Saved class {}", class.name)
+            }
+            ClassesCommand::Delete { name } => {
+                let class = match find_class(&classes, name) {
+                    Some(class) => class.name.clone(),
+                    None => {
+                        warn!("Could not find a class with the specified name");
+                        return Ok(CommandResult::default());
+                    }
+                };
+                let users = inventory
+                    .item_types
+                    .iter()
+                    .filter(|t| t.class.as_deref() == Some(class.as_str()))
+                    .count();
+                if users > 0 {
+                    warn!(
+                        "Warning: {} item types were created from class {}. They keep their values.",
+                        users, class
+                    );
+                }
+                classes.retain(|c| c.name != class);
+                format!("Deleted class {}", class)
+            }
+        };
+        save_json(&self.classes_path(), &classes)?;
+        Ok(CommandResult::messages(vec![message]))
+    }
+
+    /// Writes the local configuration file of a directory, the current one by default,
+    /// naming the inventory and the format chosen, see `local_config`. The inventory must
+    /// exist, and an existing file is only replaced with `force`.
+    fn link(
+        &self,
+        dir: Option<&Path>,
+        force: bool,
+    ) -> std::result::Result<CommandResult, CliError> {
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        let (types_path, instances_path) = inventory_paths(workdir, &self.inventory_name)?;
+        if !types_path.exists() && !instances_path.exists() {
+            warn!(
+                "There is no inventory {} in {}, nothing to link to.",
+                self.inventory_name,
+                workdir.display()
+            );
+            return Ok(CommandResult::default());
+        }
+        let absolute = |path: &Path| {
+            std::fs::canonicalize(path).map_err(|e| CliError::io(Operation::Read, path, e))
+        };
+        let dir = absolute(dir.unwrap_or(Path::new(".")))?;
+        if dir.join(local_config::FILE_NAME).exists() && !force {
+            warn!(
+                "{} already has a {}, use --force to replace it.",
+                dir.display(),
+                local_config::FILE_NAME
+            );
+            return Ok(CommandResult::default());
+        }
+        let format = if self.machine {
+            Some(profiles::Format::Machine)
+        } else if self.minimal {
+            Some(profiles::Format::Minimal)
+        } else {
+            None
+        };
+        let config = profiles::Profile {
+            workdir: Some(absolute(workdir)?),
+            name: Some(self.inventory_name.clone()),
+            format,
+        };
+        let path = local_config::save(&dir, &config)?;
+        Ok(CommandResult::messages(vec![format!(
+            "Wrote {}",
+            path.display()
+        )]))
+    }
+
+    /// Lists the item types and item instances idle since a time, see `Inventory::idle_records`,
+    /// and deletes the idle item types once confirmed. The event log adds the activity of
+    /// the item types, unless the inventory is a published one.
+    fn list_idle(
+        &self,
+        since: std::time::SystemTime,
+        delete: bool,
+        yes: bool,
+        inventory: &mut Inventory,
+    ) -> std::result::Result<CommandResult, CliError> {
+        let activity = match self.from_published {
+            Some(_) => Default::default(),
+            None => self.load_events()?.type_activity(),
+        };
+        let records = inventory.idle_records(since, &activity);
+        if !delete {
+            return Ok(CommandResult::idle(records));
+        }
+        let types = records
+            .iter()
+            .filter(|r| r.kind == "type")
+            .collect::<Vec<_>>();
+        if types.is_empty() {
+            return Ok(CommandResult::messages(vec![
+                "No item type is idle.".to_string()
+            ]));
+        }
+        if !yes {
+            if !self.interactive() {
+                warn!(
+                    "Not deleting {} item types without a terminal, use --yes to delete them.",
+                    types.len()
+                );
+                return Ok(CommandResult::default());
+            }
+            for t in &types {
+                eprintln!("  {} {}", t.id, t.name);
+            }
+            let prompt = format!(
+                "Delete these {} item types and their item instances?",
+                types.len()
+            );
+            if !prompt::confirm(&prompt) {
+                return Ok(CommandResult::default());
+            }
+        }
+        let messages = types
+            .iter()
+            .map(|t| {
+                inventory.delete_item_type(TypeId(t.id));
+                format!("Deleted item type {} {}", t.id, t.name)
+            })
+            .collect();
+        Ok(CommandResult::messages(messages))
+    }
+
+    /// Finds the expiries passed since the last run and trashes the item instances of the
+    /// item types with `auto_trash_on_expiry`, see `transition`. The expiries already in the
+    /// log are not found again, whatever the time of the last run. The expiries and the time
+    /// of the run are only recorded once the inventory is saved, see `record_update`.
+    fn update_state(
+        &self,
+        dry_run: bool,
+        inventory: &mut Inventory,
+    ) -> std::result::Result<CommandResult, CliError> {
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        let state_path = state::state_path(workdir, &self.inventory_name, self.encrypted()?);
+        let job_state = state::load(&state_path, cipher_of(&state_path)?)?;
+        let log = self.load_events()?;
+        let now = inventory.now();
+        let transitions =
+            inventory.expiry_transitions(job_state.last_update, &log.recorded_expiries());
+        let update = (!dry_run).then(|| {
+            inventory.trash_expired(&transitions);
+            state::Update {
+                expired: transitions.iter().map(|t| Event::expired(t, now)).collect(),
+                state: state::JobState {
+                    last_update: Some(transition::next_run(job_state.last_update, now)),
+                },
+            }
+        });
+        let mut result = CommandResult::expiries(transitions);
+        result.state_update = update;
+        Ok(result)
+    }
+
+    /// Adds the expiries found by update-state to the event log and saves the state of the
+    /// run, once the item instances it trashed are saved, so that a failed save finds them
+    /// again next time. Does nothing for the other commands.
+    fn record_update(&self, result: &CommandResult) -> std::result::Result<(), CliError> {
+        let update = match &result.state_update {
+            Some(update) => update,
+            None => return Ok(()),
+        };
+        let workdir = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.");
+        if !update.expired.is_empty() {
+            let events_path = self.events_path()?;
+            events::append(&events_path, cipher_of(&events_path)?, &update.expired)?;
+        }
+        let state_path = state::state_path(workdir, &self.inventory_name, self.encrypted()?);
+        state::save(&state_path, cipher_of(&state_path)?, &update.state)
+    }
+
+    /// Rebuilds the inventory at a time from the event log and optionally checks that the
+    /// log, replayed up to now, gives the inventory of the files.
+    fn state_at(
+        &self,
+        at: PointInTime,
+        verify: bool,
+        inventory: &Inventory,
+    ) -> std::result::Result<CommandResult, CliError> {
+        let log = self.load_events()?;
+        let now = inventory.now();
+        let at = at.resolve(now);
+        match log.start() {
+            None => info!("The event log is empty, changes are logged from the next one."),
+            Some(start) if at < start => info!(
+                "The event log starts at {}, nothing is known before.",
+                humantime::format_rfc3339_seconds(start)
+            ),
+            Some(_) => {}
+        }
+        let (state, skipped) = log.replay_checked(at);
+        for edit in &skipped {
+            warn!("Warning: skipped {}", skipped_edit(edit));
+        }
+        let mut result = CommandResult::state(PastState {
+            at,
+            types: state.item_types,
+            instances: state.item_instances,
+        });
+        if verify {
+            let replayed = log.replay(now);
+            let types = diff(&replayed.item_types, &inventory.item_types);
+            let instances = diff(&replayed.item_instances, &inventory.item_instances);
+            result.messages = types
+                .iter()
+                .map(|c| format!("Item type {} {}", c.id, divergence(c)))
+                .chain(
+                    instances
+                        .iter()
+                        .map(|c| format!("Item instance {} {}", c.id, divergence(c))),
+                )
+                .collect();
+            if result.messages.is_empty() {
+                result
+                    .messages
+                    .push("The event log matches the inventory files.".to_string());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Warns when an icon is an image file missing from the icons directory, where frontends
+    /// would look for it.
+    fn check_icon(&self, icon: &str) {
+        if icon::kind(icon) != icon::IconKind::Path {
+            return;
+        }
+        let path = self
+            .workdir
+            .as_ref()
+            .expect("Manager::fix_workdir wasn't called before this point.")
+            .join(icon::ICONS_DIR)
+            .join(icon);
+        if !path.is_file() {
+            warn!("Warning: the icon file {} doesn't exist", path.display());
+        }
+    }
+
+    /// Sets the icons of the item types matching the patterns of a mapping, see `read_mapping`.
+    fn set_icons_from_map(
+        &self,
+        mapping: std::collections::BTreeMap<String, String>,
+        overwrite: bool,
+        dry_run: bool,
+        inventory: &mut Inventory,
+    ) -> CommandResult {
+        let mapping = mapping.into_iter().collect::<Vec<_>>();
+        let mut icons = mapping.iter().map(|(_, icon)| icon).collect::<Vec<_>>();
+        icons.sort();
+        icons.dedup();
+        icons.into_iter().for_each(|icon| self.check_icon(icon));
+
+        let mut after = inventory.clone();
+        let ids = after.set_icons_from_map(&mapping, overwrite);
+        let mut messages = vec![format!("{} item types got a new icon", ids.len())];
+        let changes = result::diff(&inventory.item_types, &after.item_types);
+        if dry_run {
+            messages.push("Dry run, nothing was changed".to_string());
+        } else {
+            *inventory = after;
+        }
+        let mut result = CommandResult::type_updates(changes);
+        result.messages = messages;
+        result
+    }
+
+    /// Renames the location names of the mapping, or proposes to rename the groups of
+    /// similar location names, asking for each group.
+    fn normalize_locations(
+        &self,
+        dry_run: bool,
+        apply: Option<std::collections::BTreeMap<String, String>>,
+        inventory: &mut Inventory,
+    ) -> CommandResult {
+        if let Some(mapping) = apply {
+            return CommandResult::messages(
+                mapping
+                    .iter()
+                    .map(|(from, to)| renamed(from, to, inventory.rename_location(from, to)))
+                    .collect(),
+            );
+        }
+        let clusters = inventory.location_clusters();
+        if clusters.is_empty() {
+            return CommandResult::messages(vec![
+                "The location names are already normalized.".to_string()
+            ]);
+        }
+        if dry_run || !self.interactive() {
+            if !dry_run {
+                warn!("Not renaming anything without a terminal, use --apply with a mapping file.");
+            }
+            return CommandResult::locations(
+                inventory
+                    .location_counts()
+                    .into_iter()
+                    .map(|count| LocationMapping {
+                        rename_to: clusters
+                            .iter()
+                            .find(|c| c.variants.contains(&count.location))
+                            .map(|c| c.canonical.clone()),
+                        count,
+                    })
+                    .collect(),
+            );
+        }
+        let mut messages = vec![];
+        for cluster in &clusters {
+            let variants = cluster
+                .variants
+                .iter()
+                .map(|v| format!("{:?}", v))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if prompt::confirm(&format!("Rename {} to {:?}?", variants, cluster.canonical)) {
+                for variant in &cluster.variants {
+                    let changed = inventory.rename_location(variant, &cluster.canonical);
+                    messages.push(renamed(variant, &cluster.canonical, changed));
+                }
+            }
+        }
+        CommandResult::messages(messages)
+    }
+
+    /// Trashes the item instances matching the filters, once confirmed.
+    fn trash_where(&self, cmd: &TrashWhereCommand, inventory: &mut Inventory) -> CommandResult {
+        let query = cmd.query();
+        if !query.has_filters() && !cmd.all {
+            warn!("Refusing to trash every item instance, use --all if that is what you want.");
+            return CommandResult::default();
+        }
+        let matching = query.run(inventory).items;
+        if matching.is_empty() {
+            return CommandResult::messages(vec![
+                "No item instance matches the filters.".to_string()
+            ]);
+        }
+        if !cmd.yes {
+            if !self.interactive() {
+                warn!(
+                    "Not trashing {} item instances without a terminal, use --yes to trash them.",
+                    matching.len()
+                );
+                return CommandResult::default();
+            }
+            for ii in &matching {
+                let name = inventory.type_name(ii.item_type);
+                eprintln!(
+                    "  {} {} {} {}",
+                    ii.label.as_deref().unwrap_or(&ii.id.to_string()),
+                    name,
+                    ii.quantity,
+                    ii.location.as_deref().unwrap_or("")
+                );
+            }
+            if !prompt::confirm(&format!("Trash these {} item instances?", matching.len())) {
+                return CommandResult::default();
+            }
+        }
+        let trashed = inventory.trash_where(&query, cmd.reason.as_deref());
+        CommandResult::instances(
+            inventory
+                .item_instances
+                .iter()
+                .filter(|ii| trashed.contains(&ii.id)),
+            inventory.now(),
+        )
+    }
+
+    /// Writes a published file of the inventory, see `publish::publish`.
+    fn publish(
+        &self,
+        output: Option<&Path>,
+        redact: &[publish::Redacted],
+        inventory: &Inventory,
+    ) -> CommandResult {
+        let path = output.map(Path::to_path_buf).unwrap_or_else(|| {
+            self.workdir
+                .as_ref()
+                .expect("Manager::fix_workdir wasn't called before this point.")
+                .join(format!("{}_published.json", self.inventory_name))
+        });
+        match publish::publish(
+            inventory,
+            &self.inventory_name,
+            redact,
+            &path,
+            inventory.now(),
+        ) {
+            Ok(count) => CommandResult::messages(vec![format!(
+                "Published {} item types and {} item instances to {}",
+                inventory.item_types.len(),
+                count,
+                path.display()
+            )]),
+            Err(e) => {
+                warn!("{}", e);
+                CommandResult::default()
+            }
+        }
+    }
+
     /// Executes the subcommand on the inventory instance.
-    pub fn exec(&self, inventory: &mut Inventory) {
-        match &self.command {
-            Command::CreateType(cmd) => create_type(cmd, inventory),
-            Command::ReadType(cmd) => read_type(cmd, inventory, self.minimal),
-            Command::UpdateType(cmd) => update_type(cmd, inventory),
+    /// Without a subcommand, returns the dashboard of the inventory.
+    /// A command failing to read or write a file other than the inventory files returns the
+    /// error, which may leave the inventory half changed.
+    pub fn exec(&self, inventory: &mut Inventory) -> std::result::Result<CommandResult, CliError> {
+        let command = match &self.command {
+            Some(command) => command,
+            None => {
+                let mut result = dashboard(inventory, &self.inventory_name);
+                result.timestamp = humantime::format_rfc3339(inventory.now()).to_string();
+                return Ok(result);
+            }
+        };
+        // The item types used or added to are checked against their minimum afterwards.
+        let before = match command {
+            Command::Use { .. } | Command::CreateInstance(_) => Some(inventory.clone()),
+            _ => None,
+        };
+        let mut result = match command {
+            Command::CreateType(cmd) => {
+                if let Some(icon) = &cmd.icon {
+                    self.check_icon(icon);
+                }
+                create_type(cmd, inventory, &self.load_classes()?)
+            }
+            Command::ReadType(cmd) => read_type(cmd, inventory),
+            Command::UpdateType(cmd) => {
+                if let Some(Some(icon)) = &cmd.icon {
+                    self.check_icon(icon);
+                }
+                update_type(cmd, inventory, &self.load_classes()?)
+            }
             Command::DeleteType(cmd) => delete_type(cmd, inventory),
-            Command::CreateInstance(cmd) => create_instance(cmd, inventory),
-            Command::ReadInstance(cmd) => read_instance(cmd, inventory, self.minimal),
+            Command::RecomputeExpiry(cmd) => recompute_expiry(cmd, inventory),
+            Command::BulkUpdateTypes(cmd) => bulk_update_types(cmd, inventory),
+            Command::SetIconsFromMap {
+                file,
+                overwrite,
+                dry_run,
+            } => self.set_icons_from_map(read_mapping(file)?, *overwrite, *dry_run, inventory),
+            Command::CreateInstance(cmd) => {
+                match resolve_type(&cmd.item_type, inventory, self.interactive()) {
+                    Some(type_id) => create_instance(cmd, type_id, inventory),
+                    None => CommandResult::default(),
+                }
+            }
+            Command::ReadInstance(cmd) => read_instance(cmd, inventory, &self.inventory_name),
             Command::UpdateInstance(cmd) => update_instance(cmd, inventory),
             Command::DeleteInstance(cmd) => delete_instance(cmd, inventory),
-            Command::ListExpired => print_expired(inventory, self.minimal),
-            Command::ListMissing => print_missing(inventory, self.minimal),
-            Command::Use { type_id, quantity } => inventory.use_instance(*type_id, *quantity),
-            Command::Trash { instance_id } => inventory.trash(*instance_id),
+            Command::ListExpired {
+                include_best_before,
+                include_snoozed,
+            } => list_expired(inventory, *include_best_before, *include_snoozed),
+            Command::ListReturnables => {
+                CommandResult::instances(inventory.returnables(), inventory.now())
+            }
+            Command::ReturnDeposit { instances } => return_deposits(instances, inventory),
+            Command::ListMissing {
+                with_prices,
+                group_by,
+                vendor,
+            } => list_missing(inventory, *with_prices, *group_by, vendor.as_deref()),
+            Command::Digest {
+                period,
+                mark_reminded,
+                health,
+                include_snoozed,
+            } => {
+                let digest = inventory.digest(
+                    inventory.now(),
+                    &DigestOptions {
+                        period: period.duration(inventory.now()),
+                        health: health.then(|| self.health_options(5)).transpose()?,
+                        include_snoozed: *include_snoozed,
+                    },
+                );
+                if *mark_reminded {
+                    let reminded = digest
+                        .opened_reminders
+                        .iter()
+                        .map(|e| e.instance_id)
+                        .collect::<Vec<_>>();
+                    inventory.mark_reminded(&reminded);
+                }
+                CommandResult::digest(digest)
+            }
+            Command::UpdateState { dry_run } => self.update_state(*dry_run, inventory)?,
+            Command::Snooze {
+                target, duration, ..
+            } => {
+                let snooze = match duration {
+                    Some(duration) => Snooze::Until(duration.time(inventory.now())),
+                    None => Snooze::Forever,
+                };
+                snooze_target(target, Some(snooze), inventory)
+            }
+            Command::Unsnooze { target } => snooze_target(target, None, inventory),
+            Command::Prices { type_id } => CommandResult::prices(inventory.price_history(*type_id)),
+            Command::Use {
+                item_type,
+                more,
+                item,
+                all,
+                best_effort,
+                allow_overdraft,
+            } => match uses::use_items(item_type.as_deref(), more, item) {
+                Err(e) => {
+                    warn!("{}", e);
+                    CommandResult::default()
+                }
+                Ok(items) if items.len() > 1 => use_several(
+                    &items,
+                    *best_effort,
+                    *allow_overdraft,
+                    inventory,
+                    self.interactive(),
+                ),
+                Ok(items) => match resolve_type(&items[0].item_type, inventory, self.interactive())
+                {
+                    None => CommandResult::default(),
+                    Some(_) if *all && items[0].quantity.is_some() => {
+                        warn!("--all uses everything left, without a quantity");
+                        CommandResult::default()
+                    }
+                    Some(type_id) if *all => use_all(type_id, inventory),
+                    Some(type_id) => use_quantity(
+                        type_id,
+                        items[0].quantity.as_ref(),
+                        *allow_overdraft,
+                        inventory,
+                    ),
+                },
+            },
+            Command::Finish { instance } => match resolve_instance(instance, inventory) {
+                Some(id) => finish(id, inventory),
+                None => CommandResult::default(),
+            },
+            Command::TopUp {
+                instance,
+                quantity,
+                expires_at,
+            } => match resolve_instance(instance, inventory) {
+                Some(id) => {
+                    let expires_at = expires_at.map(|t| t.time(inventory.now()));
+                    top_up(id, quantity, expires_at, inventory)
+                }
+                None => CommandResult::default(),
+            },
+            Command::ParseQr { compact } => {
+                match inventory.from_compact(&self.inventory_name, compact) {
+                    Ok(ii) => CommandResult::instances(vec![ii], inventory.now()),
+                    Err(e) => {
+                        warn!("Could not resolve the compact string: {}", e);
+                        CommandResult::default()
+                    }
+                }
+            }
+            Command::FindRef { external_ref } => {
+                let found = inventory.find_by_ref(external_ref);
+                trace!(
+                    "find-ref: {} item instances have {}",
+                    found.len(),
+                    external_ref
+                );
+                CommandResult::references(found)
+            }
+            Command::Trash {
+                instance,
+                reason,
+                quantity,
+            } => match resolve_instance(instance, inventory) {
+                Some(id) => trash(id, reason.as_deref(), quantity.as_ref(), inventory),
+                None => CommandResult::default(),
+            },
+            Command::TrashWhere(cmd) => self.trash_where(cmd, inventory),
+            Command::Replace(cmd) => replace(cmd, inventory),
+            Command::ListTrashed { removed_since } => {
+                let mut query = InstanceQuery::new().removed(true);
+                if let Some(since) = removed_since {
+                    query = query.removed_since(since.ago(inventory.now()));
+                }
+                CommandResult::instances(query.run(inventory).items, inventory.now())
+            }
+            Command::Give {
+                instance,
+                to,
+                quantity,
+            } => match resolve_instance(instance, inventory) {
+                Some(id) => give(id, to.clone(), quantity.as_ref(), inventory),
+                None => CommandResult::default(),
+            },
+            Command::ListGiven { to } => CommandResult::gifts(inventory.given(to.as_deref())),
+            Command::Misplaced => CommandResult::instances(inventory.misplaced(), inventory.now()),
+            Command::ListRecount => CommandResult::types(inventory.recount_requested()),
+            Command::PutBack { instance } => {
+                if let Some(id) = resolve_instance(instance, inventory) {
+                    if let Err(InventoryError::NoHomeLocation) = inventory.put_back(id) {
+                        warn!("Item instance {} has no home location", id);
+                    }
+                }
+                CommandResult::default()
+            }
+            Command::WasteReport { months } => {
+                CommandResult::waste(inventory.waste_by_month(inventory.now(), *months))
+            }
+            Command::Tco {
+                item_type,
+                months,
+                all,
+            } => {
+                let now = inventory.now();
+                match (item_type, all) {
+                    (_, true) => CommandResult::costs(inventory.costs_of_ownership(now, *months)),
+                    (Some(selector), false) => {
+                        match resolve_type(selector, inventory, self.interactive()) {
+                            Some(type_id) => CommandResult::costs(
+                                inventory
+                                    .cost_of_ownership(type_id, now, *months)
+                                    .into_iter()
+                                    .collect(),
+                            ),
+                            None => CommandResult::default(),
+                        }
+                    }
+                    (None, false) => CommandResult::default(),
+                }
+            }
+            Command::Health { top } => CommandResult::health(
+                inventory.health_with(inventory.now(), &self.health_options(*top)?),
+            ),
+            Command::ListIdle {
+                duration,
+                delete,
+                yes,
+            } => self.list_idle(duration.ago(inventory.now()), *delete, *yes, inventory)?,
+            Command::Categories => CommandResult::categories(inventory.category_tree()),
+            Command::ValueReport { depreciated } => CommandResult::values(ValueReport {
+                depreciated: *depreciated,
+                types: inventory.values_by_type(),
+            }),
+            Command::GenerateLabels => CommandResult::messages(
+                inventory
+                    .generate_missing_labels()
+                    .iter()
+                    .map(|(id, label)| format!("{}: {}", id, label))
+                    .collect(),
+            ),
+            Command::GenerateDemo {
+                types,
+                instances,
+                seed,
+                force,
+            } => generate_demo(
+                inventory,
+                *seed,
+                testing::Sizes {
+                    types: *types,
+                    instances: *instances,
+                },
+                *force,
+            ),
+            Command::TrashEmpty { type_id } => CommandResult::messages(
+                inventory
+                    .trash_empty(*type_id)
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect(),
+            ),
+            Command::CompactIds(cmd) => compact_ids(cmd, inventory)?,
+            Command::Classes(cmd) => self.classes(cmd, inventory)?,
+            Command::Archive(cmd) => self.archive(cmd, inventory)?,
+            Command::Migrate { to, check } => self.migrate(*to, *check)?,
+            Command::Encrypt => self.set_encryption(inventory, true)?,
+            Command::Decrypt => self.set_encryption(inventory, false)?,
+            Command::Profiles => list_profiles()?,
+            Command::Link { dir, force } => self.link(dir.as_deref(), *force)?,
+            Command::ExportIcal(cmd) => export_ical(cmd, inventory, &self.inventory_name)?,
+            Command::Export(cmd) => export(cmd, inventory, &self.inventory_name)?,
+            Command::GraphLocations { format, output } => {
+                graph_locations(inventory, *format, output.as_deref())?
+            }
+            Command::Publish { output, redact } => {
+                self.publish(output.as_deref(), redact, inventory)
+            }
+            Command::Metrics {
+                output,
+                include_deposits,
+            } => write_metrics(
+                inventory,
+                &self.inventory_name,
+                output.as_deref(),
+                *include_deposits,
+            )?,
+            #[cfg(feature = "grocy")]
+            Command::ImportGrocy { path } => import_grocy(path, inventory),
+            Command::ImportLegacy { types, instances } => {
+                import_legacy(types, instances, inventory)?
+            }
+            Command::StateAt { at, verify } => self.state_at(*at, *verify, inventory)?,
+            Command::NormalizeLocations { dry_run, apply } => {
+                let apply = apply.as_deref().map(read_mapping).transpose()?;
+                self.normalize_locations(*dry_run, apply, inventory)
+            }
+            Command::LocationAdd {
+                name,
+                capacity,
+                unit,
+            } => location_add(name, *capacity, unit.as_deref(), inventory),
+            Command::LocationSet {
+                name,
+                capacity,
+                unit,
+            } => location_set(name, *capacity, unit.clone(), inventory),
+            Command::LocationList => CommandResult::location_usage(inventory.location_usage()),
+            Command::Repl => {
+                warn!("repl can only be started from the command line");
+                CommandResult::default()
+            }
+            Command::Examples { command } => print_examples(command.as_deref()),
+        };
+        if let Some(before) = before {
+            result.minimums = minimum_checks(&before, inventory);
+            result
+                .messages
+                .extend(minimum_messages(&result.minimums, inventory));
+        }
+        result.command = command.name();
+        result.timestamp = humantime::format_rfc3339(inventory.now()).to_string();
+        Ok(result)
+    }
+
+    /// Prints the result of the command in the selected format.
+    pub fn print(&self, result: &CommandResult, inventory: &Inventory) {
+        if self.machine {
+            result.print_json(self.output());
+        } else {
+            result.print_human(inventory, self.output());
+        }
+    }
+}
+
+/// How the item types and instances are printed.
+#[derive(Debug, Clone, Copy)]
+pub struct Output {
+    /// Prints one record per line instead of pretty tables.
+    pub minimal: bool,
+    /// Prints the field names before the records in minimal mode.
+    pub header: bool,
+    /// Prints on stderr instead of stdout, which has the inventory with --stdout.
+    pub to_stderr: bool,
+    /// How tables are laid out.
+    pub layout: Layout,
+    /// Adds the added and removed times to the tables of item instances.
+    pub timestamps: bool,
+    /// Adds the bar of the shelf life left to the tables of item instances.
+    pub shelf_life: bool,
+}
+
+impl Output {
+    /// Prints a line of human readable output.
+    pub fn line(&self, line: impl std::fmt::Display) {
+        if self.to_stderr {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    /// Prints a document, which ends with its own line break.
+    pub fn text(&self, text: &str) {
+        if self.to_stderr {
+            eprint!("{}", text);
+        } else {
+            print!("{}", text);
+        }
+    }
+
+    /// Prints a table, without colors on stderr.
+    pub fn table(&self, grid: &Grid) {
+        let renderer = self.layout.renderer();
+        if self.to_stderr {
+            eprint!("{}", renderer.render(grid));
+        } else {
+            renderer.print(grid);
         }
     }
 }
@@ -67,9 +1301,29 @@ pub enum Command {
     /// Modify the properties of an item type.
     #[structopt(name = "ut")]
     UpdateType(UpdateTypeCommand),
-    /// Delete an item type.
+    /// Delete an item type and its item instances, or move them to another item type.
     #[structopt(name = "dt")]
     DeleteType(DeleteTypeCommand),
+    /// Derive again the expiry of the item instances of a type from their opening time and the type ttl.
+    #[structopt(name = "recompute-expiry")]
+    RecomputeExpiry(RecomputeExpiryCommand),
+    /// Modify the properties of all the item types matching some filters.
+    #[structopt(name = "bulk-update-types")]
+    BulkUpdateTypes(BulkUpdateTypesCommand),
+    /// Set the icons of the item types whose name matches the patterns of a json file, like
+    /// {"*milk*": "🥛", "rice": "grains/rice.png"}, where * stands for any text and ? for
+    /// any character, ignoring case. The most specific pattern wins.
+    #[structopt(name = "set-icons-from-map")]
+    SetIconsFromMap {
+        /// The json file mapping name patterns to icons.
+        file: PathBuf,
+        /// Also replace the icons that are already set.
+        #[structopt(long)]
+        overwrite: bool,
+        /// Print the changes without saving them.
+        #[structopt(long)]
+        dry_run: bool,
+    },
     /// Create a new item instance.
     #[structopt(name = "ci")]
     CreateInstance(CreateInstanceCommand),
@@ -82,27 +1336,696 @@ pub enum Command {
     /// Delete an item instance permanently and all records of it.
     #[structopt(name = "di")]
     DeleteInstance(DeleteInstanceCommand),
-    /// List expired item instances.
+    /// List item instances past their use-by date.
     #[structopt(name = "list-expired")]
-    ListExpired,
+    ListExpired {
+        /// Also list the item instances past their best-before date.
+        #[structopt(long)]
+        include_best_before: bool,
+        /// Also list the snoozed item instances, see snooze.
+        #[structopt(long)]
+        include_snoozed: bool,
+    },
+    /// List the item instances in the trash whose container can be returned for its deposit.
+    #[structopt(name = "list-returnables")]
+    ListReturnables,
+    /// Mark the containers of item instances in the trash as returned, printing the total refunded.
+    #[structopt(name = "return-deposit")]
+    ReturnDeposit {
+        /// The ids or labels of the item instances.
+        #[structopt(required = true)]
+        instances: Vec<String>,
+    },
     /// List item types that do not have enough item instances
     /// to satisfy their minimum quantity.
-    #[structopt(name = "list-missing")]
-    ListMissing,
+    #[structopt(name = "list-missing", alias = "shopping-list")]
+    ListMissing {
+        /// Add the last price paid per unit and the average of the last 3 prices.
+        #[structopt(long)]
+        with_prices: bool,
+        /// Print one section per vendor of the item types, those without a vendor last.
+        /// The only grouping is vendor.
+        #[structopt(short, long, parse(try_from_str = parse_shopping_group))]
+        group_by: Option<ShoppingGroup>,
+        /// Only list the item types usually bought at this vendor, ignoring case.
+        #[structopt(long)]
+        vendor: Option<String>,
+    },
+    /// Print everything that needs attention: expired and expiring item instances,
+    /// item types to buy, item instances opened for longer than their ttl and those opened
+    /// for the reminder of their item type.
+    #[structopt(name = "digest")]
+    Digest {
+        /// How far back and ahead to report expiries.
+        #[structopt(short, long, default_value = "7days")]
+        period: TimeArg,
+        /// Record that the reminders of opened item instances were delivered, so that each
+        /// is only reminded once per opening. For scripts sending the digest.
+        #[structopt(long)]
+        mark_reminded: bool,
+        /// Add the health report of the data with its 5 most incomplete records, see health.
+        #[structopt(long)]
+        health: bool,
+        /// Also report the snoozed item instances and item types, see snooze.
+        #[structopt(long)]
+        include_snoozed: bool,
+    },
+    /// Record the item instances which expired since the last run in the event log, putting
+    /// those of the item types with auto-trash-on-expiry in the trash. For cron, before digest.
+    #[structopt(name = "update-state")]
+    UpdateState {
+        /// Print the item instances which expired since the last run, without recording or
+        /// trashing them.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Leave an item instance, or all those of an item type, out of list-expired and digest
+    /// for a while or until unsnoozed, like vinegar kept past its expiry.
+    #[structopt(name = "snooze")]
+    Snooze {
+        #[structopt(flatten)]
+        target: SnoozeTarget,
+        /// How long to snooze, like 30days, or until when, like 2024-06-01.
+        #[structopt(long = "for", required_unless = "forever")]
+        duration: Option<TimeArg>,
+        /// Snooze until unsnoozed.
+        #[structopt(long, conflicts_with = "duration")]
+        forever: bool,
+    },
+    /// Report a snoozed item instance or item type again.
+    #[structopt(name = "unsnooze")]
+    Unsnooze {
+        #[structopt(flatten)]
+        target: SnoozeTarget,
+    },
+    /// Print the prices paid per unit for an item type, oldest first.
+    #[structopt(name = "prices")]
+    Prices {
+        /// The id of the item type.
+        type_id: TypeId,
+    },
     /// Use some quantity from an item type.
     #[structopt(name = "use")]
     Use {
-        /// The type id from which to use the specified quantity.
-        type_id: u32,
-        /// The quantity to use. Defaults to 1.0.
-        quantity: Option<f32>,
+        /// The id or name of the item type from which to use the specified quantity.
+        /// A name designates the item type with exactly that name, ignoring case, or else
+        /// the only one whose name contains it. It can be followed by its quantity, like
+        /// rice:2, to use from several item types at once.
+        #[structopt(required_unless = "item")]
+        item_type: Option<String>,
+        /// The quantity to use, optionally with a unit like 500g that is converted to the unit
+        /// of the item type. Defaults to 1.0. Or more item types with their quantity, like
+        /// `use 3:1 7:0.5 12:2`.
+        #[structopt(name = "quantity")]
+        more: Vec<UseArg>,
+        /// Another item type to use from, with its quantity, as type=quantity. Can be repeated.
+        #[structopt(long, number_of_values = 1)]
+        item: Vec<UseItem>,
+        /// Use everything left in the item instance that would be used from.
+        #[structopt(short, long, conflicts_with_all = &["quantity", "item"])]
+        all: bool,
+        /// With several item types, use what there is of those that don't have enough
+        /// instead of using nothing at all.
+        #[structopt(long)]
+        best_effort: bool,
+        /// When there is not enough left, record the use of the rest anyway and flag the
+        /// item type for a recount, like item types with --allow-overdraft true do.
+        #[structopt(long, conflicts_with = "all")]
+        allow_overdraft: bool,
+    },
+    /// Use everything left in an item instance, printing the quantity used.
+    #[structopt(name = "finish")]
+    Finish {
+        /// The id or label of the item instance to finish.
+        instance: String,
+    },
+    /// Add some quantity to an item instance created with `ci --continuous`, like a jar
+    /// refilled from a new bag, printing the quantity now in it.
+    #[structopt(name = "top-up")]
+    TopUp {
+        /// The id or label of the item instance to top up.
+        instance: String,
+        /// The quantity added, like 2 or 500g, converted to the unit of the item instance.
+        quantity: QuantityArg,
+        /// The expiry of what is added. It replaces the expiry of the item instance when it
+        /// is later.
+        #[structopt(short, long)]
+        expires_at: Option<TimeArg>,
+    },
+    /// Print the item instance designated by a compact string made by `ri --qr-string`.
+    #[structopt(name = "parse-qr")]
+    ParseQr {
+        /// The compact string, like `IMG1|inv=inventory|i=42|t=rice|exp=2024-06-01`.
+        compact: String,
+    },
+    /// Print every item instance with exactly this external reference, those in the trash
+    /// included, with all their fields.
+    #[structopt(name = "find-ref")]
+    FindRef {
+        /// The external reference, like an order number, see `ci --ref`.
+        external_ref: String,
     },
     /// Put an item instance in the trash, keeping a record of its existence.
     #[structopt(name = "trash")]
     Trash {
-        /// The instance id to put to the trash.
-        instance_id: u32,
+        /// The id or label of the item instance to put to the trash.
+        instance: String,
+        /// Why the item instance is thrown away: expired, used, broken or given-away.
+        /// Defaults to expired when it is past its use-by date with some quantity left.
+        #[structopt(long, parse(try_from_str = parse_removal_reason))]
+        reason: Option<String>,
+        /// How much to trash, like 2 or 500g, which is split off the item instance while the
+        /// rest stays. Defaults to all of it.
+        #[structopt(short, long)]
+        quantity: Option<QuantityArg>,
+    },
+    /// Print the item instances in the trash, with when they were added and removed.
+    #[structopt(name = "list-trashed")]
+    ListTrashed {
+        /// Only print the item instances removed since this time, like 7days or 2030-06-01.
+        #[structopt(long)]
+        removed_since: Option<TimeArg>,
+    },
+    /// Put every item instance matching some filters in the trash, for example the expired
+    /// ones in the fridge, after asking for confirmation.
+    #[structopt(name = "trash-where")]
+    TrashWhere(TrashWhereCommand),
+    /// Replace a text in a text field of the item instances, or in the names of the item
+    /// types, like a misspelled brand in their models.
+    #[structopt(name = "replace")]
+    Replace(ReplaceCommand),
+    /// Give an item instance away, or only some quantity of it. It is removed without being
+    /// counted as waste.
+    #[structopt(name = "give")]
+    Give {
+        /// The id or label of the item instance to give.
+        instance: String,
+        /// Who the item instance is given to.
+        #[structopt(long)]
+        to: Option<String>,
+        /// How much to give, like 2 or 500g, which is split off the item instance.
+        /// Defaults to all of it.
+        #[structopt(short, long)]
+        quantity: Option<QuantityArg>,
+    },
+    /// Print the item instances given away, oldest first.
+    #[structopt(name = "list-given")]
+    ListGiven {
+        /// Only print those given to this person.
+        #[structopt(long)]
+        to: Option<String>,
     },
+    /// Print the item instances that are not at their home location.
+    #[structopt(name = "misplaced")]
+    Misplaced,
+    /// Print the item types flagged for a recount because more was used than what was left,
+    /// oldest flag first. `ut --counted` clears the flag once they are counted.
+    #[structopt(name = "list-recount")]
+    ListRecount,
+    /// Move an item instance back to its home location.
+    #[structopt(name = "put-back")]
+    PutBack {
+        /// The id or label of the item instance.
+        instance: String,
+    },
+    /// Print how much was left in the item instances removed during the last months, per
+    /// month and per reason.
+    #[structopt(name = "waste-report")]
+    WasteReport {
+        /// How many months to report, the current one included.
+        #[structopt(long, default_value = "6")]
+        months: u32,
+    },
+    /// Print the total cost of ownership of an item type: the value of the item instances
+    /// added during the last months, what was wasted of it, what is still in stock, what was
+    /// used up and what each unit used up cost.
+    #[structopt(name = "tco")]
+    Tco {
+        /// The id or name of the item type.
+        #[structopt(required_unless = "all", conflicts_with = "all")]
+        item_type: Option<String>,
+        /// How many months of purchases to count, the current one included.
+        #[structopt(long, default_value = "12")]
+        months: u32,
+        /// Print every item type instead, the ones wasting the largest part of what was
+        /// purchased first.
+        #[structopt(long)]
+        all: bool,
+    },
+    /// Print how complete the data is: the share of item types with a ttl, a minimum and a
+    /// unit, of item instances with a location, an expiry and a value, the item instances
+    /// untouched for over a year, and the records missing the most, with a score weighing
+    /// them. The weights are set in <name>_config.json.
+    #[structopt(name = "health")]
+    Health {
+        /// How many of the most incomplete records to print.
+        #[structopt(long, default_value = "10")]
+        top: usize,
+    },
+    /// Print the item types without activity for a while and the item instances left
+    /// unopened as long, with their last activity: when an item instance was added or
+    /// opened, or, from the event log, created or updated.
+    #[structopt(name = "list-idle")]
+    ListIdle {
+        /// How long without activity, like 6months.
+        #[structopt(long = "for", default_value = "1year")]
+        duration: TimeArg,
+        /// Delete the idle item types, with their item instances, once confirmed.
+        #[structopt(long)]
+        delete: bool,
+        /// Delete without asking.
+        #[structopt(long, requires = "delete")]
+        yes: bool,
+    },
+    /// Print the tree of the categories of the item types, with for each category the number
+    /// of item types, their total quantity when they have the same unit, their total value
+    /// and how many are below their minimum, subcategories included.
+    #[structopt(name = "categories")]
+    Categories,
+    /// Print the value of the item instances in the inventory per item type, with the total.
+    #[structopt(name = "value-report")]
+    ValueReport {
+        /// Also print what they are worth now, for item types losing value over the years,
+        /// see ct --depreciation-years.
+        #[structopt(long)]
+        depreciated: bool,
+    },
+    /// Give a label to the item instances that have none, printing their ids and new labels.
+    #[structopt(name = "generate-labels")]
+    GenerateLabels,
+    /// Fill an empty inventory with fake but realistic item types and item instances, the
+    /// same for the same seed, for demos and benchmarks.
+    #[structopt(name = "generate-demo", setting = structopt::clap::AppSettings::Hidden)]
+    GenerateDemo {
+        /// How many item types to generate.
+        #[structopt(long, default_value = "20")]
+        types: usize,
+        /// How many item instances to generate.
+        #[structopt(long, default_value = "60")]
+        instances: usize,
+        /// The seed of the generated data.
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+        /// Replace the item types and item instances of an inventory that is not empty.
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Put all the empty item instances in the trash, printing their ids.
+    #[structopt(name = "trash-empty")]
+    TrashEmpty {
+        /// Only trash the item instances of this item type.
+        #[structopt(short, long)]
+        type_id: Option<TypeId>,
+    },
+    /// Renumber the item types and item instances sequentially from 1, printing the old and
+    /// new ids.
+    #[structopt(name = "compact-ids")]
+    CompactIds(CompactIdsCommand),
+    /// List, create, change or delete the classes of item types, which are named bundles of defaults.
+    #[structopt(name = "classes")]
+    Classes(ClassesCommand),
+    /// Export or import all the files of the inventory as a single archive.
+    #[structopt(name = "archive")]
+    Archive(ArchiveCommand),
+    /// Upgrade the types and instances files to a format version, or print their version.
+    /// Files written by older versions are otherwise upgraded when they are next saved.
+    #[structopt(name = "migrate")]
+    Migrate {
+        /// The format version to upgrade to. Defaults to the latest one.
+        #[structopt(long)]
+        to: Option<u32>,
+        /// Only print the format version of the files, without changing anything.
+        #[structopt(long, conflicts_with = "to")]
+        check: bool,
+    },
+    /// Encrypt the types and instances files with a passphrase, see the man page for where it
+    /// is read from. The temporary files and the journal of the saves are encrypted too.
+    #[structopt(name = "encrypt")]
+    Encrypt,
+    /// Decrypt the types and instances files of an encrypted inventory.
+    #[structopt(name = "decrypt")]
+    Decrypt,
+    /// List the profiles of the profiles file, marking the default one.
+    #[structopt(name = "profiles")]
+    Profiles,
+    /// Write a .inventory-managoat.toml file naming the inventory in the current directory,
+    /// so that inv uses it there and below without --workdir and --name.
+    #[structopt(name = "link")]
+    Link {
+        /// The directory to write the file in, instead of the current directory.
+        #[structopt(long)]
+        dir: Option<PathBuf>,
+        /// Replace the file of the directory if there is one.
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Export the expiry dates as an iCalendar file with one all-day event per item instance.
+    #[structopt(name = "export-ical")]
+    ExportIcal(ExportIcalCommand),
+    /// Export the inventory as a readable document with a section per location and the items to buy.
+    #[structopt(name = "export")]
+    Export(ExportCommand),
+    /// Print a graph of the locations and of the item types stored there, for Graphviz or
+    /// Mermaid.
+    #[structopt(name = "graph-locations")]
+    GraphLocations {
+        /// The format of the graph: dot or mermaid.
+        #[structopt(short, long, default_value = "dot")]
+        format: graph::Format,
+        /// Write the graph to this file instead of printing it.
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write a read-only snapshot of the item types and of the item instances that are not in
+    /// the trash to a single file, to share it, see --from-published.
+    #[structopt(name = "publish")]
+    Publish {
+        /// The file to write, replaced atomically.
+        /// Defaults to <name>_published.json in the working directory.
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+        /// The fields of the item instances to leave out, separated by commas:
+        /// value, serial, model, extra or location, which includes the home location.
+        #[structopt(long, use_delimiter = true, possible_values = publish::Redacted::NAMES)]
+        redact: Vec<publish::Redacted>,
+    },
+    /// Print metrics in the Prometheus text format, for the node_exporter textfile collector.
+    #[structopt(name = "metrics")]
+    Metrics {
+        /// Write the metrics to this file instead of printing them.
+        /// The file is replaced atomically.
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+        /// Also report the sum of the deposits not refunded yet.
+        #[structopt(long)]
+        include_deposits: bool,
+    },
+    /// Import the products and stock entries of a Grocy export.
+    #[cfg(feature = "grocy")]
+    #[structopt(name = "import-grocy")]
+    ImportGrocy {
+        /// The directory containing products.json, stock.json and optionally locations.json,
+        /// as returned by the Grocy API.
+        path: PathBuf,
+    },
+    /// Import the item types and item instances printed by the minimal mode of the first
+    /// versions, one record per line with its fields separated by semicolons.
+    #[structopt(name = "import-legacy")]
+    ImportLegacy {
+        /// The file of the item types.
+        types: PathBuf,
+        /// The file of the item instances.
+        instances: PathBuf,
+    },
+    /// Print the item types and item instances as they were at a time, rebuilt from the
+    /// event log, which records the changes made to the inventory files.
+    #[structopt(name = "state-at")]
+    StateAt {
+        /// The time: now, a date like 2021-06-01, meaning the end of that day in UTC, or a
+        /// date and time like "2021-06-01 18:00:00".
+        #[structopt(parse(try_from_str = parse_point_in_time))]
+        at: PointInTime,
+        /// Also compare the inventory rebuilt up to now with the inventory files, printing the
+        /// item types and item instances that differ.
+        #[structopt(long)]
+        verify: bool,
+    },
+    /// Find the location names written in several ways, like "Pantry", "pantry " and
+    /// "pantery", and rename each group to a single name after asking.
+    #[structopt(name = "normalize-locations")]
+    NormalizeLocations {
+        /// Only print the location names, how much they are used and the proposed renames.
+        #[structopt(long, conflicts_with = "apply")]
+        dry_run: bool,
+        /// Rename the locations of a json file mapping location names to new ones, like
+        /// {"Pantry": "pantry"}, without asking.
+        #[structopt(long)]
+        apply: Option<PathBuf>,
+    },
+    /// Register a location with how much it can hold, see location-list.
+    #[structopt(name = "location-add")]
+    LocationAdd {
+        /// The name of the location, like Freezer.
+        name: String,
+        /// How much the location can hold, in the unit of --unit.
+        #[structopt(short, long, parse(try_from_str = parse_decimal))]
+        capacity: Option<f32>,
+        /// The unit of the capacity, like l. Without one, the item instances without a unit
+        /// are counted.
+        #[structopt(short, long)]
+        unit: Option<String>,
+    },
+    /// Change the capacity of a registered location.
+    #[structopt(name = "location-set")]
+    LocationSet {
+        /// The name of the registered location.
+        name: String,
+        /// How much the location can hold. 0 removes the capacity.
+        #[structopt(short, long, parse(try_from_str = parse_decimal))]
+        capacity: Option<f32>,
+        /// The unit of the capacity. Without a value, removes the unit.
+        #[structopt(short, long)]
+        unit: Option<Option<String>>,
+    },
+    /// List the registered locations with how much is stored there, flagging those over
+    /// their capacity.
+    #[structopt(name = "location-list")]
+    LocationList,
+    /// Load the inventory once and run the commands read from stdin, one per line, like
+    /// `ri -e`, until quit. save writes the inventory and reload loads it again; changes
+    /// are also saved at most every 2 seconds and when the session ends.
+    #[structopt(name = "repl")]
+    Repl,
+    /// Print examples of the subcommands, with what they do.
+    #[structopt(name = "examples")]
+    Examples {
+        /// The subcommand to print the examples of, like ct. Defaults to every subcommand.
+        command: Option<String>,
+    },
+}
+
+impl Command {
+    /// The inventory files the command needs. Commands that only change item instances
+    /// don't load nor save the item types, which can be a large file.
+    pub fn files(&self) -> Files {
+        match self {
+            // Custom fields are checked against the item type.
+            Command::UpdateInstance(UpdateInstanceCommand { set, .. }) if !set.is_empty() => {
+                Files::Both
+            }
+            Command::Trash { quantity: None, .. }
+            | Command::UpdateInstance(UpdateInstanceCommand { quantity: None, .. })
+            | Command::DeleteInstance(_)
+            | Command::PutBack { .. } => Files::Instances,
+            _ => Files::Both,
+        }
+    }
+
+    /// Whether the command only reads the inventory, which is all that can be done to a
+    /// published one. Item instances in the trash are not published, so the commands
+    /// about them are left out.
+    pub fn reads_only(&self) -> bool {
+        match self {
+            Command::ReadType(_)
+            | Command::ListExpired { .. }
+            | Command::ListReturnables
+            | Command::ListMissing { .. }
+            | Command::Prices { .. }
+            | Command::FindRef { .. }
+            | Command::Misplaced
+            | Command::ListRecount
+            | Command::ExportIcal(_)
+            | Command::Export(_)
+            | Command::GraphLocations { .. }
+            | Command::Metrics { .. }
+            | Command::Publish { .. }
+            | Command::Health { .. }
+            | Command::Categories
+            | Command::LocationList
+            | Command::ValueReport { .. }
+            | Command::Tco { .. }
+            | Command::Profiles
+            | Command::Examples { .. } => true,
+            Command::ReadInstance(cmd) => !cmd.qr_string,
+            Command::Digest { mark_reminded, .. } => !mark_reminded,
+            Command::UpdateState { dry_run } => *dry_run,
+            Command::ListIdle { delete, .. } => !delete,
+            Command::Replace(cmd) => cmd.dry_run,
+            _ => false,
+        }
+    }
+
+    /// Whether the changes made by the command are added to the event log. Importing an
+    /// archive replaces the log with the imported one, and the registered locations are not
+    /// part of the log.
+    pub fn logged(&self) -> bool {
+        !matches!(
+            self,
+            Command::Archive(_) | Command::LocationAdd { .. } | Command::LocationSet { .. }
+        )
+    }
+
+    /// The name of the subcommand, as typed on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::CreateType(_) => "ct",
+            Command::ReadType(_) => "rt",
+            Command::UpdateType(_) => "ut",
+            Command::DeleteType(_) => "dt",
+            Command::RecomputeExpiry(_) => "recompute-expiry",
+            Command::BulkUpdateTypes(_) => "bulk-update-types",
+            Command::SetIconsFromMap { .. } => "set-icons-from-map",
+            Command::CreateInstance(_) => "ci",
+            Command::ReadInstance(_) => "ri",
+            Command::UpdateInstance(_) => "ui",
+            Command::DeleteInstance(_) => "di",
+            Command::ListExpired { .. } => "list-expired",
+            Command::ListReturnables => "list-returnables",
+            Command::ReturnDeposit { .. } => "return-deposit",
+            Command::ListMissing { .. } => "list-missing",
+            Command::Digest { .. } => "digest",
+            Command::UpdateState { .. } => "update-state",
+            Command::Snooze { .. } => "snooze",
+            Command::Unsnooze { .. } => "unsnooze",
+            Command::Prices { .. } => "prices",
+            Command::Use { .. } => "use",
+            Command::Finish { .. } => "finish",
+            Command::TopUp { .. } => "top-up",
+            Command::ParseQr { .. } => "parse-qr",
+            Command::FindRef { .. } => "find-ref",
+            Command::Trash { .. } => "trash",
+            Command::TrashWhere(_) => "trash-where",
+            Command::Replace(_) => "replace",
+            Command::ListTrashed { .. } => "list-trashed",
+            Command::Give { .. } => "give",
+            Command::ListGiven { .. } => "list-given",
+            Command::Misplaced => "misplaced",
+            Command::ListRecount => "list-recount",
+            Command::PutBack { .. } => "put-back",
+            Command::WasteReport { .. } => "waste-report",
+            Command::Health { .. } => "health",
+            Command::ListIdle { .. } => "list-idle",
+            Command::Categories => "categories",
+            Command::ValueReport { .. } => "value-report",
+            Command::Tco { .. } => "tco",
+            Command::GenerateLabels => "generate-labels",
+            Command::GenerateDemo { .. } => "generate-demo",
+            Command::TrashEmpty { .. } => "trash-empty",
+            Command::CompactIds(_) => "compact-ids",
+            Command::Classes(_) => "classes",
+            Command::Archive(_) => "archive",
+            Command::Migrate { .. } => "migrate",
+            Command::Encrypt => "encrypt",
+            Command::Decrypt => "decrypt",
+            Command::Profiles => "profiles",
+            Command::Link { .. } => "link",
+            Command::ExportIcal(_) => "export-ical",
+            Command::Export(_) => "export",
+            Command::GraphLocations { .. } => "graph-locations",
+            Command::Publish { .. } => "publish",
+            Command::Metrics { .. } => "metrics",
+            #[cfg(feature = "grocy")]
+            Command::ImportGrocy { .. } => "import-grocy",
+            Command::ImportLegacy { .. } => "import-legacy",
+            Command::StateAt { .. } => "state-at",
+            Command::NormalizeLocations { .. } => "normalize-locations",
+            Command::LocationAdd { .. } => "location-add",
+            Command::LocationSet { .. } => "location-set",
+            Command::LocationList => "location-list",
+            Command::Repl => "repl",
+            Command::Examples { .. } => "examples",
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub enum ClassesCommand {
+    /// List the classes.
+    #[structopt(name = "list")]
+    List,
+    /// Create a class or change its defaults. Item types are not changed.
+    #[structopt(name = "set")]
+    Set {
+        /// The name of the class.
+        name: String,
+        /// The time to live of the item types once opened.
+        #[structopt(short, long)]
+        ttl: Option<Option<TimeArg>>,
+        /// Whether the item types are in the 'opened' state by default.
+        #[structopt(short, long)]
+        open_by_default: Option<bool>,
+        /// Whether item instances emptied by `use` are put in the trash.
+        #[structopt(long)]
+        auto_trash_empty: Option<bool>,
+        /// Whether only the item instances that are not opened count towards the minimum quantities.
+        #[structopt(long)]
+        minimum_counts_sealed_only: Option<bool>,
+    },
+    /// Delete a class. The item types it was applied to keep their values.
+    #[structopt(name = "delete")]
+    Delete {
+        /// The name of the class.
+        name: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum ArchiveCommand {
+    /// Write the inventory files into a compressed archive.
+    #[structopt(name = "export")]
+    Export {
+        /// The archive to create, usually ending with .tar.gz.
+        path: PathBuf,
+    },
+    /// Replace the inventory files with the ones contained in an archive.
+    #[structopt(name = "import")]
+    Import {
+        /// The archive to read.
+        path: PathBuf,
+        /// Replace the inventory even if it already exists.
+        #[structopt(short, long)]
+        force: bool,
+    },
+}
+
+// What snooze and unsnooze apply to. Not a doc comment, which structopt would use as the
+// description of both subcommands.
+#[derive(StructOpt, Debug)]
+pub struct SnoozeTarget {
+    /// The id or label of the item instance.
+    #[structopt(required_unless = "type-id", conflicts_with = "type-id")]
+    instance: Option<String>,
+    /// The id of the item type, whose item instances are all snoozed, instead of an item
+    /// instance.
+    #[structopt(long)]
+    type_id: Option<TypeId>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExportIcalCommand {
+    /// Only export the item instances expiring within this duration. Already expired item instances are always exported.
+    #[structopt(long)]
+    within: Option<TimeArg>,
+    /// Add an alarm this many days before each expiry.
+    #[structopt(short, long)]
+    alarm_days: Option<u32>,
+    /// Write the calendar to this file instead of printing it.
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExportCommand {
+    /// The format of the document: markdown, or xlsx for a spreadsheet with sheets of the
+    /// item types, the item instances and the digest numbers, which needs --output.
+    #[structopt(short, long, default_value = "markdown")]
+    format: report::Format,
+    /// What the sections are made of: location or type-class.
+    #[structopt(short, long, default_value = "location")]
+    group_by: report::GroupBy,
+    /// Write the document to this file instead of printing it.
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
 }
 
 #[derive(StructOpt, Debug)]
@@ -110,57 +2033,295 @@ pub struct CreateTypeCommand {
     /// The name of the item type.
     name: String,
     /// The minimum quantity of this item type you want to have at all times.
-    #[structopt(short, long, default_value = "0.0")]
+    #[structopt(short, long, default_value = "0.0", parse(try_from_str = parse_decimal))]
     minimum_quantity: f32,
     /// The time to live of this item type once it is opened.
     #[structopt(short, long)]
-    ttl: Option<humantime::Duration>,
+    ttl: Option<TimeArg>,
     /// Whether this item is in the 'opened' state by default. For example fresh food.
     #[structopt(short, long)]
     open_by_default: Option<bool>,
-}
-
-#[derive(StructOpt, Debug)]
-pub struct ReadTypeCommand {
-    /// The id of the item type you want to view.
-    #[structopt(short, long)]
-    id: Option<u32>,
-    /// The name of the item type you want to view.
-    #[structopt(short, long)]
+    /// Whether item instances emptied by `use` are put in the trash. Defaults to true.
+    #[structopt(long)]
+    auto_trash_empty: Option<bool>,
+    /// Whether update-state puts the item instances in the trash once they expire. Defaults
+    /// to false.
+    #[structopt(long)]
+    auto_trash_on_expiry: Option<bool>,
+    /// The minimum quantity to keep at a location, as `location=quantity`. Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    min_at: Vec<LocationMinimum>,
+    /// Whether only the item instances that are not opened count towards the minimum quantities.
+    /// Defaults to false.
+    #[structopt(long)]
+    minimum_counts_sealed_only: Option<bool>,
+    /// Use the defaults of this class. The other options take precedence over them.
+    #[structopt(short, long)]
+    class: Option<String>,
+    /// The deposit paid for the container of each item instance, refunded when it is returned.
+    #[structopt(long, parse(try_from_str = parse_decimal))]
+    deposit: Option<f32>,
+    /// The unit of the quantities, like kg. Quantities in mg, g and kg, or in ml, cl and l,
+    /// are converted to it.
+    #[structopt(long)]
+    unit: Option<String>,
+    /// Report the item instances in the digest once they have been opened for this long,
+    /// whatever their expiry, like 5days for a sourdough starter to feed.
+    #[structopt(long)]
+    remind_after_open: Option<TimeArg>,
+    /// Whether using more than what is left is recorded anyway, flagging the item type for a
+    /// recount, for consumables that are not tracked closely. Defaults to false.
+    #[structopt(long)]
+    allow_overdraft: Option<bool>,
+    /// Whether the item type is counted in whole pieces, refusing fractional quantities.
+    /// Defaults to false.
+    #[structopt(long)]
+    integer_only: Option<bool>,
+    /// The category of the item type, as a path like Food/Baking/Flour.
+    #[structopt(long)]
+    category: Option<String>,
+    /// How many years the item instances take to lose all their value, straight-line from
+    /// when they are added, for durable things like tools. See value-report.
+    #[structopt(long, parse(try_from_str = parse_decimal))]
+    depreciation_years: Option<f32>,
+    /// An icon for frontends: an emoji, the key of an icon set, or an image file in the
+    /// icons directory of the working directory, like dairy/milk.png.
+    #[structopt(long)]
+    icon: Option<String>,
+    /// The quantity of the packages the item type is sold in, in its unit, like 1 for bags
+    /// of 1 kg. The shopping list rounds up to whole packages.
+    #[structopt(long, parse(try_from_str = parse_decimal))]
+    package_size: Option<f32>,
+    /// Where the item type is usually bought, like a store. See list-missing --group-by.
+    #[structopt(long)]
+    vendor: Option<String>,
+    /// Also create an item instance of the new item type, described by the --quantity,
+    /// --location, --expires-at and --value options. Nothing is created if it is invalid.
+    #[structopt(long)]
+    with_instance: bool,
+    /// The quantity of the item instance. Defaults to 1.0.
+    #[structopt(long, requires = "with-instance", parse(try_from_str = parse_decimal))]
+    quantity: Option<f32>,
+    /// The location where the item instance is stored.
+    #[structopt(long, requires = "with-instance")]
+    location: Option<String>,
+    /// The date/time at which the item instance expires (use-by date).
+    #[structopt(long, requires = "with-instance")]
+    expires_at: Option<TimeArg>,
+    /// The monetary value of the item instance.
+    #[structopt(long, requires = "with-instance", parse(try_from_str = parse_decimal))]
+    value: Option<f32>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ReadTypeCommand {
+    /// The id of the item type you want to view.
+    #[structopt(short, long)]
+    id: Option<TypeId>,
+    /// Only show the item types whose name or a former name contains this text, ignoring case.
+    #[structopt(short, long)]
     name: Option<String>,
+    /// Only show the item types whose name or a former name is exactly the one given with
+    /// --name, ignoring case.
+    #[structopt(long, requires = "name")]
+    exact: bool,
+    /// Only show the item types without a ttl.
+    #[structopt(long)]
+    no_ttl: bool,
+    /// Only show the item types that are opened by default.
+    #[structopt(long)]
+    open_by_default: bool,
+    /// Only show the item types with a minimum quantity above 0.
+    #[structopt(long)]
+    has_min: bool,
+    /// Only show the item types without any item instance that is not removed.
+    #[structopt(long)]
+    unused: bool,
+    /// Only show the item types in this category or in its subcategories, like Food/Baking.
+    #[structopt(long)]
+    category: Option<String>,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct UpdateTypeCommand {
     /// The id of the item type you want to edit.
-    id: u32,
-    /// Set the new name of this item type.
+    id: TypeId,
+    /// Set the new name of this item type. The previous name is kept as a former name, which
+    /// still finds the item type.
     #[structopt(short, long)]
     name: Option<String>,
+    /// Rename even when another item type has or had the name, ignoring case.
+    #[structopt(long, requires = "name")]
+    force: bool,
     /// The minimum quantity of this item type you want to have at all times.
-    #[structopt(short, long)]
+    #[structopt(short, long, parse(try_from_str = parse_decimal))]
     minimum_quantity: Option<f32>,
     /// The time to live of this item type once it is opened.
     #[structopt(short, long)]
-    ttl: Option<Option<humantime::Duration>>,
+    ttl: Option<Option<TimeArg>>,
     /// Whether this item is in the 'opened' state by default. For example fresh food.
     #[structopt(short, long)]
     open_by_default: Option<bool>,
+    /// Whether item instances emptied by `use` are put in the trash. Defaults to true.
+    #[structopt(long)]
+    auto_trash_empty: Option<bool>,
+    /// Whether update-state puts the item instances in the trash once they expire.
+    #[structopt(long)]
+    auto_trash_on_expiry: Option<bool>,
+    /// Set the minimum quantity to keep at a location, as `location=quantity`.
+    /// A quantity of 0 removes the minimum of that location. Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    min_at: Vec<LocationMinimum>,
+    /// Whether only the item instances that are not opened count towards the minimum quantities.
+    #[structopt(long)]
+    minimum_counts_sealed_only: Option<bool>,
+    /// Apply the defaults of this class again. The other options take precedence over them.
+    #[structopt(short, long)]
+    class: Option<String>,
+    /// The deposit paid for the container of each item instance. 0 removes the deposit.
+    #[structopt(long, parse(try_from_str = parse_decimal))]
+    deposit: Option<f32>,
+    /// The unit of the quantities. Without a value, removes the unit.
+    /// The quantities of the item instances are not converted.
+    #[structopt(long)]
+    unit: Option<Option<String>>,
+    /// Replace the minimum quantity during a part of every year, as `MM-DD..MM-DD=quantity`
+    /// with both days included, like `05-01..09-30=2`. Ranges can wrap over the new year but
+    /// not overlap. Can be repeated, and replaces the previous seasonal minimums.
+    #[structopt(long, number_of_values = 1)]
+    seasonal: Vec<SeasonRule>,
+    /// Remove the seasonal minimums.
+    #[structopt(long, conflicts_with = "seasonal")]
+    clear_seasonal: bool,
+    /// Report the item instances in the digest once they have been opened for this long,
+    /// whatever their expiry. Without a duration, removes the reminder.
+    #[structopt(long)]
+    remind_after_open: Option<Option<TimeArg>>,
+    /// Whether using more than what is left is recorded anyway, flagging the item type for a
+    /// recount.
+    #[structopt(long)]
+    allow_overdraft: Option<bool>,
+    /// Whether the item type is counted in whole pieces, refusing fractional quantities.
+    #[structopt(long)]
+    integer_only: Option<bool>,
+    /// The category of the item type, as a path like Food/Baking/Flour. Without a value,
+    /// removes the category.
+    #[structopt(long)]
+    category: Option<Option<String>>,
+    /// How many years the item instances take to lose all their value. 0 removes the
+    /// depreciation.
+    #[structopt(long, parse(try_from_str = parse_decimal))]
+    depreciation_years: Option<f32>,
+    /// An icon for frontends, see ct --icon. Without a value, removes the icon.
+    #[structopt(long)]
+    icon: Option<Option<String>>,
+    /// Where the item type is usually bought. Without a value, removes the vendor.
+    #[structopt(long)]
+    vendor: Option<Option<String>>,
+    /// The quantity of the packages the item type is sold in. 0 removes the package size.
+    #[structopt(long, parse(try_from_str = parse_decimal))]
+    package_size: Option<f32>,
+    /// Record that the item instances of the item type were counted again and are right,
+    /// clearing its recount flag, see list-recount.
+    #[structopt(long)]
+    counted: bool,
+    /// Declare a custom field of the item instances, as `key:kind` where the kind is text,
+    /// number, date or bool, like `chemistry:text`. Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    add_field: Vec<FieldDef>,
+}
+
+/// A minimum quantity for a location, parsed from `location=quantity`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocationMinimum {
+    location: String,
+    minimum: f32,
+}
+
+impl std::str::FromStr for LocationMinimum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (location, minimum) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected location=quantity, got \"{}\"", s))?;
+        let location = location.trim();
+        if location.is_empty() {
+            return Err(format!("missing location in \"{}\"", s));
+        }
+        let minimum =
+            parse_decimal(minimum).map_err(|e| format!("invalid quantity in \"{}\": {}", s, e))?;
+        if !minimum.is_finite() || minimum < 0.0 {
+            return Err(format!("invalid quantity in \"{}\": must be positive", s));
+        }
+        Ok(LocationMinimum {
+            location: location.to_string(),
+            minimum,
+        })
+    }
 }
 
 #[derive(StructOpt, Debug)]
 pub struct DeleteTypeCommand {
     /// The id of the item type you want to delete.
-    id: u32,
+    id: TypeId,
+    /// Moves the item instances of the item type to the item type with this id, for example
+    /// when deleting a duplicate, instead of deleting them.
+    #[structopt(long)]
+    reassign_to: Option<TypeId>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct BulkUpdateTypesCommand {
+    /// Only update the item types whose name contains this text, ignoring case.
+    #[structopt(long)]
+    name_contains: Option<String>,
+    /// Only update the item types that have no ttl.
+    #[structopt(long)]
+    no_ttl: bool,
+    /// Set the time to live once opened. Without a duration, the ttl is removed.
+    #[structopt(long)]
+    set_ttl: Option<Option<TimeArg>>,
+    /// Set the minimum quantity.
+    #[structopt(long, parse(try_from_str = parse_decimal))]
+    set_min: Option<f32>,
+    /// Set whether the item types are in the 'opened' state by default.
+    #[structopt(long)]
+    set_open_by_default: Option<bool>,
+    /// Also derive again the expiry of the opened item instances when the ttl is set,
+    /// like `recompute-expiry` does.
+    #[structopt(long)]
+    recompute: bool,
+    /// Print the changes without saving them.
+    #[structopt(long)]
+    dry_run: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RecomputeExpiryCommand {
+    /// The id of the item type whose item instances are updated.
+    type_id: TypeId,
+    /// Never change the item instances that are not opened, even with --force.
+    #[structopt(long)]
+    only_opened: bool,
+    /// Also derive the expiry of the item instances that are not opened, from the time they
+    /// were added and the ttl. Otherwise their expiry is considered explicitly set and is kept.
+    #[structopt(short, long)]
+    force: bool,
+    /// Print the changes without applying them.
+    #[structopt(short, long)]
+    dry_run: bool,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct CreateInstanceCommand {
-    /// The id of the item type associated with this new item instance.
-    item_type: u32,
-    /// The quantity of this item instance. The unit is specified in the item instance. Defaults to 1.0.
+    /// The id or name of the item type associated with this new item instance, designated
+    /// like for `use`.
+    item_type: String,
+    /// The quantity of this item instance, optionally with a unit like 1.5kg that is converted
+    /// to the unit of the item instance. Defaults to 1.0.
     #[structopt(short, long, default_value = "1.0")]
-    quantity: f32,
+    quantity: QuantityArg,
     /// The model name of this item instance.
     #[structopt(short, long)]
     model: Option<String>,
@@ -173,36 +2334,282 @@ pub struct CreateInstanceCommand {
     /// The location where this item instance is stored.
     #[structopt(short, long)]
     location: Option<String>,
+    /// Where this item instance belongs, when it can be elsewhere for a while.
+    /// See `misplaced`.
+    #[structopt(long)]
+    home_location: Option<String>,
     /// The monetary value of this item instance.
-    #[structopt(short, long)]
+    #[structopt(short, long, parse(try_from_str = parse_decimal))]
     value: Option<f32>,
-    /// The date/time at which this item instance expires.
+    /// The date/time at which this item instance expires (use-by date).
+    #[structopt(short, long)]
+    expires_at: Option<TimeArg>,
+    /// The date/time after which this item instance is no longer at its best.
     #[structopt(short, long)]
-    expires_at: Option<humantime::Timestamp>,
+    best_before_at: Option<TimeArg>,
+    /// The date/time at which this item instance was used for the first time, like now,
+    /// 2021-06-01 or a duration counting back from now: 3d means 3 days ago, unlike
+    /// --expires-at. Given, it wins over the item type being opened by default, which opens
+    /// it now, and the ttl counts from it.
+    #[structopt(short, long)]
+    opened_at: Option<TimeArg>,
+    /// The unit of the quantity when it differs from the unit of the item type, like g
+    /// for an item type in kg. It has to be convertible to the unit of the item type.
+    #[structopt(short, long)]
+    unit: Option<String>,
+    /// Create a container that is topped up rather than replaced, like a jar of rice. It
+    /// stays in the inventory when it is used up, see `top-up`.
+    #[structopt(long)]
+    continuous: bool,
+    /// A reference outside of the inventory, like an order number or the url of the
+    /// purchase. See `find-ref`.
+    #[structopt(long = "ref")]
+    external_ref: Option<String>,
+    /// Where this item instance was bought, when it isn't the vendor of its item type.
+    #[structopt(long)]
+    vendor: Option<String>,
+    /// Set a custom field declared by the item type, as `key=value`, like
+    /// `chemistry=LiIon`. Dates are written like 2024-01-31 and bools as true or false. Can
+    /// be repeated.
+    #[structopt(long, number_of_values = 1)]
+    set: Vec<FieldAssignment>,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct ReadInstanceCommand {
     /// The id of the item instance.
     #[structopt(short, long)]
-    id: Option<u32>,
+    id: Option<InstanceId>,
     /// The type of the associated item type.
     #[structopt(short, long)]
-    type_id: Option<u32>,
-    /// The name of the associated item type.
+    type_id: Option<TypeId>,
+    /// Only show the item instances whose item type name contains this text, ignoring case.
     #[structopt(long)]
     type_name: Option<String>,
+    /// Only show the item instances whose item type name is exactly the one given with
+    /// --type-name, ignoring case.
+    #[structopt(long, requires = "type-name")]
+    exact: bool,
     /// List only item instances that are expired.
     #[structopt(short, long)]
     expired: bool,
+    /// List only item instances that are empty but not in the trash.
+    #[structopt(long)]
+    empty: bool,
+    /// Group the item instances by type, location or home-location, with subtotals after
+    /// each group. Ignored in minimal mode.
+    #[structopt(short, long, parse(try_from_str = parse_group_key))]
+    group_by: Option<GroupKey>,
+    /// Only show the item instances added since this time, like 7days for the last week,
+    /// today or 2030-06-01.
+    #[structopt(long)]
+    added_since: Option<TimeArg>,
+    /// Only show the item instances whose external reference contains this text, ignoring
+    /// case.
+    #[structopt(long = "ref")]
+    external_ref: Option<String>,
+    /// Print the item instance as a compact string to put in a QR code, see `parse-qr`.
+    #[structopt(long, requires = "id", conflicts_with = "group-by")]
+    qr_string: bool,
+    /// Only show the item instances whose custom field compares to a value, as `key=value`
+    /// or with !=, <, <=, > or >= instead of =, like `voltage>=3.7`. Texts are compared
+    /// ignoring case. Can be repeated.
+    #[structopt(long = "where", number_of_values = 1)]
+    custom: Vec<FieldFilter>,
+}
+
+/// How the shopping list is split into sections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShoppingGroup {
+    Vendor,
+}
+
+fn parse_shopping_group(s: &str) -> Result<ShoppingGroup, String> {
+    match s {
+        "vendor" => Ok(ShoppingGroup::Vendor),
+        _ => Err(format!("unknown grouping {}, expected vendor", s)),
+    }
+}
+
+fn parse_group_key(s: &str) -> Result<GroupKey, String> {
+    match s {
+        "type" => Ok(GroupKey::Type),
+        "location" => Ok(GroupKey::Location),
+        "home-location" => Ok(GroupKey::HomeLocation),
+        _ => Err(format!(
+            "unknown grouping {}, expected type, location or home-location",
+            s
+        )),
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct TrashWhereCommand {
+    /// Only trash the item instances of this item type.
+    #[structopt(short, long)]
+    type_id: Option<TypeId>,
+    /// Only trash the item instances whose item type name contains this text, ignoring case.
+    #[structopt(long)]
+    type_name: Option<String>,
+    /// Only trash the item instances whose item type name is exactly the one given with
+    /// --type-name, ignoring case.
+    #[structopt(long, requires = "type-name")]
+    exact: bool,
+    /// Only trash the item instances whose location contains this text, ignoring case.
+    #[structopt(short, long)]
+    location: Option<String>,
+    /// Only trash the item instances that are expired.
+    #[structopt(short, long)]
+    expired: bool,
+    /// Only trash the item instances that are empty.
+    #[structopt(long)]
+    empty: bool,
+    /// Trash every item instance. Without any filter, nothing is trashed unless this is given.
+    #[structopt(long, conflicts_with_all = &["type-id", "type-name", "location", "expired", "empty"])]
+    all: bool,
+    /// Why the item instances are thrown away, like for trash.
+    #[structopt(long, parse(try_from_str = parse_removal_reason))]
+    reason: Option<String>,
+    /// Trash the item instances without asking, which is needed without a terminal.
+    #[structopt(short, long)]
+    yes: bool,
+}
+
+impl TrashWhereCommand {
+    fn query(&self) -> InstanceQuery {
+        let mut query = InstanceQuery::new();
+        if let Some(type_id) = self.type_id {
+            query = query.type_id(type_id);
+        }
+        if let Some(type_name) = &self.type_name {
+            query = query.type_name_matching(type_name, name_mode(self.exact));
+        }
+        if let Some(location) = &self.location {
+            query = query.location_contains(location);
+        }
+        if self.expired {
+            query = query.expired(true);
+        }
+        if self.empty {
+            query = query.empty(true);
+        }
+        query
+    }
+}
+
+/// The field `replace` replaces in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaceField {
+    /// The names of the item types.
+    TypeName,
+    Instance(InstanceTextField),
+}
+
+impl std::str::FromStr for ReplaceField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(ReplaceField::TypeName),
+            _ => s.parse().map(ReplaceField::Instance).map_err(|_| {
+                format!(
+                    "unknown field {}, expected name, model, serial, extra or location",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ReplaceCommand {
+    /// The field to replace in: name for the names of the item types, or model, serial, extra
+    /// or location for the item instances.
+    #[structopt(long)]
+    field: ReplaceField,
+    /// The text to find, matching case, or a regular expression with --regex.
+    #[structopt(long)]
+    from: String,
+    /// The text to put instead. With --regex, $1 or ${name} is replaced by what a capture
+    /// group matched.
+    #[structopt(long)]
+    to: String,
+    /// Reads --from as a regular expression.
+    #[structopt(long)]
+    regex: bool,
+    /// Prints what would be replaced, before and after, without changing anything.
+    #[structopt(long)]
+    dry_run: bool,
+    /// Allows --regex with --field name. Item types are found by their names, so a loose
+    /// regular expression could rename many of them by mistake.
+    #[structopt(long)]
+    force: bool,
+    /// Only replace in this item type, or in its item instances.
+    #[structopt(short, long)]
+    type_id: Option<TypeId>,
+    /// Only replace in the item types whose name contains this text, ignoring case, or in
+    /// their item instances.
+    #[structopt(long)]
+    type_name: Option<String>,
+    /// Only replace in the item types whose name is exactly the one given with --type-name,
+    /// ignoring case.
+    #[structopt(long, requires = "type-name")]
+    exact: bool,
+    /// Only replace in the item instances whose location contains this text, ignoring case.
+    #[structopt(short, long)]
+    location: Option<String>,
+}
+
+impl ReplaceCommand {
+    fn instance_query(&self) -> InstanceQuery {
+        let mut query = InstanceQuery::new();
+        if let Some(type_id) = self.type_id {
+            query = query.type_id(type_id);
+        }
+        if let Some(type_name) = &self.type_name {
+            query = query.type_name_matching(type_name, name_mode(self.exact));
+        }
+        if let Some(location) = &self.location {
+            query = query.location_contains(location);
+        }
+        query
+    }
+
+    fn type_query(&self) -> TypeQuery {
+        let mut query = TypeQuery::new();
+        if let Some(type_id) = self.type_id {
+            query = query.id(type_id);
+        }
+        if let Some(type_name) = &self.type_name {
+            query = query.name_matching(type_name, name_mode(self.exact));
+        }
+        query
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CompactIdsCommand {
+    /// Only renumber the item types. Both are renumbered without --types nor --instances.
+    #[structopt(long)]
+    types: bool,
+    /// Only renumber the item instances.
+    #[structopt(long)]
+    instances: bool,
+    /// Renumber the item instances even if compact strings, like those of printed QR codes,
+    /// were made for some of them, which then designate other item instances or none.
+    #[structopt(long)]
+    force: bool,
+    /// Write the old and new ids to this file as json instead of printing them.
+    #[structopt(short, long)]
+    output: Option<PathBuf>,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct UpdateInstanceCommand {
-    /// The id of the item type.
-    id: u32,
+    /// The id or label of the item instance.
+    id: String,
     /// The quantity of this item instance. The unit is specified in the item instance.
-    #[structopt(short, long)]
+    #[structopt(short, long, parse(try_from_str = parse_decimal))]
     quantity: Option<f32>,
     /// The model type of this item instance.
     #[structopt(short, long)]
@@ -216,333 +2623,5786 @@ pub struct UpdateInstanceCommand {
     /// The physical location of this item instance.
     #[structopt(short, long)]
     location: Option<String>,
+    /// Where this item instance belongs. Removes it without a value.
+    #[structopt(long)]
+    home_location: Option<Option<String>>,
+    /// A reference outside of the inventory, like an order number. Removes it without a
+    /// value.
+    #[structopt(long = "ref")]
+    external_ref: Option<Option<String>>,
+    /// Where this item instance was bought. Removes it without a value.
+    #[structopt(long)]
+    vendor: Option<Option<String>>,
     /// The monetary value of this item instance.
-    #[structopt(short, long)]
+    #[structopt(short, long, parse(try_from_str = parse_decimal))]
     value: Option<f32>,
-    /// The date/time at which the item instance will expire.
+    /// The date/time at which the item instance will expire (use-by date).
     #[structopt(short, long)]
-    expires_at: Option<Option<humantime::Timestamp>>,
-    /// The date/time at which this item instance was used for the first time.
+    expires_at: Option<Option<TimeArg>>,
+    /// The date/time after which the item instance is no longer at its best.
     #[structopt(short, long)]
-    opened_at: Option<Option<humantime::Timestamp>>,
+    best_before_at: Option<Option<TimeArg>>,
+    /// The date/time at which this item instance was used for the first time, a duration
+    /// counting back from now like ci --opened-at.
+    #[structopt(short, long)]
+    opened_at: Option<Option<TimeArg>>,
+    /// Set a custom field declared by the item type, like ci --set. An empty value, like
+    /// `chemistry=`, removes the field. Can be repeated.
+    #[structopt(long, number_of_values = 1)]
+    set: Vec<FieldAssignment>,
 }
 
 #[derive(StructOpt, Debug)]
 pub struct DeleteInstanceCommand {
-    /// The id of the item instance.
-    id: u32,
+    /// The id or label of the item instance.
+    id: String,
+    /// Why the item instance is removed, like for trash.
+    #[structopt(long, parse(try_from_str = parse_removal_reason))]
+    reason: Option<String>,
 }
 
-fn main() {
-    let mut manager = Manager::from_args();
-    manager.fix_workdir();
-    let (mut inventory, types_path, instances_path) =
-        load_inventory(&manager).expect("Failed to load the inventory file");
-    manager.exec(&mut inventory);
-    save_inventory(&inventory, types_path, instances_path)
-        .expect("Failed to save data to inventory file.");
+/// A time given on the command line, which can be the current one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointInTime {
+    Now,
+    At(std::time::SystemTime),
 }
 
-pub fn default_workdir() -> PathBuf {
-    let mut dir =
-        dirs::data_local_dir().expect("Failed to locate suitable folder to store inventory data.");
-    dir.push("inventory_managoat");
-    dir
+impl PointInTime {
+    pub fn resolve(self, now: std::time::SystemTime) -> std::time::SystemTime {
+        match self {
+            PointInTime::Now => now,
+            PointInTime::At(at) => at,
+        }
+    }
 }
 
-pub fn load_inventory<'a>(
-    manager: &Manager,
-) -> std::result::Result<(Inventory, PathBuf, PathBuf), std::io::Error> {
-    let name = manager.inventory_name.clone();
-    let workdir = manager
-        .workdir
-        .as_ref()
-        .expect("Manager::fix_workdir wasn't called before this point.");
-    //let verbosity = matches.occurrences_of("v");
-
-    if metadata(workdir.clone()).is_err() {
-        DirBuilder::new().recursive(true).create(workdir.clone())?;
-    }
-
-    let mut types_path = workdir.clone();
-    types_path.push(format!("{}_types.json", name));
-    let mut instances_path = workdir.clone();
-    instances_path.push(format!("{}_instances.json", name));
-
-    if let (Ok(types), Ok(instances)) = (read(&types_path), read(&instances_path)) {
-        // deserialize
-        let item_types =
-            serde_json::from_reader(types.as_slice()).expect("Failed to deserialize types json");
-        let item_instances = serde_json::from_reader(instances.as_slice())
-            .expect("Failed to deserialize instances json");
-        Ok((
-            Inventory {
-                item_types,
-                item_instances,
-            },
-            types_path,
-            instances_path,
-        ))
-    } else {
-        Ok((Inventory::default(), types_path, instances_path))
+/// Parses now, a date, meaning the end of that day in UTC, or a date and time.
+fn parse_point_in_time(s: &str) -> Result<PointInTime, String> {
+    if s == "now" {
+        return Ok(PointInTime::Now);
     }
+    let end_of_day = format!("{} 23:59:59", s);
+    humantime::parse_rfc3339_weak(s)
+        .or_else(|_| humantime::parse_rfc3339_weak(&end_of_day))
+        .map(PointInTime::At)
+        .map_err(|_| {
+            format!(
+                "invalid time \"{}\", expected now, a date like 2021-06-01 or a date and time like \"2021-06-01 18:00:00\"",
+                s
+            )
+        })
 }
 
-pub fn save_inventory(
-    inventory: &Inventory,
-    types_path: PathBuf,
-    instances_path: PathBuf,
-) -> std::result::Result<(), std::io::Error> {
-    let types_file = File::create(types_path)?;
-    let instances_file = File::create(instances_path)?;
-    serde_json::to_writer_pretty(types_file, &inventory.item_types)?;
-    serde_json::to_writer_pretty(instances_file, &inventory.item_instances)?;
-    Ok(())
+fn parse_removal_reason(s: &str) -> Result<String, String> {
+    if REMOVAL_REASONS.contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "unknown reason {}, expected one of {}",
+            s,
+            REMOVAL_REASONS.join(", ")
+        ))
+    }
 }
 
-pub fn create_type<'a>(cmd: &CreateTypeCommand, inventory: &mut Inventory) {
-    let mut new = ItemTypeBuilder::default();
-    new.name(cmd.name.clone());
-    new.minimum_quantity(cmd.minimum_quantity);
-    new.ttl(cmd.ttl.map(|t| t.into()));
-    new.opened_by_default(cmd.open_by_default.unwrap_or(false));
-    let id = inventory.add_item_type(new.build().unwrap());
-    println!("{}", id);
+fn main() {
+    let matches = Manager::clap().get_matches();
+    let mut manager = Manager::from_clap(&matches);
+    init_logger(manager.verbose);
+    match clock::fake_now() {
+        Some(Ok(now)) => warn!(
+            "Warning: {} is set, the time is frozen at {}",
+            clock::FAKE_NOW_VAR,
+            humantime::format_rfc3339(now)
+        ),
+        Some(Err(value)) => exit_with(&CliError::FakeNow(value)),
+        None => {}
+    }
+    let name_given = match std::env::current_dir() {
+        Ok(dir) => manager.apply_local_config(&dir, matches.occurrences_of("name") > 0),
+        Err(_) => Ok(matches.occurrences_of("name") > 0),
+    }
+    .unwrap_or_else(|e| exit_with(&e));
+    manager
+        .apply_profile(name_given)
+        .unwrap_or_else(|e| exit_with(&e));
+    manager.fix_workdir();
+    match run(&manager) {
+        Ok((result, inventory)) => {
+            let started = Instant::now();
+            manager.print(&result, &inventory);
+            debug!("print took {:.1?}", started.elapsed());
+        }
+        Err(e) => {
+            if e.is_read_only() {
+                warn!("Refusing to modify a read only inventory, nothing was changed.");
+            }
+            exit_with(&e);
+        }
+    }
 }
 
-pub fn read_type<'a>(cmd: &ReadTypeCommand, inventory: &Inventory, minimal: bool) {
-    let res = if let Some(id) = &cmd.id {
-        inventory
-            .item_types
-            .iter()
-            .find(|it| it.id == *id)
-            .map(|it| vec![it])
-            .unwrap_or(vec![])
-    } else if let Some(name) = &cmd.name {
-        inventory.get_types_for_name(&name.to_string())
-    } else {
-        inventory.item_types.iter().collect::<Vec<_>>()
+/// Prints the messages of inv on stderr. The warnings and notices are printed as they are,
+/// the timings of `--verbose` and the steps of `-vv` with their level.
+fn init_logger(verbose: u8) {
+    let level = match verbose {
+        0 => log::LevelFilter::Info,
+        1 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
     };
-    print_item_types(&res, inventory, minimal);
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Warn)
+        .filter_module(module_path!(), level)
+        .format(|buf, record| match record.level() {
+            log::Level::Debug | log::Level::Trace => writeln!(
+                buf,
+                "[{}] {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            ),
+            _ => writeln!(buf, "{}", record.args()),
+        })
+        .init();
 }
 
-// TODO: Minimize?
-pub fn read_instance<'a>(cmd: &ReadInstanceCommand, inventory: &Inventory, minimal: bool) {
-    let mut instances = if let Some(id) = cmd.id {
-        inventory
-            .item_instances
-            .iter()
-            .find(|ii| ii.id == id && ii.removed_at.is_none())
-            .map(|ii| vec![ii])
-            .unwrap_or_else(|| vec![])
-    } else if let Some(type_id) = cmd.type_id {
-        inventory
-            .get_instances_for_type(type_id.clone())
-            .expect("Unknown type id specified")
-    } else if let Some(type_name) = &cmd.type_name {
-        let types = inventory.get_types_for_name(&type_name);
-        let type_ids = types.iter().map(|t| t.id).collect::<Vec<_>>();
-        inventory
-            .item_instances
-            .iter()
-            .filter(|ii| type_ids.contains(&ii.item_type) && ii.removed_at.is_none())
-            .collect::<Vec<_>>()
+/// Loads the inventory files the command needs, runs it and saves the files it changed.
+/// With --stdin and --stdout, the inventory is read from stdin and written to stdout instead.
+fn run(manager: &Manager) -> std::result::Result<(CommandResult, Inventory), CliError> {
+    // Not locked, the questions asked by the commands read stdin too.
+    run_with(manager, std::io::stdin(), std::io::stdout().lock())
+}
+
+/// `run` reading --stdin from `input` and writing --stdout to `output`.
+fn run_with(
+    manager: &Manager,
+    mut input: impl Read + Send + 'static,
+    output: impl Write,
+) -> std::result::Result<(CommandResult, Inventory), CliError> {
+    let workdir = manager.workdir.as_deref().unwrap();
+    let files = if manager.fix_ids {
+        Files::Both
     } else {
-        inventory
-            .item_instances
-            .iter()
-            .filter(|ii| ii.removed_at.is_none())
-            .collect::<Vec<_>>()
+        manager.command.as_ref().map_or(Files::Both, Command::files)
     };
-    if cmd.expired {
-        let sys_time = SystemTime::now();
-        instances.retain(|ii| {
-            if let Some(exp) = ii.expires_at {
-                exp <= sys_time
-            } else {
-                false
-            }
-        });
+    if let Some(path) = &manager.from_published {
+        if manager.command.as_ref().is_some_and(|c| !c.reads_only()) {
+            return Err(CliError::Published(path.clone()));
+        }
     }
-    print_item_instances(&instances, inventory, minimal);
-}
-
-pub fn print_item_types(types: &Vec<&ItemType>, inventory: &Inventory, minimal: bool) {
-    if minimal {
-        types.iter().for_each(|it| println!("{}", it));
+    let started = Instant::now();
+    let (mut inventory, paths) = if let Some(path) = &manager.from_published {
+        (publish::read(path)?, None)
+    } else if manager.stdin {
+        let inventory = read_inventory(&mut input, Path::new("<stdin>"), manager.fix_ids)?;
+        (inventory, None)
     } else {
-        let mut table = Table::new();
-        table.add_row(row![
-            "id",
-            "name",
-            "min",
-            "ttl",
-            "open default",
-            "total quantity"
-        ]);
-        types.iter().for_each(|t| {
-            table.add_row(row![
-                t.id.to_string(),
-                t.name.to_string(),
-                t.minimum_quantity.to_string(),
-                match t.ttl {
-                    Some(ttl) => humantime::format_duration(ttl).to_string(),
-                    None => "-".to_string(),
-                },
-                t.opened_by_default.to_string(),
-                inventory.quantity_for_type(t.id),
-            ]);
-        });
-        table.printstd();
+        let (inventory, mut types_path, mut instances_path) =
+            load_inventory(workdir, &manager.inventory_name, files, manager.fix_ids)?;
+        if manager.encrypt && !crypto::is_encrypted(&types_path) {
+            if types_path.exists() || instances_path.exists() {
+                return Err(CliError::Encryption(format!(
+                    "the inventory {} is not encrypted, use inv encrypt to encrypt it",
+                    manager.inventory_name
+                )));
+            }
+            types_path = crypto::encrypted_path(&types_path);
+            instances_path = crypto::encrypted_path(&instances_path);
+        }
+        (inventory, Some((types_path, instances_path)))
+    };
+    let events_path = events::events_path(
+        workdir,
+        &manager.inventory_name,
+        paths
+            .as_ref()
+            .is_some_and(|(types_path, _)| crypto::is_encrypted(types_path)),
+    );
+    debug!(
+        "load took {:.1?}: {} item types, {} item instances",
+        started.elapsed(),
+        inventory.item_types.len(),
+        inventory.item_instances.len()
+    );
+    let before = inventory.clone();
+    if manager.fix_ids {
+        let fixes = inventory.fix_duplicate_ids();
+        fixes
+            .types
+            .iter()
+            .for_each(|(old, new)| info!("Item type {} is now {}", old, new));
+        fixes
+            .instances
+            .iter()
+            .for_each(|(old, new)| info!("Item instance {} is now {}", old, new));
     }
-}
-
-pub fn print_item_instances(instances: &Vec<&ItemInstance>, inv: &Inventory, minimal: bool) {
-    if minimal {
-        instances.iter().for_each(|ii| println!("{}", ii));
+    if let Some(Command::Repl) = manager.command {
+        let mut result = CommandResult {
+            command: "repl",
+            profile: manager.profile.clone(),
+            ..CommandResult::default()
+        };
+        match paths {
+            Some((types_path, instances_path)) if !manager.stdout => {
+                let saved = std::cell::RefCell::new(before);
+                let save = |inventory: &Inventory| {
+                    save_inventory(inventory, &types_path, &instances_path, Files::Both)?;
+                    let mut changes = CommandResult {
+                        command: "repl",
+                        ..CommandResult::default()
+                    };
+                    changes.record_changes(&saved.borrow(), inventory);
+                    if changes.has_changes() {
+                        let recorded = cipher_of(&events_path).and_then(|cipher| {
+                            let saved = saved.borrow();
+                            events::record(&events_path, cipher, &changes, &saved, inventory, true)
+                        });
+                        if let Err(e) = recorded {
+                            warn!(
+                                "Warning: the changes were saved but not added to the event log: {}",
+                                e
+                            );
+                        }
+                        // A failing hook doesn't stop the session, whatever the settings.
+                        if let Err(e) = run_post_mutation_hook(manager, workdir, &changes) {
+                            warn!("Warning: {}", e);
+                        }
+                    }
+                    *saved.borrow_mut() = inventory.clone();
+                    Ok(())
+                };
+                let mut saver = autosave::SaveManager::new(autosave::DEFAULT_INTERVAL, save);
+                if manager.fix_ids {
+                    saver.changed(&inventory)?;
+                }
+                let reload = || {
+                    let reloaded =
+                        load_inventory(workdir, &manager.inventory_name, Files::Both, false)?.0;
+                    *saved.borrow_mut() = reloaded.clone();
+                    Ok(reloaded)
+                };
+                let stop = repl::stop_on_signals().unwrap_or_else(|e| {
+                    warn!(
+                        "Warning: SIGINT and SIGTERM can't be caught, they will lose the changes that are not saved yet: {}",
+                        e
+                    );
+                    Default::default()
+                });
+                let input = BufReader::new(input);
+                repl::session(manager, &mut inventory, input, saver, reload, &stop)?;
+            }
+            _ => warn!(
+                "repl reads its commands from stdin and saves the inventory files, it can't be used with --stdin or --stdout"
+            ),
+        }
+        result.timestamp = humantime::format_rfc3339(inventory.now()).to_string();
+        return Ok((result, inventory));
+    }
+    let started = Instant::now();
+    let mut result = manager.exec(&mut inventory)?;
+    result.profile = manager.profile.clone();
+    debug!(
+        "exec of {} took {:.1?}",
+        manager
+            .command
+            .as_ref()
+            .map_or("the dashboard", Command::name),
+        started.elapsed()
+    );
+    result.record_changes(&before, &inventory);
+    let changed = Files::changed(
+        !result.type_changes.is_empty() || result.locations_changed,
+        !result.instance_changes.is_empty(),
+    );
+    let started = Instant::now();
+    if manager.stdout {
+        write_inventory(&inventory, output)
+            .map_err(|e| CliError::io(Operation::Write, Path::new("<stdout>"), e))?;
+        manager.record_update(&result)?;
+    } else if let Some(changed) = changed {
+        match &paths {
+            Some((types_path, instances_path)) => {
+                save_inventory(&inventory, types_path, instances_path, changed)?;
+                manager.record_update(&result)?;
+                if manager.command.as_ref().is_some_and(Command::logged) {
+                    let whole = files == Files::Both;
+                    let recorded = cipher_of(&events_path).and_then(|cipher| {
+                        events::record(&events_path, cipher, &result, &before, &inventory, whole)
+                    });
+                    if let Err(e) = recorded {
+                        warn!(
+                            "Warning: the changes were saved but not added to the event log: {}",
+                            e
+                        );
+                    }
+                }
+                run_post_mutation_hook(manager, workdir, &result)?;
+            }
+            None => match &manager.from_published {
+                Some(path) => return Err(CliError::Published(path.clone())),
+                None => warn!(
+                    "Warning: the inventory read from stdin was changed but not saved, use --stdout to print it."
+                ),
+            },
+        }
     } else {
-        let mut table = Table::new();
-        table.add_row(row![
-            "id",
-            "type id",
-            "type name",
-            "quantity",
-            "model",
-            "serial",
-            "extra",
-            "location",
-            "value",
-            "opened at",
-            "expires at"
-        ]);
-        instances.iter().for_each(|t| {
-            let item_type_str = inv
-                .item_types
-                .iter()
-                .find(|ty| ty.id == t.item_type)
-                .expect("Failed to find item type for item instance")
-                .name
-                .to_string();
-            table.add_row(row![
-                t.id.to_string(),
-                t.item_type.to_string(),
-                item_type_str,
-                t.quantity.to_string(),
-                conv(&t.model),
-                conv(&t.serial),
-                conv(&t.extra),
-                conv(&t.location),
-                conv(&t.value),
-                t.opened_at
-                    .map(|t| humantime::format_rfc3339(t).to_string())
-                    .unwrap_or("".to_string()),
-                t.expires_at
-                    .map(|t| humantime::format_rfc3339(t).to_string())
-                    .unwrap_or("".to_string()),
-            ]);
-        });
-        table.printstd();
+        manager.record_update(&result)?;
     }
+    debug!("save took {:.1?}", started.elapsed());
+    Ok((result, inventory))
 }
 
-pub fn update_type<'a>(cmd: &UpdateTypeCommand, inventory: &mut Inventory) {
-    if let Some(mut item_type) = inventory.item_types.iter_mut().find(|t| t.id == cmd.id) {
-        if let Some(name) = &cmd.name {
-            item_type.name = name.to_string();
-        }
-        if let Some(min) = cmd.minimum_quantity {
-            item_type.minimum_quantity = min;
-        }
-        if let Some(ttl_opt) = cmd.ttl {
-            item_type.ttl = ttl_opt.map(|t| t.into());
-        }
-        if let Some(open_by_default) = cmd.open_by_default {
-            item_type.opened_by_default = open_by_default;
+/// Runs the hook of the settings, if any, once the changes of a command are saved. Its
+/// failure is a warning, or an error with `"hook_failure": "error"`.
+fn run_post_mutation_hook(
+    manager: &Manager,
+    workdir: &Path,
+    result: &CommandResult,
+) -> std::result::Result<(), CliError> {
+    let config = config::load(&config::config_path(workdir, &manager.inventory_name))?;
+    let hook = match config.hook(workdir) {
+        Some(hook) => hook,
+        None => return Ok(()),
+    };
+    let started = Instant::now();
+    let env = Hook::env(result, &manager.inventory_name, workdir);
+    let input = serde_json::to_string(result).expect("Failed to serialize the command result");
+    let outcome = hook.run(&env, &input);
+    debug!("hook took {:.1?}", started.elapsed());
+    match (outcome, config.hook_failure) {
+        (Ok(()), _) => Ok(()),
+        (Err(e), HookFailure::Warn) => {
+            warn!(
+                "Warning: the changes were saved but the hook {} failed: {}",
+                hook.program.display(),
+                e
+            );
+            Ok(())
         }
-    } else {
-        eprintln!("Could not find an item type with the specified id");
+        (Err(e), HookFailure::Error) => Err(CliError::Hook {
+            program: hook.program,
+            reason: e.to_string(),
+        }),
     }
 }
 
-pub fn delete_type<'a>(cmd: &DeleteTypeCommand, inventory: &mut Inventory) {
-    inventory.delete_item_type(cmd.id);
+fn exit_with(e: &CliError) -> ! {
+    error!("Error: {}", e);
+    std::process::exit(1);
 }
 
-pub fn delete_instance<'a>(cmd: &DeleteInstanceCommand, inventory: &mut Inventory) {
-    inventory
-        .delete_item_instance(cmd.id)
-        .expect("Failed to delete item instance. Wrong id specified");
+pub fn default_workdir() -> PathBuf {
+    let mut dir =
+        dirs::data_local_dir().expect("Failed to locate suitable folder to store inventory data.");
+    dir.push("inventory_managoat");
+    dir
 }
 
-pub fn create_instance<'a>(cmd: &CreateInstanceCommand, inventory: &mut Inventory) {
-    let mut new = ItemInstanceBuilder::default();
+/// Some of the files of an inventory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Files {
+    Types,
+    Instances,
+    Both,
+}
 
-    new.item_type(cmd.item_type);
-    new.model(cmd.model.clone());
-    new.serial(cmd.serial.clone());
-    new.extra(cmd.extra.clone());
-    new.location(cmd.location.clone());
-    new.value(cmd.value);
-    new.quantity(cmd.quantity);
-    new.expires_at(cmd.expires_at.clone().map(|t| t.into()));
+impl Files {
+    /// The files holding these changes, if any.
+    pub fn changed(types: bool, instances: bool) -> Option<Files> {
+        match (types, instances) {
+            (true, true) => Some(Files::Both),
+            (true, false) => Some(Files::Types),
+            (false, true) => Some(Files::Instances),
+            (false, false) => None,
+        }
+    }
 
-    let id = inventory
-        .add_item_instance(new.build().unwrap())
-        .expect("Failed to insert new item type");
-    println!("{}", id);
+    pub fn types(self) -> bool {
+        self != Files::Instances
+    }
+
+    pub fn instances(self) -> bool {
+        self != Files::Types
+    }
+}
+
+/// Loads the files of the inventory with the specified name from the working directory,
+/// first finishing the last save if it was interrupted. The files that are not loaded are
+/// considered empty, and only the ids are validated when the item types are not loaded.
+/// Missing files are considered empty, so that a new inventory can be created.
+/// Duplicate ids are an error unless `allow_duplicate_ids` is set, in which case
+/// they have to be fixed before using the inventory.
+/// Returns the inventory and the paths of its types and instances files.
+pub fn load_inventory(
+    workdir: &Path,
+    name: &str,
+    files: Files,
+    allow_duplicate_ids: bool,
+) -> std::result::Result<(Inventory, PathBuf, PathBuf), CliError> {
+    if workdir.exists() && !workdir.is_dir() {
+        return Err(CliError::NotADirectory(workdir.to_path_buf()));
+    }
+    let (types_path, instances_path) = inventory_paths(workdir, name)?;
+    if let Recovery::Finished(stale) = storage::recover(&journal_path(&types_path))? {
+        info!("The last save was interrupted, it was finished from its journal.");
+        stale
+            .iter()
+            .for_each(|path| info!("Wrote {} again", path.display()));
+    }
+    let types = if files.types() {
+        load_types(&types_path)?
+    } else {
+        InventoryFile::default()
+    };
+    let instances = if files.instances() {
+        load_instances(&instances_path)?
+    } else {
+        InventoryFile::default()
+    };
+    let mut inventory = Inventory::new(types.items, instances.items);
+    inventory.set_next_ids(types.next_id, instances.next_id);
+    inventory.locations = types.locations;
+    check_ids(&inventory, files, allow_duplicate_ids)?;
+    Ok((inventory, types_path, instances_path))
 }
 
-pub fn update_instance<'a>(cmd: &UpdateInstanceCommand, inventory: &mut Inventory) {
-    if let Some(mut item_instance) = inventory.item_instances.iter_mut().find(|t| t.id == cmd.id) {
-        if let Some(e) = cmd.quantity {
-            item_instance.quantity = e;
+/// The types and instances files of the inventory, which are the encrypted ones when they
+/// exist, see `crypto`. Having both plaintext and encrypted files is an error.
+pub fn inventory_paths(
+    workdir: &Path,
+    name: &str,
+) -> std::result::Result<(PathBuf, PathBuf), CliError> {
+    let plaintext = [
+        workdir.join(format!("{}_types.json", name)),
+        workdir.join(format!("{}_instances.json", name)),
+    ];
+    let encrypted = plaintext.clone().map(|p| crypto::encrypted_path(&p));
+    let existing = |paths: &[PathBuf]| {
+        paths
+            .iter()
+            .filter(|p| p.exists())
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    match (existing(&plaintext), existing(&encrypted)) {
+        (plaintext, encrypted) if !plaintext.is_empty() && !encrypted.is_empty() => {
+            Err(CliError::MixedEncryption {
+                plaintext,
+                encrypted,
+            })
         }
-        if let Some(e) = &cmd.model {
-            item_instance.model = Some(e.clone());
+        (_, existing) if !existing.is_empty() => {
+            let [types, instances] = encrypted;
+            Ok((types, instances))
         }
-        if let Some(e) = &cmd.serial {
-            item_instance.serial = Some(e.clone());
+        _ => {
+            let [types, instances] = plaintext;
+            Ok((types, instances))
         }
-        if let Some(e) = &cmd.extra {
-            item_instance.extra = Some(e.clone());
+    }
+}
+
+/// The cipher of the inventory the file belongs to, see `crypto::cipher`.
+fn cipher_for(
+    path: &Path,
+    confirm: bool,
+) -> std::result::Result<&'static crypto::Cipher, CliError> {
+    let workdir = path.parent().unwrap_or_else(|| Path::new(""));
+    crypto::cipher(
+        &config::config_path(workdir, &inventory_name_of(path)),
+        confirm,
+    )
+}
+
+/// The cipher of an event log or state file, `None` when it is not an encrypted one.
+fn cipher_of(path: &Path) -> std::result::Result<Option<&'static crypto::Cipher>, CliError> {
+    if crypto::is_encrypted(path) {
+        cipher_for(path, false).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// The content to write to an inventory file, encrypted when the file is.
+fn file_content(path: &Path, json: String) -> std::result::Result<String, CliError> {
+    if crypto::is_encrypted(path) {
+        cipher_for(path, true)?.seal(&json)
+    } else {
+        Ok(json)
+    }
+}
+
+/// Reads a whole inventory from a single json document, naming `source` in errors.
+/// The document is `{"types": <types file>, "instances": <instances file>}`, where each part
+/// has the content of its file and is upgraded the same way. A missing part has no items.
+pub fn read_inventory(
+    reader: impl Read,
+    source: &Path,
+    allow_duplicate_ids: bool,
+) -> std::result::Result<Inventory, CliError> {
+    let document: InventoryDocument<Option<serde_json::Value>, Option<serde_json::Value>> =
+        serde_json::from_value(parse_value(reader, source)?)
+            .map_err(|e| CliError::io(Operation::Parse, source, e.into()))?;
+    let types: InventoryFile<TypeId, Vec<ItemType>> = match document.types {
+        Some(types) => parse_inventory_file(types, source)?,
+        None => InventoryFile::default(),
+    };
+    let instances: InventoryFile<InstanceId, Vec<ItemInstance>> = match document.instances {
+        Some(instances) => parse_inventory_file(instances, source)?,
+        None => InventoryFile::default(),
+    };
+    let mut inventory = Inventory::new(types.items, instances.items);
+    inventory.set_next_ids(types.next_id, instances.next_id);
+    inventory.locations = types.locations;
+    check_ids(&inventory, Files::Both, allow_duplicate_ids)?;
+    Ok(inventory)
+}
+
+/// Writes a whole inventory as a single json document, see `read_inventory`.
+pub fn write_inventory(inventory: &Inventory, mut writer: impl Write) -> std::io::Result<()> {
+    let document = InventoryDocument {
+        types: types_file(inventory),
+        instances: instances_file(inventory),
+    };
+    serde_json::to_writer_pretty(&mut writer, &document)?;
+    writeln!(writer)
+}
+
+/// Fails on duplicate ids unless they are allowed, and reports the item instances whose
+/// unit can not be converted or whose quantity should be whole when the item types were
+/// loaded.
+fn check_ids(
+    inventory: &Inventory,
+    files: Files,
+    allow_duplicate_ids: bool,
+) -> std::result::Result<(), CliError> {
+    match inventory.validate() {
+        Err(InventoryError::DuplicateIds {
+            type_ids,
+            instance_ids,
+        }) if !allow_duplicate_ids => {
+            return Err(CliError::DuplicateIds {
+                type_ids,
+                instance_ids,
+            });
         }
-        if let Some(e) = &cmd.location {
-            item_instance.location = Some(e.clone());
+        Err(InventoryError::IncompatibleUnits { instance_ids }) if files == Files::Both => warn!(
+            "The unit of the item instances {} can not be converted to the unit of their item type, they are left out of the totals.",
+            instance_ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => {}
+    }
+    let fractional = inventory.fractional_quantities();
+    if files == Files::Both && !fractional.is_empty() {
+        warn!(
+            "The item instances {} have a fractional quantity although their item type is counted in whole quantities.",
+            fractional
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let implausible = inventory
+        .check_timestamps()
+        .into_iter()
+        .filter(|(_, error)| *error == TimestampError::Implausible)
+        .map(|(id, _)| id.to_string())
+        .collect::<Vec<_>>();
+    if files == Files::Both && !implausible.is_empty() {
+        warn!(
+            "The item instances {} have a date before {} or after {}, was the clock wrong when they were changed?",
+            implausible.join(", "),
+            time_math::EARLIEST_YEAR,
+            time_math::LATEST_YEAR
+        );
+    }
+    Ok(())
+}
+
+/// The content of the types and instances files, see `migrations`.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+struct InventoryFile<Id, I> {
+    format_version: u32,
+    next_id: Id,
+    items: I,
+    /// The registered locations, only kept in the types file.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    locations: Vec<LocationInfo>,
+}
+
+/// The whole inventory in a single document, see `read_inventory`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct InventoryDocument<T, I> {
+    types: T,
+    instances: I,
+}
+
+fn types_file(inventory: &Inventory) -> InventoryFile<TypeId, &[ItemType]> {
+    InventoryFile {
+        format_version: migrations::CURRENT_VERSION,
+        next_id: inventory.next_ids().0,
+        items: &inventory.item_types,
+        locations: inventory.locations.clone(),
+    }
+}
+
+fn instances_file(inventory: &Inventory) -> InventoryFile<InstanceId, &[ItemInstance]> {
+    InventoryFile {
+        format_version: migrations::CURRENT_VERSION,
+        next_id: inventory.next_ids().1,
+        items: &inventory.item_instances,
+        locations: vec![],
+    }
+}
+
+/// Reads the item types, the id the next one gets and the registered locations.
+fn load_types(path: &Path) -> std::result::Result<InventoryFile<TypeId, Vec<ItemType>>, CliError> {
+    read_inventory_file(path)
+}
+
+/// Reads the item instances and the id the next one gets.
+fn load_instances(
+    path: &Path,
+) -> std::result::Result<InventoryFile<InstanceId, Vec<ItemInstance>>, CliError> {
+    read_inventory_file(path)
+}
+
+/// Reads an inventory file, upgrading it to the current format version if needed.
+/// A missing file has no items.
+fn read_inventory_file<
+    Id: serde::de::DeserializeOwned + Default,
+    T: serde::de::DeserializeOwned,
+>(
+    path: &Path,
+) -> std::result::Result<InventoryFile<Id, Vec<T>>, CliError> {
+    match read_value(path)? {
+        Some(value) => parse_inventory_file(value, path),
+        None => Ok(InventoryFile::default()),
+    }
+}
+
+/// Upgrades the content of an inventory file to the current format version and reads it.
+fn parse_inventory_file<Id: serde::de::DeserializeOwned, T: serde::de::DeserializeOwned>(
+    value: serde_json::Value,
+    source: &Path,
+) -> std::result::Result<InventoryFile<Id, Vec<T>>, CliError> {
+    let value = migrations::migrate(value, migrations::CURRENT_VERSION)
+        .map_err(|e| CliError::Format(source.to_path_buf(), e))?;
+    serde_json::from_value(value).map_err(|e| CliError::io(Operation::Parse, source, e.into()))
+}
+
+/// Reads a json file, decrypting it if it is an encrypted one, returning `None` if it does
+/// not exist.
+fn read_value(path: &Path) -> std::result::Result<Option<serde_json::Value>, CliError> {
+    let data = match read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            trace!("{} doesn't exist", path.display());
+            return Ok(None);
         }
-        if let Some(e) = &cmd.value {
-            item_instance.value = Some(*e);
+        Err(e) => return Err(CliError::io(Operation::Read, path, e)),
+    };
+    trace!("read {} ({} bytes)", path.display(), data.len());
+    if crypto::is_encrypted(path) {
+        let json = cipher_for(path, false)?.open(path, &data)?;
+        parse_value(json.as_bytes(), path).map(Some)
+    } else if crypto::looks_encrypted(&data) {
+        Err(CliError::Encryption(format!(
+            "{} is encrypted, rename it to {} or decrypt it",
+            path.display(),
+            crypto::encrypted_path(path).display()
+        )))
+    } else {
+        parse_value(data.as_slice(), path).map(Some)
+    }
+}
+
+/// Parses a json document, naming `source` in errors.
+fn parse_value(
+    reader: impl Read,
+    source: &Path,
+) -> std::result::Result<serde_json::Value, CliError> {
+    serde_json::from_reader(reader).map_err(|e| {
+        if e.is_io() {
+            CliError::io(Operation::Read, source, e.into())
+        } else {
+            CliError::io(Operation::Parse, source, e.into())
         }
-        if let Some(e) = &cmd.expires_at {
-            item_instance.expires_at = e.clone().map(|t| t.into());
+    })
+}
+
+/// Reads a json object mapping texts to texts, like the patterns of set-icons-from-map or
+/// the location names of normalize-locations --apply.
+fn read_mapping(
+    path: &Path,
+) -> std::result::Result<std::collections::BTreeMap<String, String>, CliError> {
+    let data = read(path).map_err(|e| CliError::io(Operation::Read, path, e))?;
+    serde_json::from_slice(&data).map_err(|e| CliError::io(Operation::Parse, path, e.into()))
+}
+
+/// Reads a json array, returning an empty one if the file does not exist.
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> std::result::Result<Vec<T>, CliError> {
+    match read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| CliError::io(Operation::Parse, path, e.into())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(CliError::io(Operation::Read, path, e)),
+    }
+}
+
+/// Saves some files of the inventory, creating the working directory if needed.
+/// The files are written completely before replacing the existing ones, so a failure
+/// leaves the inventory as it was, and a crash while replacing them is recovered by
+/// the next `load_inventory`. Encrypted files are written encrypted, and so are their
+/// temporary files and the journal.
+pub fn save_inventory(
+    inventory: &Inventory,
+    types_path: &Path,
+    instances_path: &Path,
+    files: Files,
+) -> std::result::Result<(), CliError> {
+    create_parent_dir(types_path)?;
+    let mut contents = vec![];
+    if files.types() {
+        let json = to_json(&types_file(inventory));
+        contents.push((types_path, file_content(types_path, json)?));
+    }
+    if files.instances() {
+        let json = to_json(&instances_file(inventory));
+        contents.push((instances_path, file_content(instances_path, json)?));
+    }
+    contents
+        .iter()
+        .for_each(|(path, content)| trace!("write {} ({} bytes)", path.display(), content.len()));
+    storage::save_files(&journal_path(types_path), &contents)
+}
+
+/// The journal of the inventory whose item types are saved at this path, `<name>.journal`.
+fn journal_path(types_path: &Path) -> PathBuf {
+    types_path.with_file_name(format!("{}.journal", inventory_name_of(types_path)))
+}
+
+/// The name of the inventory a types, instances, events or state file belongs to.
+fn inventory_name_of(path: &Path) -> String {
+    crypto::plaintext_path(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| {
+            [
+                "_types.json",
+                "_instances.json",
+                "_events.json",
+                "_state.json",
+            ]
+            .iter()
+            .find_map(|suffix| n.strip_suffix(suffix))
+        })
+        .unwrap_or("inventory")
+        .to_string()
+}
+
+/// Creates the working directory containing the path if it doesn't exist.
+fn create_parent_dir(path: &Path) -> std::result::Result<(), CliError> {
+    if let Some(workdir) = path.parent() {
+        if workdir.exists() && !workdir.is_dir() {
+            return Err(CliError::NotADirectory(workdir.to_path_buf()));
         }
-        if let Some(e) = &cmd.opened_at {
-            item_instance.opened_at = e.clone().map(|t| t.into());
+        DirBuilder::new()
+            .recursive(true)
+            .create(workdir)
+            .map_err(|e| CliError::io(Operation::CreateDir, workdir, e))?;
+    }
+    Ok(())
+}
+
+/// Replaces the file with the json, writing it completely before replacing the existing one.
+fn save_json<T: serde::Serialize>(path: &Path, value: &T) -> std::result::Result<(), CliError> {
+    create_parent_dir(path)?;
+    let tmp = storage::write_tmp(path, to_json(value).as_bytes())?;
+    rename(&tmp, path).map_err(|e| CliError::io(Operation::Write, path, e))
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string_pretty(value).expect("Failed to serialize the inventory")
+}
+
+/// Creates an item type. Its values come from, by order of precedence,
+/// the options, the class and the defaults of `ItemTypeBuilder`.
+pub fn create_type(
+    cmd: &CreateTypeCommand,
+    inventory: &mut Inventory,
+    classes: &[ItemClass],
+) -> CommandResult {
+    let now = inventory.now();
+    let mut new = ItemTypeBuilder::default();
+    new.name(cmd.name.clone());
+    new.minimum_quantity(cmd.minimum_quantity);
+    let mut item_type = new.build().unwrap();
+    if let Some(name) = &cmd.class {
+        match find_class(classes, name) {
+            Some(class) => class.apply(&mut item_type),
+            None => {
+                warn!("Could not find a class with the specified name");
+                return CommandResult::default();
+            }
+        }
+    }
+    if let Some(ttl) = cmd.ttl {
+        item_type.ttl = Some(ttl.duration(now));
+    }
+    if let Some(open_by_default) = cmd.open_by_default {
+        item_type.opened_by_default = open_by_default;
+    }
+    if let Some(auto_trash_empty) = cmd.auto_trash_empty {
+        item_type.auto_trash_empty = auto_trash_empty;
+    }
+    if let Some(auto_trash_on_expiry) = cmd.auto_trash_on_expiry {
+        item_type.auto_trash_on_expiry = auto_trash_on_expiry;
+    }
+    if let Some(sealed_only) = cmd.minimum_counts_sealed_only {
+        item_type.minimum_counts_sealed_only = sealed_only;
+    }
+    item_type.deposit = cmd.deposit.filter(|d| *d > 0.0);
+    item_type.unit = cmd.unit.clone();
+    item_type.remind_after_open = cmd.remind_after_open.map(|d| d.duration(now));
+    if let Some(allow_overdraft) = cmd.allow_overdraft {
+        item_type.allow_overdraft = allow_overdraft;
+    }
+    if let Some(integer_only) = cmd.integer_only {
+        item_type.integer_only = integer_only;
+    }
+    item_type.category = cmd.category.as_deref().and_then(path::normalize);
+    item_type.depreciation_years = cmd.depreciation_years.filter(|y| *y > 0.0);
+    item_type.icon = cmd.icon.clone().filter(|i| !i.trim().is_empty());
+    item_type.vendor = cmd.vendor.clone().filter(|v| !v.trim().is_empty());
+    item_type.package_size = cmd.package_size.filter(|s| *s > 0.0);
+    cmd.min_at
+        .iter()
+        .for_each(|m| item_type.set_minimum_at(&m.location, m.minimum));
+    if !cmd.with_instance {
+        return CommandResult::created(inventory.add_item_type(item_type));
+    }
+    // Both are created on a copy, which replaces the inventory only once both succeeded.
+    let mut staged = inventory.clone();
+    let type_id = staged.add_item_type(item_type);
+    let mut new = ItemInstanceBuilder::default();
+    new.item_type(type_id);
+    new.quantity(cmd.quantity.unwrap_or(1.0));
+    new.location(cmd.location.clone());
+    new.expires_at(cmd.expires_at.map(|t| t.time(now)));
+    new.value(cmd.value);
+    match staged.add_item_instance(new.build().unwrap()) {
+        Ok(instance_id) => {
+            *inventory = staged;
+            let mut result = CommandResult::created(type_id);
+            result.messages.push(instance_id.to_string());
+            result
+        }
+        Err(e) => {
+            warn!(
+                "Could not create the item instance, nothing was created: {}",
+                instance_error(&e)
+            );
+            CommandResult::default()
+        }
+    }
+}
+
+/// Describes why an item instance could not be created.
+fn instance_error(e: &InventoryError) -> &'static str {
+    match e {
+        InventoryError::UnknownItemType => "the item type doesn't exist",
+        InventoryError::InvalidQuantity => "the quantity must be a positive number or zero",
+        InventoryError::FractionalQuantityNotAllowed => {
+            "the item type is counted in whole quantities"
+        }
+        InventoryError::IncompatibleUnits { .. } => {
+            "the unit can not be converted to the unit of the item type"
+        }
+        _ => "the item instance is invalid",
+    }
+}
+
+/// Describes the errors of custom fields, falling back to `instance_error`.
+fn custom_field_error(e: &InventoryError) -> String {
+    match e {
+        InventoryError::UnknownField { key } => format!(
+            "the item type has no custom field {}, declare it with ut --add-field",
+            key
+        ),
+        InventoryError::InvalidFieldValue { key, kind } => {
+            let expected = match kind {
+                FieldKind::Date => "a date like 2024-01-31",
+                FieldKind::Bool => "true or false",
+                FieldKind::Number => "a number",
+                FieldKind::Text => "a text",
+            };
+            format!("the custom field {} expects {}", key, expected)
+        }
+        InventoryError::DuplicateField { key } => {
+            format!("the item type already has a custom field {}", key)
         }
+        e => instance_error(e).to_string(),
+    }
+}
+
+/// How names given to filters are matched: `Substring` unless `--exact` is set.
+fn name_mode(exact: bool) -> MatchMode {
+    if exact {
+        MatchMode::Exact
     } else {
-        eprintln!("Could not find an item instance with the specified id");
+        MatchMode::Substring
     }
 }
 
-pub fn print_missing<'a>(inventory: &mut Inventory, minimal: bool) {
-    let types = inventory
-        .item_types
-        .iter()
-        .filter(|t| inventory.quantity_for_type(t.id) < t.minimum_quantity)
-        .collect::<Vec<_>>();
-    print_item_types(&types, inventory, minimal);
+pub fn read_type(cmd: &ReadTypeCommand, inventory: &Inventory) -> CommandResult {
+    let mut query = TypeQuery::new();
+    if let Some(id) = cmd.id {
+        query = query.id(id);
+    }
+    if let Some(name) = &cmd.name {
+        query = query.name_matching(name, name_mode(cmd.exact));
+    }
+    if cmd.no_ttl {
+        query = query.has_ttl(false);
+    }
+    if cmd.open_by_default {
+        query = query.opened_by_default(true);
+    }
+    if cmd.has_min {
+        query = query.has_minimum(true);
+    }
+    if cmd.unused {
+        query = query.unused(true);
+    }
+    if let Some(category) = &cmd.category {
+        query = query.category(category);
+    }
+    let items = query.run(inventory).items;
+    trace!(
+        "rt: {} of {} item types match",
+        items.len(),
+        inventory.item_types.len()
+    );
+    CommandResult::types(items)
 }
 
-pub fn print_expired<'a>(inventory: &mut Inventory, minimal: bool) {
-    let v = inventory
-        .item_instances
-        .iter()
-        .filter(|t| {
-            if let Some(expiry) = t.expires_at {
-                SystemTime::now() > expiry.into()
-            } else {
-                false
+pub fn read_instance(
+    cmd: &ReadInstanceCommand,
+    inventory: &mut Inventory,
+    inventory_name: &str,
+) -> CommandResult {
+    let mut query = InstanceQuery::new();
+    if let Some(id) = cmd.id {
+        query = query.id(id);
+    }
+    if let Some(type_id) = cmd.type_id {
+        query = query.type_id(type_id);
+    }
+    if let Some(type_name) = &cmd.type_name {
+        query = query.type_name_matching(type_name, name_mode(cmd.exact));
+    }
+    if cmd.expired {
+        query = query.expired(true);
+    }
+    if cmd.empty {
+        query = query.empty(true);
+    }
+    if let Some(since) = cmd.added_since {
+        query = query.added_since(since.ago(inventory.now()));
+    }
+    if let Some(external_ref) = &cmd.external_ref {
+        query = query.external_ref_contains(external_ref);
+    }
+    for filter in &cmd.custom {
+        if !inventory
+            .item_types
+            .iter()
+            .any(|t| t.custom_field(&filter.key).is_some())
+        {
+            warn!("No item type has the custom field {}", filter.key);
+        }
+        query = query.custom_field(filter.clone());
+    }
+    let items = query.run(inventory).items;
+    trace!(
+        "ri: {} of {} item instances match",
+        items.len(),
+        inventory.item_instances.len()
+    );
+    if cmd.qr_string {
+        let id = items.first().map(|ii| ii.id);
+        return match id.and_then(|id| inventory.issue_compact(id, inventory_name)) {
+            Some(compact) => CommandResult::text(format!("{}\n", compact)),
+            None => {
+                warn!("Could not find an item instance with the specified id");
+                CommandResult::default()
             }
-        })
-        .collect::<Vec<_>>();
-    print_item_instances(&v, &inventory, minimal);
+        };
+    }
+    match cmd.group_by {
+        Some(key) => {
+            let groups = group_instances(inventory, items, key);
+            trace!("ri: {} groups by {:?}", groups.len(), key);
+            CommandResult::instance_groups(groups.iter().map(|g| g.cloned()).collect())
+        }
+        None => CommandResult::instances(items, inventory.now()),
+    }
+}
+
+pub fn print_item_types(types: &[&ItemType], inventory: &Inventory, output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(ItemType::HEADER));
+        }
+        types.iter().for_each(|it| output.line(it));
+    } else {
+        let stats = inventory.all_type_stats();
+        // The former names are only shown when there are some, right after the name.
+        let renamed = types.iter().any(|t| !t.former_names.is_empty());
+        let mut columns = vec![
+            Column::new("id"),
+            Column::new("name").wrap(),
+            Column::new("class").priority(3),
+            Column::new("min").priority(7),
+            Column::new("ttl").priority(5),
+            Column::new("open default").priority(0),
+            Column::new("total quantity"),
+            Column::new("sealed quantity").priority(1),
+            Column::new("instances").priority(6),
+            Column::new("opened").priority(2),
+            Column::new("soonest expiry").priority(8),
+            Column::new("total value").priority(4),
+        ];
+        if renamed {
+            columns.insert(2, Column::new("formerly").priority(0).wrap());
+        }
+        let mut table = Grid::new(columns);
+        types.iter().for_each(|t| {
+            let s = stats.get(&t.id).cloned().unwrap_or_default();
+            let mut row = fields![
+                t.id.to_string(),
+                type_label(t),
+                t.class.clone().unwrap_or_default(),
+                t.minimum_quantity.to_string(),
+                match t.ttl {
+                    Some(ttl) => humantime::format_duration(ttl).to_string(),
+                    None => "-".to_string(),
+                },
+                t.opened_by_default.to_string(),
+                match (s.incompatible_units, t.recount_requested_at) {
+                    (0, None) => type_quantity(s.quantity, Some(t)),
+                    (0, Some(_)) => format!("{} (to recount)", type_quantity(s.quantity, Some(t))),
+                    (incompatible, _) => format!(
+                        "{} ({} not convertible)",
+                        type_quantity(s.quantity, Some(t)),
+                        incompatible
+                    ),
+                },
+                type_quantity(s.sealed_quantity, Some(t)),
+                s.instance_count,
+                s.opened_count,
+                s.soonest_expiry
+                    .map(|t| humantime::format_rfc3339(t).to_string())
+                    .unwrap_or_default(),
+                s.total_value,
+            ];
+            if renamed {
+                row.insert(2, Field::styled(t.former_names.join(", "), Style::Dim));
+            }
+            table.add_row(row);
+        });
+        output.table(&table);
+    }
+}
+
+pub fn print_shopping_list(items: &[ShoppingItem], inventory: &Inventory, output: Output) {
+    let with_prices = items.iter().any(|i| i.prices.is_some());
+    if output.minimal {
+        if output.header {
+            output.line(record::join(&shopping_header(with_prices)));
+        }
+        items
+            .iter()
+            .for_each(|i| output.line(record::join(&shopping_record(i))));
+    } else {
+        output.table(&shopping_table(items, inventory, with_prices));
+    }
+}
+
+/// Prints one section per vendor, the item types without a vendor last. Minimal mode prints
+/// the vendor first on every line instead.
+pub fn print_shopping_groups(
+    groups: &[VendorGroup<ShoppingItem>],
+    inventory: &Inventory,
+    output: Output,
+) {
+    let items = groups.iter().flat_map(|g| g.items.iter());
+    let with_prices = items.clone().any(|i| i.prices.is_some());
+    if output.minimal {
+        if output.header {
+            let mut header = vec!["vendor"];
+            header.extend(shopping_header(with_prices));
+            output.line(record::join(&header));
+        }
+        for group in groups {
+            let vendor = group.vendor.clone().unwrap_or_default();
+            group.items.iter().for_each(|i| {
+                let fields = std::iter::once(vendor.clone()).chain(shopping_record(i));
+                output.line(record::join(&fields.collect::<Vec<_>>()))
+            });
+        }
+        return;
+    }
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            output.line("");
+        }
+        output.line(format!(
+            "{}:",
+            group.vendor.as_deref().unwrap_or("No vendor")
+        ));
+        output.table(&shopping_table(&group.items, inventory, with_prices));
+    }
+}
+
+fn shopping_header(with_prices: bool) -> Vec<&'static str> {
+    let mut header = Shortage::HEADER.to_vec();
+    if with_prices {
+        header.extend_from_slice(&["last paid", "last paid location", "avg of last 3"]);
+    }
+    header
+}
+
+fn shopping_record(item: &ShoppingItem) -> Vec<String> {
+    let price = |p: Option<f32>| p.map(|p| p.to_string()).unwrap_or_default();
+    let mut fields = item.shortage.to_record();
+    if let Some(prices) = &item.prices {
+        let last = prices.last_paid.as_ref();
+        fields.push(price(last.map(|p| p.unit_price)));
+        fields.push(last.and_then(|p| p.location.clone()).unwrap_or_default());
+        fields.push(price(prices.average_of_last_3));
+    }
+    fields
+}
+
+fn shopping_table(items: &[ShoppingItem], inventory: &Inventory, with_prices: bool) -> Grid {
+    let price = |p: Option<f32>| p.map(|p| p.to_string()).unwrap_or_default();
+    let mut header = vec![
+        Column::new("type id").priority(0),
+        Column::new("type name").wrap(),
+        Column::new("location").priority(3),
+        Column::new("quantity").priority(1),
+        Column::new("min").priority(2),
+        Column::new("missing"),
+    ];
+    let with_packages = items.iter().any(|i| i.shortage.packages_to_buy.is_some());
+    if with_packages {
+        header.push(Column::new("to buy"));
+    }
+    if with_prices {
+        header.push(Column::new("last paid").wrap());
+        header.push(Column::new("avg of last 3"));
+    }
+    let mut table = Grid::new(header);
+    items.iter().for_each(|i| {
+        let s = &i.shortage;
+        let name = inventory.type_name(s.type_id);
+        let mut row = fields![
+            s.type_id,
+            name,
+            s.location.as_deref().unwrap_or("(total)"),
+            s.quantity,
+            s.minimum,
+            s.minimum - s.quantity,
+        ];
+        if with_packages {
+            let unit = inventory
+                .item_types
+                .iter()
+                .find(|t| t.id == s.type_id)
+                .and_then(|t| t.unit.as_deref());
+            row.push(Field::new(packages_text(s, unit)));
+        }
+        if let Some(prices) = &i.prices {
+            let last = match &prices.last_paid {
+                Some(PricePoint {
+                    unit_price,
+                    location: Some(location),
+                    ..
+                }) => format!("{} ({})", unit_price, location),
+                Some(p) => p.unit_price.to_string(),
+                None => String::new(),
+            };
+            row.push(Field::new(last));
+            row.push(Field::new(price(prices.average_of_last_3)));
+        }
+        table.add_row(row);
+    });
+    table
+}
+
+/// What to buy for a shortage, like `1 package (1 kg)`, or the quantity missing without a
+/// package size.
+fn packages_text(shortage: &Shortage, unit: Option<&str>) -> String {
+    let quantity = match unit {
+        Some(unit) => format!("{} {}", shortage.to_buy(), unit),
+        None => shortage.to_buy().to_string(),
+    };
+    match shortage.packages_to_buy {
+        Some(1) => format!("1 package ({})", quantity),
+        Some(packages) => format!("{} packages ({})", packages, quantity),
+        None => quantity,
+    }
+}
+
+pub fn print_digest(digest: &Digest, now: std::time::SystemTime, output: Output) {
+    let date = |t: std::time::SystemTime| humantime::format_rfc3339_seconds(t).to_string();
+    if output.minimal {
+        // One record per line, starting with the name of the section.
+        let entries = |section: &str, entries: &[DigestEntry]| {
+            entries.iter().for_each(|e| {
+                output.line(record::join(&[
+                    section.to_string(),
+                    e.instance_id.to_string(),
+                    e.type_name.clone(),
+                    e.quantity.to_string(),
+                    date(e.at),
+                ]))
+            });
+        };
+        entries("expired", &digest.expired);
+        entries("expiring", &digest.expiring);
+        digest.to_buy.iter().for_each(|p| {
+            output.line(record::join(&[
+                "to_buy".to_string(),
+                p.type_id.to_string(),
+                p.type_name.clone(),
+                p.suggested_quantity.to_string(),
+                p.location.clone().unwrap_or_default(),
+            ]))
+        });
+        entries("opened_too_long", &digest.opened_too_long);
+        entries("opened_reminders", &digest.opened_reminders);
+        digest.over_capacity.iter().for_each(|u| {
+            output.line(record::join(
+                &std::iter::once("over_capacity".to_string())
+                    .chain(u.to_record())
+                    .collect::<Vec<_>>(),
+            ))
+        });
+        if let Some(health) = &digest.health {
+            health_records(health).into_iter().for_each(|r| {
+                output.line(record::join(
+                    &std::iter::once("health".to_string())
+                        .chain(r)
+                        .collect::<Vec<_>>(),
+                ))
+            });
+        }
+        return;
+    }
+    if digest.is_empty() && digest.health.is_none() {
+        output.line("Nothing needs attention.");
+        return;
+    }
+    let mut sections = vec![];
+    let entries = |title: &str, verb: &str, entries: &[DigestEntry]| {
+        let mut lines = vec![format!("{}:", title)];
+        entries.iter().for_each(|e| {
+            lines.push(format!(
+                "  {} {} (instance {}) {} {}",
+                e.quantity,
+                e.type_name,
+                e.instance_id,
+                verb,
+                date(e.at)
+            ))
+        });
+        lines
+    };
+    if !digest.expired.is_empty() {
+        sections.push(entries("Expired, to throw out", "expired", &digest.expired));
+    }
+    if !digest.expiring.is_empty() {
+        sections.push(entries("Expiring soon", "expires", &digest.expiring));
+    }
+    if !digest.to_buy.is_empty() {
+        let mut lines = vec!["To buy:".to_string()];
+        digest.to_buy.iter().for_each(|p| {
+            lines.push(match &p.location {
+                Some(location) => {
+                    format!(
+                        "  {} {} for {}",
+                        p.suggested_quantity, p.type_name, location
+                    )
+                }
+                None => format!("  {} {}", p.suggested_quantity, p.type_name),
+            })
+        });
+        sections.push(lines);
+    }
+    if !digest.opened_too_long.is_empty() {
+        sections.push(entries(
+            "Opened for too long",
+            "since",
+            &digest.opened_too_long,
+        ));
+    }
+    if !digest.opened_reminders.is_empty() {
+        let mut lines = vec!["Opened a while ago:".to_string()];
+        digest.opened_reminders.iter().for_each(|e| {
+            let days = e
+                .opened_at
+                .and_then(|opened| now.duration_since(opened).ok())
+                .map(|d| d.as_secs() / (24 * 60 * 60))
+                .unwrap_or_default();
+            lines.push(format!(
+                "  {} {} (instance {}) opened {} days ago",
+                e.quantity, e.type_name, e.instance_id, days
+            ))
+        });
+        sections.push(lines);
+    }
+    if !digest.over_capacity.is_empty() {
+        let mut lines = vec!["Over capacity:".to_string()];
+        digest.over_capacity.iter().for_each(|u| {
+            let unit = u.capacity_unit.as_deref();
+            lines.push(format!(
+                "  {} holds {} of {}",
+                u.name,
+                with_unit(u.used, unit),
+                with_unit(u.capacity.unwrap_or_default(), unit)
+            ))
+        });
+        sections.push(lines);
+    }
+    if let Some(health) = &digest.health {
+        sections.push(health_lines(health));
+    }
+    output.line(
+        sections
+            .iter()
+            .map(|lines| lines.join("\n"))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    );
+}
+
+pub fn print_classes(classes: &[ItemClass], output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(ItemClass::HEADER));
+        }
+        classes
+            .iter()
+            .for_each(|c| output.line(record::join(&c.to_record())));
+    } else {
+        let mut table = Grid::with_header(&[
+            "name",
+            "ttl",
+            "open default",
+            "auto trash empty",
+            "min sealed only",
+        ]);
+        classes.iter().for_each(|c| {
+            table.add_row(fields![
+                c.name,
+                match c.ttl {
+                    Some(ttl) => humantime::format_duration(ttl).to_string(),
+                    None => "-".to_string(),
+                },
+                c.opened_by_default,
+                c.auto_trash_empty,
+                c.minimum_counts_sealed_only,
+            ]);
+        });
+        output.table(&table);
+    }
+}
+
+pub fn print_type_updates(changes: &[Change<ItemType>], output: Output) {
+    let format_ttl = |t: Option<&ItemType>| match t.and_then(|t| t.ttl) {
+        Some(ttl) => humantime::format_duration(ttl).to_string(),
+        None => "-".to_string(),
+    };
+    let header = [
+        "id",
+        "name",
+        "ttl before",
+        "ttl after",
+        "min before",
+        "min after",
+        "open default before",
+        "open default after",
+    ];
+    let rows = changes
+        .iter()
+        .map(|c| {
+            let (before, after) = (c.before.as_ref(), c.after.as_ref());
+            let min = |t: Option<&ItemType>| {
+                t.map(|t| t.minimum_quantity.to_string())
+                    .unwrap_or_default()
+            };
+            let open = |t: Option<&ItemType>| {
+                t.map(|t| t.opened_by_default.to_string())
+                    .unwrap_or_default()
+            };
+            vec![
+                c.id.to_string(),
+                after.or(before).map(|t| t.name.clone()).unwrap_or_default(),
+                format_ttl(before),
+                format_ttl(after),
+                min(before),
+                min(after),
+                open(before),
+                open(after),
+            ]
+        })
+        .collect::<Vec<_>>();
+    if output.minimal {
+        if output.header {
+            output.line(record::join(&header));
+        }
+        rows.iter().for_each(|r| output.line(record::join(r)));
+    } else {
+        let mut table = Grid::with_header(&header);
+        rows.iter()
+            .for_each(|r| table.add_row(r.iter().map(Field::new).collect()));
+        output.table(&table);
+    }
+}
+
+pub fn print_prices(prices: &[PricePoint], output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(PricePoint::HEADER));
+        }
+        prices
+            .iter()
+            .for_each(|p| output.line(record::join(&p.to_record())));
+    } else {
+        let mut table = Grid::with_header(&["at", "unit price", "instance id", "location"]);
+        prices.iter().for_each(|p| {
+            table.add_row(fields![
+                humantime::format_rfc3339(p.at),
+                p.unit_price,
+                p.instance_id,
+                p.location.clone().unwrap_or_default(),
+            ]);
+        });
+        output.table(&table);
+    }
+}
+
+pub fn print_gifts(gifts: &[Gift], inventory: &Inventory, output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(Gift::HEADER));
+        }
+        gifts
+            .iter()
+            .for_each(|g| output.line(record::join(&g.to_record())));
+    } else {
+        let mut table = Grid::with_header(&[
+            "instance id",
+            "type",
+            "quantity",
+            "value",
+            "recipient",
+            "given at",
+        ]);
+        gifts.iter().for_each(|g| {
+            table.add_row(fields![
+                g.instance_id,
+                inventory.type_name(g.type_id),
+                g.quantity,
+                g.value.map(|v| v.to_string()).unwrap_or_default(),
+                g.recipient.clone().unwrap_or_default(),
+                humantime::format_rfc3339(g.given_at),
+            ]);
+        });
+        output.table(&table);
+    }
+}
+
+/// Prints the item instances which expired since the last update-state, or a line saying
+/// there are none.
+pub fn print_expiries(transitions: &[ExpiryTransition], inventory: &Inventory, output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(ExpiryTransition::HEADER));
+        }
+        transitions
+            .iter()
+            .for_each(|t| output.line(record::join(&t.to_record())));
+    } else if transitions.is_empty() {
+        output.line("No item instance expired since the last run.");
+    } else {
+        let mut table = Grid::with_header(&["instance id", "type", "expired at", "trashed"]);
+        transitions.iter().for_each(|t| {
+            table.add_row(fields![
+                t.instance_id,
+                inventory.type_name(t.type_id),
+                humantime::format_rfc3339(t.expired_at),
+                if t.auto_trash { "yes" } else { "no" },
+            ]);
+        });
+        output.table(&table);
+    }
+}
+
+pub fn print_location_usage(usage: &[LocationUsage], output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(LocationUsage::HEADER));
+        }
+        usage
+            .iter()
+            .for_each(|u| output.line(record::join(&u.to_record())));
+    } else if usage.is_empty() {
+        output.line("No location is registered, see location-add.");
+    } else {
+        let mut table = Grid::with_header(&["location", "capacity", "used", "uncounted"]);
+        usage.iter().for_each(|u| {
+            let unit = u.capacity_unit.as_deref();
+            let style = if u.is_over() {
+                Style::Red
+            } else {
+                Style::Plain
+            };
+            let used = match u.capacity.filter(|c| *c > 0.0) {
+                Some(capacity) => format!(
+                    "{} ({:.0}%)",
+                    with_unit(u.used, unit),
+                    u.used / capacity * 100.0
+                ),
+                None => with_unit(u.used, unit),
+            };
+            table.add_row(vec![
+                Field::new(&u.name),
+                Field::new(u.capacity.map(|c| with_unit(c, unit)).unwrap_or_default()),
+                Field::styled(used, style),
+                Field::new(
+                    u.uncounted
+                        .iter()
+                        .map(|id| id.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ),
+            ]);
+        });
+        output.table(&table);
+    }
+}
+
+/// Prints the idle item types and item instances, or a line saying there are none.
+pub fn print_idle(records: &[IdleRecord], output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(IdleRecord::HEADER));
+        }
+        records
+            .iter()
+            .for_each(|r| output.line(record::join(&r.to_record())));
+    } else if records.is_empty() {
+        output.line("No item type or item instance is idle.");
+    } else {
+        let mut table = Grid::with_header(&["kind", "id", "name", "last activity"]);
+        records.iter().for_each(|r| {
+            table.add_row(fields![
+                r.kind,
+                r.id,
+                r.name,
+                r.last_activity
+                    .map(|t| humantime::format_rfc3339(t).to_string())
+                    .unwrap_or_else(|| "never".to_string()),
+            ]);
+        });
+        output.table(&table);
+    }
+}
+
+pub fn print_waste(waste: &[WasteEntry], output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(WasteEntry::HEADER));
+        }
+        waste
+            .iter()
+            .for_each(|e| output.line(record::join(&e.to_record())));
+    } else {
+        let mut table = Grid::with_header(&["month", "reason", "instances", "quantity", "value"]);
+        waste.iter().for_each(|e| {
+            table.add_row(fields![e.month, e.reason, e.instances, e.quantity, e.value]);
+        });
+        output.table(&table);
+    }
+}
+
+/// The lines of a health report for humans.
+fn health_lines(report: &HealthReport) -> Vec<String> {
+    let coverage = |what: &str, c: Coverage| {
+        format!("{}: {} of {} ({:.0}%)", what, c.set, c.total, c.percent())
+    };
+    let mut lines = vec![
+        format!("Health score: {:.0}%", report.score),
+        coverage("Item types with a ttl", report.types_with_ttl),
+        coverage("Item types with a minimum", report.types_with_minimum),
+        coverage("Item types with a unit", report.types_with_unit),
+        coverage(
+            "Item instances with a location",
+            report.instances_with_location,
+        ),
+        coverage(
+            "Item instances with an expiry",
+            report.instances_with_expiry,
+        ),
+        coverage("Item instances with a value", report.instances_with_value),
+        format!(
+            "Item instances untouched for over a year: {}",
+            report.stale_instances
+        ),
+    ];
+    if !report.most_incomplete.is_empty() {
+        lines.push("Most incomplete:".to_string());
+        report.most_incomplete.iter().for_each(|r| {
+            lines.push(match r.kind.as_str() {
+                "type" => format!("  item type {} {}: {}", r.id, r.name, r.missing.join(", ")),
+                _ => format!("  instance {} ({}): {}", r.id, r.name, r.missing.join(", ")),
+            })
+        });
+    }
+    lines
+}
+
+/// The records of a health report in minimal mode, each starting with the name of a measure,
+/// or with `incomplete` for the most incomplete records.
+fn health_records(report: &HealthReport) -> Vec<Vec<String>> {
+    let coverage =
+        |what: &str, c: Coverage| vec![what.to_string(), c.set.to_string(), c.total.to_string()];
+    let mut records = vec![
+        vec!["score".to_string(), report.score.to_string()],
+        coverage("types_with_ttl", report.types_with_ttl),
+        coverage("types_with_minimum", report.types_with_minimum),
+        coverage("types_with_unit", report.types_with_unit),
+        coverage("instances_with_location", report.instances_with_location),
+        coverage("instances_with_expiry", report.instances_with_expiry),
+        coverage("instances_with_value", report.instances_with_value),
+        vec![
+            "stale_instances".to_string(),
+            report.stale_instances.to_string(),
+        ],
+    ];
+    records.extend(report.most_incomplete.iter().map(|r| {
+        std::iter::once("incomplete".to_string())
+            .chain(r.to_record())
+            .collect()
+    }));
+    records
+}
+
+pub fn print_health(report: &HealthReport, output: Output) {
+    if output.minimal {
+        health_records(report)
+            .iter()
+            .for_each(|r| output.line(record::join(r)));
+    } else {
+        output.line(health_lines(report).join("\n"));
+    }
+}
+
+pub fn print_categories(root: &CategoryNode, output: Output) {
+    let nodes = root.walk();
+    if output.minimal {
+        if output.header {
+            output.line(record::join(CategoryNode::HEADER));
+        }
+        nodes
+            .iter()
+            .for_each(|(_, n)| output.line(record::join(&n.to_record())));
+        return;
+    }
+    let mut table = Grid::with_header(&["category", "types", "quantity", "value", "below min"]);
+    let quantity = |r: &CategoryRollup| match r.quantity {
+        Some(quantity) => with_unit(quantity, r.unit.as_deref()),
+        None => "-".to_string(),
+    };
+    let mut add_row = |name: String, r: &CategoryRollup| {
+        table.add_row(fields![
+            name,
+            r.types,
+            quantity(r),
+            r.value,
+            r.below_minimum
+        ]);
+    };
+    for (depth, node) in nodes {
+        let name = if depth == 0 {
+            "(all)".to_string()
+        } else {
+            format!("{}{}", "  ".repeat(depth - 1), node.name)
+        };
+        add_row(name, &node.rollup);
+    }
+    if !root.type_ids.is_empty() {
+        add_row("(uncategorized)".to_string(), &root.own);
+    }
+    output.table(&table);
+}
+
+/// Lists the profiles of the profiles file of the user.
+pub fn list_profiles() -> std::result::Result<CommandResult, CliError> {
+    let path = match profiles::profiles_path() {
+        Some(path) => path,
+        None => return Ok(CommandResult::profiles(vec![])),
+    };
+    let profiles = profiles::load(&path)?;
+    let mut result = CommandResult::profiles(profiles.entries());
+    if profiles.profiles.is_empty() {
+        result
+            .messages
+            .push(format!("No profile is defined in {}", path.display()));
+    }
+    Ok(result)
+}
+
+pub fn print_profiles(profiles: &[profiles::ProfileEntry], output: Output) {
+    let format = |f: Option<profiles::Format>| match f {
+        Some(profiles::Format::Table) => "table",
+        Some(profiles::Format::Minimal) => "minimal",
+        Some(profiles::Format::Machine) => "machine",
+        None => "",
+    };
+    let rows = profiles.iter().map(|p| {
+        vec![
+            p.profile_name.clone(),
+            if p.default { "*" } else { "" }.to_string(),
+            p.profile
+                .workdir
+                .as_ref()
+                .map(|w| w.display().to_string())
+                .unwrap_or_default(),
+            p.profile.name.clone().unwrap_or_default(),
+            format(p.profile.format).to_string(),
+        ]
+    });
+    let header = ["profile", "default", "workdir", "inventory", "format"];
+    if output.minimal {
+        if output.header {
+            output.line(record::join(&header));
+        }
+        rows.for_each(|row| output.line(record::join(&row)));
+        return;
+    }
+    let mut table = Grid::with_header(&header);
+    rows.for_each(|row| table.add_row(row.into_iter().map(Field::new).collect()));
+    output.table(&table);
+}
+
+pub fn print_values(report: &ValueReport, inventory: &Inventory, output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(TypeValue::HEADER));
+        }
+        report
+            .types
+            .iter()
+            .for_each(|v| output.line(record::join(&v.to_record())));
+        return;
+    }
+    let mut header = vec!["type id", "type name", "instances", "value"];
+    if report.depreciated {
+        header.push("current value");
+    }
+    let mut table = Grid::with_header(&header);
+    let mut add_row = |id: String, name: String, instances: u32, value: f32, current: f32| {
+        let mut row = fields![id, name, instances, value];
+        if report.depreciated {
+            row.push(Field::new(current.to_string()));
+        }
+        table.add_row(row);
+    };
+    for v in &report.types {
+        add_row(
+            v.type_id.to_string(),
+            inventory.type_name(v.type_id),
+            v.instances,
+            v.value,
+            v.current_value,
+        );
+    }
+    add_row(
+        String::new(),
+        "Total".to_string(),
+        report.types.iter().map(|v| v.instances).sum(),
+        report.types.iter().map(|v| v.value).sum(),
+        report.types.iter().map(|v| v.current_value).sum(),
+    );
+    output.table(&table);
+}
+
+pub fn print_costs(costs: &[TypeCost], inventory: &Inventory, output: Output) {
+    if output.minimal {
+        if output.header {
+            output.line(record::join(TypeCost::HEADER));
+        }
+        costs
+            .iter()
+            .for_each(|c| output.line(record::join(&c.to_record())));
+        return;
+    }
+    let optional = |value: Option<f32>, suffix: &str| {
+        value
+            .map(|v| format!("{:.2}{}", v, suffix))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let mut table = Grid::with_header(&[
+        "type id",
+        "type name",
+        "instances",
+        "excluded",
+        "purchased",
+        "wasted",
+        "in stock",
+        "consumed",
+        "cost per unit",
+        "waste",
+    ]);
+    for c in costs {
+        table.add_row(fields![
+            c.type_id,
+            inventory.type_name(c.type_id),
+            c.instances,
+            c.excluded,
+            format!("{:.2}", c.purchased),
+            format!("{:.2}", c.wasted),
+            format!("{:.2}", c.in_stock),
+            format!("{:.2}", c.consumed),
+            optional(c.cost_per_unit, ""),
+            optional(c.waste_percent, " %")
+        ]);
+    }
+    output.table(&table);
+}
+
+/// Warns about the item instances whose item type doesn't exist anymore, for example after
+/// it was deleted by editing the files.
+fn warn_about_orphans<'a>(instances: impl IntoIterator<Item = &'a ItemInstance>, inv: &Inventory) {
+    let orphans = inv.orphans();
+    let orphans = instances
+        .into_iter()
+        .filter(|ii| orphans.contains(ii))
+        .map(|ii| ii.id.to_string())
+        .collect::<Vec<_>>();
+    if !orphans.is_empty() {
+        warn!(
+            "Warning: the item types of the item instances {} don't exist anymore. Delete them with di, or edit their item type in the files",
+            orphans.join(", ")
+        );
+    }
+}
+
+pub fn print_item_instances(instances: &[&ItemInstance], inv: &Inventory, output: Output) {
+    warn_about_orphans(instances.iter().copied(), inv);
+    if output.minimal {
+        if output.header {
+            output.line(record::join(ItemInstance::HEADER));
+        }
+        instances.iter().for_each(|ii| output.line(ii));
+    } else {
+        let mut table = instance_table(output.timestamps, output.shelf_life);
+        for ii in instances {
+            table.add_row(instance_row(ii, inv, output));
+        }
+        output.table(&table);
+    }
+}
+
+/// Prints every field of the item instances, one table of fields per item instance, for
+/// `find-ref`. Minimal mode prints them like `ri`.
+pub fn print_references(instances: &[ItemInstance], inv: &Inventory, output: Output) {
+    if output.minimal {
+        print_item_instances(&instances.iter().collect::<Vec<_>>(), inv, output);
+        return;
+    }
+    warn_about_orphans(instances.iter(), inv);
+    for (i, ii) in instances.iter().enumerate() {
+        if i > 0 {
+            output.line("");
+        }
+        let type_name = inv
+            .item_types
+            .iter()
+            .find(|t| t.id == ii.item_type)
+            .map(|t| t.name.clone())
+            .unwrap_or_default();
+        let mut table = Grid::with_header(&["field", "value"]);
+        for (field, value) in ItemInstance::HEADER.iter().zip(ii.to_record()) {
+            table.add_row(vec![Field::new(*field), Field::new(value)]);
+            if *field == "type id" {
+                table.add_row(vec![Field::new("type name"), Field::new(type_name.clone())]);
+            }
+        }
+        output.table(&table);
+    }
+}
+
+/// Prints the groups in a single table, with a subtotal row after each group and
+/// the grand total last. Minimal mode prints the item instances without grouping.
+pub fn print_instance_groups(
+    groups: &[InstanceGroup<ItemInstance>],
+    inv: &Inventory,
+    output: Output,
+) {
+    if output.minimal {
+        let instances = groups
+            .iter()
+            .flat_map(|g| g.items.iter())
+            .collect::<Vec<_>>();
+        print_item_instances(&instances, inv, output);
+        return;
+    }
+    warn_about_orphans(groups.iter().flat_map(|g| g.items.iter()), inv);
+    let mut table = instance_table(output.timestamps, output.shelf_life);
+    let width = table.columns.len();
+    let total_row = |title: String, subtotal: &Subtotal| {
+        let mut row = vec![Field::new(""); width];
+        row[1] = Field::styled(title, Style::Bold);
+        row[4] = Field::styled(subtotal.quantity, Style::Bold);
+        let expiry = subtotal
+            .soonest_expiry
+            .map(|t| humantime::format_rfc3339(t).to_string())
+            .unwrap_or_default();
+        row[11] = Field::styled(expiry, Style::Bold);
+        row
+    };
+    for group in groups {
+        for ii in &group.items {
+            table.add_row(instance_row(ii, inv, output));
+        }
+        let title = format!(
+            "{}: {} instances",
+            group.name.as_deref().unwrap_or("none"),
+            group.subtotal.count
+        );
+        table.add_row(total_row(title, &group.subtotal));
+    }
+    let total = Subtotal::of(groups.iter().flat_map(|g| g.items.iter()));
+    table.add_row(total_row(
+        format!("Total: {} instances", total.count),
+        &total,
+    ));
+    output.table(&table);
+}
+
+/// The columns of the item instances, with the bar of their shelf life if `shelf_life` is
+/// set and when they were added and removed if `timestamps` is set. Narrow terminals drop
+/// the extra, serial and model first.
+fn instance_table(timestamps: bool, shelf_life: bool) -> Grid {
+    let mut columns = vec![
+        Column::new("id"),
+        Column::new("label").priority(8),
+        Column::new("type id").priority(3),
+        Column::new("type name").wrap(),
+        Column::new("quantity"),
+        Column::new("model").priority(2),
+        Column::new("serial").priority(1),
+        Column::new("extra").priority(0).wrap(),
+        Column::new("location").priority(7),
+        Column::new("value").priority(4),
+        Column::new("opened at").priority(5),
+        Column::new("expires at"),
+        Column::new("best before").priority(6),
+    ];
+    if shelf_life {
+        columns.insert(12, Column::new("shelf life").priority(5));
+    }
+    if timestamps {
+        columns.push(Column::new("added at").priority(10));
+        columns.push(Column::new("removed at").priority(9));
+    }
+    Grid::new(columns)
+}
+
+/// The name of an item type, after its icon when it is an emoji.
+fn type_label(item_type: &ItemType) -> String {
+    match item_type.icon.as_deref() {
+        Some(icon) if icon::kind(icon) == icon::IconKind::Emoji => {
+            format!("{} {}", icon, item_type.name)
+        }
+        _ => item_type.name.clone(),
+    }
+}
+
+fn with_unit(quantity: f32, unit: Option<&str>) -> String {
+    match unit {
+        Some(unit) => format!("{} {}", quantity, unit),
+        None => quantity.to_string(),
+    }
+}
+
+/// A quantity in the unit of an item type, without decimals if it is counted in whole pieces.
+fn type_quantity(quantity: f32, item_type: Option<&ItemType>) -> String {
+    match item_type {
+        Some(t) if t.integer_only => with_unit(quantity.round(), t.unit.as_deref()),
+        _ => with_unit(quantity, item_type.and_then(|t| t.unit.as_deref())),
+    }
+}
+
+/// The quantity of the item instance with its unit, followed by the quantity in the unit
+/// of the item type when it has its own unit, and marked when it is topped up.
+fn quantity_cell(ii: &ItemInstance, item_type: Option<&ItemType>) -> String {
+    let cell = match (&ii.unit_override, ii.normalized_quantity(item_type)) {
+        (None, _) => type_quantity(ii.quantity, item_type),
+        (Some(unit), Some(normalized)) => format!(
+            "{} = {}",
+            with_unit(ii.quantity, Some(unit)),
+            type_quantity(normalized, item_type)
+        ),
+        (Some(unit), None) => format!("{} (not convertible)", with_unit(ii.quantity, Some(unit))),
+    };
+    if ii.continuous {
+        format!("{} (topped up)", cell)
+    } else {
+        cell
+    }
+}
+
+fn instance_row(t: &ItemInstance, inv: &Inventory, output: Output) -> Vec<Field> {
+    let now = inv.now();
+    // Red past the use-by date, yellow past the best-before date.
+    let date = |time: Option<std::time::SystemTime>, past_style: Style| {
+        let text = time
+            .map(|t| humantime::format_rfc3339(t).to_string())
+            .unwrap_or_default();
+        match time {
+            Some(t) if t <= now => Field::styled(text, past_style),
+            _ => Field::new(text),
+        }
+    };
+    let item_type = inv.item_types.iter().find(|ty| ty.id == t.item_type);
+    let mut row = fields![
+        t.id.to_string(),
+        conv(&t.label),
+        t.item_type.to_string(),
+        inv.type_name(t.item_type),
+        quantity_cell(t, item_type),
+        conv(&t.model),
+        conv(&t.serial),
+        conv(&t.extra),
+        match (&t.location, &t.home_location) {
+            (location, Some(home)) if t.is_misplaced() => {
+                format!("{} (home: {})", location.as_deref().unwrap_or(""), home)
+            }
+            (location, _) => conv(location),
+        },
+        conv(&t.value),
+        t.opened_at
+            .map(|t| humantime::format_rfc3339(t).to_string())
+            .unwrap_or("".to_string()),
+    ];
+    row.push(date(t.expires_at, Style::Red));
+    if output.shelf_life {
+        // Yellow for the last quarter of the shelf life.
+        row.push(match t.shelf_life_fraction(now) {
+            Some(fraction) if fraction < 0.25 => {
+                Field::styled(render::bar(fraction), Style::Yellow)
+            }
+            Some(fraction) => Field::new(render::bar(fraction)),
+            None => Field::new(""),
+        });
+    }
+    row.push(date(t.best_before_at, Style::Yellow));
+    if output.timestamps {
+        for time in [t.added_at, t.removed_at] {
+            row.push(Field::new(
+                time.map(|t| humantime::format_rfc3339_seconds(t).to_string())
+                    .unwrap_or_default(),
+            ));
+        }
+    }
+    row
+}
+
+pub fn update_type(
+    cmd: &UpdateTypeCommand,
+    inventory: &mut Inventory,
+    classes: &[ItemClass],
+) -> CommandResult {
+    let now = inventory.now();
+    let class = match &cmd.class {
+        Some(name) => match find_class(classes, name) {
+            Some(class) => Some(class),
+            None => {
+                warn!("Could not find a class with the specified name");
+                return CommandResult::default();
+            }
+        },
+        None => None,
+    };
+    if let Some((i, j)) = season::find_overlap(&cmd.seasonal) {
+        warn!(
+            "The seasonal minimums {} and {} overlap",
+            cmd.seasonal[i], cmd.seasonal[j]
+        );
+        return CommandResult::default();
+    }
+    let declared = inventory
+        .item_types
+        .iter()
+        .find(|t| t.id == cmd.id)
+        .map(|t| t.custom_fields.clone())
+        .unwrap_or_default();
+    for (i, field) in cmd.add_field.iter().enumerate() {
+        if declared
+            .iter()
+            .chain(&cmd.add_field[..i])
+            .any(|f| f.key == field.key)
+        {
+            warn!(
+                "Could not update the item type, nothing was changed: {}",
+                custom_field_error(&InventoryError::DuplicateField {
+                    key: field.key.clone()
+                })
+            );
+            return CommandResult::default();
+        }
+    }
+    if let Some(name) = &cmd.name {
+        match inventory.rename_type(cmd.id, name, cmd.force) {
+            Ok(()) => {}
+            Err(InventoryError::DuplicateTypeName { type_id }) => {
+                warn!(
+                    "The item type {} already has or had the name {}. Use --force to rename anyway.",
+                    type_id, name
+                );
+                return CommandResult::default();
+            }
+            Err(_) => {
+                warn!("Could not find an item type with the specified id");
+                return CommandResult::default();
+            }
+        }
+    }
+    let ttl_changed =
+        if let Some(item_type) = inventory.item_types.iter_mut().find(|t| t.id == cmd.id) {
+            let old_ttl = item_type.ttl;
+            if let Some(class) = class {
+                class.apply(item_type);
+            }
+            if let Some(min) = cmd.minimum_quantity {
+                item_type.minimum_quantity = min;
+            }
+            if let Some(ttl_opt) = cmd.ttl {
+                item_type.ttl = ttl_opt.map(|t| t.duration(now));
+            }
+            if let Some(open_by_default) = cmd.open_by_default {
+                item_type.opened_by_default = open_by_default;
+            }
+            if let Some(auto_trash_empty) = cmd.auto_trash_empty {
+                item_type.auto_trash_empty = auto_trash_empty;
+            }
+            if let Some(auto_trash_on_expiry) = cmd.auto_trash_on_expiry {
+                item_type.auto_trash_on_expiry = auto_trash_on_expiry;
+            }
+            if let Some(sealed_only) = cmd.minimum_counts_sealed_only {
+                item_type.minimum_counts_sealed_only = sealed_only;
+            }
+            if let Some(unit) = &cmd.unit {
+                item_type.unit = unit.clone();
+            }
+            if let Some(remind) = cmd.remind_after_open {
+                item_type.remind_after_open = remind.map(|d| d.duration(now));
+            }
+            if let Some(allow_overdraft) = cmd.allow_overdraft {
+                item_type.allow_overdraft = allow_overdraft;
+            }
+            if let Some(integer_only) = cmd.integer_only {
+                item_type.integer_only = integer_only;
+            }
+            if let Some(category) = &cmd.category {
+                item_type.category = category.as_deref().and_then(path::normalize);
+            }
+            if let Some(years) = cmd.depreciation_years {
+                item_type.depreciation_years = Some(years).filter(|y| *y > 0.0);
+            }
+            if let Some(icon) = &cmd.icon {
+                item_type.icon = icon.clone().filter(|i| !i.trim().is_empty());
+            }
+            if let Some(vendor) = &cmd.vendor {
+                item_type.vendor = vendor.clone().filter(|v| !v.trim().is_empty());
+            }
+            if let Some(size) = cmd.package_size {
+                item_type.package_size = Some(size).filter(|s| *s > 0.0);
+            }
+            if cmd.counted {
+                item_type.recount_requested_at = None;
+            }
+            if let Some(deposit) = cmd.deposit {
+                item_type.deposit = Some(deposit).filter(|d| *d > 0.0);
+            }
+            cmd.min_at
+                .iter()
+                .for_each(|m| item_type.set_minimum_at(&m.location, m.minimum));
+            if cmd.clear_seasonal || !cmd.seasonal.is_empty() {
+                item_type.seasonal_minimums = cmd.seasonal.clone();
+            }
+            item_type
+                .custom_fields
+                .extend(cmd.add_field.iter().cloned());
+            item_type.ttl != old_ttl
+        } else {
+            warn!("Could not find an item type with the specified id");
+            return CommandResult::default();
+        };
+    if ttl_changed {
+        let affected = inventory
+            .clone()
+            .recompute_expiries(cmd.id, RecomputePolicy::default())
+            .map(|changes| changes.len())
+            .unwrap_or(0);
+        if affected > 0 {
+            warn!(
+                "The expiry of {} opened item instances no longer matches the ttl. Use `inv recompute-expiry {}` to update them.",
+                affected, cmd.id
+            );
+        }
+    }
+    if cmd.integer_only == Some(true) {
+        let fractional = inventory
+            .fractional_quantities()
+            .into_iter()
+            .filter(|id| {
+                inventory
+                    .item_instances
+                    .iter()
+                    .any(|ii| ii.id == *id && ii.item_type == cmd.id)
+            })
+            .count();
+        if fractional > 0 {
+            warn!(
+                "{} item instances have a fractional quantity. Update them with `inv ui <id> -q <quantity>`.",
+                fractional
+            );
+        }
+    }
+    CommandResult::default()
+}
+
+pub fn recompute_expiry(cmd: &RecomputeExpiryCommand, inventory: &mut Inventory) -> CommandResult {
+    let policy = RecomputePolicy {
+        only_opened: cmd.only_opened,
+        force: cmd.force,
+    };
+    let changes = if cmd.dry_run {
+        inventory.clone().recompute_expiries(cmd.type_id, policy)
+    } else {
+        inventory.recompute_expiries(cmd.type_id, policy)
+    };
+    let changes = match changes {
+        Ok(changes) => changes,
+        Err(_) => {
+            warn!("Could not find an item type with the specified id");
+            return CommandResult::default();
+        }
+    };
+    let format = |t: Option<std::time::SystemTime>| {
+        t.map(|t| humantime::format_rfc3339(t).to_string())
+            .unwrap_or_else(|| "-".to_string())
+    };
+    CommandResult::messages(
+        changes
+            .iter()
+            .map(|(id, old, new)| format!("{}: {} -> {}", id, format(*old), format(*new)))
+            .collect(),
+    )
+}
+
+pub fn bulk_update_types(cmd: &BulkUpdateTypesCommand, inventory: &mut Inventory) -> CommandResult {
+    let mut filter = TypeQuery::new();
+    if let Some(name) = &cmd.name_contains {
+        filter = filter.name_contains(name);
+    }
+    if cmd.no_ttl {
+        filter = filter.has_ttl(false);
+    }
+    let update = TypeUpdate {
+        ttl: cmd
+            .set_ttl
+            .map(|ttl| ttl.map(|t| t.duration(inventory.now()))),
+        minimum_quantity: cmd.set_min,
+        opened_by_default: cmd.set_open_by_default,
+    };
+    let mut after = inventory.clone();
+    let ids = after.update_types_where(&filter, &update);
+    let mut messages = vec![format!("{} item types matched", ids.len())];
+    if update.ttl.is_some() {
+        let mut recomputed = if cmd.recompute {
+            after.clone()
+        } else {
+            inventory.clone()
+        };
+        let affected = ids
+            .iter()
+            .map(|id| {
+                recomputed
+                    .recompute_expiries(*id, RecomputePolicy::default())
+                    .map(|changes| changes.len())
+                    .unwrap_or(0)
+            })
+            .sum::<usize>();
+        if cmd.recompute {
+            after = recomputed;
+            messages.push(format!(
+                "Recomputed the expiry of {} opened item instances",
+                affected
+            ));
+        } else if affected > 0 {
+            warn!(
+                "The expiry of {} opened item instances no longer matches the ttl. Use --recompute to update them.",
+                affected
+            );
+        }
+    }
+    let changes = result::diff(&inventory.item_types, &after.item_types);
+    if cmd.dry_run {
+        messages.push("Dry run, nothing was changed".to_string());
+    } else {
+        *inventory = after;
+    }
+    let mut result = CommandResult::type_updates(changes);
+    result.messages = messages;
+    result
+}
+
+pub fn delete_type(cmd: &DeleteTypeCommand, inventory: &mut Inventory) -> CommandResult {
+    let to = match cmd.reassign_to {
+        Some(to) => to,
+        None => {
+            inventory.delete_item_type(cmd.id);
+            return CommandResult::default();
+        }
+    };
+    match inventory.delete_type_reassigning(cmd.id, to) {
+        Ok(moved) => CommandResult::messages(vec![format!(
+            "Moved {} item instances to item type {}",
+            moved, to
+        )]),
+        Err(e) => {
+            let reason = match e {
+                InventoryError::SameItemType => {
+                    "the item instances can't be moved to the deleted item type".to_string()
+                }
+                InventoryError::UnknownItemType => "one of the item types doesn't exist".to_string(),
+                InventoryError::IncompatibleUnits { instance_ids } => format!(
+                    "the unit of item instances {:?} can not be converted to the unit of item type {}",
+                    instance_ids, to
+                ),
+                _ => format!("{:?}", e),
+            };
+            warn!("Could not delete item type {}: {}", cmd.id, reason);
+            CommandResult::default()
+        }
+    }
+}
+
+pub fn delete_instance(cmd: &DeleteInstanceCommand, inventory: &mut Inventory) -> CommandResult {
+    let id = inventory
+        .resolve_instance(&cmd.id)
+        .expect("Failed to delete item instance. Wrong id specified");
+    inventory
+        .delete_item_instance(id, cmd.reason.as_deref())
+        .expect("Failed to delete item instance. Wrong id specified");
+    CommandResult::default()
+}
+
+/// Finds the item type designated by an id or a name, printing an error if there is none.
+/// When several item types match the name, asks which one is meant if `interactive`,
+/// otherwise lists them. Nothing is returned if the question is cancelled.
+pub fn resolve_type(selector: &str, inventory: &Inventory, interactive: bool) -> Option<TypeId> {
+    match inventory.resolve_type(selector) {
+        Ok(id) => Some(id),
+        Err(InventoryError::AmbiguousName { type_ids }) => {
+            let quantities = inventory.quantities_by_type();
+            let candidates = type_ids
+                .iter()
+                .filter_map(|id| inventory.item_types.iter().find(|t| t.id == *id))
+                .map(|t| {
+                    let quantity = quantities.get(&t.id).copied().unwrap_or(0.0);
+                    match &t.unit {
+                        Some(unit) => format!("{}: {} ({} {})", t.id, t.name, quantity, unit),
+                        None => format!("{}: {} ({})", t.id, t.name, quantity),
+                    }
+                })
+                .collect::<Vec<_>>();
+            if interactive {
+                eprintln!("Several item types match {}:", selector);
+                let choice = prompt::choose_one(&candidates).map(|i| type_ids[i]);
+                if choice.is_none() {
+                    info!("Cancelled, nothing was changed");
+                }
+                choice
+            } else {
+                warn!(
+                    "Several item types match {}, use the id of one of them instead: {}",
+                    selector,
+                    candidates.join(", ")
+                );
+                None
+            }
+        }
+        Err(_) => {
+            warn!("Could not find an item type with the specified id or name");
+            None
+        }
+    }
+}
+
+/// Finds the item instance designated by an id or a label, printing an error if there is none.
+pub fn resolve_instance(selector: &str, inventory: &Inventory) -> Option<InstanceId> {
+    let id = inventory.resolve_instance(selector).ok();
+    if id.is_none() {
+        warn!("Could not find an item instance with the specified id or label");
+    }
+    id
+}
+
+pub fn create_instance(
+    cmd: &CreateInstanceCommand,
+    type_id: TypeId,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    let now = inventory.now();
+    let item_type = inventory.item_types.iter().find(|t| t.id == type_id);
+    let unit = cmd
+        .unit
+        .as_deref()
+        .or_else(|| item_type.and_then(|t| t.unit.as_deref()));
+    let quantity = match item_type {
+        Some(item_type) => convert_quantity(&cmd.quantity, unit, item_type, "add"),
+        None => Some(cmd.quantity.amount),
+    };
+    let quantity = match quantity {
+        Some(quantity) => quantity,
+        None => return CommandResult::default(),
+    };
+    let mut custom = std::collections::BTreeMap::new();
+    if let Some(item_type) = item_type {
+        match item_type.parse_custom(&cmd.set) {
+            Ok(values) => custom::apply_custom(&mut custom, values),
+            Err(e) => {
+                warn!(
+                    "Could not create the item instance: {}",
+                    custom_field_error(&e)
+                );
+                return CommandResult::default();
+            }
+        }
+    }
+    let mut new = ItemInstanceBuilder::default();
+
+    new.item_type(type_id);
+    new.model(cmd.model.clone());
+    new.serial(cmd.serial.clone());
+    new.extra(cmd.extra.clone());
+    new.location(cmd.location.clone());
+    new.home_location(cmd.home_location.clone());
+    new.value(cmd.value);
+    new.quantity(quantity);
+    new.expires_at(cmd.expires_at.map(|t| t.time(now)));
+    new.best_before_at(cmd.best_before_at.map(|t| t.time(now)));
+    new.opened_at(cmd.opened_at.map(|t| t.ago(now)));
+    new.unit_override(cmd.unit.clone());
+    new.continuous(cmd.continuous);
+    new.external_ref(cmd.external_ref.clone());
+    new.vendor(cmd.vendor.clone().filter(|v| !v.trim().is_empty()));
+    new.custom(custom);
+
+    match inventory.add_item_instance(new.build().unwrap()) {
+        Ok(id) => CommandResult::created(id),
+        Err(e) => {
+            warn!("Could not create the item instance: {}", instance_error(&e));
+            CommandResult::default()
+        }
+    }
+}
+
+pub fn update_instance(cmd: &UpdateInstanceCommand, inventory: &mut Inventory) -> CommandResult {
+    let now = inventory.now();
+    let id = inventory.resolve_instance(&cmd.id).ok();
+    if let (Some(id), Some(quantity)) = (id, cmd.quantity) {
+        if let Err(e) = inventory.set_quantity(id, quantity) {
+            warn!(
+                "Could not update the item instance, nothing was changed: {}",
+                instance_error(&e)
+            );
+            return CommandResult::default();
+        }
+    }
+    if let (Some(id), false) = (id, cmd.set.is_empty()) {
+        if let Err(e) = inventory.set_custom(id, &cmd.set) {
+            warn!(
+                "Could not update the item instance, nothing was changed: {}",
+                custom_field_error(&e)
+            );
+            return CommandResult::default();
+        }
+    }
+    if let Some(item_instance) = inventory
+        .item_instances
+        .iter_mut()
+        .find(|t| Some(t.id) == id)
+    {
+        if let Some(e) = &cmd.model {
+            item_instance.model = Some(e.clone());
+        }
+        if let Some(e) = &cmd.serial {
+            item_instance.serial = Some(e.clone());
+        }
+        if let Some(e) = &cmd.extra {
+            item_instance.extra = Some(e.clone());
+        }
+        if let Some(e) = &cmd.location {
+            item_instance.location = Some(e.clone());
+        }
+        if let Some(e) = &cmd.home_location {
+            item_instance.home_location = e.clone();
+        }
+        if let Some(e) = &cmd.external_ref {
+            item_instance.external_ref = e.clone();
+        }
+        if let Some(e) = &cmd.vendor {
+            item_instance.vendor = e.clone().filter(|v| !v.trim().is_empty());
+        }
+        if let Some(e) = &cmd.value {
+            item_instance.value = Some(*e);
+        }
+        if let Some(e) = &cmd.expires_at {
+            item_instance.expires_at = e.map(|t| t.time(now));
+        }
+        if let Some(e) = &cmd.best_before_at {
+            item_instance.best_before_at = e.map(|t| t.time(now));
+        }
+        if let Some(e) = &cmd.opened_at {
+            item_instance.opened_at = e.map(|t| t.ago(now));
+        }
+    } else {
+        warn!("Could not find an item instance with the specified id or label");
+    }
+    if cmd.external_ref.is_some() {
+        inventory.invalidate_ref_index();
+    }
+    CommandResult::default()
+}
+
+/// Whether the uses of an item type that don't find enough are recorded anyway.
+fn overdraft_allowed(type_id: TypeId, allow_overdraft: bool, inventory: &Inventory) -> bool {
+    allow_overdraft
+        || inventory
+            .item_types
+            .iter()
+            .any(|t| t.id == type_id && t.allow_overdraft)
+}
+
+/// Reports that more was used than what was left, returning what was used.
+/// Fills the inventory with generated data, refusing to replace what it has without `force`.
+fn generate_demo(
+    inventory: &mut Inventory,
+    seed: u64,
+    sizes: testing::Sizes,
+    force: bool,
+) -> CommandResult {
+    let empty = inventory.item_types.is_empty() && inventory.item_instances.is_empty();
+    if !empty && !force {
+        warn!(
+            "The inventory is not empty, use --force to replace its item types and item instances"
+        );
+        return CommandResult::default();
+    }
+    inventory.item_types.clear();
+    inventory.item_instances.clear();
+    let instances = inventory.add_generated(seed, sizes);
+    CommandResult::messages(vec![format!(
+        "Generated {} item types and {} item instances",
+        inventory.item_types.len(),
+        instances
+    )])
+}
+
+fn overdraft_used(used: f32, overdraft: Option<InstanceId>, inventory: &Inventory) -> f32 {
+    match overdraft.and_then(|id| inventory.item_instances.iter().find(|ii| ii.id == id)) {
+        Some(ii) => {
+            let missing = ii.initial_quantity.unwrap_or_default();
+            warn!(
+                "{} more than what was left was used, recorded by item instance {}. {} is flagged for a recount, see list-recount",
+                missing,
+                ii.id,
+                inventory.type_name(ii.item_type)
+            );
+            used + missing
+        }
+        None => used,
+    }
+}
+
+/// Logs which item instances a use was taken from when it spilled over several of them,
+/// returning what was used.
+fn log_hops(report: &UseReport) -> f32 {
+    if report.hops.len() > 1 {
+        for hop in &report.hops {
+            let mut state = vec![];
+            if hop.opened {
+                state.push("opened");
+            }
+            if hop.trashed {
+                state.push("emptied and trashed");
+            } else if hop.emptied {
+                state.push("emptied");
+            }
+            match state.is_empty() {
+                true => info!("Used {} from item instance {}", hop.used, hop.id),
+                false => info!(
+                    "Used {} from item instance {}, {}",
+                    hop.used,
+                    hop.id,
+                    state.join(", ")
+                ),
+            }
+        }
+    }
+    report.used
+}
+
+/// Uses some quantity of an item type, 1.0 by default, converted to the unit of the item type.
+pub fn use_quantity(
+    type_id: TypeId,
+    quantity: Option<&QuantityArg>,
+    allow_overdraft: bool,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    let quantity = match (
+        quantity,
+        inventory.item_types.iter().find(|t| t.id == type_id),
+    ) {
+        (Some(quantity), Some(item_type)) => {
+            match convert_quantity(quantity, item_type.unit.as_deref(), item_type, "use") {
+                Some(quantity) => Some(quantity),
+                None => return CommandResult::default(),
+            }
+        }
+        (quantity, _) => quantity.map(|q| q.amount),
+    };
+    let used = if overdraft_allowed(type_id, allow_overdraft, inventory) {
+        inventory
+            .use_allowing_overdraft(type_id, quantity)
+            .map(|(used, overdraft)| overdraft_used(used, overdraft, inventory))
+    } else {
+        inventory
+            .use_instance(type_id, quantity)
+            .map(|report| log_hops(&report))
+    };
+    match used {
+        Ok(consumed) => {
+            let requested = quantity.unwrap_or(1.0);
+            if consumed > 0.0 && requested - consumed > QUANTITY_EPSILON {
+                warn!(
+                    "Only {} of {} could be used, nothing is left",
+                    consumed, requested
+                );
+            }
+        }
+        Err(InventoryError::InvalidQuantity) => {
+            warn!("The quantity to use must be a positive number")
+        }
+        Err(InventoryError::FractionalQuantityNotAllowed) => {
+            warn!("The item type is counted in whole quantities, nothing was used")
+        }
+        Err(_) => warn!("Could not find an item type with the specified id"),
+    }
+    CommandResult::default()
+}
+
+/// Compares the item types having a minimum quantity whose quantity changed to their
+/// minimum, before and after a command. A quantity equal to the minimum is not below it.
+fn minimum_checks(before: &Inventory, after: &Inventory) -> Vec<MinimumCheck> {
+    let now = after.now();
+    let quantities_before = before.quantities_by_type();
+    let quantities_after = after.quantities_by_type();
+    after
+        .item_types
+        .iter()
+        .filter_map(|t| {
+            let minimum = t.effective_minimum(now);
+            let old = quantities_before.get(&t.id).copied().unwrap_or(0.0);
+            let quantity = quantities_after.get(&t.id).copied().unwrap_or(0.0);
+            if minimum <= 0.0 || old == quantity {
+                return None;
+            }
+            let below_minimum = quantity < minimum;
+            Some(MinimumCheck {
+                type_id: t.id,
+                quantity,
+                minimum,
+                below_minimum,
+                crossed_threshold: (old < minimum) != below_minimum,
+            })
+        })
+        .collect()
+}
+
+/// The lines telling which item types went below their minimum, or back above it.
+fn minimum_messages(checks: &[MinimumCheck], inventory: &Inventory) -> Vec<String> {
+    checks
+        .iter()
+        .filter(|c| c.crossed_threshold)
+        .map(|c| {
+            let item_type = inventory.item_types.iter().find(|t| t.id == c.type_id);
+            let name = inventory.type_name(c.type_id);
+            let quantity = type_quantity(c.quantity, item_type);
+            let minimum = type_quantity(c.minimum, item_type);
+            if c.below_minimum {
+                format!(
+                    "{} is now below its minimum: {} < {}, added to the shopping list",
+                    name, quantity, minimum
+                )
+            } else {
+                format!(
+                    "{} is no longer below its minimum: {} >= {}",
+                    name, quantity, minimum
+                )
+            }
+        })
+        .collect()
+}
+
+/// Uses from several item types, all of them or none: nothing is changed if an item type
+/// can't be found or, without `best_effort`, doesn't have enough left.
+pub fn use_several(
+    items: &[UseItem],
+    best_effort: bool,
+    allow_overdraft: bool,
+    inventory: &mut Inventory,
+    interactive: bool,
+) -> CommandResult {
+    let abort = || {
+        warn!("Nothing was used");
+        CommandResult::default()
+    };
+    let mut staged = inventory.clone();
+    let mut summaries = vec![];
+    for item in items {
+        let type_id = match resolve_type(&item.item_type, &staged, interactive) {
+            Some(type_id) => type_id,
+            None => return abort(),
+        };
+        let item_type = staged
+            .item_types
+            .iter()
+            .find(|t| t.id == type_id)
+            .expect("resolve_type returns existing item types");
+        let type_name = item_type.name.clone();
+        let requested = match &item.quantity {
+            Some(quantity) => {
+                match convert_quantity(quantity, item_type.unit.as_deref(), item_type, "use") {
+                    Some(quantity) => quantity,
+                    None => return abort(),
+                }
+            }
+            None => 1.0,
+        };
+        let used = if overdraft_allowed(type_id, allow_overdraft, &staged) {
+            staged
+                .use_allowing_overdraft(type_id, Some(requested))
+                .map(|(used, overdraft)| overdraft_used(used, overdraft, &staged))
+        } else {
+            staged
+                .use_instance(type_id, Some(requested))
+                .map(|report| log_hops(&report))
+        };
+        let used = match used {
+            Ok(used) => used,
+            Err(InventoryError::InvalidQuantity) => {
+                warn!("The quantity to use must be a positive number");
+                return abort();
+            }
+            Err(InventoryError::FractionalQuantityNotAllowed) => {
+                warn!("{} is counted in whole quantities", type_name);
+                return abort();
+            }
+            Err(_) => {
+                warn!("Could not find an item type with the specified id");
+                return abort();
+            }
+        };
+        if requested - used > QUANTITY_EPSILON && !best_effort {
+            warn!(
+                "Only {} of {} {} is left, use --best-effort to use it anyway",
+                used, requested, type_name
+            );
+            return abort();
+        }
+        summaries.push(UseSummary {
+            type_id,
+            type_name,
+            requested,
+            used,
+            left: 0.0,
+        });
+    }
+    for summary in &mut summaries {
+        summary.left = staged.type_stats(summary.type_id).quantity;
+    }
+    *inventory = staged;
+    CommandResult::uses(summaries)
+}
+
+/// Converts a quantity argument to a unit of an item type, printing why it can't be.
+fn convert_quantity(
+    quantity: &QuantityArg,
+    unit: Option<&str>,
+    item_type: &ItemType,
+    verb: &str,
+) -> Option<f32> {
+    let converted = quantity.in_unit(unit);
+    if converted.is_none() {
+        match unit {
+            Some(unit) => warn!(
+                "Type '{}' is measured in {}; cannot {} {}",
+                item_type.name, unit, verb, quantity
+            ),
+            None => warn!(
+                "Type '{}' has no unit; cannot {} {}",
+                item_type.name, verb, quantity
+            ),
+        }
+    }
+    converted
+}
+
+/// Converts the quantity argument of a command to the unit of an item instance, printing
+/// why it can't be. `Ok(None)` without a quantity or an item instance.
+fn instance_quantity(
+    quantity: Option<&QuantityArg>,
+    instance_id: InstanceId,
+    inventory: &Inventory,
+    verb: &str,
+) -> Result<Option<f32>, ()> {
+    match (
+        quantity,
+        inventory
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == instance_id),
+    ) {
+        (Some(quantity), Some(ii)) => {
+            let item_type = inventory.item_types.iter().find(|t| t.id == ii.item_type);
+            let unit = ii
+                .unit_override
+                .as_deref()
+                .or_else(|| item_type.and_then(|t| t.unit.as_deref()));
+            let converted = match item_type {
+                Some(item_type) => convert_quantity(quantity, unit, item_type, verb),
+                None => Some(quantity.amount),
+            };
+            converted.map(Some).ok_or(())
+        }
+        _ => Ok(None),
+    }
+}
+
+pub fn top_up(
+    instance_id: InstanceId,
+    quantity: &QuantityArg,
+    expires_at: Option<std::time::SystemTime>,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    let quantity = match instance_quantity(Some(quantity), instance_id, inventory, "top up") {
+        Ok(Some(quantity)) => quantity,
+        _ => return CommandResult::default(),
+    };
+    match inventory.top_up_instance(instance_id, quantity, expires_at) {
+        Ok(total) => CommandResult::messages(vec![total.to_string()]),
+        Err(InventoryError::NotContinuous) => {
+            warn!("The item instance was not created with ci --continuous");
+            CommandResult::default()
+        }
+        Err(InventoryError::FinishedItemInstance) => {
+            warn!("The item instance is in the trash");
+            CommandResult::default()
+        }
+        Err(InventoryError::InvalidQuantity) => {
+            warn!("The quantity to top up has to be more than zero");
+            CommandResult::default()
+        }
+        Err(InventoryError::FractionalQuantityNotAllowed) => {
+            warn!("The item type is counted in whole quantities");
+            CommandResult::default()
+        }
+        Err(_) => {
+            warn!("Could not find an item instance with the specified id");
+            CommandResult::default()
+        }
+    }
+}
+
+/// Puts an item instance in the trash, or only some quantity of it.
+pub fn trash(
+    instance_id: InstanceId,
+    reason: Option<&str>,
+    quantity: Option<&QuantityArg>,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    let quantity = match instance_quantity(quantity, instance_id, inventory, "trash") {
+        Ok(Some(quantity)) => quantity,
+        Ok(None) => {
+            inventory.trash_with_reason(instance_id, reason);
+            return CommandResult::default();
+        }
+        Err(()) => return CommandResult::default(),
+    };
+    match inventory.trash_quantity_with_reason(instance_id, quantity, reason) {
+        Ok(trashed_id) => CommandResult::messages(vec![trashed_id.to_string()]),
+        Err(InventoryError::FinishedItemInstance) => {
+            warn!("The item instance is already in the trash");
+            CommandResult::default()
+        }
+        Err(InventoryError::InvalidQuantity) => {
+            warn!("The quantity to trash has to be more than zero and at most what is left");
+            CommandResult::default()
+        }
+        Err(InventoryError::FractionalQuantityNotAllowed) => {
+            warn!("The item type is counted in whole quantities");
+            CommandResult::default()
+        }
+        Err(_) => {
+            warn!("Could not find an item instance with the specified id");
+            CommandResult::default()
+        }
+    }
+}
+
+pub fn give(
+    instance_id: InstanceId,
+    recipient: Option<String>,
+    quantity: Option<&QuantityArg>,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    let quantity = match instance_quantity(quantity, instance_id, inventory, "give") {
+        Ok(quantity) => quantity,
+        Err(()) => return CommandResult::default(),
+    };
+    match inventory.give(instance_id, quantity, recipient) {
+        Ok(given_id) => CommandResult::messages(vec![given_id.to_string()]),
+        Err(InventoryError::FinishedItemInstance) => {
+            warn!("The item instance is already in the trash");
+            CommandResult::default()
+        }
+        Err(InventoryError::InvalidQuantity) => {
+            warn!("The quantity to give has to be more than zero and at most what is left");
+            CommandResult::default()
+        }
+        Err(InventoryError::FractionalQuantityNotAllowed) => {
+            warn!("The item type is counted in whole quantities");
+            CommandResult::default()
+        }
+        Err(_) => {
+            warn!("Could not find an item instance with the specified id");
+            CommandResult::default()
+        }
+    }
+}
+
+pub fn compact_ids(
+    cmd: &CompactIdsCommand,
+    inventory: &mut Inventory,
+) -> std::result::Result<CommandResult, CliError> {
+    let both = !cmd.types && !cmd.instances;
+    let changes = match inventory.compact_ids(cmd.types || both, cmd.instances || both, cmd.force) {
+        Ok(changes) => changes,
+        Err(InventoryError::CompactStringsIssued { instance_ids }) => {
+            warn!(
+                "Compact strings, like those of QR codes, were made for the item instances {}. \
+                 Their ids would change, use --force to renumber them anyway.",
+                instance_ids
+                    .iter()
+                    .map(|id| id.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            return Ok(CommandResult::default());
+        }
+        Err(_) => return Ok(CommandResult::default()),
+    };
+    if let Some(path) = &cmd.output {
+        save_json(path, &changes)?;
+        return Ok(CommandResult::default());
+    }
+    let types = changes
+        .types
+        .iter()
+        .map(|(old, new)| format!("Item type {} is now {}", old, new));
+    let instances = changes
+        .instances
+        .iter()
+        .map(|(old, new)| format!("Item instance {} is now {}", old, new));
+    Ok(CommandResult::messages(types.chain(instances).collect()))
+}
+
+pub fn use_all(type_id: TypeId, inventory: &mut Inventory) -> CommandResult {
+    if !inventory.item_types.iter().any(|t| t.id == type_id) {
+        warn!("Could not find an item type with the specified id");
+        return CommandResult::default();
+    }
+    match inventory.pick_use_target(type_id).map(|ii| ii.id) {
+        Some(id) => finish(id, inventory),
+        None => {
+            warn!("Could not find an item instance with the specified type id to use (or all items were used.)");
+            CommandResult::default()
+        }
+    }
+}
+
+pub fn finish(instance_id: InstanceId, inventory: &mut Inventory) -> CommandResult {
+    match inventory.finish_instance(instance_id) {
+        Ok(consumed) => CommandResult::messages(vec![consumed.to_string()]),
+        Err(InventoryError::FinishedItemInstance) => {
+            warn!("The item instance is already empty or in the trash");
+            CommandResult::default()
+        }
+        Err(_) => {
+            warn!("Could not find an item instance with the specified id");
+            CommandResult::default()
+        }
+    }
+}
+
+pub fn list_missing(
+    inventory: &Inventory,
+    with_prices: bool,
+    group_by: Option<ShoppingGroup>,
+    vendor: Option<&str>,
+) -> CommandResult {
+    let items = inventory
+        .shortages()
+        .into_iter()
+        .filter(|shortage| vendor.is_none_or(|v| inventory.bought_at(shortage.type_id, v)))
+        .map(|shortage| {
+            let prices = if with_prices {
+                let history = inventory.price_history(shortage.type_id);
+                let recent = &history[history.len().saturating_sub(3)..];
+                Some(Prices {
+                    last_paid: history.last().cloned(),
+                    average_of_last_3: if recent.is_empty() {
+                        None
+                    } else {
+                        Some(recent.iter().map(|p| p.unit_price).sum::<f32>() / recent.len() as f32)
+                    },
+                })
+            } else {
+                None
+            };
+            ShoppingItem { shortage, prices }
+        })
+        .collect::<Vec<_>>();
+    trace!(
+        "list-missing: {} shortages among {} item types",
+        items.len(),
+        inventory.item_types.len()
+    );
+    match group_by {
+        Some(ShoppingGroup::Vendor) => {
+            CommandResult::shopping_groups(inventory.group_by_vendor(items, |i| i.shortage.type_id))
+        }
+        None => CommandResult::shopping_list(items),
+    }
+}
+
+pub fn list_expired(
+    inventory: &Inventory,
+    include_best_before: bool,
+    include_snoozed: bool,
+) -> CommandResult {
+    let now = inventory.now();
+    let mut v = inventory
+        .item_instances
+        .iter()
+        .filter(|t| match t.expiry_state(now) {
+            ExpiryState::Expired => true,
+            ExpiryState::PastBestBefore => include_best_before,
+            ExpiryState::Fresh => false,
+        })
+        .filter(|t| include_snoozed || !inventory.is_snoozed(t, now))
+        .collect::<Vec<_>>();
+    v.sort_by(|a, b| a.cmp_expiry(b));
+    trace!(
+        "list-expired: {} of {} item instances are expired",
+        v.len(),
+        inventory.item_instances.len()
+    );
+    CommandResult::instances(v, now)
+}
+
+/// Replaces a text in the names of the item types or in a text field of the item instances,
+/// see `Inventory::replace_in_instances`, listing what changed, or what would with --dry-run.
+pub fn replace(cmd: &ReplaceCommand, inventory: &mut Inventory) -> CommandResult {
+    if cmd.field == ReplaceField::TypeName && cmd.regex && !cmd.force {
+        warn!("Refusing to rename item types with a regular expression, use --force if that is what you want.");
+        return CommandResult::default();
+    }
+    if cmd.field == ReplaceField::TypeName && cmd.location.is_some() {
+        warn!("--location only applies to the fields of item instances.");
+        return CommandResult::default();
+    }
+    let matcher = if cmd.regex {
+        match TextMatcher::regex(&cmd.from) {
+            Ok(matcher) => matcher,
+            Err(InventoryError::InvalidPattern { message }) => {
+                warn!("Invalid regular expression:\n{}", message);
+                return CommandResult::default();
+            }
+            Err(e) => unreachable!("unexpected error {:?}", e),
+        }
+    } else {
+        TextMatcher::Exact(cmd.from.clone())
+    };
+    let mut preview;
+    let target = if cmd.dry_run {
+        preview = inventory.clone();
+        &mut preview
+    } else {
+        inventory
+    };
+    let (changes, kind) = match cmd.field {
+        ReplaceField::TypeName => {
+            match target.replace_in_types(&matcher, &cmd.to, &cmd.type_query()) {
+                Ok(changes) => (replacements(changes), "item types"),
+                Err(e) => {
+                    let reason = match e {
+                        InventoryError::DuplicateTypeName { type_id } => {
+                            format!("a new name is taken by item type {}", type_id)
+                        }
+                        InventoryError::InvalidName => "a new name is empty".to_string(),
+                        e => instance_error(&e).to_string(),
+                    };
+                    warn!("Nothing was renamed: {}.", reason);
+                    return CommandResult::default();
+                }
+            }
+        }
+        ReplaceField::Instance(field) => (
+            replacements(target.replace_in_instances(
+                field,
+                &matcher,
+                &cmd.to,
+                &cmd.instance_query(),
+            )),
+            "item instances",
+        ),
+    };
+    let summary = match (changes.len(), cmd.dry_run) {
+        (0, _) => "Nothing matches.".to_string(),
+        (count, true) => format!("Would replace in {} {}.", count, kind),
+        (count, false) => format!("Replaced in {} {}.", count, kind),
+    };
+    let mut result = CommandResult::replacements(changes);
+    result.messages.push(summary);
+    result
+}
+
+/// The replacements in the item types or item instances with these ids.
+fn replacements<I: Into<u32>>(changes: Vec<(I, String, String)>) -> Vec<Replacement> {
+    changes
+        .into_iter()
+        .map(|(id, before, after)| Replacement {
+            id: id.into(),
+            before,
+            after,
+        })
+        .collect()
+}
+
+/// Snoozes the item instance or the item type, or unsnoozes it without a snooze.
+pub fn snooze_target(
+    target: &SnoozeTarget,
+    snooze: Option<Snooze>,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    let result = match (&target.instance, target.type_id) {
+        (Some(selector), _) => match resolve_instance(selector, inventory) {
+            Some(id) => inventory.snooze_instance(id, snooze),
+            None => return CommandResult::default(),
+        },
+        (None, Some(type_id)) => inventory.snooze_type(type_id, snooze),
+        (None, None) => return CommandResult::default(),
+    };
+    if result.is_err() {
+        warn!("Could not find an item type with the specified id");
+    }
+    CommandResult::default()
+}
+
+/// The counts of the inventory, what needs attention and the next expiries, printed when
+/// no subcommand is given.
+pub fn dashboard(inventory: &Inventory, inventory_name: &str) -> CommandResult {
+    let now = inventory.now();
+    let present = inventory
+        .item_instances
+        .iter()
+        .filter(|ii| ii.removed_at.is_none())
+        .collect::<Vec<_>>();
+    let expired = present
+        .iter()
+        .filter(|ii| ii.expiry_state(now) == ExpiryState::Expired)
+        .count();
+    let below_minimum = inventory
+        .shortages()
+        .iter()
+        .map(|s| s.type_id)
+        .collect::<std::collections::HashSet<_>>()
+        .len();
+    let mut expiring = present
+        .iter()
+        .filter(|ii| ii.expires_at.is_some_and(|at| at > now))
+        .collect::<Vec<_>>();
+    expiring.sort_by(|a, b| a.cmp_expiry(b));
+    let soonest_expiries = expiring
+        .iter()
+        .take(3)
+        .filter_map(|ii| {
+            Some(DigestEntry {
+                instance_id: ii.id,
+                type_id: ii.item_type,
+                type_name: inventory.type_name(ii.item_type),
+                quantity: ii.quantity,
+                at: ii.expires_at?,
+                opened_at: ii.opened_at,
+            })
+        })
+        .collect();
+    CommandResult::dashboard(Dashboard {
+        inventory: inventory_name.to_string(),
+        types: inventory.item_types.len(),
+        instances: present.len(),
+        expired,
+        below_minimum,
+        soonest_expiries,
+    })
+}
+
+pub fn export_ical(
+    cmd: &ExportIcalCommand,
+    inventory: &Inventory,
+    inventory_name: &str,
+) -> std::result::Result<CommandResult, CliError> {
+    let calendar = ical::render(
+        inventory,
+        inventory_name,
+        inventory.now(),
+        cmd.within.map(|w| w.duration(inventory.now())),
+        cmd.alarm_days,
+    );
+    if let Some(path) = &cmd.output {
+        write(path, calendar).map_err(|e| CliError::io(Operation::Write, path, e))?;
+        Ok(CommandResult::default())
+    } else {
+        Ok(CommandResult::text(calendar))
+    }
+}
+
+pub fn export(
+    cmd: &ExportCommand,
+    inventory: &Inventory,
+    inventory_name: &str,
+) -> std::result::Result<CommandResult, CliError> {
+    let document = match cmd.format {
+        report::Format::Markdown => {
+            report::markdown(inventory, inventory_name, inventory.now(), cmd.group_by)
+        }
+        #[cfg(feature = "xlsx")]
+        report::Format::Xlsx => {
+            let path = match &cmd.output {
+                Some(path) => path,
+                None => {
+                    warn!("The xlsx format can only be written to a file, use --output");
+                    return Ok(CommandResult::default());
+                }
+            };
+            xlsx::workbook(inventory, inventory.now())
+                .map_err(|e| std::io::Error::other(e.to_string()))
+                .and_then(|workbook| write(path, workbook))
+                .map_err(|e| CliError::io(Operation::Write, path, e))?;
+            return Ok(CommandResult::default());
+        }
+    };
+    if let Some(path) = &cmd.output {
+        write(path, document).map_err(|e| CliError::io(Operation::Write, path, e))?;
+        Ok(CommandResult::default())
+    } else {
+        Ok(CommandResult::text(document))
+    }
+}
+
+pub fn graph_locations(
+    inventory: &Inventory,
+    format: graph::Format,
+    output: Option<&Path>,
+) -> std::result::Result<CommandResult, CliError> {
+    let graph = graph::render(inventory, inventory.now(), format);
+    if let Some(path) = output {
+        write(path, graph).map_err(|e| CliError::io(Operation::Write, path, e))?;
+        Ok(CommandResult::default())
+    } else {
+        Ok(CommandResult::text(graph))
+    }
+}
+
+pub fn location_add(
+    name: &str,
+    capacity: Option<f32>,
+    unit: Option<&str>,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    if location::tidy_location(name).is_empty() {
+        warn!("The name of the location can not be empty");
+        return CommandResult::default();
+    }
+    if capacity.is_some_and(|c| c < 0.0) {
+        warn!("The capacity can not be negative");
+        return CommandResult::default();
+    }
+    let location = LocationInfo {
+        name: name.to_string(),
+        capacity,
+        capacity_unit: unit.map(str::to_string),
+    };
+    if let Err(InventoryError::DuplicateLocation) = inventory.add_location(location) {
+        warn!(
+            "The location {} is already registered, use location-set to change it",
+            name
+        );
+    }
+    CommandResult::default()
+}
+
+pub fn location_set(
+    name: &str,
+    capacity: Option<f32>,
+    unit: Option<Option<String>>,
+    inventory: &mut Inventory,
+) -> CommandResult {
+    if capacity.is_some_and(|c| c < 0.0) {
+        warn!("The capacity can not be negative");
+        return CommandResult::default();
+    }
+    let location = match inventory.location_info_mut(name) {
+        Some(location) => location,
+        None => {
+            warn!(
+                "The location {} is not registered, use location-add to register it",
+                name
+            );
+            return CommandResult::default();
+        }
+    };
+    if let Some(capacity) = capacity {
+        location.capacity = Some(capacity).filter(|c| *c > 0.0);
+    }
+    if let Some(unit) = unit {
+        location.capacity_unit = unit;
+    }
+    CommandResult::default()
+}
+
+/// Returns the containers of the item instances. Nothing is returned if one of them can't be.
+pub fn return_deposits(selectors: &[String], inventory: &mut Inventory) -> CommandResult {
+    let mut staged = inventory.clone();
+    let mut refunded = 0.0;
+    for selector in selectors {
+        let id = match resolve_instance(selector, &staged) {
+            Some(id) => id,
+            None => return CommandResult::default(),
+        };
+        match staged.return_deposit(id) {
+            Ok(deposit) => refunded += deposit,
+            Err(_) => {
+                warn!(
+                    "Item instance {} is not in the trash, has no deposit or was already returned",
+                    selector
+                );
+                return CommandResult::default();
+            }
+        }
+    }
+    *inventory = staged;
+    CommandResult::messages(vec![format!("Refunded {}", refunded)])
+}
+
+pub fn write_metrics(
+    inventory: &Inventory,
+    inventory_name: &str,
+    output: Option<&Path>,
+    include_deposits: bool,
+) -> std::result::Result<CommandResult, CliError> {
+    let text = metrics::render(inventory, inventory_name, inventory.now(), include_deposits);
+    if let Some(path) = output {
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(".tmp");
+        write(&tmp, text).map_err(|e| CliError::io(Operation::Write, Path::new(&tmp), e))?;
+        rename(&tmp, path).map_err(|e| CliError::io(Operation::Write, path, e))?;
+        Ok(CommandResult::default())
+    } else {
+        Ok(CommandResult::text(text))
+    }
+}
+
+#[cfg(feature = "grocy")]
+pub fn import_grocy(path: &std::path::Path, inventory: &mut Inventory) -> CommandResult {
+    let summary = grocy::import(path, inventory).expect("Failed to import the Grocy export");
+    CommandResult::messages(summary.lines())
+}
+
+pub fn import_legacy(
+    types: &Path,
+    instances: &Path,
+    inventory: &mut Inventory,
+) -> std::result::Result<CommandResult, CliError> {
+    let records = legacy::import(types, instances, inventory)?;
+    let count = |kind: &str| {
+        records
+            .iter()
+            .filter(|r| r.kind == kind && r.id.is_some())
+            .count()
+    };
+    let remapped = records.iter().filter(|r| r.result == "remapped").count();
+    let skipped = records.iter().filter(|r| r.id.is_none()).count();
+    Ok(CommandResult {
+        messages: vec![format!(
+            "Imported {} item types and {} item instances, {} with a new id, and skipped {} lines.",
+            count("type"),
+            count("instance"),
+            remapped,
+            skipped
+        )],
+        ..CommandResult::legacy_import(records)
+    })
+}
+
+pub fn print_legacy_import(records: &[ImportedRecord], output: Output) {
+    let header = ["kind", "line", "legacy id", "id", "name", "result"];
+    let id = |id: Option<u32>| id.map(|id| id.to_string()).unwrap_or_default();
+    let rows = records
+        .iter()
+        .map(|r| {
+            vec![
+                r.kind.to_string(),
+                r.line.to_string(),
+                id(r.legacy_id),
+                id(r.id),
+                r.name.clone(),
+                r.result.clone(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    if output.minimal {
+        if output.header {
+            output.line(record::join(&header));
+        }
+        rows.iter().for_each(|r| output.line(record::join(r)));
+    } else if !rows.is_empty() {
+        let mut table = Grid::with_header(&header);
+        rows.iter()
+            .for_each(|r| table.add_row(r.iter().map(Field::new).collect()));
+        output.table(&table);
+    }
+}
+
+/// Describes an edit of the event log that could not be replayed.
+fn skipped_edit(edit: &SkippedEdit) -> String {
+    let item = match edit.kind {
+        ItemKind::Type => "item type",
+        ItemKind::Instance => "item instance",
+    };
+    let reason = match edit.reason {
+        SkipReason::Missing => "which doesn't exist",
+        SkipReason::AlreadyExists => "which already exists",
+        SkipReason::UnknownItemType => "whose item type doesn't exist",
+    };
+    format!(
+        "the change of {} {} by {} at {}, {}",
+        item,
+        edit.id,
+        edit.command,
+        humantime::format_rfc3339_seconds(edit.at),
+        reason
+    )
+}
+
+/// How an item of the files differs from the event log, as the change from the log to them.
+fn divergence<T: Identified>(change: &Change<T>) -> &'static str {
+    match (&change.before, &change.after) {
+        (None, _) => "is in the files but not in the event log",
+        (_, None) => "is in the event log but not in the files",
+        _ => "differs between the event log and the files",
+    }
+}
+
+/// Prints the item types and the item instances not removed of a past state of the inventory,
+/// with the expiries as they were then.
+pub fn print_state(state: &PastState, output: Output) {
+    let mut inventory = Inventory::new(state.types.clone(), state.instances.clone());
+    inventory.set_clock(std::sync::Arc::new(FixedClock::new(state.at)));
+    print_item_types(&state.types.iter().collect::<Vec<_>>(), &inventory, output);
+    let instances = state
+        .instances
+        .iter()
+        .filter(|ii| ii.removed_at.is_none())
+        .filter(|ii| state.types.iter().any(|t| t.id == ii.item_type))
+        .collect::<Vec<_>>();
+    print_item_instances(&instances, &inventory, output);
+}
+
+pub fn print_uses(uses: &[UseSummary], output: Output) {
+    let header = ["type id", "name", "requested", "used", "left"];
+    let rows = uses
+        .iter()
+        .map(|u| {
+            vec![
+                u.type_id.to_string(),
+                u.type_name.clone(),
+                u.requested.to_string(),
+                u.used.to_string(),
+                u.left.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    if output.minimal {
+        if output.header {
+            output.line(record::join(&header));
+        }
+        rows.iter().for_each(|r| output.line(record::join(r)));
+    } else {
+        let mut table = Grid::with_header(&header);
+        rows.iter()
+            .for_each(|r| table.add_row(r.iter().map(Field::new).collect()));
+        output.table(&table);
+    }
+}
+
+pub fn print_replacements(replacements: &[Replacement], output: Output) {
+    let header = ["id", "before", "after"];
+    let rows = replacements
+        .iter()
+        .map(|r| vec![r.id.to_string(), r.before.clone(), r.after.clone()])
+        .collect::<Vec<_>>();
+    if output.minimal {
+        if output.header {
+            output.line(record::join(&header));
+        }
+        rows.iter().for_each(|r| output.line(record::join(r)));
+    } else if !rows.is_empty() {
+        let mut table = Grid::with_header(&header);
+        rows.iter()
+            .for_each(|r| table.add_row(r.iter().map(Field::new).collect()));
+        output.table(&table);
+    }
+}
+
+/// Prints the dashboard, as a single json object in minimal mode.
+pub fn print_dashboard(dashboard: &Dashboard, output: Output) {
+    if output.minimal {
+        output.line(serde_json::to_string(dashboard).expect("Failed to serialize the dashboard"));
+        return;
+    }
+    output.line(format!(
+        "Inventory {}: {} item types, {} item instances",
+        dashboard.inventory, dashboard.types, dashboard.instances
+    ));
+    output.line(format!(
+        "{} expired, {} item types below their minimum",
+        dashboard.expired, dashboard.below_minimum
+    ));
+    if !dashboard.soonest_expiries.is_empty() {
+        output.line("Expiring next:");
+        dashboard.soonest_expiries.iter().for_each(|e| {
+            output.line(format!(
+                "  {} {} (instance {}) expires {}",
+                e.quantity,
+                e.type_name,
+                e.instance_id,
+                humantime::format_rfc3339_seconds(e.at)
+            ))
+        });
+    }
+}
+
+fn renamed(from: &str, to: &str, changed: usize) -> String {
+    format!(
+        "Renamed {:?} to {:?} in {} item instances and item types",
+        from, to, changed
+    )
+}
+
+pub fn print_locations(locations: &[LocationMapping], output: Output) {
+    let header = ["location", "instances", "minimums", "rename to"];
+    if output.minimal {
+        if output.header {
+            output.line(record::join(&header));
+        }
+        locations.iter().for_each(|l| {
+            output.line(record::join(&[
+                l.count.location.clone(),
+                l.count.instances.to_string(),
+                l.count.minimums.to_string(),
+                l.rename_to.clone().unwrap_or_default(),
+            ]))
+        });
+    } else {
+        // Quoted, to show the whitespace that differs.
+        let mut table = Grid::with_header(&header);
+        locations.iter().for_each(|l| {
+            table.add_row(fields![
+                format!("{:?}", l.count.location),
+                l.count.instances,
+                l.count.minimums,
+                l.rename_to
+                    .as_ref()
+                    .map(|r| format!("{:?}", r))
+                    .unwrap_or_default(),
+            ])
+        });
+        output.table(&table);
+    }
+}
+
+pub fn print_examples(command: Option<&str>) -> CommandResult {
+    let examples = examples::examples_of(command);
+    if examples.is_empty() {
+        warn!(
+            "There is no subcommand named {}, see inv --help",
+            command.unwrap_or_default()
+        );
+        return CommandResult::default();
+    }
+    CommandResult::text(examples::render(&examples))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn location_minimums_are_parsed() {
+        let parsed = "office=2".parse::<LocationMinimum>().unwrap();
+        assert_eq!(parsed.location, "office");
+        assert_eq!(parsed.minimum, 2.0);
+        let parsed = "a=b = 0.5".parse::<LocationMinimum>().unwrap();
+        assert_eq!(parsed.location, "a=b");
+        assert_eq!(parsed.minimum, 0.5);
+        assert!("office".parse::<LocationMinimum>().is_err());
+        assert!("=2".parse::<LocationMinimum>().is_err());
+        assert!("office=two".parse::<LocationMinimum>().is_err());
+        assert!("office=-1".parse::<LocationMinimum>().is_err());
+        assert_eq!(
+            "office=0,5".parse::<LocationMinimum>().unwrap().minimum,
+            0.5
+        );
+        assert!("office=1,500".parse::<LocationMinimum>().is_err());
+    }
+
+    #[test]
+    fn numbers_accept_a_decimal_comma() {
+        let cmd = CreateTypeCommand::from_iter(&["ct", "rice", "-m", "1,5", "--deposit", "0.25"]);
+        assert_eq!((cmd.minimum_quantity, cmd.deposit), (1.5, Some(0.25)));
+        let cmd = UpdateInstanceCommand::from_iter(&["ui", "3", "-q", "0,5", "-v", "12,99"]);
+        assert_eq!((cmd.quantity, cmd.value), (Some(0.5), Some(12.99)));
+        let error = UpdateInstanceCommand::from_iter_safe(&["ui", "3", "-v", "1,500"]).unwrap_err();
+        assert!(
+            error.message.contains("write 1500 or 1.5"),
+            "{}",
+            error.message
+        );
+        assert!(UpdateTypeCommand::from_iter_safe(&["ut", "1", "-m", "1,5,0"]).is_err());
+        assert!(Manager::from_iter_safe(&["inv", "use", "rice", "1,500g"]).is_err());
+    }
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("inv_main_{}_{}", test, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn inventory_with_a_type() -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("rice".to_string())
+                .build()
+                .unwrap(),
+        );
+        inventory
+    }
+
+    #[test]
+    fn options_take_precedence_over_classes() {
+        use std::time::Duration;
+        let mut fresh = ItemClass::new("fresh");
+        fresh.ttl = Some(Duration::from_secs(5 * 24 * 60 * 60));
+        fresh.opened_by_default = true;
+        let classes = vec![fresh];
+        let mut inventory = Inventory::default();
+
+        let cmd =
+            CreateTypeCommand::from_iter(&["ct", "milk", "--class", "Fresh", "--ttl", "2days"]);
+        create_type(&cmd, &mut inventory, &classes);
+        let milk = &inventory.item_types[0];
+        assert_eq!(milk.class.as_deref(), Some("fresh"));
+        assert_eq!(milk.ttl, Some(Duration::from_secs(2 * 24 * 60 * 60)));
+        assert!(milk.opened_by_default);
+
+        let cmd = UpdateTypeCommand::from_iter(&["ut", "1", "--class", "fresh", "-o", "false"]);
+        update_type(&cmd, &mut inventory, &classes);
+        let milk = &inventory.item_types[0];
+        assert_eq!(milk.ttl, Some(Duration::from_secs(5 * 24 * 60 * 60)));
+        assert!(!milk.opened_by_default);
+
+        let cmd = CreateTypeCommand::from_iter(&["ct", "salt", "--class", "pantry"]);
+        create_type(&cmd, &mut inventory, &classes);
+        assert_eq!(inventory.item_types.len(), 1);
+    }
+
+    #[test]
+    fn seasonal_minimums_are_replaced_together() {
+        let mut inventory = inventory_with_a_type();
+        let update = |args: &[&str], inventory: &mut Inventory| {
+            let mut all = vec!["ut", "1"];
+            all.extend_from_slice(args);
+            update_type(&UpdateTypeCommand::from_iter(&all), inventory, &[]);
+            inventory.item_types[0]
+                .seasonal_minimums
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let summer_and_winter = [
+            "--seasonal",
+            "05-01..09-30=2",
+            "--seasonal",
+            "12-01..01-31=1",
+        ];
+        assert_eq!(
+            update(&summer_and_winter, &mut inventory),
+            vec!["05-01..09-30=2", "12-01..01-31=1"]
+        );
+        let overlapping = [
+            "--seasonal",
+            "12-01..01-31=1",
+            "--seasonal",
+            "01-15..02-15=3",
+        ];
+        assert_eq!(update(&overlapping, &mut inventory).len(), 2);
+        assert_eq!(
+            update(&["--seasonal", "06-01..08-31=4"], &mut inventory),
+            vec!["06-01..08-31=4"]
+        );
+        assert_eq!(update(&["-m", "1"], &mut inventory).len(), 1);
+        assert!(update(&["--clear-seasonal"], &mut inventory).is_empty());
+        assert!(
+            UpdateTypeCommand::from_iter_safe(&["ut", "1", "--seasonal", "06-31..08-31=4"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn narrow_instance_tables_drop_the_extra_serial_and_model_first() {
+        use render::Renderer;
+        let mut inventory = inventory_with_a_type();
+        let rice = inventory.item_types[0].id;
+        let id = inventory
+            .add_item_instance(ItemInstance {
+                item_type: rice,
+                quantity: 2.0,
+                model: Some("basmati".to_string()),
+                serial: Some("SN-1".to_string()),
+                extra: Some("from the market".to_string()),
+                location: Some("pantry".to_string()),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        let mut table = instance_table(false, false);
+        let instance = inventory
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == id)
+            .unwrap();
+        let output = Manager::from_iter(&["inv", "--no-shelf-life"]).output();
+        table.add_row(instance_row(instance, &inventory, output));
+        let columns = |width: usize| {
+            let text = render::Compact { width }.render(&table);
+            text.lines().next().unwrap().to_string()
+        };
+        assert_eq!(
+            columns(200),
+            "id  label    type id  type name  quantity  model    serial  extra            location  value  opened at  expires at  best before"
+        );
+        assert_eq!(
+            columns(110),
+            "id  label    type id  type name  quantity  model    location  value  opened at  expires at  best before"
+        );
+        assert_eq!(
+            columns(100),
+            "id  label    type id  type name  quantity  location  value  opened at  expires at  best before"
+        );
+        assert_eq!(
+            columns(60),
+            "id  label    type name  quantity  location  expires at"
+        );
+    }
+
+    #[test]
+    fn instance_tables_show_the_shelf_life_left() {
+        use render::Renderer;
+        let mut inventory = inventory_with_a_type();
+        let now = inventory.now();
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        for (opened, expires) in [(4, Some(1)), (1, Some(3)), (1, None)] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(1),
+                    opened_at: Some(now - day * opened),
+                    expires_at: expires.map(|e| now + day * e),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        let output = Manager::from_iter(&["inv"]).output();
+        let mut table = instance_table(false, output.shelf_life);
+        let shelf_life = table
+            .columns
+            .iter()
+            .position(|c| c.name == "shelf life")
+            .unwrap();
+        for ii in &inventory.item_instances {
+            table.add_row(instance_row(ii, &inventory, output));
+        }
+        let text = render::Classic.render(&table);
+        assert!(text.contains("[##------] 20%"));
+        assert!(text.contains("[######--] 75%"));
+        let fields = table
+            .rows
+            .iter()
+            .map(|r| &r[shelf_life])
+            .collect::<Vec<_>>();
+        assert_eq!(fields[0].style, Style::Yellow);
+        assert_eq!(fields[1].style, Style::Plain);
+        assert_eq!(fields[2].text, "");
+    }
+
+    #[test]
+    fn names_are_matched_exactly_on_request() {
+        let mut inventory = inventory_with_a_type();
+        for name in ["Rice flour", "味噌", "白味噌"] {
+            inventory.add_item_type(
+                ItemTypeBuilder::default()
+                    .name(name.to_string())
+                    .build()
+                    .unwrap(),
+            );
+        }
+        let names = |args: &[&str]| {
+            let mut all = vec!["rt"];
+            all.extend_from_slice(args);
+            match read_type(&ReadTypeCommand::from_iter(&all), &inventory).listing {
+                Some(result::Listing::Types(types)) => types.into_iter().map(|t| t.name).collect(),
+                _ => vec![],
+            }
+        };
+        assert_eq!(names(&["-n", "RICE"]), vec!["rice", "Rice flour"]);
+        assert_eq!(names(&["-n", "RICE", "--exact"]), vec!["rice"]);
+        assert_eq!(names(&["-n", "味噌"]), vec!["味噌", "白味噌"]);
+        assert_eq!(names(&["-n", "味噌", "--exact"]), vec!["味噌"]);
+        assert!(ReadTypeCommand::from_iter_safe(&["rt", "--exact"]).is_err());
+    }
+
+    #[test]
+    fn type_and_instance_are_created_together() {
+        let mut inventory = Inventory::default();
+        let cmd = CreateTypeCommand::from_iter(&[
+            "ct",
+            "olive oil",
+            "--ttl",
+            "90days",
+            "--with-instance",
+            "--quantity",
+            "0.5",
+            "--location",
+            "pantry",
+        ]);
+        let result = create_type(&cmd, &mut inventory, &[]);
+        assert_eq!(result.created_id, Some(1));
+        assert_eq!(result.messages, vec!["1"]);
+        let ii = &inventory.item_instances[0];
+        assert_eq!((ii.item_type, ii.quantity), (TypeId(1), 0.5));
+        assert_eq!(ii.location.as_deref(), Some("pantry"));
+
+        // Instance options are refused without --with-instance.
+        assert!(CreateTypeCommand::from_iter_safe(&["ct", "rice", "--quantity", "1"]).is_err());
+    }
+
+    #[test]
+    fn invalid_instance_rolls_back_the_type() {
+        let mut inventory = inventory_with_a_type();
+        let before = inventory.clone();
+        let cmd =
+            CreateTypeCommand::from_iter(&["ct", "olive oil", "--with-instance", "--quantity=-1"]);
+        let result = create_type(&cmd, &mut inventory, &[]);
+        assert_eq!(result.created_id, None);
+        let mut result = CommandResult::default();
+        result.record_changes(&before, &inventory);
+        assert!(!result.has_changes());
+    }
+
+    #[test]
+    fn missing_workdir_is_created_on_save() {
+        let workdir = temp_dir("missing").join("nested");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        assert!(!workdir.exists());
+        save_inventory(&inventory_with_a_type(), &types, &instances, Files::Both).unwrap();
+        let (inventory, _, _) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        assert_eq!(inventory.item_types.len(), 1);
+    }
+
+    #[test]
+    fn types_are_designated_by_name_without_asking_when_not_interactive() {
+        let workdir = temp_dir("type_names");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        let mut brown_rice = inventory.item_types[0].clone();
+        brown_rice.name = "brown rice".to_string();
+        inventory.add_item_type(brown_rice);
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.push("--no-interactive");
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        let (result, inventory) = run_args(&["ci", "RICE"]);
+        assert_eq!(result.created_id, Some(1));
+        assert_eq!(inventory.item_instances[0].item_type, TypeId(1));
+        let (result, inventory) = run_args(&["ci", "ric"]);
+        assert_eq!(result.created_id, None);
+        assert_eq!(inventory.item_instances.len(), 1);
+        let (_, inventory) = run_args(&["use", "brown"]);
+        assert_eq!(inventory.item_instances.len(), 1);
+        assert_eq!(inventory.item_instances[0].quantity, 1.0);
+    }
+
+    #[test]
+    fn registered_locations_are_saved_with_the_item_types() {
+        let workdir = temp_dir("location_capacity");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.item_types[0].unit = Some("kg".to_string());
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        let (result, _) = run_args(&["location-add", "Pantry", "-c", "2", "-u", "kg"]);
+        assert!(result.locations_changed);
+        let (result, _) = run_args(&["location-add", " pantry"]);
+        assert!(!result.has_changes());
+        run_args(&["ci", "rice", "-q", "1500g", "-l", "pantry"]);
+        run_args(&["ci", "rice", "-q", "1", "-l", "Cellar"]);
+        let (result, _) = run_args(&["location-list"]);
+        match result.listing {
+            Some(result::Listing::LocationUsage(usage)) => {
+                assert_eq!(usage.len(), 1);
+                assert_eq!(usage[0].used, 1.5);
+                assert!(!usage[0].is_over());
+            }
+            listing => panic!("unexpected listing {:?}", listing),
+        }
+
+        let (_, inventory) = run_args(&["location-set", "PANTRY", "-c", "1", "-u"]);
+        assert_eq!(inventory.locations[0].capacity, Some(1.0));
+        assert_eq!(inventory.locations[0].capacity_unit, None);
+        let (_, inventory) = run_args(&["location-set", "pantry", "-u", "g"]);
+        assert_eq!(inventory.over_capacity()[0].used, 1500.0);
+        let (result, _) = run_args(&["location-set", "cellar", "-c", "1"]);
+        assert!(!result.has_changes());
+        let types_file = read_to_string(workdir.join("home_types.json")).unwrap();
+        assert!(types_file.contains("\"capacity_unit\": \"g\""));
+        assert!(!read_to_string(workdir.join("home_instances.json"))
+            .unwrap()
+            .contains("\"locations\""));
+    }
+
+    #[test]
+    fn custom_fields_round_trip_through_the_files() {
+        let workdir = temp_dir("custom_fields");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        save_inventory(&inventory_with_a_type(), &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let listed = |args: &[&str]| {
+            let mut all = vec!["ri"];
+            all.extend_from_slice(args);
+            match run_args(&all).0.listing {
+                Some(result::Listing::Instances(instances)) => {
+                    instances.iter().map(|ii| ii.id).collect::<Vec<_>>()
+                }
+                listing => panic!("unexpected listing {:?}", listing),
+            }
+        };
+
+        run_args(&[
+            "ut",
+            "1",
+            "--add-field",
+            "grain:text",
+            "--add-field",
+            "harvested:date",
+        ]);
+        let (_, inventory) = run_args(&["ut", "1", "--add-field", "grain:number", "-m", "3"]);
+        assert_eq!(inventory.item_types[0].custom_fields.len(), 2);
+        assert_eq!(inventory.item_types[0].minimum_quantity, 0.0);
+
+        run_args(&[
+            "ci",
+            "rice",
+            "--set",
+            "grain=long",
+            "--set",
+            "harvested=2024-09-01",
+        ]);
+        run_args(&["ci", "rice", "--set", "grain=Short"]);
+        let (result, _) = run_args(&["ci", "rice", "--set", "harvested=autumn"]);
+        assert!(!result.has_changes());
+        let (_, inventory) = run_args(&["ui", "2", "--set", "grain=short", "-l", "shelf"]);
+        assert_eq!(
+            inventory.item_instances[1].custom["grain"],
+            FieldValue::Text("short".to_string())
+        );
+        let (_, inventory) = run_args(&["ui", "2", "--set", "colour=white", "-l", "cellar"]);
+        assert_eq!(
+            inventory.item_instances[1].location.as_deref(),
+            Some("shelf")
+        );
+
+        assert_eq!(listed(&["--where", "grain=LONG"]), vec![InstanceId(1)]);
+        assert_eq!(
+            listed(&["--where", "harvested>=2024-01-01"]),
+            vec![InstanceId(1)]
+        );
+        assert_eq!(
+            listed(&["--where", "grain!=long", "--where", "harvested<2025-01-01"]),
+            Vec::<InstanceId>::new()
+        );
+        assert_eq!(
+            listed(&["--where", "grain>a"]),
+            vec![InstanceId(1), InstanceId(2)]
+        );
+
+        let types_file = read_to_string(workdir.join("home_types.json")).unwrap();
+        assert!(types_file.contains("\"kind\": \"date\""));
+        let (_, inventory) = run_args(&["ui", "1", "--set", "harvested="]);
+        assert_eq!(
+            inventory.item_instances[0]
+                .custom
+                .keys()
+                .collect::<Vec<_>>(),
+            vec!["grain"]
+        );
+    }
+
+    #[test]
+    fn opened_at_counts_back_from_now() {
+        use std::time::{Duration, SystemTime};
+        let workdir = temp_dir("opened_at");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        run_args(&["ct", "milk", "--ttl", "5days", "-o", "true"]);
+        let day = Duration::from_secs(24 * 60 * 60);
+
+        let before = SystemTime::now();
+        let (_, inventory) = run_args(&["ci", "milk", "--opened-at", "3d"]);
+        let after = SystemTime::now();
+        let milk = &inventory.item_instances[0];
+        let opened_at = milk.opened_at.unwrap();
+        assert!(before - day * 3 <= opened_at && opened_at <= after - day * 3);
+        assert_eq!(milk.expires_at, Some(opened_at + day * 5));
+        assert_eq!(milk.added_at, Some(opened_at));
+
+        let (_, inventory) = run_args(&["ci", "milk", "--opened-at", "2024-01-31 08:00:00"]);
+        let opened_at = humantime::parse_rfc3339_weak("2024-01-31 08:00:00").unwrap();
+        assert_eq!(inventory.item_instances[1].opened_at, Some(opened_at));
+        assert_eq!(
+            inventory.item_instances[1].expires_at,
+            Some(opened_at + day * 5)
+        );
+
+        let (_, inventory) = run_args(&["ui", "2", "--opened-at", "1day"]);
+        let opened_at = inventory.item_instances[1].opened_at.unwrap();
+        assert!(opened_at <= SystemTime::now() - day && opened_at >= before - day);
+    }
+
+    #[test]
+    fn replace_previews_then_changes_the_fields() {
+        let workdir = temp_dir("replace");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        save_inventory(&inventory_with_a_type(), &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let replacements = |result: &CommandResult| match &result.listing {
+            Some(result::Listing::Replacements(replacements)) => replacements.clone(),
+            listing => panic!("unexpected listing {:?}", listing),
+        };
+        run_args(&["ci", "rice", "-m", "Bosh 1800"]);
+        run_args(&["ci", "rice", "-m", "Bosh GSR"]);
+
+        let args = [
+            "replace", "--field", "model", "--from", "Bosh", "--to", "Bosch",
+        ];
+        let (result, inventory) = run_args(&[&args[..], &["--dry-run"]].concat());
+        assert_eq!(replacements(&result).len(), 2);
+        assert_eq!(result.messages, vec!["Would replace in 2 item instances."]);
+        assert!(!result.has_changes());
+        assert_eq!(
+            inventory.item_instances[0].model.as_deref(),
+            Some("Bosh 1800")
+        );
+
+        let (result, inventory) = run_args(&args);
+        assert_eq!(
+            replacements(&result)[0],
+            Replacement {
+                id: 1,
+                before: "Bosh 1800".to_string(),
+                after: "Bosch 1800".to_string(),
+            }
+        );
+        assert_eq!(result.instance_changes.len(), 2);
+        assert_eq!(
+            inventory.item_instances[1].model.as_deref(),
+            Some("Bosch GSR")
+        );
+
+        // Invalid patterns and regular expressions on names are refused.
+        let (result, _) = run_args(&[
+            "replace", "--regex", "--field", "model", "--from", "(", "--to", "x",
+        ]);
+        assert!(result.listing.is_none());
+        let regex = ["replace", "--regex", "--from", "^(r)ice", "--to", "${1}ed"];
+        let (result, _) = run_args(&[&regex[..], &["--field", "name"]].concat());
+        assert!(result.listing.is_none());
+        let (result, inventory) = run_args(&[&regex[..], &["--field", "name", "--force"]].concat());
+        assert_eq!(result.messages, vec!["Replaced in 1 item types."]);
+        assert_eq!(inventory.item_types[0].name, "red");
+    }
+
+    #[test]
+    fn idle_types_are_deleted_once_confirmed() {
+        let workdir = temp_dir("list_idle");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        save_inventory(&inventory_with_a_type(), &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec![
+                "inv",
+                "-w",
+                workdir.to_str().unwrap(),
+                "-n",
+                "home",
+                "--no-interactive",
+            ];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let idle = |args: &[&str]| {
+            let mut all = vec!["list-idle"];
+            all.extend_from_slice(args);
+            match run_args(&all).0.listing {
+                Some(result::Listing::Idle(records)) => records
+                    .iter()
+                    .map(|r| (r.kind.clone(), r.id))
+                    .collect::<Vec<_>>(),
+                listing => panic!("unexpected listing {:?}", listing),
+            }
+        };
+
+        run_args(&["ct", "flour"]);
+        run_args(&["ci", "rice"]);
+        assert_eq!(idle(&[]), vec![("type".to_string(), 2)]);
+        assert_eq!(
+            idle(&["--for", "0s"]),
+            vec![
+                ("type".to_string(), 1),
+                ("type".to_string(), 2),
+                ("instance".to_string(), 1)
+            ]
+        );
+
+        let (result, inventory) = run_args(&["list-idle", "--delete"]);
+        assert!(!result.has_changes());
+        assert_eq!(inventory.item_types.len(), 2);
+        let (result, inventory) = run_args(&["list-idle", "--delete", "--yes"]);
+        assert!(result.has_changes());
+        assert_eq!(inventory.item_types.len(), 1);
+        assert_eq!(inventory.item_types[0].name, "rice");
+    }
+
+    #[test]
+    fn quantities_are_converted_to_the_unit_of_the_item_type() {
+        let workdir = temp_dir("quantity_units");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.item_types[0].unit = Some("kg".to_string());
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        let (_, inventory) = run_args(&["ci", "rice", "-q", "1.5kg"]);
+        assert_eq!(inventory.item_instances[0].quantity, 1.5);
+        let (_, inventory) = run_args(&["use", "rice", "500g"]);
+        assert_eq!(inventory.item_instances[0].quantity, 1.0);
+        let (_, inventory) = run_args(&["use", "rice", "0.25"]);
+        assert_eq!(inventory.item_instances[0].quantity, 0.75);
+        let (result, inventory) = run_args(&["use", "rice", "500ml"]);
+        assert!(!result.has_changes());
+        assert_eq!(inventory.item_instances[0].quantity, 0.75);
+        let (result, _) = run_args(&["ci", "rice", "-q", "2l"]);
+        assert_eq!(result.created_id, None);
+        let (_, inventory) = run_args(&["ci", "rice", "-q", "0.5kg", "--unit", "g"]);
+        assert_eq!(inventory.item_instances[1].quantity, 500.0);
+        assert!(Manager::from_iter_safe(&["inv", "use", "rice", "lots"]).is_err());
+    }
+
+    #[test]
+    fn locations_are_normalized_from_a_mapping_file() {
+        let workdir = temp_dir("normalize_locations");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        for location in ["pantry", "pantry", "Pantry", "pantery "] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(1),
+                    location: Some(location.to_string()),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.push("--no-interactive");
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        for args in [
+            &["normalize-locations", "--dry-run"][..],
+            &["normalize-locations"],
+        ] {
+            let (result, _) = run_args(args);
+            assert!(!result.has_changes());
+            let proposed = match result.listing {
+                Some(result::Listing::Locations(locations)) => locations
+                    .into_iter()
+                    .map(|l| (l.count.location, l.count.instances, l.rename_to))
+                    .collect::<Vec<_>>(),
+                _ => panic!("Expected locations"),
+            };
+            let pantry = Some("pantry".to_string());
+            assert_eq!(
+                proposed,
+                vec![
+                    ("Pantry".to_string(), 1, pantry.clone()),
+                    ("pantery ".to_string(), 1, pantry),
+                    ("pantry".to_string(), 2, None),
+                ]
+            );
+        }
+
+        let mapping = workdir.join("renames.json");
+        write(&mapping, r#"{"Pantry": "pantry", "pantery ": "pantry"}"#).unwrap();
+        let (result, inventory) =
+            run_args(&["normalize-locations", "--apply", mapping.to_str().unwrap()]);
+        assert_eq!(result.instance_changes.len(), 2);
+        assert!(inventory
+            .item_instances
+            .iter()
+            .all(|ii| ii.location.as_deref() == Some("pantry")));
+        let (result, _) = run_args(&["normalize-locations"]);
+        assert_eq!(
+            result.messages,
+            vec!["The location names are already normalized."]
+        );
+    }
+
+    #[test]
+    fn delivered_reminders_are_saved() {
+        use std::time::Duration;
+        let workdir = temp_dir("mark_reminded");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.item_types[0].remind_after_open = Some(Duration::from_secs(24 * 60 * 60));
+        let mut ii = ItemInstance {
+            item_type: TypeId(1),
+            ..ItemInstance::default()
+        };
+        ii.opened_at = Some(current_time() - Duration::from_secs(2 * 24 * 60 * 60));
+        inventory.add_item_instance(ii).unwrap();
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let reminders = |result: CommandResult| match result.listing {
+            Some(result::Listing::Digest(digest)) => digest.opened_reminders.len(),
+            _ => panic!("Expected a digest"),
+        };
+
+        let (result, _) = run_args(&["digest"]);
+        assert!(!result.has_changes());
+        assert_eq!(reminders(result), 1);
+        let (result, inventory) = run_args(&["digest", "--mark-reminded"]);
+        assert_eq!(result.instance_changes.len(), 1);
+        assert!(inventory.item_instances[0].last_reminded_at.is_some());
+        assert_eq!(reminders(result), 1);
+        let (result, _) = run_args(&["digest", "--mark-reminded"]);
+        assert!(!result.has_changes());
+        assert_eq!(reminders(result), 0);
+    }
+
+    #[test]
+    fn health_is_weighted_by_the_settings_file() {
+        let workdir = temp_dir("health");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                location: Some("pantry".to_string()),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap().0
+        };
+        let health = |result: CommandResult| match result.listing {
+            Some(result::Listing::Health(report)) => report,
+            Some(result::Listing::Digest(digest)) => digest.health.unwrap(),
+            _ => panic!("Expected a health report"),
+        };
+
+        let report = health(run_args(&["health"]));
+        assert_eq!(report.types_with_ttl.set, 0);
+        assert_eq!(report.instances_with_location.percent(), 100.0);
+        assert_eq!(report.stale_instances, 0);
+        // (0 + 0 + 0 + 100 + 0 + 0 + 100) / 7
+        assert!((report.score - 200.0 / 7.0).abs() < 0.001);
+        assert_eq!(report.most_incomplete[0].kind, "type");
+
+        write(
+            config::config_path(&workdir, "home"),
+            r#"{"health_weights": {"type_ttl": 0, "type_minimum": 0, "type_unit": 0, "instance_value": 2}}"#,
+        )
+        .unwrap();
+        let report = health(run_args(&["health", "--top", "1"]));
+        // (100 + 0 + 0 * 2 + 100) / 5
+        assert!((report.score - 40.0).abs() < 0.001);
+        assert_eq!(report.most_incomplete.len(), 1);
+        assert_eq!(report.most_incomplete[0].missing, vec!["expiry", "value"]);
+        assert_eq!(
+            health(run_args(&["digest", "--health"])).score,
+            report.score
+        );
+        assert!(run_args(&["digest"]).listing.is_some_and(|l| match l {
+            result::Listing::Digest(digest) => digest.health.is_none(),
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn part_of_an_instance_is_trashed_in_its_unit() {
+        let workdir = temp_dir("trash_quantity");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.item_types[0].unit = Some("kg".to_string());
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                quantity: 2.0,
+                value: Some(10.0),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let all = [
+            "inv",
+            "-w",
+            workdir.to_str().unwrap(),
+            "-n",
+            "home",
+            "trash",
+            "1",
+            "-q",
+            "500g",
+        ];
+        let (result, inventory) = run(&Manager::from_iter(&all)).unwrap();
+        assert_eq!(result.messages, vec!["2"]);
+        let quantities = inventory
+            .item_instances
+            .iter()
+            .map(|ii| (ii.id, ii.quantity, ii.removed_at.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            quantities,
+            vec![(InstanceId(1), 1.5, false), (InstanceId(2), 0.5, true)]
+        );
+    }
+
+    #[test]
+    fn instances_are_trashed_by_filter_once_confirmed() {
+        let workdir = temp_dir("trash_where");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        for location in ["fridge", "pantry", "fridge"] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(1),
+                    location: Some(location.to_string()),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(&["--no-interactive", "trash-where"]);
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        for args in [&[][..], &["--reason", "broken"], &["-l", "fridge"]] {
+            let (result, _) = run_args(args);
+            assert!(!result.has_changes(), "{:?}", args);
+        }
+        let (result, inventory) = run_args(&["-l", "fridge", "--reason", "broken", "--yes"]);
+        match result.listing {
+            Some(result::Listing::Instances(trashed)) => {
+                assert_eq!(
+                    trashed.iter().map(|ii| ii.id).collect::<Vec<_>>(),
+                    vec![InstanceId(1), InstanceId(3)]
+                )
+            }
+            other => panic!("expected instances, got {:?}", other),
+        }
+        let reasons = inventory
+            .item_instances
+            .iter()
+            .map(|ii| ii.removal_reason.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(reasons, vec![Some("broken"), None, Some("broken")]);
+
+        let (result, inventory) = run_args(&["--all", "--yes"]);
+        assert_eq!(result.instance_changes.len(), 1);
+        assert!(inventory
+            .item_instances
+            .iter()
+            .all(|ii| ii.removed_at.is_some()));
+    }
+
+    #[test]
+    fn listings_survive_item_instances_whose_item_type_is_gone() {
+        let workdir = temp_dir("orphans");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        for item_type in [1, 1] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(item_type),
+                    location: Some("pantry".to_string()),
+                    home_location: Some("fridge".to_string()),
+                    value: Some(2.0),
+                    expires_at: Some(std::time::SystemTime::UNIX_EPOCH),
+                    opened_at: Some(std::time::SystemTime::UNIX_EPOCH),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        // Deleted by editing the files, without deleting its item instances.
+        inventory.item_instances[1].item_type = TypeId(7);
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+
+        let commands: &[&[&str]] = &[
+            &[],
+            &["rt"],
+            &["ri"],
+            &["ri", "-i", "2"],
+            &["ri", "-g", "type"],
+            &["ri", "-g", "location"],
+            &["ri", "-e"],
+            &["list-expired", "--include-best-before"],
+            &["list-missing", "--with-prices"],
+            &["digest"],
+            &["prices", "7"],
+            &["misplaced"],
+            &["list-returnables"],
+            &["waste-report"],
+            &["export"],
+            &["export", "-g", "type-class"],
+            &["export-ical"],
+            &["metrics", "--include-deposits"],
+            &["publish", "-o", "/dev/null"],
+        ];
+        for args in commands {
+            for format in [&[][..], &["--minimal"], &["--machine"]] {
+                let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+                all.extend_from_slice(&["--no-interactive", "--renderer", "classic"]);
+                all.extend_from_slice(format);
+                all.extend_from_slice(args);
+                let manager = Manager::from_iter(&all);
+                let (result, inventory) = run(&manager).unwrap();
+                manager.print(&result, &inventory);
+            }
+        }
+    }
+
+    #[test]
+    fn repl_sessions_run_every_line_on_one_inventory() {
+        let workdir = temp_dir("repl");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        save_inventory(&inventory_with_a_type(), &types, &instances, Files::Both).unwrap();
+        let script = [
+            "# a comment",
+            "ci rice -q 2 -l \"top shelf\"",
+            "bogus",
+            "ci \"unclosed",
+            "repl",
+            "export -o /nonexistent/directory/report.md",
+            "save",
+            "ui 1 -q 5",
+            "reload",
+            "--minimal ri -t 1",
+            "ct beans",
+            "quit",
+            "ct never",
+        ]
+        .join("\n");
+        let all = ["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+        let manager = Manager::from_iter(all.iter().chain(&["--no-interactive", "repl"]));
+        let (result, _) = run_with(&manager, std::io::Cursor::new(script), Vec::new()).unwrap();
+        assert_eq!(result.command, "repl");
+
+        let (inventory, _, _) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let names = inventory
+            .item_types
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["rice", "beans"]);
+        assert_eq!(inventory.item_instances.len(), 1);
+        assert_eq!(inventory.item_instances[0].quantity, 2.0);
+        assert_eq!(
+            inventory.item_instances[0].location.as_deref(),
+            Some("top shelf")
+        );
+        let events = read_to_string(events::events_path(&workdir, "home", false)).unwrap();
+        assert!(events.contains("\"command\":\"repl\""), "{}", events);
+
+        let manager = Manager::from_iter(all.iter().chain(&["--stdout", "repl"]));
+        let mut output = vec![];
+        run_with(&manager, "ct ignored".as_bytes(), &mut output).unwrap();
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn instances_are_filtered_by_when_they_were_added_and_trashed() {
+        let workdir = temp_dir("added_since");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        let now = current_time();
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        for (added, removed) in [(10, None), (1, None), (20, Some(3))] {
+            let id = inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(1),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+            let ii = inventory
+                .item_instances
+                .iter_mut()
+                .find(|ii| ii.id == id)
+                .unwrap();
+            ii.added_at = Some(now - day * added);
+            ii.removed_at = removed.map(|days| now - day * days);
+        }
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let ids = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            match run(&Manager::from_iter(&all)).unwrap().0.listing {
+                Some(result::Listing::Instances(instances)) => {
+                    instances.iter().map(|ii| ii.id).collect::<Vec<_>>()
+                }
+                other => panic!("expected instances, got {:?}", other),
+            }
+        };
+
+        assert_eq!(ids(&["ri", "--added-since", "2days"]), vec![InstanceId(2)]);
+        assert_eq!(
+            ids(&["ri", "--added-since", "1 month"]),
+            vec![InstanceId(1), InstanceId(2)]
+        );
+        assert_eq!(ids(&["list-trashed"]), vec![InstanceId(3)]);
+        assert_eq!(
+            ids(&["list-trashed", "--removed-since", "1 week"]),
+            vec![InstanceId(3)]
+        );
+        assert!(ids(&["list-trashed", "--removed-since", "1day"]).is_empty());
+    }
+
+    #[test]
+    fn published_inventories_can_only_be_read() {
+        let workdir = temp_dir("publish");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                value: Some(4.0),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let published = workdir.join("shared.json");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all))
+        };
+
+        let output = published.to_str().unwrap();
+        run_args(&["publish", "-o", output, "--redact", "value,serial"]).unwrap();
+        let (result, inventory) = run_args(&["--from-published", output, "ri"]).unwrap();
+        assert!(!result.has_changes());
+        assert_eq!(inventory.item_types[0].name, "rice");
+        assert_eq!(inventory.item_instances[0].value, None);
+
+        for args in [
+            &["ui", "1", "-q", "3"][..],
+            &["trash", "1"],
+            &["digest", "--mark-reminded"],
+        ] {
+            let mut all = vec!["--from-published", output];
+            all.extend_from_slice(args);
+            match run_args(&all) {
+                Err(e) => assert!(e.is_read_only(), "{:?}: {}", args, e),
+                Ok(_) => panic!("{:?} was accepted", args),
+            }
+        }
+        assert_eq!(
+            load_inventory(&workdir, "home", Files::Both, false)
+                .unwrap()
+                .0
+                .item_instances[0]
+                .value,
+            Some(4.0)
+        );
+    }
+
+    #[test]
+    fn deleted_types_move_their_instances_when_asked() {
+        let workdir = temp_dir("dt_reassign");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.add_item_type(inventory.item_types[0].clone());
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(2),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        let (result, inventory) = run_args(&["dt", "2", "--reassign-to", "3"]);
+        assert!(!result.has_changes());
+        assert_eq!(inventory.item_types.len(), 2);
+
+        let (result, inventory) = run_args(&["dt", "2", "--reassign-to", "1"]);
+        assert_eq!(
+            result.messages,
+            vec!["Moved 1 item instances to item type 1".to_string()]
+        );
+        assert_eq!(inventory.item_types.len(), 1);
+        assert_eq!(inventory.item_instances[0].item_type, TypeId(1));
+    }
+
+    #[test]
+    fn several_item_types_are_used_all_or_nothing() {
+        let workdir = temp_dir("use_several");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        let mut flour = inventory.item_types[0].clone();
+        flour.name = "flour".to_string();
+        flour.unit = Some("kg".to_string());
+        inventory.add_item_type(flour);
+        for (item_type, quantity) in [(1, 3.0), (2, 1.0)] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(item_type),
+                    quantity,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.push("--no-interactive");
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let quantities = |inventory: &Inventory| {
+            inventory
+                .item_instances
+                .iter()
+                .map(|ii| ii.quantity)
+                .collect::<Vec<_>>()
+        };
+
+        for args in [
+            &["use", "rice:1", "flour:2kg"][..],
+            &["use", "rice:1", "pasta:1"],
+            &["use", "--item", "rice=1", "--item", "flour=2l"],
+        ] {
+            let (result, inventory) = run_args(args);
+            assert!(!result.has_changes(), "{:?}", args);
+            assert_eq!(quantities(&inventory), vec![3.0, 1.0]);
+        }
+
+        let (result, inventory) = run_args(&["use", "rice:2", "--item", "flour=250g"]);
+        assert_eq!(quantities(&inventory), vec![1.0, 0.75]);
+        let summary = match result.listing {
+            Some(result::Listing::Uses(uses)) => uses,
+            _ => panic!("Expected uses"),
+        };
+        assert_eq!(
+            summary.iter().map(|u| (u.used, u.left)).collect::<Vec<_>>(),
+            vec![(2.0, 1.0), (0.25, 0.75)]
+        );
+
+        let (_, inventory) = run_args(&["use", "rice:2", "flour:0.25", "--best-effort"]);
+        assert_eq!(quantities(&inventory), vec![0.0, 0.5]);
+        assert!(Manager::from_iter_safe(&["inv", "use", "rice:1", "2"]).is_ok());
+        assert!(Manager::from_iter_safe(&["inv", "use", "--item", "rice"]).is_err());
+        assert!(
+            Manager::from_iter_safe(&["inv", "use", "rice", "--all", "--item", "a=1"]).is_err()
+        );
+        assert!(Manager::from_iter_safe(&["inv", "use"]).is_err());
+    }
+
+    #[test]
+    fn icons_are_set_from_a_map_and_published() {
+        let workdir = temp_dir("icons");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let icons = |inventory: &Inventory| {
+            inventory
+                .item_types
+                .iter()
+                .map(|t| t.icon.clone().unwrap_or_default())
+                .collect::<Vec<_>>()
+        };
+
+        run_args(&["ct", "milk"]);
+        run_args(&["ct", "oat milk", "--icon", "dairy/oat.png"]);
+        run_args(&["ct", "rice"]);
+        let map = workdir.join("icons.json");
+        write(
+            &map,
+            r#"{"*MILK*": "🥛", "ric?": "grains", "*oat milk*": "🌾"}"#,
+        )
+        .unwrap();
+        let map = map.to_str().unwrap();
+
+        let (result, inventory) = run_args(&["set-icons-from-map", map, "--dry-run"]);
+        assert!(!result.has_changes());
+        match result.listing {
+            Some(result::Listing::TypeUpdates(changes)) => assert_eq!(changes.len(), 2),
+            _ => panic!("set-icons-from-map lists the changes"),
+        }
+        assert_eq!(icons(&inventory), vec!["", "dairy/oat.png", ""]);
+        let (_, inventory) = run_args(&["set-icons-from-map", map]);
+        assert_eq!(icons(&inventory), vec!["🥛", "dairy/oat.png", "grains"]);
+        let (_, inventory) = run_args(&["set-icons-from-map", map, "--overwrite"]);
+        assert_eq!(icons(&inventory), vec!["🥛", "🌾", "grains"]);
+        assert_eq!(type_label(&inventory.item_types[0]), "🥛 milk");
+        assert_eq!(type_label(&inventory.item_types[2]), "rice");
+
+        let published = workdir.join("shared.json");
+        run_args(&["publish", "-o", published.to_str().unwrap()]);
+        let (_, inventory) = run_args(&["--from-published", published.to_str().unwrap(), "rt"]);
+        assert_eq!(icons(&inventory), vec!["🥛", "🌾", "grains"]);
+
+        let (_, inventory) = run_args(&["ut", "1", "--icon"]);
+        assert_eq!(inventory.item_types[0].icon, None);
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn value_report_depreciates_durable_items() {
+        let workdir = temp_dir("value_report");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        run_args(&["ct", "drill", "--depreciation-years", "2,5"]);
+        run_args(&["ct", "rice"]);
+        run_args(&["ci", "drill", "-v", "100"]);
+        run_args(&["ci", "rice", "-v", "3"]);
+        let (_, mut inventory) = run_args(&["ci", "rice"]);
+        assert_eq!(inventory.item_types[0].depreciation_years, Some(2.5));
+
+        let now = inventory.now();
+        inventory.item_instances[0].added_at = Some(now - value::YEAR);
+        let report = Manager::from_iter(&["inv", "value-report", "--depreciated"]);
+        match report.exec(&mut inventory).unwrap().listing {
+            Some(result::Listing::Values(report)) => {
+                assert!(report.depreciated);
+                let values = report
+                    .types
+                    .iter()
+                    .map(|v| (v.type_id, v.instances, v.value, v.current_value.round()))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    values,
+                    vec![(TypeId(1), 1, 100.0, 60.0), (TypeId(2), 1, 3.0, 3.0)]
+                );
+            }
+            _ => panic!("value-report lists the values per item type"),
+        }
+
+        let (_, inventory) = run_args(&["ut", "1", "--depreciation-years", "0"]);
+        assert_eq!(inventory.item_types[0].depreciation_years, None);
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn recompute_expiry_of_an_unknown_item_type_is_a_warning() {
+        let workdir = temp_dir("recompute_unknown");
+        let args = [
+            "inv",
+            "-w",
+            workdir.to_str().unwrap(),
+            "recompute-expiry",
+            "99",
+        ];
+        let (result, _) = run(&Manager::from_iter(&args)).unwrap();
+        assert_eq!(result.messages, Vec::<String>::new());
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn tco_reports_one_or_every_item_type() {
+        let workdir = temp_dir("tco");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        run_args(&["ct", "rice"]);
+        run_args(&["ct", "salad"]);
+        run_args(&["ci", "rice", "-q", "2", "-v", "4"]);
+        run_args(&["ci", "salad", "-q", "4", "-v", "8"]);
+        run_args(&["use", "rice", "1"]);
+        run_args(&["use", "salad", "1"]);
+        run_args(&["trash", "2", "--reason", "expired"]);
+        let costs = |args: &[&str]| match run_args(args).0.listing {
+            Some(result::Listing::Costs(costs)) => costs
+                .iter()
+                .map(|c| (c.type_id.0, c.cost_per_unit, c.waste_percent))
+                .collect::<Vec<_>>(),
+            other => panic!("unexpected listing {:?}", other),
+        };
+        assert_eq!(
+            costs(&["tco", "--all"]),
+            vec![(2, Some(8.0), Some(75.0)), (1, Some(2.0), Some(0.0))]
+        );
+        assert_eq!(costs(&["tco", "rice"]), vec![(1, Some(2.0), Some(0.0))]);
+        assert!(Manager::from_iter_safe(&["inv", "tco"]).is_err());
+        assert!(Manager::from_iter_safe(&["inv", "tco", "rice", "--all"]).is_err());
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn snooze_and_unsnooze_have_their_own_description() {
+        for (command, description) in [
+            ("snooze", "Leave an item instance"),
+            ("unsnooze", "Report a snoozed item instance"),
+        ] {
+            let help = Manager::from_iter_safe(&["inv", command, "--help"])
+                .unwrap_err()
+                .message;
+            assert!(help.contains(description), "{}", help);
+        }
+    }
+
+    #[test]
+    fn snoozed_item_instances_are_left_out_until_their_snooze_ends() {
+        use std::sync::Arc;
+        use std::time::{Duration, SystemTime};
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let day = Duration::from_secs(24 * 60 * 60);
+        let mut inventory = inventory_with_a_type();
+        inventory.set_clock(Arc::new(FixedClock::new(start)));
+        let mut vinegar = inventory.item_types[0].clone();
+        vinegar.name = "vinegar".to_string();
+        inventory.add_item_type(vinegar);
+        for item_type in [1, 1, 2] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(item_type),
+                    expires_at: Some(start - day),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        let exec = |args: &[&str], inventory: &mut Inventory| {
+            let mut all = vec!["inv"];
+            all.extend_from_slice(args);
+            Manager::from_iter(&all).exec(inventory).unwrap()
+        };
+        let expired = |args: &[&str], inventory: &mut Inventory| match exec(args, inventory).listing
+        {
+            Some(result::Listing::Instances(instances)) => {
+                instances.iter().map(|l| l.id).collect::<Vec<_>>()
+            }
+            _ => panic!("list-expired lists item instances"),
+        };
+
+        exec(&["snooze", "1", "--for", "30days"], &mut inventory);
+        exec(&["snooze", "--type-id", "2", "--forever"], &mut inventory);
+        assert_eq!(
+            expired(&["list-expired"], &mut inventory),
+            vec![InstanceId(2)]
+        );
+        assert_eq!(
+            expired(&["list-expired", "--include-snoozed"], &mut inventory),
+            vec![InstanceId(1), InstanceId(2), InstanceId(3)]
+        );
+        assert_eq!(
+            inventory.item_instances[0].snoozed_until,
+            Some(start + 30 * day)
+        );
+
+        inventory.set_clock(Arc::new(FixedClock::new(start + 30 * day)));
+        assert_eq!(
+            expired(&["list-expired"], &mut inventory),
+            vec![InstanceId(1), InstanceId(2)]
+        );
+        exec(&["unsnooze", "--type-id", "2"], &mut inventory);
+        assert_eq!(
+            expired(&["list-expired"], &mut inventory),
+            vec![InstanceId(1), InstanceId(2), InstanceId(3)]
+        );
+    }
+
+    #[test]
+    fn profiles_fill_the_options_not_given() {
+        let workdir = temp_dir("profiles");
+        let profile = profiles::Profile {
+            workdir: Some(workdir.clone()),
+            name: Some("cottage".to_string()),
+            format: Some(profiles::Format::Machine),
+        };
+        let mut manager = Manager::from_iter(&["inv", "ct", "firewood"]);
+        manager.use_profile("cottage", &profile, false);
+        assert_eq!(manager.workdir.as_ref(), Some(&workdir));
+        assert_eq!(manager.inventory_name, "cottage");
+        assert!(manager.machine && !manager.minimal);
+        let (result, _) = run(&manager).unwrap();
+        assert_eq!(result.profile.as_deref(), Some("cottage"));
+        assert!(workdir.join("cottage_types.json").exists());
+
+        let other = temp_dir("profiles_flags");
+        let mut manager = Manager::from_iter(&[
+            "inv",
+            "-w",
+            other.to_str().unwrap(),
+            "-n",
+            "home",
+            "--minimal",
+            "rt",
+        ]);
+        manager.use_profile("cottage", &profile, true);
+        assert_eq!(manager.workdir.as_ref(), Some(&other));
+        assert_eq!(manager.inventory_name, "home");
+        assert!(manager.minimal && !manager.machine);
+        let (result, _) = run(&Manager::from_iter(&[
+            "inv",
+            "-w",
+            other.to_str().unwrap(),
+            "rt",
+        ]))
+        .unwrap();
+        assert_eq!(result.profile, None);
+        remove_dir_all(&workdir).unwrap();
+        remove_dir_all(&other).unwrap();
+    }
+
+    #[test]
+    fn local_config_files_come_between_the_flags_and_the_profile() {
+        let root = temp_dir("local_config");
+        let project = root.join("project");
+        let nested = project.join("src").join("deep");
+        create_dir_all(&nested).unwrap();
+        std::fs::write(
+            project.join(local_config::FILE_NAME),
+            "name = \"lab\"\nworkdir = \"inventory\"\nformat = \"minimal\"\n",
+        )
+        .unwrap();
+        let profile = profiles::Profile {
+            workdir: Some(root.clone()),
+            name: Some("cottage".to_string()),
+            format: Some(profiles::Format::Machine),
+        };
+
+        let mut manager = Manager::from_iter(&["inv", "rt"]);
+        let name_given = manager.apply_local_config(&nested, false).unwrap();
+        assert!(name_given);
+        manager.use_profile("cottage", &profile, name_given);
+        assert_eq!(manager.workdir, Some(project.join("inventory")));
+        assert_eq!(manager.inventory_name, "lab");
+        assert!(manager.minimal && !manager.machine);
+
+        let mut manager = Manager::from_iter(&["inv", "-n", "home", "--machine", "rt"]);
+        assert!(manager.apply_local_config(&nested, true).unwrap());
+        assert_eq!(manager.inventory_name, "home");
+        assert_eq!(manager.workdir, Some(project.join("inventory")));
+        assert!(manager.machine && !manager.minimal);
+
+        let mut manager = Manager::from_iter(&["inv", "--no-local-config", "rt"]);
+        assert!(!manager.apply_local_config(&nested, false).unwrap());
+        manager.use_profile("cottage", &profile, false);
+        assert_eq!(manager.inventory_name, "cottage");
+        assert_eq!(manager.workdir, Some(root.clone()));
+
+        // Outside of the project, there is no local configuration file.
+        let mut manager = Manager::from_iter(&["inv", "rt"]);
+        assert!(!manager.apply_local_config(&root, false).unwrap());
+        assert_eq!(manager.workdir, None);
+        remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn link_points_at_an_existing_inventory() {
+        let root = temp_dir("link");
+        let workdir = root.join("inventory");
+        let (_, types, instances) = load_inventory(&workdir, "lab", Files::Both, false).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "lab"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let dir = root.to_str().unwrap();
+        run_args(&["link", "--dir", dir]);
+        assert!(local_config::find(&root).is_none());
+
+        save_inventory(&inventory_with_a_type(), &types, &instances, Files::Both).unwrap();
+        let (result, _) = run_args(&["link", "--dir", dir]);
+        assert_eq!(result.messages.len(), 1);
+        let path = root.join(local_config::FILE_NAME);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "workdir = \"inventory\"\nname = \"lab\"\n"
+        );
+        let (result, _) = run_args(&["--minimal", "link", "--dir", dir]);
+        assert!(result.messages.is_empty());
+        run_args(&["--minimal", "link", "--dir", dir, "--force"]);
+
+        let mut manager = Manager::from_iter(&["inv", "rt"]);
+        manager.apply_local_config(&root, false).unwrap();
+        assert!(manager.minimal);
+        let (_, inventory) = run(&manager).unwrap();
+        assert_eq!(inventory.item_types[0].name, "rice");
+        remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn crossing_the_minimum_is_reported() {
+        let workdir = temp_dir("minimum_crossing");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap().0
+        };
+        let check = |result: &CommandResult| {
+            let c = &result.minimums[0];
+            (c.quantity, c.below_minimum, c.crossed_threshold)
+        };
+
+        run_args(&["ct", "rice", "--unit", "kg", "--minimum-quantity", "1"]);
+        run_args(&["ct", "salt"]);
+        let result = run_args(&["ci", "rice", "-q", "2"]);
+        assert_eq!(check(&result), (2.0, false, true));
+        assert_eq!(
+            result.messages,
+            vec!["rice is no longer below its minimum: 2 kg >= 1 kg"]
+        );
+
+        // Exactly the minimum is not below it.
+        let result = run_args(&["use", "rice", "1"]);
+        assert_eq!(check(&result), (1.0, false, false));
+        assert!(result.messages.is_empty());
+
+        let result = run_args(&["use", "rice", "0.5"]);
+        assert_eq!(check(&result), (0.5, true, true));
+        assert_eq!(
+            result.messages,
+            vec!["rice is now below its minimum: 0.5 kg < 1 kg, added to the shopping list"]
+        );
+        let result = run_args(&["use", "rice", "0.25"]);
+        assert_eq!(check(&result), (0.25, true, false));
+
+        let result = run_args(&["ci", "rice", "-q", "0.75"]);
+        assert_eq!(check(&result), (1.0, false, true));
+        let result = run_args(&["ci", "salt"]);
+        assert!(result.minimums.is_empty() && result.messages.is_empty());
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn continuous_instances_are_topped_up() {
+        let workdir = temp_dir("top_up");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        run_args(&["ct", "rice", "--unit", "kg"]);
+        run_args(&["ci", "rice", "-q", "1", "--continuous"]);
+        run_args(&["ci", "rice", "-q", "1"]);
+        let (_, inventory) = run_args(&["use", "rice", "1"]);
+        assert_eq!(inventory.item_instances[0].quantity, 0.0);
+        assert!(inventory.item_instances[0].removed_at.is_none());
+
+        let (result, inventory) = run_args(&["top-up", "1", "500g", "-e", "2030-01-01"]);
+        assert_eq!(result.messages, vec!["0.5"]);
+        assert!(inventory.item_instances[0].expires_at.is_some());
+
+        let (result, inventory) = run_args(&["top-up", "2", "1"]);
+        assert!(!result.has_changes());
+        assert_eq!(inventory.item_instances[1].quantity, 1.0);
+        run_args(&["trash", "1"]);
+        let (result, _) = run_args(&["top-up", "1", "1"]);
+        assert!(!result.has_changes());
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn instances_are_found_by_their_external_reference() {
+        let workdir = temp_dir("external_ref");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let ids = |result: &CommandResult| match &result.listing {
+            Some(result::Listing::Instances(instances)) => {
+                instances.iter().map(|ii| ii.id).collect::<Vec<_>>()
+            }
+            Some(result::Listing::References(instances)) => {
+                instances.iter().map(|ii| ii.id).collect::<Vec<_>>()
+            }
+            other => panic!("unexpected listing {:?}", other),
+        };
+
+        run_args(&["ct", "rice"]);
+        run_args(&["ci", "rice", "--ref", "PO-1234"]);
+        run_args(&["ci", "rice", "--ref", "PO-1299"]);
+        run_args(&["ci", "rice"]);
+        let (result, _) = run_args(&["ri", "--ref", "po-12"]);
+        assert_eq!(ids(&result), vec![InstanceId(1), InstanceId(2)]);
+        run_args(&["trash", "1"]);
+        let (result, _) = run_args(&["find-ref", "PO-1234"]);
+        assert_eq!(ids(&result), vec![InstanceId(1)]);
+        let (result, _) = run_args(&["find-ref", "PO-12"]);
+        assert!(ids(&result).is_empty());
+
+        run_args(&["ui", "3", "--ref", "PO-1234"]);
+        let (_, inventory) = run_args(&["ui", "2", "--ref"]);
+        assert_eq!(inventory.item_instances[1].external_ref, None);
+        let (result, _) = run_args(&["find-ref", "PO-1234"]);
+        assert_eq!(ids(&result), vec![InstanceId(1), InstanceId(3)]);
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn types_are_listed_by_category() {
+        let workdir = temp_dir("categories");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let type_ids = |result: CommandResult| match result.listing {
+            Some(result::Listing::Types(types)) => types.iter().map(|t| t.id.0).collect(),
+            _ => vec![],
+        };
+
+        run_args(&["ct", "flour", "--category", " Food / Baking/ "]);
+        run_args(&["ct", "milk", "--category", "food/Dairy"]);
+        let (_, inventory) = run_args(&["ct", "soap"]);
+        assert_eq!(
+            inventory.item_types[0].category.as_deref(),
+            Some("Food/Baking")
+        );
+        assert_eq!(
+            type_ids(run_args(&["rt", "--category", "Food"]).0),
+            vec![1, 2]
+        );
+        assert!(type_ids(run_args(&["rt", "--category", "Food/Bak"]).0).is_empty());
+
+        let (result, _) = run_args(&["categories"]);
+        match result.listing {
+            Some(result::Listing::Categories(root)) => {
+                assert_eq!(root.rollup.types, 3);
+                assert_eq!(root.type_ids, vec![TypeId(3)]);
+                assert_eq!(root.children[0].path, "Food");
+                assert_eq!(root.children[0].rollup.types, 2);
+            }
+            _ => panic!("categories prints the tree of categories"),
+        }
+
+        let (_, inventory) = run_args(&["ut", "1", "--category"]);
+        assert_eq!(inventory.item_types[0].category, None);
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn integer_only_types_keep_whole_quantities() {
+        let workdir = temp_dir("integer_only");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let quantity = |inventory: &Inventory| inventory.item_instances[0].quantity;
+
+        run_args(&["ct", "eggs", "--unit", "pieces", "--integer-only", "true"]);
+        let (result, inventory) = run_args(&["ci", "eggs", "-q", "1.5"]);
+        assert_eq!(result.created_id, None);
+        assert!(inventory.item_instances.is_empty());
+        run_args(&["ci", "eggs", "-q", "6"]);
+
+        let (result, inventory) = run_args(&["use", "eggs", "0.5"]);
+        assert!(!result.has_changes());
+        assert_eq!(quantity(&inventory), 6.0);
+        let (result, inventory) = run_args(&["ui", "1", "-q", "2,5", "-l", "fridge"]);
+        assert!(!result.has_changes());
+        assert_eq!(inventory.item_instances[0].location, None);
+        let (_, inventory) = run_args(&["ui", "1", "-q", "4"]);
+        assert_eq!(quantity(&inventory), 4.0);
+        let (_, inventory) = run_args(&["use", "eggs", "1"]);
+        assert_eq!(quantity(&inventory), 3.0);
+
+        let eggs = &inventory.item_types[0];
+        assert!(eggs.integer_only);
+        assert_eq!(type_quantity(2.999_999_8, Some(eggs)), "3 pieces");
+        let (_, inventory) = run_args(&["ut", "1", "--integer-only", "false"]);
+        assert_eq!(
+            type_quantity(2.5, Some(&inventory.item_types[0])),
+            "2.5 pieces"
+        );
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn overdrafts_flag_item_types_until_they_are_counted() {
+        let workdir = temp_dir("overdraft");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.item_types[0].minimum_quantity = 2.0;
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                quantity: 1.0,
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let listed = |result: CommandResult| match result.listing {
+            Some(result::Listing::Types(types)) => types.iter().map(|t| t.id.0).collect(),
+            Some(result::Listing::Shortages(items)) => {
+                items.iter().map(|i| i.shortage.quantity as u32).collect()
+            }
+            _ => vec![],
+        };
+
+        let (result, inventory) = run_args(&["use", "rice", "3"]);
+        assert_eq!(result.instance_changes.len(), 1);
+        assert!(inventory.item_types[0].recount_requested_at.is_none());
+        assert!(listed(run_args(&["list-recount"]).0).is_empty());
+
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.item_types[0].minimum_quantity = 2.0;
+        inventory.item_types[0].allow_overdraft = true;
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                quantity: 1.0,
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let (result, inventory) = run_args(&["use", "rice", "3"]);
+        assert_eq!(result.instance_changes.len(), 2);
+        assert!(inventory.item_types[0].recount_requested_at.is_some());
+        let overdraft = &inventory.item_instances[1];
+        assert_eq!(overdraft.initial_quantity, Some(2.0));
+        assert!(overdraft.removed_at.is_some());
+        assert_eq!(listed(run_args(&["list-recount"]).0), vec![1]);
+
+        run_args(&["ci", "rice", "-q", "5"]);
+        assert_eq!(listed(run_args(&["list-missing"]).0), vec![0]);
+        run_args(&["ut", "1", "--counted"]);
+        assert!(listed(run_args(&["list-recount"]).0).is_empty());
+        assert!(listed(run_args(&["list-missing"]).0).is_empty());
+    }
+
+    #[test]
+    fn shopping_lists_are_split_by_vendor() {
+        let workdir = temp_dir("vendor");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let groups = |result: CommandResult| match result.listing {
+            Some(result::Listing::ShoppingGroups(groups)) => groups
+                .into_iter()
+                .map(|g| {
+                    let ids = g.items.iter().map(|i| i.shortage.type_id.0).collect();
+                    (g.vendor, ids)
+                })
+                .collect::<Vec<(Option<String>, Vec<u32>)>>(),
+            other => panic!("unexpected listing {:?}", other),
+        };
+
+        run_args(&["ct", "coffee", "-m", "1", "--vendor", "market"]);
+        run_args(&["ct", "salt", "-m", "1"]);
+        run_args(&["ct", "milk", "-m", "1", "--vendor", "Corner shop"]);
+        run_args(&["ct", "flour", "-m", "1", "--vendor", "Market"]);
+        let (result, _) = run_args(&["shopping-list", "--group-by", "vendor"]);
+        assert_eq!(
+            groups(result),
+            vec![
+                (Some("Corner shop".to_string()), vec![3]),
+                (Some("market".to_string()), vec![1, 4]),
+                (None, vec![2]),
+            ]
+        );
+
+        let (result, _) = run_args(&["list-missing", "--vendor", "MARKET"]);
+        match result.listing {
+            Some(result::Listing::Shortages(items)) => assert_eq!(
+                items.iter().map(|i| i.shortage.type_id).collect::<Vec<_>>(),
+                vec![TypeId(1), TypeId(4)]
+            ),
+            other => panic!("unexpected listing {:?}", other),
+        }
+        let (_, inventory) = run_args(&["ut", "4", "--vendor"]);
+        assert_eq!(inventory.item_types[3].vendor, None);
+        let (result, _) = run_args(&["list-missing", "--vendor", "market", "-g", "vendor"]);
+        assert_eq!(groups(result), vec![(Some("market".to_string()), vec![1])]);
+
+        let (_, inventory) = run_args(&["ci", "salt", "--vendor", "online"]);
+        assert_eq!(
+            inventory.item_instances[0].vendor.as_deref(),
+            Some("online")
+        );
+        assert_eq!(inventory.vendor_of(TypeId(2)), None);
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn shopping_lists_round_up_to_packages() {
+        let workdir = temp_dir("packages");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let shortages = |result: CommandResult| match result.listing {
+            Some(result::Listing::Shortages(items)) => {
+                items.into_iter().map(|i| i.shortage).collect::<Vec<_>>()
+            }
+            other => panic!("unexpected listing {:?}", other),
+        };
+        run_args(&[
+            "ct",
+            "rice",
+            "-m",
+            "1",
+            "--unit",
+            "kg",
+            "--package-size",
+            "1",
+        ]);
+        run_args(&["ct", "salt", "-m", "0,5"]);
+        run_args(&["ci", "rice", "-q", "300g"]);
+        let shortages = shortages(run_args(&["shopping-list"]).0);
+        assert_eq!(shortages[0].packages_to_buy, Some(1));
+        assert_eq!(packages_text(&shortages[0], Some("kg")), "1 package (1 kg)");
+        assert_eq!(shortages[1].packages_to_buy, None);
+        assert_eq!(packages_text(&shortages[1], None), "0.5");
+
+        let json = serde_json::to_value(&shortages[0]).unwrap();
+        assert_eq!(json["package_size"], 1.0);
+        assert_eq!(json["packages_to_buy"], 1);
+
+        let (_, inventory) = run_args(&["ut", "1", "--package-size", "0"]);
+        assert_eq!(inventory.item_types[0].package_size, None);
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn renamed_types_keep_their_former_names() {
+        let workdir = temp_dir("rename_type");
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        let names = |result: CommandResult| match result.listing {
+            Some(result::Listing::Types(types)) => {
+                types.into_iter().map(|t| t.name).collect::<Vec<_>>()
+            }
+            other => panic!("unexpected listing {:?}", other),
+        };
+
+        run_args(&["ct", "Milk"]);
+        run_args(&["ct", "rice"]);
+        let (_, inventory) = run_args(&["ut", "2", "-n", "MILK"]);
+        assert_eq!(inventory.item_types[1].name, "rice");
+        let (_, inventory) = run_args(&["ut", "1", "-n", "Oat milk"]);
+        assert_eq!(inventory.item_types[0].former_names, vec!["Milk"]);
+        let (result, _) = run_args(&["rt", "-n", "milk", "--exact"]);
+        assert_eq!(names(result), vec!["Oat milk"]);
+        let (_, inventory) = run_args(&["ci", "milk"]);
+        assert_eq!(inventory.item_instances[0].item_type, TypeId(1));
+
+        // The former name is taken until it is forced or taken back.
+        let (_, inventory) = run_args(&["ut", "2", "-n", "milk"]);
+        assert_eq!(inventory.item_types[1].name, "rice");
+        let (_, inventory) = run_args(&["ut", "1", "-n", "Milk"]);
+        assert_eq!(inventory.item_types[0].name, "Milk");
+        assert_eq!(inventory.item_types[0].former_names, vec!["Oat milk"]);
+        let (_, inventory) = run_args(&["ut", "2", "-n", "oat milk", "--force"]);
+        assert_eq!(inventory.item_types[1].name, "oat milk");
+        assert_eq!(inventory.resolve_type("oat milk"), Ok(TypeId(2)));
+        remove_dir_all(&workdir).unwrap();
+    }
+
+    #[test]
+    fn compacted_ids_are_saved_and_qr_codes_are_protected() {
+        let workdir = temp_dir("compact_ids");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        inventory.item_types[0].id = TypeId(5);
+        for _ in 0..3 {
+            inventory
+                .add_item_instance(
+                    ItemInstanceBuilder::default()
+                        .item_type(TypeId(5))
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+        inventory.item_instances.remove(0);
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+
+        let (result, _) = run_args(&["ri", "--id", "3", "--qr-string"]);
+        assert!(result.text.unwrap().starts_with("IMG1|inv=home|i=3|"));
+        let (result, inventory) = run_args(&["compact-ids"]);
+        assert!(!result.has_changes());
+        assert_eq!(inventory.item_instances[0].id, InstanceId(2));
+
+        let (result, inventory) = run_args(&["compact-ids", "--types"]);
+        assert_eq!(result.messages, vec!["Item type 5 is now 1"]);
+        assert_eq!(inventory.item_instances[0].item_type, TypeId(1));
+        let mapping = workdir.join("mapping.json");
+        let mapping_arg = mapping.to_str().unwrap();
+        let (_, inventory) = run_args(&["compact-ids", "--force", "-o", mapping_arg]);
+        let ids = inventory
+            .item_instances
+            .iter()
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![InstanceId(1), InstanceId(2)]);
+        let mapping: serde_json::Value =
+            serde_json::from_str(&read_to_string(&mapping).unwrap()).unwrap();
+        assert_eq!(
+            mapping,
+            serde_json::json!({"types": [], "instances": [[2, 1], [3, 2]]})
+        );
+    }
+
+    #[test]
+    fn instance_only_commands_do_not_rewrite_the_types() {
+        let workdir = temp_dir("instances_only");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        for _ in 0..2 {
+            inventory
+                .add_item_instance(
+                    ItemInstanceBuilder::default()
+                        .item_type(TypeId(1))
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let past = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        let age = |path: &Path| {
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(past)
+                .unwrap()
+        };
+        let modified = |path: &Path| metadata(path).unwrap().modified().unwrap();
+        let run_args = |args: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "-n", "home"];
+            all.extend_from_slice(args);
+            run(&Manager::from_iter(&all)).unwrap()
+        };
+        age(&types);
+        age(&instances);
+
+        let (_, inventory) = run_args(&["trash", "rice-01"]);
+        assert!(inventory.item_types.is_empty());
+        assert_eq!(modified(&types), past);
+        assert_ne!(modified(&instances), past);
+
+        // Creating an item instance needs the item type, but doesn't change it.
+        age(&instances);
+        let (result, inventory) = run_args(&["ci", "1"]);
+        assert_eq!(result.created_id, Some(3));
+        assert_eq!(inventory.item_types.len(), 1);
+        assert_eq!(modified(&types), past);
+        assert_ne!(modified(&instances), past);
+    }
+
+    #[test]
+    fn every_historical_format_loads_the_same_inventory() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/migrations");
+        let (latest, _, _) =
+            load_inventory(&fixtures.join("v3"), "home", Files::Both, false).unwrap();
+        assert_eq!(latest.item_types.len(), 2);
+        assert_eq!(latest.item_instances[1].extra.as_deref(), Some("basmati"));
+        for version in &["v1", "v2"] {
+            let (inventory, _, _) =
+                load_inventory(&fixtures.join(version), "home", Files::Both, false).unwrap();
+            assert_eq!(inventory.item_types, latest.item_types);
+            assert_eq!(inventory.item_instances, latest.item_instances);
+            assert_eq!(inventory.next_ids(), (TypeId(4), InstanceId(3)));
+        }
+    }
+
+    #[test]
+    fn newer_formats_are_refused() {
+        let workdir = temp_dir("newer_format");
+        let types = workdir.join("home_types.json");
+        write(
+            &types,
+            r#"{"format_version": 99, "next_id": 1, "items": []}"#,
+        )
+        .unwrap();
+        let err = load_inventory(&workdir, "home", Files::Both, false).unwrap_err();
+        assert!(matches!(&err, CliError::Format(path, _) if *path == types));
+        assert!(err.to_string().ends_with("please upgrade inv"));
+    }
+
+    #[test]
+    fn deleted_ids_are_remembered_across_saves() {
+        let workdir = temp_dir("next_ids");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        let deleted = inventory.add_item_type(inventory.item_types[0].clone());
+        inventory.delete_item_type(deleted);
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+        let (mut inventory, _, _) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        assert_eq!(
+            inventory.add_item_type(inventory.item_types[0].clone()),
+            TypeId(deleted.0 + 1)
+        );
+    }
+
+    #[test]
+    fn piped_inventories_are_never_saved_to_files() {
+        let workdir = temp_dir("piped").join("workdir");
+        let mut document = vec![];
+        write_inventory(&inventory_with_a_type(), &mut document).unwrap();
+        let args = |extra: &[&str]| {
+            let mut all = vec!["inv", "-w", workdir.to_str().unwrap(), "--stdin"];
+            all.extend_from_slice(extra);
+            Manager::from_iter(&all)
+        };
+
+        let mut output = vec![];
+        let (_, inventory) = run_with(
+            &args(&["--stdout", "ci", "rice"]),
+            std::io::Cursor::new(document.clone()),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(inventory.item_instances.len(), 1);
+        let piped = read_inventory(&output[..], Path::new("<stdout>"), false).unwrap();
+        assert_eq!(piped.item_types, inventory.item_types);
+        assert_eq!(piped.item_instances, inventory.item_instances);
+        assert_eq!(piped.next_ids(), (TypeId(2), InstanceId(2)));
+
+        let mut output = vec![];
+        run_with(
+            &args(&["ci", "rice"]),
+            std::io::Cursor::new(document),
+            &mut output,
+        )
+        .unwrap();
+        assert!(output.is_empty());
+        assert!(!workdir.exists());
+        assert!(read_inventory(&b"[]"[..], Path::new("<stdin>"), false).is_err());
+    }
+
+    #[test]
+    fn file_in_place_of_workdir_is_an_error() {
+        let workdir = temp_dir("file").join("workdir");
+        write(&workdir, "not a directory").unwrap();
+        let err = load_inventory(&workdir, "home", Files::Both, false).unwrap_err();
+        assert!(matches!(&err, CliError::NotADirectory(path) if *path == workdir));
+        assert!(err.to_string().contains("is not a directory"));
+        let err = save_inventory(
+            &inventory_with_a_type(),
+            &workdir.join("home_types.json"),
+            &workdir.join("home_instances.json"),
+            Files::Both,
+        )
+        .unwrap_err();
+        assert!(matches!(err, CliError::NotADirectory(_)));
+    }
+
+    #[test]
+    fn invalid_json_names_the_file() {
+        let workdir = temp_dir("invalid");
+        let types = workdir.join("home_types.json");
+        write(&types, "{").unwrap();
+        let err = load_inventory(&workdir, "home", Files::Both, false).unwrap_err();
+        assert!(
+            matches!(&err, CliError::Io { operation: Operation::Parse, path, .. } if *path == types)
+        );
+        assert!(err
+            .to_string()
+            .starts_with(&format!("failed to parse {}", types.display())));
+    }
+
+    #[test]
+    fn duplicate_ids_are_refused_unless_allowed() {
+        let workdir = temp_dir("duplicates");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        let mut inventory = inventory_with_a_type();
+        let copy = inventory.item_types[0].clone();
+        inventory.item_types.push(copy);
+        save_inventory(&inventory, &types, &instances, Files::Both).unwrap();
+
+        let err = load_inventory(&workdir, "home", Files::Both, false).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "duplicate ids in the inventory files: item types 1. Use --fix-ids to give new ids to the later duplicates"
+        );
+        let (inventory, _, _) = load_inventory(&workdir, "home", Files::Both, true).unwrap();
+        assert_eq!(inventory.item_types.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_workdir_can_be_read_but_not_written() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let workdir = temp_dir("read_only");
+        let (_, types, instances) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        save_inventory(&inventory_with_a_type(), &types, &instances, Files::Both).unwrap();
+        set_permissions(&workdir, Permissions::from_mode(0o555)).unwrap();
+        // Permissions are not enforced for privileged users.
+        let probe = workdir.join("probe");
+        if write(&probe, "").is_ok() {
+            let _ = remove_file(&probe);
+            set_permissions(&workdir, Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+
+        let (mut inventory, _, _) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        assert_eq!(inventory.item_types.len(), 1);
+        inventory.delete_item_type(TypeId(1));
+        let err = save_inventory(&inventory, &types, &instances, Files::Both).unwrap_err();
+        set_permissions(&workdir, Permissions::from_mode(0o755)).unwrap();
+        assert!(err.is_read_only());
+        assert!(err.to_string().contains(&types.display().to_string()));
+        let (inventory, _, _) = load_inventory(&workdir, "home", Files::Both, false).unwrap();
+        assert_eq!(inventory.item_types.len(), 1);
+    }
 }
@@ -0,0 +1,178 @@
+//! Prometheus metrics in the text exposition format used by the node_exporter
+//! textfile collector.
+
+use inv_manager::*;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+/// Renders the metrics of the inventory. Every series is labelled with the inventory name.
+/// The outstanding deposits are only rendered when `include_deposits` is set.
+pub fn render(
+    inventory: &Inventory,
+    inventory_name: &str,
+    now: SystemTime,
+    include_deposits: bool,
+) -> String {
+    let stats = inventory.all_type_stats();
+    let mut types = inventory.item_types.iter().collect::<Vec<_>>();
+    types.sort_by_key(|t| t.id);
+    let inv = format!("inventory=\"{}\"", escape_label_value(inventory_name));
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "inventory_quantity",
+        "Total quantity of the item instances of an item type.",
+    );
+    for t in &types {
+        let quantity = stats.get(&t.id).map(|s| s.quantity).unwrap_or(0.0);
+        sample(&mut out, "inventory_quantity", &inv, Some(t), quantity);
+    }
+
+    header(
+        &mut out,
+        "inventory_below_minimum",
+        "Whether an item type has less than its minimum quantity.",
+    );
+    for t in &types {
+        let quantity = stats.get(&t.id).map(|s| s.quantity).unwrap_or(0.0);
+        let below = if quantity < t.effective_minimum(now) {
+            1.0
+        } else {
+            0.0
+        };
+        sample(&mut out, "inventory_below_minimum", &inv, Some(t), below);
+    }
+
+    let expired = inventory
+        .item_instances
+        .iter()
+        .filter(|ii| ii.removed_at.is_none())
+        .filter(|ii| ii.expires_at.map(|exp| exp <= now).unwrap_or(false))
+        .count();
+    header(
+        &mut out,
+        "inventory_expired_instances",
+        "Number of item instances that are expired.",
+    );
+    sample(
+        &mut out,
+        "inventory_expired_instances",
+        &inv,
+        None,
+        expired as f32,
+    );
+
+    let total_value = stats.values().map(|s| s.total_value).sum::<f32>();
+    header(
+        &mut out,
+        "inventory_total_value",
+        "Sum of the monetary value of all item instances.",
+    );
+    sample(&mut out, "inventory_total_value", &inv, None, total_value);
+
+    if include_deposits {
+        header(
+            &mut out,
+            "inventory_outstanding_deposits",
+            "Sum of the deposits of the item instances whose container was not returned.",
+        );
+        sample(
+            &mut out,
+            "inventory_outstanding_deposits",
+            &inv,
+            None,
+            inventory.outstanding_deposits(),
+        );
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} gauge", name).unwrap();
+}
+
+fn sample(out: &mut String, name: &str, inv: &str, item_type: Option<&ItemType>, value: f32) {
+    match item_type {
+        Some(t) => writeln!(
+            out,
+            "{}{{{},type=\"{}\",type_id=\"{}\"}} {}",
+            name,
+            inv,
+            escape_label_value(&t.name),
+            t.id,
+            value
+        ),
+        None => writeln!(out, "{}{{{}}} {}", name, inv, value),
+    }
+    .unwrap();
+}
+
+/// Escapes a label value. Any unicode is allowed in label values, except that
+/// backslashes, double quotes and line feeds have to be escaped.
+fn escape_label_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn matches_golden_file() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(start)));
+        let rice = inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("rice".to_string())
+                .minimum_quantity(5.0)
+                .build()
+                .unwrap(),
+        );
+        let hostile = inventory.add_item_type(
+            ItemTypeBuilder::default()
+                .name("crème \"brûlée\"\\\nbox".to_string())
+                .build()
+                .unwrap(),
+        );
+        let mut add = |type_id, quantity, value, expires_at| {
+            inventory
+                .add_item_instance(
+                    ItemInstanceBuilder::default()
+                        .item_type(type_id)
+                        .quantity(quantity)
+                        .value(value)
+                        .expires_at(expires_at)
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap()
+        };
+        add(rice, 2.0, Some(4.0), Some(start - Duration::from_secs(1)));
+        add(rice, 0.5, Some(1.5), Some(start + Duration::from_secs(1)));
+        let trashed = add(hostile, 1.0, Some(100.0), Some(start));
+        add(hostile, 3.0, None, None);
+        inventory.trash(trashed);
+
+        let golden = include_str!("../fixtures/metrics/inventory.prom");
+        assert_eq!(render(&inventory, "home", start, false), golden);
+
+        inventory.item_types[0].deposit = Some(0.25);
+        let deposits = render(&inventory, "home", start, true);
+        assert!(deposits.starts_with(golden));
+        assert!(deposits.ends_with("inventory_outstanding_deposits{inventory=\"home\"} 0.5\n"));
+    }
+}
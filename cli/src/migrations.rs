@@ -0,0 +1,177 @@
+//! Upgrades of the inventory files written by older versions.
+//!
+//! Each version of the format of the types and instances files is upgraded to the next one
+//! by a pure function over the json, before the items are deserialized:
+//!
+//! 1. A bare json array of items, as written before the files were versioned.
+//! 2. The items are wrapped in an object with the version:
+//!    `{"format_version": 2, "items": [...]}`.
+//! 3. `next_id` is added to the object, the lowest id never given, so that the ids of
+//!    deleted items are not given again.
+
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// The version of the files written by this program.
+pub const CURRENT_VERSION: u32 = 3;
+
+/// `MIGRATIONS[n - 1]` upgrades a file from version `n` to version `n + 1`.
+const MIGRATIONS: &[fn(Value) -> Value] = &[wrap_items, add_next_id];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationError {
+    /// The file was written by a newer version of this program.
+    TooNew(u32),
+    /// Files are never downgraded, the file already has this newer version.
+    Downgrade(u32),
+    /// The file is neither an array nor an object with a format version.
+    Malformed,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::TooNew(version) => write!(
+                f,
+                "the file has format version {} but this version of inv only supports up to {}, please upgrade inv",
+                version, CURRENT_VERSION
+            ),
+            MigrationError::Downgrade(version) => write!(
+                f,
+                "the file already has format version {}, files can not be downgraded",
+                version
+            ),
+            MigrationError::Malformed => write!(f, "the file has no format version"),
+        }
+    }
+}
+
+/// Returns the format version of a file.
+pub fn version_of(file: &Value) -> Result<u32, MigrationError> {
+    match file {
+        Value::Array(_) => Ok(1),
+        Value::Object(map) => map
+            .get("format_version")
+            .and_then(Value::as_u64)
+            .map(|v| v as u32)
+            .filter(|v| *v >= 2)
+            .ok_or(MigrationError::Malformed),
+        _ => Err(MigrationError::Malformed),
+    }
+}
+
+/// Upgrades a file to a version, one version at a time.
+pub fn migrate(mut file: Value, to: u32) -> Result<Value, MigrationError> {
+    let version = version_of(&file)?;
+    if version > CURRENT_VERSION {
+        return Err(MigrationError::TooNew(version));
+    }
+    if to > CURRENT_VERSION {
+        return Err(MigrationError::TooNew(to));
+    }
+    if version > to {
+        return Err(MigrationError::Downgrade(version));
+    }
+    for migration in &MIGRATIONS[(version - 1) as usize..(to - 1) as usize] {
+        file = migration(file);
+    }
+    Ok(file)
+}
+
+/// 1 to 2: wraps the array of items in an object with the version.
+fn wrap_items(file: Value) -> Value {
+    let mut map = Map::new();
+    map.insert("format_version".to_string(), Value::from(2));
+    map.insert("items".to_string(), file);
+    Value::Object(map)
+}
+
+/// 2 to 3: adds the lowest id never given, which is after the highest id in use
+/// since the ids of deleted items were not remembered before.
+fn add_next_id(mut file: Value) -> Value {
+    let max = file["items"]
+        .as_array()
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item["id"].as_u64())
+                .max()
+                .unwrap_or(0)
+        })
+        .unwrap_or(0);
+    if let Value::Object(map) = &mut file {
+        map.insert("format_version".to_string(), Value::from(3));
+        map.insert("next_id".to_string(), Value::from(max + 1));
+    }
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(version: u32, file: &str) -> Value {
+        let path = format!(
+            "{}/fixtures/migrations/v{}/home_{}.json",
+            env!("CARGO_MANIFEST_DIR"),
+            version,
+            file
+        );
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn every_step_matches_the_next_fixture() {
+        for file in &["types", "instances"] {
+            for version in 1..CURRENT_VERSION {
+                let old = fixture(version, file);
+                assert_eq!(version_of(&old), Ok(version));
+                let upgraded = migrate(old, version + 1).unwrap();
+                assert_eq!(
+                    upgraded,
+                    fixture(version + 1, file),
+                    "{} v{}",
+                    file,
+                    version
+                );
+            }
+            let latest = migrate(fixture(1, file), CURRENT_VERSION).unwrap();
+            assert_eq!(latest, fixture(CURRENT_VERSION, file));
+            assert_eq!(migrate(latest.clone(), CURRENT_VERSION), Ok(latest));
+        }
+    }
+
+    #[test]
+    fn newer_and_malformed_files_are_refused() {
+        let newer = serde_json::json!({"format_version": CURRENT_VERSION + 1, "items": []});
+        assert_eq!(
+            migrate(newer, CURRENT_VERSION),
+            Err(MigrationError::TooNew(CURRENT_VERSION + 1))
+        );
+        assert!(MigrationError::TooNew(9)
+            .to_string()
+            .ends_with("please upgrade inv"));
+        assert_eq!(
+            migrate(fixture(3, "types"), 2),
+            Err(MigrationError::Downgrade(3))
+        );
+        assert_eq!(
+            migrate(serde_json::json!([]), CURRENT_VERSION + 1),
+            Err(MigrationError::TooNew(CURRENT_VERSION + 1))
+        );
+        assert_eq!(
+            version_of(&serde_json::json!({"items": []})),
+            Err(MigrationError::Malformed)
+        );
+        assert_eq!(
+            version_of(&serde_json::json!("text")),
+            Err(MigrationError::Malformed)
+        );
+    }
+
+    #[test]
+    fn next_id_of_an_empty_file() {
+        let file = migrate(serde_json::json!([]), CURRENT_VERSION).unwrap();
+        assert_eq!(file["next_id"], 1);
+    }
+}
@@ -0,0 +1,196 @@
+//! Named sets of global options, to switch between inventories without typing their working
+//! directory and name. They are in `profiles.json` in the configuration directory of the
+//! user, for example `~/.config/inventory_managoat/profiles.json`:
+//!
+//! ```json
+//! {
+//!     "default": "home",
+//!     "profiles": {
+//!         "home": {"name": "home"},
+//!         "cottage": {"workdir": "/mnt/sync/cottage", "name": "cottage", "format": "minimal"}
+//!     }
+//! }
+//! ```
+//!
+//! The profile is the one of `--profile`, otherwise the one of `IMGOAT_PROFILE`, otherwise
+//! the default one. Its values only apply to the options that are not given.
+
+use crate::error::{CliError, Operation};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::read;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// The environment variable naming the profile when `--profile` isn't given.
+pub const PROFILE_VAR: &str = "IMGOAT_PROFILE";
+
+/// How the results are printed, like `--minimal` and `--machine`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Table,
+    Minimal,
+    Machine,
+}
+
+/// The options set by a profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Profile {
+    pub workdir: Option<PathBuf>,
+    /// The name of the inventory, like `--name`.
+    pub name: Option<String>,
+    pub format: Option<Format>,
+}
+
+/// The content of the profiles file.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Profiles {
+    /// The profile used when none is chosen.
+    pub default: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A profile as listed by `profiles`, with its name in `profile` since `name` is the one
+/// of the inventory.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProfileEntry {
+    #[serde(rename = "profile")]
+    pub profile_name: String,
+    pub default: bool,
+    #[serde(flatten)]
+    pub profile: Profile,
+}
+
+impl Profiles {
+    /// The profile chosen with `--profile`, otherwise in the environment, otherwise the
+    /// default one, with its name. A profile that doesn't exist is an error.
+    pub fn select(
+        &self,
+        flag: Option<&str>,
+        env: Option<&str>,
+    ) -> Result<Option<(&str, &Profile)>, CliError> {
+        let name = match flag.or(env).or(self.default.as_deref()) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        match self.profiles.get_key_value(name) {
+            Some((name, profile)) => Ok(Some((name, profile))),
+            None => Err(CliError::UnknownProfile {
+                name: name.to_string(),
+                known: self.profiles.keys().cloned().collect(),
+            }),
+        }
+    }
+
+    /// The profiles in alphabetical order.
+    pub fn entries(&self) -> Vec<ProfileEntry> {
+        self.profiles
+            .iter()
+            .map(|(name, profile)| ProfileEntry {
+                profile_name: name.clone(),
+                default: self.default.as_ref() == Some(name),
+                profile: profile.clone(),
+            })
+            .collect()
+    }
+}
+
+/// The profiles file of the user, if there is a configuration directory.
+pub fn profiles_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("inventory_managoat").join("profiles.json"))
+}
+
+/// Reads the profiles, which are none when the file doesn't exist.
+pub fn load(path: &Path) -> Result<Profiles, CliError> {
+    match read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| CliError::io(Operation::Parse, path, e.into())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Profiles::default()),
+        Err(e) => Err(CliError::io(Operation::Read, path, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profiles() -> Profiles {
+        serde_json::from_str(
+            r#"{"default": "home", "profiles": {
+                "home": {"name": "home"},
+                "cottage": {"workdir": "/mnt/cottage", "name": "cottage", "format": "minimal"}
+            }}"#,
+        )
+        .unwrap()
+    }
+
+    fn selected(profiles: &Profiles, flag: Option<&str>, env: Option<&str>) -> Option<String> {
+        profiles
+            .select(flag, env)
+            .unwrap()
+            .map(|(name, _)| name.to_string())
+    }
+
+    #[test]
+    fn the_flag_wins_over_the_environment_and_the_default() {
+        let profiles = profiles();
+        let cottage = Some("cottage".to_string());
+        assert_eq!(selected(&profiles, Some("cottage"), Some("home")), cottage);
+        assert_eq!(selected(&profiles, None, Some("cottage")), cottage);
+        assert_eq!(selected(&profiles, None, None), Some("home".to_string()));
+        let (_, profile) = profiles.select(Some("cottage"), None).unwrap().unwrap();
+        assert_eq!(profile.format, Some(Format::Minimal));
+        assert_eq!(profile.workdir, Some(PathBuf::from("/mnt/cottage")));
+
+        let without_default = Profiles {
+            default: None,
+            ..profiles
+        };
+        assert_eq!(selected(&without_default, None, None), None);
+    }
+
+    #[test]
+    fn unknown_profiles_are_errors() {
+        let profiles = profiles();
+        for (flag, env) in [(Some("cotage"), None), (None, Some("cotage"))] {
+            let err = profiles.select(flag, env).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "there is no profile named cotage, the profiles are: cottage, home"
+            );
+        }
+        let broken = Profiles {
+            default: Some("gone".to_string()),
+            ..Profiles::default()
+        };
+        assert!(matches!(
+            broken.select(None, None),
+            Err(CliError::UnknownProfile { .. })
+        ));
+        assert_eq!(Profiles::default().select(None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn profiles_are_listed_with_the_default_marked() {
+        let entries = profiles().entries();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.profile_name.as_str(), e.default))
+                .collect::<Vec<_>>(),
+            vec![("cottage", false), ("home", true)]
+        );
+        let json = serde_json::to_value(&entries[0]).unwrap();
+        assert_eq!(json["profile"], "cottage");
+        assert_eq!(json["name"], "cottage");
+        assert_eq!(json["format"], "minimal");
+        let path = std::env::temp_dir().join(format!("inv_profiles_{}.json", std::process::id()));
+        assert_eq!(load(&path).unwrap(), Profiles::default());
+        std::fs::write(&path, r#"{"profiles": {"a": {"format": "xml"}}}"#).unwrap();
+        assert!(load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
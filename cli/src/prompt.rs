@@ -0,0 +1,122 @@
+//! Questions asked on the terminal when a command needs more information.
+
+use std::fmt::Display;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Whether questions can be asked, which needs stdin to be a terminal.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal()
+}
+
+/// Shows a numbered menu of items on stderr and reads the number of one from stdin.
+/// Returns `None` when the selection is cancelled with an empty line or the end of the input.
+pub fn choose_one<T: Display>(items: &[T]) -> Option<usize> {
+    choose_one_with(items, &mut io::stdin().lock(), &mut io::stderr())
+}
+
+/// `choose_one` reading from and writing to something else than the terminal.
+/// Numbers outside of the menu are asked again.
+pub fn choose_one_with<T: Display>(
+    items: &[T],
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Option<usize> {
+    for (i, item) in items.iter().enumerate() {
+        writeln!(output, "{}) {}", i + 1, item).ok()?;
+    }
+    loop {
+        write!(output, "Choose 1-{} (empty to cancel): ", items.len()).ok()?;
+        output.flush().ok()?;
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            writeln!(output).ok()?;
+            return None;
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+        match line.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= items.len() => return Some(n - 1),
+            _ => writeln!(output, "{} is not in the list", line).ok()?,
+        }
+    }
+}
+
+/// Asks a yes or no question on stderr and reads the answer from stdin.
+/// Anything else than y or yes, the end of the input included, means no.
+pub fn confirm(question: &str) -> bool {
+    confirm_with(question, &mut io::stdin().lock(), &mut io::stderr())
+}
+
+/// `confirm` reading from and writing to something else than the terminal.
+pub fn confirm_with(question: &str, input: &mut impl BufRead, output: &mut impl Write) -> bool {
+    if write!(output, "{} [y/N] ", question)
+        .and_then(|_| output.flush())
+        .is_err()
+    {
+        return false;
+    }
+    let mut line = String::new();
+    match input.read_line(&mut line) {
+        Ok(0) | Err(_) => {
+            let _ = writeln!(output);
+            false
+        }
+        Ok(_) => matches!(line.trim().to_lowercase().as_str(), "y" | "yes"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choose(input: &str) -> (Option<usize>, String) {
+        let mut output = vec![];
+        let choice = choose_one_with(&["milk", "oat milk"], &mut input.as_bytes(), &mut output);
+        (choice, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn numbers_select_items() {
+        let (choice, output) = choose("2\n");
+        assert_eq!(choice, Some(1));
+        assert_eq!(
+            output,
+            "1) milk\n2) oat milk\nChoose 1-2 (empty to cancel): "
+        );
+    }
+
+    #[test]
+    fn invalid_numbers_are_asked_again() {
+        let (choice, output) = choose("3\nmilk\n 1 \n");
+        assert_eq!(choice, Some(0));
+        assert!(output.contains("3 is not in the list"));
+        assert!(output.contains("milk is not in the list"));
+    }
+
+    #[test]
+    fn empty_lines_and_end_of_input_cancel() {
+        assert_eq!(choose("\n2\n").0, None);
+        assert_eq!(choose("").0, None);
+        assert_eq!(choose("0\n").0, None);
+    }
+
+    #[test]
+    fn only_yes_confirms() {
+        let confirm = |input: &str| {
+            let mut output = vec![];
+            let answer = confirm_with("Rename?", &mut input.as_bytes(), &mut output);
+            assert!(String::from_utf8(output)
+                .unwrap()
+                .starts_with("Rename? [y/N] "));
+            answer
+        };
+        assert!(confirm("y\n"));
+        assert!(confirm(" Yes \n"));
+        assert!(!confirm("n\n"));
+        assert!(!confirm("\n"));
+        assert!(!confirm("yep\n"));
+        assert!(!confirm(""));
+    }
+}
@@ -0,0 +1,265 @@
+//! Read-only snapshots of an inventory, to share it without giving access to its files.
+//!
+//! A published file is a single json document holding the item types and the item instances
+//! that are not in the trash, leaving out the fields that are null or redacted. Its `data`
+//! is stored compactly with a checksum, so that a file damaged or only partly synced is
+//! refused instead of being misread.
+
+use crate::error::{CliError, Operation};
+use crate::storage;
+use inv_manager::{Inventory, ItemInstance, ItemType};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use serde_json::Value;
+use std::fs::{read_to_string, rename};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The version of the published format written by this program.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A field of the item instances that can be left out of a published file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Redacted {
+    Value,
+    Serial,
+    Model,
+    Extra,
+    /// The location and the home location.
+    Location,
+}
+
+impl Redacted {
+    pub const NAMES: &'static [&'static str] = &["value", "serial", "model", "extra", "location"];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Redacted::Value => "value",
+            Redacted::Serial => "serial",
+            Redacted::Model => "model",
+            Redacted::Extra => "extra",
+            Redacted::Location => "location",
+        }
+    }
+
+    /// The json fields of an item instance holding it.
+    fn fields(self) -> &'static [&'static str] {
+        match self {
+            Redacted::Value => &["value"],
+            Redacted::Serial => &["serial"],
+            Redacted::Model => &["model"],
+            Redacted::Extra => &["extra"],
+            Redacted::Location => &["location", "home_location"],
+        }
+    }
+}
+
+impl FromStr for Redacted {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "value" => Ok(Redacted::Value),
+            "serial" => Ok(Redacted::Serial),
+            "model" => Ok(Redacted::Model),
+            "extra" => Ok(Redacted::Extra),
+            "location" => Ok(Redacted::Location),
+            _ => Err(format!(
+                "unknown field {}, expected one of {}",
+                s,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Published<D> {
+    format_version: u32,
+    inventory_name: String,
+    /// RFC 3339 timestamp of the publication.
+    published_at: String,
+    redacted: Vec<String>,
+    /// `crc32:` followed by the CRC-32 of the bytes of `data`, in hexadecimal.
+    checksum: String,
+    data: D,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Data<T, I> {
+    types: Vec<T>,
+    instances: Vec<I>,
+}
+
+fn checksum(data: &str) -> String {
+    let mut crc = flate2::Crc::new();
+    crc.update(data.as_bytes());
+    format!("crc32:{:08x}", crc.sum())
+}
+
+/// The json object of an item, without its null fields and the fields given.
+fn stripped(item: Value, fields: &[&str]) -> Value {
+    match item {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, value)| !value.is_null() && !fields.contains(&key.as_str()))
+                .collect(),
+        ),
+        item => item,
+    }
+}
+
+/// Writes the item types and the item instances that are not in the trash to the file,
+/// replacing it atomically. Returns the number of item instances published.
+pub fn publish(
+    inventory: &Inventory,
+    name: &str,
+    redacted: &[Redacted],
+    path: &Path,
+    now: SystemTime,
+) -> Result<usize, CliError> {
+    let fields = redacted
+        .iter()
+        .flat_map(|r| r.fields().iter().copied())
+        .collect::<Vec<_>>();
+    let failed = |e: serde_json::Error| CliError::io(Operation::Write, path, e.into());
+    let types = inventory
+        .item_types
+        .iter()
+        .map(|it| serde_json::to_value(it).map(|v| stripped(v, &[])))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(failed)?;
+    let instances = inventory
+        .item_instances
+        .iter()
+        .filter(|ii| ii.removed_at.is_none())
+        .map(|ii| serde_json::to_value(ii).map(|v| stripped(v, &fields)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(failed)?;
+    let count = instances.len();
+    let data = serde_json::to_string(&Data { types, instances }).map_err(failed)?;
+    let document = Published {
+        format_version: FORMAT_VERSION,
+        inventory_name: name.to_string(),
+        published_at: humantime::format_rfc3339_seconds(now).to_string(),
+        redacted: redacted.iter().map(|r| r.name().to_string()).collect(),
+        checksum: checksum(&data),
+        data: RawValue::from_string(data).map_err(failed)?,
+    };
+    let mut content = serde_json::to_string_pretty(&document).map_err(failed)?;
+    content.push('\n');
+    let tmp = storage::write_tmp(path, content.as_bytes())?;
+    rename(&tmp, path).map_err(|e| CliError::io(Operation::Write, path, e))?;
+    Ok(count)
+}
+
+/// Reads the inventory of a published file, checking its checksum.
+pub fn read(path: &Path) -> Result<Inventory, CliError> {
+    let invalid = |message: String| {
+        CliError::io(
+            Operation::Parse,
+            path,
+            Error::new(ErrorKind::InvalidData, message),
+        )
+    };
+    let content = read_to_string(path).map_err(|e| CliError::io(Operation::Read, path, e))?;
+    let document: Published<Box<RawValue>> = serde_json::from_str(&content)
+        .map_err(|e| CliError::io(Operation::Parse, path, e.into()))?;
+    if document.format_version > FORMAT_VERSION {
+        return Err(invalid(format!(
+            "unsupported published format version {}, the latest supported version is {}",
+            document.format_version, FORMAT_VERSION
+        )));
+    }
+    if checksum(document.data.get()) != document.checksum {
+        return Err(invalid(
+            "the checksum doesn't match the data, the file is damaged or not fully synced"
+                .to_string(),
+        ));
+    }
+    let data: Data<ItemType, ItemInstance> = serde_json::from_str(document.data.get())
+        .map_err(|e| CliError::io(Operation::Parse, path, e.into()))?;
+    Ok(Inventory::new(data.types, data.instances))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use inv_manager::{InstanceId, TypeId};
+    use std::fs::{remove_file, write};
+    use std::time::Duration;
+
+    fn published_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("inv_publish_{}_{}.json", name, std::process::id()))
+    }
+
+    fn inventory() -> Inventory {
+        let rice = ItemType {
+            id: TypeId(1),
+            name: "rice".to_string(),
+            ..ItemType::default()
+        };
+        let instance = |id: u32, removed: bool| ItemInstance {
+            id: InstanceId(id),
+            item_type: TypeId(1),
+            quantity: 2.0,
+            serial: Some("SN-1".to_string()),
+            value: Some(3.5),
+            location: Some("pantry".to_string()),
+            home_location: Some("pantry".to_string()),
+            removed_at: if removed {
+                Some(SystemTime::UNIX_EPOCH)
+            } else {
+                None
+            },
+            ..ItemInstance::default()
+        };
+        Inventory::new(vec![rice], vec![instance(1, false), instance(2, true)])
+    }
+
+    #[test]
+    fn published_files_leave_out_the_trash_and_the_redacted_fields() {
+        let path = published_path("redacted");
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let redacted = [Redacted::Value, Redacted::Location];
+        assert_eq!(
+            publish(&inventory(), "home", &redacted, &path, now).unwrap(),
+            1
+        );
+
+        let document: Value = serde_json::from_str(&read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            document["redacted"],
+            serde_json::json!(["value", "location"])
+        );
+        let instance = &document["data"]["instances"][0];
+        assert_eq!(instance["serial"], "SN-1");
+        for field in &["value", "location", "home_location", "removed_at", "model"] {
+            assert!(instance.get(field).is_none(), "{}", field);
+        }
+
+        let inventory = read(&path).unwrap();
+        assert_eq!(inventory.item_types[0].name, "rice");
+        assert_eq!(inventory.item_instances.len(), 1);
+        assert_eq!(inventory.item_instances[0].value, None);
+        assert_eq!(inventory.item_instances[0].quantity, 2.0);
+        remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn damaged_published_files_are_refused() {
+        let path = published_path("damaged");
+        publish(&inventory(), "home", &[], &path, SystemTime::UNIX_EPOCH).unwrap();
+        let content = read_to_string(&path).unwrap();
+        write(
+            &path,
+            content.replace("\"quantity\":2.0", "\"quantity\":20.0"),
+        )
+        .unwrap();
+        let error = read(&path).unwrap_err().to_string();
+        assert!(error.contains("checksum"), "{}", error);
+        remove_file(&path).unwrap();
+    }
+}
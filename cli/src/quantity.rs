@@ -0,0 +1,96 @@
+//! Quantities typed on the command line, with an optional unit like `500g`.
+
+use inv_manager::decimal::{parse_decimal, ParseError};
+use inv_manager::unit;
+use std::fmt;
+use std::str::FromStr;
+
+/// A quantity argument. Its unit is only known to be right once the item type is resolved,
+/// see `in_unit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantityArg {
+    pub amount: f32,
+    pub unit: Option<String>,
+}
+
+impl QuantityArg {
+    /// The quantity in a unit. A quantity without unit is already in it, and a quantity with
+    /// a unit needs one it can be converted to. Returns `None` otherwise.
+    pub fn in_unit(&self, target: Option<&str>) -> Option<f32> {
+        match (&self.unit, target) {
+            (None, _) => Some(self.amount),
+            (Some(unit), Some(target)) => unit::convert(self.amount, unit, target),
+            (Some(_), None) => None,
+        }
+    }
+}
+
+impl FromStr for QuantityArg {
+    type Err = String;
+
+    /// Parses a number followed by an optional unit, with or without a space: `2`, `1.5kg`,
+    /// `1,5kg`, `500 g`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let end = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',' || c == '-' || c == '+'))
+            .unwrap_or(s.len());
+        let (amount, unit) = s.split_at(end);
+        let amount = parse_decimal(amount).map_err(|e| match e {
+            ParseError::Ambiguous(_) => e.to_string(),
+            _ => format!("{} is not a quantity like 2, 1.5kg or 500g", s),
+        })?;
+        let unit = unit.trim();
+        Ok(QuantityArg {
+            amount,
+            unit: if unit.is_empty() {
+                None
+            } else {
+                Some(unit.to_string())
+            },
+        })
+    }
+}
+
+impl fmt::Display for QuantityArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.amount, self.unit.as_deref().unwrap_or(""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> QuantityArg {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn units_are_optional() {
+        assert_eq!(parse("2").in_unit(None), Some(2.0));
+        assert_eq!(parse("2").in_unit(Some("kg")), Some(2.0));
+        assert_eq!(parse("0.5").unit, None);
+        assert_eq!(parse("1.5kg").unit.as_deref(), Some("kg"));
+        assert_eq!(parse(" 500 g ").unit.as_deref(), Some("g"));
+        assert_eq!(parse("3pieces").to_string(), "3pieces");
+        assert!("kg".parse::<QuantityArg>().is_err());
+        assert!("".parse::<QuantityArg>().is_err());
+        assert!("1..5g".parse::<QuantityArg>().is_err());
+        assert_eq!(parse("1,5kg").amount, 1.5);
+        assert_eq!(parse("0,25 l").unit.as_deref(), Some("l"));
+        assert_eq!(
+            "1,500g".parse::<QuantityArg>(),
+            Err("1,500 is ambiguous, write 1500 or 1.5".to_string())
+        );
+    }
+
+    #[test]
+    fn units_are_converted_to_the_target() {
+        assert_eq!(parse("500g").in_unit(Some("kg")), Some(0.5));
+        assert_eq!(parse("1.5KG").in_unit(Some("g")), Some(1500.0));
+        assert_eq!(parse("3 pieces").in_unit(Some("Pieces")), Some(3.0));
+        assert_eq!(parse("500ml").in_unit(Some("kg")), None);
+        assert_eq!(parse("500g").in_unit(None), None);
+    }
+}
@@ -0,0 +1,633 @@
+//! How tables are laid out on the terminal.
+//!
+//! The listings build a `Grid` and a `Renderer` turns it into text: `Classic` draws the
+//! prettytable tables with borders, `Compact` fits the table to the width of the terminal,
+//! dropping the columns of lowest priority then truncating or wrapping the widest fields.
+
+use prettytable::{Cell, Row, Table};
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The priority of the columns which are never dropped.
+pub const KEEP: u8 = u8::MAX;
+
+/// Terminals narrower than this use the compact renderer by default.
+pub const COMPACT_BELOW: usize = 100;
+
+/// The width assumed when the compact renderer is asked for outside of a terminal.
+const DEFAULT_WIDTH: usize = 80;
+
+/// Columns are not shrunk below this width, unless their fields are all shorter.
+const MIN_WIDTH: usize = 8;
+
+/// Columns are dropped when the table doesn't fit with its columns shrunk to this width.
+/// Wider columns are shrunk rather than dropping others.
+const COMFORTABLE_WIDTH: usize = 24;
+
+const SEPARATOR: &str = "  ";
+
+/// Builds a row of fields from values shown as they are displayed.
+macro_rules! fields {
+    ($($field:expr),* $(,)?) => {
+        vec![$($crate::render::Field::new(&$field)),*]
+    };
+}
+
+/// How a field stands out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Bold,
+    Red,
+    Yellow,
+    /// Secondary information, like the former names of an item type.
+    Dim,
+}
+
+impl Style {
+    /// The prettytable style specification.
+    fn spec(self) -> &'static str {
+        match self {
+            Style::Plain => "",
+            Style::Bold => "b",
+            Style::Red => "Fr",
+            Style::Yellow => "Fy",
+            Style::Dim => "FD",
+        }
+    }
+
+    /// The escape sequence starting the style on a terminal.
+    fn escape(self) -> &'static str {
+        match self {
+            Style::Plain => "",
+            Style::Bold => "\x1b[1m",
+            Style::Red => "\x1b[31m",
+            Style::Yellow => "\x1b[33m",
+            Style::Dim => "\x1b[2m",
+        }
+    }
+}
+
+/// The text of a cell.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Field {
+    pub fn new(text: impl Display) -> Self {
+        Field::styled(text, Style::Plain)
+    }
+
+    pub fn styled(text: impl Display, style: Style) -> Self {
+        Field {
+            text: text.to_string(),
+            style,
+        }
+    }
+}
+
+/// What the compact renderer does with the fields wider than their column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Cut the field, ending it with an ellipsis.
+    Truncate,
+    /// Continue the field on the next lines, between words when possible.
+    Wrap,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    /// When the table is too wide, the columns of lowest priority are dropped first.
+    /// Columns of priority `KEEP` are never dropped.
+    pub priority: u8,
+    pub overflow: Overflow,
+}
+
+impl Column {
+    /// A column which is kept and whose long fields are truncated.
+    pub fn new(name: &str) -> Self {
+        Column {
+            name: name.to_string(),
+            priority: KEEP,
+            overflow: Overflow::Truncate,
+        }
+    }
+
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn wrap(mut self) -> Self {
+        self.overflow = Overflow::Wrap;
+        self
+    }
+}
+
+/// A table, before it is laid out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Grid {
+    pub columns: Vec<Column>,
+    /// Rows have one field per column.
+    pub rows: Vec<Vec<Field>>,
+}
+
+impl Grid {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Grid {
+            columns,
+            rows: vec![],
+        }
+    }
+
+    /// A table of columns which are all kept and truncated.
+    pub fn with_header(names: &[&str]) -> Self {
+        Grid::new(names.iter().map(|n| Column::new(n)).collect())
+    }
+
+    pub fn add_row(&mut self, row: Vec<Field>) {
+        debug_assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+}
+
+/// Turns tables into text.
+pub trait Renderer {
+    /// The table as lines of text without colors, each ending with a line break.
+    fn render(&self, grid: &Grid) -> String;
+
+    /// Prints the table on stdout, with colors when it is a terminal.
+    fn print(&self, grid: &Grid) {
+        print!("{}", self.render(grid));
+    }
+}
+
+/// The tables with borders of prettytable.
+#[derive(Debug, Clone, Copy)]
+pub struct Classic;
+
+impl Classic {
+    fn table(grid: &Grid) -> Table {
+        let mut table = Table::new();
+        table.add_row(Row::new(
+            grid.columns.iter().map(|c| Cell::new(&c.name)).collect(),
+        ));
+        for row in &grid.rows {
+            table.add_row(Row::new(
+                row.iter()
+                    .map(|f| Cell::new(&f.text).style_spec(f.style.spec()))
+                    .collect(),
+            ));
+        }
+        table
+    }
+}
+
+impl Renderer for Classic {
+    fn render(&self, grid: &Grid) -> String {
+        Classic::table(grid).to_string()
+    }
+
+    fn print(&self, grid: &Grid) {
+        Classic::table(grid).printstd();
+    }
+}
+
+/// Columns separated by spaces, fitted to a width.
+#[derive(Debug, Clone, Copy)]
+pub struct Compact {
+    pub width: usize,
+}
+
+impl Compact {
+    /// The indices of the columns shown and their widths.
+    fn layout(&self, grid: &Grid) -> Vec<(usize, usize)> {
+        let natural = grid
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                grid.rows
+                    .iter()
+                    .map(|r| r[i].text.width())
+                    .chain(std::iter::once(c.name.width()))
+                    .max()
+                    .unwrap_or(0)
+                    .max(1)
+            })
+            .collect::<Vec<_>>();
+        let minimum = |i: usize| natural[i].min(MIN_WIDTH);
+        let comfortable = |i: usize| natural[i].min(COMFORTABLE_WIDTH);
+        let total = |columns: &[(usize, usize)]| {
+            columns.iter().map(|(_, w)| w).sum::<usize>()
+                + SEPARATOR.len() * columns.len().saturating_sub(1)
+        };
+
+        let mut shown = (0..grid.columns.len())
+            .map(|i| (i, comfortable(i)))
+            .collect::<Vec<_>>();
+        while total(&shown) > self.width {
+            // The rightmost of the columns of lowest priority.
+            let dropped = shown
+                .iter()
+                .enumerate()
+                .filter(|(_, (i, _))| grid.columns[*i].priority != KEEP)
+                .min_by_key(|(position, (i, _))| {
+                    (grid.columns[*i].priority, std::cmp::Reverse(*position))
+                })
+                .map(|(position, _)| position);
+            match dropped {
+                Some(position) => {
+                    shown.remove(position);
+                }
+                None => break,
+            }
+        }
+
+        shown.iter_mut().for_each(|(i, w)| *w = natural[*i]);
+        while total(&shown) > self.width {
+            let widest = shown
+                .iter_mut()
+                .filter(|(i, w)| *w > minimum(*i))
+                .max_by_key(|(_, w)| *w);
+            match widest {
+                Some((_, w)) => *w -= 1,
+                None => break,
+            }
+        }
+        shown
+    }
+
+    /// The table as lines of text, with escape sequences coloring the styled fields.
+    pub fn render_with_colors(&self, grid: &Grid, colors: bool) -> String {
+        let layout = self.layout(grid);
+        let mut text = String::new();
+        let header = layout
+            .iter()
+            .map(|(i, w)| {
+                let column = &grid.columns[*i];
+                let style = if colors { Style::Bold } else { Style::Plain };
+                vec![(truncate(&column.name, *w), style)]
+            })
+            .collect::<Vec<_>>();
+        push_lines(&mut text, &header, &layout);
+        let rule = layout
+            .iter()
+            .map(|(_, w)| vec![("-".repeat(*w), Style::Plain)])
+            .collect::<Vec<_>>();
+        push_lines(&mut text, &rule, &layout);
+        for row in &grid.rows {
+            let cells = layout
+                .iter()
+                .map(|(i, w)| {
+                    let field = &row[*i];
+                    let style = if colors { field.style } else { Style::Plain };
+                    let lines = match grid.columns[*i].overflow {
+                        Overflow::Truncate => vec![truncate(&field.text, *w)],
+                        Overflow::Wrap => wrap(&field.text, *w),
+                    };
+                    lines.into_iter().map(|l| (l, style)).collect()
+                })
+                .collect::<Vec<_>>();
+            push_lines(&mut text, &cells, &layout);
+        }
+        text
+    }
+}
+
+impl Renderer for Compact {
+    fn render(&self, grid: &Grid) -> String {
+        self.render_with_colors(grid, false)
+    }
+
+    fn print(&self, grid: &Grid) {
+        print!(
+            "{}",
+            self.render_with_colors(grid, std::io::stdout().is_terminal())
+        );
+    }
+}
+
+/// Appends the lines of a row whose cells can span several lines.
+fn push_lines(text: &mut String, cells: &[Vec<(String, Style)>], layout: &[(usize, usize)]) {
+    let height = cells.iter().map(|c| c.len()).max().unwrap_or(0).max(1);
+    for line in 0..height {
+        let mut out = String::new();
+        for (position, (cell, (_, width))) in cells.iter().zip(layout).enumerate() {
+            if position > 0 {
+                out.push_str(SEPARATOR);
+            }
+            let (part, style) = cell
+                .get(line)
+                .map(|(part, style)| (part.as_str(), *style))
+                .unwrap_or(("", Style::Plain));
+            if style == Style::Plain || part.is_empty() {
+                out.push_str(part);
+            } else {
+                out.push_str(style.escape());
+                out.push_str(part);
+                out.push_str("\x1b[0m");
+            }
+            out.push_str(&" ".repeat(width.saturating_sub(part.width())));
+        }
+        text.push_str(out.trim_end());
+        text.push('\n');
+    }
+}
+
+/// The text on a single line, cut to the width with an ellipsis when it is wider.
+fn truncate(text: &str, width: usize) -> String {
+    let text = text.replace('\n', " ");
+    if text.width() <= width {
+        return text;
+    }
+    let mut cut = String::new();
+    let mut cut_width = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if cut_width + w + 1 > width {
+            break;
+        }
+        cut.push(c);
+        cut_width += w;
+    }
+    cut.push('…');
+    cut
+}
+
+/// The text in lines of at most the width, broken between words, and inside of the words
+/// wider than the width.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            let separator = if line.is_empty() { 0 } else { 1 };
+            if line.width() + separator + word.width() <= width {
+                if separator == 1 {
+                    line.push(' ');
+                }
+                line.push_str(word);
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for c in word.chars() {
+                if line.width() + c.width().unwrap_or(0) > width && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(c);
+            }
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// The renderer asked for on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RendererKind {
+    Classic,
+    Compact,
+}
+
+impl FromStr for RendererKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(RendererKind::Classic),
+            "compact" => Ok(RendererKind::Compact),
+            _ => Err(format!(
+                "unknown renderer \"{}\", expected classic or compact",
+                s
+            )),
+        }
+    }
+}
+
+/// The renderer used, with the width it fits tables to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Classic,
+    Compact { width: usize },
+}
+
+impl Layout {
+    /// The renderer asked for, or the compact one on terminals narrower than `COMPACT_BELOW`.
+    /// Outside of a terminal, tables are classic unless asked otherwise.
+    pub fn choose(kind: Option<RendererKind>, terminal_width: Option<usize>) -> Self {
+        match (kind, terminal_width) {
+            (Some(RendererKind::Classic), _) => Layout::Classic,
+            (Some(RendererKind::Compact), width) => Layout::Compact {
+                width: width.unwrap_or(DEFAULT_WIDTH),
+            },
+            (None, Some(width)) if width < COMPACT_BELOW => Layout::Compact { width },
+            (None, _) => Layout::Classic,
+        }
+    }
+
+    pub fn renderer(self) -> Box<dyn Renderer> {
+        match self {
+            Layout::Classic => Box::new(Classic),
+            Layout::Compact { width } => Box::new(Compact { width }),
+        }
+    }
+}
+
+/// The number of cells of the bars drawn by `bar`.
+const BAR_WIDTH: usize = 8;
+
+/// Draws a fraction from 0.0 to 1.0 as a bar followed by its percentage, like
+/// `[####----] 47%`.
+pub fn bar(fraction: f32) -> String {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let full = (fraction * BAR_WIDTH as f32).round() as usize;
+    format!(
+        "[{}{}] {}%",
+        "#".repeat(full),
+        "-".repeat(BAR_WIDTH - full),
+        (fraction * 100.0).round()
+    )
+}
+
+/// The width of the terminal, if any.
+pub fn terminal_width() -> Option<usize> {
+    term_size::dimensions().map(|(width, _)| width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Item instances, with columns like those of `ri`.
+    fn instances() -> Grid {
+        let mut grid = Grid::new(vec![
+            Column::new("id"),
+            Column::new("type name").wrap(),
+            Column::new("quantity"),
+            Column::new("model").priority(2),
+            Column::new("serial").priority(1),
+            Column::new("extra").priority(0).wrap(),
+            Column::new("location").priority(7),
+            Column::new("expires at"),
+        ]);
+        grid.add_row(fields![
+            1,
+            "Crème fraîche",
+            "0.5 kg",
+            "",
+            "",
+            "bought for the tarte flambée of Sunday",
+            "fridge",
+            "2021-06-01",
+        ]);
+        grid.add_row(vec![
+            Field::new(2),
+            Field::new("味噌"),
+            Field::new(1),
+            Field::new("DX-2000"),
+            Field::new("SN123456789"),
+            Field::new(""),
+            Field::new("pantry"),
+            Field::styled("2020-01-01", Style::Red),
+        ]);
+        grid
+    }
+
+    fn render(grid: &Grid, width: usize) -> String {
+        Compact { width }.render(grid)
+    }
+
+    #[test]
+    fn wide_terminals_show_every_column() {
+        assert_eq!(
+            render(&instances(), 120),
+            "\
+id  type name      quantity  model    serial       extra                                   location  expires at
+--  -------------  --------  -------  -----------  --------------------------------------  --------  ----------
+1   Crème fraîche  0.5 kg                          bought for the tarte flambée of Sunday  fridge    2021-06-01
+2   味噌           1         DX-2000  SN123456789                                          pantry    2020-01-01
+"
+        );
+    }
+
+    #[test]
+    fn low_priority_columns_are_dropped_first() {
+        assert_eq!(
+            render(&instances(), 80),
+            "\
+id  type name      quantity  model    serial       location  expires at
+--  -------------  --------  -------  -----------  --------  ----------
+1   Crème fraîche  0.5 kg                          fridge    2021-06-01
+2   味噌           1         DX-2000  SN123456789  pantry    2020-01-01
+"
+        );
+        assert_eq!(
+            render(&instances(), 60),
+            "\
+id  type name      quantity  model    location  expires at
+--  -------------  --------  -------  --------  ----------
+1   Crème fraîche  0.5 kg             fridge    2021-06-01
+2   味噌           1         DX-2000  pantry    2020-01-01
+"
+        );
+        assert_eq!(
+            render(&instances(), 40),
+            "\
+id  type name      quantity  expires at
+--  -------------  --------  ----------
+1   Crème fraîche  0.5 kg    2021-06-01
+2   味噌           1         2020-01-01
+"
+        );
+    }
+
+    #[test]
+    fn narrow_terminals_wrap_and_truncate() {
+        assert_eq!(
+            render(&instances(), 36),
+            "\
+id  type name   quantity  expires at
+--  ----------  --------  ----------
+1   Crème       0.5 kg    2021-06-01
+    fraîche
+2   味噌        1         2020-01-01
+"
+        );
+        assert_eq!(
+            render(&instances(), 30),
+            "\
+id  type na…  quantity  expires…
+--  --------  --------  --------
+1   Crème     0.5 kg    2021-06…
+    fraîche
+2   味噌      1         2020-01…
+"
+        );
+    }
+
+    #[test]
+    fn tables_too_wide_for_their_minimums_overflow() {
+        let lines = render(&instances(), 10);
+        assert!(lines.lines().all(|l| l.width() > 10));
+        assert_eq!(lines.lines().count(), 5);
+    }
+
+    #[test]
+    fn styles_are_colored_on_request() {
+        let text = Compact { width: 60 }.render_with_colors(&instances(), true);
+        assert!(text.starts_with("\x1b[1mid\x1b[0m  "));
+        assert!(text.contains("\x1b[31m2020-01-01\x1b[0m"));
+        assert!(!render(&instances(), 60).contains('\x1b'));
+    }
+
+    #[test]
+    fn bars_show_the_fraction_and_its_percentage() {
+        assert_eq!(bar(0.47), "[####----] 47%");
+        assert_eq!(bar(1.0), "[########] 100%");
+        assert_eq!(bar(0.0), "[--------] 0%");
+        assert_eq!(bar(1.5), "[########] 100%");
+        assert_eq!(bar(0.01), "[--------] 1%");
+    }
+
+    #[test]
+    fn fields_are_cut_by_display_width() {
+        assert_eq!(truncate("abcdef", 6), "abcdef");
+        assert_eq!(truncate("abcdefg", 6), "abcde…");
+        assert_eq!(truncate("味噌味噌", 6), "味噌…");
+        assert_eq!(truncate("two\nlines", 9), "two lines");
+        assert_eq!(wrap("", 5), vec![""]);
+        assert_eq!(wrap("a bb ccc", 4), vec!["a bb", "ccc"]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("one\ntwo", 10), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn the_renderer_depends_on_the_terminal() {
+        assert_eq!(Layout::choose(None, None), Layout::Classic);
+        assert_eq!(Layout::choose(None, Some(100)), Layout::Classic);
+        assert_eq!(
+            Layout::choose(None, Some(99)),
+            Layout::Compact { width: 99 }
+        );
+        assert_eq!(
+            Layout::choose(Some(RendererKind::Classic), Some(40)),
+            Layout::Classic
+        );
+        assert_eq!(
+            Layout::choose(Some(RendererKind::Compact), None),
+            Layout::Compact { width: 80 }
+        );
+        assert!("fancy".parse::<RendererKind>().is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! A session running many commands on an inventory loaded once, see the repl subcommand.
+//!
+//! Each line is a command line without the program name, like `ri -e` or
+//! `ci rice -l "top shelf"`, parsed like the arguments of the program. The global options of
+//! a line add to those of the session, but the inventory is always the one of the session.
+//! A line failing, with an error or by panicking, leaves the inventory as it was before it.
+//! The commands never ask questions, like with `--no-interactive`, since their answers
+//! would be read as the next lines.
+//!
+//! SIGINT and SIGTERM end the session like `quit`, saving what is pending, see `autosave`.
+
+use crate::autosave::SaveManager;
+use crate::error::{CliError, Operation};
+use crate::{Command, Manager};
+use inv_manager::Inventory;
+use log::{error, info, warn};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use structopt::StructOpt;
+
+/// Splits a line into arguments at whitespace, like a shell: quotes group words, and a
+/// backslash escapes the next character outside of single quotes.
+pub fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => arg.get_or_insert_with(String::new).push(c),
+            (_, '\\') => match chars.next() {
+                Some(c) => arg.get_or_insert_with(String::new).push(c),
+                None => return Err("the line ends with a backslash".to_string()),
+            },
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("the quote {} is not closed", q));
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// What a line of the session asks for.
+enum Line {
+    Empty,
+    Save,
+    Reload,
+    Quit,
+    Command(Box<Manager>),
+}
+
+/// Parses a line, using the session for what the line doesn't set.
+fn parse_line(line: &str, session: &Manager) -> Result<Line, String> {
+    let args = split_line(line)?;
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => return Ok(Line::Empty),
+        [first, ..] if first.starts_with('#') => return Ok(Line::Empty),
+        ["save"] => return Ok(Line::Save),
+        ["reload"] => return Ok(Line::Reload),
+        ["quit"] | ["exit"] => return Ok(Line::Quit),
+        _ => {}
+    }
+    let mut manager = Manager::from_iter_safe(std::iter::once("inv".to_string()).chain(args))
+        .map_err(|e| e.message)?;
+    if manager.stdin || manager.stdout || manager.from_published.is_some() || manager.fix_ids {
+        return Err(
+            "--stdin, --stdout, --from-published and --fix-ids can't be used in a session"
+                .to_string(),
+        );
+    }
+    if let Some(
+        command @ (Command::Repl
+        | Command::Archive(_)
+        | Command::Migrate { .. }
+        | Command::Encrypt
+        | Command::Decrypt),
+    ) = &manager.command
+    {
+        return Err(format!(
+            "{} can't be used in a session, quit it first",
+            command.name()
+        ));
+    }
+    manager.workdir = session.workdir.clone();
+    manager.inventory_name = session.inventory_name.clone();
+    manager.minimal |= session.minimal;
+    manager.header |= session.header;
+    manager.timestamps |= session.timestamps;
+    manager.machine |= session.machine;
+    // The session reads stdin, so its commands can't ask questions there.
+    manager.no_interactive = true;
+    manager.renderer = manager.renderer.or(session.renderer);
+    Ok(Line::Command(Box::new(manager)))
+}
+
+/// How often the session wakes up to save pending changes and check for signals while it
+/// waits for a line.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Sets the returned flag on SIGINT and SIGTERM instead of ending the process, so that a
+/// session can save what is pending before exiting.
+pub fn stop_on_signals() -> io::Result<Arc<AtomicBool>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, stop.clone())?;
+    }
+    Ok(stop)
+}
+
+/// Runs the commands read from `input` until `quit`, its end or `stop` being set, then
+/// saves what is pending. `input` is read on another thread, so that pending changes are
+/// saved once their interval passed even when no line comes. `save` writes the inventory
+/// and `reload` loads it again from its files, dropping the changes that are not saved yet.
+pub fn session<F: FnMut(&Inventory) -> Result<(), CliError>>(
+    session: &Manager,
+    inventory: &mut Inventory,
+    input: impl BufRead + Send + 'static,
+    mut saver: SaveManager<F>,
+    mut reload: impl FnMut() -> Result<Inventory, CliError>,
+    stop: &AtomicBool,
+) -> Result<(), CliError> {
+    let interactive = session.interactive();
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in input.lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let mut prompt = true;
+    while !stop.load(Ordering::Relaxed) {
+        if interactive && prompt {
+            eprint!("inv> ");
+            let _ = io::stderr().flush();
+        }
+        let line = match lines.recv_timeout(TICK) {
+            Ok(line) => line.map_err(|e| CliError::io(Operation::Read, Path::new("<stdin>"), e))?,
+            Err(RecvTimeoutError::Timeout) => {
+                prompt = false;
+                if let Err(e) = saver.tick(inventory) {
+                    error!("Error: {}", e);
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        prompt = true;
+        match parse_line(&line, session) {
+            Err(e) => warn!("{}", e),
+            Ok(Line::Empty) => {}
+            Ok(Line::Quit) => break,
+            Ok(Line::Save) => match saver.save_now(inventory) {
+                Ok(()) => info!("Saved"),
+                Err(e) => error!("Error: {}", e),
+            },
+            Ok(Line::Reload) => match reload() {
+                Ok(reloaded) => {
+                    if saver.is_pending() {
+                        info!("The changes that were not saved are discarded");
+                    }
+                    *inventory = reloaded;
+                    saver.discard();
+                }
+                Err(e) => error!("Error: {}", e),
+            },
+            Ok(Line::Command(manager)) => {
+                let before = inventory.clone();
+                match panic::catch_unwind(AssertUnwindSafe(|| manager.exec(inventory))) {
+                    Ok(Ok(mut result)) => {
+                        result.record_changes(&before, inventory);
+                        manager.print(&result, inventory);
+                        if result.has_changes() {
+                            if let Err(e) = saver.changed(inventory) {
+                                error!("Error: {}", e);
+                            }
+                        }
+                        // update-state records its run once what it trashed is saved.
+                        if result.state_update.is_some() {
+                            let saved = if saver.is_pending() {
+                                saver.save_now(inventory)
+                            } else {
+                                Ok(())
+                            };
+                            if let Err(e) = saved.and_then(|()| manager.record_update(&result)) {
+                                error!("Error: {}", e);
+                            }
+                        }
+                    }
+                    Ok(Err(e)) => {
+                        *inventory = before;
+                        error!("Error: {}", e);
+                    }
+                    Err(_) => {
+                        *inventory = before;
+                        warn!("The command failed, the inventory was not changed");
+                    }
+                }
+            }
+        }
+        if let Err(e) = saver.tick(inventory) {
+            error!("Error: {}", e);
+        }
+    }
+    saver.shutdown(inventory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_split_like_a_shell_would() {
+        let cases: &[(&str, &[&str])] = &[
+            ("", &[]),
+            ("  ri   -e ", &["ri", "-e"]),
+            (
+                r#"ci rice -l "top shelf""#,
+                &["ci", "rice", "-l", "top shelf"],
+            ),
+            (
+                r#"ci 'it''s' -x "a \"b\"""#,
+                &["ci", "its", "-x", "a \"b\""],
+            ),
+            (
+                r"ui 3 -x top\ shelf ''",
+                &["ui", "3", "-x", "top shelf", ""],
+            ),
+            (r"ui 3 -x 'a\b'", &["ui", "3", "-x", r"a\b"]),
+        ];
+        for (line, args) in cases {
+            assert_eq!(split_line(line).unwrap(), *args, "{}", line);
+        }
+        assert!(split_line("ci \"rice").is_err());
+        assert!(split_line("ci rice\\").is_err());
+    }
+
+    #[test]
+    fn lines_never_ask_questions() {
+        let session = Manager::from_iter(&["inv", "-n", "home", "repl"]);
+        match parse_line("trash-where --all", &session) {
+            Ok(Line::Command(manager)) => assert!(manager.no_interactive),
+            _ => panic!("trash-where is a command"),
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Markdown report of the inventory, for sharing with people who don't use inv.
+
+use inv_manager::*;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The formats `export` can write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Markdown,
+    /// A workbook with sheets of the item types, the item instances and the digest numbers.
+    #[cfg(feature = "xlsx")]
+    Xlsx,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(Format::Markdown),
+            #[cfg(feature = "xlsx")]
+            "xlsx" => Ok(Format::Xlsx),
+            #[cfg(feature = "xlsx")]
+            _ => Err(format!("unknown format {}, expected markdown or xlsx", s)),
+            #[cfg(not(feature = "xlsx"))]
+            _ => Err(format!("unknown format {}, expected markdown", s)),
+        }
+    }
+}
+
+/// How the item instances are split into sections.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Location,
+    /// The class of the item type.
+    TypeClass,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "location" => Ok(GroupBy::Location),
+            "type-class" => Ok(GroupBy::TypeClass),
+            _ => Err(format!(
+                "unknown grouping {}, expected location or type-class",
+                s
+            )),
+        }
+    }
+}
+
+/// Renders the item instances that are not removed, with a section per group
+/// ordered by name and the item instances without a group last, followed by the
+/// item types to buy.
+pub fn markdown(
+    inventory: &Inventory,
+    inventory_name: &str,
+    now: SystemTime,
+    group_by: GroupBy,
+) -> String {
+    let type_of = |id: TypeId| inventory.item_types.iter().find(|t| t.id == id);
+    let type_name = |id: TypeId| inventory.type_name(id);
+
+    let mut groups: BTreeMap<Option<String>, Vec<&ItemInstance>> = BTreeMap::new();
+    for ii in inventory
+        .item_instances
+        .iter()
+        .filter(|ii| ii.removed_at.is_none())
+    {
+        let group = match group_by {
+            GroupBy::Location => ii.location.clone(),
+            GroupBy::TypeClass => type_of(ii.item_type).and_then(|t| t.class.clone()),
+        };
+        groups.entry(group).or_default().push(ii);
+    }
+    // `None` sorts first in a BTreeMap, but the items without a group are the least interesting.
+    let mut groups = groups.into_iter().collect::<Vec<_>>();
+    let without_group = groups.iter().filter(|(g, _)| g.is_none()).count();
+    groups.rotate_left(without_group);
+
+    let mut out = format!("# Inventory {}\n\n", escape(inventory_name));
+    out.push_str(&format!("Generated on {}.\n", date(now)));
+    for (group, mut instances) in groups {
+        let title = group.unwrap_or_else(|| match group_by {
+            GroupBy::Location => "No location".to_string(),
+            GroupBy::TypeClass => "No class".to_string(),
+        });
+        out.push_str(&format!("\n## {}\n\n", escape(&title)));
+        out.push_str("| Item | Quantity | Expires | Notes |\n");
+        out.push_str("| --- | ---: | --- | --- |\n");
+        instances.sort_by_key(|ii| (type_name(ii.item_type), ii.id));
+        for ii in instances {
+            let expires = match (ii.expires_at, ii.best_before_at) {
+                (Some(exp), _) => date(exp),
+                (None, Some(bb)) => format!("best before {}", date(bb)),
+                (None, None) => String::new(),
+            };
+            out.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                escape(&type_name(ii.item_type)),
+                ii.quantity,
+                expires,
+                escape(ii.extra.as_deref().unwrap_or(""))
+            ));
+        }
+    }
+
+    out.push_str("\n## Needs buying\n\n");
+    let shortages = inventory.shortages();
+    if shortages.is_empty() {
+        out.push_str("Nothing.\n");
+    } else {
+        out.push_str("| Item | Location | Missing |\n");
+        out.push_str("| --- | --- | ---: |\n");
+        for s in shortages {
+            out.push_str(&format!(
+                "| {} | {} | {} |\n",
+                escape(&type_name(s.type_id)),
+                escape(s.location.as_deref().unwrap_or("anywhere")),
+                (s.minimum - s.quantity).ceil()
+            ));
+        }
+    }
+    out
+}
+
+/// Formats the UTC date as `YYYY-MM-DD`.
+fn date(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()[..10].to_string()
+}
+
+/// Escapes the pipes, which would end table cells, and puts the text on a single line.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|")
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn inventory(start: SystemTime) -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(start)));
+        let mut add_type = |name: &str, minimum: f32, class: Option<&str>| {
+            let mut item_type = ItemTypeBuilder::default()
+                .name(name.to_string())
+                .minimum_quantity(minimum)
+                .build()
+                .unwrap();
+            item_type.class = class.map(|c| c.to_string());
+            inventory.add_item_type(item_type)
+        };
+        let rice = add_type("rice", 4.0, Some("pantry"));
+        let milk = add_type("milk", 0.0, Some("fresh"));
+        let cables = add_type("USB A|C cables", 0.0, None);
+        inventory.item_types[1].set_minimum_at("kitchen", 2.0);
+        let instances = vec![
+            (rice, 2.0, Some("kitchen"), Some(200), None, Some("basmati")),
+            (milk, 0.5, Some("kitchen"), Some(3), None, None),
+            (
+                cables,
+                4.0,
+                None,
+                None,
+                None,
+                Some("in the box | top shelf"),
+            ),
+            (
+                rice,
+                1.0,
+                Some("cellar"),
+                None,
+                Some(400),
+                Some("for\nguests"),
+            ),
+        ];
+        for (type_id, quantity, location, expires, best_before, extra) in instances {
+            inventory
+                .add_item_instance(
+                    ItemInstanceBuilder::default()
+                        .item_type(type_id)
+                        .quantity(quantity)
+                        .location(location.map(|l| l.to_string()))
+                        .expires_at(expires.map(|d| start + DAY * d))
+                        .best_before_at(best_before.map(|d| start + DAY * d))
+                        .extra(extra.map(|e| e.to_string()))
+                        .build()
+                        .unwrap(),
+                )
+                .unwrap();
+        }
+        let trashed = inventory
+            .add_item_instance(
+                ItemInstanceBuilder::default()
+                    .item_type(milk)
+                    .quantity(1.0)
+                    .location(Some("kitchen".to_string()))
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        inventory.trash(trashed);
+        inventory
+    }
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    #[test]
+    fn matches_golden_file_by_location() {
+        let report = markdown(&inventory(start()), "home", start(), GroupBy::Location);
+        assert_eq!(report, include_str!("../fixtures/report/by_location.md"));
+    }
+
+    #[test]
+    fn matches_golden_file_by_type_class() {
+        let report = markdown(&inventory(start()), "home", start(), GroupBy::TypeClass);
+        assert_eq!(report, include_str!("../fixtures/report/by_type_class.md"));
+    }
+
+    #[test]
+    fn cells_stay_on_one_line() {
+        assert_eq!(escape("a|b\n  c"), "a\\|b c");
+    }
+}
@@ -0,0 +1,709 @@
+//! The structured result of a command.
+//!
+//! In machine mode, the result is printed on stdout as a single json object.
+//! Otherwise it is rendered for humans, which is the only other place printing to stdout.
+//!
+//! The json schema is stable within a `schema_version`. Every field is always present:
+//! - `schema_version`: the version of this schema, currently 1.
+//! - `command`: the name of the subcommand, as typed on the command line.
+//! - `timestamp`: RFC 3339 time at which the command ran.
+//! - `created_id`: the id of the item type or item instance created by the command, or null.
+//! - `listing`: null, or `{"kind": "types" | "instances" | "shortages" | "prices", "items": [...]}`
+//!   for read commands. Types and instances have the same fields as in the inventory files,
+//!   plus `shelf_life` for the instances of "instances": the part of their shelf life left,
+//!   from 1 when opened, or added, to 0 at their expiry, or null without both dates.
+//!   For `ri --group-by`, the kind is "instance_groups" and items are groups with the fields
+//!   `name` (null for no location), `items` (instances) and `subtotal`, an object with the
+//!   fields `quantity`, `count` and `soonest_expiry`.
+//!   Shortages have the fields `type_id`, `location` (null for the total of the item type),
+//!   `quantity`, `minimum`, `package_size` and `packages_to_buy` (null without a package
+//!   size), plus `last_paid` (a price or null) and `average_of_last_3`
+//!   when prices were requested. For `list-missing --group-by vendor`, the kind is
+//!   "shopping_groups" and items are groups with the fields `vendor` (null for the item types
+//!   without one) and `items`, shortages like those of "shortages". Prices have the fields `at`, `unit_price`, `instance_id`
+//!   and `location`. For `digest`, the kind is "digest" and `items` is an object with the
+//!   sections that are not empty among `expired`, `expiring`, `to_buy`, `opened_too_long`,
+//!   `opened_reminders` and `over_capacity`, like the items of "location_usage", plus
+//!   `health` with --health, like the items of `health`.
+//!   For `classes list`, the kind is "classes" and items have the same fields as in the
+//!   classes file. For `bulk-update-types`, the kind is "type_updates" and items are the
+//!   changes that are, or would be with --dry-run, made to the item types, like `type_changes`.
+//!   For `waste-report`, the kind is "waste" and items have the fields `month` (`YYYY-MM`),
+//!   `reason`, `instances`, `quantity` and `value`, given away item instances excluded.
+//!   For `list-given`, the kind is "gifts" and items have the fields `instance_id`,
+//!   `type_id`, `quantity`, `value` (null without a value), `recipient` and `given_at`.
+//!   For `state-at`, the kind is "state" and `items` is an object with the fields `at`,
+//!   `types` and `instances`, the item instances removed by then included.
+//!   For `use` with several item types, the kind is "uses" and items have the fields
+//!   `type_id`, `type_name`, `requested`, `used` and `left`, the quantity left afterwards.
+//!   For `normalize-locations --dry-run`, the kind is "locations" and items have the fields
+//!   `location`, `instances`, `minimums` and `rename_to`, the proposed name or null.
+//!   For `health`, the kind is "health" and `items` is an object with the fields `score`
+//!   (0 to 100), `types_with_ttl`, `types_with_minimum`, `types_with_unit`,
+//!   `instances_with_location`, `instances_with_expiry` and `instances_with_value` (each
+//!   `{"set", "total"}`), `stale_instances` and `most_incomplete`, records with the fields
+//!   `kind` ("type" or "instance"), `id`, `name`, `missing` and `weight`.
+//!   For `categories`, the kind is "categories" and `items` is the root category, an object
+//!   with the fields `name` and `path` (both empty for the root), `type_ids` (the item types
+//!   directly in it, those without a category for the root), `own` and `rollup`, the totals
+//!   of those item types and of the whole subtree, and `children`, the subcategories.
+//!   Totals have the fields `types`, `quantity` (null when the units differ), `unit`,
+//!   `value` and `below_minimum`.
+//!   For `value-report`, the kind is "values" and `items` is an object with the fields
+//!   `depreciated`, whether --depreciated was given, and `types`, whose entries have the
+//!   fields `type_id`, `instances`, `value` and `current_value`.
+//!   For `tco`, the kind is "costs" and items have the fields `type_id`, `instances`,
+//!   `excluded`, `purchased`, `wasted`, `in_stock`, `consumed`, `consumed_quantity`,
+//!   `cost_per_unit` and `waste_percent`, both null when they can't be computed.
+//!   For `find-ref`, the kind is "references" and items are instances, those in the trash
+//!   included.
+//!   For `update-state`, the kind is "expiries" and items have the fields `instance_id`,
+//!   `type_id`, `expired_at` and `auto_trash`, whether the item instance is put in the trash.
+//!   For `location-list`, the kind is "location_usage" and items have the fields `name`,
+//!   `capacity` and `capacity_unit` (null when not set), `used`, the quantity at the location
+//!   in the unit of the capacity, and `uncounted`, the ids of the item instances there whose
+//!   unit can not be converted to it.
+//!   For `list-idle`, the kind is "idle" and items have the fields `kind` ("type" or
+//!   "instance"), `id`, `name`, the name of the item type, and `last_activity`, the last
+//!   activity of the item type or when the item instance was added, null when unknown.
+//!   For `replace`, the kind is "replacements" and items have the fields `id`, of the item
+//!   type or item instance, `before` and `after`, the text of the field.
+//!   For `import-legacy`, the kind is "legacy_import" and items have the fields `kind`
+//!   ("type" or "instance"), `line`, `legacy_id`, `id` (null when skipped), `name` and
+//!   `result`: "kept", "remapped", "reused" or why the line was skipped.
+//!   For `profiles`, the kind is "profiles" and items have the fields `profile`, its name,
+//!   `default`, whether it is the default profile, `workdir`, `name` and `format`.
+//!   Without a subcommand, `command` is empty, the kind is "dashboard" and `items` is an
+//!   object with the fields `inventory` (its name), `types`, `instances`, `expired`,
+//!   `below_minimum` (the number of item types) and `soonest_expiries`, the next three
+//!   expiries like the entries of the digest.
+//! - `type_changes` and `instance_changes`: every item type and item instance modified by the
+//!   command, as `{"id", "before", "after"}` where `before` is null for created items
+//!   and `after` is null for deleted items.
+//! - `minimums`: for `use` and `ci`, the item types with a minimum quantity whose quantity
+//!   changed, as `{"type_id", "quantity", "minimum", "below_minimum", "crossed_threshold"}`
+//!   where `crossed_threshold` is true when the command moved the item type below its
+//!   minimum, or back to it. Empty for the other commands.
+//! - `messages`: lines of human readable information.
+//! - `text`: the document generated by export commands, or null.
+//! - `profile`: the name of the profile the options came from, see `profiles`, or null.
+
+use crate::legacy::ImportedRecord;
+use crate::profiles::ProfileEntry;
+use crate::state;
+use crate::{
+    print_categories, print_classes, print_costs, print_dashboard, print_digest, print_expiries,
+    print_gifts, print_health, print_idle, print_instance_groups, print_item_instances,
+    print_item_types, print_legacy_import, print_location_usage, print_locations, print_prices,
+    print_profiles, print_references, print_replacements, print_shopping_groups,
+    print_shopping_list, print_state, print_type_updates, print_uses, print_values, print_waste,
+    Output,
+};
+use inv_manager::*;
+use serde::Serialize;
+use std::ops::Deref;
+use std::time::SystemTime;
+
+/// The version of the json schema of `CommandResult`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The state of an item before and after a command.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Change<T: Identified> {
+    pub id: T::Id,
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+/// A location name, how much it is used and the name it would be renamed to.
+#[derive(Serialize, Debug, Clone)]
+pub struct LocationMapping {
+    #[serde(flatten)]
+    pub count: LocationCount,
+    pub rename_to: Option<String>,
+}
+
+/// The overview printed when no subcommand is given.
+#[derive(Serialize, Debug, Clone)]
+pub struct Dashboard {
+    pub inventory: String,
+    pub types: usize,
+    /// The item instances that are not removed.
+    pub instances: usize,
+    pub expired: usize,
+    /// The item types below one of their minimums.
+    pub below_minimum: usize,
+    pub soonest_expiries: Vec<DigestEntry>,
+}
+
+/// What `use` took from an item type, in its unit.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct UseSummary {
+    pub type_id: TypeId,
+    pub type_name: String,
+    pub requested: f32,
+    pub used: f32,
+    /// The quantity of the item type left afterwards.
+    pub left: f32,
+}
+
+/// A text field changed by `replace`, or that would be with --dry-run.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Replacement {
+    /// The id of the item type or of the item instance.
+    pub id: u32,
+    pub before: String,
+    pub after: String,
+}
+
+/// Where an item type changed by a command stands against its minimum quantity.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MinimumCheck {
+    pub type_id: TypeId,
+    /// The quantity of the item type after the command, see `Inventory::quantity_for_type`.
+    pub quantity: f32,
+    /// The minimum quantity of the day, see `ItemType::effective_minimum`.
+    pub minimum: f32,
+    pub below_minimum: bool,
+    /// Whether the item type was on the other side of its minimum before the command.
+    pub crossed_threshold: bool,
+}
+
+/// The items printed by a read command.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "kind", content = "items", rename_all = "snake_case")]
+pub enum Listing {
+    Types(Vec<ItemType>),
+    Instances(Vec<ListedInstance>),
+    InstanceGroups(Vec<InstanceGroup<ItemInstance>>),
+    Shortages(Vec<ShoppingItem>),
+    ShoppingGroups(Vec<VendorGroup<ShoppingItem>>),
+    Prices(Vec<PricePoint>),
+    Digest(Digest),
+    Classes(Vec<ItemClass>),
+    TypeUpdates(Vec<Change<ItemType>>),
+    Waste(Vec<WasteEntry>),
+    Gifts(Vec<Gift>),
+    State(PastState),
+    Locations(Vec<LocationMapping>),
+    Uses(Vec<UseSummary>),
+    Dashboard(Dashboard),
+    Health(HealthReport),
+    Categories(CategoryNode),
+    Values(ValueReport),
+    Costs(Vec<TypeCost>),
+    Profiles(Vec<ProfileEntry>),
+    References(Vec<ItemInstance>),
+    Expiries(Vec<ExpiryTransition>),
+    LocationUsage(Vec<LocationUsage>),
+    Idle(Vec<IdleRecord>),
+    Replacements(Vec<Replacement>),
+    LegacyImport(Vec<ImportedRecord>),
+}
+
+/// An item instance of the "instances" listing, with the part of its shelf life left.
+#[derive(Serialize, Debug, Clone)]
+pub struct ListedInstance {
+    #[serde(flatten)]
+    pub instance: ItemInstance,
+    /// See `ItemInstance::shelf_life_fraction`.
+    pub shelf_life: Option<f32>,
+}
+
+impl Deref for ListedInstance {
+    type Target = ItemInstance;
+
+    fn deref(&self) -> &ItemInstance {
+        &self.instance
+    }
+}
+
+/// The value of the inventory per item type.
+#[derive(Serialize, Debug, Clone)]
+pub struct ValueReport {
+    /// Whether the depreciated values are printed for humans.
+    pub depreciated: bool,
+    pub types: Vec<TypeValue>,
+}
+
+/// The inventory as it was at a time, rebuilt from the event log.
+#[derive(Serialize, Debug, Clone)]
+pub struct PastState {
+    pub at: SystemTime,
+    pub types: Vec<ItemType>,
+    pub instances: Vec<ItemInstance>,
+}
+
+/// A line of the shopping list.
+#[derive(Serialize, Debug, Clone)]
+pub struct ShoppingItem {
+    #[serde(flatten)]
+    pub shortage: Shortage,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub prices: Option<Prices>,
+}
+
+/// What was paid recently for an item type, per unit.
+#[derive(Serialize, Debug, Clone)]
+pub struct Prices {
+    pub last_paid: Option<PricePoint>,
+    pub average_of_last_3: Option<f32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandResult {
+    pub schema_version: u32,
+    pub command: &'static str,
+    pub timestamp: String,
+    /// The id of the item type or item instance created, depending on the command.
+    pub created_id: Option<u32>,
+    pub listing: Option<Listing>,
+    pub type_changes: Vec<Change<ItemType>>,
+    pub instance_changes: Vec<Change<ItemInstance>>,
+    pub minimums: Vec<MinimumCheck>,
+    pub messages: Vec<String>,
+    pub text: Option<String>,
+    pub profile: Option<String>,
+    /// Whether the registered locations changed, which are saved with the item types.
+    #[serde(skip)]
+    pub locations_changed: bool,
+    /// What update-state records once the inventory is saved, see `Manager::record_update`.
+    #[serde(skip)]
+    pub state_update: Option<state::Update>,
+}
+
+impl Default for CommandResult {
+    fn default() -> Self {
+        CommandResult {
+            schema_version: SCHEMA_VERSION,
+            command: "",
+            timestamp: String::new(),
+            created_id: None,
+            listing: None,
+            type_changes: vec![],
+            instance_changes: vec![],
+            minimums: vec![],
+            messages: vec![],
+            text: None,
+            profile: None,
+            locations_changed: false,
+            state_update: None,
+        }
+    }
+}
+
+impl CommandResult {
+    pub fn created(id: impl Into<u32>) -> Self {
+        CommandResult {
+            created_id: Some(id.into()),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn types<'a>(types: impl IntoIterator<Item = &'a ItemType>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Types(types.into_iter().cloned().collect())),
+            ..CommandResult::default()
+        }
+    }
+
+    /// The item instances, with the part of their shelf life left at a time.
+    pub fn instances<'a>(
+        instances: impl IntoIterator<Item = &'a ItemInstance>,
+        now: SystemTime,
+    ) -> Self {
+        let instances = instances
+            .into_iter()
+            .map(|ii| ListedInstance {
+                instance: ii.clone(),
+                shelf_life: ii.shelf_life_fraction(now),
+            })
+            .collect();
+        CommandResult {
+            listing: Some(Listing::Instances(instances)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn references<'a>(instances: impl IntoIterator<Item = &'a ItemInstance>) -> Self {
+        CommandResult {
+            listing: Some(Listing::References(
+                instances.into_iter().cloned().collect(),
+            )),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn expiries(transitions: Vec<ExpiryTransition>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Expiries(transitions)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn replacements(replacements: Vec<Replacement>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Replacements(replacements)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn legacy_import(records: Vec<ImportedRecord>) -> Self {
+        CommandResult {
+            listing: Some(Listing::LegacyImport(records)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn idle(records: Vec<IdleRecord>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Idle(records)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn location_usage(usage: Vec<LocationUsage>) -> Self {
+        CommandResult {
+            listing: Some(Listing::LocationUsage(usage)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn shopping_groups(groups: Vec<VendorGroup<ShoppingItem>>) -> Self {
+        CommandResult {
+            listing: Some(Listing::ShoppingGroups(groups)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn shopping_list(items: Vec<ShoppingItem>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Shortages(items)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn digest(digest: Digest) -> Self {
+        CommandResult {
+            listing: Some(Listing::Digest(digest)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn type_updates(changes: Vec<Change<ItemType>>) -> Self {
+        CommandResult {
+            listing: Some(Listing::TypeUpdates(changes)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn instance_groups(groups: Vec<InstanceGroup<ItemInstance>>) -> Self {
+        CommandResult {
+            listing: Some(Listing::InstanceGroups(groups)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn classes(classes: Vec<ItemClass>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Classes(classes)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn prices(prices: Vec<PricePoint>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Prices(prices)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn waste(waste: Vec<WasteEntry>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Waste(waste)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn gifts(gifts: Vec<Gift>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Gifts(gifts)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn state(state: PastState) -> Self {
+        CommandResult {
+            listing: Some(Listing::State(state)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn locations(locations: Vec<LocationMapping>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Locations(locations)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn health(report: HealthReport) -> Self {
+        CommandResult {
+            listing: Some(Listing::Health(report)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn categories(root: CategoryNode) -> Self {
+        CommandResult {
+            listing: Some(Listing::Categories(root)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn values(report: ValueReport) -> Self {
+        CommandResult {
+            listing: Some(Listing::Values(report)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn costs(costs: Vec<TypeCost>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Costs(costs)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn profiles(profiles: Vec<ProfileEntry>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Profiles(profiles)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn dashboard(dashboard: Dashboard) -> Self {
+        CommandResult {
+            listing: Some(Listing::Dashboard(dashboard)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn uses(uses: Vec<UseSummary>) -> Self {
+        CommandResult {
+            listing: Some(Listing::Uses(uses)),
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn messages(messages: Vec<String>) -> Self {
+        CommandResult {
+            messages,
+            ..CommandResult::default()
+        }
+    }
+
+    pub fn text(text: String) -> Self {
+        CommandResult {
+            text: Some(text),
+            ..CommandResult::default()
+        }
+    }
+
+    /// Fills the changes by comparing the inventory before and after the command.
+    pub fn record_changes(&mut self, before: &Inventory, after: &Inventory) {
+        self.type_changes = diff(&before.item_types, &after.item_types);
+        self.instance_changes = diff(&before.item_instances, &after.item_instances);
+        self.locations_changed = before.locations != after.locations;
+    }
+
+    /// Whether the command modified the inventory.
+    pub fn has_changes(&self) -> bool {
+        !self.type_changes.is_empty() || !self.instance_changes.is_empty() || self.locations_changed
+    }
+
+    /// Prints the result as a single line of json.
+    pub fn print_json(&self, output: Output) {
+        output.line(serde_json::to_string(self).expect("Failed to serialize the command result"));
+    }
+
+    /// Prints the result for humans. Changes are not printed.
+    pub fn print_human(&self, inventory: &Inventory, output: Output) {
+        if let Some(id) = self.created_id {
+            output.line(id);
+        }
+        match &self.listing {
+            Some(Listing::Types(types)) => {
+                print_item_types(&types.iter().collect::<Vec<_>>(), inventory, output)
+            }
+            Some(Listing::Instances(instances)) => print_item_instances(
+                &instances.iter().map(|l| &l.instance).collect::<Vec<_>>(),
+                inventory,
+                output,
+            ),
+            Some(Listing::InstanceGroups(groups)) => {
+                print_instance_groups(groups, inventory, output)
+            }
+            Some(Listing::Shortages(items)) => print_shopping_list(items, inventory, output),
+            Some(Listing::ShoppingGroups(groups)) => {
+                print_shopping_groups(groups, inventory, output)
+            }
+            Some(Listing::Prices(prices)) => print_prices(prices, output),
+            Some(Listing::Digest(digest)) => print_digest(digest, inventory.now(), output),
+            Some(Listing::Classes(classes)) => print_classes(classes, output),
+            Some(Listing::TypeUpdates(changes)) => print_type_updates(changes, output),
+            Some(Listing::Waste(waste)) => print_waste(waste, output),
+            Some(Listing::Gifts(gifts)) => print_gifts(gifts, inventory, output),
+            Some(Listing::State(state)) => print_state(state, output),
+            Some(Listing::Locations(locations)) => print_locations(locations, output),
+            Some(Listing::Uses(uses)) => print_uses(uses, output),
+            Some(Listing::Dashboard(dashboard)) => print_dashboard(dashboard, output),
+            Some(Listing::Health(report)) => print_health(report, output),
+            Some(Listing::Categories(root)) => print_categories(root, output),
+            Some(Listing::Values(report)) => print_values(report, inventory, output),
+            Some(Listing::Costs(costs)) => print_costs(costs, inventory, output),
+            Some(Listing::Profiles(profiles)) => print_profiles(profiles, output),
+            Some(Listing::References(instances)) => print_references(instances, inventory, output),
+            Some(Listing::Expiries(transitions)) => print_expiries(transitions, inventory, output),
+            Some(Listing::LocationUsage(usage)) => print_location_usage(usage, output),
+            Some(Listing::Idle(records)) => print_idle(records, output),
+            Some(Listing::Replacements(replacements)) => print_replacements(replacements, output),
+            Some(Listing::LegacyImport(records)) => print_legacy_import(records, output),
+            None => {}
+        }
+        self.messages.iter().for_each(|m| output.line(m));
+        if let Some(text) = &self.text {
+            output.text(text);
+        }
+    }
+}
+
+/// Returns the items that were added, removed or modified, ordered by id.
+pub fn diff<T: Clone + PartialEq + Identified>(before: &[T], after: &[T]) -> Vec<Change<T>> {
+    let id = T::id;
+    let mut ids = before
+        .iter()
+        .chain(after.iter())
+        .map(id)
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+    ids.into_iter()
+        .filter_map(|i| {
+            let b = before.iter().find(|t| id(t) == i);
+            let a = after.iter().find(|t| id(t) == i);
+            if b == a {
+                None
+            } else {
+                Some(Change {
+                    id: i,
+                    before: b.cloned(),
+                    after: a.cloned(),
+                })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_recorded() {
+        let mut before = Inventory::default();
+        let kept = before.add_item_type(
+            ItemTypeBuilder::default()
+                .name("kept".to_string())
+                .build()
+                .unwrap(),
+        );
+        let deleted = before.add_item_type(
+            ItemTypeBuilder::default()
+                .name("deleted".to_string())
+                .build()
+                .unwrap(),
+        );
+        let used = before
+            .add_item_instance(
+                ItemInstanceBuilder::default()
+                    .item_type(kept)
+                    .quantity(2.0)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        let mut after = before.clone();
+        after.delete_item_type(deleted);
+        after.use_instance(kept, Some(0.5)).unwrap();
+
+        let mut result = CommandResult::default();
+        result.record_changes(&before, &after);
+        assert_eq!(result.type_changes.len(), 1);
+        assert_eq!(result.type_changes[0].id, deleted);
+        assert!(result.type_changes[0].after.is_none());
+        assert_eq!(result.instance_changes.len(), 1);
+        let change = &result.instance_changes[0];
+        assert_eq!(change.id, used);
+        assert_eq!(change.before.as_ref().unwrap().quantity, 2.0);
+        assert_eq!(change.after.as_ref().unwrap().quantity, 1.5);
+    }
+
+    #[test]
+    fn json_has_a_stable_envelope() {
+        let result = CommandResult::created(InstanceId(3));
+        let json = serde_json::to_value(&result).unwrap();
+        let mut keys = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                "command",
+                "created_id",
+                "instance_changes",
+                "listing",
+                "messages",
+                "minimums",
+                "profile",
+                "schema_version",
+                "text",
+                "timestamp",
+                "type_changes"
+            ]
+        );
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["created_id"], 3);
+
+        let listing = serde_json::to_value(CommandResult::types(vec![])).unwrap();
+        assert_eq!(listing["listing"]["kind"], "types");
+        assert!(listing["listing"]["items"].as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn listed_instances_have_their_shelf_life() {
+        let start = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let instances = [
+            ItemInstance {
+                id: InstanceId(1),
+                added_at: Some(start),
+                expires_at: Some(start + 4 * day),
+                ..ItemInstance::default()
+            },
+            ItemInstance {
+                id: InstanceId(2),
+                ..ItemInstance::default()
+            },
+        ];
+        let json = serde_json::to_value(CommandResult::instances(&instances, start + day)).unwrap();
+        let items = &json["listing"]["items"];
+        assert_eq!(items[0]["id"], 1);
+        assert_eq!(items[0]["shelf_life"], 0.75);
+        assert!(items[0]["expires_at"].is_object());
+        assert!(items[1]["shelf_life"].is_null());
+    }
+}
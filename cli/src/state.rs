@@ -0,0 +1,81 @@
+//! The state of the maintenance jobs of an inventory, `<name>_state.json`. It holds the
+//! time of the last `update-state`, whose next run looks at the expiries passed since.
+//! The state of an encrypted inventory is `<name>_state.json.enc`, see `crypto`.
+
+use crate::crypto::{self, Cipher};
+use crate::error::{CliError, Operation};
+use crate::storage;
+use inv_manager::Event;
+use serde::{Deserialize, Serialize};
+use std::fs::{read, remove_file, rename};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct JobState {
+    /// When `update-state` last ran, which never goes back, see `transition::next_run`.
+    pub last_update: Option<SystemTime>,
+}
+
+/// What a run of `update-state` records once the item instances it trashed are saved: the
+/// expiries it found, for the event log, and the state after the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub expired: Vec<Event>,
+    pub state: JobState,
+}
+
+/// The state file of the inventory with this name, the encrypted one for an encrypted
+/// inventory.
+pub fn state_path(workdir: &Path, name: &str, encrypted: bool) -> PathBuf {
+    let path = workdir.join(format!("{}_state.json", name));
+    if encrypted {
+        crypto::encrypted_path(&path)
+    } else {
+        path
+    }
+}
+
+/// Reads the state, decrypting it with the cipher of an encrypted state file. The state is
+/// empty when the file doesn't exist.
+pub fn load(path: &Path, cipher: Option<&Cipher>) -> Result<JobState, CliError> {
+    let data = match read(path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(JobState::default()),
+        Err(e) => return Err(CliError::io(Operation::Read, path, e)),
+    };
+    let json = match cipher {
+        Some(cipher) => cipher.open(path, &data)?.into_bytes(),
+        None => data,
+    };
+    serde_json::from_slice(&json).map_err(|e| CliError::io(Operation::Parse, path, e.into()))
+}
+
+/// Replaces the state file, writing it completely first.
+pub fn save(path: &Path, cipher: Option<&Cipher>, state: &JobState) -> Result<(), CliError> {
+    let mut json = serde_json::to_string_pretty(state).expect("Failed to serialize the state");
+    if let Some(cipher) = cipher {
+        json = cipher.seal(&json)?;
+    }
+    let tmp = storage::write_tmp(path, json.as_bytes())?;
+    rename(&tmp, path).map_err(|e| CliError::io(Operation::Write, path, e))
+}
+
+/// Writes the state at `from` to `to`, decrypting it with `from_cipher` and encrypting it
+/// with `to_cipher`, then removes it, for `encrypt` and `decrypt`. Returns whether there
+/// was a state file.
+pub fn convert(
+    from: &Path,
+    from_cipher: Option<&Cipher>,
+    to: &Path,
+    to_cipher: Option<&Cipher>,
+) -> Result<bool, CliError> {
+    if !from.exists() {
+        return Ok(false);
+    }
+    save(to, to_cipher, &load(from, from_cipher)?)?;
+    remove_file(from).map_err(|e| CliError::io(Operation::Write, from, e))?;
+    Ok(true)
+}
@@ -0,0 +1,242 @@
+//! Saving of several files at once, so that a crash in the middle of a save can be finished.
+//!
+//! Each file is replaced atomically by a rename, but a crash between two renames would leave
+//! files that don't match, like an item instance whose item type was not saved. So before
+//! the renames, a journal with the new content of every file and its hash is written next
+//! to them, and it is removed once they are all replaced. Finding a journal when loading
+//! means that the last save was interrupted: the files whose content differs from the
+//! journal are written again.
+
+use crate::error::{CliError, Operation};
+use serde::{Deserialize, Serialize};
+use std::fs::{read, remove_file, rename, write};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Journal {
+    files: Vec<JournalEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct JournalEntry {
+    /// The file name, relative to the directory of the journal.
+    name: String,
+    /// The FNV-1a hash of the content, in hexadecimal.
+    hash: String,
+    content: String,
+}
+
+/// What `recover` found.
+#[derive(Debug, PartialEq)]
+pub enum Recovery {
+    /// There was no journal, the last save completed.
+    Clean,
+    /// The last save was interrupted and was finished. Contains the files that had to be
+    /// written again, which is none when the crash happened after the last rename.
+    Finished(Vec<PathBuf>),
+}
+
+/// Replaces the files with their new content as a whole, using the journal at this path.
+/// The files have to be in the same directory as the journal.
+pub fn save_files(journal: &Path, files: &[(&Path, String)]) -> Result<(), CliError> {
+    let mut tmps = vec![];
+    for (path, content) in files {
+        match write_tmp(path, content.as_bytes()) {
+            Ok(tmp) => tmps.push(tmp),
+            Err(e) => {
+                tmps.iter().for_each(|tmp| {
+                    let _ = remove_file(tmp);
+                });
+                return Err(e);
+            }
+        }
+    }
+    if let Err(e) = write_journal(journal, files) {
+        tmps.iter().for_each(|tmp| {
+            let _ = remove_file(tmp);
+        });
+        return Err(e);
+    }
+    for ((path, _), tmp) in files.iter().zip(&tmps) {
+        rename(tmp, path).map_err(|e| CliError::io(Operation::Write, path, e))?;
+    }
+    remove_file(journal).map_err(|e| CliError::io(Operation::Write, journal, e))
+}
+
+/// Finishes the save interrupted before the journal at this path was removed, if any.
+/// Nothing is changed when the journal can not be trusted.
+pub fn recover(journal: &Path) -> Result<Recovery, CliError> {
+    let data = match read(journal) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Recovery::Clean),
+        Err(e) => return Err(CliError::io(Operation::Read, journal, e)),
+    };
+    let interrupted = |reason: String| CliError::InterruptedSave {
+        journal: journal.to_path_buf(),
+        reason,
+    };
+    let parsed =
+        serde_json::from_slice::<Journal>(&data).map_err(|e| interrupted(e.to_string()))?;
+    for entry in &parsed.files {
+        if hash(entry.content.as_bytes()) != entry.hash {
+            return Err(interrupted(format!(
+                "the content saved for {} is damaged",
+                entry.name
+            )));
+        }
+        if Path::new(&entry.name).file_name() != Some(entry.name.as_ref()) {
+            return Err(interrupted(format!("{} is not a file name", entry.name)));
+        }
+    }
+    let dir = journal.parent().unwrap_or_else(|| Path::new(""));
+    let mut stale = vec![];
+    for entry in parsed.files {
+        let path = dir.join(&entry.name);
+        let current = read(&path).ok().map(|c| hash(&c));
+        if current.as_ref() != Some(&entry.hash) {
+            let tmp = write_tmp(&path, entry.content.as_bytes())?;
+            rename(&tmp, &path).map_err(|e| CliError::io(Operation::Write, &path, e))?;
+            stale.push(path);
+        } else {
+            // Left by the interrupted save if it crashed before renaming it.
+            let _ = remove_file(tmp_path(&path));
+        }
+    }
+    remove_file(journal).map_err(|e| CliError::io(Operation::Write, journal, e))?;
+    Ok(Recovery::Finished(stale))
+}
+
+/// The temporary file the content of the path is written to before replacing it.
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// Writes the data next to the path, returning the path of the temporary file.
+pub fn write_tmp(path: &Path, data: &[u8]) -> Result<PathBuf, CliError> {
+    let tmp = tmp_path(path);
+    if let Err(e) = write(&tmp, data) {
+        let _ = remove_file(&tmp);
+        return Err(CliError::io(Operation::Write, path, e));
+    }
+    Ok(tmp)
+}
+
+fn write_journal(journal: &Path, files: &[(&Path, String)]) -> Result<(), CliError> {
+    let files = files
+        .iter()
+        .map(|(path, content)| JournalEntry {
+            name: path
+                .file_name()
+                .expect("Saved files have a name")
+                .to_string_lossy()
+                .into_owned(),
+            hash: hash(content.as_bytes()),
+            content: content.clone(),
+        })
+        .collect();
+    let data = serde_json::to_vec(&Journal { files }).expect("Failed to serialize the journal");
+    let tmp = write_tmp(journal, &data)?;
+    rename(&tmp, journal).map_err(|e| CliError::io(Operation::Write, journal, e))
+}
+
+/// The 64 bits FNV-1a hash, which is stable across versions unlike the hasher of std.
+fn hash(data: &[u8]) -> String {
+    let hash = data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all};
+
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("inv_storage_{}_{}", test, std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A directory with two saved files and the journal of a save changing both,
+    /// interrupted after the first `renamed` renames.
+    fn interrupted_save(test: &str, renamed: usize) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = temp_dir(test);
+        let (a, b, journal) = (
+            dir.join("a.json"),
+            dir.join("b.json"),
+            dir.join("x.journal"),
+        );
+        save_files(&journal, &[(&a, "old a".into()), (&b, "old b".into())]).unwrap();
+        let files = [
+            (a.as_path(), "new a".to_string()),
+            (b.as_path(), "new b".into()),
+        ];
+        let tmps = files
+            .iter()
+            .map(|(path, content)| write_tmp(path, content.as_bytes()).unwrap())
+            .collect::<Vec<_>>();
+        write_journal(&journal, &files).unwrap();
+        for ((path, _), tmp) in files.iter().zip(&tmps).take(renamed) {
+            rename(tmp, path).unwrap();
+        }
+        (a, b, journal)
+    }
+
+    #[test]
+    fn saves_without_leaving_a_journal() {
+        let dir = temp_dir("save");
+        let (a, journal) = (dir.join("a.json"), dir.join("x.journal"));
+        save_files(&journal, &[(&a, "a".into())]).unwrap();
+        assert_eq!(read_to_string(&a).unwrap(), "a");
+        assert!(!journal.exists());
+        assert!(!tmp_path(&a).exists());
+        assert_eq!(recover(&journal).unwrap(), Recovery::Clean);
+    }
+
+    #[test]
+    fn crash_before_the_journal_keeps_the_old_files() {
+        let dir = temp_dir("before_journal");
+        let (a, journal) = (dir.join("a.json"), dir.join("x.journal"));
+        save_files(&journal, &[(&a, "old a".into())]).unwrap();
+        write_tmp(&a, b"new a").unwrap();
+        assert_eq!(recover(&journal).unwrap(), Recovery::Clean);
+        assert_eq!(read_to_string(&a).unwrap(), "old a");
+    }
+
+    #[test]
+    fn crash_at_each_rename_is_rolled_forward() {
+        for renamed in 0..=2 {
+            let (a, b, journal) = interrupted_save(&format!("rename_{}", renamed), renamed);
+            let stale = [a.clone(), b.clone()][renamed..].to_vec();
+            assert_eq!(recover(&journal).unwrap(), Recovery::Finished(stale));
+            assert_eq!(read_to_string(&a).unwrap(), "new a");
+            assert_eq!(read_to_string(&b).unwrap(), "new b");
+            assert!(!journal.exists());
+            assert!(!tmp_path(&a).exists() && !tmp_path(&b).exists());
+            assert_eq!(recover(&journal).unwrap(), Recovery::Clean);
+        }
+    }
+
+    #[test]
+    fn damaged_journal_changes_nothing() {
+        let (a, _, journal) = interrupted_save("damaged", 0);
+        let damaged = read_to_string(&journal).unwrap().replace("new b", "bad b");
+        write(&journal, damaged).unwrap();
+        let err = recover(&journal).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("the content saved for b.json is damaged"));
+        assert_eq!(read_to_string(&a).unwrap(), "old a");
+        assert!(journal.exists());
+
+        write(&journal, "{").unwrap();
+        assert!(matches!(
+            recover(&journal),
+            Err(CliError::InterruptedSave { .. })
+        ));
+    }
+}
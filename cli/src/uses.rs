@@ -0,0 +1,160 @@
+//! The item types and quantities given to `use`, like `rice 250g` or `3:1 7:0.5 12:2`.
+
+use crate::quantity::QuantityArg;
+use std::str::FromStr;
+
+/// An item type to use from, by id or name, and the quantity to use, 1.0 by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UseItem {
+    pub item_type: String,
+    pub quantity: Option<QuantityArg>,
+}
+
+impl UseItem {
+    /// Splits `type<separator>quantity` at the last separator. Without it, the whole text is
+    /// the item type.
+    fn split(s: &str, separator: char) -> Result<Self, String> {
+        match s.rsplit_once(separator) {
+            Some((item_type, quantity)) if !item_type.trim().is_empty() => Ok(UseItem {
+                item_type: item_type.trim().to_string(),
+                quantity: Some(quantity.parse()?),
+            }),
+            Some(_) => Err(format!("{} has no item type before the {}", s, separator)),
+            None => Ok(UseItem {
+                item_type: s.to_string(),
+                quantity: None,
+            }),
+        }
+    }
+}
+
+/// An item of the `--item` option, as `type=quantity`.
+impl FromStr for UseItem {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.contains('=') {
+            return Err(format!("{} is not like type=quantity", s));
+        }
+        UseItem::split(s, '=')
+    }
+}
+
+/// A positional argument of `use` after the first one: the quantity of the first item type,
+/// or another item type as `type:quantity`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UseArg {
+    Quantity(QuantityArg),
+    Item(UseItem),
+}
+
+impl FromStr for UseArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains(':') {
+            UseItem::split(s, ':').map(UseArg::Item)
+        } else {
+            s.parse().map(UseArg::Quantity)
+        }
+    }
+}
+
+/// Gathers the item types to use from the positional arguments and the `--item` options.
+/// `rice 250g` is the item type rice with a quantity, otherwise every positional argument
+/// is an item type optionally followed by `:quantity`.
+pub fn use_items(
+    first: Option<&str>,
+    rest: &[UseArg],
+    options: &[UseItem],
+) -> Result<Vec<UseItem>, String> {
+    let mut items = vec![];
+    if let Some(first) = first {
+        match rest {
+            [UseArg::Quantity(quantity)] if !first.contains(':') => items.push(UseItem {
+                item_type: first.to_string(),
+                quantity: Some(quantity.clone()),
+            }),
+            _ => {
+                items.push(UseItem::split(first, ':')?);
+                for arg in rest {
+                    match arg {
+                        UseArg::Item(item) => items.push(item.clone()),
+                        UseArg::Quantity(quantity) => {
+                            return Err(format!(
+                                "{} is not like type:quantity, only a single item type can be followed by its quantity",
+                                quantity
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+    }
+    items.extend(options.iter().cloned());
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(first: Option<&str>, rest: &[&str], options: &[&str]) -> Result<Vec<String>, String> {
+        let rest = rest
+            .iter()
+            .map(|a| a.parse())
+            .collect::<Result<Vec<UseArg>, _>>()?;
+        let options = options
+            .iter()
+            .map(|a| a.parse())
+            .collect::<Result<Vec<UseItem>, _>>()?;
+        Ok(use_items(first, &rest, &options)?
+            .iter()
+            .map(|i| match &i.quantity {
+                Some(q) => format!("{}={}", i.item_type, q),
+                None => i.item_type.clone(),
+            })
+            .collect())
+    }
+
+    #[test]
+    fn a_single_item_type_takes_its_quantity_after_it() {
+        assert_eq!(items(Some("rice"), &[], &[]).unwrap(), vec!["rice"]);
+        assert_eq!(
+            items(Some("rice"), &["250g"], &[]).unwrap(),
+            vec!["rice=250g"]
+        );
+        assert_eq!(items(Some("rice:2"), &[], &[]).unwrap(), vec!["rice=2"]);
+        assert_eq!(
+            items(Some("milk"), &["0,5l"], &[]).unwrap(),
+            vec!["milk=0.5l"]
+        );
+    }
+
+    #[test]
+    fn several_item_types_are_pairs() {
+        assert_eq!(
+            items(Some("3:1"), &["7:0.5", "olive oil:20cl"], &[]).unwrap(),
+            vec!["3=1", "7=0.5", "olive oil=20cl"]
+        );
+        assert_eq!(
+            items(Some("milk"), &["7:0.5"], &["12=2"]).unwrap(),
+            vec!["milk", "7=0.5", "12=2"]
+        );
+        assert_eq!(
+            items(None, &[], &["3=1", "a=b=2"]).unwrap(),
+            vec!["3=1", "a=b=2"]
+        );
+    }
+
+    #[test]
+    fn malformed_items_are_refused() {
+        assert!(items(Some("rice"), &["lots"], &[]).is_err());
+        assert!(items(Some("3:1"), &["2"], &[]).is_err());
+        assert!(items(Some("rice"), &["2", "7:1"], &[]).is_err());
+        assert!(items(Some(":2"), &[], &[]).is_err());
+        assert!(items(Some("rice:"), &[], &[]).is_err());
+        assert!(items(None, &[], &["rice"]).is_err());
+        assert!(items(None, &[], &["rice=some"]).is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! Spreadsheet export of the inventory, with one sheet per table ready for pivot tables.
+
+use inv_manager::*;
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, Worksheet, XlsxError};
+use std::time::SystemTime;
+
+const SECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// The value of a cell. Dates are written as Excel dates, so that they can be filtered.
+enum Cell {
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Date(SystemTime),
+    Empty,
+}
+
+fn text(value: Option<&str>) -> Cell {
+    value.map_or(Cell::Empty, |v| Cell::Text(v.to_string()))
+}
+
+fn number(value: Option<f32>) -> Cell {
+    value.map_or(Cell::Empty, |v| Cell::Number(v.into()))
+}
+
+fn date(value: Option<SystemTime>) -> Cell {
+    value.map_or(Cell::Empty, Cell::Date)
+}
+
+/// The signed number of days from `now` to `to`, with decimals.
+fn days_between(now: SystemTime, to: SystemTime) -> f64 {
+    match to.duration_since(now) {
+        Ok(ahead) => ahead.as_secs_f64() / SECONDS_PER_DAY,
+        Err(e) => -e.duration().as_secs_f64() / SECONDS_PER_DAY,
+    }
+}
+
+/// Writes a sheet with a bold header, a filter on every column and the header kept visible.
+fn write_sheet(
+    sheet: &mut Worksheet,
+    name: &str,
+    header: &[&str],
+    rows: &[Vec<Cell>],
+) -> Result<(), XlsxError> {
+    let bold = Format::new().set_bold();
+    let date_format = Format::new().set_num_format("yyyy-mm-dd hh:mm:ss");
+    sheet.set_name(name)?;
+    for (col, title) in header.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, *title, &bold)?;
+    }
+    for (row, cells) in rows.iter().enumerate() {
+        let row = row as u32 + 1;
+        for (col, cell) in cells.iter().enumerate() {
+            let col = col as u16;
+            match cell {
+                Cell::Text(t) => sheet.write_string(row, col, t)?,
+                Cell::Number(n) => sheet.write_number(row, col, *n)?,
+                Cell::Bool(b) => sheet.write_boolean(row, col, *b)?,
+                Cell::Date(d) => {
+                    let seconds = match d.duration_since(SystemTime::UNIX_EPOCH) {
+                        Ok(after) => after.as_secs() as i64,
+                        Err(e) => -(e.duration().as_secs() as i64),
+                    };
+                    let excel = ExcelDateTime::from_timestamp(seconds)?;
+                    sheet.write_datetime_with_format(row, col, &excel, &date_format)?
+                }
+                Cell::Empty => sheet,
+            };
+        }
+    }
+    sheet.autofilter(0, 0, rows.len() as u32, header.len() as u16 - 1)?;
+    sheet.set_freeze_panes(1, 0)?;
+    sheet.autofit();
+    Ok(())
+}
+
+/// The workbook of the inventory at `now`: the item types with their statistics, every item
+/// instance, removed ones included, with columns derived from its item type and its expiry,
+/// and the numbers of the digest.
+pub fn workbook(inventory: &Inventory, now: SystemTime) -> Result<Vec<u8>, XlsxError> {
+    let stats = inventory.all_type_stats();
+    let types = inventory
+        .item_types
+        .iter()
+        .map(|t| {
+            let s = stats.get(&t.id).cloned().unwrap_or_default();
+            vec![
+                Cell::Number(t.id.0.into()),
+                Cell::Text(t.name.clone()),
+                text(t.class.as_deref()),
+                text(t.unit.as_deref()),
+                Cell::Number(t.effective_minimum(now).into()),
+                t.ttl.map_or(Cell::Empty, |ttl| {
+                    Cell::Number(ttl.as_secs_f64() / SECONDS_PER_DAY)
+                }),
+                Cell::Bool(t.opened_by_default),
+                number(t.deposit),
+                Cell::Number(s.quantity.into()),
+                Cell::Number(s.sealed_quantity.into()),
+                Cell::Number(s.instance_count.into()),
+                Cell::Number(s.opened_count.into()),
+                date(s.soonest_expiry),
+                Cell::Number(s.total_value.into()),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let instances = inventory
+        .item_instances
+        .iter()
+        .map(|ii| {
+            let item_type = inventory.item_types.iter().find(|t| t.id == ii.item_type);
+            let expiry_state = match ii.expiry_state(now) {
+                ExpiryState::Fresh => "fresh",
+                ExpiryState::PastBestBefore => "past best before",
+                ExpiryState::Expired => "expired",
+            };
+            vec![
+                Cell::Number(ii.id.0.into()),
+                Cell::Number(ii.item_type.0.into()),
+                text(item_type.map(|t| t.name.as_str())),
+                text(item_type.and_then(|t| t.class.as_deref())),
+                Cell::Number(ii.quantity.into()),
+                text(
+                    ii.unit_override
+                        .as_deref()
+                        .or_else(|| item_type.and_then(|t| t.unit.as_deref())),
+                ),
+                text(ii.location.as_deref()),
+                number(ii.value),
+                number(ii.unit_price()),
+                date(ii.added_at),
+                date(ii.opened_at),
+                date(ii.expires_at),
+                date(ii.best_before_at),
+                date(ii.removed_at),
+                text(ii.removal_reason.as_deref()),
+                Cell::Text(expiry_state.to_string()),
+                ii.expires_at.map_or(Cell::Empty, |exp| {
+                    Cell::Number(days_between(now, exp).floor())
+                }),
+                text(ii.label.as_deref()),
+                text(ii.external_ref.as_deref()),
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let digest = inventory.digest(now, &DigestOptions::default());
+    let present = inventory
+        .item_instances
+        .iter()
+        .filter(|ii| ii.removed_at.is_none())
+        .count();
+    let count = |n: usize| Cell::Number(n as f64);
+    let summary = vec![
+        vec![Cell::Text("at".to_string()), Cell::Date(now)],
+        vec![
+            Cell::Text("item types".to_string()),
+            count(inventory.item_types.len()),
+        ],
+        vec![Cell::Text("item instances".to_string()), count(present)],
+        vec![
+            Cell::Text("total value".to_string()),
+            Cell::Number(stats.values().map(|s| f64::from(s.total_value)).sum()),
+        ],
+        vec![
+            Cell::Text("expired during the last week".to_string()),
+            count(digest.expired.len()),
+        ],
+        vec![
+            Cell::Text("expiring during the next week".to_string()),
+            count(digest.expiring.len()),
+        ],
+        vec![
+            Cell::Text("item types to buy".to_string()),
+            count(digest.to_buy.len()),
+        ],
+        vec![
+            Cell::Text("opened for too long".to_string()),
+            count(digest.opened_too_long.len()),
+        ],
+        vec![
+            Cell::Text("opened reminders".to_string()),
+            count(digest.opened_reminders.len()),
+        ],
+    ];
+
+    let mut workbook = Workbook::new();
+    write_sheet(
+        workbook.add_worksheet(),
+        "Types",
+        &[
+            "id",
+            "name",
+            "class",
+            "unit",
+            "min",
+            "ttl days",
+            "open default",
+            "deposit",
+            "total quantity",
+            "sealed quantity",
+            "instances",
+            "opened",
+            "soonest expiry",
+            "total value",
+        ],
+        &types,
+    )?;
+    write_sheet(
+        workbook.add_worksheet(),
+        "Instances",
+        &[
+            "id",
+            "type id",
+            "type name",
+            "class",
+            "quantity",
+            "unit",
+            "location",
+            "value",
+            "unit price",
+            "added at",
+            "opened at",
+            "expires at",
+            "best before",
+            "removed at",
+            "removal reason",
+            "expiry state",
+            "days until expiry",
+            "label",
+            "external ref",
+        ],
+        &instances,
+    )?;
+    write_sheet(
+        workbook.add_worksheet(),
+        "Summary",
+        &["metric", "value"],
+        &summary,
+    )?;
+    workbook.save_to_buffer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{open_workbook_from_rs, Data, Reader, Xlsx};
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// The serial number of a time in Excel, counting days since the end of 1899.
+    fn serial(t: SystemTime) -> f64 {
+        t.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as f64 / (24.0 * 3600.0)
+            + 25569.0
+    }
+
+    #[test]
+    fn workbooks_are_read_back() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut inventory = Inventory::default();
+        let rice = inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            class: Some("pantry".to_string()),
+            unit: Some("kg".to_string()),
+            minimum_quantity: 5.0,
+            ..ItemType::default()
+        });
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: rice,
+                quantity: 2.0,
+                value: Some(6.0),
+                location: Some("cellar".to_string()),
+                external_ref: Some("PO-1234".to_string()),
+                expires_at: Some(now + DAY * 3 + DAY / 2),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+
+        let data = workbook(&inventory, now).unwrap();
+        let mut book: Xlsx<_> = open_workbook_from_rs(Cursor::new(data)).unwrap();
+        assert_eq!(book.sheet_names(), vec!["Types", "Instances", "Summary"]);
+
+        let types = book.worksheet_range("Types").unwrap();
+        assert_eq!(types.get_value((0, 1)), Some(&Data::String("name".into())));
+        assert_eq!(types.get_value((1, 1)), Some(&Data::String("rice".into())));
+        assert_eq!(types.get_value((1, 8)), Some(&Data::Float(2.0)));
+
+        let instances = book.worksheet_range("Instances").unwrap();
+        assert_eq!(
+            instances.get_value((0, 16)),
+            Some(&Data::String("days until expiry".into()))
+        );
+        assert_eq!(
+            instances.get_value((1, 2)),
+            Some(&Data::String("rice".into()))
+        );
+        assert_eq!(
+            instances.get_value((1, 5)),
+            Some(&Data::String("kg".into()))
+        );
+        assert_eq!(instances.get_value((1, 8)), Some(&Data::Float(3.0)));
+        match instances.get_value((1, 11)) {
+            Some(Data::DateTime(expiry)) => {
+                assert_eq!(expiry.as_f64(), serial(now + DAY * 3 + DAY / 2))
+            }
+            other => panic!("expected a date, got {:?}", other),
+        }
+        assert_eq!(
+            instances.get_value((1, 15)),
+            Some(&Data::String("fresh".into()))
+        );
+        assert_eq!(instances.get_value((1, 16)), Some(&Data::Float(3.0)));
+        assert_eq!(
+            instances.get_value((1, 18)),
+            Some(&Data::String("PO-1234".into()))
+        );
+
+        let summary = book.worksheet_range("Summary").unwrap();
+        assert!(matches!(summary.get_value((1, 1)), Some(Data::DateTime(_))));
+        assert_eq!(
+            summary.get_value((7, 0)),
+            Some(&Data::String("item types to buy".into()))
+        );
+        assert_eq!(summary.get_value((7, 1)), Some(&Data::Float(1.0)));
+    }
+}
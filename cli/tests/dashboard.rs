@@ -0,0 +1,85 @@
+//! Runs the binary without a subcommand.
+
+use std::fs;
+use std::process::Command;
+
+/// A workdir with the inventory home: milk below its minimum, one expired and two fresh rice.
+fn fixture(name: &str) -> std::path::PathBuf {
+    let workdir = std::env::temp_dir().join(format!("inv_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&workdir);
+    fs::create_dir_all(&workdir).unwrap();
+    fs::write(
+        workdir.join("home_types.json"),
+        r#"{"format_version": 3, "next_id": 3, "items": [
+            {"id": 1, "name": "rice", "minimum_quantity": 0.0, "ttl": null,
+            "opened_by_default": false},
+            {"id": 2, "name": "milk", "minimum_quantity": 2.0, "ttl": null,
+            "opened_by_default": false}]}"#,
+    )
+    .unwrap();
+    let instance = |id: u32, expires: u64| {
+        format!(
+            r#"{{"id": {}, "item_type": 1, "quantity": 1.0, "model": null, "serial": null,
+            "extra": null, "location": null, "value": null, "opened_at": null,
+            "expires_at": {{"secs_since_epoch": {}, "nanos_since_epoch": 0}},
+            "added_at": null, "removed_at": null}}"#,
+            id, expires
+        )
+    };
+    fs::write(
+        workdir.join("home_instances.json"),
+        format!(
+            r#"{{"format_version": 3, "next_id": 4, "items": [{}, {}, {}]}}"#,
+            instance(1, 1_000_000_000),
+            instance(2, 4_000_000_000),
+            instance(3, 3_000_000_000)
+        ),
+    )
+    .unwrap();
+    workdir
+}
+
+fn inv(workdir: &std::path::Path, args: &[&str]) -> std::process::Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn the_dashboard_is_printed_without_a_subcommand() {
+    let workdir = fixture("dashboard");
+    let output = inv(&workdir, &[]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout.lines().collect::<Vec<_>>(),
+        vec![
+            "Inventory home: 2 item types, 3 item instances",
+            "1 expired, 1 item types below their minimum",
+            "Expiring next:",
+            "  1 rice (instance 3) expires 2065-01-24T05:20:00Z",
+            "  1 rice (instance 2) expires 2096-10-02T07:06:40Z",
+        ]
+    );
+
+    let output = inv(&workdir, &["--minimal"]);
+    let dashboard: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(dashboard["inventory"], "home");
+    assert_eq!(dashboard["instances"], 3);
+    assert_eq!(dashboard["expired"], 1);
+    assert_eq!(dashboard["below_minimum"], 1);
+    assert_eq!(dashboard["soonest_expiries"][0]["instance_id"], 3);
+
+    let output = inv(&workdir, &["--machine"]);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["listing"]["kind"], "dashboard");
+    assert_eq!(result["listing"]["items"]["types"], 2);
+    fs::remove_dir_all(&workdir).unwrap();
+}
@@ -0,0 +1,99 @@
+//! Encrypts an inventory, uses it with the right and wrong passphrases, and decrypts it.
+#![cfg(feature = "encryption")]
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn inv(workdir: &Path, passphrase: Option<&str>, args: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_inv"));
+    command
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home"])
+        .args(args)
+        .stdin(Stdio::null())
+        .env_remove("INVENTORY_MANAGOAT_PASSPHRASE");
+    if let Some(passphrase) = passphrase {
+        command.env("INVENTORY_MANAGOAT_PASSPHRASE", passphrase);
+    }
+    command.output().unwrap()
+}
+
+fn succeeds(output: Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn fails(output: Output) -> String {
+    assert!(!output.status.success());
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn encrypted_inventories_need_their_passphrase() {
+    let workdir = std::env::temp_dir().join(format!("inv_encryption_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&workdir);
+    let (types, types_enc) = (
+        workdir.join("home_types.json"),
+        workdir.join("home_types.json.enc"),
+    );
+    let instances_enc = workdir.join("home_instances.json.enc");
+    succeeds(inv(&workdir, None, &["ct", "rice", "--unit", "kg"]));
+    assert!(fails(inv(&workdir, None, &["--encrypt", "rt"])).contains("inv encrypt"));
+
+    succeeds(inv(&workdir, Some("hunter2"), &["encrypt"]));
+    assert!(!types.exists());
+    succeeds(inv(
+        &workdir,
+        Some("hunter2"),
+        &["ci", "rice", "-q", "2", "--serial", "SECRET123"],
+    ));
+    succeeds(inv(&workdir, Some("hunter2"), &["update-state"]));
+    // Nothing is left in plaintext, the event log and the state file included.
+    for entry in fs::read_dir(&workdir).unwrap() {
+        let path = entry.unwrap().path();
+        let content = String::from_utf8_lossy(&fs::read(&path).unwrap()).into_owned();
+        assert!(
+            !content.contains("SECRET123") && !content.contains("rice"),
+            "{}",
+            path.display()
+        );
+    }
+    assert!(workdir.join("home_events.json.enc").exists());
+    assert!(workdir.join("home_state.json.enc").exists());
+    let state = succeeds(inv(&workdir, Some("hunter2"), &["state-at", "now"]));
+    assert!(state.contains("SECRET123"), "{}", state);
+    assert!(!workdir.join("home.journal").exists());
+    assert!(succeeds(inv(&workdir, Some("hunter2"), &["rt"])).contains("rice"));
+
+    let saved = fs::read(&instances_enc).unwrap();
+    let error = fails(inv(&workdir, Some("letmein"), &["ci", "rice", "-q", "1"]));
+    assert!(error.contains("can not be decrypted"), "{}", error);
+    assert!(fails(inv(&workdir, None, &["rt"])).contains("INVENTORY_MANAGOAT_PASSPHRASE"));
+    assert_eq!(fs::read(&instances_enc).unwrap(), saved);
+
+    fs::write(workdir.join("home.key"), "hunter2\n").unwrap();
+    fs::write(
+        workdir.join("home_config.json"),
+        r#"{"keyfile": "home.key"}"#,
+    )
+    .unwrap();
+    assert!(succeeds(inv(&workdir, None, &["rt"])).contains("rice"));
+
+    fs::write(&types, "{}").unwrap();
+    assert!(fails(inv(&workdir, None, &["rt"])).contains("both plaintext files"));
+    fs::remove_file(&types).unwrap();
+
+    succeeds(inv(&workdir, None, &["decrypt"]));
+    assert!(!types_enc.exists() && !instances_enc.exists());
+    assert!(!workdir.join("home_events.json.enc").exists());
+    let events = fs::read_to_string(workdir.join("home_events.json")).unwrap();
+    assert!(events.contains("SECRET123"));
+    fs::remove_file(workdir.join("home_config.json")).unwrap();
+    let instances = succeeds(inv(&workdir, None, &["ri"]));
+    assert!(instances.contains("rice"), "{}", instances);
+    fs::remove_dir_all(&workdir).unwrap();
+}
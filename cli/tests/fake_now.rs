@@ -0,0 +1,86 @@
+//! Runs the binary with the time frozen by IMGOAT_FAKE_NOW.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn inv(workdir: &Path, fake_now: &str, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home"])
+        .args(args)
+        .env("IMGOAT_FAKE_NOW", fake_now)
+        .output()
+        .unwrap()
+}
+
+/// Runs the same commands in a new working directory, returning the content of its files.
+fn session(workdir: &Path) -> BTreeMap<String, Vec<u8>> {
+    let _ = fs::remove_dir_all(workdir);
+    let commands: &[&[&str]] = &[
+        &["ct", "rice", "--unit", "kg"],
+        &["ci", "rice", "-q", "2", "-e", "3days"],
+        &["ci", "rice", "-q", "1"],
+        &["ui", "1", "--opened-at", "0s"],
+        &["use", "rice", "0.5"],
+        &["trash", "2"],
+    ];
+    for args in commands {
+        let output = inv(workdir, "2024-01-15T12:00:00Z", args);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(
+            stderr.starts_with(
+                "Warning: IMGOAT_FAKE_NOW is set, the time is frozen at 2024-01-15T12:00:00Z\n"
+            ),
+            "{}",
+            stderr
+        );
+    }
+    fs::read_dir(workdir)
+        .unwrap()
+        .map(|entry| {
+            let entry = entry.unwrap();
+            let name = entry.file_name().into_string().unwrap();
+            (name, fs::read(entry.path()).unwrap())
+        })
+        .collect()
+}
+
+#[test]
+fn frozen_runs_write_identical_files() {
+    let base = std::env::temp_dir().join(format!("inv_fake_now_{}", std::process::id()));
+    let first = session(&base.join("first"));
+    let second = session(&base.join("second"));
+    assert!(first.contains_key("home_instances.json"));
+    assert_eq!(
+        first.keys().collect::<Vec<_>>(),
+        second.keys().collect::<Vec<_>>()
+    );
+    for (name, content) in &first {
+        assert!(&second[name] == content, "{} differs", name);
+    }
+
+    let instances = String::from_utf8(first["home_instances.json"].clone()).unwrap();
+    let instances: serde_json::Value = serde_json::from_str(&instances).unwrap();
+    let instances = instances["items"].as_array().unwrap();
+    let secs = |field: &serde_json::Value| field["secs_since_epoch"].as_u64().unwrap();
+    assert_eq!(secs(&instances[0]["added_at"]), 1_705_320_000);
+    assert_eq!(secs(&instances[0]["opened_at"]), 1_705_320_000);
+    assert_eq!(
+        secs(&instances[0]["expires_at"]),
+        1_705_320_000 + 3 * 86_400
+    );
+    assert_eq!(secs(&instances[1]["removed_at"]), 1_705_320_000);
+
+    let output = inv(&base.join("first"), "yesterday-ish", &["rt"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("IMGOAT_FAKE_NOW is set to yesterday-ish"));
+    fs::remove_dir_all(&base).unwrap();
+}
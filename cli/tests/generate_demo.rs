@@ -0,0 +1,46 @@
+//! Fills an inventory with generated data, like for a demo.
+
+use std::fs;
+use std::process::Command;
+
+fn inv(workdir: &std::path::Path, args: &[&str]) -> std::process::Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "demo"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn dashboard(workdir: &std::path::Path) -> serde_json::Value {
+    serde_json::from_slice(&inv(workdir, &["--minimal"]).stdout).unwrap()
+}
+
+#[test]
+fn only_empty_inventories_are_generated_without_force() {
+    let workdir = std::env::temp_dir().join(format!("inv_demo_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&workdir);
+
+    let output = inv(&workdir, &["generate-demo", "--types", "12", "--seed", "5"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Generated 12 item types and 60 item instances\n"
+    );
+    let generated = dashboard(&workdir);
+    assert_eq!(generated["types"], 12);
+    assert!(generated["expired"].as_u64().unwrap() > 0);
+    assert!(generated["below_minimum"].as_u64().unwrap() > 0);
+
+    let output = inv(&workdir, &["generate-demo", "--types", "3"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--force"));
+    assert_eq!(dashboard(&workdir), generated);
+
+    inv(&workdir, &["generate-demo", "--types", "3", "--force"]);
+    assert_eq!(dashboard(&workdir)["types"], 3);
+    fs::remove_dir_all(&workdir).unwrap();
+}
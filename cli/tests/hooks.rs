@@ -0,0 +1,82 @@
+//! Runs the binary with a stub hook set in the settings.
+#![cfg(unix)]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn inv(workdir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Writes the hook, logging its environment and input to `hook.log`, then exiting with
+/// `status`, and the settings running it.
+fn set_hook(workdir: &Path, status: u8, failure: &str) {
+    let script = format!(
+        "#!/bin/sh\n\
+         echo \"$IMGOAT_COMMAND|$IMGOAT_AFFECTED_IDS|$IMGOAT_AFFECTED_TYPE_IDS|$IMGOAT_INVENTORY\" >> hook.log\n\
+         cat >> hook.log\n\
+         echo >> hook.log\n\
+         echo hook output\n\
+         exit {}\n",
+        status
+    );
+    let hook = workdir.join("hook.sh");
+    fs::write(&hook, script).unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        workdir.join("home_config.json"),
+        format!(
+            r#"{{"post_mutation_hook": "hook.sh", "hook_failure": "{}"}}"#,
+            failure
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn hooks_run_after_the_changes_are_saved() {
+    let workdir = std::env::temp_dir().join(format!("inv_hooks_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&workdir);
+    fs::create_dir_all(&workdir).unwrap();
+    set_hook(&workdir, 0, "warn");
+    let log = || fs::read_to_string(workdir.join("hook.log")).unwrap_or_default();
+
+    let output = inv(&workdir, &["ct", "rice"]);
+    assert!(output.status.success());
+    // The output of the hook doesn't mix with the output of the command.
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("hook output"));
+    let output = inv(&workdir, &["ci", "rice", "-q", "2"]);
+    assert!(output.status.success());
+    inv(&workdir, &["rt"]);
+    let lines = log().lines().map(str::to_string).collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4, "{:?}", lines);
+    assert_eq!(lines[0], "ct||1|home");
+    assert_eq!(lines[2], "ci|1||home");
+    let result: serde_json::Value = serde_json::from_str(&lines[3]).unwrap();
+    assert_eq!(result["command"], "ci");
+    assert_eq!(result["created_id"], 1);
+
+    set_hook(&workdir, 1, "warn");
+    let output = inv(&workdir, &["ui", "1", "-q", "1"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("the changes were saved but the hook"));
+
+    set_hook(&workdir, 1, "error");
+    let output = inv(&workdir, &["ui", "1", "-q", "0.5"]);
+    assert!(!output.status.success());
+    let output = inv(&workdir, &["--minimal", "ri"]);
+    let listed = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(listed.split('\t').nth(2), Some("0.5"), "{}", listed);
+    fs::remove_dir_all(&workdir).unwrap();
+}
@@ -0,0 +1,84 @@
+//! Runs the binary with output files that can not be written, and archives that can not be
+//! read.
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn inv(workdir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Checks that the command fails with an error naming the file instead of panicking.
+fn fails_to_write(workdir: &Path, args: &[&str]) {
+    let output = inv(workdir, args);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr);
+    assert!(
+        stderr.contains("Error: failed to write /nonexistent/"),
+        "{:?}: {}",
+        args,
+        stderr
+    );
+}
+
+#[test]
+fn unwritable_outputs_are_errors() {
+    let workdir = std::env::temp_dir().join(format!("inv_outputs_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&workdir);
+    assert!(inv(&workdir, &["ct", "rice"]).status.success());
+
+    fails_to_write(&workdir, &["metrics", "-o", "/nonexistent/m.prom"]);
+    fails_to_write(&workdir, &["export", "-o", "/nonexistent/report.md"]);
+    #[cfg(feature = "xlsx")]
+    fails_to_write(
+        &workdir,
+        &[
+            "export",
+            "--format",
+            "xlsx",
+            "-o",
+            "/nonexistent/report.xlsx",
+        ],
+    );
+    fails_to_write(
+        &workdir,
+        &["graph-locations", "-o", "/nonexistent/graph.dot"],
+    );
+    fails_to_write(
+        &workdir,
+        &["export-ical", "-o", "/nonexistent/expiries.ics"],
+    );
+    std::fs::remove_dir_all(&workdir).unwrap();
+}
+
+#[test]
+fn missing_or_corrupt_archives_are_errors() {
+    let workdir = std::env::temp_dir().join(format!("inv_archives_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&workdir);
+    assert!(inv(&workdir, &["ct", "rice"]).status.success());
+    let corrupt = workdir.join("corrupt.tar.gz");
+    std::fs::write(&corrupt, "not an archive").unwrap();
+
+    for args in [
+        vec!["archive", "export", "/nonexistent/home.tar.gz"],
+        vec!["archive", "import", "/nonexistent/home.tar.gz"],
+        vec!["archive", "import", "--force", corrupt.to_str().unwrap()],
+    ] {
+        let output = inv(&workdir, &args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, stderr);
+        assert!(
+            stderr.starts_with(&format!("Error: {}", args.last().unwrap())),
+            "{:?}: {}",
+            args,
+            stderr
+        );
+    }
+    // Nothing was replaced.
+    assert!(inv(&workdir, &["rt"]).status.success());
+    std::fs::remove_dir_all(&workdir).unwrap();
+}
@@ -0,0 +1,70 @@
+//! Runs the repl and checks that the changes it has not saved yet reach the disk when it is
+//! idle and when it is stopped by a signal.
+#![cfg(unix)]
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread::sleep;
+use std::time::Duration;
+
+fn item_types(workdir: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args([
+            "-w",
+            workdir.to_str().unwrap(),
+            "-n",
+            "home",
+            "--minimal",
+            "rt",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn pending_changes_are_saved_when_idle_and_on_sigterm() {
+    let workdir = std::env::temp_dir().join(format!("inv_repl_signals_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&workdir);
+    let mut repl = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home", "repl"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = repl.stdin.take().unwrap();
+
+    // With no more input, the timer saves the last change after the interval.
+    stdin.write_all(b"ct bread\nct rice\n").unwrap();
+    stdin.flush().unwrap();
+    sleep(Duration::from_secs(4));
+    let saved = item_types(&workdir);
+    assert!(
+        saved.contains("bread") && saved.contains("rice"),
+        "{}",
+        saved
+    );
+
+    // SIGTERM saves what the interval still holds back, with stdin left open.
+    stdin.write_all(b"ct pasta\nct salt\n").unwrap();
+    stdin.flush().unwrap();
+    sleep(Duration::from_millis(500));
+    let killed = Command::new("kill")
+        .args(["-TERM", &repl.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert!(repl.wait().unwrap().success());
+    let saved = item_types(&workdir);
+    assert!(
+        saved.contains("pasta") && saved.contains("salt"),
+        "{}",
+        saved
+    );
+
+    drop(stdin);
+    std::fs::remove_dir_all(&workdir).unwrap();
+}
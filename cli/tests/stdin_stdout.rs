@@ -0,0 +1,47 @@
+//! Runs the binary with the inventory piped through stdin and stdout.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn inventory_is_piped_through() {
+    // Files that don't exist, to check that nothing is read from nor written to them.
+    let workdir = std::env::temp_dir().join(format!("inv_piped_{}", std::process::id()));
+    let input = r#"{
+        "types": [{"id": 3, "name": "rice", "minimum_quantity": 0.0, "ttl": null,
+            "opened_by_default": false}],
+        "instances": {"format_version": 3, "next_id": 8, "items": [
+            {"id": 7, "item_type": 3, "quantity": 2.5, "model": null, "serial": null,
+            "extra": null, "location": null, "value": null, "opened_at": null,
+            "expires_at": null, "added_at": null, "removed_at": null}]}
+    }"#;
+    let mut child = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "--stdin", "--stdout"])
+        .args(["use", "3", "1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!workdir.exists());
+
+    let document: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(document["types"]["format_version"], 3);
+    assert_eq!(document["types"]["items"][0]["name"], "rice");
+    assert_eq!(document["instances"]["next_id"], 8);
+    let instance = &document["instances"]["items"][0];
+    assert_eq!(instance["quantity"], 1.5);
+    assert!(!instance["opened_at"].is_null());
+}
@@ -0,0 +1,146 @@
+//! Runs update-state at frozen times, checking what it records in the event log.
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+fn inv(workdir: &Path, fake_now: &str, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home", "--machine"])
+        .args(args)
+        .env("IMGOAT_FAKE_NOW", fake_now)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+/// The ids of the item instances listed by update-state.
+fn update_state(workdir: &Path, fake_now: &str, args: &[&str]) -> Vec<u64> {
+    let mut all = vec!["update-state"];
+    all.extend_from_slice(args);
+    let output = inv(workdir, fake_now, &all);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["listing"]["kind"], "expiries");
+    result["listing"]["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["instance_id"].as_u64().unwrap())
+        .collect()
+}
+
+/// The ids of the item instances of the expired events of the log.
+fn logged_expiries(workdir: &Path) -> Vec<u64> {
+    fs::read_to_string(workdir.join("home_events.json"))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .filter(|event| event["kind"] == "expired")
+        .map(|event| event["instance_id"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+fn expiries_are_recorded_once_and_trashed_on_demand() {
+    let workdir = std::env::temp_dir().join(format!("inv_update_state_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&workdir);
+    let start = "2024-01-10T12:00:00Z";
+    inv(
+        &workdir,
+        start,
+        &["ct", "milk", "--auto-trash-on-expiry", "true"],
+    );
+    inv(&workdir, start, &["ct", "rice"]);
+    inv(
+        &workdir,
+        start,
+        &["ci", "milk", "-e", "2024-01-12 00:00:00"],
+    );
+    inv(
+        &workdir,
+        start,
+        &["ci", "rice", "-e", "2024-01-12 00:00:00"],
+    );
+    inv(
+        &workdir,
+        start,
+        &["ci", "rice", "-e", "2024-01-20 00:00:00"],
+    );
+
+    assert!(update_state(&workdir, start, &[]).is_empty());
+    assert_eq!(
+        update_state(&workdir, "2024-01-13T00:00:00Z", &["--dry-run"]),
+        vec![1, 2]
+    );
+    assert!(logged_expiries(&workdir).is_empty());
+    assert_eq!(
+        update_state(&workdir, "2024-01-13T00:00:00Z", &[]),
+        vec![1, 2]
+    );
+    assert_eq!(logged_expiries(&workdir), vec![1, 2]);
+
+    let instances = fs::read_to_string(workdir.join("home_instances.json")).unwrap();
+    let instances: serde_json::Value = serde_json::from_str(&instances).unwrap();
+    assert_eq!(instances["items"][0]["removal_reason"], "expired");
+    assert_eq!(
+        instances["items"][1]["removal_reason"],
+        serde_json::Value::Null
+    );
+
+    // Again, with the clock set back, then without the state file.
+    assert!(update_state(&workdir, "2024-01-13T00:00:00Z", &[]).is_empty());
+    assert!(update_state(&workdir, "2024-01-11T00:00:00Z", &[]).is_empty());
+    fs::remove_file(workdir.join("home_state.json")).unwrap();
+    assert!(update_state(&workdir, "2024-01-14T00:00:00Z", &[]).is_empty());
+
+    assert_eq!(update_state(&workdir, "2024-01-21T00:00:00Z", &[]), vec![3]);
+    assert_eq!(logged_expiries(&workdir), vec![1, 2, 3]);
+    fs::remove_dir_all(&workdir).unwrap();
+}
+
+#[test]
+fn expiries_are_recorded_once_the_inventory_is_saved() {
+    let workdir =
+        std::env::temp_dir().join(format!("inv_update_state_save_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&workdir);
+    let start = "2024-01-10T12:00:00Z";
+    inv(
+        &workdir,
+        start,
+        &["ct", "milk", "--auto-trash-on-expiry", "true"],
+    );
+    inv(
+        &workdir,
+        start,
+        &["ci", "milk", "-e", "2024-01-12 00:00:00"],
+    );
+
+    // A directory in the way of the temporary file makes saving the trashed milk fail.
+    let blocker = workdir.join("home_instances.json.tmp");
+    fs::create_dir_all(blocker.join("blocker")).unwrap();
+    let failed = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args([
+            "-w",
+            workdir.to_str().unwrap(),
+            "-n",
+            "home",
+            "update-state",
+        ])
+        .env("IMGOAT_FAKE_NOW", "2024-01-13T00:00:00Z")
+        .output()
+        .unwrap();
+    assert_eq!(failed.status.code(), Some(1));
+    assert!(logged_expiries(&workdir).is_empty());
+    assert!(!workdir.join("home_state.json").exists());
+
+    fs::remove_dir_all(&blocker).unwrap();
+    assert_eq!(update_state(&workdir, "2024-01-13T00:00:00Z", &[]), vec![1]);
+    assert_eq!(logged_expiries(&workdir), vec![1]);
+    assert!(workdir.join("home_state.json").exists());
+    fs::remove_dir_all(&workdir).unwrap();
+}
@@ -0,0 +1,51 @@
+//! Runs the binary with --verbose, whose messages go to stderr only.
+
+use std::process::{Command, Output};
+
+fn inv(workdir: &std::path::Path, args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_inv"))
+        .args(["-w", workdir.to_str().unwrap(), "-n", "home"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+#[test]
+fn phases_are_timed_on_stderr() {
+    let workdir = std::env::temp_dir().join(format!("inv_verbose_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&workdir);
+
+    let output = inv(&workdir, &["-vv", "--machine", "ct", "rice"]);
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["command"], "ct");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    for marker in [
+        "[debug] load took ",
+        "[debug] exec of ct took ",
+        "[debug] save took ",
+        "[debug] print took ",
+        "[trace] write ",
+    ] {
+        assert!(stderr.contains(marker), "{} not in {}", marker, stderr);
+    }
+
+    let stderr = String::from_utf8(inv(&workdir, &["-vv", "rt"]).stderr).unwrap();
+    assert!(stderr.contains("[trace] read "), "{}", stderr);
+    assert!(stderr.contains("[trace] rt: 1 of 1 item types match"));
+
+    let stderr = String::from_utf8(inv(&workdir, &["-v", "rt"]).stderr).unwrap();
+    assert!(stderr.contains("[debug] load took ") && !stderr.contains("[trace]"));
+
+    let output = inv(&workdir, &["examples", "nothing"]);
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "There is no subcommand named nothing, see inv --help\n"
+    );
+    std::fs::remove_dir_all(&workdir).unwrap();
+}
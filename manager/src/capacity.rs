@@ -0,0 +1,262 @@
+//! How much the locations can hold, like a freezer of about 30 L.
+//!
+//! Locations are only names on the item instances, so their capacities are kept in a
+//! registry of the inventory, `Inventory::locations`. The item instances at a registered
+//! location are summed in the unit of its capacity, leaving out those whose unit can not be
+//! converted to it, and the location is over capacity when the sum is above it. Item
+//! instances at locations that are not registered are never looked at.
+
+use crate::location::{similar_locations, tidy_location};
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{unit, InstanceId, Inventory, InventoryError};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A registered location.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LocationInfo {
+    /// The name of the location, matching the locations of the item instances like
+    /// `similar_locations`.
+    pub name: String,
+    pub capacity: Option<f32>,
+    /// The unit of the capacity. Without one, only the item instances without a unit are
+    /// counted.
+    pub capacity_unit: Option<String>,
+}
+
+/// How much of a registered location is used.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LocationUsage {
+    pub name: String,
+    pub capacity: Option<f32>,
+    pub capacity_unit: Option<String>,
+    /// The quantity of the item instances at the location, in the unit of the capacity.
+    pub used: f32,
+    /// The item instances at the location whose unit can not be converted to the unit of
+    /// the capacity, which are not counted.
+    pub uncounted: Vec<InstanceId>,
+}
+
+impl LocationUsage {
+    /// Whether more is stored than the location can hold.
+    pub fn is_over(&self) -> bool {
+        self.capacity.is_some_and(|capacity| self.used > capacity)
+    }
+}
+
+#[cfg(feature = "display")]
+impl Record for LocationUsage {
+    const HEADER: &'static [&'static str] =
+        &["name", "capacity", "capacity unit", "used", "uncounted"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.capacity.map(|c| c.to_string()).unwrap_or_default(),
+            self.capacity_unit.clone().unwrap_or_default(),
+            self.used.to_string(),
+            self.uncounted
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+        ]
+    }
+}
+
+impl Inventory {
+    /// Returns the registered location matching the name, see `similar_locations`.
+    pub fn location_info(&self, name: &str) -> Option<&LocationInfo> {
+        self.locations
+            .iter()
+            .find(|l| similar_locations(&l.name, name))
+    }
+
+    /// Registers a location, with its name tidied. Fails with `DuplicateLocation` when a
+    /// registered location already matches the name.
+    pub fn add_location(&mut self, mut location: LocationInfo) -> Result<(), InventoryError> {
+        location.name = tidy_location(&location.name);
+        if self.location_info(&location.name).is_some() {
+            return Err(InventoryError::DuplicateLocation);
+        }
+        self.locations.push(location);
+        Ok(())
+    }
+
+    /// Returns the registered location matching the name to change it.
+    pub fn location_info_mut(&mut self, name: &str) -> Option<&mut LocationInfo> {
+        self.locations
+            .iter_mut()
+            .find(|l| similar_locations(&l.name, name))
+    }
+
+    /// Sums the item instances that are not removed at each registered location, in the
+    /// order of the registry.
+    pub fn location_usage(&self) -> Vec<LocationUsage> {
+        let types = self.types_by_id();
+        self.locations
+            .iter()
+            .map(|location| {
+                let mut usage = LocationUsage {
+                    name: location.name.clone(),
+                    capacity: location.capacity,
+                    capacity_unit: location.capacity_unit.clone(),
+                    used: 0.0,
+                    uncounted: vec![],
+                };
+                let present = self.item_instances.iter().filter(|ii| {
+                    ii.removed_at.is_none()
+                        && ii
+                            .location
+                            .as_deref()
+                            .is_some_and(|l| similar_locations(l, &location.name))
+                });
+                for ii in present {
+                    let unit = ii
+                        .unit_override
+                        .as_deref()
+                        .or_else(|| types.get(&ii.item_type).and_then(|t| t.unit.as_deref()));
+                    let quantity = match (unit, location.capacity_unit.as_deref()) {
+                        (None, None) => Some(ii.quantity),
+                        (Some(unit), Some(capacity_unit)) => {
+                            unit::convert(ii.quantity, unit, capacity_unit)
+                        }
+                        _ => None,
+                    };
+                    match quantity {
+                        Some(quantity) => usage.used += quantity,
+                        None => usage.uncounted.push(ii.id),
+                    }
+                }
+                usage
+            })
+            .collect()
+    }
+
+    /// The registered locations holding more than their capacity.
+    pub fn over_capacity(&self) -> Vec<LocationUsage> {
+        self.location_usage()
+            .into_iter()
+            .filter(LocationUsage::is_over)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemInstance, ItemType, TypeId};
+
+    /// A freezer of 30 L and a shelf of 10 pieces, with frozen peas in litres and in
+    /// millilitres, meat in kilograms and jars counted in pieces.
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        for (name, capacity, unit) in [("Freezer", 30.0, Some("l")), ("shelf", 10.0, None)] {
+            inventory
+                .add_location(LocationInfo {
+                    name: name.to_string(),
+                    capacity: Some(capacity),
+                    capacity_unit: unit.map(str::to_string),
+                })
+                .unwrap();
+        }
+        let types = [("peas", Some("l")), ("meat", Some("kg")), ("jar", None)]
+            .iter()
+            .map(|(name, unit)| {
+                inventory.add_item_type(ItemType {
+                    name: name.to_string(),
+                    unit: unit.map(str::to_string),
+                    ..ItemType::default()
+                })
+            })
+            .collect::<Vec<_>>();
+        let instances: &[(TypeId, f32, Option<&str>, &str)] = &[
+            (types[0], 20.0, None, "freezer"),
+            (types[0], 8000.0, Some("ml"), " Freezer "),
+            (types[1], 2.0, None, "Freezer"),
+            (types[2], 4.0, None, "Shelf"),
+            (types[2], 40.0, None, "Cellar"),
+        ];
+        for (item_type, quantity, unit, location) in instances {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: *item_type,
+                    quantity: *quantity,
+                    unit_override: unit.map(str::to_string),
+                    location: Some(location.to_string()),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    #[test]
+    fn compatible_units_are_summed() {
+        let mut inventory = inventory();
+        let usage = inventory.location_usage();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage[0].name, "Freezer");
+        assert_eq!(usage[0].used, 28.0);
+        assert_eq!(usage[0].uncounted, vec![InstanceId(3)]);
+        assert!(!usage[0].is_over());
+        assert_eq!(usage[1].used, 4.0);
+        assert!(inventory.over_capacity().is_empty());
+
+        inventory.item_instances[0].quantity = 25.0;
+        inventory.trash(InstanceId(4));
+        let over = inventory.over_capacity();
+        assert_eq!(over.len(), 1);
+        assert_eq!(over[0].used, 33.0);
+        assert_eq!(inventory.location_usage()[1].used, 0.0);
+    }
+
+    #[test]
+    fn locations_are_registered_once() {
+        let mut inventory = inventory();
+        assert_eq!(
+            inventory.add_location(LocationInfo {
+                name: "  FREEZER".to_string(),
+                ..LocationInfo::default()
+            }),
+            Err(InventoryError::DuplicateLocation)
+        );
+        inventory
+            .add_location(LocationInfo {
+                name: " Garage  shelf ".to_string(),
+                ..LocationInfo::default()
+            })
+            .unwrap();
+        assert_eq!(inventory.locations[2].name, "Garage shelf");
+        assert!(!inventory.location_usage()[2].is_over());
+
+        inventory.location_info_mut("freezer").unwrap().capacity = None;
+        assert!(!inventory.location_usage()[0].is_over());
+        assert!(inventory.location_info("cellar").is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn the_registry_round_trips() {
+        let location = LocationInfo {
+            name: "Freezer".to_string(),
+            capacity: Some(30.0),
+            capacity_unit: Some("l".to_string()),
+        };
+        let json = serde_json::to_string(&location).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"Freezer","capacity":30.0,"capacity_unit":"l"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<LocationInfo>(&json).unwrap(),
+            location
+        );
+        let bare = serde_json::from_str::<LocationInfo>(r#"{"name":"Shelf"}"#).unwrap();
+        assert_eq!(bare.capacity, None);
+    }
+}
@@ -0,0 +1,288 @@
+//! Categories of item types, like `Food/Baking/Flour`, and the totals of each category.
+//!
+//! The category of an item type is a path, see `path`. Categories are compared ignoring
+//! case, and the name shown for a category is the one of its first item type.
+
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{fold_case, path, unit, Inventory, ItemType, TypeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// The totals of some item types.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CategoryRollup {
+    /// The number of item types.
+    pub types: usize,
+    /// The sum of the quantities of the item types, when they all have the same unit.
+    pub quantity: Option<f32>,
+    /// The unit of `quantity`.
+    pub unit: Option<String>,
+    /// The sum of the values of the item instances that are not removed.
+    pub value: f32,
+    /// The number of item types below one of their minimums, see `Inventory::shortages`.
+    pub below_minimum: usize,
+}
+
+impl CategoryRollup {
+    /// Adds the totals of other item types.
+    fn merge(&mut self, other: &CategoryRollup) {
+        if other.types == 0 {
+            return;
+        }
+        if self.types == 0 {
+            *self = other.clone();
+            return;
+        }
+        let same_unit = match (&self.unit, &other.unit) {
+            (None, None) => true,
+            (Some(a), Some(b)) => unit::same(a, b),
+            _ => false,
+        };
+        self.quantity = match (self.quantity, other.quantity) {
+            (Some(a), Some(b)) if same_unit => Some(a + b),
+            _ => None,
+        };
+        if self.quantity.is_none() {
+            self.unit = None;
+        }
+        self.types += other.types;
+        self.value += other.value;
+        self.below_minimum += other.below_minimum;
+    }
+}
+
+/// A category, with the item types directly in it and its subcategories.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CategoryNode {
+    /// The last segment of the path, empty for the root.
+    pub name: String,
+    /// The whole path, empty for the root.
+    pub path: String,
+    /// The ids of the item types whose category is this one, in ascending order. Those of
+    /// the root are the item types without a category.
+    pub type_ids: Vec<TypeId>,
+    /// The totals of `type_ids`.
+    pub own: CategoryRollup,
+    /// The totals of the item types of the category and of its subcategories.
+    pub rollup: CategoryRollup,
+    /// The subcategories, in alphabetical order ignoring case.
+    pub children: Vec<CategoryNode>,
+}
+
+impl CategoryNode {
+    /// The category and all its subcategories, depth first, with their depth below this one.
+    pub fn walk(&self) -> Vec<(usize, &CategoryNode)> {
+        let mut nodes = vec![(0, self)];
+        for child in &self.children {
+            nodes.extend(child.walk().into_iter().map(|(depth, n)| (depth + 1, n)));
+        }
+        nodes
+    }
+
+    /// The subcategory at a path, created if missing.
+    fn child_at(&mut self, segments: &[String]) -> &mut CategoryNode {
+        let (first, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => return self,
+        };
+        let index = match self
+            .children
+            .iter()
+            .position(|c| fold_case(&c.name) == fold_case(first))
+        {
+            Some(index) => index,
+            None => {
+                let path = if self.path.is_empty() {
+                    first.clone()
+                } else {
+                    format!("{}{}{}", self.path, path::SEPARATOR, first)
+                };
+                self.children.push(CategoryNode {
+                    name: first.clone(),
+                    path,
+                    ..CategoryNode::default()
+                });
+                self.children.len() - 1
+            }
+        };
+        self.children[index].child_at(rest)
+    }
+
+    /// Sorts the subcategories and computes the rollups from the `own` totals.
+    fn finish(&mut self) {
+        self.children.sort_by_key(|c| fold_case(&c.name));
+        let mut rollup = self.own.clone();
+        for child in &mut self.children {
+            child.finish();
+            rollup.merge(&child.rollup);
+        }
+        self.rollup = rollup;
+    }
+}
+
+#[cfg(feature = "display")]
+impl Record for CategoryNode {
+    const HEADER: &'static [&'static str] =
+        &["path", "types", "quantity", "unit", "value", "below min"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.path.clone(),
+            self.rollup.types.to_string(),
+            crate::conv(&self.rollup.quantity),
+            crate::conv(&self.rollup.unit),
+            self.rollup.value.to_string(),
+            self.rollup.below_minimum.to_string(),
+        ]
+    }
+}
+
+impl ItemType {
+    /// Whether the item type is in a category or one of its subcategories, see
+    /// `path::is_within`. Item types without a category are only within the empty one.
+    pub fn in_category(&self, category: &str) -> bool {
+        path::is_within(self.category.as_deref().unwrap_or(""), category)
+    }
+}
+
+impl Inventory {
+    /// The tree of the categories of the item types, whose root holds the item types
+    /// without a category and the totals of the whole inventory.
+    pub fn category_tree(&self) -> CategoryNode {
+        let stats = self.all_type_stats();
+        let below_minimum = self
+            .shortages()
+            .iter()
+            .map(|s| s.type_id)
+            .collect::<HashSet<_>>();
+        let mut root = CategoryNode::default();
+        let mut types = self.item_types.iter().collect::<Vec<_>>();
+        types.sort_by_key(|it| it.id);
+        for it in types {
+            let segments = path::segments(it.category.as_deref().unwrap_or(""));
+            let node = root.child_at(&segments);
+            let stats = stats.get(&it.id).cloned().unwrap_or_default();
+            node.type_ids.push(it.id);
+            node.own.merge(&CategoryRollup {
+                types: 1,
+                quantity: Some(stats.quantity),
+                unit: it.unit.clone(),
+                value: stats.total_value,
+                below_minimum: usize::from(below_minimum.contains(&it.id)),
+            });
+        }
+        root.finish();
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FixedClock, ItemInstance};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    /// The name, category, unit, quantity and minimum quantity of an item type.
+    type Type<'a> = (&'a str, Option<&'a str>, Option<&'a str>, f32, f32);
+
+    fn inventory(types: &[Type]) -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        )));
+        for (name, category, unit, quantity, minimum) in types {
+            let id = inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                category: category.map(String::from),
+                unit: unit.map(String::from),
+                minimum_quantity: *minimum,
+                ..ItemType::default()
+            });
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: id,
+                    quantity: *quantity,
+                    value: Some(2.0),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    fn summary(root: &CategoryNode) -> Vec<String> {
+        root.walk()
+            .iter()
+            .map(|(depth, n)| {
+                format!(
+                    "{}{}: {} {:?} {:?} {} {} {:?}",
+                    "-".repeat(*depth),
+                    n.path,
+                    n.rollup.types,
+                    n.rollup.quantity,
+                    n.rollup.unit,
+                    n.rollup.value,
+                    n.rollup.below_minimum,
+                    n.type_ids.iter().map(|id| id.0).collect::<Vec<_>>()
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn deep_categories_roll_up_to_the_root() {
+        let inventory = inventory(&[
+            ("flour", Some("Food/Baking/Flour"), Some("kg"), 1.0, 2.0),
+            (
+                "rye flour",
+                Some("food / baking/ flour"),
+                Some("KG"),
+                0.5,
+                0.0,
+            ),
+            ("yeast", Some("Food/Baking"), Some("g"), 100.0, 0.0),
+            ("milk", Some("Food/Dairy/Milk/Fresh"), Some("l"), 2.0, 3.0),
+            ("soap", Some("Household"), None, 3.0, 0.0),
+            ("candles", Some("household"), None, 5.0, 0.0),
+        ]);
+        assert_eq!(
+            summary(&inventory.category_tree()),
+            vec![
+                ": 6 None None 12 2 []",
+                "-Food: 4 None None 8 2 []",
+                "--Food/Baking: 3 None None 6 1 [3]",
+                "---Food/Baking/Flour: 2 Some(1.5) Some(\"kg\") 4 1 [1, 2]",
+                "--Food/Dairy: 1 Some(2.0) Some(\"l\") 2 1 []",
+                "---Food/Dairy/Milk: 1 Some(2.0) Some(\"l\") 2 1 []",
+                "----Food/Dairy/Milk/Fresh: 1 Some(2.0) Some(\"l\") 2 1 [4]",
+                "-Household: 2 Some(8.0) None 4 0 [5, 6]",
+            ]
+        );
+    }
+
+    #[test]
+    fn uncategorized_types_stay_at_the_root() {
+        let inventory = inventory(&[
+            ("rice", None, Some("kg"), 1.0, 0.0),
+            ("pasta", Some(" / "), Some("kg"), 2.0, 0.0),
+            ("tea", Some("Drinks"), Some("kg"), 0.25, 0.0),
+        ]);
+        let root = inventory.category_tree();
+        assert_eq!(root.type_ids, vec![TypeId(1), TypeId(2)]);
+        assert_eq!(root.own.quantity, Some(3.0));
+        assert_eq!(root.rollup.quantity, Some(3.25));
+        assert_eq!(root.rollup.unit.as_deref(), Some("kg"));
+        assert_eq!(root.children.len(), 1);
+        assert!(inventory.item_types[0].in_category(""));
+        assert!(!inventory.item_types[0].in_category("Drinks"));
+
+        let empty = Inventory::default().category_tree();
+        assert_eq!(empty.rollup, CategoryRollup::default());
+        assert_eq!(empty.walk().len(), 1);
+    }
+}
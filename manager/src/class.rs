@@ -0,0 +1,129 @@
+//! Named bundles of item type defaults, like "fresh" or "pantry".
+//!
+//! The values of a class are copied into the item types it is applied to, so that
+//! changing or deleting a class never changes existing item types.
+
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{ItemType, MatchMode};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ItemClass {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ttl: Option<Duration>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub opened_by_default: bool,
+    #[cfg_attr(feature = "serde", serde(default = "crate::default_true"))]
+    pub auto_trash_empty: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub minimum_counts_sealed_only: bool,
+}
+
+impl ItemClass {
+    /// Creates a class with the same defaults as `ItemTypeBuilder`.
+    pub fn new(name: &str) -> Self {
+        ItemClass {
+            name: name.to_string(),
+            ttl: None,
+            opened_by_default: false,
+            auto_trash_empty: true,
+            minimum_counts_sealed_only: false,
+        }
+    }
+
+    /// Copies the defaults of the class into the item type and records the class name.
+    /// Values set explicitly have to be assigned after this to take precedence.
+    pub fn apply(&self, item_type: &mut ItemType) {
+        item_type.class = Some(self.name.clone());
+        item_type.ttl = self.ttl;
+        item_type.opened_by_default = self.opened_by_default;
+        item_type.auto_trash_empty = self.auto_trash_empty;
+        item_type.minimum_counts_sealed_only = self.minimum_counts_sealed_only;
+    }
+}
+
+#[cfg(feature = "display")]
+impl Record for ItemClass {
+    const HEADER: &'static [&'static str] = &[
+        "name",
+        "ttl",
+        "open default",
+        "auto trash empty",
+        "min sealed only",
+    ];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.ttl
+                .map(|ttl| humantime::format_duration(ttl).to_string())
+                .unwrap_or_default(),
+            self.opened_by_default.to_string(),
+            self.auto_trash_empty.to_string(),
+            self.minimum_counts_sealed_only.to_string(),
+        ]
+    }
+}
+
+/// Finds a class by name, ignoring case.
+pub fn find_class<'a>(classes: &'a [ItemClass], name: &str) -> Option<&'a ItemClass> {
+    classes
+        .iter()
+        .find(|c| MatchMode::Exact.matches(&c.name, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ItemType;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn class_values_are_copied() {
+        let mut fresh = ItemClass::new("fresh");
+        fresh.ttl = Some(5 * DAY);
+        fresh.opened_by_default = true;
+        let mut item_type = ItemType {
+            name: "salad".to_string(),
+            ttl: Some(DAY),
+            ..ItemType::default()
+        };
+        fresh.apply(&mut item_type);
+        assert_eq!(item_type.class.as_deref(), Some("fresh"));
+        assert_eq!(item_type.ttl, Some(5 * DAY));
+        assert!(item_type.opened_by_default);
+        assert!(item_type.auto_trash_empty);
+
+        // Changing the class later does not change the item type.
+        fresh.ttl = None;
+        assert_eq!(item_type.ttl, Some(5 * DAY));
+
+        // A class without ttl removes the ttl.
+        ItemClass::new("pantry").apply(&mut item_type);
+        assert_eq!(item_type.ttl, None);
+        assert!(!item_type.opened_by_default);
+    }
+
+    #[test]
+    fn classes_are_found_ignoring_case() {
+        let classes = vec![ItemClass::new("fresh"), ItemClass::new("Pantry")];
+        assert_eq!(
+            find_class(&classes, "pantry").map(|c| c.name.as_str()),
+            Some("Pantry")
+        );
+        assert!(find_class(&classes, "frozen").is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn partial_classes_deserialize_with_type_defaults() {
+        let class: ItemClass = serde_json::from_str(r#"{"name":"pantry"}"#).unwrap();
+        assert_eq!(class, ItemClass::new("pantry"));
+    }
+}
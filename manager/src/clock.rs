@@ -0,0 +1,101 @@
+//! Sources of the current time used by the inventory operations.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Provides the current time to the inventory.
+///
+/// Every operation of `Inventory` that records a timestamp asks its clock for the time
+/// instead of calling `SystemTime::now` directly, which makes the time controllable.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The environment variable freezing the system clock at a time, like
+/// `2024-01-15T12:00:00Z`, for reproducible demos and tests. See `current_time`.
+#[cfg(feature = "time")]
+pub const FAKE_NOW_VAR: &str = "IMGOAT_FAKE_NOW";
+
+/// The time set in `FAKE_NOW_VAR`, or its value when it is not a time.
+/// Returns `None` when it is not set.
+#[cfg(feature = "time")]
+pub fn fake_now() -> Option<Result<SystemTime, String>> {
+    let value = std::env::var(FAKE_NOW_VAR).ok()?;
+    Some(humantime::parse_rfc3339_weak(value.trim()).map_err(|_| value))
+}
+
+/// The current time of the operating system, or the time of `FAKE_NOW_VAR` when it is set
+/// to a valid time. Every reading of the time goes through it.
+#[cfg(feature = "time")]
+pub fn current_time() -> SystemTime {
+    match fake_now() {
+        Some(Ok(time)) => time,
+        _ => SystemTime::now(),
+    }
+}
+
+/// The clock of the operating system, see `current_time`. This is the default clock with the
+/// `time` feature.
+#[cfg(feature = "time")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "time")]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        current_time()
+    }
+}
+
+/// A clock that stays at the same time until it is explicitly moved.
+#[derive(Debug)]
+pub struct FixedClock {
+    time: Mutex<SystemTime>,
+}
+
+impl FixedClock {
+    /// Creates a clock frozen at the specified time.
+    pub fn new(time: SystemTime) -> Self {
+        FixedClock {
+            time: Mutex::new(time),
+        }
+    }
+
+    /// Moves the clock to the specified time.
+    pub fn set(&self, time: SystemTime) {
+        *self.time.lock().expect("FixedClock mutex was poisoned") = time;
+    }
+
+    /// Moves the clock forward by the specified duration.
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.time.lock().expect("FixedClock mutex was poisoned");
+        *time += duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        *self.time.lock().expect("FixedClock mutex was poisoned")
+    }
+}
+
+/// Returns a shared handle to the system clock.
+#[cfg(feature = "time")]
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// Returns the clock of new inventories, which is the system clock.
+#[cfg(feature = "time")]
+pub fn default_clock() -> Arc<dyn Clock> {
+    system()
+}
+
+/// Returns the clock of new inventories. Without the `time` feature, it is stopped at the
+/// Unix epoch until the inventory is given another clock.
+#[cfg(not(feature = "time"))]
+pub fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(FixedClock::new(SystemTime::UNIX_EPOCH))
+}
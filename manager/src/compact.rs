@@ -0,0 +1,226 @@
+//! A compact text form of an item instance, short enough for a small QR code.
+//!
+//! The form is `IMG1|inv=<inventory>|i=<id>|t=<type name>|exp=<YYYY-MM-DD>`, where `IMG` is
+//! followed by the version of the format, and `exp` is omitted without an expiry.
+//! Backslashes and pipes inside of values are escaped with a backslash.
+//! Fields that are not known are ignored, so that later versions can add some.
+
+use crate::{date, InstanceId, Inventory, ItemInstance};
+use std::fmt;
+
+/// The prefix and version of the compact strings produced by this version.
+const HEADER: &str = "IMG1";
+
+/// Why a compact string could not be resolved to an item instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactError {
+    /// The string doesn't start with a supported prefix and version.
+    UnsupportedVersion,
+    /// A required field is missing or invalid.
+    Malformed,
+    /// The string was made for another inventory, whose name is given.
+    OtherInventory(String),
+    /// No item instance has the id of the string.
+    UnknownItemInstance(InstanceId),
+    /// The item instance with the id of the string is of another item type,
+    /// which means that the string is outdated.
+    TypeMismatch { expected: String, found: String },
+}
+
+impl fmt::Display for CompactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompactError::UnsupportedVersion => write!(f, "not a supported compact string"),
+            CompactError::Malformed => write!(f, "the compact string is malformed"),
+            CompactError::OtherInventory(name) => {
+                write!(f, "the compact string is for the inventory {}", name)
+            }
+            CompactError::UnknownItemInstance(id) => {
+                write!(f, "there is no item instance with id {}", id)
+            }
+            CompactError::TypeMismatch { expected, found } => write!(
+                f,
+                "the item instance is a {} instead of a {}, the compact string is outdated",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl ItemInstance {
+    /// Encodes the item instance in the compact form.
+    pub fn to_compact(&self, inventory_name: &str, type_name: &str) -> String {
+        let mut fields = vec![
+            HEADER.to_string(),
+            format!("inv={}", escape(inventory_name)),
+            format!("i={}", self.id),
+            format!("t={}", escape(type_name)),
+        ];
+        if let Some(exp) = self.expires_at {
+            fields.push(format!("exp={}", date::format_date(exp)));
+        }
+        fields.join("|")
+    }
+}
+
+impl Inventory {
+    /// Encodes an item instance in the compact form, marking it so that `compact_ids` doesn't
+    /// change its id silently. Returns `None` if the item instance doesn't exist.
+    pub fn issue_compact(
+        &mut self,
+        instance_id: InstanceId,
+        inventory_name: &str,
+    ) -> Option<String> {
+        let ii = self.item_instances.iter().find(|ii| ii.id == instance_id)?;
+        let compact = ii.to_compact(inventory_name, &self.type_name(ii.item_type));
+        self.item_instances
+            .iter_mut()
+            .find(|ii| ii.id == instance_id)?
+            .compact_issued = true;
+        Some(compact)
+    }
+
+    /// Finds the item instance designated by a compact string made for the inventory
+    /// with this name. Removed item instances are found too.
+    pub fn from_compact(
+        &self,
+        inventory_name: &str,
+        compact: &str,
+    ) -> Result<&ItemInstance, CompactError> {
+        let fields = split(compact.trim());
+        if fields.first().map(|f| f.as_str()) != Some(HEADER) {
+            return Err(CompactError::UnsupportedVersion);
+        }
+        let field = |key: &str| {
+            fields[1..]
+                .iter()
+                .find_map(|f| f.strip_prefix(key)?.strip_prefix('='))
+        };
+        let inventory = field("inv").ok_or(CompactError::Malformed)?;
+        if inventory != inventory_name {
+            return Err(CompactError::OtherInventory(inventory.to_string()));
+        }
+        let id = field("i")
+            .and_then(|i| i.parse::<InstanceId>().ok())
+            .ok_or(CompactError::Malformed)?;
+        let ii = self
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == id)
+            .ok_or(CompactError::UnknownItemInstance(id))?;
+        if let Some(expected) = field("t") {
+            let found = self
+                .item_types
+                .iter()
+                .find(|t| t.id == ii.item_type)
+                .map(|t| t.name.as_str())
+                .unwrap_or_default();
+            if found != expected {
+                return Err(CompactError::TypeMismatch {
+                    expected: expected.to_string(),
+                    found: found.to_string(),
+                });
+            }
+        }
+        Ok(ii)
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Splits at the pipes that are not escaped, unescaping the fields.
+fn split(compact: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = compact.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    fields.last_mut().unwrap().push(escaped);
+                }
+            }
+            '|' => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemInstance, ItemType, TypeId};
+    use std::time::{Duration, SystemTime};
+
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        for name in &["rice", r"beans | lentils \ peas"] {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            });
+        }
+        for type_id in &[1, 2] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(*type_id),
+                    expires_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_717_200_000)),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    #[test]
+    fn round_trips() {
+        let inventory = inventory();
+        let ii = &inventory.item_instances[0];
+        let compact = ii.to_compact("home", "rice");
+        assert_eq!(compact, "IMG1|inv=home|i=1|t=rice|exp=2024-06-01");
+        assert_eq!(inventory.from_compact("home", &compact), Ok(ii));
+
+        let ii = &inventory.item_instances[1];
+        let compact = ii.to_compact("my|home", &inventory.item_types[1].name);
+        assert_eq!(
+            compact,
+            r"IMG1|inv=my\|home|i=2|t=beans \| lentils \\ peas|exp=2024-06-01"
+        );
+        assert_eq!(inventory.from_compact("my|home", &compact), Ok(ii));
+    }
+
+    #[test]
+    fn only_matching_strings_are_resolved() {
+        let inventory = inventory();
+        assert_eq!(
+            inventory.from_compact("home", "IMG9|inv=home|i=1"),
+            Err(CompactError::UnsupportedVersion)
+        );
+        assert_eq!(
+            inventory.from_compact("home", "IMG1|inv=home|i=one"),
+            Err(CompactError::Malformed)
+        );
+        assert_eq!(
+            inventory.from_compact("home", "IMG1|inv=work|i=1"),
+            Err(CompactError::OtherInventory("work".to_string()))
+        );
+        assert_eq!(
+            inventory.from_compact("home", "IMG1|inv=home|i=3"),
+            Err(CompactError::UnknownItemInstance(InstanceId(3)))
+        );
+        assert_eq!(
+            inventory.from_compact("home", "IMG1|inv=home|i=2|t=rice"),
+            Err(CompactError::TypeMismatch {
+                expected: "rice".to_string(),
+                found: r"beans | lentils \ peas".to_string(),
+            })
+        );
+        // Unknown and missing optional fields are fine.
+        let ii = inventory
+            .from_compact("home", "IMG1|i=1|inv=home|note=x")
+            .unwrap();
+        assert_eq!(ii.id, InstanceId(1));
+    }
+}
@@ -0,0 +1,315 @@
+//! The total cost of ownership of item types: what was paid for them, against what was
+//! thrown away.
+
+use crate::waste::{first_month, month_of};
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{unit, Inventory, TypeId, GIVEN_AWAY, QUANTITY_EPSILON};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// What the item instances of an item type added during a period cost, and what became of
+/// them. Values are those of the item instances when they were added, shared out per unit.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TypeCost {
+    pub type_id: TypeId,
+    /// The number of item instances counted.
+    pub instances: u32,
+    /// The number of item instances left out of the sums: those without a value, and those
+    /// whose unit can not be converted to the one of the item type.
+    pub excluded: u32,
+    /// The sum of the values of the item instances, what was paid for them.
+    pub purchased: f32,
+    /// The part of the value left in the item instances removed with some quantity left.
+    pub wasted: f32,
+    /// The part of the value left in the item instances that are not removed yet.
+    pub in_stock: f32,
+    /// The part of the value that was used up: what was purchased, neither wasted nor in
+    /// stock.
+    pub consumed: f32,
+    /// The quantity used up, in the unit of the item type.
+    pub consumed_quantity: f32,
+    /// What each unit used up cost, waste included: the consumed and wasted values over the
+    /// consumed quantity. `None` when nothing was used up.
+    pub cost_per_unit: Option<f32>,
+    /// The wasted value as a percentage of the purchased one. `None` when nothing with a
+    /// value was purchased.
+    pub waste_percent: Option<f32>,
+}
+
+#[cfg(feature = "display")]
+impl Record for TypeCost {
+    const HEADER: &'static [&'static str] = &[
+        "type id",
+        "instances",
+        "excluded",
+        "purchased",
+        "wasted",
+        "in stock",
+        "consumed",
+        "consumed quantity",
+        "cost per unit",
+        "waste percent",
+    ];
+
+    fn to_record(&self) -> Vec<String> {
+        let optional = |value: Option<f32>| value.map(|v| v.to_string()).unwrap_or_default();
+        vec![
+            self.type_id.to_string(),
+            self.instances.to_string(),
+            self.excluded.to_string(),
+            self.purchased.to_string(),
+            self.wasted.to_string(),
+            self.in_stock.to_string(),
+            self.consumed.to_string(),
+            self.consumed_quantity.to_string(),
+            optional(self.cost_per_unit),
+            optional(self.waste_percent),
+        ]
+    }
+}
+
+impl TypeCost {
+    fn new(type_id: TypeId) -> Self {
+        TypeCost {
+            type_id,
+            ..TypeCost::default()
+        }
+    }
+
+    /// Derives the consumed value and the ratios from the sums.
+    fn finish(mut self) -> Self {
+        self.consumed = (self.purchased - self.wasted - self.in_stock).max(0.0);
+        self.cost_per_unit = if self.consumed_quantity > QUANTITY_EPSILON {
+            Some((self.consumed + self.wasted) / self.consumed_quantity)
+        } else {
+            None
+        };
+        self.waste_percent = if self.purchased > 0.0 {
+            Some(self.wasted / self.purchased * 100.0)
+        } else {
+            None
+        };
+        self
+    }
+}
+
+impl Inventory {
+    /// The cost of ownership of an item type, for its item instances added during the last
+    /// `months` months, the month of `now` included. Item instances given away are left out,
+    /// since they were neither used nor wasted, and so are those without `added_at`.
+    /// Returns `None` if the item type doesn't exist.
+    pub fn cost_of_ownership(
+        &self,
+        type_id: TypeId,
+        now: SystemTime,
+        months: u32,
+    ) -> Option<TypeCost> {
+        if !self.has_item_type(type_id) {
+            return None;
+        }
+        let cost = self
+            .costs(now, months)
+            .remove(&type_id)
+            .unwrap_or_else(|| TypeCost::new(type_id));
+        Some(cost.finish())
+    }
+
+    /// The cost of ownership of every item type with an item instance added during the last
+    /// `months` months, see `cost_of_ownership`. The item types wasting the largest part of
+    /// what was purchased come first, those that purchased nothing with a value last.
+    pub fn costs_of_ownership(&self, now: SystemTime, months: u32) -> Vec<TypeCost> {
+        let mut costs = self
+            .costs(now, months)
+            .into_values()
+            .map(TypeCost::finish)
+            .collect::<Vec<_>>();
+        costs.sort_by(|a, b| match (a.waste_percent, b.waste_percent) {
+            (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+        costs
+    }
+
+    /// Sums the costs of the item instances added during the period, per item type.
+    fn costs(&self, now: SystemTime, months: u32) -> BTreeMap<TypeId, TypeCost> {
+        let since = first_month(now, months);
+        let types = self.types_by_id();
+        let mut costs = BTreeMap::<TypeId, TypeCost>::new();
+        for ii in &self.item_instances {
+            match ii.added_at {
+                Some(added_at) if added_at <= now && month_of(added_at) >= since => {}
+                _ => continue,
+            }
+            if ii.removal_reason.as_deref() == Some(GIVEN_AWAY) {
+                continue;
+            }
+            let cost = costs
+                .entry(ii.item_type)
+                .or_insert_with(|| TypeCost::new(ii.item_type));
+            let type_unit = types.get(&ii.item_type).and_then(|t| t.unit.as_deref());
+            let factor = match (ii.unit_override.as_deref(), type_unit) {
+                (None, _) => Some(1.0),
+                (Some(unit), Some(type_unit)) => unit::convert(1.0, unit, type_unit),
+                (Some(_), None) => None,
+            };
+            let (unit_price, factor) = match (ii.unit_price(), factor) {
+                (Some(unit_price), Some(factor)) => (unit_price, factor),
+                _ => {
+                    cost.excluded += 1;
+                    continue;
+                }
+            };
+            let bought = ii.initial_quantity.unwrap_or(ii.quantity);
+            let left = ii.quantity.max(0.0);
+            cost.instances += 1;
+            cost.purchased += unit_price * bought;
+            match ii.removed_at {
+                Some(removed_at) if removed_at <= now => cost.wasted += unit_price * left,
+                _ => cost.in_stock += unit_price * left,
+            }
+            cost.consumed_quantity += (bought - left).max(0.0) * factor;
+        }
+        costs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FixedClock, InstanceId, ItemInstance, ItemType};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// 2020-09-13, in the middle of a month.
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    /// Adds an item instance of 4 bought for `value` on `added`, of which `left` is left.
+    fn add(
+        inventory: &mut Inventory,
+        type_id: TypeId,
+        added: SystemTime,
+        value: Option<f32>,
+        left: f32,
+    ) -> InstanceId {
+        inventory.set_clock(Arc::new(FixedClock::new(added)));
+        let id = inventory
+            .add_item_instance(ItemInstance {
+                item_type: type_id,
+                quantity: 4.0,
+                value,
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        inventory
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == id)
+            .unwrap()
+            .quantity = left;
+        inventory.set_clock(Arc::new(FixedClock::new(start())));
+        id
+    }
+
+    fn pantry() -> (Inventory, TypeId, TypeId) {
+        let mut inventory = Inventory::default();
+        let rice = inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            unit: Some("kg".to_string()),
+            ..ItemType::default()
+        });
+        let salad = inventory.add_item_type(ItemType {
+            name: "salad".to_string(),
+            ..ItemType::default()
+        });
+        let s = start();
+        // Used up.
+        let used = add(&mut inventory, rice, s - DAY * 20, Some(8.0), 0.0);
+        inventory.trash_with_reason(used, Some("used"));
+        // Half used, in stock.
+        add(&mut inventory, rice, s - DAY * 5, Some(8.0), 2.0);
+        // Without a value.
+        add(&mut inventory, rice, s - DAY * 5, None, 4.0);
+        // Bought more than a year ago.
+        add(&mut inventory, rice, s - DAY * 400, Some(8.0), 0.0);
+        // A quarter eaten, the rest thrown away.
+        let thrown = add(&mut inventory, salad, s - DAY * 3, Some(8.0), 3.0);
+        inventory.trash_with_reason(thrown, Some("expired"));
+        // Given away, neither used nor wasted.
+        let given = add(&mut inventory, salad, s - DAY * 3, Some(8.0), 4.0);
+        inventory.trash_with_reason(given, Some(GIVEN_AWAY));
+        (inventory, rice, salad)
+    }
+
+    #[test]
+    fn costs_are_split_between_consumed_wasted_and_in_stock() {
+        let (inventory, rice, salad) = pantry();
+        let rice = inventory.cost_of_ownership(rice, start(), 12).unwrap();
+        assert_eq!((rice.instances, rice.excluded), (2, 1));
+        assert_eq!(rice.purchased, 16.0);
+        assert_eq!(
+            (rice.wasted, rice.in_stock, rice.consumed),
+            (0.0, 4.0, 12.0)
+        );
+        assert_eq!(rice.consumed_quantity, 6.0);
+        assert_eq!(rice.cost_per_unit, Some(2.0));
+        assert_eq!(rice.waste_percent, Some(0.0));
+
+        let salad = inventory.cost_of_ownership(salad, start(), 12).unwrap();
+        assert_eq!(salad.instances, 1);
+        assert_eq!(
+            (salad.purchased, salad.wasted, salad.consumed),
+            (8.0, 6.0, 2.0)
+        );
+        // The waste makes the part eaten cost 8.0 instead of 2.0.
+        assert_eq!(salad.cost_per_unit, Some(8.0));
+        assert_eq!(salad.waste_percent, Some(75.0));
+
+        let all = inventory.costs_of_ownership(start(), 12);
+        assert_eq!(
+            all.iter().map(|c| c.type_id).collect::<Vec<_>>(),
+            vec![TypeId(2), TypeId(1)]
+        );
+        assert_eq!(inventory.cost_of_ownership(TypeId(3), start(), 12), None);
+    }
+
+    #[test]
+    fn nothing_consumed_has_no_cost_per_unit() {
+        let (mut inventory, rice, _) = pantry();
+        let empty = inventory.cost_of_ownership(rice, start() - DAY * 365, 1);
+        assert_eq!(empty, Some(TypeCost::new(rice)));
+        // Unused and still in stock.
+        let flour = inventory.add_item_type(ItemType {
+            name: "flour".to_string(),
+            ..ItemType::default()
+        });
+        add(&mut inventory, flour, start(), Some(8.0), 4.0);
+        // Without any value.
+        let nuts = inventory.add_item_type(ItemType {
+            name: "nuts".to_string(),
+            ..ItemType::default()
+        });
+        add(&mut inventory, nuts, start(), None, 1.0);
+        let flour = inventory.cost_of_ownership(flour, start(), 1).unwrap();
+        assert_eq!((flour.consumed, flour.cost_per_unit), (0.0, None));
+        assert_eq!(flour.waste_percent, Some(0.0));
+        let nuts = inventory.cost_of_ownership(nuts, start(), 1).unwrap();
+        assert_eq!((nuts.excluded, nuts.waste_percent), (1, None));
+        assert_eq!(
+            inventory
+                .costs_of_ownership(start(), 1)
+                .last()
+                .unwrap()
+                .type_id,
+            nuts.type_id
+        );
+    }
+}
@@ -0,0 +1,630 @@
+//! Custom fields, the metadata only some kinds of items have, like the chemistry of batteries
+//! or the color code of paint.
+//!
+//! Each item type declares its fields in `ItemType::custom_fields`, with a key and the kind
+//! of their values. The item instances of the item type hold values for some of those keys in
+//! `ItemInstance::custom`, which are parsed and checked against the fields of their item type
+//! when they are set. Setting a key the item type doesn't declare, or a value that is not of
+//! the kind of the field, is refused.
+//!
+//! Item instances are filtered on their custom fields with `key=value` and the other
+//! comparisons of `FieldFilter`, the value being parsed like the field of each item type.
+
+use crate::date;
+use crate::decimal::parse_decimal;
+use crate::{fold_case, InstanceId, Inventory, InventoryError, ItemInstance, ItemType, TypeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The kinds of values of custom fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FieldKind {
+    Text,
+    Number,
+    /// A day, written `YYYY-MM-DD`.
+    Date,
+    /// `true` or `false`, also written `yes` or `no`.
+    Bool,
+}
+
+impl FromStr for FieldKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "text" => Ok(FieldKind::Text),
+            "number" => Ok(FieldKind::Number),
+            "date" => Ok(FieldKind::Date),
+            "bool" => Ok(FieldKind::Bool),
+            other => Err(format!(
+                "unknown kind {}, expected text, number, date or bool",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FieldKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FieldKind::Text => "text",
+            FieldKind::Number => "number",
+            FieldKind::Date => "date",
+            FieldKind::Bool => "bool",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A custom field declared by an item type, written `key:kind` like `chemistry:text`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FieldDef {
+    /// Letters, digits, `_`, `-` and `.`, so that it can't be mistaken for a comparison.
+    pub key: String,
+    pub kind: FieldKind,
+}
+
+impl FieldDef {
+    /// Parses a value of the kind of the field.
+    pub fn parse_value(&self, s: &str) -> Result<FieldValue, InventoryError> {
+        let invalid = || InventoryError::InvalidFieldValue {
+            key: self.key.clone(),
+            kind: self.kind,
+        };
+        match self.kind {
+            FieldKind::Text => Ok(FieldValue::Text(s.to_string())),
+            FieldKind::Number => match parse_decimal(s) {
+                Ok(n) if n.is_finite() => Ok(FieldValue::Number(n)),
+                _ => Err(invalid()),
+            },
+            FieldKind::Date => parse_day(s).map(FieldValue::Date).ok_or_else(invalid),
+            FieldKind::Bool => match s.trim() {
+                "true" | "yes" => Ok(FieldValue::Bool(true)),
+                "false" | "no" => Ok(FieldValue::Bool(false)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+impl FromStr for FieldDef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, kind) = s
+            .rsplit_once(':')
+            .ok_or_else(|| format!("expected key:kind like chemistry:text, got \"{}\"", s))?;
+        Ok(FieldDef {
+            key: parse_key(key)?,
+            kind: kind.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for FieldDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.key, self.kind)
+    }
+}
+
+/// The value of a custom field of an item instance.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum FieldValue {
+    Bool(bool),
+    Number(f32),
+    /// The start of the day in UTC.
+    Date(SystemTime),
+    Text(String),
+}
+
+impl FieldValue {
+    /// Compares two values of the same kind, texts ignoring case. Values of different kinds
+    /// are not ordered.
+    pub fn compare(&self, other: &FieldValue) -> Option<Ordering> {
+        match (self, other) {
+            (FieldValue::Bool(a), FieldValue::Bool(b)) => Some(a.cmp(b)),
+            (FieldValue::Number(a), FieldValue::Number(b)) => a.partial_cmp(b),
+            (FieldValue::Date(a), FieldValue::Date(b)) => Some(a.cmp(b)),
+            (FieldValue::Text(a), FieldValue::Text(b)) => Some(fold_case(a).cmp(&fold_case(b))),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldValue::Bool(b) => write!(f, "{}", b),
+            FieldValue::Number(n) => write!(f, "{}", n),
+            FieldValue::Date(d) => write!(f, "{}", date::format_date(*d)),
+            FieldValue::Text(t) => write!(f, "{}", t),
+        }
+    }
+}
+
+/// A value given to a custom field, written `key=value`. An empty value removes the field
+/// from the item instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldAssignment {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for FieldAssignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected key=value like chemistry=LiIon, got \"{}\"", s))?;
+        Ok(FieldAssignment {
+            key: parse_key(key)?,
+            value: value.to_string(),
+        })
+    }
+}
+
+/// How a `FieldFilter` compares the value of an item instance to its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// The operators, longest first so that `<=` is not read as `<`.
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("!=", Comparison::NotEqual),
+        ("<=", Comparison::LessOrEqual),
+        (">=", Comparison::GreaterOrEqual),
+        ("=", Comparison::Equal),
+        ("<", Comparison::Less),
+        (">", Comparison::Greater),
+    ];
+
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A filter on a custom field, written `key=value`, or with `!=`, `<`, `<=`, `>` or `>=`
+/// instead of `=`, like `voltage>=3.7` or `bought<2024-01-01`.
+///
+/// The value is parsed like the field of the item type of each item instance, and compared
+/// to the value of the item instance, texts ignoring case. Item instances whose item type
+/// doesn't declare the field, that have no value for it, or whose item type can't parse the
+/// value never match, whatever the comparison.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldFilter {
+    pub key: String,
+    pub comparison: Comparison,
+    pub value: String,
+}
+
+impl FieldFilter {
+    /// Whether an item instance of an item type passes the filter.
+    pub fn matches(&self, item_type: Option<&ItemType>, ii: &ItemInstance) -> bool {
+        let expected = match item_type
+            .and_then(|t| t.custom_field(&self.key))
+            .map(|def| def.parse_value(&self.value))
+        {
+            Some(Ok(expected)) => expected,
+            _ => return false,
+        };
+        ii.custom
+            .get(&self.key)
+            .and_then(|value| value.compare(&expected))
+            .is_some_and(|ordering| self.comparison.holds(ordering))
+    }
+}
+
+impl FromStr for FieldFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let start = s.find(['!', '<', '>', '=']).ok_or_else(|| {
+            format!(
+                "expected key=value, or a comparison like voltage>=3.7, got \"{}\"",
+                s
+            )
+        })?;
+        let (operator, comparison) = Comparison::OPERATORS
+            .iter()
+            .find(|(operator, _)| s[start..].starts_with(operator))
+            .ok_or_else(|| format!("unknown comparison in \"{}\"", s))?;
+        Ok(FieldFilter {
+            key: parse_key(&s[..start])?,
+            comparison: *comparison,
+            value: s[start + operator.len()..].to_string(),
+        })
+    }
+}
+
+/// Checks a key, trimmed.
+fn parse_key(key: &str) -> Result<String, String> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err("missing key".to_string());
+    }
+    match key
+        .chars()
+        .find(|c| !(c.is_alphanumeric() || matches!(c, '_' | '-' | '.')))
+    {
+        Some(c) => Err(format!("invalid character {:?} in the key {}", c, key)),
+        None => Ok(key.to_string()),
+    }
+}
+
+/// Parses `YYYY-MM-DD`, checking that the day exists.
+fn parse_day(s: &str) -> Option<SystemTime> {
+    let mut parts = s.trim().splitn(3, '-');
+    let year = parts.next()?.parse::<i32>().ok()?;
+    let month = parts.next()?.parse::<u8>().ok()?;
+    let day = parts.next()?.parse::<u8>().ok()?;
+    if !(1..=12).contains(&month) || !(1..=date::days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    Some(date::from_civil_date(year, month, day))
+}
+
+impl ItemType {
+    /// Returns the custom field declared with the key.
+    pub fn custom_field(&self, key: &str) -> Option<&FieldDef> {
+        self.custom_fields.iter().find(|f| f.key == key)
+    }
+
+    /// Parses the values given to the custom fields of the item type. `None` stands for an
+    /// empty value, which removes the field.
+    pub fn parse_custom(
+        &self,
+        assignments: &[FieldAssignment],
+    ) -> Result<Vec<(String, Option<FieldValue>)>, InventoryError> {
+        assignments
+            .iter()
+            .map(|a| {
+                let def = self
+                    .custom_field(&a.key)
+                    .ok_or_else(|| InventoryError::UnknownField { key: a.key.clone() })?;
+                let value = if a.value.is_empty() {
+                    None
+                } else {
+                    Some(def.parse_value(&a.value)?)
+                };
+                Ok((a.key.clone(), value))
+            })
+            .collect()
+    }
+}
+
+impl Inventory {
+    /// Declares a custom field on an item type. Fails with `DuplicateField` when the item type
+    /// already has a field with the key, whatever its kind.
+    pub fn add_custom_field(
+        &mut self,
+        type_id: TypeId,
+        field: FieldDef,
+    ) -> Result<(), InventoryError> {
+        let item_type = self
+            .item_types
+            .iter_mut()
+            .find(|t| t.id == type_id)
+            .ok_or(InventoryError::UnknownItemType)?;
+        if item_type.custom_field(&field.key).is_some() {
+            return Err(InventoryError::DuplicateField { key: field.key });
+        }
+        item_type.custom_fields.push(field);
+        Ok(())
+    }
+
+    /// Sets the custom fields of an item instance, checked against the fields of its item
+    /// type. Nothing is changed when one of them is refused.
+    pub fn set_custom(
+        &mut self,
+        instance_id: InstanceId,
+        assignments: &[FieldAssignment],
+    ) -> Result<(), InventoryError> {
+        let type_id = self
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?
+            .item_type;
+        let values = self
+            .item_types
+            .iter()
+            .find(|t| t.id == type_id)
+            .ok_or(InventoryError::UnknownItemType)?
+            .parse_custom(assignments)?;
+        let ii = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == instance_id)
+            .expect("the item instance was found above");
+        apply_custom(&mut ii.custom, values);
+        Ok(())
+    }
+}
+
+/// Applies the values returned by `ItemType::parse_custom`, removing the empty ones.
+pub fn apply_custom(
+    custom: &mut BTreeMap<String, FieldValue>,
+    values: Vec<(String, Option<FieldValue>)>,
+) {
+    for (key, value) in values {
+        match value {
+            Some(value) => custom.insert(key, value),
+            None => custom.remove(&key),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Batteries with a chemistry, a voltage, a purchase day and whether they are
+    /// rechargeable, and paint without custom fields.
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        let batteries = inventory.add_item_type(ItemType {
+            name: "batteries".to_string(),
+            ..ItemType::default()
+        });
+        inventory.add_item_type(ItemType {
+            name: "paint".to_string(),
+            ..ItemType::default()
+        });
+        for field in [
+            "chemistry:text",
+            "voltage:number",
+            "bought:date",
+            "rechargeable:bool",
+        ] {
+            inventory
+                .add_custom_field(batteries, field.parse().unwrap())
+                .unwrap();
+        }
+        for item_type in [batteries, batteries, TypeId(2)] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    fn set(
+        inventory: &mut Inventory,
+        id: InstanceId,
+        assignments: &[&str],
+    ) -> Result<(), InventoryError> {
+        let assignments = assignments
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect::<Vec<_>>();
+        inventory.set_custom(id, &assignments)
+    }
+
+    fn matching(inventory: &Inventory, filter: &str) -> Vec<u32> {
+        let filter = filter.parse::<FieldFilter>().unwrap();
+        inventory
+            .item_instances
+            .iter()
+            .filter(|ii| {
+                let item_type = inventory.item_types.iter().find(|t| t.id == ii.item_type);
+                filter.matches(item_type, ii)
+            })
+            .map(|ii| ii.id.0)
+            .collect()
+    }
+
+    #[test]
+    fn definitions_and_filters_are_parsed() {
+        let def = "chemistry:text".parse::<FieldDef>().unwrap();
+        assert_eq!(def.kind, FieldKind::Text);
+        assert_eq!(def.to_string(), "chemistry:text");
+        assert_eq!(
+            " color.code : number"
+                .parse::<FieldDef>()
+                .unwrap()
+                .to_string(),
+            "color.code:number"
+        );
+        for invalid in [
+            "chemistry",
+            "chemistry:color",
+            ":text",
+            "a b:text",
+            "a=b:text",
+        ] {
+            assert!(invalid.parse::<FieldDef>().is_err(), "{}", invalid);
+        }
+
+        let filter = "voltage>=3.7".parse::<FieldFilter>().unwrap();
+        assert_eq!(filter.key, "voltage");
+        assert_eq!(filter.comparison, Comparison::GreaterOrEqual);
+        assert_eq!(filter.value, "3.7");
+        let filter = "note=a=b".parse::<FieldFilter>().unwrap();
+        assert_eq!(filter.comparison, Comparison::Equal);
+        assert_eq!(filter.value, "a=b");
+        assert_eq!(
+            "k!=".parse::<FieldFilter>().unwrap().comparison,
+            Comparison::NotEqual
+        );
+        for invalid in ["voltage", "=3", "volt age<3", "voltage!3"] {
+            assert!(invalid.parse::<FieldFilter>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn values_are_checked_against_the_item_type() {
+        let mut inventory = inventory();
+        set(
+            &mut inventory,
+            InstanceId(1),
+            &["chemistry=LiIon", "voltage=3.7", "bought=2024-02-29"],
+        )
+        .unwrap();
+        let custom = &inventory.item_instances[0].custom;
+        assert_eq!(custom["chemistry"], FieldValue::Text("LiIon".to_string()));
+        assert_eq!(custom["voltage"], FieldValue::Number(3.7));
+        assert_eq!(custom["bought"].to_string(), "2024-02-29");
+
+        let refused = [
+            (
+                "color=red",
+                InventoryError::UnknownField {
+                    key: "color".to_string(),
+                },
+            ),
+            (
+                "voltage=high",
+                InventoryError::InvalidFieldValue {
+                    key: "voltage".to_string(),
+                    kind: FieldKind::Number,
+                },
+            ),
+            (
+                "bought=2023-02-29",
+                InventoryError::InvalidFieldValue {
+                    key: "bought".to_string(),
+                    kind: FieldKind::Date,
+                },
+            ),
+            (
+                "rechargeable=maybe",
+                InventoryError::InvalidFieldValue {
+                    key: "rechargeable".to_string(),
+                    kind: FieldKind::Bool,
+                },
+            ),
+        ];
+        for (assignment, error) in refused {
+            assert_eq!(
+                set(
+                    &mut inventory,
+                    InstanceId(1),
+                    &["rechargeable=yes", assignment]
+                ),
+                Err(error)
+            );
+        }
+        // Nothing is set when a value is refused.
+        assert!(!inventory.item_instances[0]
+            .custom
+            .contains_key("rechargeable"));
+        assert_eq!(
+            set(&mut inventory, InstanceId(3), &["chemistry=LiIon"]),
+            Err(InventoryError::UnknownField {
+                key: "chemistry".to_string()
+            })
+        );
+
+        set(
+            &mut inventory,
+            InstanceId(1),
+            &["voltage=", "rechargeable=no"],
+        )
+        .unwrap();
+        let custom = &inventory.item_instances[0].custom;
+        assert!(!custom.contains_key("voltage"));
+        assert_eq!(custom["rechargeable"], FieldValue::Bool(false));
+
+        assert_eq!(
+            inventory.add_custom_field(TypeId(1), "voltage:text".parse().unwrap()),
+            Err(InventoryError::DuplicateField {
+                key: "voltage".to_string()
+            })
+        );
+        assert_eq!(
+            inventory.add_custom_field(TypeId(9), "voltage:text".parse().unwrap()),
+            Err(InventoryError::UnknownItemType)
+        );
+    }
+
+    #[test]
+    fn filters_compare_values_of_their_kind() {
+        let mut inventory = inventory();
+        set(
+            &mut inventory,
+            InstanceId(1),
+            &["chemistry=LiIon", "voltage=3.7", "bought=2024-01-15"],
+        )
+        .unwrap();
+        set(
+            &mut inventory,
+            InstanceId(2),
+            &["chemistry=NiMH", "voltage=1.2", "rechargeable=true"],
+        )
+        .unwrap();
+
+        assert_eq!(matching(&inventory, "chemistry=liion"), vec![1]);
+        assert_eq!(matching(&inventory, "chemistry!=LiIon"), vec![2]);
+        assert_eq!(matching(&inventory, "voltage>1.5"), vec![1]);
+        assert_eq!(matching(&inventory, "voltage<=3.7"), vec![1, 2]);
+        assert_eq!(matching(&inventory, "bought<2024-02-01"), vec![1]);
+        assert_eq!(matching(&inventory, "rechargeable=yes"), vec![2]);
+        // Values of the wrong kind and missing fields never match.
+        assert!(matching(&inventory, "voltage=high").is_empty());
+        assert!(matching(&inventory, "bought!=2024-01-15").is_empty());
+        assert!(matching(&inventory, "color=red").is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn fields_round_trip() {
+        let mut inventory = inventory();
+        set(
+            &mut inventory,
+            InstanceId(1),
+            &[
+                "chemistry=LiIon",
+                "voltage=3.7",
+                "bought=2024-01-15",
+                "rechargeable=yes",
+            ],
+        )
+        .unwrap();
+
+        let item_type = &inventory.item_types[0];
+        let json = serde_json::to_string(&item_type.custom_fields).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"key":"chemistry","kind":"text"},{"key":"voltage","kind":"number"},{"key":"bought","kind":"date"},{"key":"rechargeable","kind":"bool"}]"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Vec<FieldDef>>(&json).unwrap(),
+            item_type.custom_fields
+        );
+
+        let ii = &inventory.item_instances[0];
+        let json = serde_json::to_string(ii).unwrap();
+        assert_eq!(serde_json::from_str::<ItemInstance>(&json).unwrap(), *ii);
+        let json = serde_json::to_string(&ii.custom).unwrap();
+        assert!(json.contains(r#""chemistry":"LiIon""#));
+        assert!(json.contains(r#""rechargeable":true"#));
+    }
+}
@@ -0,0 +1,128 @@
+//! Calendar dates of timestamps, computed without formatting them.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// The number of days from 1970-01-01 to the day of a time in UTC.
+fn days_since_epoch(time: SystemTime) -> i64 {
+    let seconds = match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+    };
+    seconds.div_euclid(SECONDS_PER_DAY)
+}
+
+/// The year, month and day of a time in UTC, in the proleptic Gregorian calendar.
+pub fn civil_date(time: SystemTime) -> (i32, u8, u8) {
+    // The days since 0000-03-01, so that leap days end the years.
+    let days = days_since_epoch(time) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let march_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * march_month + 2) / 5 + 1;
+    let month = if march_month < 10 {
+        march_month + 3
+    } else {
+        march_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as i32, month as u8, day as u8)
+}
+
+/// The date of a time in UTC, as `YYYY-MM-DD`.
+pub fn format_date(time: SystemTime) -> String {
+    let (year, month, day) = civil_date(time);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// The start of a day in UTC. Days past the end of the month carry over to the next ones.
+pub fn from_civil_date(year: i32, month: u8, day: u8) -> SystemTime {
+    // The inverse of `civil_date`, with years starting in March.
+    let year = i64::from(year) - if month <= 2 { 1 } else { 0 };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let march_month = (i64::from(month) + 9) % 12;
+    let day_of_year = (153 * march_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+    let seconds = days * SECONDS_PER_DAY;
+    if seconds >= 0 {
+        UNIX_EPOCH + Duration::from_secs(seconds as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(-seconds as u64)
+    }
+}
+
+/// The number of days of a month.
+pub fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The day of the week of a time in UTC, from 0 for Monday to 6 for Sunday.
+pub fn weekday(time: SystemTime) -> u8 {
+    // The first of January 1970 was a Thursday.
+    (days_since_epoch(time) + 3).rem_euclid(7) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(seconds: i64) -> SystemTime {
+        if seconds >= 0 {
+            UNIX_EPOCH + Duration::from_secs(seconds as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(-seconds as u64)
+        }
+    }
+
+    #[test]
+    fn dates_match_the_calendar() {
+        assert_eq!(civil_date(UNIX_EPOCH), (1970, 1, 1));
+        assert_eq!(civil_date(at(1_600_000_000)), (2020, 9, 13));
+        assert_eq!(civil_date(at(951_782_400)), (2000, 2, 29));
+        assert_eq!(civil_date(at(951_868_799)), (2000, 2, 29));
+        assert_eq!(civil_date(at(951_868_800)), (2000, 3, 1));
+        assert_eq!(civil_date(at(1_609_459_199)), (2020, 12, 31));
+        assert_eq!(civil_date(at(-1)), (1969, 12, 31));
+        assert_eq!(format_date(at(4_102_444_800)), "2100-01-01");
+    }
+
+    #[test]
+    fn days_start_at_midnight() {
+        assert_eq!(from_civil_date(1970, 1, 1), UNIX_EPOCH);
+        assert_eq!(from_civil_date(2000, 2, 29), at(951_782_400));
+        assert_eq!(from_civil_date(2000, 2, 30), at(951_868_800));
+        assert_eq!(from_civil_date(1969, 12, 31), at(-SECONDS_PER_DAY));
+        for seconds in (-5_000_000_000i64..5_000_000_000).step_by(7_777_777) {
+            let (year, month, day) = civil_date(at(seconds));
+            let start = from_civil_date(year, month, day);
+            assert_eq!(civil_date(start), (year, month, day));
+            assert!(start <= at(seconds) && at(seconds) < start + Duration::from_secs(86_400));
+        }
+        assert_eq!(days_in_month(2000, 2), 29);
+        assert_eq!(days_in_month(2100, 2), 28);
+        assert_eq!(days_in_month(2024, 4), 30);
+        assert_eq!(weekday(UNIX_EPOCH), 3);
+        assert_eq!(weekday(at(1_600_000_000)), 6);
+        assert_eq!(weekday(at(-1)), 2);
+    }
+
+    #[test]
+    fn dates_match_rfc3339() {
+        for seconds in (0..5_000_000_000i64).step_by(7_777_777) {
+            let rfc3339 = humantime::format_rfc3339_seconds(at(seconds)).to_string();
+            assert_eq!(format_date(at(seconds)), rfc3339[..10]);
+        }
+    }
+}
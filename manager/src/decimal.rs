@@ -0,0 +1,145 @@
+//! Decimal numbers as people type them, with a dot or a comma before the decimals.
+//!
+//! `1.5` and `1,5` are the same number. A comma followed by exactly three digits, like
+//! `1,500`, is refused: it could be a thousand separator as much as decimals.
+
+use std::fmt;
+
+/// Why a text is not a decimal number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    /// The text is not a number at all.
+    Invalid(String),
+    /// The comma could be a thousand separator or the decimal separator.
+    Ambiguous(String),
+    /// The number is infinite or not a number.
+    NotFinite(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "expected a number, got nothing"),
+            ParseError::Invalid(s) => write!(f, "{} is not a number like 2, 1.5 or 1,5", s),
+            ParseError::Ambiguous(s) => {
+                let (whole, decimals) = s.split_once(',').unwrap_or((s, ""));
+                let short = decimals.trim_end_matches('0');
+                let point = if short.is_empty() { "" } else { "." };
+                write!(
+                    f,
+                    "{} is ambiguous, write {}{} or {}{}{}",
+                    s, whole, decimals, whole, point, short
+                )
+            }
+            ParseError::NotFinite(s) => write!(f, "{} is not a finite number", s),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Whether a comma between these parts reads as a thousand separator: one to three digits
+/// without a leading zero, then exactly three digits.
+fn looks_like_thousands(whole: &str, decimals: &str) -> bool {
+    let digits = whole.trim_start_matches(['-', '+']);
+    (1..=3).contains(&digits.len())
+        && !digits.starts_with('0')
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && decimals.len() == 3
+        && decimals.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parses a decimal number written with a dot or a comma as decimal separator.
+pub fn parse_decimal(s: &str) -> Result<f32, ParseError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    let normalized = match s.split(',').collect::<Vec<_>>().as_slice() {
+        [_] => s.to_string(),
+        [whole, decimals] if !s.contains('.') => {
+            if looks_like_thousands(whole, decimals) {
+                return Err(ParseError::Ambiguous(s.to_string()));
+            }
+            format!("{}.{}", whole, decimals)
+        }
+        _ => return Err(ParseError::Invalid(s.to_string())),
+    };
+    if !normalized.bytes().any(|b| b.is_ascii_digit()) {
+        return Err(ParseError::Invalid(s.to_string()));
+    }
+    let value = normalized
+        .parse::<f32>()
+        .map_err(|_| ParseError::Invalid(s.to_string()))?;
+    if !value.is_finite() {
+        return Err(ParseError::NotFinite(s.to_string()));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dots_and_commas_are_decimal_separators() {
+        let cases: &[(&str, f32)] = &[
+            ("2", 2.0),
+            ("1.5", 1.5),
+            ("1,5", 1.5),
+            (" 1,5 ", 1.5),
+            ("-1,5", -1.5),
+            ("+1.5", 1.5),
+            ("0,125", 0.125),
+            ("0.125", 0.125),
+            ("1,50", 1.5),
+            ("1,5000", 1.5),
+            ("1234,567", 1234.567),
+            ("1500", 1500.0),
+            ("1.500", 1.5),
+            (",5", 0.5),
+            ("5,", 5.0),
+            (".5", 0.5),
+            ("1e3", 1000.0),
+        ];
+        for (s, value) in cases {
+            assert_eq!(parse_decimal(s), Ok(*value), "{}", s);
+        }
+    }
+
+    #[test]
+    fn thousand_separators_and_garbage_are_refused() {
+        let cases: &[(&str, ParseError)] = &[
+            ("", ParseError::Empty),
+            ("  ", ParseError::Empty),
+            ("1,500", ParseError::Ambiguous("1,500".to_string())),
+            ("-12,345", ParseError::Ambiguous("-12,345".to_string())),
+            ("999,999", ParseError::Ambiguous("999,999".to_string())),
+            ("1,500,000", ParseError::Invalid("1,500,000".to_string())),
+            ("1,500.5", ParseError::Invalid("1,500.5".to_string())),
+            ("1.5,5", ParseError::Invalid("1.5,5".to_string())),
+            (",", ParseError::Invalid(",".to_string())),
+            ("abc", ParseError::Invalid("abc".to_string())),
+            ("1..5", ParseError::Invalid("1..5".to_string())),
+            ("inf", ParseError::Invalid("inf".to_string())),
+            ("NaN", ParseError::Invalid("NaN".to_string())),
+            ("1e39", ParseError::NotFinite("1e39".to_string())),
+        ];
+        for (s, error) in cases {
+            assert_eq!(parse_decimal(s).as_ref(), Err(error), "{}", s);
+        }
+    }
+
+    #[test]
+    fn ambiguous_numbers_suggest_both_readings() {
+        assert_eq!(
+            parse_decimal("1,500").unwrap_err().to_string(),
+            "1,500 is ambiguous, write 1500 or 1.5"
+        );
+        assert_eq!(
+            parse_decimal("2,000").unwrap_err().to_string(),
+            "2,000 is ambiguous, write 2000 or 2"
+        );
+    }
+}
@@ -0,0 +1,487 @@
+//! A report of everything in the inventory that needs attention.
+
+use crate::{
+    time_math, HealthOptions, HealthReport, InstanceId, Inventory, ItemInstance, LocationUsage,
+    TypeId,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+/// The options of `Inventory::digest`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestOptions {
+    /// How far back and ahead the expiries are reported. Defaults to a week.
+    pub period: Duration,
+    /// The options of the health report to add, none by default.
+    pub health: Option<HealthOptions>,
+    /// Whether to report the snoozed item instances and item types too, see `snooze`.
+    pub include_snoozed: bool,
+}
+
+impl Default for DigestOptions {
+    fn default() -> Self {
+        DigestOptions {
+            period: Duration::from_secs(7 * 24 * 60 * 60),
+            health: None,
+            include_snoozed: false,
+        }
+    }
+}
+
+/// An item instance in a section of the digest.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DigestEntry {
+    pub instance_id: InstanceId,
+    pub type_id: TypeId,
+    pub type_name: String,
+    pub quantity: f32,
+    /// The date that put the item instance in its section: the expiry, the end of its ttl,
+    /// or the end of the `remind_after_open` of its item type.
+    pub at: SystemTime,
+    /// When the item instance was opened, for the sections about opened item instances.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub opened_at: Option<SystemTime>,
+}
+
+/// An item type to buy to reach a minimum quantity.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DigestPurchase {
+    pub type_id: TypeId,
+    pub type_name: String,
+    /// The location that is short, or `None` for the total of the item type.
+    pub location: Option<String>,
+    /// The missing quantity, rounded up to whole packages for the item types with a
+    /// package size, otherwise to a whole number.
+    pub suggested_quantity: f32,
+}
+
+/// Everything that needs attention, by section. Each section is ordered by date or by item type.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Digest {
+    /// Item instances that expired during the last period, to throw out.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub expired: Vec<DigestEntry>,
+    /// Item instances expiring during the next period.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub expiring: Vec<DigestEntry>,
+    /// Item types below one of their minimum quantities.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub to_buy: Vec<DigestPurchase>,
+    /// Item instances opened for longer than the ttl of their item type.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub opened_too_long: Vec<DigestEntry>,
+    /// Item instances opened for the `remind_after_open` of their item type, which are not
+    /// expired and were not reminded since. See `Inventory::mark_reminded`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub opened_reminders: Vec<DigestEntry>,
+    /// Registered locations holding more than their capacity, see `capacity`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Vec::is_empty")
+    )]
+    pub over_capacity: Vec<LocationUsage>,
+    /// How complete the data is, when requested by `DigestOptions::health`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub health: Option<HealthReport>,
+}
+
+impl Digest {
+    /// Whether there is nothing that needs attention. The health report doesn't count.
+    pub fn is_empty(&self) -> bool {
+        self.expired.is_empty()
+            && self.expiring.is_empty()
+            && self.to_buy.is_empty()
+            && self.opened_too_long.is_empty()
+            && self.opened_reminders.is_empty()
+            && self.over_capacity.is_empty()
+    }
+}
+
+impl Inventory {
+    /// Assembles the digest at the specified time, ignoring the clock of the inventory.
+    /// Removed item instances are never reported, and snoozed ones only when asked to.
+    pub fn digest(&self, now: SystemTime, options: &DigestOptions) -> Digest {
+        let entry = |ii: &ItemInstance, at: SystemTime| DigestEntry {
+            instance_id: ii.id,
+            type_id: ii.item_type,
+            type_name: self.type_name(ii.item_type),
+            quantity: ii.quantity,
+            at,
+            opened_at: ii.opened_at,
+        };
+        let mut present = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+            .filter(|ii| options.include_snoozed || !self.is_snoozed(ii, now))
+            .collect::<Vec<_>>();
+        present.sort_by(|a, b| a.cmp_age(b));
+        let since = time_math::saturating_sub_duration(now, options.period);
+        let until = time_math::checked_add_duration(now, options.period);
+
+        let mut expired = vec![];
+        let mut expiring = vec![];
+        for ii in &present {
+            match ii.expires_at {
+                Some(exp) if exp > since && exp <= now => expired.push(entry(ii, exp)),
+                Some(exp) if exp > now && until.is_none_or(|until| exp <= until) => {
+                    expiring.push(entry(ii, exp))
+                }
+                _ => {}
+            }
+        }
+
+        let mut opened_too_long = present
+            .iter()
+            .filter_map(|ii| {
+                let ttl = self
+                    .item_types
+                    .iter()
+                    .find(|it| it.id == ii.item_type)?
+                    .ttl?;
+                let limit = time_math::checked_add_duration(ii.opened_at?, ttl)?;
+                if time_math::is_past(limit, now) {
+                    Some(entry(ii, limit))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut opened_reminders = present
+            .iter()
+            .filter_map(|ii| {
+                let remind_after = self
+                    .item_types
+                    .iter()
+                    .find(|it| it.id == ii.item_type)?
+                    .remind_after_open?;
+                let limit = time_math::checked_add_duration(ii.opened_at?, remind_after)?;
+                let expired = ii
+                    .expires_at
+                    .is_some_and(|exp| time_math::is_past(exp, now));
+                let reminded = ii.last_reminded_at.is_some_and(|at| at >= limit);
+                if time_math::is_past(limit, now) && !expired && !reminded {
+                    Some(entry(ii, limit))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let to_buy = self
+            .shortages()
+            .into_iter()
+            .filter(|s| {
+                options.include_snoozed
+                    || !self
+                        .item_types
+                        .iter()
+                        .any(|t| t.id == s.type_id && t.is_snoozed(now))
+            })
+            .map(|s| DigestPurchase {
+                type_id: s.type_id,
+                type_name: self.type_name(s.type_id),
+                suggested_quantity: match s.packages_to_buy {
+                    Some(_) => s.to_buy(),
+                    None => s.to_buy().ceil(),
+                },
+                location: s.location,
+            })
+            .collect();
+
+        for section in [
+            &mut expired,
+            &mut expiring,
+            &mut opened_too_long,
+            &mut opened_reminders,
+        ] {
+            // Stable, so the item instances due at the same time stay oldest first.
+            section.sort_by_key(|e| e.at);
+        }
+        Digest {
+            expired,
+            expiring,
+            to_buy,
+            opened_too_long,
+            opened_reminders,
+            over_capacity: self.over_capacity(),
+            health: options
+                .health
+                .as_ref()
+                .map(|health| self.health_with(now, health)),
+        }
+    }
+
+    /// Records that the digest was delivered with these item instances among its reminders,
+    /// so that they are not reminded again until they are opened again.
+    pub fn mark_reminded(&mut self, instance_ids: &[InstanceId]) {
+        let now = self.now();
+        self.item_instances
+            .iter_mut()
+            .filter(|ii| instance_ids.contains(&ii.id))
+            .for_each(|ii| ii.last_reminded_at = Some(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FixedClock, ItemInstance, ItemType, LocationInfo, Snooze};
+    use std::sync::Arc;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    /// A kitchen with something to report in every section, and some noise.
+    fn fixture() -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(start() - DAY * 30)));
+        let mut add_type = |name: &str, minimum: f32, ttl_days: Option<u32>| {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                minimum_quantity: minimum,
+                ttl: ttl_days.map(|d| DAY * d),
+                ..ItemType::default()
+            })
+        };
+        let milk = add_type("milk", 0.0, Some(5));
+        let eggs = add_type("eggs", 12.0, None);
+        let rice = add_type("rice", 1.0, None);
+        let mut add = |type_id, quantity, expires: Option<i64>, opened: Option<i64>| {
+            let day = |d: i64| {
+                if d < 0 {
+                    start() - DAY * (-d as u32)
+                } else {
+                    start() + DAY * d as u32
+                }
+            };
+            let mut ii = ItemInstance {
+                item_type: type_id,
+                quantity,
+                expires_at: expires.map(day),
+                ..ItemInstance::default()
+            };
+            ii.opened_at = opened.map(day);
+            inventory.insert_item_instance(ii).unwrap()
+        };
+        add(milk, 1.0, Some(-2), None); // 1: expired this week
+        add(milk, 0.5, Some(-10), None); // 2: expired before this week
+        add(milk, 1.0, Some(3), Some(-6)); // 3: expiring, opened too long
+        add(eggs, 6.0, Some(7), None); // 4: expiring on the last day of next week
+        add(eggs, 4.0, Some(8), Some(-1)); // 5: after next week
+        add(rice, 2.0, None, Some(-20)); // 6: no ttl, never opened too long
+        let trashed = add(milk, 1.0, Some(-1), Some(-9)); // 7: in the trash
+        inventory.set_clock(Arc::new(FixedClock::new(start())));
+        inventory.trash(trashed);
+        inventory
+    }
+
+    fn ids(entries: &[DigestEntry]) -> Vec<u32> {
+        entries.iter().map(|e| e.instance_id.0).collect()
+    }
+
+    #[test]
+    fn sections_match_the_fixture() {
+        let digest = fixture().digest(start(), &DigestOptions::default());
+        assert_eq!(ids(&digest.expired), vec![1]);
+        assert_eq!(digest.expired[0].type_name, "milk");
+        assert_eq!(digest.expired[0].at, start() - 2 * DAY);
+        assert_eq!(ids(&digest.expiring), vec![3, 4]);
+        assert_eq!(ids(&digest.opened_too_long), vec![3]);
+        assert_eq!(digest.opened_too_long[0].at, start() - DAY);
+        assert_eq!(
+            digest.to_buy,
+            vec![DigestPurchase {
+                type_id: TypeId(2),
+                type_name: "eggs".to_string(),
+                location: None,
+                suggested_quantity: 2.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn full_locations_are_reported() {
+        let mut inventory = fixture();
+        inventory
+            .add_location(LocationInfo {
+                name: "Fridge".to_string(),
+                capacity: Some(6.0),
+                capacity_unit: None,
+            })
+            .unwrap();
+        assert!(inventory
+            .digest(start(), &DigestOptions::default())
+            .over_capacity
+            .is_empty());
+
+        for ii in inventory
+            .item_instances
+            .iter_mut()
+            .filter(|ii| ii.id <= InstanceId(4))
+        {
+            ii.location = Some("fridge".to_string());
+        }
+        let digest = inventory.digest(start(), &DigestOptions::default());
+        assert_eq!(digest.over_capacity.len(), 1);
+        assert_eq!(digest.over_capacity[0].used, 8.5);
+    }
+
+    #[test]
+    fn snoozed_records_are_left_out() {
+        let mut inventory = fixture();
+        inventory
+            .snooze_instance(InstanceId(1), Some(Snooze::Forever))
+            .unwrap();
+        inventory
+            .snooze_type(TypeId(2), Some(Snooze::Until(start() + DAY)))
+            .unwrap();
+        let digest = inventory.digest(start(), &DigestOptions::default());
+        assert!(digest.expired.is_empty());
+        assert_eq!(ids(&digest.expiring), vec![3]);
+        assert!(digest.to_buy.is_empty());
+
+        let all = DigestOptions {
+            include_snoozed: true,
+            ..DigestOptions::default()
+        };
+        assert_eq!(ids(&inventory.digest(start(), &all).expired), vec![1]);
+
+        // The snooze of eggs is over the next day.
+        let later = inventory.digest(start() + DAY, &DigestOptions::default());
+        assert_eq!(ids(&later.expiring), vec![3, 4, 5]);
+        assert_eq!(later.to_buy.len(), 1);
+    }
+
+    #[test]
+    fn period_and_time_are_parameters() {
+        let inventory = fixture();
+        let options = DigestOptions {
+            period: DAY * 30,
+            ..DigestOptions::default()
+        };
+        let digest = inventory.digest(start(), &options);
+        assert_eq!(ids(&digest.expired), vec![2, 1]);
+        assert_eq!(ids(&digest.expiring), vec![3, 4, 5]);
+
+        let later = inventory.digest(start() + DAY * 9, &DigestOptions::default());
+        assert_eq!(ids(&later.expired), vec![3, 4, 5]);
+        assert!(later.expiring.is_empty());
+    }
+
+    #[test]
+    fn opened_item_instances_are_reminded_once_per_opening() {
+        let mut inventory = fixture();
+        let starter = inventory.add_item_type(ItemType {
+            name: "sourdough starter".to_string(),
+            remind_after_open: Some(DAY * 5),
+            ..ItemType::default()
+        });
+        let mut add = |opened: SystemTime, expires: Option<SystemTime>| {
+            let mut ii = ItemInstance {
+                item_type: starter,
+                expires_at: expires,
+                ..ItemInstance::default()
+            };
+            ii.opened_at = Some(opened);
+            inventory.insert_item_instance(ii).unwrap()
+        };
+        let fed = add(start() - DAY * 6, None);
+        let recent = add(start() - DAY * 4, None); // Not for long enough yet.
+        add(start() - DAY * 10, Some(start() - DAY)); // Expired.
+        let reminders = |inventory: &Inventory, now| {
+            ids(&inventory
+                .digest(now, &DigestOptions::default())
+                .opened_reminders)
+        };
+        assert_eq!(reminders(&inventory, start()), vec![fed.0]);
+        let digest = inventory.digest(start(), &DigestOptions::default());
+        assert_eq!(digest.opened_reminders[0].at, start() - DAY);
+        assert_eq!(
+            digest.opened_reminders[0].opened_at,
+            Some(start() - DAY * 6)
+        );
+
+        inventory.mark_reminded(&[fed]);
+        assert!(reminders(&inventory, start()).is_empty());
+        assert_eq!(reminders(&inventory, start() + DAY * 3), vec![recent.0]);
+
+        // Opened again, it is reminded again once the new threshold is crossed.
+        let ii = inventory
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == fed)
+            .unwrap();
+        ii.opened_at = Some(start() + DAY);
+        assert!(!reminders(&inventory, start() + DAY * 5).contains(&fed.0));
+        assert!(reminders(&inventory, start() + DAY * 6).contains(&fed.0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn reminder_fields_default_when_missing() {
+        let item_type: ItemType = serde_json::from_str(
+            r#"{"id": 1, "name": "marinade", "minimum_quantity": 0.0, "ttl": null,
+                "opened_by_default": false}"#,
+        )
+        .unwrap();
+        assert_eq!(item_type.remind_after_open, None);
+        let json = serde_json::to_value(ItemInstance::default()).unwrap();
+        let mut json = json.as_object().unwrap().clone();
+        json.remove("last_reminded_at");
+        let ii: ItemInstance = serde_json::from_value(json.into()).unwrap();
+        assert_eq!(ii.last_reminded_at, None);
+
+        let mut item_type = item_type;
+        item_type.remind_after_open = Some(DAY * 2);
+        let json = serde_json::to_string(&item_type).unwrap();
+        assert_eq!(serde_json::from_str::<ItemType>(&json).unwrap(), item_type);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn empty_sections_are_omitted_from_json() {
+        let digest = Digest::default();
+        assert!(digest.is_empty());
+        assert_eq!(serde_json::to_string(&digest).unwrap(), "{}");
+        let digest = fixture().digest(start() - DAY * 400, &DigestOptions::default());
+        let json = serde_json::to_value(&digest).unwrap();
+        let keys = json.as_object().unwrap().keys().collect::<Vec<_>>();
+        assert_eq!(keys, vec!["to_buy"]);
+
+        let options = DigestOptions {
+            health: Some(HealthOptions::default()),
+            ..DigestOptions::default()
+        };
+        let digest = fixture().digest(start() - DAY * 400, &options);
+        assert_eq!(digest.health.as_ref().unwrap().types_with_ttl.set, 1);
+        let json = serde_json::to_value(&digest).unwrap();
+        assert!(json["health"]["score"].is_number());
+    }
+}
@@ -0,0 +1,487 @@
+//! A log of the changes made to the inventory, to rebuild what it contained at any time.
+//!
+//! The log starts with a checkpoint, the whole inventory, followed by the items changed by
+//! each command. More checkpoints can be added along the way so that replaying the log
+//! starts from the last one instead of the beginning. The log also records when item
+//! instances expired, which replays ignore, see `transition`.
+
+use crate::{ExpiryTransition, Identified, InstanceId, Inventory, ItemInstance, ItemType, TypeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+/// The new state of an item.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "op", rename_all = "snake_case"))]
+pub enum Edit<T: Identified> {
+    Created { item: T },
+    Updated { item: T },
+    Deleted { id: T::Id },
+}
+
+impl<T: Identified> Edit<T> {
+    /// The edit bringing an item from its state before to its state after, both of the id.
+    /// Returns `None` when the item exists in neither.
+    pub fn between(id: T::Id, before: Option<&T>, after: Option<T>) -> Option<Self> {
+        match (before, after) {
+            (None, Some(item)) => Some(Edit::Created { item }),
+            (Some(_), Some(item)) => Some(Edit::Updated { item }),
+            (Some(_), None) => Some(Edit::Deleted { id }),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum Event {
+    /// The whole inventory, from which replays start.
+    Checkpoint {
+        at: SystemTime,
+        item_types: Vec<ItemType>,
+        item_instances: Vec<ItemInstance>,
+        next_type_id: TypeId,
+        next_instance_id: InstanceId,
+    },
+    /// The items changed by a command.
+    Changes {
+        at: SystemTime,
+        command: String,
+        types: Vec<Edit<ItemType>>,
+        instances: Vec<Edit<ItemInstance>>,
+    },
+    /// An item instance became expired, recorded at `at` by a later run of
+    /// `inv update-state`.
+    Expired {
+        at: SystemTime,
+        instance_id: InstanceId,
+        type_id: TypeId,
+        expired_at: SystemTime,
+    },
+}
+
+impl Event {
+    /// A checkpoint of the inventory, at the time of its clock.
+    pub fn checkpoint(inventory: &Inventory) -> Self {
+        let (next_type_id, next_instance_id) = inventory.next_ids();
+        Event::Checkpoint {
+            at: inventory.now(),
+            item_types: inventory.item_types.clone(),
+            item_instances: inventory.item_instances.clone(),
+            next_type_id,
+            next_instance_id,
+        }
+    }
+
+    /// The record of an expiry transition, at a time.
+    pub fn expired(transition: &ExpiryTransition, at: SystemTime) -> Self {
+        Event::Expired {
+            at,
+            instance_id: transition.instance_id,
+            type_id: transition.type_id,
+            expired_at: transition.expired_at,
+        }
+    }
+
+    pub fn at(&self) -> SystemTime {
+        match self {
+            Event::Checkpoint { at, .. }
+            | Event::Changes { at, .. }
+            | Event::Expired { at, .. } => *at,
+        }
+    }
+}
+
+/// The kind of item an edit is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemKind {
+    Type,
+    Instance,
+}
+
+/// Why an edit could not be replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The item to update or delete doesn't exist.
+    Missing,
+    /// The item to create already exists.
+    AlreadyExists,
+    /// The item instance to create or update is of an item type that doesn't exist.
+    UnknownItemType,
+}
+
+/// An edit left out of a replay because it doesn't apply to the inventory rebuilt so far,
+/// which happens when the files were changed without being logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkippedEdit {
+    /// When the command making the edit ran.
+    pub at: SystemTime,
+    pub command: String,
+    pub kind: ItemKind,
+    /// The id of the item type or item instance, depending on `kind`.
+    pub id: u32,
+    pub reason: SkipReason,
+}
+
+/// The events of an inventory, oldest first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventLog {
+    pub events: Vec<Event>,
+}
+
+impl EventLog {
+    /// The time of the first event, before which nothing is known.
+    pub fn start(&self) -> Option<SystemTime> {
+        self.events.first().map(Event::at)
+    }
+
+    /// The inventory as it was at a time, see `replay_checked`.
+    pub fn replay(&self, until: SystemTime) -> Inventory {
+        self.replay_checked(until).0
+    }
+
+    /// Rebuilds the inventory as it was at a time, from the last checkpoint made at or before
+    /// it, or from an empty inventory, and the changes made since, up to the time included.
+    /// Edits that don't apply, like updates of items that don't exist, are skipped and
+    /// returned with the inventory.
+    pub fn replay_checked(&self, until: SystemTime) -> (Inventory, Vec<SkippedEdit>) {
+        let start = self
+            .events
+            .iter()
+            .rposition(|e| matches!(e, Event::Checkpoint { .. }) && e.at() <= until);
+        let mut inventory = Inventory::default();
+        let mut skipped = vec![];
+        let events = match start {
+            Some(start) => &self.events[start..],
+            None => &self.events[..],
+        };
+        for event in events.iter().filter(|e| e.at() <= until) {
+            match event {
+                Event::Checkpoint {
+                    item_types,
+                    item_instances,
+                    next_type_id,
+                    next_instance_id,
+                    ..
+                } => {
+                    inventory.item_types = item_types.clone();
+                    inventory.item_instances = item_instances.clone();
+                    inventory.set_next_ids(*next_type_id, *next_instance_id);
+                }
+                Event::Changes {
+                    at,
+                    command,
+                    types,
+                    instances,
+                } => {
+                    let mut skip = |kind, id: u32, reason| {
+                        skipped.push(SkippedEdit {
+                            at: *at,
+                            command: command.clone(),
+                            kind,
+                            id,
+                            reason,
+                        })
+                    };
+                    // Item types first, for the item instances created with their item type.
+                    for edit in types {
+                        if let Err((id, reason)) = apply(&mut inventory.item_types, edit, |_| true)
+                        {
+                            skip(ItemKind::Type, id.into(), reason);
+                        }
+                    }
+                    let types = &inventory.item_types;
+                    for edit in instances {
+                        let known_type =
+                            |ii: &ItemInstance| types.iter().any(|t| t.id == ii.item_type);
+                        if let Err((id, reason)) =
+                            apply(&mut inventory.item_instances, edit, known_type)
+                        {
+                            skip(ItemKind::Instance, id.into(), reason);
+                        }
+                    }
+                }
+                Event::Expired { .. } => {}
+            }
+        }
+        (inventory, skipped)
+    }
+
+    /// When each item instance was last created or updated, by id. Checkpoints don't count.
+    pub fn last_changes(&self) -> HashMap<InstanceId, SystemTime> {
+        let mut changes = HashMap::new();
+        for event in &self.events {
+            if let Event::Changes { at, instances, .. } = event {
+                for edit in instances {
+                    if let Edit::Created { item } | Edit::Updated { item } = edit {
+                        changes.insert(item.id, *at);
+                    }
+                }
+            }
+        }
+        changes
+    }
+
+    /// When an item instance of each item type was last created or updated, by type id.
+    /// Checkpoints don't count.
+    pub fn type_activity(&self) -> HashMap<TypeId, SystemTime> {
+        let mut activity = HashMap::new();
+        for event in &self.events {
+            if let Event::Changes { at, instances, .. } = event {
+                for edit in instances {
+                    if let Edit::Created { item } | Edit::Updated { item } = edit {
+                        activity.insert(item.item_type, *at);
+                    }
+                }
+            }
+        }
+        activity
+    }
+
+    /// The expiry transitions already recorded, as the id of the item instance and the
+    /// expiry it crossed.
+    pub fn recorded_expiries(&self) -> HashSet<(InstanceId, SystemTime)> {
+        self.events
+            .iter()
+            .filter_map(|e| match e {
+                Event::Expired {
+                    instance_id,
+                    expired_at,
+                    ..
+                } => Some((*instance_id, *expired_at)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Applies an edit to the items, checking that it can be. `valid` tells whether a new state
+/// of an item can be stored.
+fn apply<T: Clone + Identified>(
+    items: &mut Vec<T>,
+    edit: &Edit<T>,
+    valid: impl Fn(&T) -> bool,
+) -> Result<(), (T::Id, SkipReason)> {
+    let id = T::id;
+    match edit {
+        Edit::Created { item } => {
+            if items.iter().any(|i| id(i) == id(item)) {
+                return Err((id(item), SkipReason::AlreadyExists));
+            }
+            if !valid(item) {
+                return Err((id(item), SkipReason::UnknownItemType));
+            }
+            items.push(item.clone());
+        }
+        Edit::Updated { item } => {
+            if !valid(item) {
+                return Err((id(item), SkipReason::UnknownItemType));
+            }
+            match items.iter_mut().find(|i| id(i) == id(item)) {
+                Some(existing) => *existing = item.clone(),
+                None => return Err((id(item), SkipReason::Missing)),
+            }
+        }
+        Edit::Deleted { id: deleted } => {
+            if !items.iter().any(|i| id(i) == *deleted) {
+                return Err((*deleted, SkipReason::Missing));
+            }
+            items.retain(|i| id(i) != *deleted);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FixedClock;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn at(hours: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000 + hours * 3600)
+    }
+
+    fn rice(id: u32) -> ItemType {
+        ItemType {
+            id: TypeId(id),
+            name: "rice".to_string(),
+            ..ItemType::default()
+        }
+    }
+
+    fn bag(id: u32, item_type: u32, quantity: f32) -> ItemInstance {
+        ItemInstance {
+            id: InstanceId(id),
+            item_type: TypeId(item_type),
+            quantity,
+            ..ItemInstance::default()
+        }
+    }
+
+    fn changes(
+        hours: u64,
+        types: Vec<Edit<ItemType>>,
+        instances: Vec<Edit<ItemInstance>>,
+    ) -> Event {
+        Event::Changes {
+            at: at(hours),
+            command: "test".to_string(),
+            types,
+            instances,
+        }
+    }
+
+    fn log() -> EventLog {
+        EventLog {
+            events: vec![
+                changes(
+                    1,
+                    vec![Edit::Created { item: rice(1) }],
+                    vec![Edit::Created {
+                        item: bag(1, 1, 2.0),
+                    }],
+                ),
+                changes(
+                    2,
+                    vec![],
+                    vec![Edit::Updated {
+                        item: bag(1, 1, 1.5),
+                    }],
+                ),
+                changes(3, vec![], vec![Edit::Deleted { id: InstanceId(1) }]),
+            ],
+        }
+    }
+
+    #[test]
+    fn changes_are_replayed_up_to_the_time() {
+        let log = log();
+        assert!(log.replay(at(0)).item_types.is_empty());
+        let first = log.replay(at(1));
+        assert_eq!(first.item_types, vec![rice(1)]);
+        assert_eq!(first.item_instances, vec![bag(1, 1, 2.0)]);
+        assert_eq!(log.replay(at(2)).item_instances[0].quantity, 1.5);
+        let last = log.replay_checked(at(10));
+        assert!(last.0.item_instances.is_empty());
+        assert!(last.1.is_empty());
+        assert_eq!(log.start(), Some(at(1)));
+    }
+
+    #[test]
+    fn last_changes_are_those_of_created_and_updated_instances() {
+        let mut log = log();
+        log.events.push(changes(
+            4,
+            vec![],
+            vec![Edit::Created {
+                item: bag(2, 1, 1.0),
+            }],
+        ));
+        let changes = log.last_changes();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[&InstanceId(1)], at(2));
+        assert_eq!(changes[&InstanceId(2)], at(4));
+    }
+
+    #[test]
+    fn replays_start_from_the_last_checkpoint() {
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(at(5))));
+        inventory.add_item_type(rice(0));
+        inventory.add_item_instance(bag(0, 1, 4.0)).unwrap();
+        let mut log = log();
+        log.events.push(Event::checkpoint(&inventory));
+        log.events.push(changes(
+            6,
+            vec![],
+            vec![Edit::Updated {
+                item: bag(1, 1, 3.0),
+            }],
+        ));
+
+        let replayed = log.replay(at(6));
+        assert_eq!(replayed.item_types, inventory.item_types);
+        assert_eq!(replayed.item_instances[0].quantity, 3.0);
+        assert_eq!(replayed.next_ids(), inventory.next_ids());
+        assert_eq!(log.replay(at(5)).item_instances, inventory.item_instances);
+        assert!(log.replay(at(4)).item_instances.is_empty());
+    }
+
+    #[test]
+    fn edits_of_unknown_items_are_skipped() {
+        let log = EventLog {
+            events: vec![
+                changes(1, vec![Edit::Created { item: rice(1) }], vec![]),
+                changes(
+                    2,
+                    vec![
+                        Edit::Created { item: rice(1) },
+                        Edit::Deleted { id: TypeId(7) },
+                    ],
+                    vec![
+                        Edit::Updated {
+                            item: bag(3, 1, 1.0),
+                        },
+                        Edit::Created {
+                            item: bag(4, 9, 1.0),
+                        },
+                        Edit::Created {
+                            item: bag(5, 1, 1.0),
+                        },
+                    ],
+                ),
+            ],
+        };
+        let (inventory, skipped) = log.replay_checked(at(2));
+        assert_eq!(inventory.item_types, vec![rice(1)]);
+        assert_eq!(inventory.item_instances, vec![bag(5, 1, 1.0)]);
+        let reasons = skipped
+            .iter()
+            .map(|s| (s.kind, s.id, s.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reasons,
+            vec![
+                (ItemKind::Type, 1, SkipReason::AlreadyExists),
+                (ItemKind::Type, 7, SkipReason::Missing),
+                (ItemKind::Instance, 3, SkipReason::Missing),
+                (ItemKind::Instance, 4, SkipReason::UnknownItemType),
+            ]
+        );
+        assert!(skipped.iter().all(|s| s.at == at(2) && s.command == "test"));
+    }
+
+    #[test]
+    fn edits_are_made_from_states() {
+        assert_eq!(
+            Edit::between(TypeId(1), None, Some(rice(1))),
+            Some(Edit::Created { item: rice(1) })
+        );
+        assert_eq!(
+            Edit::between(TypeId(1), Some(&rice(1)), Some(rice(1))),
+            Some(Edit::Updated { item: rice(1) })
+        );
+        assert_eq!(
+            Edit::between(TypeId(1), Some(&rice(1)), None),
+            Some(Edit::Deleted { id: TypeId(1) })
+        );
+        assert_eq!(Edit::<ItemType>::between(TypeId(1), None, None), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn events_are_tagged_in_json() {
+        let json = serde_json::to_value(changes(1, vec![Edit::Deleted { id: TypeId(2) }], vec![]))
+            .unwrap();
+        assert_eq!(json["kind"], "changes");
+        assert_eq!(json["types"][0]["op"], "deleted");
+        assert_eq!(json["types"][0]["id"], 2);
+        let parsed: Event = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.at(), at(1));
+    }
+}
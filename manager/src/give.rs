@@ -0,0 +1,210 @@
+//! Item instances given away to someone, which are not waste.
+
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{InstanceId, Inventory, InventoryError, ItemInstance, MatchMode, NamePattern, TypeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// The reason recorded for item instances removed with `Inventory::give`.
+pub const GIVEN_AWAY: &str = "given-away";
+
+/// An item instance that was given away.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Gift {
+    pub instance_id: InstanceId,
+    pub type_id: TypeId,
+    pub quantity: f32,
+    /// The part of the value of the item instance that was given, if it has a value.
+    pub value: Option<f32>,
+    pub recipient: Option<String>,
+    pub given_at: SystemTime,
+}
+
+impl Gift {
+    fn new(ii: &ItemInstance, given_at: SystemTime) -> Self {
+        Gift {
+            instance_id: ii.id,
+            type_id: ii.item_type,
+            quantity: ii.quantity,
+            value: ii.unit_price().map(|p| p * ii.quantity),
+            recipient: ii.recipient.clone(),
+            given_at,
+        }
+    }
+}
+
+#[cfg(feature = "display")]
+impl Record for Gift {
+    const HEADER: &'static [&'static str] = &[
+        "instance id",
+        "type id",
+        "quantity",
+        "value",
+        "recipient",
+        "given at",
+    ];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.instance_id.to_string(),
+            self.type_id.to_string(),
+            self.quantity.to_string(),
+            self.value.map(|v| v.to_string()).unwrap_or_default(),
+            self.recipient.clone().unwrap_or_default(),
+            humantime::format_rfc3339(self.given_at).to_string(),
+        ]
+    }
+}
+
+impl Inventory {
+    /// Gives an item instance away, or only some quantity of it which is split off first,
+    /// see `split_instance`. The given item instance is removed with the reason `given-away`
+    /// and the recipient. Returns the id of the given item instance.
+    pub fn give(
+        &mut self,
+        instance_id: InstanceId,
+        quantity: Option<f32>,
+        recipient: Option<String>,
+    ) -> Result<InstanceId, InventoryError> {
+        let now = self.now();
+        let item_instance = self
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        if item_instance.removed_at.is_some() {
+            return Err(InventoryError::FinishedItemInstance);
+        }
+        let given_id = match quantity {
+            Some(quantity) if quantity < item_instance.quantity => {
+                self.split_instance(instance_id, quantity)?
+            }
+            Some(quantity) if quantity > item_instance.quantity || !quantity.is_finite() => {
+                return Err(InventoryError::InvalidQuantity)
+            }
+            _ => instance_id,
+        };
+        let given = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == given_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        given.removed_at = Some(now);
+        given.removal_reason = Some(GIVEN_AWAY.to_string());
+        given.recipient = recipient;
+        Ok(given_id)
+    }
+
+    /// The item instances given away, optionally only those given to a recipient, whose
+    /// name is compared without case. Ordered by the time they were given.
+    pub fn given(&self, recipient: Option<&str>) -> Vec<Gift> {
+        let recipient = recipient.map(|r| NamePattern::new(r, MatchMode::Exact));
+        let mut gifts = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removal_reason.as_deref() == Some(GIVEN_AWAY))
+            .filter(|ii| match &recipient {
+                Some(recipient) => ii
+                    .recipient
+                    .as_deref()
+                    .map(|r| recipient.matches(r))
+                    .unwrap_or(false),
+                None => true,
+            })
+            .filter_map(|ii| Some(Gift::new(ii, ii.removed_at?)))
+            .collect::<Vec<_>>();
+        gifts.sort_by_key(|g| (g.given_at, g.instance_id));
+        gifts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemInstance, ItemType};
+
+    fn pantry() -> (Inventory, InstanceId) {
+        let mut inventory = Inventory::default();
+        inventory.add_item_type(ItemType {
+            name: "jam".to_string(),
+            ..ItemType::default()
+        });
+        let id = inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                quantity: 4.0,
+                value: Some(8.0),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        (inventory, id)
+    }
+
+    #[test]
+    fn giving_some_splits_the_item_instance() {
+        let (mut inventory, id) = pantry();
+        let given = inventory
+            .give(id, Some(1.0), Some("Sam".to_string()))
+            .unwrap();
+        assert_ne!(given, id);
+
+        let kept = inventory
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == id)
+            .unwrap();
+        assert_eq!(kept.quantity, 3.0);
+        assert_eq!(kept.value, Some(6.0));
+        assert_eq!(kept.removed_at, None);
+        let gift = inventory
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == given)
+            .unwrap();
+        assert_eq!(gift.quantity, 1.0);
+        assert_eq!(gift.value, Some(2.0));
+        assert_eq!(gift.removal_reason.as_deref(), Some(GIVEN_AWAY));
+        assert_ne!(gift.label, kept.label);
+
+        let gifts = inventory.given(Some("sam"));
+        assert_eq!(gifts.len(), 1);
+        assert_eq!(gifts[0].value, Some(2.0));
+        assert!(inventory.given(Some("Alex")).is_empty());
+    }
+
+    #[test]
+    fn giving_everything_removes_the_item_instance() {
+        let (mut inventory, id) = pantry();
+        assert_eq!(inventory.give(id, Some(4.0), None), Ok(id));
+        assert_eq!(inventory.item_instances.len(), 1);
+        assert_eq!(inventory.given(None)[0].recipient, None);
+        assert_eq!(
+            inventory.give(id, None, None),
+            Err(InventoryError::FinishedItemInstance)
+        );
+
+        let (mut inventory, id) = pantry();
+        assert_eq!(
+            inventory.give(id, Some(5.0), None),
+            Err(InventoryError::InvalidQuantity)
+        );
+        assert_eq!(
+            inventory.give(id, Some(0.0), None),
+            Err(InventoryError::InvalidQuantity)
+        );
+        assert_eq!(inventory.give(id, None, None), Ok(id));
+    }
+
+    #[test]
+    fn gifts_are_not_waste() {
+        let (mut inventory, id) = pantry();
+        inventory.give(id, Some(1.0), None).unwrap();
+        inventory.trash(id);
+        let waste = inventory.waste_by_month(inventory.now(), 1);
+        assert_eq!(waste.len(), 1);
+        assert_eq!(waste[0].quantity, 3.0);
+    }
+}
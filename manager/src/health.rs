@@ -0,0 +1,436 @@
+//! How complete the data of the inventory is, and the records to fill in first.
+
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{InstanceId, Inventory, ItemInstance, ItemKind, ItemType};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+/// How much each measure counts in the score. A weight of 0 leaves the measure out.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct HealthWeights {
+    pub type_ttl: f32,
+    pub type_minimum: f32,
+    pub type_unit: f32,
+    pub instance_location: f32,
+    pub instance_expiry: f32,
+    pub instance_value: f32,
+    /// Item instances touched during the last `HealthOptions::stale_after`.
+    pub instance_touched: f32,
+}
+
+impl Default for HealthWeights {
+    fn default() -> Self {
+        HealthWeights {
+            type_ttl: 1.0,
+            type_minimum: 1.0,
+            type_unit: 1.0,
+            instance_location: 1.0,
+            instance_expiry: 1.0,
+            instance_value: 1.0,
+            instance_touched: 1.0,
+        }
+    }
+}
+
+/// The options of `Inventory::health_with`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthOptions {
+    pub weights: HealthWeights,
+    /// How long an item instance can go untouched before it is reported. Defaults to a year.
+    pub stale_after: Duration,
+    /// When each item instance was last changed, by id, for those changed without leaving a
+    /// trace in the item instance itself, like an update. See `EventLog::last_changes`.
+    pub last_changes: HashMap<InstanceId, SystemTime>,
+    /// How many of the most incomplete records to report. Defaults to 10.
+    pub top: usize,
+}
+
+impl Default for HealthOptions {
+    fn default() -> Self {
+        HealthOptions {
+            weights: HealthWeights::default(),
+            stale_after: Duration::from_secs(365 * 24 * 60 * 60),
+            last_changes: HashMap::new(),
+            top: 10,
+        }
+    }
+}
+
+/// How many records of a kind have a field set.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Coverage {
+    pub set: usize,
+    pub total: usize,
+}
+
+impl Coverage {
+    /// The percentage of records with the field set, 100 without records.
+    pub fn percent(self) -> f32 {
+        if self.total == 0 {
+            100.0
+        } else {
+            self.set as f32 * 100.0 / self.total as f32
+        }
+    }
+}
+
+/// A record missing some fields, to fill in.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IncompleteRecord {
+    /// `type` or `instance`.
+    pub kind: String,
+    /// The id of the item type or item instance, depending on `kind`.
+    pub id: u32,
+    /// The name of the item type, or of the item type of the item instance.
+    pub name: String,
+    /// The fields missing, among `ttl`, `minimum`, `unit`, `location`, `expiry`, `value`
+    /// and `touched` for item instances untouched for too long.
+    pub missing: Vec<String>,
+    /// The sum of the weights of the missing fields.
+    pub weight: f32,
+}
+
+#[cfg(feature = "display")]
+impl Record for IncompleteRecord {
+    const HEADER: &'static [&'static str] = &["kind", "id", "name", "missing"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.kind.clone(),
+            self.id.to_string(),
+            self.name.clone(),
+            self.missing.join(" "),
+        ]
+    }
+}
+
+/// How complete the item types and the item instances that are not removed are.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HealthReport {
+    /// The weighted average of the percentages, from 0 to 100.
+    pub score: f32,
+    pub types_with_ttl: Coverage,
+    /// Item types with a minimum quantity, by location or by season.
+    pub types_with_minimum: Coverage,
+    pub types_with_unit: Coverage,
+    pub instances_with_location: Coverage,
+    pub instances_with_expiry: Coverage,
+    pub instances_with_value: Coverage,
+    /// Item instances neither added, opened, used nor updated during the stale period.
+    /// Those without any known time are not counted.
+    pub stale_instances: usize,
+    /// The records missing the most, by weight, then types first and by id.
+    pub most_incomplete: Vec<IncompleteRecord>,
+}
+
+fn has_minimum(it: &ItemType) -> bool {
+    it.minimum_quantity > 0.0
+        || !it.minimum_by_location.is_empty()
+        || !it.seasonal_minimums.is_empty()
+}
+
+impl Inventory {
+    /// The health of the inventory at the time of its clock, with the default options.
+    pub fn health(&self) -> HealthReport {
+        self.health_with(self.now(), &HealthOptions::default())
+    }
+
+    /// The health of the inventory at the specified time, ignoring the clock of the inventory.
+    /// Removed item instances are not counted.
+    pub fn health_with(&self, now: SystemTime, options: &HealthOptions) -> HealthReport {
+        let weights = &options.weights;
+        let present = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+            .collect::<Vec<_>>();
+        let stale_since = now.checked_sub(options.stale_after);
+        let is_stale = |ii: &ItemInstance| {
+            let touched = [
+                ii.added_at,
+                ii.opened_at,
+                options.last_changes.get(&ii.id).copied(),
+            ]
+            .iter()
+            .flatten()
+            .max()
+            .copied();
+            matches!((touched, stale_since), (Some(at), Some(since)) if at < since)
+        };
+        let coverage = |set: usize, total: usize| Coverage { set, total };
+        let types = |f: fn(&ItemType) -> bool| {
+            coverage(
+                self.item_types.iter().filter(|it| f(it)).count(),
+                self.item_types.len(),
+            )
+        };
+        let instances = |f: fn(&ItemInstance) -> bool| {
+            coverage(present.iter().filter(|ii| f(ii)).count(), present.len())
+        };
+
+        let types_with_ttl = types(|it| it.ttl.is_some());
+        let types_with_minimum = types(has_minimum);
+        let types_with_unit = types(|it| it.unit.is_some());
+        let instances_with_location = instances(|ii| ii.location.is_some());
+        let instances_with_expiry = instances(|ii| ii.expires_at.is_some());
+        let instances_with_value = instances(|ii| ii.value.is_some());
+        let stale_instances = present.iter().filter(|ii| is_stale(ii)).count();
+        let touched = coverage(present.len() - stale_instances, present.len());
+
+        let measures = [
+            (types_with_ttl, weights.type_ttl),
+            (types_with_minimum, weights.type_minimum),
+            (types_with_unit, weights.type_unit),
+            (instances_with_location, weights.instance_location),
+            (instances_with_expiry, weights.instance_expiry),
+            (instances_with_value, weights.instance_value),
+            (touched, weights.instance_touched),
+        ];
+        let (sum, total_weight) = measures
+            .iter()
+            .filter(|(c, weight)| c.total > 0 && *weight > 0.0)
+            .fold((0.0, 0.0), |(sum, total), (c, weight)| {
+                (sum + c.percent() * weight, total + weight)
+            });
+        let score = if total_weight > 0.0 {
+            sum / total_weight
+        } else {
+            100.0
+        };
+
+        let record = |kind: ItemKind, id: u32, name: String, missing: &[(bool, f32, &str)]| {
+            let missing = missing
+                .iter()
+                .filter(|(set, weight, _)| !set && *weight > 0.0)
+                .collect::<Vec<_>>();
+            IncompleteRecord {
+                kind: match kind {
+                    ItemKind::Type => "type",
+                    ItemKind::Instance => "instance",
+                }
+                .to_string(),
+                id,
+                name,
+                missing: missing.iter().map(|(_, _, f)| f.to_string()).collect(),
+                weight: missing.iter().map(|(_, weight, _)| weight).sum(),
+            }
+        };
+        let mut most_incomplete = self
+            .item_types
+            .iter()
+            .map(|it| {
+                record(
+                    ItemKind::Type,
+                    it.id.into(),
+                    it.name.clone(),
+                    &[
+                        (it.ttl.is_some(), weights.type_ttl, "ttl"),
+                        (has_minimum(it), weights.type_minimum, "minimum"),
+                        (it.unit.is_some(), weights.type_unit, "unit"),
+                    ],
+                )
+            })
+            .chain(present.iter().map(|ii| {
+                record(
+                    ItemKind::Instance,
+                    ii.id.into(),
+                    self.type_name(ii.item_type),
+                    &[
+                        (ii.location.is_some(), weights.instance_location, "location"),
+                        (ii.expires_at.is_some(), weights.instance_expiry, "expiry"),
+                        (ii.value.is_some(), weights.instance_value, "value"),
+                        (!is_stale(ii), weights.instance_touched, "touched"),
+                    ],
+                )
+            }))
+            .filter(|r| !r.missing.is_empty())
+            .collect::<Vec<_>>();
+        // Stable, so types stay before item instances and both by id.
+        most_incomplete.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+        most_incomplete.truncate(options.top);
+
+        HealthReport {
+            score,
+            types_with_ttl,
+            types_with_minimum,
+            types_with_unit,
+            instances_with_location,
+            instances_with_expiry,
+            instances_with_value,
+            stale_instances,
+            most_incomplete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FixedClock, SeasonRule, TypeId};
+    use std::sync::Arc;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    /// Two complete item types out of four, and item instances with known gaps.
+    fn fixture() -> Inventory {
+        let item_type = |id: u32, name: &str, complete: bool| ItemType {
+            id: TypeId(id),
+            name: name.to_string(),
+            minimum_quantity: if complete { 1.0 } else { 0.0 },
+            ttl: if complete { Some(DAY) } else { None },
+            unit: if complete {
+                Some("kg".to_string())
+            } else {
+                None
+            },
+            ..ItemType::default()
+        };
+        let mut seasonal = item_type(4, "sunscreen", false);
+        seasonal.seasonal_minimums = vec![SeasonRule {
+            start_month_day: (6, 1),
+            end_month_day: (8, 31),
+            minimum: 2.0,
+        }];
+        let instance =
+            |id: u32, item_type: u32, complete: bool, added_days_ago: Option<u32>| ItemInstance {
+                id: InstanceId(id),
+                item_type: TypeId(item_type),
+                location: if complete {
+                    Some("pantry".to_string())
+                } else {
+                    None
+                },
+                expires_at: if complete { Some(start() + DAY) } else { None },
+                value: if complete { Some(2.0) } else { None },
+                added_at: added_days_ago.map(|days| start() - DAY * days),
+                ..ItemInstance::default()
+            };
+        let mut removed = instance(5, 3, false, Some(800));
+        removed.removed_at = Some(start() - DAY);
+        let mut inventory = Inventory::new(
+            vec![
+                item_type(1, "rice", true),
+                item_type(2, "flour", true),
+                item_type(3, "hammer", false),
+                seasonal,
+            ],
+            vec![
+                instance(1, 1, true, Some(10)),
+                instance(2, 1, true, Some(400)),
+                instance(3, 3, false, None),
+                instance(4, 3, false, Some(500)),
+                removed,
+            ],
+        );
+        inventory.set_clock(Arc::new(FixedClock::new(start())));
+        inventory
+    }
+
+    #[test]
+    fn percentages_match_the_fixture() {
+        let report = fixture().health();
+        assert_eq!(report.types_with_ttl, Coverage { set: 2, total: 4 });
+        assert_eq!(report.types_with_minimum, Coverage { set: 3, total: 4 });
+        assert_eq!(report.types_with_unit.percent(), 50.0);
+        assert_eq!(
+            report.instances_with_location,
+            Coverage { set: 2, total: 4 }
+        );
+        assert_eq!(report.instances_with_expiry.percent(), 50.0);
+        assert_eq!(report.instances_with_value.percent(), 50.0);
+        assert_eq!(report.stale_instances, 2);
+        // (50 + 75 + 50 + 50 + 50 + 50 + 50) / 7
+        assert!(
+            (report.score - 375.0 / 7.0).abs() < 0.001,
+            "{}",
+            report.score
+        );
+    }
+
+    #[test]
+    fn most_incomplete_records_come_first() {
+        let report = fixture().health();
+        let ids = report
+            .most_incomplete
+            .iter()
+            .map(|r| (r.kind.as_str(), r.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                ("instance", 4),
+                ("type", 3),
+                ("instance", 3),
+                ("type", 4),
+                ("instance", 2)
+            ]
+        );
+        assert_eq!(
+            report.most_incomplete[0].missing,
+            vec!["location", "expiry", "value", "touched"]
+        );
+        assert_eq!(report.most_incomplete[0].name, "hammer");
+    }
+
+    #[test]
+    fn weights_and_changes_are_options() {
+        let inventory = fixture();
+        let mut options = HealthOptions {
+            weights: HealthWeights {
+                type_ttl: 0.0,
+                type_minimum: 0.0,
+                type_unit: 0.0,
+                instance_value: 3.0,
+                ..HealthWeights::default()
+            },
+            top: 2,
+            ..HealthOptions::default()
+        };
+        options
+            .last_changes
+            .insert(InstanceId(4), start() - DAY * 3);
+        let report = inventory.health_with(start(), &options);
+        assert_eq!(report.stale_instances, 1);
+        // (50 * 3 + 50 + 50 + 75) / 6
+        assert!(
+            (report.score - 325.0 / 6.0).abs() < 0.001,
+            "{}",
+            report.score
+        );
+        let ids = report
+            .most_incomplete
+            .iter()
+            .map(|r| r.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![3, 4]);
+        assert!(report.most_incomplete.iter().all(|r| r.kind == "instance"));
+
+        let empty = Inventory::default().health_with(start(), &options);
+        assert_eq!(empty.score, 100.0);
+        assert!(empty.most_incomplete.is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn missing_weights_default_in_json() {
+        let weights: HealthWeights = serde_json::from_str(r#"{"type_ttl": 2.5}"#).unwrap();
+        assert_eq!(
+            weights,
+            HealthWeights {
+                type_ttl: 2.5,
+                ..HealthWeights::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Icons of item types for frontends: an emoji, the key of an icon set, or an image file.
+//!
+//! Image files are given by their path relative to the `icons` directory next to the
+//! inventory, like `dairy/milk.png`. Whether they exist is for the frontend to check.
+
+use crate::{Inventory, MatchMode, NamePattern, TypeId};
+
+/// The directory holding the image files of the icons, in the working directory.
+pub const ICONS_DIR: &str = "icons";
+
+/// The extensions of the image files that icons can be, without a directory.
+const IMAGE_EXTENSIONS: &[&str] = &["png", "svg", "jpg", "jpeg", "gif", "webp"];
+
+/// What an icon is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconKind {
+    /// A text without ASCII letters or digits, like `🥛`, shown as is.
+    Emoji,
+    /// A name the frontend maps to an icon, like `milk-bottle`.
+    Key,
+    /// A path under `ICONS_DIR`, recognized by a `/` or an image extension.
+    Path,
+}
+
+/// Tells what an icon is.
+pub fn kind(icon: &str) -> IconKind {
+    let extension = icon.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
+    if icon.contains('/')
+        || icon.contains('\\')
+        || extension.is_some_and(|e| IMAGE_EXTENSIONS.contains(&e.as_str()))
+    {
+        IconKind::Path
+    } else if !icon.is_empty() && !icon.chars().any(|c| c.is_ascii_alphanumeric()) {
+        IconKind::Emoji
+    } else {
+        IconKind::Key
+    }
+}
+
+/// The number of characters of a pattern that are not wildcards, the most specific of
+/// several patterns matching a name being the one with the most.
+fn specificity(pattern: &str) -> usize {
+    pattern.chars().filter(|c| *c != '*' && *c != '?').count()
+}
+
+impl Inventory {
+    /// Sets the icons of the item types whose name matches a pattern of `map`, see
+    /// `MatchMode::Glob`. When several patterns match, the most specific one is used, like
+    /// `*oat milk*` rather than `*milk*` for oat milk, then the first in alphabetical order.
+    /// The item types already having an icon keep it unless `overwrite` is set.
+    /// Returns the ids of the item types whose icon changed.
+    ///
+    /// ```
+    /// use inv_manager::{Inventory, ItemType};
+    ///
+    /// let mut inventory = Inventory::default();
+    /// let oat_milk = inventory.add_item_type(ItemType {
+    ///     name: "Oat milk".to_string(),
+    ///     ..ItemType::default()
+    /// });
+    /// let map = [("*milk*", "🥛"), ("*oat milk*", "🌾")];
+    /// let changed = inventory.set_icons_from_map(&map, false);
+    /// assert_eq!(changed, [oat_milk]);
+    /// assert_eq!(inventory.item_types[0].icon.as_deref(), Some("🌾"));
+    /// ```
+    pub fn set_icons_from_map<P: AsRef<str>, I: AsRef<str>>(
+        &mut self,
+        map: &[(P, I)],
+        overwrite: bool,
+    ) -> Vec<TypeId> {
+        let mut rules = map
+            .iter()
+            .map(|(pattern, icon)| {
+                let (pattern, icon) = (pattern.as_ref(), icon.as_ref());
+                (NamePattern::new(pattern, MatchMode::Glob), pattern, icon)
+            })
+            .collect::<Vec<_>>();
+        rules.sort_by(|a, b| {
+            specificity(b.1)
+                .cmp(&specificity(a.1))
+                .then_with(|| a.1.cmp(b.1))
+        });
+        let mut changed = vec![];
+        for it in &mut self.item_types {
+            if it.icon.is_some() && !overwrite {
+                continue;
+            }
+            let icon = match rules
+                .iter()
+                .find(|(pattern, _, _)| pattern.matches(&it.name))
+            {
+                Some((_, _, icon)) => icon,
+                None => continue,
+            };
+            if it.icon.as_deref() != Some(*icon) {
+                it.icon = Some(icon.to_string());
+                changed.push(it.id);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ItemType;
+
+    #[test]
+    fn icons_are_told_apart() {
+        assert_eq!(kind("🥛"), IconKind::Emoji);
+        assert_eq!(kind("🧑‍🍳"), IconKind::Emoji);
+        assert_eq!(kind("milk-bottle"), IconKind::Key);
+        assert_eq!(kind("mdi:cup"), IconKind::Key);
+        assert_eq!(kind("milk.PNG"), IconKind::Path);
+        assert_eq!(kind("dairy/milk"), IconKind::Path);
+        assert_eq!(kind(""), IconKind::Key);
+    }
+
+    #[test]
+    fn the_most_specific_pattern_wins() {
+        let mut inventory = Inventory::default();
+        for (name, icon) in [
+            ("Milk", None),
+            ("Oat milk", None),
+            ("Rice", None),
+            ("Soy milk", Some("soy.png")),
+        ] {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                icon: icon.map(String::from),
+                ..ItemType::default()
+            });
+        }
+        let map = [("*milk*", "🥛"), ("*oat milk*", "🌾"), ("m*", "Ⓜ")]
+            .iter()
+            .map(|(p, i)| (p.to_string(), i.to_string()))
+            .collect::<Vec<_>>();
+        let icons = |inventory: &Inventory| {
+            inventory
+                .item_types
+                .iter()
+                .map(|it| it.icon.clone().unwrap_or_default())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            inventory.set_icons_from_map(&map, false),
+            vec![TypeId(1), TypeId(2)]
+        );
+        assert_eq!(icons(&inventory), vec!["🥛", "🌾", "", "soy.png"]);
+        assert_eq!(
+            inventory.set_icons_from_map(&map, false),
+            Vec::<TypeId>::new()
+        );
+        assert_eq!(inventory.set_icons_from_map(&map, true), vec![TypeId(4)]);
+        assert_eq!(icons(&inventory)[3], "🥛");
+    }
+}
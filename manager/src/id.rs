@@ -0,0 +1,94 @@
+//! The ids of item types and item instances.
+//!
+//! They are numbers in the files and on the command line, but different types in the code,
+//! so that passing the id of an item instance where the one of an item type is expected
+//! doesn't compile:
+//!
+//! ```compile_fail,E0308
+//! use inv_manager::{InstanceId, Inventory};
+//!
+//! let mut inventory = Inventory::default();
+//! let instance_id = InstanceId(42);
+//! inventory.delete_item_type(instance_id);
+//! ```
+//!
+//! ```
+//! use inv_manager::{InstanceId, TypeId};
+//!
+//! let type_id: TypeId = "42".parse().unwrap();
+//! assert_eq!(type_id, TypeId::from(42));
+//! assert_eq!(type_id.to_string(), "42");
+//! assert_eq!(u32::from(InstanceId(7)), 7);
+//! ```
+
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+macro_rules! id {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+        #[cfg_attr(feature = "serde", serde(transparent))]
+        pub struct $name(pub u32);
+
+        impl From<u32> for $name {
+            fn from(id: u32) -> Self {
+                $name(id)
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(id: $name) -> Self {
+                id.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map($name)
+            }
+        }
+    };
+}
+
+id!(
+    /// The id of an item type.
+    TypeId
+);
+id!(
+    /// The id of an item instance.
+    InstanceId
+);
+
+/// Something with an id, to write code working for both item types and item instances.
+pub trait Identified {
+    type Id: Copy + Eq + Ord + std::hash::Hash + fmt::Debug + fmt::Display + Into<u32>;
+
+    fn id(&self) -> Self::Id;
+}
+
+impl Identified for crate::ItemType {
+    type Id = TypeId;
+
+    fn id(&self) -> TypeId {
+        self.id
+    }
+}
+
+impl Identified for crate::ItemInstance {
+    type Id = InstanceId;
+
+    fn id(&self) -> InstanceId {
+        self.id
+    }
+}
@@ -0,0 +1,212 @@
+//! The item types nobody uses anymore and the item instances left unopened for a long time.
+//!
+//! The last activity of an item type is the latest time one of its item instances was added
+//! or opened, or, with the event log, created or updated, see `EventLog::type_activity`. An
+//! item type is idle when it had no activity since the start of the window, and an item
+//! instance is idle when it is still unopened and was added before it. Activity at the very
+//! start of the window counts as being in it.
+
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{Inventory, ItemInstance, ItemType, TypeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// An idle item type or item instance.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct IdleRecord {
+    /// `type` or `instance`.
+    pub kind: String,
+    /// The id of the item type or item instance, depending on `kind`.
+    pub id: u32,
+    /// The name of the item type, or of the item type of the item instance.
+    pub name: String,
+    /// The last activity of the item type, or when the item instance was added. None when
+    /// nothing is known.
+    pub last_activity: Option<SystemTime>,
+}
+
+#[cfg(feature = "display")]
+impl Record for IdleRecord {
+    const HEADER: &'static [&'static str] = &["kind", "id", "name", "last activity"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.kind.clone(),
+            self.id.to_string(),
+            self.name.clone(),
+            self.last_activity
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+impl Inventory {
+    /// The item types without activity since a time, with their last activity, from the
+    /// dates of their item instances. See `idle_types_with` to add those of the event log.
+    pub fn idle_types(&self, since: SystemTime) -> Vec<(&ItemType, Option<SystemTime>)> {
+        self.idle_types_with(since, &HashMap::new())
+    }
+
+    /// The item types without activity since a time, with their last activity, from the
+    /// dates of their item instances and the last `activity` known elsewhere by type id.
+    pub fn idle_types_with(
+        &self,
+        since: SystemTime,
+        activity: &HashMap<TypeId, SystemTime>,
+    ) -> Vec<(&ItemType, Option<SystemTime>)> {
+        let mut last = activity.clone();
+        for ii in &self.item_instances {
+            for &at in [ii.added_at, ii.opened_at].iter().flatten() {
+                let entry = last.entry(ii.item_type).or_insert(at);
+                *entry = (*entry).max(at);
+            }
+        }
+        self.item_types
+            .iter()
+            .map(|t| (t, last.get(&t.id).copied()))
+            .filter(|(_, at)| at.is_none_or(|at| at < since))
+            .collect()
+    }
+
+    /// The item instances not removed and never opened which were added before a time.
+    /// Those without an addition date are left out.
+    pub fn idle_instances(&self, since: SystemTime) -> Vec<&ItemInstance> {
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none() && ii.opened_at.is_none())
+            .filter(|ii| ii.added_at.is_some_and(|at| at < since))
+            .collect()
+    }
+
+    /// The idle item types then the idle item instances, see `idle_types_with` and
+    /// `idle_instances`.
+    pub fn idle_records(
+        &self,
+        since: SystemTime,
+        activity: &HashMap<TypeId, SystemTime>,
+    ) -> Vec<IdleRecord> {
+        let types = self
+            .idle_types_with(since, activity)
+            .into_iter()
+            .map(|(t, last_activity)| IdleRecord {
+                kind: "type".to_string(),
+                id: t.id.into(),
+                name: t.name.clone(),
+                last_activity,
+            });
+        let instances = self.idle_instances(since).into_iter().map(|ii| IdleRecord {
+            kind: "instance".to_string(),
+            id: ii.id.into(),
+            name: self.type_name(ii.item_type),
+            last_activity: ii.added_at,
+        });
+        types.chain(instances).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::{Edit, Event, EventLog};
+    use crate::FixedClock;
+    use crate::InstanceId;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    /// Rice added at the start and opened 10 days later, flour added at the start and never
+    /// opened, and salt without item instances.
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(start())));
+        for name in ["rice", "flour", "salt"] {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            });
+        }
+        for (item_type, opened_at) in [(1, Some(start() + DAY * 10)), (2, None)] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(item_type),
+                    opened_at,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    fn idle_type_ids(types: &[(&ItemType, Option<SystemTime>)]) -> Vec<u32> {
+        types.iter().map(|(t, _)| t.id.0).collect()
+    }
+
+    #[test]
+    fn activity_at_the_start_of_the_window_is_in_it() {
+        let inventory = inventory();
+        let idle = inventory.idle_types(start() + DAY * 10);
+        assert_eq!(idle_type_ids(&idle), vec![2, 3]);
+        assert_eq!(idle[0].1, Some(start()));
+        assert_eq!(idle[1].1, None);
+
+        let idle = inventory.idle_types(start() + DAY * 10 + Duration::from_secs(1));
+        assert_eq!(idle_type_ids(&idle), vec![1, 2, 3]);
+        assert_eq!(idle[0].1, Some(start() + DAY * 10));
+
+        assert_eq!(idle_type_ids(&inventory.idle_types(start())), vec![3]);
+    }
+
+    #[test]
+    fn only_unopened_instances_added_before_the_window_are_idle() {
+        let mut inventory = inventory();
+        assert!(inventory.idle_instances(start()).is_empty());
+        let idle = inventory.idle_instances(start() + Duration::from_secs(1));
+        assert_eq!(idle.iter().map(|ii| ii.id.0).collect::<Vec<_>>(), vec![2]);
+
+        inventory.trash(InstanceId(2));
+        assert!(inventory.idle_instances(start() + DAY).is_empty());
+    }
+
+    #[test]
+    fn the_event_log_adds_activity() {
+        let inventory = inventory();
+        let mut flour = inventory.item_instances[1].clone();
+        flour.quantity = 0.5;
+        let log = EventLog {
+            events: vec![Event::Changes {
+                at: start() + DAY * 20,
+                command: "use".to_string(),
+                types: vec![],
+                instances: vec![Edit::Updated { item: flour }],
+            }],
+        };
+        let activity = log.type_activity();
+        let idle = inventory.idle_types_with(start() + DAY * 20, &activity);
+        assert_eq!(idle_type_ids(&idle), vec![1, 3]);
+
+        let records = inventory.idle_records(start() + DAY * 20, &activity);
+        let kinds = records
+            .iter()
+            .map(|r| (r.kind.as_str(), r.id, r.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                ("type", 1, "rice"),
+                ("type", 3, "salt"),
+                ("instance", 2, "flour")
+            ]
+        );
+        assert_eq!(records[2].last_activity, Some(start()));
+    }
+}
@@ -1,77 +1,587 @@
+//! The inventory of item types and item instances, and the reports made from it.
+//!
+//! Optional parts are behind cargo features, all enabled by default:
+//! - `serde`: `Serialize` and `Deserialize` for the inventory and its reports.
+//! - `builders`: `ItemTypeBuilder` and `ItemInstanceBuilder`. Without them, item types and
+//!   item instances can be made from their `Default`.
+//! - `display`: the `record` format and the `Display` implementations based on it.
+//! - `time`: `SystemClock`, reading the time of the operating system, or the one frozen by
+//!   `IMGOAT_FAKE_NOW` (see `clock::current_time`), which is not available on
+//!   `wasm32-unknown-unknown`. Without it, inventories have a clock stopped at
+//!   the Unix epoch until `Inventory::set_clock` is given another one.
+//! - `log`: warnings, like timestamps out of order, sent through the `log` crate for the
+//!   application to show or filter. Without it, they are dropped.
+
+#[cfg(feature = "serde")]
 #[macro_use]
 extern crate serde;
+#[cfg(feature = "builders")]
 #[macro_use]
 extern crate derive_builder;
 
+/// Sends a warning through the `log` crate, or drops it without the `log` feature.
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::warn!($($arg)*);
+        #[cfg(not(feature = "log"))]
+        let _ = format_args!($($arg)*);
+    }};
+}
+
+pub mod capacity;
+pub mod category;
+pub mod class;
+pub mod clock;
+pub mod compact;
+pub mod cost;
+pub mod custom;
+pub mod date;
+pub mod decimal;
+pub mod digest;
+pub mod event;
+pub mod give;
+pub mod health;
+pub mod icon;
+pub mod id;
+pub mod idle;
+pub mod location;
+pub mod name;
+pub mod path;
+pub mod query;
+#[cfg(feature = "display")]
+pub mod record;
+pub mod reference;
+pub mod rename;
+pub mod replace;
+pub mod season;
+pub mod snooze;
+pub mod testing;
+pub mod time_math;
+pub mod transition;
+pub mod unit;
+pub mod value;
+pub mod vendor;
+pub mod waste;
+
+pub use capacity::{LocationInfo, LocationUsage};
+pub use category::{CategoryNode, CategoryRollup};
+pub use class::{find_class, ItemClass};
+#[cfg(feature = "time")]
+pub use clock::{current_time, SystemClock};
+pub use clock::{Clock, FixedClock};
+pub use compact::CompactError;
+pub use cost::TypeCost;
+pub use custom::{FieldAssignment, FieldDef, FieldFilter, FieldKind, FieldValue};
+pub use digest::{Digest, DigestEntry, DigestOptions, DigestPurchase};
+pub use event::{Edit, Event, EventLog, ItemKind, SkipReason, SkippedEdit};
+pub use give::{Gift, GIVEN_AWAY};
+pub use health::{Coverage, HealthOptions, HealthReport, HealthWeights, IncompleteRecord};
+pub use id::{Identified, InstanceId, TypeId};
+pub use idle::IdleRecord;
+pub use location::{LocationCluster, LocationCount};
+pub use name::{fold_case, MatchMode, NamePattern};
+pub use query::{
+    group_instances, GroupKey, InstanceGroup, InstanceQuery, QueryResult, SortKey, Subtotal,
+    TypeQuery, TypeSortKey,
+};
+#[cfg(feature = "display")]
+pub use record::Record;
+pub use replace::{InstanceTextField, TextMatcher};
+pub use season::SeasonRule;
+pub use snooze::Snooze;
+pub use transition::ExpiryTransition;
+pub use value::TypeValue;
+pub use vendor::VendorGroup;
+pub use waste::{WasteEntry, EXPIRED, REMOVAL_REASONS, UNSPECIFIED};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
-use std::ops::Add;
 use std::result::Result;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Builder)]
+/// How close to zero a quantity has to be to count as nothing when using an item type.
+/// An item instance that would be left with this much or less is emptied instead, and
+/// what is still to be used after a first item instance is dropped when it is this much
+/// or less, so that rounding errors like those of 0.1 + 0.2 neither leave crumbs in item
+/// instances nor open the next one. A request smaller than this is still used as is.
+pub const QUANTITY_EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "builders", derive(Builder))]
 pub struct ItemType {
-    #[builder(setter(skip))]
-    pub id: u32,
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    pub id: TypeId,
     pub name: String,
-    #[builder(default)]
+    #[cfg_attr(feature = "builders", builder(default))]
     pub minimum_quantity: f32,
-    #[builder(default)]
+    #[cfg_attr(feature = "builders", builder(default))]
     pub ttl: Option<Duration>,
-    #[builder(default)]
+    #[cfg_attr(feature = "builders", builder(default))]
     pub opened_by_default: bool,
+    /// Whether `use_instance` trashes the item instances it empties.
+    /// Disable it to keep empty containers around, like returnable bottles.
+    #[cfg_attr(feature = "builders", builder(default = "true"))]
+    #[cfg_attr(feature = "serde", serde(default = "default_true"))]
+    pub auto_trash_empty: bool,
+    /// Minimum quantities to keep at specific locations, in addition to `minimum_quantity`
+    /// which applies to the total.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub minimum_by_location: Vec<(String, f32)>,
+    /// Whether the minimum quantities are compared to the quantity of the item instances
+    /// that are not opened, instead of all of them.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub minimum_counts_sealed_only: bool,
+    /// The class whose defaults were last applied, if any. See `ItemClass`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub class: Option<String>,
+    /// The deposit paid for the container of each item instance, refunded when it is returned.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deposit: Option<f32>,
+    /// The unit of the quantities, like `kg`. See `ItemInstance::unit_override`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unit: Option<String>,
+    /// Minimum quantities replacing `minimum_quantity` during parts of the year.
+    /// They don't overlap, see `season::find_overlap`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seasonal_minimums: Vec<SeasonRule>,
+    /// How long after being opened an item instance is reported by the digest, whatever its
+    /// expiry, like a sourdough starter to feed. See `Digest::opened_reminders`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub remind_after_open: Option<Duration>,
+    /// Whether using more than what is left is recorded anyway, for consumables that are not
+    /// tracked closely. See `Inventory::use_allowing_overdraft`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub allow_overdraft: bool,
+    /// When more than what was left was used, meaning that the quantities of the item type
+    /// are wrong and have to be counted again. Its quantity counts as nothing for the
+    /// minimums until then, see `Inventory::shortages`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub recount_requested_at: Option<SystemTime>,
+    /// Whether the item type is counted in whole pieces, so that its item instances are
+    /// added, updated, used and split by whole quantities only. See `is_whole`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub integer_only: bool,
+    /// Where the item type belongs in a hierarchy of categories, as a path like
+    /// `Food/Baking/Flour`. See `Inventory::category_tree`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub category: Option<String>,
+    /// How many years the item instances take to lose all their value, straight-line from
+    /// when they were added, for durable things like tools. See `ItemInstance::current_value`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub depreciation_years: Option<f32>,
+    /// An icon for frontends: an emoji, the key of an icon set or an image file under the
+    /// `icons` directory. See `icon::kind`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub icon: Option<String>,
+    /// Where the item type is usually bought, like a store. See `Inventory::group_by_vendor`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub vendor: Option<String>,
+    /// The names the item type had before being renamed, oldest first, which still find it.
+    /// See `Inventory::rename_type`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub former_names: Vec<String>,
+    /// Whether `inv update-state` puts the item instances in the trash once they expire.
+    /// See `Inventory::expiry_transitions`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auto_trash_on_expiry: bool,
+    /// When the reports stop leaving out the item instances of the item type, see `snooze`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub snoozed_until: Option<SystemTime>,
+    /// Whether the reports leave out the item instances of the item type until it is
+    /// unsnoozed.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub snoozed_forever: bool,
+    /// The custom fields the item instances can have, like the chemistry of batteries. See
+    /// `custom`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub custom_fields: Vec<FieldDef>,
+    /// The quantity of the packages the item type is sold in, in its unit, like 1 for bags
+    /// of 1 kg of rice. The shopping list rounds up to whole packages, see
+    /// `packages_to_buy`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub package_size: Option<f32>,
 }
 
-impl fmt::Display for ItemType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{};{};{};{};{}",
-            self.id,
-            self.name,
-            self.minimum_quantity,
+/// The same defaults as `ItemTypeBuilder`, with an empty name.
+impl Default for ItemType {
+    fn default() -> Self {
+        ItemType {
+            id: TypeId(0),
+            name: String::new(),
+            minimum_quantity: 0.0,
+            ttl: None,
+            opened_by_default: false,
+            auto_trash_empty: default_true(),
+            minimum_by_location: vec![],
+            minimum_counts_sealed_only: false,
+            class: None,
+            deposit: None,
+            unit: None,
+            seasonal_minimums: vec![],
+            remind_after_open: None,
+            allow_overdraft: false,
+            recount_requested_at: None,
+            integer_only: false,
+            category: None,
+            depreciation_years: None,
+            icon: None,
+            vendor: None,
+            former_names: vec![],
+            auto_trash_on_expiry: false,
+            snoozed_until: None,
+            snoozed_forever: false,
+            custom_fields: vec![],
+            package_size: None,
+        }
+    }
+}
+
+impl ItemType {
+    /// Whether the name or one of the former names of the item type matches a pattern.
+    pub fn is_called(&self, pattern: &NamePattern) -> bool {
+        pattern.matches(&self.name) || self.former_names.iter().any(|n| pattern.matches(n))
+    }
+
+    /// The minimum quantity of the total on the day of `now`: the one of the seasonal rule
+    /// applying that day, otherwise `minimum_quantity`.
+    pub fn effective_minimum(&self, now: SystemTime) -> f32 {
+        season::seasonal_minimum(&self.seasonal_minimums, now).unwrap_or(self.minimum_quantity)
+    }
+
+    /// Sets the minimum quantity to keep at a location. A minimum of zero removes it.
+    pub fn set_minimum_at(&mut self, location: &str, minimum: f32) {
+        self.minimum_by_location.retain(|(l, _)| l != location);
+        if minimum > 0.0 {
+            self.minimum_by_location
+                .push((location.to_string(), minimum));
+        }
+    }
+}
+
+pub(crate) fn default_true() -> bool {
+    true
+}
+
+/// Whether a quantity is a whole number, up to `QUANTITY_EPSILON` for rounding errors.
+pub fn is_whole(quantity: f32) -> bool {
+    (quantity - quantity.round()).abs() <= QUANTITY_EPSILON
+}
+
+#[cfg(feature = "display")]
+impl Record for ItemType {
+    const HEADER: &'static [&'static str] = &[
+        "id",
+        "name",
+        "min",
+        "ttl",
+        "open default",
+        "auto trash empty",
+        "min by location",
+        "min sealed only",
+        "class",
+        "deposit",
+        "unit",
+        "seasonal min",
+        "remind after open",
+        "allow overdraft",
+        "recount requested at",
+        "integer only",
+        "category",
+        "depreciation years",
+        "icon",
+        "vendor",
+        "former names",
+        "auto trash on expiry",
+        "snoozed until",
+        "snoozed forever",
+        "custom fields",
+        "package size",
+    ];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.name.clone(),
+            self.minimum_quantity.to_string(),
             self.ttl
                 .map(|ttl| humantime::format_duration(ttl).to_string())
-                .unwrap_or("".to_string()),
-            self.opened_by_default
-        )
+                .unwrap_or_default(),
+            self.opened_by_default.to_string(),
+            self.auto_trash_empty.to_string(),
+            self.minimum_by_location
+                .iter()
+                .map(|(location, min)| format!("{}={}", location, min))
+                .collect::<Vec<_>>()
+                .join(";"),
+            self.minimum_counts_sealed_only.to_string(),
+            self.class.clone().unwrap_or_default(),
+            conv(&self.deposit),
+            conv(&self.unit),
+            self.seasonal_minimums
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            self.remind_after_open
+                .map(|d| humantime::format_duration(d).to_string())
+                .unwrap_or_default(),
+            self.allow_overdraft.to_string(),
+            self.recount_requested_at
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+            self.integer_only.to_string(),
+            conv(&self.category),
+            conv(&self.depreciation_years),
+            conv(&self.icon),
+            conv(&self.vendor),
+            self.former_names.join(";"),
+            self.auto_trash_on_expiry.to_string(),
+            self.snoozed_until
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+            self.snoozed_forever.to_string(),
+            self.custom_fields
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>()
+                .join(";"),
+            conv(&self.package_size),
+        ]
+    }
+}
+
+#[cfg(feature = "display")]
+impl fmt::Display for ItemType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", record::join(&self.to_record()))
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Builder)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "builders", derive(Builder))]
 pub struct ItemInstance {
-    #[builder(setter(skip))]
-    pub id: u32,
-    pub item_type: u32,
-    #[builder(default = "1.0")]
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    pub id: InstanceId,
+    pub item_type: TypeId,
+    #[cfg_attr(feature = "builders", builder(default = "1.0"))]
     pub quantity: f32,
-    #[builder(default)]
+    #[cfg_attr(feature = "builders", builder(default))]
     pub model: Option<String>,
-    #[builder(default)]
+    #[cfg_attr(feature = "builders", builder(default))]
     pub serial: Option<String>,
-    #[builder(default)]
+    #[cfg_attr(feature = "builders", builder(default))]
     pub extra: Option<String>,
-    #[builder(default)]
+    /// Where the item instance currently is.
+    #[cfg_attr(feature = "builders", builder(default))]
     pub location: Option<String>,
-    #[builder(default)]
+    /// Where the item instance belongs, like the garage for a drill currently in the kitchen.
+    /// See `ItemInstance::is_misplaced`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub home_location: Option<String>,
+    /// The monetary value of the whole item instance when it was added, not per unit.
+    #[cfg_attr(feature = "builders", builder(default))]
     pub value: Option<f32>,
-    #[builder(default)]
+    #[cfg_attr(feature = "builders", builder(default))]
     pub opened_at: Option<SystemTime>,
-    #[builder(default)]
+    /// The use-by date, after which the item instance should not be used anymore.
+    #[cfg_attr(feature = "builders", builder(default))]
     pub expires_at: Option<SystemTime>,
-    #[builder(setter(skip))]
+    /// The best-before date, after which the item instance is still usable but not at its best.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub best_before_at: Option<SystemTime>,
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
     pub added_at: Option<SystemTime>,
-    #[builder(setter(skip))]
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
     pub removed_at: Option<SystemTime>,
+    /// The quantity when the item instance was added, which `value` was paid for.
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub initial_quantity: Option<f32>,
+    /// A unique handle like `rice-03`, generated from the name of the item type.
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub label: Option<String>,
+    /// When the container was returned and the deposit of the item type refunded.
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub returned_at: Option<SystemTime>,
+    /// The unit of the quantity when it differs from the unit of the item type, like `g`
+    /// for a bag of flour whose item type counts in `kg`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unit_override: Option<String>,
+    /// Why the item instance was removed, like `expired`, `used`, `broken` or `given-away`.
+    /// See `Inventory::trash_with_reason`.
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub removal_reason: Option<String>,
+    /// Who the item instance was given to, when it was removed with `Inventory::give`.
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub recipient: Option<String>,
+    /// Whether a compact string, which refers to the id, was made for the item instance,
+    /// for example to print a QR code. See `Inventory::compact_ids`.
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub compact_issued: bool,
+    /// When the digest was last delivered with the item instance among its reminders, so
+    /// that it is reminded once per opening. See `Inventory::mark_reminded`.
+    #[cfg_attr(feature = "builders", builder(setter(skip)))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub last_reminded_at: Option<SystemTime>,
+    /// Whether the item instance is a container that is topped up rather than replaced,
+    /// like a jar of rice refilled from new bags. It stays in the inventory when it is
+    /// used up. See `Inventory::top_up_instance`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub continuous: bool,
+    /// A reference outside of the inventory, like an order number or the url of the
+    /// purchase. See `Inventory::find_by_ref`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub external_ref: Option<String>,
+    /// Where the item instance was bought, when it matters apart from the vendor of its
+    /// item type.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub vendor: Option<String>,
+    /// When the reports stop leaving out the item instance, see `snooze`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub snoozed_until: Option<SystemTime>,
+    /// Whether the reports leave out the item instance until it is unsnoozed.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub snoozed_forever: bool,
+    /// The values of the custom fields of the item type, by key. See `Inventory::set_custom`.
+    #[cfg_attr(feature = "builders", builder(default))]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub custom: BTreeMap<String, FieldValue>,
 }
 
-impl fmt::Display for ItemInstance {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{};{};{};{};{};{};{};{};{};{}",
-            self.id,
-            self.item_type,
-            self.quantity,
+/// The same defaults as `ItemInstanceBuilder`, for the item type 0.
+impl Default for ItemInstance {
+    fn default() -> Self {
+        ItemInstance {
+            id: InstanceId(0),
+            item_type: TypeId(0),
+            quantity: 1.0,
+            model: None,
+            serial: None,
+            extra: None,
+            location: None,
+            home_location: None,
+            value: None,
+            opened_at: None,
+            expires_at: None,
+            best_before_at: None,
+            added_at: None,
+            removed_at: None,
+            initial_quantity: None,
+            label: None,
+            returned_at: None,
+            unit_override: None,
+            removal_reason: None,
+            recipient: None,
+            compact_issued: false,
+            last_reminded_at: None,
+            continuous: false,
+            external_ref: None,
+            vendor: None,
+            snoozed_until: None,
+            snoozed_forever: false,
+            custom: BTreeMap::new(),
+        }
+    }
+}
+
+impl ItemInstance {
+    /// The price paid per unit of quantity, which is `value` divided by the quantity the
+    /// item instance had when it was added. Item instances added before the initial quantity
+    /// was recorded use their current quantity instead.
+    /// Returns `None` without a value or without a positive quantity.
+    pub fn unit_price(&self) -> Option<f32> {
+        let value = self.value?;
+        let quantity = self.initial_quantity.unwrap_or(self.quantity);
+        if quantity > 0.0 && value.is_finite() {
+            Some(value / quantity)
+        } else {
+            None
+        }
+    }
+
+    /// The quantity converted to the unit of the item type. Returns `None` when the units
+    /// are not compatible, or when the item instance has a unit and the item type has none.
+    pub fn normalized_quantity(&self, item_type: Option<&ItemType>) -> Option<f32> {
+        let type_unit = item_type.and_then(|t| t.unit.as_deref());
+        match (self.unit_override.as_deref(), type_unit) {
+            (None, _) => Some(self.quantity),
+            (Some(unit), Some(type_unit)) => unit::convert(self.quantity, unit, type_unit),
+            (Some(_), None) => None,
+        }
+    }
+}
+
+#[cfg(feature = "display")]
+impl Record for ItemInstance {
+    const HEADER: &'static [&'static str] = &[
+        "id",
+        "type id",
+        "quantity",
+        "model",
+        "serial",
+        "extra",
+        "location",
+        "value",
+        "opened at",
+        "expires at",
+        "best before",
+        "label",
+        "returned at",
+        "unit",
+        "home location",
+        "added at",
+        "removed at",
+        "continuous",
+        "external ref",
+        "vendor",
+        "snoozed until",
+        "snoozed forever",
+        "custom",
+    ];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.item_type.to_string(),
+            self.quantity.to_string(),
             conv(&self.model),
             conv(&self.serial),
             conv(&self.extra),
@@ -79,11 +589,120 @@ impl fmt::Display for ItemInstance {
             conv(&self.value),
             self.opened_at
                 .map(|t| humantime::format_rfc3339(t).to_string())
-                .unwrap_or("".to_string()),
+                .unwrap_or_default(),
             self.expires_at
                 .map(|t| humantime::format_rfc3339(t).to_string())
-                .unwrap_or("".to_string())
-        )
+                .unwrap_or_default(),
+            self.best_before_at
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+            conv(&self.label),
+            self.returned_at
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+            conv(&self.unit_override),
+            conv(&self.home_location),
+            self.added_at
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+            self.removed_at
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+            self.continuous.to_string(),
+            conv(&self.external_ref),
+            conv(&self.vendor),
+            self.snoozed_until
+                .map(|t| humantime::format_rfc3339(t).to_string())
+                .unwrap_or_default(),
+            self.snoozed_forever.to_string(),
+            self.custom
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect::<Vec<_>>()
+                .join(";"),
+        ]
+    }
+}
+
+#[cfg(feature = "display")]
+impl fmt::Display for ItemInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", record::join(&self.to_record()))
+    }
+}
+
+impl ItemInstance {
+    /// Returns whether the item instance is past its use-by date (`expires_at`)
+    /// or its best-before date at the specified time. A date equal to `now` is past.
+    pub fn expiry_state(&self, now: SystemTime) -> ExpiryState {
+        let past = |date: Option<SystemTime>| date.map(|d| d <= now).unwrap_or(false);
+        if past(self.expires_at) {
+            ExpiryState::Expired
+        } else if past(self.best_before_at) {
+            ExpiryState::PastBestBefore
+        } else {
+            ExpiryState::Fresh
+        }
+    }
+
+    /// Returns the part of its shelf life the item instance has left at a time, from 1.0 when
+    /// it was opened, or added if it is not, to 0.0 at its use-by date (`expires_at`) and
+    /// after. `None` without an expiry or a start, or when the expiry is not after the start.
+    pub fn shelf_life_fraction(&self, now: SystemTime) -> Option<f32> {
+        let expires_at = self.expires_at?;
+        let start = self.opened_at.or(self.added_at)?;
+        let total = expires_at
+            .duration_since(start)
+            .ok()
+            .filter(|d| !d.is_zero())?;
+        let left = time_math::saturating_since(expires_at, now);
+        Some((left.as_secs_f64() / total.as_secs_f64()).min(1.0) as f32)
+    }
+
+    /// The order of the item instances expiring first: by use-by date, those without one
+    /// last, then like `cmp_age`. Item instances expiring together, like those of a
+    /// six-pack, so always come in the same order, whatever their order in the inventory.
+    pub fn cmp_expiry(&self, other: &ItemInstance) -> Ordering {
+        query::none_last(&self.expires_at, &other.expires_at).then_with(|| self.cmp_age(other))
+    }
+
+    /// The order of the oldest item instances first: by when they were added, those
+    /// without a date last, then by id.
+    pub fn cmp_age(&self, other: &ItemInstance) -> Ordering {
+        query::none_last(&self.added_at, &other.added_at).then(self.id.cmp(&other.id))
+    }
+
+    /// Verifies that the stored timestamps happened in a possible order,
+    /// which is `added_at <= opened_at <= removed_at`, and that none of them is in a year
+    /// that makes no sense, see `time_math::is_plausible`.
+    /// Missing timestamps are not checked.
+    pub fn check_timestamps(&self) -> Result<(), TimestampError> {
+        if let (Some(added), Some(opened)) = (self.added_at, self.opened_at) {
+            if opened < added {
+                return Err(TimestampError::OpenedBeforeAdded);
+            }
+        }
+        if let (Some(added), Some(removed)) = (self.added_at, self.removed_at) {
+            if removed < added {
+                return Err(TimestampError::RemovedBeforeAdded);
+            }
+        }
+        if let (Some(opened), Some(removed)) = (self.opened_at, self.removed_at) {
+            if removed < opened {
+                return Err(TimestampError::RemovedBeforeOpened);
+            }
+        }
+        let stored = [
+            self.added_at,
+            self.opened_at,
+            self.removed_at,
+            self.expires_at,
+            self.best_before_at,
+        ];
+        if !stored.iter().flatten().all(|t| time_math::is_plausible(*t)) {
+            return Err(TimestampError::Implausible);
+        }
+        Ok(())
     }
 }
 
@@ -91,91 +710,465 @@ pub fn conv<T: ToString>(s: &Option<T>) -> String {
     s.as_ref().map(|m| m.to_string()).unwrap_or_default()
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum UseState {
+    #[default]
     New,
     Used,
 }
 
-impl Default for UseState {
-    fn default() -> Self {
-        UseState::New
-    }
-}
-
-#[derive(Default, Serialize, Deserialize, Debug, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Inventory {
     pub item_types: Vec<ItemType>,
     pub item_instances: Vec<ItemInstance>,
+    /// The locations whose capacity is known, see `capacity`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub locations: Vec<LocationInfo>,
+    /// The lowest ids that may be given, so that the ids of deleted item types and
+    /// item instances are not given again. See `next_ids`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    next_type_id: TypeId,
+    #[cfg_attr(feature = "serde", serde(default))]
+    next_instance_id: InstanceId,
+    #[cfg_attr(feature = "serde", serde(skip, default = "clock::default_clock"))]
+    clock: Arc<dyn Clock>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    ref_index: reference::RefIndex,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Inventory::new(vec![], vec![])
+    }
 }
 
 impl Inventory {
-    pub fn add_item_type(&mut self, mut item_type: ItemType) -> u32 {
+    /// Creates an inventory from existing data, using the default clock, see
+    /// `clock::default_clock`.
+    pub fn new(item_types: Vec<ItemType>, item_instances: Vec<ItemInstance>) -> Self {
+        Inventory {
+            item_types,
+            item_instances,
+            locations: vec![],
+            next_type_id: TypeId(0),
+            next_instance_id: InstanceId(0),
+            clock: clock::default_clock(),
+            ref_index: Default::default(),
+        }
+    }
+
+    /// Returns the ids the next item type and item instance will get, which are above every id
+    /// ever given, including those of deleted item types and item instances, to save them.
+    pub fn next_ids(&self) -> (TypeId, InstanceId) {
+        (self.free_type_id(), self.free_instance_id())
+    }
+
+    /// Restores the ids saved from `next_ids`. Ids lower than those in use are ignored.
+    pub fn set_next_ids(&mut self, next_type_id: TypeId, next_instance_id: InstanceId) {
+        self.next_type_id = next_type_id;
+        self.next_instance_id = next_instance_id;
+    }
+
+    /// Replaces the clock used to timestamp the operations on this inventory.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Returns the current time according to the clock of this inventory.
+    pub fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Returns the id and error of all item instances whose timestamps are out of order or implausible.
+    pub fn check_timestamps(&self) -> Vec<(InstanceId, TimestampError)> {
+        self.item_instances
+            .iter()
+            .filter_map(|ii| ii.check_timestamps().err().map(|e| (ii.id, e)))
+            .collect()
+    }
+
+    /// Checks that the ids of the item types and of the item instances are unique,
+    /// which can stop being the case when the files are edited by hand, then that the
+    /// quantities of the item instances that are not removed can be converted to the unit
+    /// of their item type.
+    pub fn validate(&self) -> Result<(), InventoryError> {
+        let type_ids = duplicates(self.item_types.iter().map(|t| t.id));
+        let instance_ids = duplicates(self.item_instances.iter().map(|ii| ii.id));
+        if !type_ids.is_empty() || !instance_ids.is_empty() {
+            return Err(InventoryError::DuplicateIds {
+                type_ids,
+                instance_ids,
+            });
+        }
+        let types = self.types_by_id();
+        let instance_ids = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+            .filter(|ii| {
+                ii.normalized_quantity(types.get(&ii.item_type).copied())
+                    .is_none()
+            })
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        if instance_ids.is_empty() {
+            Ok(())
+        } else {
+            Err(InventoryError::IncompatibleUnits { instance_ids })
+        }
+    }
+
+    fn types_by_id(&self) -> HashMap<TypeId, &ItemType> {
+        self.item_types.iter().map(|t| (t.id, t)).collect()
+    }
+
+    /// Gives a new id to every item type and item instance whose id was already used
+    /// by an earlier one in the list. Item instances keep referring to the first item type
+    /// with their type id, and reassigned item instances whose label is also duplicated
+    /// get a new label. Returns the old and new ids.
+    pub fn fix_duplicate_ids(&mut self) -> IdReassignments {
+        let mut fixes = IdReassignments::default();
+        let mut seen = HashSet::new();
+        for i in 0..self.item_types.len() {
+            let id = self.item_types[i].id;
+            if !seen.insert(id) {
+                let new = self.free_type_id();
+                self.item_types[i].id = new;
+                seen.insert(new);
+                fixes.types.push((id, new));
+            }
+        }
+        let mut seen = HashSet::new();
+        for i in 0..self.item_instances.len() {
+            let id = self.item_instances[i].id;
+            if !seen.insert(id) {
+                let new = self.free_instance_id();
+                self.item_instances[i].id = new;
+                seen.insert(new);
+                fixes.instances.push((id, new));
+                let label = self.item_instances[i].label.clone();
+                if label.is_some() && self.item_instances[..i].iter().any(|ii| ii.label == label) {
+                    let label = self.generate_label(self.item_instances[i].item_type);
+                    self.item_instances[i].label = Some(label);
+                }
+            }
+        }
+        fixes
+    }
+
+    /// Renumbers the item types, the item instances or both sequentially from 1, in the order
+    /// of their ids, and rewrites the item types of the item instances to match. The next ids
+    /// given are after the new highest ones. Returns the ids that changed.
+    /// Fails without changing anything if an item instance whose id would change has had a
+    /// compact string made for it, unless `force` is set, since the string would then refer
+    /// to another item instance. The ids have to be unique, see `fix_duplicate_ids`.
+    pub fn compact_ids(
+        &mut self,
+        types: bool,
+        instances: bool,
+        force: bool,
+    ) -> Result<IdReassignments, InventoryError> {
+        fn renumber<I: Copy + Ord + From<u32>>(ids: impl Iterator<Item = I>) -> Vec<(I, I)> {
+            let mut ids = ids.collect::<Vec<_>>();
+            ids.sort_unstable();
+            ids.into_iter()
+                .zip((1..).map(I::from))
+                .filter(|(old, new)| old != new)
+                .collect()
+        }
+        let mut changes = IdReassignments::default();
+        if types {
+            changes.types = renumber(self.item_types.iter().map(|t| t.id));
+        }
+        if instances {
+            changes.instances = renumber(self.item_instances.iter().map(|ii| ii.id));
+        }
+        let type_ids = changes.types.iter().copied().collect::<HashMap<_, _>>();
+        let instance_ids = changes.instances.iter().copied().collect::<HashMap<_, _>>();
+        let issued = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.compact_issued && instance_ids.contains_key(&ii.id))
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        if !issued.is_empty() && !force {
+            return Err(InventoryError::CompactStringsIssued {
+                instance_ids: issued,
+            });
+        }
+
+        for t in &mut self.item_types {
+            if let Some(new) = type_ids.get(&t.id) {
+                t.id = *new;
+            }
+        }
+        for ii in &mut self.item_instances {
+            if let Some(new) = type_ids.get(&ii.item_type) {
+                ii.item_type = *new;
+            }
+            if let Some(new) = instance_ids.get(&ii.id) {
+                ii.id = *new;
+                ii.compact_issued = false;
+            }
+        }
+        if types {
+            self.next_type_id = TypeId(0);
+        }
+        if instances {
+            self.next_instance_id = InstanceId(0);
+        }
+        Ok(changes)
+    }
+
+    pub fn add_item_type(&mut self, mut item_type: ItemType) -> TypeId {
         let free_id = self.free_type_id();
         item_type.id = free_id;
         self.item_types.push(item_type);
         free_id
     }
 
+    /// Adds an item instance, applying the defaults of its item type.
+    /// Fails if the item type doesn't exist or if the quantity is negative or not a number.
+    ///
+    /// An explicit `opened_at` is kept, otherwise it is the current time if the item type
+    /// is opened by default. An explicit `expires_at` is kept, otherwise an opened instance
+    /// of an item type with a ttl expires at `opened_at + ttl`, otherwise it has no expiry.
+    /// `added_at` is the current time, or `opened_at` if that is earlier.
     pub fn add_item_instance(
         &mut self,
         mut item_instance: ItemInstance,
-    ) -> Result<u32, InventoryError> {
+    ) -> Result<InstanceId, InventoryError> {
+        if !item_instance.quantity.is_finite() || item_instance.quantity < 0.0 {
+            return Err(InventoryError::InvalidQuantity);
+        }
         let free_id = self.free_instance_id();
+        let now = self.now();
         item_instance.id = free_id;
-        if let Some(it) = self
+        let it = self
             .item_types
             .iter()
             .find(|it| it.id == item_instance.item_type)
-        {
-            if it.opened_by_default {
-                item_instance.opened_at = Some(SystemTime::now());
-                if let Some(ttl) = it.ttl {
-                    item_instance.expires_at = Some(SystemTime::now().add(ttl.clone()));
+            .ok_or(InventoryError::UnknownItemType)?;
+        if it.integer_only && !is_whole(item_instance.quantity) {
+            return Err(InventoryError::FractionalQuantityNotAllowed);
+        }
+        if let (Some(unit), Some(type_unit)) = (&item_instance.unit_override, &it.unit) {
+            if unit::same(unit, type_unit) {
+                item_instance.unit_override = None;
+            }
+        }
+        if item_instance.normalized_quantity(Some(it)).is_none() {
+            return Err(InventoryError::IncompatibleUnits {
+                instance_ids: vec![],
+            });
+        }
+        if item_instance.opened_at.is_none() && it.opened_by_default {
+            item_instance.opened_at = Some(now);
+        }
+        if item_instance.expires_at.is_none() {
+            if let (Some(opened_at), Some(ttl)) = (item_instance.opened_at, it.ttl) {
+                // A ttl too long to be represented never expires.
+                if let Some(exp) = time_math::checked_add_duration(opened_at, ttl) {
+                    warn_if_not_after(exp, opened_at, free_id);
+                    item_instance.expires_at = Some(exp);
                 }
             }
-        } else {
+        }
+        item_instance.added_at = Some(item_instance.opened_at.map_or(now, |o| o.min(now)));
+        item_instance.label = Some(self.generate_label(item_instance.item_type));
+        item_instance.initial_quantity = Some(item_instance.quantity);
+        self.item_instances.push(item_instance);
+        Ok(free_id)
+    }
+
+    /// Adds an item instance without applying the defaults of its item type,
+    /// keeping the timestamps it already has. Only the id is assigned, and `added_at`
+    /// is set to the current time if missing. Useful to import existing data.
+    pub fn insert_item_instance(
+        &mut self,
+        mut item_instance: ItemInstance,
+    ) -> Result<InstanceId, InventoryError> {
+        if !self.has_item_type(item_instance.item_type) {
             return Err(InventoryError::UnknownItemType);
         }
-        // TODO check the type "open by default" thingy
-        item_instance.added_at = Some(SystemTime::now());
+        let free_id = self.free_instance_id();
+        item_instance.id = free_id;
+        if item_instance.added_at.is_none() {
+            item_instance.added_at = Some(self.now());
+        }
+        if item_instance.initial_quantity.is_none() {
+            item_instance.initial_quantity = Some(item_instance.quantity);
+        }
+        item_instance.label = Some(self.generate_label(item_instance.item_type));
+        warn_if_out_of_order(&item_instance);
         self.item_instances.push(item_instance);
         Ok(free_id)
     }
 
-    pub fn use_instance<'a>(&mut self, type_id: u32, quantity: Option<f32>) {
-        let mut remaining = 0.0;
-        let mut trash_id = 0;
-        let mut item_instances = self
+    /// Returns a label that no item instance has yet, made of the slug of the item type name
+    /// and the first free counter, like `rice-03`.
+    pub fn generate_label(&self, type_id: TypeId) -> String {
+        let slug = self
+            .item_types
+            .iter()
+            .find(|it| it.id == type_id)
+            .map(|it| slug(&it.name))
+            .filter(|slug| !slug.is_empty())
+            .unwrap_or_else(|| "item".to_string());
+        (1..)
+            .map(|counter| format!("{}-{:02}", slug, counter))
+            .find(|label| {
+                !self
+                    .item_instances
+                    .iter()
+                    .any(|ii| ii.label.as_ref() == Some(label))
+            })
+            .expect("Ran out of labels")
+    }
+
+    /// Gives a label to the item instances that have none, in id order.
+    /// Returns the ids and new labels.
+    pub fn generate_missing_labels(&mut self) -> Vec<(InstanceId, String)> {
+        let mut ids = self
             .item_instances
-            .iter_mut()
-            .filter(|t| t.item_type == type_id && t.removed_at.is_none())
+            .iter()
+            .filter(|ii| ii.label.is_none())
+            .map(|ii| (ii.id, ii.item_type))
             .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.into_iter()
+            .map(|(id, type_id)| {
+                let label = self.generate_label(type_id);
+                if let Some(ii) = self.item_instances.iter_mut().find(|ii| ii.id == id) {
+                    ii.label = Some(label.clone());
+                }
+                (id, label)
+            })
+            .collect()
+    }
+
+    /// Finds the item type designated by an id or, when no item type has that id, by a name.
+    /// A name designates the item type with exactly that name, ignoring case, or else the only
+    /// item type whose name contains it: `MatchMode::Exact`, then `MatchMode::Substring`.
+    /// Former names count too, but an exact current name wins over them.
+    /// Fails with `AmbiguousName` when several item types could be meant.
+    pub fn resolve_type(&self, selector: &str) -> Result<TypeId, InventoryError> {
+        if let Ok(id) = selector.parse::<TypeId>() {
+            if self.item_types.iter().any(|t| t.id == id) {
+                return Ok(id);
+            }
+        }
+        let candidates = self.types_matching(selector, MatchMode::Substring);
+        let mut exact = self.types_matching(selector, MatchMode::Exact);
+        if exact.len() > 1 {
+            exact.retain(|t| MatchMode::Exact.matches(&t.name, selector));
+        }
+        match (exact.as_slice(), candidates.as_slice()) {
+            ([t], _) | ([], [t]) => Ok(t.id),
+            (_, []) => Err(InventoryError::UnknownItemType),
+            _ => Err(InventoryError::AmbiguousName {
+                type_ids: candidates.iter().map(|t| t.id).collect(),
+            }),
+        }
+    }
+
+    /// Finds the item instance designated by an id or, when no item instance has that id,
+    /// by a label.
+    pub fn resolve_instance(&self, selector: &str) -> Result<InstanceId, InventoryError> {
+        if let Ok(id) = selector.parse::<InstanceId>() {
+            if self.item_instances.iter().any(|ii| ii.id == id) {
+                return Ok(id);
+            }
+        }
+        self.item_instances
+            .iter()
+            .find(|ii| ii.label.as_deref() == Some(selector))
+            .map(|ii| ii.id)
+            .ok_or(InventoryError::UnknownItemInstance)
+    }
 
-        let mut target = item_instances.iter_mut().find(|ii| ii.opened_at.is_some());
-        if target.is_none() {
-            target = item_instances.first_mut();
-        }
-        if let Some(item_instance) = target {
-            if let Some(e) = quantity {
-                item_instance.quantity = item_instance.quantity - e;
-                if item_instance.quantity < 0.0 {
-                    remaining = item_instance.quantity;
-                    trash_id = item_instance.id;
-                    item_instance.quantity = 0.0;
+    /// Uses some quantity (1.0 by default) of an item type, opening the item instances it is
+    /// taken from. Each item instance gives at most what it has left, until the quantity is
+    /// reached or nothing is left. The target is picked again by `pick_use_target` for what
+    /// remains after each emptied item instance, so an opened one is finished before a sealed
+    /// one is opened. Returns the quantity actually used, which is less than requested when
+    /// there was not enough, and what was taken from each item instance.
+    /// Emptied item instances are trashed if the item type is `auto_trash_empty`, except
+    /// `continuous` ones, which wait to be topped up.
+    /// See `QUANTITY_EPSILON` for how rounding errors are handled.
+    /// Fails without changing anything if the quantity is negative or not finite, or if the
+    /// item type does not exist, even when item instances still refer to it.
+    pub fn use_instance(
+        &mut self,
+        type_id: TypeId,
+        quantity: Option<f32>,
+    ) -> Result<UseReport, InventoryError> {
+        let now = self.now();
+        let it = self
+            .item_types
+            .iter()
+            .find(|it| it.id == type_id)
+            .ok_or(InventoryError::UnknownItemType)?;
+        let ttl = it.ttl;
+        let auto_trash_empty = it.auto_trash_empty;
+        let type_unit = it.unit.clone();
+        let requested = quantity.unwrap_or(1.0);
+        if !requested.is_finite() || requested < 0.0 {
+            return Err(InventoryError::InvalidQuantity);
+        }
+        if it.integer_only && !is_whole(requested) {
+            return Err(InventoryError::FractionalQuantityNotAllowed);
+        }
+        let mut remaining = requested;
+        let mut report = UseReport::default();
+        while remaining > 0.0 && !(report.used > 0.0 && remaining <= QUANTITY_EPSILON) {
+            let target_id = match self.pick_use_target(type_id) {
+                Some(ii) => ii.id,
+                None => {
+                    if report.used == 0.0 {
+                        warn!("Could not find an item instance with the specified type id to use (or all items were used.)");
+                    }
+                    break;
                 }
+            };
+            let item_instance = self
+                .item_instances
+                .iter_mut()
+                .find(|ii| ii.id == target_id)
+                .expect("the use target exists");
+            // The quantities are used in the unit of the item type. Item instances whose unit
+            // can not be converted are used as if they had the same unit.
+            let units = match (&item_instance.unit_override, &type_unit) {
+                (Some(unit), Some(type_unit)) => Some((unit.as_str(), type_unit.as_str())),
+                _ => None,
+            };
+            let convert = |quantity: f32, to_type: bool| {
+                units
+                    .and_then(|(unit, type_unit)| match to_type {
+                        true => unit::convert(quantity, unit, type_unit),
+                        false => unit::convert(quantity, type_unit, unit),
+                    })
+                    .unwrap_or(quantity)
+            };
+            let available = convert(item_instance.quantity, true);
+            let mut draw = remaining.min(available);
+            if available - draw <= QUANTITY_EPSILON {
+                draw = available;
+                item_instance.quantity = 0.0;
             } else {
-                item_instance.quantity -= 1.0;
+                item_instance.quantity -= convert(draw, false);
             }
-            if item_instance.opened_at.is_none() {
-                item_instance.opened_at = Some(SystemTime::now());
-                let it = self
-                    .item_types
-                    .iter()
-                    .find(|it| it.id == type_id)
-                    .expect("No item type found with the specified id");
-                if let Some(ttl) = it.ttl {
-                    let candidate_exp = SystemTime::now().add(ttl);
+            report.used += draw;
+            remaining -= draw;
+            let opened = item_instance.opened_at.is_none();
+            if opened {
+                item_instance.opened_at = Some(now);
+                if let Some(candidate_exp) =
+                    ttl.and_then(|ttl| time_math::checked_add_duration(now, ttl))
+                {
+                    warn_if_not_after(candidate_exp, now, item_instance.id);
                     let new_exp = if let Some(old) = item_instance.expires_at {
                         if old < candidate_exp {
                             old
@@ -188,86 +1181,3087 @@ impl Inventory {
                     item_instance.expires_at = Some(new_exp);
                 }
             }
-        } else {
-            eprintln!("Could not find an item instance with the specified type id to use (or all items were used.)");
-        }
-
-        if remaining < -0.0005 {
-            self.trash(trash_id);
-            self.use_instance(type_id, Some(-remaining));
+            warn_if_out_of_order(item_instance);
+            let emptied = item_instance.quantity == 0.0;
+            let trashed = emptied && auto_trash_empty && !item_instance.continuous;
+            if trashed {
+                self.trash(target_id);
+            }
+            report.hops.push(UseHop {
+                id: target_id,
+                used: draw,
+                opened,
+                emptied,
+                trashed,
+            });
         }
+        Ok(report)
     }
 
-    pub fn trash<'a>(&mut self, instance_id: u32) {
-        if let Some(mut item_instance) =
-            self.item_instances.iter_mut().find(|t| t.id == instance_id)
-        {
-            item_instance.removed_at = Some(SystemTime::now());
+    /// Uses some quantity of an item type like `use_instance`, and records what is missing
+    /// when there is not enough left: an item instance of the missing quantity is created
+    /// already used up and trashed with the reason `used`, so that quantities never go
+    /// negative, and the item type is flagged for a recount. Returns the quantity used from
+    /// the item instances and the id of the item instance recording the rest, if any.
+    pub fn use_allowing_overdraft(
+        &mut self,
+        type_id: TypeId,
+        quantity: Option<f32>,
+    ) -> Result<(f32, Option<InstanceId>), InventoryError> {
+        let requested = quantity.unwrap_or(1.0);
+        let used = if self.pick_use_target(type_id).is_some() {
+            self.use_instance(type_id, Some(requested))?.used
+        } else if !requested.is_finite() || requested < 0.0 {
+            return Err(InventoryError::InvalidQuantity);
         } else {
-            eprintln!("Could not find an item instance with the specified id to trash");
+            match self.item_types.iter().find(|it| it.id == type_id) {
+                None => return Err(InventoryError::UnknownItemType),
+                Some(it) if it.integer_only && !is_whole(requested) => {
+                    return Err(InventoryError::FractionalQuantityNotAllowed)
+                }
+                Some(_) => 0.0,
+            }
+        };
+        let missing = requested - used;
+        if missing <= QUANTITY_EPSILON {
+            return Ok((used, None));
         }
+        let now = self.now();
+        let overdraft = ItemInstance {
+            item_type: type_id,
+            quantity: 0.0,
+            initial_quantity: Some(missing),
+            added_at: Some(now),
+            opened_at: Some(now),
+            ..ItemInstance::default()
+        };
+        let id = self.insert_item_instance(overdraft)?;
+        self.trash_with_reason(id, Some("used"));
+        if let Some(it) = self.item_types.iter_mut().find(|it| it.id == type_id) {
+            it.recount_requested_at.get_or_insert(now);
+        }
+        Ok((used, Some(id)))
     }
 
-    pub fn delete_item_type(&mut self, id: u32) {
-        self.item_types.retain(|t| t.id != id);
-        self.item_instances.retain(|i| i.item_type != id);
+    /// The item types flagged for a recount, oldest flag first. See
+    /// `ItemType::recount_requested_at`.
+    pub fn recount_requested(&self) -> Vec<&ItemType> {
+        let mut types = self
+            .item_types
+            .iter()
+            .filter(|it| it.recount_requested_at.is_some())
+            .collect::<Vec<_>>();
+        types.sort_by_key(|it| (it.recount_requested_at, it.id));
+        types
     }
 
-    pub fn delete_item_instance(&mut self, id: u32) -> Result<(), InventoryError> {
-        if let Some(inst) = self.item_instances.iter_mut().find(|inst| inst.id == id) {
-            inst.removed_at = Some(SystemTime::now());
-            Ok(())
-        } else {
-            Err(InventoryError::UnknownItemInstance)
+    /// Uses all the remaining quantity of an item instance, leaving exactly nothing, and
+    /// trashes it unless its item type keeps empty instances or it is `continuous`.
+    /// Returns the quantity that was consumed.
+    pub fn finish_instance(&mut self, instance_id: InstanceId) -> Result<f32, InventoryError> {
+        let now = self.now();
+        let item_instance = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        if item_instance.removed_at.is_some() || item_instance.quantity <= 0.0 {
+            return Err(InventoryError::FinishedItemInstance);
+        }
+        let consumed = item_instance.quantity;
+        item_instance.quantity = 0.0;
+        if item_instance.opened_at.is_none() {
+            item_instance.opened_at = Some(now);
+        }
+        warn_if_out_of_order(item_instance);
+        let type_id = item_instance.item_type;
+        let continuous = item_instance.continuous;
+        let auto_trash_empty = self
+            .item_types
+            .iter()
+            .find(|it| it.id == type_id)
+            .map(|it| it.auto_trash_empty)
+            .unwrap_or(true);
+        if auto_trash_empty && !continuous {
+            self.trash(instance_id);
         }
+        Ok(consumed)
     }
 
-    pub fn get_instances_for_type(&self, id: u32) -> Result<Vec<&ItemInstance>, InventoryError> {
-        if !self.has_item_type(id) {
-            return Err(InventoryError::UnknownItemType);
-        }
-        Ok(self
+    /// Adds some quantity to a `continuous` item instance, in its unit, like a new bag of
+    /// rice poured into the jar. The expiry moves to `expires_at` when it is later than the
+    /// current one, or when there was none. Returns the quantity now in the item instance.
+    /// Fails for item instances that are not continuous or in the trash, and for quantities
+    /// that are not more than zero, or not whole for `integer_only` item types.
+    pub fn top_up_instance(
+        &mut self,
+        instance_id: InstanceId,
+        quantity: f32,
+        expires_at: Option<SystemTime>,
+    ) -> Result<f32, InventoryError> {
+        let item_instance = self
             .item_instances
             .iter()
-            .filter(|inst| inst.item_type == id && inst.removed_at.is_none())
-            .collect::<Vec<_>>())
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        if item_instance.removed_at.is_some() {
+            return Err(InventoryError::FinishedItemInstance);
+        }
+        if !item_instance.continuous {
+            return Err(InventoryError::NotContinuous);
+        }
+        if !quantity.is_finite() || quantity <= 0.0 {
+            return Err(InventoryError::InvalidQuantity);
+        }
+        if !is_whole(quantity) && self.is_integer_only(item_instance.item_type) {
+            return Err(InventoryError::FractionalQuantityNotAllowed);
+        }
+        let item_instance = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == instance_id)
+            .expect("the item instance was just found");
+        item_instance.quantity += quantity;
+        if let Some(expires_at) = expires_at {
+            if item_instance.expires_at.is_none_or(|old| old < expires_at) {
+                item_instance.expires_at = Some(expires_at);
+            }
+        }
+        Ok(item_instance.quantity)
     }
 
-    fn has_item_type(&self, id: u32) -> bool {
-        self.item_types.iter().any(|ty| ty.id == id)
-    }
+    /// Moves some quantity of an item instance to a new item instance, which is identical
+    /// except for its id and label. The value is shared in proportion to the quantities.
+    /// The quantity has to be more than zero and less than what is left, in the unit of the
+    /// item instance. Returns the id of the new item instance.
+    pub fn split_instance(
+        &mut self,
+        instance_id: InstanceId,
+        quantity: f32,
+    ) -> Result<InstanceId, InventoryError> {
+        let free_id = self.free_instance_id();
+        let index = self
+            .item_instances
+            .iter()
+            .position(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        let original = &self.item_instances[index];
+        if original.removed_at.is_some() {
+            return Err(InventoryError::FinishedItemInstance);
+        }
+        if !quantity.is_finite() || quantity <= 0.0 || quantity >= original.quantity {
+            return Err(InventoryError::InvalidQuantity);
+        }
+        if !is_whole(quantity) && self.is_integer_only(original.item_type) {
+            return Err(InventoryError::FractionalQuantityNotAllowed);
+        }
+        let unit_price = original.unit_price();
+        let initial_quantity = original.initial_quantity.unwrap_or(original.quantity);
+        let mut split = original.clone();
+        split.id = free_id;
+        split.quantity = quantity;
+        split.initial_quantity = Some(quantity);
+        split.value = unit_price.map(|p| p * quantity);
+        split.compact_issued = false;
+        split.label = Some(self.generate_label(split.item_type));
 
-    fn free_type_id(&self) -> u32 {
-        self.item_types.iter().map(|it| it.id).max().unwrap_or(0) + 1
+        let original = &mut self.item_instances[index];
+        original.quantity -= quantity;
+        original.initial_quantity = Some((initial_quantity - quantity).max(original.quantity));
+        if let (Some(p), Some(q)) = (unit_price, original.initial_quantity) {
+            original.value = Some(p * q);
+        }
+        self.item_instances.push(split);
+        Ok(free_id)
     }
 
-    fn free_instance_id(&self) -> u32 {
-        self.item_instances
+    /// Sets the quantity left in an item instance, which has to be a positive number or zero,
+    /// and a whole one if its item type is `integer_only`.
+    pub fn set_quantity(
+        &mut self,
+        instance_id: InstanceId,
+        quantity: f32,
+    ) -> Result<(), InventoryError> {
+        if !quantity.is_finite() || quantity < 0.0 {
+            return Err(InventoryError::InvalidQuantity);
+        }
+        let type_id = self
+            .item_instances
             .iter()
-            .map(|ii| ii.id)
-            .max()
-            .unwrap_or(0)
-            + 1
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?
+            .item_type;
+        if !is_whole(quantity) && self.is_integer_only(type_id) {
+            return Err(InventoryError::FractionalQuantityNotAllowed);
+        }
+        let item_instance = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == instance_id)
+            .expect("the item instance was just found");
+        item_instance.quantity = quantity;
+        Ok(())
     }
 
-    pub fn get_types_for_name(&self, name: &String) -> Vec<&ItemType> {
+    fn is_integer_only(&self, type_id: TypeId) -> bool {
         self.item_types
             .iter()
-            .filter(|t| t.name.to_lowercase().contains(&name.to_lowercase()))
-            .collect::<Vec<_>>()
+            .any(|it| it.id == type_id && it.integer_only)
     }
 
-    pub fn quantity_for_type(&self, type_id: u32) -> f32 {
+    /// The ids of the item instances in the inventory whose quantity is not a whole number
+    /// although their item type is `integer_only`, like those added before it was set.
+    pub fn fractional_quantities(&self) -> Vec<InstanceId> {
         self.item_instances
             .iter()
-            .filter(|ii| ii.item_type == type_id && ii.removed_at.is_none())
-            .map(|ii| ii.quantity)
-            .fold(0.0, |accum, e| accum + e)
+            .filter(|ii| ii.removed_at.is_none() && !is_whole(ii.quantity))
+            .filter(|ii| self.is_integer_only(ii.item_type))
+            .map(|ii| ii.id)
+            .collect()
     }
-}
 
-#[derive(Debug, Clone)]
+    /// Returns the item instance that `use_instance` takes from, among the instances of the type
+    /// that are neither removed nor empty. Opened instances come first, ordered by earliest expiry and then by
+    /// lowest quantity, so that an opened container is finished before the next one goes stale.
+    /// When none is opened, the instance expiring first is picked.
+    /// Instances without an expiry come after the ones having one. The remaining ties go to
+    /// the instance added first, then to the lowest id, so the pick never depends on the
+    /// order of the instances in the inventory.
+    pub fn pick_use_target(&self, type_id: TypeId) -> Option<&ItemInstance> {
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.item_type == type_id && ii.removed_at.is_none() && ii.quantity > 0.0)
+            .min_by(|a, b| {
+                let opened = b.opened_at.is_some().cmp(&a.opened_at.is_some());
+                let expiry = query::none_last(&a.expires_at, &b.expires_at);
+                let quantity = if a.opened_at.is_some() && b.opened_at.is_some() {
+                    a.quantity
+                        .partial_cmp(&b.quantity)
+                        .unwrap_or(Ordering::Equal)
+                } else {
+                    Ordering::Equal
+                };
+                opened
+                    .then(expiry)
+                    .then(quantity)
+                    .then_with(|| a.cmp_age(b))
+            })
+    }
+
+    /// Applies the update to every item type matching the filters of the query,
+    /// ignoring its sorting and paging. Returns the ids of the matching item types.
+    /// The expiries of item instances are not changed, see `recompute_expiries`.
+    pub fn update_types_where(&mut self, filter: &TypeQuery, update: &TypeUpdate) -> Vec<TypeId> {
+        let stats = filter.stats_for(self);
+        self.item_types
+            .iter_mut()
+            .filter(|t| filter.matches(t, &stats))
+            .map(|t| {
+                if let Some(ttl) = update.ttl {
+                    t.ttl = ttl;
+                }
+                if let Some(minimum) = update.minimum_quantity {
+                    t.minimum_quantity = minimum;
+                }
+                if let Some(opened_by_default) = update.opened_by_default {
+                    t.opened_by_default = opened_by_default;
+                }
+                t.id
+            })
+            .collect()
+    }
+
+    /// Derives again the expiry of the item instances of a type from their opening time
+    /// and the current ttl of the type, or with `force` from the time sealed instances were
+    /// added. Removed instances are never changed.
+    /// Returns the id, old expiry and new expiry of every instance that changed.
+    pub fn recompute_expiries(
+        &mut self,
+        type_id: TypeId,
+        policy: RecomputePolicy,
+    ) -> Result<Vec<ExpiryChange>, InventoryError> {
+        let ttl = self
+            .item_types
+            .iter()
+            .find(|t| t.id == type_id)
+            .ok_or(InventoryError::UnknownItemType)?
+            .ttl;
+        let mut changes = vec![];
+        for ii in self
+            .item_instances
+            .iter_mut()
+            .filter(|ii| ii.item_type == type_id && ii.removed_at.is_none())
+        {
+            let new_exp = match ii.opened_at {
+                Some(opened) => ttl.and_then(|ttl| time_math::checked_add_duration(opened, ttl)),
+                None if policy.force && !policy.only_opened => match (ii.added_at, ttl) {
+                    (Some(added), Some(ttl)) => time_math::checked_add_duration(added, ttl),
+                    _ => continue,
+                },
+                None => continue,
+            };
+            if new_exp != ii.expires_at {
+                changes.push((ii.id, ii.expires_at, new_exp));
+                ii.expires_at = new_exp;
+            }
+        }
+        Ok(changes)
+    }
+
+    pub fn trash(&mut self, instance_id: InstanceId) {
+        self.trash_with_reason(instance_id, None);
+    }
+
+    /// Puts an item instance in the trash, recording why. Without a reason, an item instance
+    /// that still has some quantity left and is past its use-by date is recorded as `expired`.
+    ///
+    /// ```
+    /// use inv_manager::{Inventory, ItemInstance, ItemType};
+    ///
+    /// let mut inventory = Inventory::default();
+    /// let item_type = inventory.add_item_type(ItemType {
+    ///     name: "glass".to_string(),
+    ///     ..ItemType::default()
+    /// });
+    /// let id = inventory
+    ///     .add_item_instance(ItemInstance {
+    ///         item_type,
+    ///         ..ItemInstance::default()
+    ///     })
+    ///     .unwrap();
+    /// inventory.trash_with_reason(id, Some("broken"));
+    /// assert_eq!(
+    ///     inventory.item_instances[0].removal_reason.as_deref(),
+    ///     Some("broken")
+    /// );
+    /// ```
+    pub fn trash_with_reason(&mut self, instance_id: InstanceId, reason: Option<&str>) {
+        let now = self.now();
+        if let Some(item_instance) = self.item_instances.iter_mut().find(|t| t.id == instance_id) {
+            remove(item_instance, now, reason);
+        } else {
+            warn!("Could not find an item instance with the specified id to trash");
+        }
+    }
+
+    /// Puts some quantity of an item instance in the trash, like `trash_quantity_with_reason`
+    /// without a reason.
+    pub fn trash_quantity(
+        &mut self,
+        instance_id: InstanceId,
+        quantity: f32,
+    ) -> Result<InstanceId, InventoryError> {
+        self.trash_quantity_with_reason(instance_id, quantity, None)
+    }
+
+    /// Splits some quantity off an item instance and puts it in the trash, see
+    /// `split_instance` and `trash_with_reason`. The rest stays in the inventory, and the whole
+    /// item instance is trashed when the quantity is all that is left. Returns the id of the
+    /// trashed item instance.
+    pub fn trash_quantity_with_reason(
+        &mut self,
+        instance_id: InstanceId,
+        quantity: f32,
+        reason: Option<&str>,
+    ) -> Result<InstanceId, InventoryError> {
+        let now = self.now();
+        let item_instance = self
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        if item_instance.removed_at.is_some() {
+            return Err(InventoryError::FinishedItemInstance);
+        }
+        let trashed_id = if quantity == item_instance.quantity && quantity > 0.0 {
+            instance_id
+        } else {
+            self.split_instance(instance_id, quantity)?
+        };
+        let trashed = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == trashed_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        remove(trashed, now, reason);
+        Ok(trashed_id)
+    }
+
+    /// Returns the item instances whose container can be returned: those in the trash,
+    /// with a deposit on their item type, and not returned yet.
+    pub fn returnables(&self) -> Vec<&ItemInstance> {
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_some() && ii.returned_at.is_none())
+            .filter(|ii| self.deposit_of(ii).is_some())
+            .collect()
+    }
+
+    /// Marks the container of a trashed item instance as returned.
+    /// Returns the deposit refunded.
+    pub fn return_deposit(&mut self, instance_id: InstanceId) -> Result<f32, InventoryError> {
+        let now = self.now();
+        let index = self
+            .item_instances
+            .iter()
+            .position(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        let ii = &self.item_instances[index];
+        let deposit = match self.deposit_of(ii) {
+            Some(deposit) if ii.removed_at.is_some() && ii.returned_at.is_none() => deposit,
+            _ => return Err(InventoryError::NotReturnable),
+        };
+        self.item_instances[index].returned_at = Some(now);
+        Ok(deposit)
+    }
+
+    /// The sum of the deposits that were not refunded yet, for the item instances
+    /// in the inventory or in the trash.
+    pub fn outstanding_deposits(&self) -> f32 {
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.returned_at.is_none())
+            .filter_map(|ii| self.deposit_of(ii))
+            .fold(0.0, |total, deposit| total + deposit)
+    }
+
+    fn deposit_of(&self, ii: &ItemInstance) -> Option<f32> {
+        self.item_types
+            .iter()
+            .find(|t| t.id == ii.item_type)?
+            .deposit
+            .filter(|d| *d > 0.0)
+    }
+
+    /// Trashes the empty item instances, optionally only those of one item type.
+    /// Returns the ids of the trashed instances.
+    pub fn trash_empty(&mut self, type_id: Option<TypeId>) -> Vec<InstanceId> {
+        let ids = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none() && ii.quantity <= 0.0)
+            .filter(|ii| type_id.map(|t| t == ii.item_type).unwrap_or(true))
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        ids.iter().for_each(|id| self.trash(*id));
+        ids
+    }
+
+    /// Trashes the item instances matching a query, like `trash_with_reason`, ignoring its
+    /// paging and the removed item instances. Returns the ids of the trashed instances.
+    pub fn trash_where(&mut self, query: &InstanceQuery, reason: Option<&str>) -> Vec<InstanceId> {
+        let ids = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none() && query.matches(self, ii))
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        ids.iter()
+            .for_each(|id| self.trash_with_reason(*id, reason));
+        ids
+    }
+
+    pub fn delete_item_type(&mut self, id: TypeId) {
+        let (next_type_id, next_instance_id) = self.next_ids();
+        self.set_next_ids(next_type_id, next_instance_id);
+        self.item_types.retain(|t| t.id != id);
+        self.item_instances.retain(|i| i.item_type != id);
+    }
+
+    /// Deletes an item type after moving its item instances, removed ones included, to
+    /// another item type. The item instances keep their unit: the unit of the deleted item
+    /// type becomes theirs when it is not the unit of the other one. Returns the number of
+    /// item instances moved, and nothing changes if one of them could not be converted to
+    /// the unit of the other item type.
+    pub fn delete_type_reassigning(
+        &mut self,
+        id: TypeId,
+        to: TypeId,
+    ) -> Result<usize, InventoryError> {
+        if id == to {
+            return Err(InventoryError::SameItemType);
+        }
+        let from_unit = self
+            .item_types
+            .iter()
+            .find(|t| t.id == id)
+            .ok_or(InventoryError::UnknownItemType)?
+            .unit
+            .clone();
+        let target = self
+            .item_types
+            .iter()
+            .find(|t| t.id == to)
+            .ok_or(InventoryError::UnknownItemType)?;
+        let moved = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.item_type == id)
+            .map(|ii| {
+                let mut moved = ii.clone();
+                moved.item_type = to;
+                if moved.unit_override.is_none() {
+                    moved.unit_override = from_unit.clone();
+                }
+                if let (Some(unit), Some(type_unit)) = (&moved.unit_override, &target.unit) {
+                    if unit::same(unit, type_unit) {
+                        moved.unit_override = None;
+                    }
+                }
+                moved
+            })
+            .collect::<Vec<_>>();
+        let incompatible = moved
+            .iter()
+            .filter(|ii| ii.normalized_quantity(Some(target)).is_none())
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        if !incompatible.is_empty() {
+            return Err(InventoryError::IncompatibleUnits {
+                instance_ids: incompatible,
+            });
+        }
+        for moved in &moved {
+            if let Some(ii) = self.item_instances.iter_mut().find(|ii| ii.id == moved.id) {
+                *ii = moved.clone();
+            }
+        }
+        self.delete_item_type(id);
+        Ok(moved.len())
+    }
+
+    /// Removes an item instance, recording why like `trash_with_reason`.
+    pub fn delete_item_instance(
+        &mut self,
+        id: InstanceId,
+        reason: Option<&str>,
+    ) -> Result<(), InventoryError> {
+        let now = self.now();
+        if let Some(inst) = self.item_instances.iter_mut().find(|inst| inst.id == id) {
+            remove(inst, now, reason);
+            Ok(())
+        } else {
+            Err(InventoryError::UnknownItemInstance)
+        }
+    }
+
+    pub fn get_instances_for_type(&self, id: TypeId) -> Result<Vec<&ItemInstance>, InventoryError> {
+        if !self.has_item_type(id) {
+            return Err(InventoryError::UnknownItemType);
+        }
+        Ok(self
+            .item_instances
+            .iter()
+            .filter(|inst| inst.item_type == id && inst.removed_at.is_none())
+            .collect::<Vec<_>>())
+    }
+
+    /// The name of the item type, or a placeholder like `[missing type 7]` for the item
+    /// instances whose item type was deleted by editing the files.
+    #[must_use]
+    pub fn type_name(&self, id: TypeId) -> String {
+        self.item_types
+            .iter()
+            .find(|it| it.id == id)
+            .map(|it| it.name.clone())
+            .unwrap_or_else(|| format!("[missing type {}]", id))
+    }
+
+    /// The item instances that are not in the trash and whose item type doesn't exist.
+    pub fn orphans(&self) -> Vec<&ItemInstance> {
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none() && !self.has_item_type(ii.item_type))
+            .collect()
+    }
+
+    fn has_item_type(&self, id: TypeId) -> bool {
+        self.item_types.iter().any(|ty| ty.id == id)
+    }
+
+    fn free_type_id(&self) -> TypeId {
+        let after_max = self.item_types.iter().map(|it| it.id.0).max().unwrap_or(0) + 1;
+        TypeId(after_max).max(self.next_type_id)
+    }
+
+    fn free_instance_id(&self) -> InstanceId {
+        let after_max = self
+            .item_instances
+            .iter()
+            .map(|ii| ii.id.0)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        InstanceId(after_max).max(self.next_instance_id)
+    }
+
+    /// Returns the item types whose name contains a text, ignoring case.
+    ///
+    /// ```
+    /// use inv_manager::{Inventory, ItemType};
+    ///
+    /// let mut inventory = Inventory::default();
+    /// for name in ["Rice", "rice flour", "milk"] {
+    ///     inventory.add_item_type(ItemType {
+    ///         name: name.to_string(),
+    ///         ..ItemType::default()
+    ///     });
+    /// }
+    /// let names = inventory
+    ///     .get_types_for_name("RICE")
+    ///     .iter()
+    ///     .map(|t| t.name.as_str())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(names, ["Rice", "rice flour"]);
+    /// ```
+    #[must_use]
+    pub fn get_types_for_name(&self, name: &str) -> Vec<&ItemType> {
+        self.types_matching(name, MatchMode::Substring)
+    }
+
+    /// Returns the item types whose name or a former name matches a text, ignoring case, in
+    /// the order of the inventory.
+    #[must_use]
+    pub fn types_matching(&self, text: &str, mode: MatchMode) -> Vec<&ItemType> {
+        let pattern = NamePattern::new(text, mode);
+        self.item_types
+            .iter()
+            .filter(|t| t.is_called(&pattern))
+            .collect::<Vec<_>>()
+    }
+
+    /// Returns the total quantity of the item instances of an item type that are not removed,
+    /// in the unit of the item type. Like the other sums of quantities, it leaves out the
+    /// item instances whose unit can not be converted, which `validate` reports.
+    /// Use `quantities_by_type` for more than one item type.
+    pub fn quantity_for_type(&self, type_id: TypeId) -> f32 {
+        self.sum_for_type(type_id, |_| true)
+    }
+
+    /// Returns the `quantity_for_type` of every item type having at least one item instance
+    /// that is not removed, computed in a single pass over the item instances.
+    pub fn quantities_by_type(&self) -> HashMap<TypeId, f32> {
+        let types = self.types_by_id();
+        let mut map = HashMap::new();
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+            .for_each(|ii| {
+                let quantity = ii.normalized_quantity(types.get(&ii.item_type).copied());
+                let sum = map.entry(ii.item_type).or_insert(0.0);
+                if let Some(quantity) = quantity {
+                    *sum += quantity;
+                }
+            });
+        map
+    }
+
+    /// Returns the total quantity of the item instances of an item type that are neither
+    /// removed nor opened.
+    pub fn sealed_quantity_for_type(&self, type_id: TypeId) -> f32 {
+        self.sum_for_type(type_id, |ii| ii.opened_at.is_none())
+    }
+
+    /// Returns the total quantity of the item instances of an item type that are not removed
+    /// and are at the specified location.
+    pub fn quantity_at_location(&self, type_id: TypeId, location: &str) -> f32 {
+        self.sum_for_type(type_id, |ii| ii.location.as_deref() == Some(location))
+    }
+
+    /// Sums the normalized quantities of the item instances of an item type that are not
+    /// removed and pass the filter.
+    fn sum_for_type(&self, type_id: TypeId, filter: impl Fn(&ItemInstance) -> bool) -> f32 {
+        let item_type = self.item_types.iter().find(|t| t.id == type_id);
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.item_type == type_id && ii.removed_at.is_none())
+            .filter(|ii| filter(ii))
+            .filter_map(|ii| ii.normalized_quantity(item_type))
+            .fold(0.0, |accum, e| accum + e)
+    }
+
+    /// Returns the prices paid for an item type, oldest first, including the item instances
+    /// that were removed. Item instances without a value, a positive quantity or an
+    /// `added_at` time are skipped.
+    pub fn price_history(&self, type_id: TypeId) -> Vec<PricePoint> {
+        let mut history = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.item_type == type_id)
+            .filter_map(|ii| {
+                Some(PricePoint {
+                    at: ii.added_at?,
+                    unit_price: ii.unit_price()?,
+                    instance_id: ii.id,
+                    location: ii.location.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        history.sort_by_key(|p| (p.at, p.instance_id));
+        history
+    }
+
+    /// Returns every minimum quantity that is not met, ordered by item type.
+    /// The global minimum of an item type, which depends on the season, comes before its
+    /// minimums per location.
+    /// Only the sealed quantity counts for the item types having `minimum_counts_sealed_only`,
+    /// and nothing counts for the item types flagged for a recount, whose quantities are not
+    /// known: their whole minimums are reported.
+    pub fn shortages(&self) -> Vec<Shortage> {
+        // The quantities counted for each item type, in total and per location, summed in a
+        // single pass over the item instances.
+        let now = self.now();
+        let types = self.types_by_id();
+        let mut sums = HashMap::<(TypeId, Option<&str>), f32>::new();
+        for ii in self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+        {
+            let t = match types.get(&ii.item_type) {
+                Some(t) if t.recount_requested_at.is_some() => continue,
+                Some(t) if !t.minimum_counts_sealed_only || ii.opened_at.is_none() => t,
+                _ => continue,
+            };
+            if let Some(quantity) = ii.normalized_quantity(Some(t)) {
+                *sums.entry((t.id, None)).or_insert(0.0) += quantity;
+                if let Some(location) = ii.location.as_deref() {
+                    *sums.entry((t.id, Some(location))).or_insert(0.0) += quantity;
+                }
+            }
+        }
+
+        let mut shortages = vec![];
+        for t in &self.item_types {
+            let counted =
+                |location: Option<&str>| sums.get(&(t.id, location)).copied().unwrap_or(0.0);
+            let quantity = counted(None);
+            let minimum = t.effective_minimum(now);
+            if quantity < minimum {
+                shortages.push(Shortage::new(t, None, quantity, minimum));
+            }
+            for (location, minimum) in &t.minimum_by_location {
+                let quantity = counted(Some(location));
+                if quantity < *minimum {
+                    shortages.push(Shortage::new(t, Some(location.clone()), quantity, *minimum));
+                }
+            }
+        }
+        shortages
+    }
+
+    /// Returns the statistics of the item instances of an item type that are not removed.
+    pub fn type_stats(&self, type_id: TypeId) -> TypeStats {
+        let item_type = self.item_types.iter().find(|t| t.id == type_id);
+        let mut stats = TypeStats::default();
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.item_type == type_id && ii.removed_at.is_none())
+            .for_each(|ii| stats.add(ii, item_type));
+        stats
+    }
+
+    /// Returns the statistics of every item type having at least one item instance
+    /// that is not removed, computed in a single pass over the item instances.
+    pub fn all_type_stats(&self) -> HashMap<TypeId, TypeStats> {
+        let types = self.types_by_id();
+        let mut map = HashMap::<TypeId, TypeStats>::new();
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+            .for_each(|ii| {
+                let item_type = types.get(&ii.item_type).copied();
+                map.entry(ii.item_type).or_default().add(ii, item_type)
+            });
+        map
+    }
+}
+
+/// The id, old expiry and new expiry of an item instance.
+pub type ExpiryChange = (InstanceId, Option<SystemTime>, Option<SystemTime>);
+
+/// The changes applied by `Inventory::update_types_where`. Fields left to `None` are kept.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeUpdate {
+    /// The new ttl, where `Some(None)` removes the ttl.
+    pub ttl: Option<Option<Duration>>,
+    pub minimum_quantity: Option<f32>,
+    pub opened_by_default: Option<bool>,
+}
+
+/// Selects the item instances changed by `Inventory::recompute_expiries`.
+/// Opened instances are always changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecomputePolicy {
+    /// Never changes the instances that are not opened, even with `force`.
+    pub only_opened: bool,
+    /// Also derives the expiry of the instances that are not opened, from the time they were
+    /// added and the ttl. Since the ttl only applies once opened, those expiries were set
+    /// explicitly and are kept otherwise, as are those of the instances without an addition
+    /// time or whose type has no ttl.
+    pub force: bool,
+}
+
+/// The price paid per unit for an item instance.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PricePoint {
+    /// When the item instance was added.
+    pub at: SystemTime,
+    pub unit_price: f32,
+    pub instance_id: InstanceId,
+    /// Where the item instance was stored, which can hold the store it was bought at.
+    pub location: Option<String>,
+}
+
+#[cfg(feature = "display")]
+impl Record for PricePoint {
+    const HEADER: &'static [&'static str] = &["at", "unit price", "instance id", "location"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            humantime::format_rfc3339(self.at).to_string(),
+            self.unit_price.to_string(),
+            self.instance_id.to_string(),
+            self.location.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// A minimum quantity of an item type that is not met.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Shortage {
+    pub type_id: TypeId,
+    /// The location that is short, or `None` for the total of the item type.
+    pub location: Option<String>,
+    pub quantity: f32,
+    pub minimum: f32,
+    /// The package size of the item type, see `ItemType::package_size`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub package_size: Option<f32>,
+    /// How many packages to buy to cover what is missing, for the item types with a package
+    /// size.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub packages_to_buy: Option<u32>,
+}
+
+impl Shortage {
+    /// The shortage of an item type, rounded up to whole packages when it has a package size.
+    pub fn new(
+        item_type: &ItemType,
+        location: Option<String>,
+        quantity: f32,
+        minimum: f32,
+    ) -> Self {
+        let package_size = item_type.package_size;
+        Shortage {
+            type_id: item_type.id,
+            location,
+            quantity,
+            minimum,
+            package_size,
+            packages_to_buy: package_size.map(|size| packages_to_buy(minimum - quantity, size)),
+        }
+    }
+
+    /// The quantity to buy: whole packages when the item type has a package size, otherwise
+    /// what is missing.
+    pub fn to_buy(&self) -> f32 {
+        match (self.packages_to_buy, self.package_size) {
+            (Some(packages), Some(size)) => packages as f32 * size,
+            _ => (self.minimum - self.quantity).max(0.0),
+        }
+    }
+}
+
+/// The number of packages of a size covering a missing quantity. An exact multiple, up to
+/// `QUANTITY_EPSILON` packages for rounding errors, isn't rounded up, and nothing missing
+/// needs no package.
+pub fn packages_to_buy(missing: f32, package_size: f32) -> u32 {
+    if !(missing > 0.0 && package_size > 0.0) {
+        return 0;
+    }
+    let packages = missing / package_size;
+    if is_whole(packages) {
+        packages.round() as u32
+    } else {
+        packages.ceil() as u32
+    }
+}
+
+#[cfg(feature = "display")]
+impl Record for Shortage {
+    const HEADER: &'static [&'static str] = &[
+        "type id",
+        "location",
+        "quantity",
+        "min",
+        "package size",
+        "packages to buy",
+    ];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.type_id.to_string(),
+            self.location.clone().unwrap_or_default(),
+            self.quantity.to_string(),
+            self.minimum.to_string(),
+            conv(&self.package_size),
+            conv(&self.packages_to_buy),
+        ]
+    }
+}
+
+/// Statistics about the item instances of an item type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TypeStats {
+    /// The sum of the quantities.
+    pub quantity: f32,
+    /// The sum of the quantities of the item instances that are not opened.
+    pub sealed_quantity: f32,
+    /// The number of item instances.
+    pub instance_count: u32,
+    /// The number of item instances that are opened.
+    pub opened_count: u32,
+    /// The earliest expiry date.
+    pub soonest_expiry: Option<SystemTime>,
+    /// The sum of the monetary values. Instances without a value are ignored.
+    pub total_value: f32,
+    /// The number of item instances whose unit can not be converted to the unit of the
+    /// item type, which are left out of the quantities.
+    pub incompatible_units: u32,
+}
+
+impl TypeStats {
+    fn add(&mut self, item_instance: &ItemInstance, item_type: Option<&ItemType>) {
+        let quantity = item_instance.normalized_quantity(item_type);
+        if quantity.is_none() {
+            self.incompatible_units += 1;
+        }
+        let quantity = quantity.unwrap_or(0.0);
+        self.quantity += quantity;
+        self.instance_count += 1;
+        if item_instance.opened_at.is_some() {
+            self.opened_count += 1;
+        } else {
+            self.sealed_quantity += quantity;
+        }
+        if let Some(exp) = item_instance.expires_at {
+            if self.soonest_expiry.map(|s| exp < s).unwrap_or(true) {
+                self.soonest_expiry = Some(exp);
+            }
+        }
+        self.total_value += item_instance.value.unwrap_or(0.0);
+    }
+}
+
+/// Lowercases a name, keeping letters and digits and joining the words with dashes.
+fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Warns when an expiry computed from a ttl does not come after the opening time.
+fn warn_if_not_after(expires_at: SystemTime, opened_at: SystemTime, instance_id: InstanceId) {
+    if expires_at <= opened_at {
+        warn!(
+            "Warning: the computed expiry of item instance {} is not after its opening time.",
+            instance_id
+        );
+    }
+}
+
+/// Marks an item instance as removed now, see `Inventory::trash_with_reason`.
+fn remove(item_instance: &mut ItemInstance, now: SystemTime, reason: Option<&str>) {
+    let expired = item_instance.quantity > 0.0
+        && item_instance
+            .expires_at
+            .map(|exp| exp <= now)
+            .unwrap_or(false);
+    item_instance.removed_at = Some(now);
+    item_instance.removal_reason = reason.map(str::to_string).or_else(|| {
+        if expired {
+            Some(EXPIRED.to_string())
+        } else {
+            None
+        }
+    });
+    warn_if_out_of_order(item_instance);
+}
+
+/// Warns when the timestamps of an item instance are out of order,
+/// which usually means that the system clock was wrong at some point.
+fn warn_if_out_of_order(item_instance: &ItemInstance) {
+    if let Err(e) = item_instance.check_timestamps() {
+        warn!(
+            "Warning: the timestamps of item instance {} are out of order ({}). Is the system clock correct?",
+            item_instance.id, e
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InventoryError {
     UnknownItemType,
     UnknownItemInstance,
+    /// The item instance has no home location to be put back to.
+    NoHomeLocation,
+    /// The item instances would be moved to the item type they are already of.
+    SameItemType,
+    /// The item instance is already empty or removed.
+    FinishedItemInstance,
+    /// The quantity is negative or not a number.
+    InvalidQuantity,
+    /// The quantity is not a whole number but the item type is `integer_only`.
+    FractionalQuantityNotAllowed,
+    /// The item instance is not in the trash, has no deposit or was already returned.
+    NotReturnable,
+    /// The item instance is not `continuous`, so it can not be topped up.
+    NotContinuous,
+    /// Compact strings were made for these item instances, whose ids would change.
+    CompactStringsIssued {
+        instance_ids: Vec<InstanceId>,
+    },
+    /// Several item types match a name, listed in the order of the inventory.
+    AmbiguousName {
+        type_ids: Vec<TypeId>,
+    },
+    /// Another item type already has the name, or had it, ignoring case.
+    DuplicateTypeName {
+        type_id: TypeId,
+    },
+    /// A registered location already has the name, see `Inventory::add_location`.
+    DuplicateLocation,
+    /// Some ids are used more than once, listed in ascending order.
+    DuplicateIds {
+        type_ids: Vec<TypeId>,
+        instance_ids: Vec<InstanceId>,
+    },
+    /// The unit of these item instances can not be converted to the unit of their item type.
+    /// Empty when the item instance is refused before getting an id.
+    IncompatibleUnits {
+        instance_ids: Vec<InstanceId>,
+    },
+    /// The item type has no custom field with the key.
+    UnknownField {
+        key: String,
+    },
+    /// The value given to a custom field is not of its kind.
+    InvalidFieldValue {
+        key: String,
+        kind: FieldKind,
+    },
+    /// The item type already has a custom field with the key.
+    DuplicateField {
+        key: String,
+    },
+    /// The name of an item type would be empty.
+    InvalidName,
+    /// The regular expression can not be compiled, see `TextMatcher::regex`.
+    InvalidPattern {
+        message: String,
+    },
+}
+
+/// What `Inventory::use_instance` did, in the order the item instances were taken from.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct UseReport {
+    /// The quantity used, in the unit of the item type.
+    pub used: f32,
+    pub hops: Vec<UseHop>,
+}
+
+/// What was taken from one item instance by `Inventory::use_instance`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct UseHop {
+    pub id: InstanceId,
+    /// The quantity taken, in the unit of the item type.
+    pub used: f32,
+    /// Whether the item instance was opened by this use.
+    pub opened: bool,
+    /// Whether nothing is left in the item instance.
+    pub emptied: bool,
+    /// Whether the emptied item instance was trashed, see `ItemType::auto_trash_empty`.
+    pub trashed: bool,
+}
+
+/// The ids changed by `Inventory::fix_duplicate_ids` or `Inventory::compact_ids`, as
+/// `(old, new)` in the order of the lists or of the old ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct IdReassignments {
+    pub types: Vec<(TypeId, TypeId)>,
+    pub instances: Vec<(InstanceId, InstanceId)>,
+}
+
+impl IdReassignments {
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty() && self.instances.is_empty()
+    }
+}
+
+/// Returns the ids that appear more than once, in ascending order.
+fn duplicates<I: Copy + Ord + std::hash::Hash>(ids: impl Iterator<Item = I>) -> Vec<I> {
+    let mut counts = HashMap::new();
+    ids.for_each(|id| *counts.entry(id).or_insert(0) += 1);
+    let mut duplicates = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+    duplicates.sort_unstable();
+    duplicates
+}
+
+/// How far an item instance is past its dates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryState {
+    /// Before both dates, or without dates.
+    Fresh,
+    /// Past the best-before date but not past the use-by date.
+    PastBestBefore,
+    /// Past the use-by date.
+    Expired,
+}
+
+/// The ways in which the timestamps of an item instance can be out of order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampError {
+    /// `opened_at` is before `added_at`.
+    OpenedBeforeAdded,
+    /// `removed_at` is before `added_at`.
+    RemovedBeforeAdded,
+    /// `removed_at` is before `opened_at`.
+    RemovedBeforeOpened,
+    /// A timestamp is before `time_math::EARLIEST_YEAR` or after `time_math::LATEST_YEAR`.
+    Implausible,
+}
+
+impl fmt::Display for TimestampError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimestampError::OpenedBeforeAdded => write!(f, "opened before being added"),
+            TimestampError::RemovedBeforeAdded => write!(f, "removed before being added"),
+            TimestampError::RemovedBeforeOpened => write!(f, "removed before being opened"),
+            TimestampError::Implausible => write!(
+                f,
+                "a date is before {} or after {}",
+                time_math::EARLIEST_YEAR,
+                time_math::LATEST_YEAR
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    fn inventory_at(time: SystemTime) -> (Inventory, Arc<FixedClock>) {
+        let clock = Arc::new(FixedClock::new(time));
+        let mut inventory = Inventory::default();
+        inventory.set_clock(clock.clone());
+        (inventory, clock)
+    }
+
+    fn add_type(
+        inventory: &mut Inventory,
+        ttl: Option<Duration>,
+        opened_by_default: bool,
+    ) -> TypeId {
+        inventory.add_item_type(ItemType {
+            name: "milk".to_string(),
+            ttl,
+            opened_by_default,
+            ..ItemType::default()
+        })
+    }
+
+    fn add_instance(inventory: &mut Inventory, type_id: TypeId, quantity: f32) -> InstanceId {
+        inventory
+            .add_item_instance(ItemInstance {
+                item_type: type_id,
+                quantity,
+                ..ItemInstance::default()
+            })
+            .unwrap()
+    }
+
+    fn instance(inventory: &Inventory, id: InstanceId) -> &ItemInstance {
+        inventory
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == id)
+            .unwrap()
+    }
+
+    #[test]
+    fn price_history_uses_initial_quantity() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let mut add_priced = |quantity, value| {
+            let id = inventory
+                .add_item_instance(ItemInstance {
+                    item_type: ty,
+                    quantity,
+                    value,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+            clock.advance(DAY);
+            id
+        };
+        let first = add_priced(2.0, Some(5.0));
+        add_priced(1.0, None);
+        add_priced(0.0, Some(3.0));
+        let last = add_priced(4.0, Some(6.0));
+        // Using half of the first instance must not change what was paid per unit.
+        inventory.use_instance(ty, Some(1.0)).unwrap();
+        inventory.trash(first);
+
+        let history = inventory.price_history(ty);
+        assert_eq!(
+            history
+                .iter()
+                .map(|p| (p.instance_id, p.unit_price, p.at))
+                .collect::<Vec<_>>(),
+            vec![(first, 2.5, start()), (last, 1.5, start() + 3 * DAY)]
+        );
+    }
+
+    #[test]
+    fn unit_price_falls_back_to_current_quantity() {
+        let mut ii = ItemInstance {
+            item_type: TypeId(1),
+            quantity: 4.0,
+            value: Some(2.0),
+            ..ItemInstance::default()
+        };
+        assert_eq!(ii.unit_price(), Some(0.5));
+        ii.initial_quantity = Some(8.0);
+        assert_eq!(ii.unit_price(), Some(0.25));
+        ii.initial_quantity = Some(0.0);
+        assert_eq!(ii.unit_price(), None);
+        ii.value = None;
+        assert_eq!(ii.unit_price(), None);
+    }
+
+    #[test]
+    fn shortages_per_location() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let item_type = inventory
+            .item_types
+            .iter_mut()
+            .find(|t| t.id == ty)
+            .unwrap();
+        item_type.minimum_quantity = 2.0;
+        item_type.set_minimum_at("office", 2.0);
+        item_type.set_minimum_at("home", 1.0);
+        let mut add_at = |quantity, location: &str| {
+            let id = add_instance(&mut inventory, ty, quantity);
+            inventory
+                .item_instances
+                .iter_mut()
+                .find(|ii| ii.id == id)
+                .unwrap()
+                .location = Some(location.to_string());
+            id
+        };
+        add_at(1.5, "home");
+        add_at(0.5, "office");
+        let trashed = add_at(3.0, "office");
+        inventory.trash(trashed);
+
+        assert_eq!(inventory.quantity_at_location(ty, "office"), 0.5);
+        assert_eq!(
+            inventory.shortages(),
+            vec![Shortage {
+                type_id: ty,
+                location: Some("office".to_string()),
+                quantity: 0.5,
+                minimum: 2.0,
+                package_size: None,
+                packages_to_buy: None,
+            }]
+        );
+
+        let item_type = inventory
+            .item_types
+            .iter_mut()
+            .find(|t| t.id == ty)
+            .unwrap();
+        item_type.set_minimum_at("office", 0.0);
+        item_type.minimum_quantity = 3.0;
+        assert_eq!(
+            item_type.minimum_by_location,
+            vec![("home".to_string(), 1.0)]
+        );
+        let shortages = inventory.shortages();
+        assert_eq!(shortages.len(), 1);
+        assert_eq!(shortages[0].location, None);
+        assert_eq!(shortages[0].quantity, 2.0);
+    }
+
+    #[test]
+    fn shortages_are_rounded_up_to_whole_packages() {
+        assert_eq!(packages_to_buy(2.0, 1.0), 2);
+        assert_eq!(packages_to_buy(0.1 + 0.2, 0.1), 3);
+        assert_eq!(packages_to_buy(2.05, 1.0), 3);
+        assert_eq!(packages_to_buy(0.7, 1.0), 1);
+        assert_eq!(packages_to_buy(0.0, 1.0), 0);
+        assert_eq!(packages_to_buy(-0.5, 1.0), 0);
+
+        let mut inventory = Inventory::default();
+        let rice = inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            minimum_quantity: 1.0,
+            package_size: Some(1.0),
+            ..ItemType::default()
+        });
+        let salt = inventory.add_item_type(ItemType {
+            name: "salt".to_string(),
+            minimum_quantity: 0.5,
+            ..ItemType::default()
+        });
+        add_instance(&mut inventory, rice, 0.3);
+        add_instance(&mut inventory, salt, 0.25);
+        let shortages = inventory.shortages();
+        assert_eq!(shortages[0].package_size, Some(1.0));
+        assert_eq!(shortages[0].packages_to_buy, Some(1));
+        assert_eq!(shortages[0].to_buy(), 1.0);
+        assert_eq!(shortages[1].packages_to_buy, None);
+        assert_eq!(shortages[1].to_buy(), 0.25);
+    }
+
+    #[test]
+    fn seasonal_minimums_replace_the_minimum_on_their_days() {
+        // start() is on 2020-09-13.
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        add_instance(&mut inventory, ty, 1.0);
+        let item_type = &mut inventory.item_types[0];
+        item_type.minimum_quantity = 1.0;
+        item_type.seasonal_minimums = vec![
+            "05-01..09-30=2".parse().unwrap(),
+            "11-01..02-28=0".parse().unwrap(),
+        ];
+        assert_eq!(inventory.shortages()[0].minimum, 2.0);
+
+        clock.advance(DAY * 18);
+        assert!(inventory.shortages().is_empty());
+        inventory.item_types[0].minimum_quantity = 3.0;
+        assert_eq!(inventory.shortages()[0].minimum, 3.0);
+        clock.advance(DAY * 100);
+        assert!(inventory.shortages().is_empty());
+        assert_eq!(
+            inventory.item_types[0].effective_minimum(start() + DAY * 200),
+            3.0
+        );
+    }
+
+    #[cfg(feature = "builders")]
+    #[test]
+    fn defaults_are_those_of_the_builders() {
+        let item_type = ItemTypeBuilder::default()
+            .name(String::new())
+            .build()
+            .unwrap();
+        assert_eq!(item_type, ItemType::default());
+        let ii = ItemInstanceBuilder::default()
+            .item_type(TypeId(0))
+            .build()
+            .unwrap();
+        assert_eq!(ii, ItemInstance::default());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn old_item_types_deserialize_with_defaults() {
+        let json = r#"{"id":1,"name":"coffee","minimum_quantity":1.0,"ttl":null,"opened_by_default":false}"#;
+        let item_type: ItemType = serde_json::from_str(json).unwrap();
+        assert!(item_type.minimum_by_location.is_empty());
+        assert!(item_type.auto_trash_empty);
+        assert!(!item_type.minimum_counts_sealed_only);
+        assert!(item_type.seasonal_minimums.is_empty());
+    }
+
+    #[test]
+    fn extreme_durations_never_panic() {
+        const FOREVER: Duration = Duration::from_secs(u64::MAX);
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(FOREVER), false);
+        inventory.item_types[0].remind_after_open = Some(FOREVER);
+        inventory.item_types[0].depreciation_years = Some(f32::MAX);
+        let sealed = add_instance(&mut inventory, ty, 2.0);
+        inventory.item_types[0].opened_by_default = true;
+        let opened = add_instance(&mut inventory, ty, 1.0);
+        // An expiry too far to be represented is no expiry.
+        assert_eq!(instance(&inventory, opened).expires_at, None);
+        inventory.use_instance(ty, Some(1.5)).unwrap();
+        assert!(instance(&inventory, sealed).opened_at.is_some());
+        assert_eq!(instance(&inventory, sealed).expires_at, None);
+        let policy = RecomputePolicy {
+            force: true,
+            ..RecomputePolicy::default()
+        };
+        assert!(inventory.recompute_expiries(ty, policy).unwrap().is_empty());
+
+        // Timestamps in the future of each other, like after an edit by hand.
+        let ii = &mut inventory.item_instances[0];
+        ii.added_at = Some(start() + DAY * 365);
+        ii.expires_at = Some(SystemTime::UNIX_EPOCH);
+        assert!(ii.shelf_life_fraction(start()).is_none());
+        let ii = &mut inventory.item_instances[1];
+        ii.expires_at = Some(date::from_civil_date(2300, 1, 1));
+        assert_eq!(ii.check_timestamps(), Err(TimestampError::Implausible));
+        for now in [SystemTime::UNIX_EPOCH, start()] {
+            let options = DigestOptions {
+                period: FOREVER,
+                ..DigestOptions::default()
+            };
+            inventory.digest(now, &options);
+            let options = HealthOptions {
+                stale_after: FOREVER,
+                ..HealthOptions::default()
+            };
+            inventory.health_with(now, &options);
+            inventory.waste_by_month(now, u32::MAX);
+            inventory.costs_of_ownership(now, u32::MAX);
+            inventory.idle_records(now, &HashMap::new());
+        }
+        inventory.values_by_type();
+        inventory.expiry_transitions(Some(SystemTime::UNIX_EPOCH), &HashSet::new());
+    }
+
+    #[test]
+    fn use_opens_with_clock_time() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(7 * DAY), false);
+        let id = add_instance(&mut inventory, ty, 1.0);
+        clock.advance(DAY);
+        inventory.use_instance(ty, Some(0.5)).unwrap();
+        let ii = instance(&inventory, id);
+        assert_eq!(ii.added_at, Some(start()));
+        assert_eq!(ii.opened_at, Some(start() + DAY));
+        assert_eq!(ii.expires_at, Some(start() + 8 * DAY));
+        assert_eq!(ii.check_timestamps(), Ok(()));
+    }
+
+    #[test]
+    fn opened_by_default_uses_clock_time() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(DAY), true);
+        let id = add_instance(&mut inventory, ty, 1.0);
+        let ii = instance(&inventory, id);
+        assert_eq!(ii.opened_at, Some(start()));
+        assert_eq!(ii.expires_at, Some(start() + DAY));
+    }
+
+    #[test]
+    fn clock_going_backwards_is_detected() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(DAY), false);
+        let id = add_instance(&mut inventory, ty, 1.0);
+        clock.set(SystemTime::UNIX_EPOCH);
+        inventory.use_instance(ty, Some(0.5)).unwrap();
+        assert_eq!(
+            inventory.check_timestamps(),
+            vec![(id, TimestampError::OpenedBeforeAdded)]
+        );
+        inventory.trash(id);
+        assert_eq!(
+            instance(&inventory, id).removed_at,
+            Some(SystemTime::UNIX_EPOCH)
+        );
+    }
+
+    #[test]
+    fn type_stats_ignore_removed_instances() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(DAY), false);
+        let other = add_type(&mut inventory, None, false);
+        let first = add_instance(&mut inventory, ty, 2.0);
+        add_instance(&mut inventory, ty, 1.0);
+        let removed = add_instance(&mut inventory, ty, 5.0);
+        add_instance(&mut inventory, other, 1.0);
+        inventory.item_instances[0].value = Some(3.0);
+        inventory.trash(removed);
+        clock.advance(DAY);
+        inventory.use_instance(ty, Some(0.5)).unwrap();
+
+        let stats = inventory.type_stats(ty);
+        assert_eq!(
+            stats,
+            TypeStats {
+                quantity: 2.5,
+                sealed_quantity: 1.0,
+                instance_count: 2,
+                opened_count: 1,
+                soonest_expiry: Some(start() + 2 * DAY),
+                total_value: 3.0,
+                incompatible_units: 0,
+            }
+        );
+        assert_eq!(instance(&inventory, first).quantity, 1.5);
+        let all = inventory.all_type_stats();
+        assert_eq!(all.get(&ty), Some(&stats));
+        assert_eq!(all.get(&other).map(|s| s.instance_count), Some(1));
+    }
+
+    #[test]
+    fn update_types_where_only_changes_matches() {
+        let (mut inventory, _) = inventory_at(start());
+        let milk = add_type(&mut inventory, None, false);
+        let fresh_milk = add_type(&mut inventory, Some(DAY), false);
+        inventory.item_types[1].name = "Fresh milk".to_string();
+        let rice = add_type(&mut inventory, None, false);
+        inventory.item_types[2].name = "rice".to_string();
+
+        let update = TypeUpdate {
+            ttl: Some(Some(5 * DAY)),
+            opened_by_default: Some(true),
+            ..TypeUpdate::default()
+        };
+        let filter = TypeQuery::new()
+            .name_contains("MILK")
+            .has_ttl(false)
+            .limit(0);
+        assert_eq!(inventory.update_types_where(&filter, &update), vec![milk]);
+        let ttls = inventory
+            .item_types
+            .iter()
+            .map(|t| t.ttl)
+            .collect::<Vec<_>>();
+        assert_eq!(ttls, vec![Some(5 * DAY), Some(DAY), None]);
+        assert!(inventory.item_types[0].opened_by_default);
+        assert!(!inventory.item_types[1].opened_by_default);
+
+        let update = TypeUpdate {
+            minimum_quantity: Some(2.0),
+            ..TypeUpdate::default()
+        };
+        let ids = inventory.update_types_where(&TypeQuery::new(), &update);
+        assert_eq!(ids, vec![milk, fresh_milk, rice]);
+        assert_eq!(inventory.item_types[1].ttl, Some(DAY));
+        assert!(inventory
+            .item_types
+            .iter()
+            .all(|t| t.minimum_quantity == 2.0));
+    }
+
+    #[test]
+    fn recompute_expiries_follows_ttl_changes() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(DAY), false);
+        let opened = add_instance(&mut inventory, ty, 1.0);
+        let explicit = add_instance(&mut inventory, ty, 1.0);
+        clock.advance(DAY);
+        inventory.use_instance(ty, Some(0.5)).unwrap();
+        inventory.item_instances[1].expires_at = Some(start() + 30 * DAY);
+        assert_eq!(
+            instance(&inventory, opened).expires_at,
+            Some(start() + 2 * DAY)
+        );
+        inventory.item_types[0].ttl = Some(7 * DAY);
+
+        let mut dry_run = inventory.clone();
+        let changes = dry_run
+            .recompute_expiries(
+                ty,
+                RecomputePolicy {
+                    only_opened: true,
+                    force: true,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![(opened, Some(start() + 2 * DAY), Some(start() + 8 * DAY))]
+        );
+
+        let changes = inventory
+            .recompute_expiries(ty, RecomputePolicy::default())
+            .unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            instance(&inventory, explicit).expires_at,
+            Some(start() + 30 * DAY)
+        );
+        let changes = inventory
+            .recompute_expiries(
+                ty,
+                RecomputePolicy {
+                    only_opened: false,
+                    force: true,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec![(explicit, Some(start() + 30 * DAY), Some(start() + 7 * DAY))]
+        );
+        inventory.item_types[0].ttl = None;
+        inventory.item_instances[1].expires_at = Some(start() + 30 * DAY);
+        let changes = inventory
+            .recompute_expiries(
+                ty,
+                RecomputePolicy {
+                    only_opened: false,
+                    force: true,
+                },
+            )
+            .unwrap();
+        assert_eq!(changes, vec![(opened, Some(start() + 8 * DAY), None)]);
+        assert_eq!(
+            instance(&inventory, explicit).expires_at,
+            Some(start() + 30 * DAY)
+        );
+        assert!(inventory
+            .recompute_expiries(TypeId(ty.0 + 1), RecomputePolicy::default())
+            .is_err());
+    }
+
+    fn set_state(
+        inventory: &mut Inventory,
+        id: InstanceId,
+        opened: bool,
+        expires_in: Option<u32>,
+        quantity: f32,
+    ) {
+        let ii = inventory
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == id)
+            .unwrap();
+        ii.opened_at = if opened { Some(start()) } else { None };
+        ii.expires_at = expires_in.map(|days| start() + days * DAY);
+        ii.quantity = quantity;
+    }
+
+    fn picked(inventory: &Inventory, ty: TypeId) -> Option<InstanceId> {
+        inventory.pick_use_target(ty).map(|ii| ii.id)
+    }
+
+    #[test]
+    fn pick_use_target_prefers_opened_then_soonest_expiry() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        assert_eq!(picked(&inventory, ty), None);
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        let c = add_instance(&mut inventory, ty, 1.0);
+
+        // Nothing opened, nothing expiring: lowest id.
+        assert_eq!(picked(&inventory, ty), Some(a));
+        // Nothing opened: soonest expiry, even with a higher quantity.
+        set_state(&mut inventory, a, false, Some(5), 0.5);
+        set_state(&mut inventory, b, false, Some(2), 3.0);
+        set_state(&mut inventory, c, false, None, 0.1);
+        assert_eq!(picked(&inventory, ty), Some(b));
+        // An opened instance wins over an unopened one expiring sooner.
+        set_state(&mut inventory, c, true, Some(9), 1.0);
+        assert_eq!(picked(&inventory, ty), Some(c));
+        // Two opened: soonest expiry.
+        set_state(&mut inventory, a, true, Some(5), 1.0);
+        assert_eq!(picked(&inventory, ty), Some(a));
+        // Two opened with the same expiry: lowest quantity.
+        set_state(&mut inventory, c, true, Some(5), 0.2);
+        assert_eq!(picked(&inventory, ty), Some(c));
+        // Opened with an expiry before opened without one.
+        set_state(&mut inventory, a, true, None, 0.1);
+        assert_eq!(picked(&inventory, ty), Some(c));
+        // Same expiry and quantity: lowest id.
+        set_state(&mut inventory, a, true, Some(5), 0.2);
+        assert_eq!(picked(&inventory, ty), Some(a));
+        // Removed instances are never picked.
+        inventory.trash(a);
+        inventory.trash(c);
+        assert_eq!(picked(&inventory, ty), Some(b));
+    }
+
+    #[test]
+    fn same_day_expiries_are_used_in_the_same_order_whatever_the_storage_order() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        // A six-pack added in two trips, one of them without an addition date, and a bottle
+        // expiring sooner.
+        let pack = (0..6)
+            .map(|_| add_instance(&mut inventory, ty, 1.0))
+            .collect::<Vec<_>>();
+        let bottle = add_instance(&mut inventory, ty, 1.0);
+        for (i, id) in pack.iter().enumerate() {
+            set_state(&mut inventory, *id, false, Some(7), 1.0);
+            let ii = inventory.item_instances.iter_mut().find(|ii| ii.id == *id);
+            ii.unwrap().added_at = match i {
+                0 => None,
+                1 | 4 => Some(start() + DAY),
+                _ => Some(start()),
+            };
+        }
+        set_state(&mut inventory, bottle, false, Some(3), 1.0);
+
+        let consumption = |inventory: &Inventory| {
+            let mut inventory = inventory.clone();
+            let mut order = vec![];
+            while let Some(id) = picked(&inventory, ty) {
+                order.push(id);
+                inventory.use_instance(ty, Some(1.0)).unwrap();
+            }
+            order
+        };
+        let expected = vec![bottle, pack[2], pack[3], pack[5], pack[1], pack[4], pack[0]];
+        assert_eq!(consumption(&inventory), expected);
+        for shuffle in 1..inventory.item_instances.len() {
+            let mut shuffled = inventory.clone();
+            shuffled.item_instances.rotate_left(shuffle);
+            if shuffle % 2 == 0 {
+                shuffled.item_instances.reverse();
+            }
+            assert_eq!(consumption(&shuffled), expected);
+            let mut listed = shuffled.item_instances.iter().collect::<Vec<_>>();
+            listed.sort_by(|a, b| a.cmp_expiry(b));
+            assert_eq!(listed.iter().map(|ii| ii.id).collect::<Vec<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn use_spills_over_in_pick_order() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        let c = add_instance(&mut inventory, ty, 1.0);
+        set_state(&mut inventory, a, false, Some(9), 1.0);
+        set_state(&mut inventory, b, true, Some(5), 0.5);
+        set_state(&mut inventory, c, false, Some(3), 1.0);
+        inventory.use_instance(ty, Some(1.0)).unwrap();
+        assert!(instance(&inventory, b).removed_at.is_some());
+        assert_eq!(instance(&inventory, c).quantity, 0.5);
+        assert_eq!(instance(&inventory, a).quantity, 1.0);
+    }
+
+    #[test]
+    fn spillover_drains_opened_instances_before_opening_sealed_ones() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let first = add_instance(&mut inventory, ty, 1.0);
+        let second = add_instance(&mut inventory, ty, 1.0);
+        let sealed = add_instance(&mut inventory, ty, 1.0);
+        // The sealed one expires first and the second opened one last.
+        set_state(&mut inventory, first, true, Some(5), 0.5);
+        set_state(&mut inventory, second, true, Some(9), 0.25);
+        set_state(&mut inventory, sealed, false, Some(2), 1.0);
+
+        let report = inventory.use_instance(ty, Some(1.0)).unwrap();
+        assert_eq!(report.used, 1.0);
+        let hops = report
+            .hops
+            .iter()
+            .map(|hop| (hop.id, hop.used, hop.opened, hop.emptied))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hops,
+            vec![
+                (first, 0.5, false, true),
+                (second, 0.25, false, true),
+                (sealed, 0.25, true, false),
+            ]
+        );
+        assert!(instance(&inventory, second).removed_at.is_some());
+        assert_eq!(instance(&inventory, sealed).quantity, 0.75);
+        assert!(report.hops[..2].iter().all(|hop| hop.trashed));
+
+        // A use not reaching the next item instance has a single hop.
+        let report = inventory.use_instance(ty, Some(0.5)).unwrap();
+        assert_eq!(report.hops.len(), 1);
+        assert!(!report.hops[0].opened);
+    }
+
+    #[test]
+    fn integer_only_types_refuse_fractional_quantities() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let eggs = add_instance(&mut inventory, ty, 2.5);
+        let carton = add_instance(&mut inventory, ty, 6.0);
+        inventory.item_types[0].integer_only = true;
+        assert_eq!(inventory.fractional_quantities(), vec![eggs]);
+
+        let fractional = ItemInstance {
+            item_type: ty,
+            quantity: 1.5,
+            ..ItemInstance::default()
+        };
+        assert_eq!(
+            inventory.add_item_instance(fractional),
+            Err(InventoryError::FractionalQuantityNotAllowed)
+        );
+        assert_eq!(
+            inventory.set_quantity(eggs, 1.5),
+            Err(InventoryError::FractionalQuantityNotAllowed)
+        );
+        assert_eq!(
+            inventory.set_quantity(eggs, -1.0),
+            Err(InventoryError::InvalidQuantity)
+        );
+        assert_eq!(inventory.set_quantity(eggs, 2.0), Ok(()));
+        assert!(inventory.fractional_quantities().is_empty());
+
+        let before = inventory.item_instances.clone();
+        assert_eq!(
+            inventory.use_instance(ty, Some(0.5)),
+            Err(InventoryError::FractionalQuantityNotAllowed)
+        );
+        assert_eq!(
+            inventory.use_allowing_overdraft(ty, Some(20.5)),
+            Err(InventoryError::FractionalQuantityNotAllowed)
+        );
+        assert_eq!(
+            inventory.split_instance(eggs, 0.5),
+            Err(InventoryError::FractionalQuantityNotAllowed)
+        );
+        assert_eq!(inventory.item_instances, before);
+
+        // Rounding errors still count as whole quantities.
+        assert_eq!(
+            inventory
+                .use_instance(ty, Some(0.1 + 0.2 + 0.7))
+                .map(|r| r.used),
+            Ok(1.0)
+        );
+        assert!(inventory.split_instance(carton, 2.0).is_ok());
+    }
+
+    #[test]
+    fn overdrafts_are_recorded_and_flag_a_recount() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        inventory.item_types[0].minimum_quantity = 2.0;
+        add_instance(&mut inventory, ty, 1.0);
+        add_instance(&mut inventory, ty, 0.5);
+        inventory.item_instances[1].location = Some("pantry".to_string());
+        inventory.item_types[0].set_minimum_at("pantry", 1.0);
+        assert_eq!(
+            inventory.use_allowing_overdraft(ty, Some(0.5)),
+            Ok((0.5, None))
+        );
+        assert!(inventory.recount_requested().is_empty());
+
+        clock.advance(DAY);
+        let (used, overdraft) = inventory.use_allowing_overdraft(ty, Some(1.5)).unwrap();
+        assert_eq!(used, 1.0);
+        let overdraft = overdraft.unwrap();
+        let ii = instance(&inventory, overdraft);
+        assert_eq!((ii.quantity, ii.initial_quantity), (0.0, Some(0.5)));
+        assert_eq!(ii.removal_reason.as_deref(), Some("used"));
+        assert!(inventory.item_instances.iter().all(|ii| ii.quantity >= 0.0));
+        assert_eq!(inventory.recount_requested()[0].id, ty);
+        assert_eq!(
+            inventory.item_types[0].recount_requested_at,
+            Some(start() + DAY)
+        );
+        assert!(inventory.waste_by_month(start() + DAY, 1).is_empty());
+
+        // Without any stock, the whole quantity is recorded and the flag keeps its time.
+        clock.advance(DAY);
+        let (used, overdraft) = inventory.use_allowing_overdraft(ty, None).unwrap();
+        assert_eq!(used, 0.0);
+        assert_eq!(
+            instance(&inventory, overdraft.unwrap()).initial_quantity,
+            Some(1.0)
+        );
+        assert_eq!(
+            inventory.item_types[0].recount_requested_at,
+            Some(start() + DAY)
+        );
+        assert_eq!(
+            inventory.use_allowing_overdraft(ty, Some(-1.0)),
+            Err(InventoryError::InvalidQuantity)
+        );
+        assert_eq!(
+            inventory.use_allowing_overdraft(TypeId(ty.0 + 1), None),
+            Err(InventoryError::UnknownItemType)
+        );
+
+        // Flagged item types count as empty for their minimums until they are counted.
+        add_instance(&mut inventory, ty, 5.0);
+        let shortages = inventory.shortages();
+        assert_eq!(shortages.len(), 2);
+        assert!(shortages.iter().all(|s| s.quantity == 0.0));
+        inventory.item_types[0].recount_requested_at = None;
+        let shortages = inventory.shortages();
+        assert_eq!(shortages.len(), 1);
+        assert_eq!(shortages[0].location.as_deref(), Some("pantry"));
+    }
+
+    #[test]
+    fn draining_exactly_to_zero_trashes() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        inventory.use_instance(ty, Some(1.0)).unwrap();
+        assert_eq!(instance(&inventory, a).quantity, 0.0);
+        assert!(instance(&inventory, a).removed_at.is_some());
+        assert_eq!(instance(&inventory, b).quantity, 1.0);
+        assert!(instance(&inventory, b).opened_at.is_none());
+    }
+
+    #[test]
+    fn overshooting_trashes_and_spills_over() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        inventory.use_instance(ty, Some(1.5)).unwrap();
+        assert!(instance(&inventory, a).removed_at.is_some());
+        assert_eq!(instance(&inventory, b).quantity, 0.5);
+        assert!(instance(&inventory, b).removed_at.is_none());
+    }
+
+    #[test]
+    fn quantities_are_summed_in_the_unit_of_the_type() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        inventory.item_types[0].unit = Some("kg".to_string());
+        inventory.item_types[0].minimum_quantity = 2.0;
+        let add = |inventory: &mut Inventory, quantity: f32, unit: Option<&str>| {
+            inventory.add_item_instance(ItemInstance {
+                item_type: ty,
+                quantity,
+                unit_override: unit.map(|u| u.to_string()),
+                ..ItemInstance::default()
+            })
+        };
+        let grams = add(&mut inventory, 500.0, Some("g")).unwrap();
+        let same = add(&mut inventory, 1.0, Some("KG")).unwrap();
+        assert_eq!(
+            instance(&inventory, grams).unit_override.as_deref(),
+            Some("g")
+        );
+        assert_eq!(instance(&inventory, same).unit_override, None);
+        assert_eq!(inventory.quantity_for_type(ty), 1.5);
+        assert_eq!(inventory.shortages()[0].quantity, 1.5);
+        assert_eq!(inventory.type_stats(ty).quantity, 1.5);
+        assert_eq!(
+            add(&mut inventory, 1.0, Some("l")),
+            Err(InventoryError::IncompatibleUnits {
+                instance_ids: vec![]
+            })
+        );
+        assert_eq!(inventory.validate(), Ok(()));
+
+        // Changing the unit of the type afterwards leaves the item instance out of the sums.
+        inventory.item_types[0].unit = Some("l".to_string());
+        assert_eq!(
+            inventory.validate(),
+            Err(InventoryError::IncompatibleUnits {
+                instance_ids: vec![grams]
+            })
+        );
+        assert_eq!(inventory.quantity_for_type(ty), 1.0);
+        assert_eq!(inventory.type_stats(ty).incompatible_units, 1);
+        inventory.item_types[0].unit = None;
+        assert_eq!(
+            instance(&inventory, grams).normalized_quantity(Some(&inventory.item_types[0])),
+            None
+        );
+        inventory.trash(grams);
+        assert_eq!(inventory.validate(), Ok(()));
+    }
+
+    #[test]
+    fn deleted_types_can_reassign_their_instances() {
+        let (mut inventory, _) = inventory_at(start());
+        let (kept, duplicate, empty) = (
+            add_type(&mut inventory, None, false),
+            add_type(&mut inventory, None, false),
+            add_type(&mut inventory, None, false),
+        );
+        let first = add_instance(&mut inventory, duplicate, 1.0);
+        let trashed = add_instance(&mut inventory, duplicate, 2.0);
+        inventory.trash(trashed);
+        add_instance(&mut inventory, kept, 3.0);
+
+        assert_eq!(
+            inventory.delete_type_reassigning(duplicate, duplicate),
+            Err(InventoryError::SameItemType)
+        );
+        assert_eq!(
+            inventory.delete_type_reassigning(duplicate, TypeId(42)),
+            Err(InventoryError::UnknownItemType)
+        );
+        assert_eq!(inventory.item_types.len(), 3);
+
+        assert_eq!(inventory.delete_type_reassigning(duplicate, kept), Ok(2));
+        assert_eq!(instance(&inventory, first).item_type, kept);
+        assert_eq!(instance(&inventory, trashed).item_type, kept);
+        assert_eq!(inventory.type_stats(kept).quantity, 4.0);
+        assert!(inventory.item_types.iter().all(|t| t.id != duplicate));
+
+        assert_eq!(inventory.delete_type_reassigning(empty, kept), Ok(0));
+        assert_eq!(inventory.item_types.len(), 1);
+        assert_eq!(
+            inventory.add_item_type(ItemType::default()),
+            TypeId(empty.0 + 1)
+        );
+    }
+
+    #[test]
+    fn reassigned_instances_keep_their_unit() {
+        let (mut inventory, _) = inventory_at(start());
+        let kilos = add_type(&mut inventory, None, false);
+        inventory.item_types[0].unit = Some("kg".to_string());
+        let grams = add_type(&mut inventory, None, false);
+        inventory.item_types[1].unit = Some("g".to_string());
+        let liters = add_type(&mut inventory, None, false);
+        inventory.item_types[2].unit = Some("l".to_string());
+        let rice = add_instance(&mut inventory, grams, 500.0);
+        let milk = add_instance(&mut inventory, liters, 1.0);
+
+        assert_eq!(
+            inventory.delete_type_reassigning(liters, kilos),
+            Err(InventoryError::IncompatibleUnits {
+                instance_ids: vec![milk]
+            })
+        );
+        assert_eq!(instance(&inventory, milk).item_type, liters);
+
+        assert_eq!(inventory.delete_type_reassigning(grams, kilos), Ok(1));
+        assert_eq!(
+            instance(&inventory, rice).unit_override.as_deref(),
+            Some("g")
+        );
+        assert_eq!(inventory.type_stats(kilos).quantity, 0.5);
+    }
+
+    #[test]
+    fn compacted_ids_are_sequential_and_keep_references() {
+        let (mut inventory, _) = inventory_at(start());
+        for _ in 0..4 {
+            add_type(&mut inventory, None, false);
+        }
+        inventory.delete_item_type(TypeId(1));
+        inventory.delete_item_type(TypeId(3));
+        let (a, b) = (2, 4);
+        let instances = [a, b, b, a, b]
+            .iter()
+            .map(|t| add_instance(&mut inventory, TypeId(*t), 1.0))
+            .collect::<Vec<_>>();
+        inventory.trash(instances[0]);
+        inventory.delete_item_instance(instances[1], None).unwrap();
+        inventory.item_instances.retain(|ii| ii.id != instances[2]);
+        let before = inventory.clone();
+
+        let changes = inventory.compact_ids(true, true, false).unwrap();
+        assert_eq!(
+            changes.types,
+            vec![(TypeId(2), TypeId(1)), (TypeId(4), TypeId(2))]
+        );
+        assert_eq!(
+            changes.instances,
+            vec![
+                (InstanceId(4), InstanceId(3)),
+                (InstanceId(5), InstanceId(4))
+            ]
+        );
+        assert_eq!(inventory.validate(), Ok(()));
+        assert_eq!(inventory.next_ids(), (TypeId(3), InstanceId(5)));
+        // Every item instance refers to the same item type as before.
+        let type_of = |inv: &Inventory, ii: &ItemInstance| {
+            inv.item_types
+                .iter()
+                .find(|t| t.id == ii.item_type)
+                .unwrap()
+                .clone()
+        };
+        for (old, new) in before.item_instances.iter().zip(&inventory.item_instances) {
+            assert_eq!(new.label, old.label);
+            assert_eq!(type_of(&inventory, new), {
+                let mut t = type_of(&before, old);
+                t.id = new.item_type;
+                t
+            });
+        }
+        // Every id is 1 to n, and every changed one is in the mapping.
+        let ids = inventory
+            .item_instances
+            .iter()
+            .map(|ii| ii.id.0)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        for (old, new) in before.item_instances.iter().zip(&inventory.item_instances) {
+            if old.id != new.id {
+                assert!(changes.instances.contains(&(old.id, new.id)));
+            }
+        }
+        assert!(inventory.compact_ids(true, true, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn compact_strings_prevent_compacting_their_ids() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let first = add_instance(&mut inventory, ty, 1.0);
+        let second = add_instance(&mut inventory, ty, 1.0);
+        inventory.item_instances.retain(|ii| ii.id != first);
+        inventory.issue_compact(second, "home").unwrap();
+        inventory.item_types[0].id = TypeId(7);
+        inventory.item_instances[0].item_type = TypeId(7);
+        let before = inventory.clone();
+
+        assert_eq!(
+            inventory.compact_ids(true, true, false),
+            Err(InventoryError::CompactStringsIssued {
+                instance_ids: vec![second]
+            })
+        );
+        assert_eq!(inventory.item_types, before.item_types);
+        assert_eq!(inventory.item_instances, before.item_instances);
+        // The compact strings only name the item type, which can be renumbered.
+        assert_eq!(
+            inventory.compact_ids(true, false, false).unwrap().types,
+            vec![(TypeId(7), TypeId(1))]
+        );
+        let changes = inventory.compact_ids(false, true, true).unwrap();
+        assert_eq!(changes.instances, vec![(second, InstanceId(1))]);
+        assert!(!inventory.item_instances[0].compact_issued);
+    }
+
+    #[test]
+    fn quantities_by_type_match_quantity_for_type() {
+        let (mut inventory, _) = inventory_at(start());
+        let milk = add_type(&mut inventory, None, false);
+        let unused = add_type(&mut inventory, None, false);
+        add_instance(&mut inventory, milk, 1.5);
+        let trashed = add_instance(&mut inventory, milk, 2.0);
+        inventory.trash(trashed);
+        add_instance(&mut inventory, milk, 0.25);
+        let quantities = inventory.quantities_by_type();
+        assert_eq!(
+            quantities.get(&milk),
+            Some(&inventory.quantity_for_type(milk))
+        );
+        assert_eq!(quantities.get(&milk), Some(&1.75));
+        assert_eq!(quantities.get(&unused), None);
+    }
+
+    /// Run with `cargo test -- --ignored` to compare a listing of the quantities of every item
+    /// type with one `quantity_for_type` per item type, on 10000 item instances.
+    #[test]
+    #[ignore]
+    fn quantities_of_every_type_in_a_single_pass() {
+        let (mut inventory, _) = inventory_at(start());
+        let types = (0..1000)
+            .map(|_| add_type(&mut inventory, None, false))
+            .collect::<Vec<_>>();
+        for i in 0..10_000 {
+            inventory.item_instances.push(ItemInstance {
+                item_type: types[i % types.len()],
+                quantity: 1.0,
+                ..ItemInstance::default()
+            });
+        }
+
+        let timer = std::time::Instant::now();
+        let per_type = types
+            .iter()
+            .map(|t| inventory.quantity_for_type(*t))
+            .collect::<Vec<_>>();
+        let per_type_time = timer.elapsed();
+        let timer = std::time::Instant::now();
+        let quantities = inventory.quantities_by_type();
+        let single_pass = types.iter().map(|t| quantities[t]).collect::<Vec<_>>();
+        let single_pass_time = timer.elapsed();
+        let timer = std::time::Instant::now();
+        assert!(inventory.shortages().is_empty());
+        let shortages_time = timer.elapsed();
+
+        println!(
+            "quantity_for_type per type: {:?}, quantities_by_type: {:?}, shortages: {:?}",
+            per_type_time, single_pass_time, shortages_time
+        );
+        assert_eq!(per_type, single_pass);
+        assert!(single_pass_time < per_type_time);
+    }
+
+    #[test]
+    fn ids_of_deleted_items_are_not_given_again() {
+        let (mut inventory, _) = inventory_at(start());
+        add_type(&mut inventory, None, false);
+        let ty = add_type(&mut inventory, None, false);
+        add_instance(&mut inventory, ty, 1.0);
+        assert_eq!(inventory.next_ids(), (TypeId(3), InstanceId(2)));
+        inventory.delete_item_type(ty);
+        assert_eq!(inventory.next_ids(), (TypeId(3), InstanceId(2)));
+        assert_eq!(add_type(&mut inventory, None, false), TypeId(3));
+
+        let mut restored = Inventory::new(inventory.item_types.clone(), vec![]);
+        assert_eq!(restored.next_ids(), (TypeId(4), InstanceId(1)));
+        restored.set_next_ids(TypeId(10), InstanceId(0));
+        assert_eq!(restored.next_ids(), (TypeId(10), InstanceId(1)));
+    }
+
+    #[test]
+    fn use_converts_to_the_unit_of_the_instance() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        inventory.item_types[0].unit = Some("kg".to_string());
+        let grams = inventory
+            .add_item_instance(ItemInstance {
+                item_type: ty,
+                quantity: 500.0,
+                unit_override: Some("g".to_string()),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        let kilos = add_instance(&mut inventory, ty, 1.0);
+        assert_eq!(
+            inventory.use_instance(ty, Some(0.25)).map(|r| r.used),
+            Ok(0.25)
+        );
+        assert_eq!(instance(&inventory, grams).quantity, 250.0);
+        assert_eq!(
+            inventory.use_instance(ty, Some(0.5)).map(|r| r.used),
+            Ok(0.5)
+        );
+        assert!(instance(&inventory, grams).removed_at.is_some());
+        assert_eq!(instance(&inventory, kilos).quantity, 0.75);
+    }
+
+    #[test]
+    fn use_draws_from_a_chain_of_instances() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let ids = (0..4)
+            .map(|_| add_instance(&mut inventory, ty, 0.3))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inventory.use_instance(ty, Some(0.7)).map(|r| r.used),
+            Ok(0.7)
+        );
+        for id in &ids[..2] {
+            assert!(instance(&inventory, *id).removed_at.is_some());
+        }
+        assert!((instance(&inventory, ids[2]).quantity - 0.2).abs() < 1e-6);
+        assert!(instance(&inventory, ids[2]).opened_at.is_some());
+        assert_eq!(instance(&inventory, ids[3]).quantity, 0.3);
+        assert!(instance(&inventory, ids[3]).opened_at.is_none());
+
+        // Asking for more than is left uses everything and reports how much that was.
+        let consumed = inventory.use_instance(ty, Some(5.0)).unwrap().used;
+        assert!((consumed - 0.5).abs() < 1e-6);
+        assert!(ids
+            .iter()
+            .all(|id| instance(&inventory, *id).removed_at.is_some()));
+    }
+
+    #[test]
+    fn rounding_errors_do_not_leave_crumbs_or_open_more() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, ty, 0.3);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        // 0.1 + 0.2 is slightly more than 0.3 in floats.
+        inventory.use_instance(ty, Some(0.1)).unwrap();
+        inventory.use_instance(ty, Some(0.2)).unwrap();
+        assert_eq!(instance(&inventory, a).quantity, 0.0);
+        assert!(instance(&inventory, a).removed_at.is_some());
+        assert!(instance(&inventory, b).opened_at.is_none());
+
+        // Just above the boundary, the next item instance is not opened for the crumb.
+        let c = add_instance(&mut inventory, ty, 0.5);
+        set_state(&mut inventory, c, false, Some(1), 0.5);
+        let consumed = inventory
+            .use_instance(ty, Some(0.5 + QUANTITY_EPSILON / 2.0))
+            .unwrap()
+            .used;
+        assert_eq!(consumed, 0.5);
+        assert!(instance(&inventory, c).removed_at.is_some());
+        assert!(instance(&inventory, b).opened_at.is_none());
+        assert_eq!(instance(&inventory, b).quantity, 1.0);
+    }
+
+    #[test]
+    fn requests_smaller_than_epsilon_are_used_as_is() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let tiny = QUANTITY_EPSILON / 4.0;
+        assert_eq!(
+            inventory.use_instance(ty, Some(tiny)).map(|r| r.used),
+            Ok(tiny)
+        );
+        assert_eq!(instance(&inventory, a).quantity, 1.0 - tiny);
+        assert!(instance(&inventory, a).opened_at.is_some());
+        assert_eq!(
+            inventory.use_instance(ty, Some(0.0)).map(|r| r.used),
+            Ok(0.0)
+        );
+        assert_eq!(
+            inventory.use_instance(ty, Some(-1.0)),
+            Err(InventoryError::InvalidQuantity)
+        );
+        assert_eq!(
+            inventory.use_instance(ty, Some(f32::NAN)),
+            Err(InventoryError::InvalidQuantity)
+        );
+    }
+
+    #[test]
+    fn empty_instances_are_kept_without_auto_trash() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        inventory.item_types[0].auto_trash_empty = false;
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        let c = add_instance(&mut inventory, ty, 1.0);
+        inventory.use_instance(ty, Some(1.0)).unwrap();
+        inventory.use_instance(ty, Some(1.5)).unwrap();
+        for id in &[a, b] {
+            assert_eq!(instance(&inventory, *id).quantity, 0.0);
+            assert!(instance(&inventory, *id).removed_at.is_none());
+        }
+        assert_eq!(instance(&inventory, c).quantity, 0.5);
+        assert_eq!(inventory.pick_use_target(ty).map(|ii| ii.id), Some(c));
+
+        assert_eq!(
+            inventory.trash_empty(Some(TypeId(ty.0 + 1))),
+            Vec::<InstanceId>::new()
+        );
+        assert_eq!(inventory.trash_empty(Some(ty)), vec![a, b]);
+        assert!(instance(&inventory, a).removed_at.is_some());
+        assert!(instance(&inventory, c).removed_at.is_none());
+    }
+
+    #[test]
+    fn continuous_instances_are_topped_up_instead_of_trashed() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(Duration::from_secs(3600)), false);
+        let jar = add_instance(&mut inventory, ty, 1.0);
+        inventory.item_instances[0].continuous = true;
+        let bag = add_instance(&mut inventory, ty, 1.0);
+
+        assert_eq!(inventory.use_instance(ty, Some(1.5)).unwrap().used, 1.5);
+        assert_eq!(instance(&inventory, jar).quantity, 0.0);
+        assert!(instance(&inventory, jar).removed_at.is_none());
+        assert!(instance(&inventory, bag).removed_at.is_none());
+        assert_eq!(inventory.quantity_for_type(ty), 0.5);
+        let opened_expiry = start() + Duration::from_secs(3600);
+        assert_eq!(instance(&inventory, jar).expires_at, Some(opened_expiry));
+
+        // An earlier expiry is kept, a later one replaces it.
+        let earlier = start() + Duration::from_secs(60);
+        assert_eq!(inventory.top_up_instance(jar, 2.0, Some(earlier)), Ok(2.0));
+        assert_eq!(instance(&inventory, jar).expires_at, Some(opened_expiry));
+        let later = start() + Duration::from_secs(7200);
+        assert_eq!(inventory.top_up_instance(jar, 0.5, Some(later)), Ok(2.5));
+        assert_eq!(instance(&inventory, jar).expires_at, Some(later));
+        assert_eq!(inventory.quantity_for_type(ty), 3.0);
+
+        // Expired continuous item instances are reported like any other.
+        clock.advance(Duration::from_secs(7200));
+        assert_eq!(
+            instance(&inventory, jar).expiry_state(inventory.now()),
+            ExpiryState::Expired
+        );
+        assert_eq!(inventory.finish_instance(jar), Ok(2.5));
+        assert!(instance(&inventory, jar).removed_at.is_none());
+    }
+
+    #[test]
+    fn only_continuous_instances_in_the_inventory_are_topped_up() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let jar = add_instance(&mut inventory, ty, 1.0);
+        inventory.item_instances[0].continuous = true;
+        let bag = add_instance(&mut inventory, ty, 1.0);
+
+        assert_eq!(
+            inventory.top_up_instance(bag, 1.0, None),
+            Err(InventoryError::NotContinuous)
+        );
+        assert_eq!(
+            inventory.top_up_instance(InstanceId(jar.0 + 10), 1.0, None),
+            Err(InventoryError::UnknownItemInstance)
+        );
+        for quantity in [0.0, -1.0, f32::NAN] {
+            assert_eq!(
+                inventory.top_up_instance(jar, quantity, None),
+                Err(InventoryError::InvalidQuantity)
+            );
+        }
+        inventory.item_types[0].integer_only = true;
+        assert_eq!(
+            inventory.top_up_instance(jar, 0.5, None),
+            Err(InventoryError::FractionalQuantityNotAllowed)
+        );
+        inventory.trash(jar);
+        assert_eq!(
+            inventory.top_up_instance(jar, 1.0, None),
+            Err(InventoryError::FinishedItemInstance)
+        );
+        assert_eq!(instance(&inventory, jar).quantity, 1.0);
+    }
+
+    #[test]
+    fn instances_matching_a_query_are_trashed() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let ids = (0..4)
+            .map(|_| add_instance(&mut inventory, ty, 1.0))
+            .collect::<Vec<_>>();
+        for (i, location) in ["fridge", "Fridge door", "pantry", "fridge"]
+            .iter()
+            .enumerate()
+        {
+            inventory.item_instances[i].location = Some(location.to_string());
+            inventory.item_instances[i].expires_at = Some(start() + DAY * (i as u32 + 1));
+        }
+        inventory.trash(ids[0]);
+        clock.set(start() + DAY * 3);
+
+        let query = InstanceQuery::new()
+            .expired(true)
+            .location_contains("fridge");
+        assert!(query.has_filters());
+        assert!(!InstanceQuery::new().limit(1).has_filters());
+        let removed_at = instance(&inventory, ids[0]).removed_at;
+        assert_eq!(inventory.trash_where(&query, Some("expired")), vec![ids[1]]);
+        assert_eq!(
+            instance(&inventory, ids[1]).removal_reason.as_deref(),
+            Some("expired")
+        );
+        assert_eq!(instance(&inventory, ids[0]).removed_at, removed_at);
+        assert!(instance(&inventory, ids[2]).removed_at.is_none());
+        assert!(instance(&inventory, ids[3]).removed_at.is_none());
+        assert_eq!(
+            inventory.trash_where(&query, None),
+            Vec::<InstanceId>::new()
+        );
+    }
+
+    #[test]
+    fn removed_before_opened_is_detected() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let id = add_instance(&mut inventory, ty, 1.0);
+        clock.advance(2 * DAY);
+        inventory.use_instance(ty, Some(0.5)).unwrap();
+        clock.set(start() + DAY);
+        inventory.delete_item_instance(id, None).unwrap();
+        assert_eq!(
+            instance(&inventory, id).check_timestamps(),
+            Err(TimestampError::RemovedBeforeOpened)
+        );
+    }
+
+    #[test]
+    fn add_use_trash_sequence() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, Some(3 * DAY), false);
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        assert_eq!(inventory.quantity_for_type(ty), 2.0);
+
+        inventory.use_instance(ty, Some(0.25)).unwrap();
+        assert_eq!(instance(&inventory, a).quantity, 0.75);
+        assert_eq!(instance(&inventory, a).opened_at, Some(start()));
+        assert_eq!(instance(&inventory, a).expires_at, Some(start() + 3 * DAY));
+        assert!(instance(&inventory, b).opened_at.is_none());
+
+        // The opened instance keeps being used and keeps its expiry.
+        clock.advance(DAY);
+        inventory.use_instance(ty, Some(0.25)).unwrap();
+        assert_eq!(instance(&inventory, a).quantity, 0.5);
+        assert_eq!(instance(&inventory, a).expires_at, Some(start() + 3 * DAY));
+
+        inventory.trash(b);
+        assert_eq!(instance(&inventory, b).removed_at, Some(start() + DAY));
+        assert_eq!(inventory.quantity_for_type(ty), 0.5);
+
+        // Using more than what is left empties and trashes the last instance.
+        inventory.use_instance(ty, Some(1.0)).unwrap();
+        assert_eq!(instance(&inventory, a).quantity, 0.0);
+        assert_eq!(instance(&inventory, a).removed_at, Some(start() + DAY));
+        assert_eq!(inventory.quantity_for_type(ty), 0.0);
+        assert!(inventory.pick_use_target(ty).is_none());
+        assert_eq!(
+            inventory.use_instance(ty, Some(1.0)).map(|r| r.used),
+            Ok(0.0)
+        );
+    }
+
+    #[test]
+    fn use_defaults_to_one() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let id = add_instance(&mut inventory, ty, 3.0);
+        inventory.use_instance(ty, None).unwrap();
+        assert_eq!(instance(&inventory, id).quantity, 2.0);
+    }
+
+    #[test]
+    fn opening_keeps_the_earliest_expiry() {
+        let (mut inventory, clock) = inventory_at(start());
+        let soon = add_type(&mut inventory, Some(7 * DAY), false);
+        let late = add_type(&mut inventory, Some(7 * DAY), false);
+        let no_ttl = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, soon, 1.0);
+        let b = add_instance(&mut inventory, late, 1.0);
+        let c = add_instance(&mut inventory, no_ttl, 1.0);
+        set_state(&mut inventory, a, false, Some(1), 1.0);
+        set_state(&mut inventory, b, false, Some(30), 1.0);
+        clock.advance(DAY / 2);
+
+        for ty in &[soon, late, no_ttl] {
+            inventory.use_instance(*ty, Some(0.5)).unwrap();
+        }
+        assert_eq!(instance(&inventory, a).expires_at, Some(start() + DAY));
+        assert_eq!(
+            instance(&inventory, b).expires_at,
+            Some(start() + DAY / 2 + 7 * DAY)
+        );
+        assert_eq!(instance(&inventory, c).opened_at, Some(start() + DAY / 2));
+        assert_eq!(instance(&inventory, c).expires_at, None);
+    }
+
+    #[test]
+    fn minimum_quantity_queries() {
+        let (mut inventory, _) = inventory_at(start());
+        let short = add_type(&mut inventory, None, false);
+        let enough = add_type(&mut inventory, None, false);
+        let no_minimum = add_type(&mut inventory, None, false);
+        inventory.item_types[0].minimum_quantity = 2.0;
+        inventory.item_types[1].minimum_quantity = 1.0;
+        add_instance(&mut inventory, short, 1.5);
+        let trashed = add_instance(&mut inventory, short, 5.0);
+        inventory.trash(trashed);
+        add_instance(&mut inventory, enough, 1.0);
+
+        assert_eq!(inventory.quantity_for_type(short), 1.5);
+        assert_eq!(inventory.quantity_for_type(no_minimum), 0.0);
+        let shortages = inventory.shortages();
+        assert_eq!(shortages.len(), 1);
+        assert_eq!(shortages[0].type_id, short);
+        assert_eq!(shortages[0].quantity, 1.5);
+        assert_eq!(shortages[0].minimum, 2.0);
+    }
+
+    #[test]
+    fn ids_are_allocated_after_the_highest_id() {
+        let (mut inventory, _) = inventory_at(start());
+        let a = add_type(&mut inventory, None, false);
+        let b = add_type(&mut inventory, None, false);
+        let c = add_type(&mut inventory, None, false);
+        assert_eq!((a, b, c), (TypeId(1), TypeId(2), TypeId(3)));
+        inventory.delete_item_type(b);
+        assert!(!inventory.has_item_type(b));
+        assert_eq!(add_type(&mut inventory, None, false), TypeId(4));
+
+        let x = add_instance(&mut inventory, a, 1.0);
+        let y = add_instance(&mut inventory, c, 1.0);
+        assert_eq!((x, y), (InstanceId(1), InstanceId(2)));
+        // Removed instances keep their id.
+        inventory.delete_item_instance(y, None).unwrap();
+        inventory.trash(x);
+        assert_eq!(add_instance(&mut inventory, a, 1.0), InstanceId(3));
+
+        // Deleting an item type deletes its instances.
+        inventory.delete_item_type(c);
+        assert!(inventory.item_instances.iter().all(|ii| ii.item_type != c));
+        assert_eq!(
+            inventory.delete_item_instance(y, None),
+            Err(InventoryError::UnknownItemInstance)
+        );
+    }
+
+    #[test]
+    fn orphan_instances_are_reported_instead_of_panicking() {
+        let mut orphan = ItemInstance {
+            item_type: TypeId(7),
+            quantity: 1.0,
+            ..ItemInstance::default()
+        };
+        orphan.id = InstanceId(1);
+        let mut inventory = Inventory::new(vec![], vec![orphan.clone()]);
+        inventory.set_clock(Arc::new(FixedClock::new(start())));
+
+        assert_eq!(
+            inventory.use_instance(TypeId(7), Some(0.5)),
+            Err(InventoryError::UnknownItemType)
+        );
+        assert_eq!(inventory.item_instances, vec![orphan.clone()]);
+        assert_eq!(
+            inventory.get_instances_for_type(TypeId(7)).unwrap_err(),
+            InventoryError::UnknownItemType
+        );
+        assert_eq!(
+            inventory.add_item_instance(orphan).unwrap_err(),
+            InventoryError::UnknownItemType
+        );
+        // Still counted, so that the missing type can be noticed.
+        assert_eq!(inventory.quantity_for_type(TypeId(7)), 1.0);
+        assert_eq!(inventory.orphans(), vec![&inventory.item_instances[0]]);
+        assert_eq!(inventory.type_name(TypeId(7)), "[missing type 7]");
+    }
+
+    #[test]
+    fn finishing_leaves_exactly_nothing() {
+        let (mut inventory, clock) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let id = add_instance(&mut inventory, ty, 1.0);
+        inventory.use_instance(ty, Some(0.1)).unwrap();
+        inventory.use_instance(ty, Some(0.2)).unwrap();
+        clock.advance(DAY);
+
+        let consumed = inventory.finish_instance(id).unwrap();
+        assert!((consumed - 0.7).abs() < 1e-6);
+        let ii = instance(&inventory, id);
+        assert_eq!(ii.quantity, 0.0);
+        assert_eq!(ii.opened_at, Some(start()));
+        assert_eq!(ii.removed_at, Some(start() + DAY));
+        assert_eq!(
+            inventory.finish_instance(id),
+            Err(InventoryError::FinishedItemInstance)
+        );
+        assert_eq!(
+            inventory.finish_instance(InstanceId(id.0 + 1)),
+            Err(InventoryError::UnknownItemInstance)
+        );
+    }
+
+    #[test]
+    fn finishing_keeps_empty_instances_without_auto_trash() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        inventory.item_types[0].auto_trash_empty = false;
+        let id = add_instance(&mut inventory, ty, 2.0);
+        assert_eq!(inventory.finish_instance(id), Ok(2.0));
+        let ii = instance(&inventory, id);
+        assert_eq!(ii.quantity, 0.0);
+        assert_eq!(ii.opened_at, Some(start()));
+        assert!(ii.removed_at.is_none());
+        assert_eq!(
+            inventory.finish_instance(id),
+            Err(InventoryError::FinishedItemInstance)
+        );
+    }
+
+    #[test]
+    fn minimum_can_count_sealed_instances_only() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        inventory.item_types[0].minimum_quantity = 2.0;
+        inventory.item_types[0].set_minimum_at("home", 1.0);
+        let opened = add_instance(&mut inventory, ty, 1.0);
+        let sealed = add_instance(&mut inventory, ty, 1.0);
+        for id in &[opened, sealed] {
+            inventory
+                .item_instances
+                .iter_mut()
+                .find(|ii| ii.id == *id)
+                .unwrap()
+                .location = Some("home".to_string());
+        }
+        inventory.use_instance(ty, Some(0.5)).unwrap();
+        assert_eq!(inventory.quantity_for_type(ty), 1.5);
+        assert_eq!(inventory.sealed_quantity_for_type(ty), 1.0);
+        assert_eq!(inventory.type_stats(ty).sealed_quantity, 1.0);
+
+        // Off: the opened bottle counts, only the global minimum is not met.
+        let shortages = inventory.shortages();
+        assert_eq!(shortages.len(), 1);
+        assert_eq!(shortages[0].quantity, 1.5);
+
+        // On: the last sealed bottle is below both minimums.
+        inventory.item_types[0].minimum_counts_sealed_only = true;
+        inventory.item_types[0].set_minimum_at("home", 1.5);
+        let shortages = inventory.shortages();
+        assert_eq!(
+            shortages
+                .iter()
+                .map(|s| (s.location.as_deref(), s.quantity))
+                .collect::<Vec<_>>(),
+            vec![(None, 1.0), (Some("home"), 1.0)]
+        );
+    }
+
+    #[test]
+    fn expiry_state_distinguishes_best_before_and_use_by() {
+        let mut ii = ItemInstance {
+            item_type: TypeId(1),
+            ..ItemInstance::default()
+        };
+        assert_eq!(ii.expiry_state(start()), ExpiryState::Fresh);
+        ii.best_before_at = Some(start());
+        ii.expires_at = Some(start() + 2 * DAY);
+        assert_eq!(ii.expiry_state(start() - DAY), ExpiryState::Fresh);
+        assert_eq!(ii.expiry_state(start()), ExpiryState::PastBestBefore);
+        assert_eq!(ii.expiry_state(start() + 2 * DAY), ExpiryState::Expired);
+        ii.expires_at = None;
+        assert_eq!(
+            ii.expiry_state(start() + 9 * DAY),
+            ExpiryState::PastBestBefore
+        );
+    }
+
+    #[test]
+    fn shelf_life_runs_from_the_opening_or_the_addition_to_the_expiry() {
+        let mut ii = ItemInstance {
+            item_type: TypeId(1),
+            ..ItemInstance::default()
+        };
+        assert_eq!(ii.shelf_life_fraction(start()), None);
+        ii.expires_at = Some(start() + 4 * DAY);
+        assert_eq!(ii.shelf_life_fraction(start()), None);
+        ii.added_at = Some(start());
+        assert_eq!(ii.shelf_life_fraction(start()), Some(1.0));
+        assert_eq!(ii.shelf_life_fraction(start() + DAY), Some(0.75));
+        assert_eq!(ii.shelf_life_fraction(start() + 9 * DAY), Some(0.0));
+        assert_eq!(ii.shelf_life_fraction(start() - DAY), Some(1.0));
+        ii.opened_at = Some(start() + 2 * DAY);
+        assert_eq!(ii.shelf_life_fraction(start() + 3 * DAY), Some(0.5));
+
+        // Opened at or after its expiry.
+        ii.opened_at = Some(start() + 4 * DAY);
+        assert_eq!(ii.shelf_life_fraction(start() + 5 * DAY), None);
+        ii.opened_at = Some(start() + 6 * DAY);
+        assert_eq!(ii.shelf_life_fraction(start() + 7 * DAY), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn old_item_instances_deserialize_without_best_before() {
+        let json = r#"{"id":1,"item_type":1,"quantity":1.0,"model":null,"serial":null,"extra":null,"location":null,"value":null,"opened_at":null,"expires_at":null,"added_at":null,"removed_at":null}"#;
+        let ii: ItemInstance = serde_json::from_str(json).unwrap();
+        assert_eq!(ii.best_before_at, None);
+        assert_eq!(ii.initial_quantity, None);
+    }
+
+    #[test]
+    fn types_are_resolved_by_id_or_name() {
+        let (mut inventory, _) = inventory_at(start());
+        let mut add_named = |name: &str| {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            })
+        };
+        let milk = add_named("Milk");
+        let oat_milk = add_named("oat milk");
+        let rice = add_named("rice");
+        let brown_rice = add_named("brown rice");
+        assert_eq!(inventory.resolve_type(&rice.to_string()), Ok(rice));
+        assert_eq!(inventory.resolve_type("milk"), Ok(milk));
+        assert_eq!(inventory.resolve_type("oat"), Ok(oat_milk));
+        assert_eq!(
+            inventory.resolve_type("ric"),
+            Err(InventoryError::AmbiguousName {
+                type_ids: vec![rice, brown_rice]
+            })
+        );
+        assert_eq!(
+            inventory.resolve_type("42"),
+            Err(InventoryError::UnknownItemType)
+        );
+    }
+
+    #[test]
+    fn unicode_names_are_resolved_without_case() {
+        let (mut inventory, _) = inventory_at(start());
+        let mut add_named = |name: &str| {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            })
+        };
+        let creme = add_named("Crème fraîche");
+        let miso = add_named("味噌");
+        let white_miso = add_named("白味噌");
+        let strasse = add_named("Straße salt");
+        assert_eq!(inventory.resolve_type("CRÈME FRAÎCHE"), Ok(creme));
+        assert_eq!(inventory.resolve_type("fraî"), Ok(creme));
+        assert_eq!(inventory.resolve_type("味噌"), Ok(miso));
+        assert_eq!(inventory.resolve_type("白"), Ok(white_miso));
+        assert_eq!(inventory.resolve_type("STRASSE"), Ok(strasse));
+        assert_eq!(
+            inventory.resolve_type("creme"),
+            Err(InventoryError::UnknownItemType)
+        );
+        let ids = |types: Vec<&ItemType>| types.iter().map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(inventory.types_matching("味噌", MatchMode::Substring)),
+            vec![miso, white_miso]
+        );
+        assert_eq!(
+            ids(inventory.types_matching("味噌", MatchMode::Prefix)),
+            vec![miso]
+        );
+        assert!(inventory
+            .types_matching("crème", MatchMode::Exact)
+            .is_empty());
+    }
+
+    #[test]
+    fn labels_are_unique_and_resolvable() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = inventory.add_item_type(ItemType {
+            name: "Basmati Rice (1kg)".to_string(),
+            ..ItemType::default()
+        });
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        assert_eq!(
+            instance(&inventory, a).label.as_deref(),
+            Some("basmati-rice-1kg-01")
+        );
+        assert_eq!(
+            instance(&inventory, b).label.as_deref(),
+            Some("basmati-rice-1kg-02")
+        );
+
+        // A taken label bumps the counter.
+        inventory
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == a)
+            .unwrap()
+            .label = Some("basmati-rice-1kg-03".to_string());
+        assert_eq!(inventory.generate_label(ty), "basmati-rice-1kg-01");
+        let c = add_instance(&mut inventory, ty, 1.0);
+        assert_eq!(
+            instance(&inventory, c).label.as_deref(),
+            Some("basmati-rice-1kg-01")
+        );
+
+        assert_eq!(inventory.resolve_instance(&b.to_string()), Ok(b));
+        assert_eq!(inventory.resolve_instance("basmati-rice-1kg-03"), Ok(a));
+        assert_eq!(
+            inventory.resolve_instance("basmati-rice-1kg-09"),
+            Err(InventoryError::UnknownItemInstance)
+        );
+        assert_eq!(
+            inventory.resolve_instance("42"),
+            Err(InventoryError::UnknownItemInstance)
+        );
+        assert_eq!(slug("  --  "), "");
+        assert_eq!(inventory.generate_label(TypeId(ty.0 + 1)), "item-01");
+    }
+
+    #[test]
+    fn missing_labels_are_backfilled() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, ty, 1.0);
+        let b = add_instance(&mut inventory, ty, 1.0);
+        let c = add_instance(&mut inventory, ty, 1.0);
+        inventory.item_instances[0].label = None;
+        inventory.item_instances[2].label = None;
+        assert_eq!(
+            inventory.generate_missing_labels(),
+            vec![(a, "milk-01".to_string()), (c, "milk-03".to_string())]
+        );
+        assert_eq!(instance(&inventory, b).label.as_deref(), Some("milk-02"));
+        assert!(inventory.generate_missing_labels().is_empty());
+    }
+
+    #[test]
+    fn duplicate_ids_are_detected_and_fixed() {
+        let (mut inventory, _) = inventory_at(start());
+        let milk = add_type(&mut inventory, None, false);
+        let a = add_instance(&mut inventory, milk, 1.0);
+        add_instance(&mut inventory, milk, 2.0);
+        assert_eq!(inventory.validate(), Ok(()));
+        assert!(inventory.fix_duplicate_ids().is_empty());
+
+        // As if the files were edited by copy pasting.
+        let mut copy = inventory.item_types[0].clone();
+        copy.name = "oat milk".to_string();
+        inventory.item_types.push(copy);
+        let copy = inventory.item_instances[0].clone();
+        inventory.item_instances.push(copy);
+        inventory
+            .item_instances
+            .push(inventory.item_instances[1].clone());
+        assert_eq!(
+            inventory.validate(),
+            Err(InventoryError::DuplicateIds {
+                type_ids: vec![milk],
+                instance_ids: vec![InstanceId(1), InstanceId(2)],
+            })
+        );
+
+        let fixes = inventory.fix_duplicate_ids();
+        assert_eq!(fixes.types, vec![(milk, TypeId(2))]);
+        assert_eq!(
+            fixes.instances,
+            vec![
+                (InstanceId(1), InstanceId(3)),
+                (InstanceId(2), InstanceId(4))
+            ]
+        );
+        assert_eq!(inventory.validate(), Ok(()));
+        assert_eq!(inventory.item_types[1].name, "oat milk");
+        // The first ones keep their id, and instances keep referring to the first type.
+        assert_eq!(instance(&inventory, a).quantity, 1.0);
+        assert_eq!(instance(&inventory, InstanceId(4)).quantity, 2.0);
+        assert!(inventory
+            .item_instances
+            .iter()
+            .all(|ii| ii.item_type == milk));
+        let labels = inventory
+            .item_instances
+            .iter()
+            .map(|ii| ii.label.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["milk-01", "milk-02", "milk-03", "milk-04"]);
+        assert_eq!(inventory.quantity_for_type(milk), 6.0);
+    }
+
+    #[test]
+    fn invalid_quantities_are_refused() {
+        let (mut inventory, _) = inventory_at(start());
+        let ty = add_type(&mut inventory, None, false);
+        for quantity in &[-1.0, f32::NAN, f32::INFINITY] {
+            let ii = ItemInstance {
+                item_type: ty,
+                quantity: *quantity,
+                ..ItemInstance::default()
+            };
+            assert_eq!(
+                inventory.add_item_instance(ii),
+                Err(InventoryError::InvalidQuantity)
+            );
+        }
+        assert!(inventory.item_instances.is_empty());
+        add_instance(&mut inventory, ty, 0.0);
+    }
+
+    #[test]
+    fn explicit_dates_take_precedence_over_type_defaults() {
+        let explicit_expiry = start() + 30 * DAY;
+        let explicit_opening = start() - DAY;
+        for &opened_by_default in &[false, true] {
+            for &ttl in &[None, Some(2 * DAY)] {
+                for &expires_at in &[None, Some(explicit_expiry)] {
+                    for &opened_at in &[None, Some(explicit_opening)] {
+                        let (mut inventory, _) = inventory_at(start());
+                        let ty = add_type(&mut inventory, ttl, opened_by_default);
+                        let mut ii = ItemInstance {
+                            item_type: ty,
+                            expires_at,
+                            ..ItemInstance::default()
+                        };
+                        ii.opened_at = opened_at;
+                        let id = inventory.add_item_instance(ii).unwrap();
+                        let ii = instance(&inventory, id);
+
+                        let expected_opening = match (opened_at, opened_by_default) {
+                            (Some(explicit), _) => Some(explicit),
+                            (None, true) => Some(start()),
+                            (None, false) => None,
+                        };
+                        let expected_expiry = match (expires_at, expected_opening, ttl) {
+                            (Some(explicit), _, _) => Some(explicit),
+                            (None, Some(opening), Some(ttl)) => Some(opening + ttl),
+                            _ => None,
+                        };
+                        let case = (opened_by_default, ttl, expires_at, opened_at);
+                        assert_eq!(ii.opened_at, expected_opening, "{:?}", case);
+                        assert_eq!(ii.expires_at, expected_expiry, "{:?}", case);
+                        assert_eq!(ii.check_timestamps(), Ok(()), "{:?}", case);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn deposits_are_refunded_once_trashed() {
+        let (mut inventory, clock) = inventory_at(start());
+        let beer = add_type(&mut inventory, None, false);
+        inventory.item_types[0].deposit = Some(0.25);
+        let water = add_type(&mut inventory, None, false);
+        let bottle = add_instance(&mut inventory, beer, 1.0);
+        let other = add_instance(&mut inventory, beer, 1.0);
+        let no_deposit = add_instance(&mut inventory, water, 1.0);
+        assert_eq!(inventory.outstanding_deposits(), 0.5);
+
+        // Active.
+        assert!(inventory.returnables().is_empty());
+        assert_eq!(
+            inventory.return_deposit(bottle),
+            Err(InventoryError::NotReturnable)
+        );
+
+        // Trashed.
+        clock.advance(DAY);
+        inventory.trash(bottle);
+        inventory.trash(no_deposit);
+        let ids = inventory
+            .returnables()
+            .iter()
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![bottle]);
+        assert_eq!(
+            inventory.return_deposit(no_deposit),
+            Err(InventoryError::NotReturnable)
+        );
+
+        // Returned.
+        clock.advance(DAY);
+        assert_eq!(inventory.return_deposit(bottle), Ok(0.25));
+        assert_eq!(
+            instance(&inventory, bottle).returned_at,
+            Some(start() + 2 * DAY)
+        );
+        assert!(inventory.returnables().is_empty());
+        assert_eq!(
+            inventory.return_deposit(bottle),
+            Err(InventoryError::NotReturnable)
+        );
+        assert_eq!(
+            inventory.return_deposit(InstanceId(42)),
+            Err(InventoryError::UnknownItemInstance)
+        );
+        assert_eq!(inventory.outstanding_deposits(), 0.25);
+        assert_eq!(instance(&inventory, other).returned_at, None);
+        inventory.return_deposit(other).unwrap_err();
+        inventory.trash(other);
+        inventory.return_deposit(other).unwrap();
+        assert!(inventory.outstanding_deposits().is_sign_positive());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn files_without_deposits_still_load() {
+        let item_type: ItemType = serde_json::from_str(
+            r#"{"id":1,"name":"beer","minimum_quantity":0.0,"ttl":null,"opened_by_default":false}"#,
+        )
+        .unwrap();
+        assert_eq!(item_type.deposit, None);
+        let instance: ItemInstance = serde_json::from_str(
+            r#"{"id":1,"item_type":1,"quantity":1.0,"model":null,"serial":null,"extra":null,
+            "location":null,"value":null,"opened_at":null,"expires_at":null}"#,
+        )
+        .unwrap();
+        assert_eq!(instance.returned_at, None);
+        let json = serde_json::to_string(&instance).unwrap();
+        let instance: ItemInstance = serde_json::from_str(&json).unwrap();
+        assert_eq!(instance.returned_at, None);
+    }
 }
@@ -0,0 +1,456 @@
+//! Location names that drifted apart, like "Pantry", "pantry " and "pantery", and item
+//! instances away from their home location.
+
+use crate::{fold_case, InstanceId, Inventory, InventoryError, ItemInstance};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A location name, exactly as written, and how much it is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LocationCount {
+    pub location: String,
+    /// The item instances at the location or whose home it is, removed ones included.
+    pub instances: usize,
+    /// The minimums of item types at the location.
+    pub minimums: usize,
+}
+
+impl LocationCount {
+    fn uses(&self) -> usize {
+        self.instances + self.minimums
+    }
+}
+
+/// Location names which seem to be the same place, and the name proposed for all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LocationCluster {
+    pub canonical: String,
+    /// The names to rename to the canonical one, which is not among them.
+    pub variants: Vec<String>,
+}
+
+/// The name without the whitespace at its ends and with single spaces between its words.
+pub fn tidy_location(location: &str) -> String {
+    location.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Whether two location names are probably the same place: they are equal once tidied and
+/// compared without case, or they are a few typos apart. Names of less than 4 letters have
+/// to be equal, names of less than 8 letters can differ by one edit, and longer names by
+/// two. Names whose numbers differ, like "shelf 1" and "shelf 2", are different places.
+pub fn similar_locations(a: &str, b: &str) -> bool {
+    let a = fold_case(&tidy_location(a));
+    let b = fold_case(&tidy_location(b));
+    if a == b {
+        return true;
+    }
+    let digits = |s: &str| s.chars().filter(|c| c.is_ascii_digit()).collect::<String>();
+    if digits(&a) != digits(&b) {
+        return false;
+    }
+    let shortest = a.chars().count().min(b.chars().count());
+    let allowed = match shortest {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    levenshtein(&a, &b) <= allowed
+}
+
+/// The number of characters to insert, delete or replace to turn a text into another.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let replace = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Groups the similar location names. Names are grouped as soon as they are similar to one
+/// of the group. The name proposed for a group is the most used one, tidied, preferring the
+/// tidy names and then the first in alphabetical order. Groups of a single name that is
+/// already tidy are left out.
+pub fn cluster_locations(counts: &[LocationCount]) -> Vec<LocationCluster> {
+    // Union-find over the indices of the names.
+    let mut parents = (0..counts.len()).collect::<Vec<_>>();
+    fn root(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    for i in 0..counts.len() {
+        for j in i + 1..counts.len() {
+            if similar_locations(&counts[i].location, &counts[j].location) {
+                let (ri, rj) = (root(&mut parents, i), root(&mut parents, j));
+                parents[ri.max(rj)] = ri.min(rj);
+            }
+        }
+    }
+    let mut groups = BTreeMap::<usize, Vec<&LocationCount>>::new();
+    for (i, count) in counts.iter().enumerate() {
+        let r = root(&mut parents, i);
+        groups.entry(r).or_default().push(count);
+    }
+    groups
+        .into_values()
+        .filter_map(|group| {
+            let best = group
+                .iter()
+                .min_by(|a, b| {
+                    b.uses()
+                        .cmp(&a.uses())
+                        .then_with(|| {
+                            let tidy = |c: &LocationCount| tidy_location(&c.location) == c.location;
+                            tidy(b).cmp(&tidy(a))
+                        })
+                        .then_with(|| a.location.cmp(&b.location))
+                })
+                .expect("Groups are not empty");
+            let canonical = tidy_location(&best.location);
+            let variants = group
+                .iter()
+                .map(|c| c.location.clone())
+                .filter(|l| *l != canonical)
+                .collect::<Vec<_>>();
+            if variants.is_empty() {
+                None
+            } else {
+                Some(LocationCluster {
+                    canonical,
+                    variants,
+                })
+            }
+        })
+        .collect()
+}
+
+impl ItemInstance {
+    /// Whether the item instance is away from its home location, ignoring case and
+    /// whitespace like `similar_locations`. Without a home location it is never misplaced,
+    /// and with one but no current location it is.
+    pub fn is_misplaced(&self) -> bool {
+        match (&self.home_location, &self.location) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(home), Some(current)) => {
+                fold_case(&tidy_location(home)) != fold_case(&tidy_location(current))
+            }
+        }
+    }
+}
+
+impl Inventory {
+    /// The item instances that are not removed and are away from their home location.
+    pub fn misplaced(&self) -> Vec<&ItemInstance> {
+        self.item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none() && ii.is_misplaced())
+            .collect()
+    }
+
+    /// Moves an item instance back to its home location.
+    pub fn put_back(&mut self, id: InstanceId) -> Result<(), InventoryError> {
+        let ii = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        match &ii.home_location {
+            Some(home) => {
+                ii.location = Some(home.clone());
+                Ok(())
+            }
+            None => Err(InventoryError::NoHomeLocation),
+        }
+    }
+
+    /// The distinct location names of the item instances, current and home, and of the
+    /// minimums of the item types, in alphabetical order.
+    pub fn location_counts(&self) -> Vec<LocationCount> {
+        let mut counts = BTreeMap::<&str, LocationCount>::new();
+        fn entry<'a, 'b>(
+            counts: &'b mut BTreeMap<&'a str, LocationCount>,
+            location: &'a str,
+        ) -> &'b mut LocationCount {
+            counts.entry(location).or_insert_with(|| LocationCount {
+                location: location.to_string(),
+                instances: 0,
+                minimums: 0,
+            })
+        }
+        for ii in &self.item_instances {
+            if let Some(location) = &ii.location {
+                entry(&mut counts, location).instances += 1;
+            }
+            match (&ii.home_location, &ii.location) {
+                (Some(home), Some(location)) if home == location => {}
+                (Some(home), _) => entry(&mut counts, home).instances += 1,
+                (None, _) => {}
+            }
+        }
+        for t in &self.item_types {
+            for (location, _) in &t.minimum_by_location {
+                entry(&mut counts, location).minimums += 1;
+            }
+        }
+        counts.into_values().collect()
+    }
+
+    /// The groups of location names that seem to be the same place, see `cluster_locations`.
+    pub fn location_clusters(&self) -> Vec<LocationCluster> {
+        cluster_locations(&self.location_counts())
+    }
+
+    /// Renames a location, written exactly, in the current and home locations of the item
+    /// instances, removed ones included, and in the minimums of the item types. An item type with minimums at both locations
+    /// keeps the largest. Returns the number of item instances and item types changed.
+    pub fn rename_location(&mut self, from: &str, to: &str) -> usize {
+        if from == to {
+            return 0;
+        }
+        let mut changed = 0;
+        for ii in &mut self.item_instances {
+            let mut renamed = false;
+            for location in [&mut ii.location, &mut ii.home_location] {
+                if location.as_deref() == Some(from) {
+                    *location = Some(to.to_string());
+                    renamed = true;
+                }
+            }
+            if renamed {
+                changed += 1;
+            }
+        }
+        for t in &mut self.item_types {
+            let renamed = t
+                .minimum_by_location
+                .iter()
+                .find(|(l, _)| l == from)
+                .map(|(_, minimum)| *minimum);
+            if let Some(minimum) = renamed {
+                let existing = t
+                    .minimum_by_location
+                    .iter()
+                    .find(|(l, _)| l == to)
+                    .map(|(_, minimum)| *minimum)
+                    .unwrap_or(0.0);
+                t.minimum_by_location.retain(|(l, _)| l != from);
+                t.set_minimum_at(to, minimum.max(existing));
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::InstanceId;
+    use crate::ItemType;
+
+    fn inventory(locations: &[&str]) -> Inventory {
+        let mut inventory = Inventory::default();
+        let rice = inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            ..ItemType::default()
+        });
+        for location in locations {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: rice,
+                    location: Some(location.to_string()),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    #[test]
+    fn distances_count_edits() {
+        assert_eq!(levenshtein("pantry", "pantry"), 0);
+        assert_eq!(levenshtein("pantry", "pantery"), 1);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("cave", "cavé"), 1);
+    }
+
+    #[test]
+    fn typos_case_and_whitespace_are_similar() {
+        assert!(similar_locations("Pantry", "pantry "));
+        assert!(similar_locations("pantry", "pantery"));
+        assert!(similar_locations("living  room", "Living Room"));
+        assert!(similar_locations("basement shelf", "basment shef"));
+        assert!(!similar_locations("box", "bin"));
+        assert!(!similar_locations("shelf 1", "shelf 2"));
+        assert!(!similar_locations("fridge", "freezer"));
+        assert!(!similar_locations("garage", "garden"));
+    }
+
+    #[test]
+    fn similar_locations_are_clustered() {
+        let inventory = inventory(&[
+            "pantry", "pantry", "Pantry", "pantery", "pantry ", "fridge", "Fridge", "Fridge",
+            "shelf 1", "shelf 2",
+        ]);
+        assert_eq!(
+            inventory.location_clusters(),
+            vec![
+                LocationCluster {
+                    canonical: "Fridge".to_string(),
+                    variants: vec!["fridge".to_string()],
+                },
+                LocationCluster {
+                    canonical: "pantry".to_string(),
+                    variants: vec![
+                        "Pantry".to_string(),
+                        "pantery".to_string(),
+                        "pantry ".to_string()
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn canonical_names_are_tidy() {
+        let inventory = inventory(&[" cellar", " cellar", "Cellar", "garage"]);
+        assert_eq!(
+            inventory.location_clusters(),
+            vec![LocationCluster {
+                canonical: "cellar".to_string(),
+                variants: vec![" cellar".to_string(), "Cellar".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn locations_are_counted() {
+        let mut inventory = inventory(&["pantry", "pantry", "Pantry"]);
+        inventory.item_types[0].set_minimum_at("cellar", 1.0);
+        inventory.item_types[0].set_minimum_at("pantry", 2.0);
+        let counts = inventory.location_counts();
+        let summary = counts
+            .iter()
+            .map(|c| (c.location.as_str(), c.instances, c.minimums))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![("Pantry", 1, 0), ("cellar", 0, 1), ("pantry", 2, 1)]
+        );
+    }
+
+    #[test]
+    fn renames_apply_to_instances_and_minimums() {
+        let mut inventory = inventory(&["pantry", "Pantry", "Pantry", "fridge"]);
+        inventory.item_types[0].set_minimum_at("Pantry", 3.0);
+        inventory.item_types[0].set_minimum_at("pantry", 1.0);
+        inventory.trash(InstanceId(3));
+
+        assert_eq!(inventory.rename_location("Pantry", "pantry"), 3);
+        let locations = inventory
+            .item_instances
+            .iter()
+            .map(|ii| ii.location.as_deref().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(locations, vec!["pantry", "pantry", "pantry", "fridge"]);
+        assert_eq!(
+            inventory.item_types[0].minimum_by_location,
+            vec![("pantry".to_string(), 3.0)]
+        );
+        assert_eq!(inventory.rename_location("Pantry", "pantry"), 0);
+        assert_eq!(inventory.rename_location("fridge", "fridge"), 0);
+
+        inventory.item_instances[3].home_location = Some("garage".to_string());
+        inventory.item_instances[2].home_location = Some("garage".to_string());
+        inventory.item_instances[2].location = Some("garage".to_string());
+        assert_eq!(inventory.location_counts()[1].instances, 2);
+        assert_eq!(inventory.rename_location("garage", "Garage"), 2);
+        assert_eq!(
+            inventory.item_instances[3].home_location.as_deref(),
+            Some("Garage")
+        );
+    }
+
+    #[test]
+    fn only_instances_with_a_home_elsewhere_are_misplaced() {
+        let at = |location: Option<&str>, home: Option<&str>| ItemInstance {
+            location: location.map(str::to_string),
+            home_location: home.map(str::to_string),
+            ..ItemInstance::default()
+        };
+        assert!(!at(None, None).is_misplaced());
+        assert!(!at(Some("kitchen"), None).is_misplaced());
+        assert!(!at(Some("garage"), Some("garage")).is_misplaced());
+        assert!(!at(Some("Garage "), Some("garage")).is_misplaced());
+        assert!(at(Some("kitchen"), Some("garage")).is_misplaced());
+        assert!(at(None, Some("garage")).is_misplaced());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn home_locations_default_when_missing() {
+        let ii: ItemInstance = serde_json::from_str(
+            r#"{"id": 7, "item_type": 3, "quantity": 2.5, "model": null, "serial": null,
+                "extra": null, "location": "kitchen", "value": null, "opened_at": null,
+                "expires_at": null, "added_at": null, "removed_at": null}"#,
+        )
+        .unwrap();
+        assert_eq!(ii.home_location, None);
+        assert!(!ii.is_misplaced());
+
+        let ii = ItemInstance {
+            home_location: Some("garage".to_string()),
+            ..ii
+        };
+        let json = serde_json::to_string(&ii).unwrap();
+        assert_eq!(serde_json::from_str::<ItemInstance>(&json).unwrap(), ii);
+    }
+
+    #[test]
+    fn misplaced_instances_are_put_back() {
+        let mut inventory = inventory(&["kitchen", "kitchen", "garage"]);
+        for ii in &mut inventory.item_instances[1..] {
+            ii.home_location = Some("garage".to_string());
+        }
+        let ids = |inventory: &Inventory| {
+            inventory
+                .misplaced()
+                .iter()
+                .map(|ii| ii.id.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&inventory), vec![2]);
+
+        assert_eq!(inventory.put_back(InstanceId(2)), Ok(()));
+        assert_eq!(
+            inventory.item_instances[1].location.as_deref(),
+            Some("garage")
+        );
+        assert!(ids(&inventory).is_empty());
+        assert_eq!(
+            inventory.put_back(InstanceId(1)),
+            Err(InventoryError::NoHomeLocation)
+        );
+        assert_eq!(
+            inventory.put_back(InstanceId(42)),
+            Err(InventoryError::UnknownItemInstance)
+        );
+
+        inventory.item_instances[2].location = Some("kitchen".to_string());
+        inventory.trash(InstanceId(3));
+        assert!(ids(&inventory).is_empty());
+    }
+}
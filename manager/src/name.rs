@@ -0,0 +1,184 @@
+//! How names typed by people are compared to the names of the inventory.
+//!
+//! Names are compared without case, after `fold_case`. Every command designating item types
+//! by name uses one of the `MatchMode`s, see `Inventory::types_matching`.
+
+/// How a text typed to designate something is compared to its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The whole name.
+    Exact,
+    /// Any part of the name.
+    Substring,
+    /// The beginning of the name.
+    Prefix,
+    /// The whole name, where `*` stands for any text and `?` for any single character,
+    /// like `*milk*`.
+    Glob,
+}
+
+impl MatchMode {
+    /// Whether a name matches a text, both compared without case.
+    pub fn matches(self, name: &str, text: &str) -> bool {
+        NamePattern::new(text, self).matches(name)
+    }
+}
+
+/// A text and the way it is compared to names, folded once to be compared to many names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamePattern {
+    folded: String,
+    mode: MatchMode,
+}
+
+impl NamePattern {
+    pub fn new(text: &str, mode: MatchMode) -> Self {
+        NamePattern {
+            folded: fold_case(text),
+            mode,
+        }
+    }
+
+    /// Whether a name matches the text, compared without case.
+    pub fn matches(&self, name: &str) -> bool {
+        let name = fold_case(name);
+        match self.mode {
+            MatchMode::Exact => name == self.folded,
+            MatchMode::Substring => name.contains(&self.folded),
+            MatchMode::Prefix => name.starts_with(&self.folded),
+            MatchMode::Glob => glob_matches(&self.folded, &name),
+        }
+    }
+}
+
+/// Whether a text matches a pattern where `*` stands for any text and `?` for any single
+/// character. After a mismatch, the last `*` is retried one character further, which is
+/// enough since a `*` can always take what the previous ones would have.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // The position after the last `*` and the text position it was tried at.
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, t));
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((after_star, tried)) => {
+                    p = after_star;
+                    t = tried + 1;
+                    star = Some((after_star, t));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Folds the case of a text so that texts differing only by case become equal.
+///
+/// This is the Unicode lowercase mapping, which already handles accented and non-Latin
+/// letters like `È` or `Σ`, plus the full case folding of the characters whose lowercase
+/// is not their folded form: `ß` and `ẞ` become `ss`, the final `ς` becomes `σ`, the long `ſ`
+/// becomes `s` and the Latin ligatures like `ﬁ` are split. The few other characters folding
+/// differently in Unicode's CaseFolding.txt, mostly Cherokee and Greek variants, keep their
+/// lowercase. Texts are not normalized, so an accent written as a combining character,
+/// like `e` followed by U+0301, still differs from the accented letter `é`.
+pub fn fold_case(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            'ß' | 'ẞ' => folded.push_str("ss"),
+            'ς' => folded.push('σ'),
+            'ſ' => folded.push('s'),
+            'ﬀ' => folded.push_str("ff"),
+            'ﬁ' => folded.push_str("fi"),
+            'ﬂ' => folded.push_str("fl"),
+            'ﬃ' => folded.push_str("ffi"),
+            'ﬄ' => folded.push_str("ffl"),
+            'ﬅ' | 'ﬆ' => folded.push_str("st"),
+            c => folded.extend(c.to_lowercase()),
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accents_are_kept_and_case_ignored() {
+        assert_eq!(fold_case("Crème Fraîche"), "crème fraîche");
+        assert!(MatchMode::Exact.matches("Crème fraîche", "CRÈME FRAÎCHE"));
+        assert!(!MatchMode::Exact.matches("Crème fraîche", "creme fraiche"));
+        assert!(MatchMode::Substring.matches("Crème fraîche", "FRAÎ"));
+        assert!(MatchMode::Prefix.matches("Crème fraîche", "crÈ"));
+        assert!(!MatchMode::Prefix.matches("Crème fraîche", "fraîche"));
+        assert!(MatchMode::Exact.matches("ÉCLAIR", "éclair"));
+    }
+
+    #[test]
+    fn non_latin_names_match() {
+        assert_eq!(fold_case("味噌"), "味噌");
+        assert!(MatchMode::Exact.matches("味噌", "味噌"));
+        assert!(MatchMode::Substring.matches("白味噌", "味噌"));
+        assert!(MatchMode::Prefix.matches("味噌汁", "味噌"));
+        assert!(!MatchMode::Prefix.matches("白味噌", "味噌"));
+        assert!(MatchMode::Exact.matches("Молоко", "МОЛОКО"));
+        assert!(MatchMode::Exact.matches("ΟΔΥΣΣΕΥΣ", "Οδυσσευς"));
+    }
+
+    #[test]
+    fn full_case_folding_is_applied() {
+        assert_eq!(fold_case("Straße"), "strasse");
+        assert!(MatchMode::Exact.matches("STRASSE", "straße"));
+        assert!(MatchMode::Exact.matches("ẞ", "ss"));
+        assert!(MatchMode::Substring.matches("oﬃce supplies", "OFFICE"));
+        assert!(MatchMode::Exact.matches("ſoap", "Soap"));
+    }
+
+    #[test]
+    fn globs_match_whole_names() {
+        let cases: &[(&str, &str, bool)] = &[
+            ("*milk*", "Oat milk", true),
+            ("*milk*", "Milk", true),
+            ("*milk*", "mil", false),
+            ("milk*", "oat milk", false),
+            ("*milk", "milk powder", false),
+            ("m?lk", "MILK", true),
+            ("m?lk", "mlk", false),
+            ("*a*b*c", "aXbYbZc", true),
+            ("*a*b*c", "aXbYbZcd", false),
+            ("**", "", true),
+            ("", "", true),
+            ("", "rice", false),
+            ("*味噌*", "白味噌汁", true),
+            ("straße", "STRASSE", true),
+        ];
+        for (pattern, name, matches) in cases {
+            assert_eq!(
+                MatchMode::Glob.matches(name, pattern),
+                *matches,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn empty_texts_match_every_name_but_exactly() {
+        assert!(MatchMode::Substring.matches("rice", ""));
+        assert!(MatchMode::Prefix.matches("rice", ""));
+        assert!(!MatchMode::Exact.matches("rice", ""));
+    }
+}
@@ -0,0 +1,73 @@
+//! Hierarchical names written as a path of segments separated by `/`, like
+//! `Food/Baking/Flour`, used by the categories of item types.
+//!
+//! Segments are compared ignoring case and the whitespace around them, like location names,
+//! and a path is within another when it starts with all of its segments: `Food/Baking` is
+//! within `Food` but not within `Foo`.
+
+use crate::fold_case;
+use crate::location::tidy_location;
+
+/// The separator of the segments.
+pub const SEPARATOR: char = '/';
+
+/// The segments of a path, tidied, without the empty ones.
+pub fn segments(path: &str) -> Vec<String> {
+    path.split(SEPARATOR)
+        .map(tidy_location)
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// The path with its segments tidied and without empty segments, like `Food/Baking` for
+/// ` Food //  Baking/`. Returns `None` when no segment is left.
+pub fn normalize(path: &str) -> Option<String> {
+    let segments = segments(path);
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join(&SEPARATOR.to_string()))
+    }
+}
+
+/// Whether a path is the prefix or one of its descendants, comparing whole segments and
+/// ignoring case. Every path is within the empty prefix.
+pub fn is_within(path: &str, prefix: &str) -> bool {
+    let path = segments(path);
+    let prefix = segments(prefix);
+    prefix.len() <= path.len()
+        && prefix
+            .iter()
+            .zip(&path)
+            .all(|(a, b)| fold_case(a) == fold_case(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_tidied() {
+        assert_eq!(
+            normalize(" Food //  Baking/"),
+            Some("Food/Baking".to_string())
+        );
+        assert_eq!(
+            normalize("Food/Dry  goods"),
+            Some("Food/Dry goods".to_string())
+        );
+        assert_eq!(normalize(" / "), None);
+        assert_eq!(segments("a/b/c"), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn prefixes_match_whole_segments() {
+        assert!(is_within("Food/Baking/Flour", "Food"));
+        assert!(is_within("Food/Baking/Flour", "food/baking "));
+        assert!(is_within("Food/Baking", "Food/Baking"));
+        assert!(is_within("Food", ""));
+        assert!(!is_within("Food/Baking", "Foo"));
+        assert!(!is_within("Food", "Food/Baking"));
+        assert!(!is_within("Drinks/Food", "Food"));
+    }
+}
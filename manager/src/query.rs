@@ -0,0 +1,882 @@
+//! Filtering, sorting and paging of item types and item instances.
+//!
+//! Results are always sorted by the requested key first and by id second,
+//! so that paging through equal keys is stable.
+
+use crate::{
+    fold_case, ExpiryState, FieldFilter, InstanceId, Inventory, ItemInstance, ItemType, MatchMode,
+    NamePattern, TypeId, TypeStats,
+};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// A page of results.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult<T> {
+    /// The items of the requested page.
+    pub items: Vec<T>,
+    /// The number of items matching the filters, before paging.
+    pub total_matching: usize,
+}
+
+/// The keys item instances can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Id,
+    TypeId,
+    Quantity,
+    /// Sorts by location, ignoring case. Instances without a location come last.
+    Location,
+    /// Instances without a value come last.
+    Value,
+    /// Instances without an opening date come last.
+    OpenedAt,
+    /// Instances without an expiry date come last.
+    ExpiresAt,
+    AddedAt,
+}
+
+/// The keys item types can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypeSortKey {
+    #[default]
+    Id,
+    /// Sorts by name, ignoring case.
+    Name,
+    MinimumQuantity,
+}
+
+/// A query over the item instances of an inventory.
+/// Removed instances are excluded unless `include_removed` is set.
+#[derive(Debug, Clone, Default)]
+#[must_use = "a query does nothing until it is run"]
+pub struct InstanceQuery {
+    id: Option<InstanceId>,
+    type_id: Option<TypeId>,
+    type_name: Option<NamePattern>,
+    location: Option<NamePattern>,
+    external_ref: Option<NamePattern>,
+    custom: Vec<FieldFilter>,
+    expired: Option<bool>,
+    opened: Option<bool>,
+    empty: Option<bool>,
+    added_since: Option<SystemTime>,
+    removed: Option<bool>,
+    removed_since: Option<SystemTime>,
+    include_removed: bool,
+    sort: SortKey,
+    descending: bool,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl InstanceQuery {
+    pub fn new() -> Self {
+        InstanceQuery::default()
+    }
+
+    /// Only matches the instance with this id.
+    pub fn id(mut self, id: InstanceId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Only matches instances of this item type.
+    pub fn type_id(mut self, type_id: TypeId) -> Self {
+        self.type_id = Some(type_id);
+        self
+    }
+
+    /// Only matches instances whose item type name contains this text, ignoring case.
+    pub fn type_name_contains(self, name: &str) -> Self {
+        self.type_name_matching(name, MatchMode::Substring)
+    }
+
+    /// Only matches instances whose item type name matches this text, ignoring case.
+    pub fn type_name_matching(mut self, name: &str, mode: MatchMode) -> Self {
+        self.type_name = Some(NamePattern::new(name, mode));
+        self
+    }
+
+    /// Only matches instances whose location contains this text, ignoring case.
+    pub fn location_contains(mut self, location: &str) -> Self {
+        self.location = Some(NamePattern::new(location, MatchMode::Substring));
+        self
+    }
+
+    /// Only matches instances whose external reference contains this text, ignoring case.
+    pub fn external_ref_contains(mut self, external_ref: &str) -> Self {
+        self.external_ref = Some(NamePattern::new(external_ref, MatchMode::Substring));
+        self
+    }
+
+    /// Only matches instances whose custom field passes the filter. Can be given several
+    /// times, all of them having to pass.
+    pub fn custom_field(mut self, filter: FieldFilter) -> Self {
+        self.custom.push(filter);
+        self
+    }
+
+    /// Only matches instances that are past their use-by date, or not.
+    /// Instances without a use-by date are never expired, whatever their best-before date.
+    pub fn expired(mut self, expired: bool) -> Self {
+        self.expired = Some(expired);
+        self
+    }
+
+    /// Only matches instances that are opened, or not opened.
+    pub fn opened(mut self, opened: bool) -> Self {
+        self.opened = Some(opened);
+        self
+    }
+
+    /// Only matches instances that are empty, meaning a quantity of zero or less, or not empty.
+    pub fn empty(mut self, empty: bool) -> Self {
+        self.empty = Some(empty);
+        self
+    }
+
+    /// Only matches instances added at or after this time. Instances without an added time
+    /// never match.
+    pub fn added_since(mut self, since: SystemTime) -> Self {
+        self.added_since = Some(since);
+        self
+    }
+
+    /// Only matches instances that were trashed or deleted, which are then included, or
+    /// that were not.
+    pub fn removed(mut self, removed: bool) -> Self {
+        self.removed = Some(removed);
+        self.include_removed |= removed;
+        self
+    }
+
+    /// Only matches instances removed at or after this time, which are then included.
+    pub fn removed_since(mut self, since: SystemTime) -> Self {
+        self.removed_since = Some(since);
+        self.include_removed = true;
+        self
+    }
+
+    /// Also matches instances that were trashed or deleted.
+    pub fn include_removed(mut self, include_removed: bool) -> Self {
+        self.include_removed = include_removed;
+        self
+    }
+
+    pub fn sort(mut self, sort: SortKey) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Reverses the order of the sort key. Ties are still ordered by ascending id.
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = descending;
+        self
+    }
+
+    /// The maximum number of items returned.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The number of matching items to skip.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Whether the query filters the item instances at all. Sorting, paging and
+    /// `include_removed` are not filters.
+    pub fn has_filters(&self) -> bool {
+        self.id.is_some()
+            || self.type_id.is_some()
+            || self.type_name.is_some()
+            || self.location.is_some()
+            || self.external_ref.is_some()
+            || !self.custom.is_empty()
+            || self.expired.is_some()
+            || self.opened.is_some()
+            || self.empty.is_some()
+            || self.added_since.is_some()
+            || self.removed.is_some()
+            || self.removed_since.is_some()
+    }
+
+    /// Returns whether the instance passes the filters of this query.
+    pub fn matches(&self, inventory: &Inventory, ii: &ItemInstance) -> bool {
+        if !self.include_removed && ii.removed_at.is_some() {
+            return false;
+        }
+        if self.id.map(|id| id != ii.id).unwrap_or(false) {
+            return false;
+        }
+        if self.type_id.map(|t| t != ii.item_type).unwrap_or(false) {
+            return false;
+        }
+        if let Some(name) = &self.type_name {
+            let matching = inventory
+                .item_types
+                .iter()
+                .find(|t| t.id == ii.item_type)
+                .map(|t| name.matches(&t.name))
+                .unwrap_or(false);
+            if !matching {
+                return false;
+            }
+        }
+        if let Some(location) = &self.location {
+            let matching = ii
+                .location
+                .as_ref()
+                .map(|l| location.matches(l))
+                .unwrap_or(false);
+            if !matching {
+                return false;
+            }
+        }
+        if let Some(external_ref) = &self.external_ref {
+            let matching = ii
+                .external_ref
+                .as_ref()
+                .map(|r| external_ref.matches(r))
+                .unwrap_or(false);
+            if !matching {
+                return false;
+            }
+        }
+        if !self.custom.is_empty() {
+            let item_type = inventory.item_types.iter().find(|t| t.id == ii.item_type);
+            if !self.custom.iter().all(|f| f.matches(item_type, ii)) {
+                return false;
+            }
+        }
+        if let Some(expired) = self.expired {
+            let now = inventory.now();
+            if (ii.expiry_state(now) == ExpiryState::Expired) != expired {
+                return false;
+            }
+        }
+        if let Some(opened) = self.opened {
+            if ii.opened_at.is_some() != opened {
+                return false;
+            }
+        }
+        if let Some(empty) = self.empty {
+            if (ii.quantity <= 0.0) != empty {
+                return false;
+            }
+        }
+        if let Some(removed) = self.removed {
+            if ii.removed_at.is_some() != removed {
+                return false;
+            }
+        }
+        let at_or_after = |time: Option<SystemTime>, since: Option<SystemTime>| match since {
+            Some(since) => time.is_some_and(|t| t >= since),
+            None => true,
+        };
+        at_or_after(ii.added_at, self.added_since) && at_or_after(ii.removed_at, self.removed_since)
+    }
+
+    /// Runs the query over the inventory.
+    pub fn run<'a>(&self, inventory: &'a Inventory) -> QueryResult<&'a ItemInstance> {
+        let mut items = inventory
+            .item_instances
+            .iter()
+            .filter(|ii| self.matches(inventory, ii))
+            .collect::<Vec<_>>();
+        items.sort_by(|a, b| {
+            let ord = compare_instances(self.sort, a, b);
+            let ord = if self.descending { ord.reverse() } else { ord };
+            ord.then(a.id.cmp(&b.id))
+        });
+        page(items, self.offset, self.limit)
+    }
+}
+
+/// A query over the item types of an inventory.
+#[derive(Debug, Clone, Default)]
+#[must_use = "a query does nothing until it is run"]
+pub struct TypeQuery {
+    id: Option<TypeId>,
+    name: Option<NamePattern>,
+    has_ttl: Option<bool>,
+    opened_by_default: Option<bool>,
+    has_minimum: Option<bool>,
+    unused: Option<bool>,
+    category: Option<String>,
+    sort: TypeSortKey,
+    descending: bool,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl TypeQuery {
+    pub fn new() -> Self {
+        TypeQuery::default()
+    }
+
+    /// Only matches the item type with this id.
+    pub fn id(mut self, id: TypeId) -> Self {
+        self.id = Some(id);
+        self
+    }
+
+    /// Only matches item types whose name or a former name contains this text, ignoring case.
+    pub fn name_contains(self, name: &str) -> Self {
+        self.name_matching(name, MatchMode::Substring)
+    }
+
+    /// Only matches item types whose name or a former name matches this text, ignoring case.
+    pub fn name_matching(mut self, name: &str, mode: MatchMode) -> Self {
+        self.name = Some(NamePattern::new(name, mode));
+        self
+    }
+
+    /// Only matches item types that have a ttl, or that have none.
+    pub fn has_ttl(mut self, has_ttl: bool) -> Self {
+        self.has_ttl = Some(has_ttl);
+        self
+    }
+
+    /// Only matches item types that are opened by default, or that are not.
+    pub fn opened_by_default(mut self, opened_by_default: bool) -> Self {
+        self.opened_by_default = Some(opened_by_default);
+        self
+    }
+
+    /// Only matches item types whose minimum quantity is above 0, or that have none.
+    /// Minimum quantities of locations are not considered.
+    pub fn has_minimum(mut self, has_minimum: bool) -> Self {
+        self.has_minimum = Some(has_minimum);
+        self
+    }
+
+    /// Only matches item types without any item instance that is not removed,
+    /// or with at least one.
+    pub fn unused(mut self, unused: bool) -> Self {
+        self.unused = Some(unused);
+        self
+    }
+
+    /// Only matches item types in this category or in one of its subcategories, see
+    /// `ItemType::in_category`.
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    pub fn sort(mut self, sort: TypeSortKey) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Reverses the order of the sort key. Ties are still ordered by ascending id.
+    pub fn descending(mut self, descending: bool) -> Self {
+        self.descending = descending;
+        self
+    }
+
+    /// The maximum number of items returned.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The number of matching items to skip.
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Returns whether the item type passes the filters of this query. `stats` are those of
+    /// `Inventory::all_type_stats`, see `stats_for`.
+    pub fn matches(&self, item_type: &ItemType, stats: &HashMap<TypeId, TypeStats>) -> bool {
+        if self.id.map(|id| id != item_type.id).unwrap_or(false) {
+            return false;
+        }
+        if let Some(name) = &self.name {
+            if !item_type.is_called(name) {
+                return false;
+            }
+        }
+        if let Some(has_ttl) = self.has_ttl {
+            if item_type.ttl.is_some() != has_ttl {
+                return false;
+            }
+        }
+        if let Some(opened_by_default) = self.opened_by_default {
+            if item_type.opened_by_default != opened_by_default {
+                return false;
+            }
+        }
+        if let Some(has_minimum) = self.has_minimum {
+            if (item_type.minimum_quantity > 0.0) != has_minimum {
+                return false;
+            }
+        }
+        if let Some(unused) = self.unused {
+            if stats.contains_key(&item_type.id) == unused {
+                return false;
+            }
+        }
+        if let Some(category) = &self.category {
+            if !item_type.in_category(category) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the statistics `matches` needs for the inventory, which are only computed
+    /// when filtering on whether the item types are used.
+    pub fn stats_for(&self, inventory: &Inventory) -> HashMap<TypeId, TypeStats> {
+        if self.unused.is_some() {
+            inventory.all_type_stats()
+        } else {
+            HashMap::new()
+        }
+    }
+
+    /// Runs the query over the inventory.
+    pub fn run<'a>(&self, inventory: &'a Inventory) -> QueryResult<&'a ItemType> {
+        let stats = self.stats_for(inventory);
+        let mut items = inventory
+            .item_types
+            .iter()
+            .filter(|t| self.matches(t, &stats))
+            .collect::<Vec<_>>();
+        items.sort_by(|a, b| {
+            let ord = match self.sort {
+                TypeSortKey::Id => Ordering::Equal,
+                TypeSortKey::Name => fold_case(&a.name).cmp(&fold_case(&b.name)),
+                TypeSortKey::MinimumQuantity => a
+                    .minimum_quantity
+                    .partial_cmp(&b.minimum_quantity)
+                    .unwrap_or(Ordering::Equal),
+            };
+            let ord = if self.descending { ord.reverse() } else { ord };
+            ord.then(a.id.cmp(&b.id))
+        });
+        page(items, self.offset, self.limit)
+    }
+}
+
+/// What item instances are grouped by in `group_instances`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupKey {
+    /// Groups are ordered by lowercase type name, then by type id.
+    Type,
+    /// Groups are ordered by lowercase location. Instances without a location come last.
+    Location,
+    /// Like `Location`, with the home locations of the item instances.
+    HomeLocation,
+}
+
+/// The totals of a group of item instances.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Subtotal {
+    pub quantity: f32,
+    pub count: usize,
+    pub soonest_expiry: Option<SystemTime>,
+}
+
+impl Subtotal {
+    pub fn of<'a>(instances: impl IntoIterator<Item = &'a ItemInstance>) -> Self {
+        let mut subtotal = Subtotal {
+            quantity: 0.0,
+            count: 0,
+            soonest_expiry: None,
+        };
+        for ii in instances {
+            subtotal.quantity += ii.quantity;
+            subtotal.count += 1;
+            subtotal.soonest_expiry = match (subtotal.soonest_expiry, ii.expires_at) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+        }
+        subtotal
+    }
+}
+
+/// Item instances sharing an item type or a location.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct InstanceGroup<T> {
+    /// The name of the item type or the location. `None` for the instances without a location,
+    /// or whose item type doesn't exist.
+    pub name: Option<String>,
+    pub items: Vec<T>,
+    pub subtotal: Subtotal,
+}
+
+impl InstanceGroup<&ItemInstance> {
+    pub fn cloned(&self) -> InstanceGroup<ItemInstance> {
+        InstanceGroup {
+            name: self.name.clone(),
+            items: self.items.iter().map(|ii| (*ii).clone()).collect(),
+            subtotal: self.subtotal.clone(),
+        }
+    }
+}
+
+/// The lowercase name of a group and the type id when grouping by type.
+type GroupOrder = (Option<String>, TypeId);
+
+/// Splits item instances, usually the result of a query, into groups.
+/// The order of the instances within a group is kept.
+pub fn group_instances<'a>(
+    inventory: &Inventory,
+    instances: Vec<&'a ItemInstance>,
+    key: GroupKey,
+) -> Vec<InstanceGroup<&'a ItemInstance>> {
+    // The sort key of the group and its name.
+    let group_of = |ii: &ItemInstance| match key {
+        GroupKey::Type => {
+            let name = inventory
+                .item_types
+                .iter()
+                .find(|t| t.id == ii.item_type)
+                .map(|t| t.name.clone());
+            ((name.as_ref().map(|n| fold_case(n)), ii.item_type), name)
+        }
+        GroupKey::Location => (
+            (ii.location.as_ref().map(|l| fold_case(l)), TypeId(0)),
+            ii.location.clone(),
+        ),
+        GroupKey::HomeLocation => (
+            (ii.home_location.as_ref().map(|l| fold_case(l)), TypeId(0)),
+            ii.home_location.clone(),
+        ),
+    };
+    let mut groups: Vec<(GroupOrder, InstanceGroup<&ItemInstance>)> = vec![];
+    for ii in instances {
+        let (sort, name) = group_of(ii);
+        match groups.iter_mut().find(|(s, _)| *s == sort) {
+            Some((_, group)) => group.items.push(ii),
+            None => groups.push((
+                sort,
+                InstanceGroup {
+                    name,
+                    items: vec![ii],
+                    subtotal: Subtotal::of(vec![]),
+                },
+            )),
+        }
+    }
+    groups.sort_by(|(a, _), (b, _)| none_last(&a.0, &b.0).then(a.1.cmp(&b.1)));
+    groups
+        .into_iter()
+        .map(|(_, mut group)| {
+            group.subtotal = Subtotal::of(group.items.iter().copied());
+            group
+        })
+        .collect()
+}
+
+fn compare_instances(key: SortKey, a: &ItemInstance, b: &ItemInstance) -> Ordering {
+    match key {
+        SortKey::Id => Ordering::Equal,
+        SortKey::TypeId => a.item_type.cmp(&b.item_type),
+        SortKey::Quantity => a
+            .quantity
+            .partial_cmp(&b.quantity)
+            .unwrap_or(Ordering::Equal),
+        SortKey::Location => none_last(
+            &a.location.as_ref().map(|l| fold_case(l)),
+            &b.location.as_ref().map(|l| fold_case(l)),
+        ),
+        SortKey::Value => match (a.value, b.value) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        },
+        SortKey::OpenedAt => none_last(&a.opened_at, &b.opened_at),
+        // Item instances expiring together come oldest first.
+        SortKey::ExpiresAt => none_last(&a.expires_at, &b.expires_at)
+            .then_with(|| none_last(&a.added_at, &b.added_at)),
+        SortKey::AddedAt => none_last(&a.added_at, &b.added_at),
+    }
+}
+
+pub(crate) fn none_last<T: Ord>(a: &Option<T>, b: &Option<T>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    }
+}
+
+fn page<T>(items: Vec<T>, offset: usize, limit: Option<usize>) -> QueryResult<T> {
+    let total_matching = items.len();
+    let items = items
+        .into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+    QueryResult {
+        items,
+        total_matching,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FixedClock, ItemInstance, ItemType};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(start())));
+        for name in &["Milk", "rice", "Oat milk"] {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            });
+        }
+        let instances = vec![
+            (1, Some("Fridge"), Some(start() + DAY)),
+            (1, Some("fridge door"), Some(start() - DAY)),
+            (2, Some("Pantry"), None),
+            (3, Some("Fridge"), Some(start() + DAY)),
+            (3, None, Some(start() + DAY)),
+            (2, Some("Pantry"), Some(start() + 2 * DAY)),
+        ];
+        for (type_id, location, expires_at) in instances {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(type_id),
+                    location: location.map(|l| l.to_string()),
+                    expires_at,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    fn ids(result: &QueryResult<&ItemInstance>) -> Vec<u32> {
+        result.items.iter().map(|ii| ii.id.0).collect()
+    }
+
+    #[test]
+    fn filters_instances() {
+        let inventory = inventory();
+        let result = InstanceQuery::new()
+            .location_contains("FRIDGE")
+            .run(&inventory);
+        assert_eq!(ids(&result), vec![1, 2, 4]);
+        let result = InstanceQuery::new()
+            .type_id(TypeId(1))
+            .expired(false)
+            .run(&inventory);
+        assert_eq!(ids(&result), vec![1]);
+        let result = InstanceQuery::new().expired(true).run(&inventory);
+        assert_eq!(ids(&result), vec![2]);
+        let result = InstanceQuery::new()
+            .type_name_contains("milk")
+            .run(&inventory);
+        assert_eq!(ids(&result), vec![1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn filters_instances_by_external_reference() {
+        let mut inventory = inventory();
+        inventory.item_instances[0].external_ref = Some("ORDER-1234".to_string());
+        inventory.item_instances[3].external_ref = Some("order-1299".to_string());
+        let query = InstanceQuery::new().external_ref_contains("order-12");
+        assert!(query.has_filters());
+        assert_eq!(ids(&query.run(&inventory)), vec![1, 4]);
+        let query = InstanceQuery::new().external_ref_contains("1234");
+        assert_eq!(ids(&query.run(&inventory)), vec![1]);
+    }
+
+    #[test]
+    fn removed_instances_are_excluded_by_default() {
+        let mut inventory = inventory();
+        inventory.trash(InstanceId(1));
+        assert_eq!(
+            ids(&InstanceQuery::new().type_id(TypeId(1)).run(&inventory)),
+            vec![2]
+        );
+        let result = InstanceQuery::new()
+            .type_id(TypeId(1))
+            .include_removed(true)
+            .run(&inventory);
+        assert_eq!(ids(&result), vec![1, 2]);
+    }
+
+    #[test]
+    fn filters_instances_by_when_they_were_added_and_removed() {
+        let mut inventory = inventory();
+        for (i, ii) in inventory.item_instances.iter_mut().enumerate() {
+            ii.added_at = Some(start() - DAY * (i as u32));
+        }
+        inventory.item_instances[5].added_at = None;
+        inventory.item_instances[0].removed_at = Some(start() - DAY * 3);
+        inventory.item_instances[1].removed_at = Some(start() - DAY);
+
+        let query = InstanceQuery::new().added_since(start() - DAY * 2);
+        assert_eq!(ids(&query.run(&inventory)), vec![3]);
+        let query = query.include_removed(true);
+        assert_eq!(ids(&query.run(&inventory)), vec![1, 2, 3]);
+        let trashed = InstanceQuery::new().removed(true);
+        assert_eq!(ids(&trashed.run(&inventory)), vec![1, 2]);
+        let recent = InstanceQuery::new().removed_since(start() - DAY * 2);
+        assert_eq!(ids(&recent.run(&inventory)), vec![2]);
+        assert!(recent.has_filters());
+    }
+
+    #[test]
+    fn equal_keys_are_ordered_by_id() {
+        let inventory = inventory();
+        let sorted = InstanceQuery::new()
+            .sort(SortKey::ExpiresAt)
+            .run(&inventory);
+        assert_eq!(ids(&sorted), vec![2, 1, 4, 5, 6, 3]);
+        assert_eq!(sorted.total_matching, 6);
+        let descending = InstanceQuery::new()
+            .sort(SortKey::ExpiresAt)
+            .descending(true)
+            .run(&inventory);
+        assert_eq!(ids(&descending), vec![3, 6, 1, 4, 5, 2]);
+    }
+
+    #[test]
+    fn pages_are_stable() {
+        let inventory = inventory();
+        let query = InstanceQuery::new().sort(SortKey::Location).limit(2);
+        let mut paged = vec![];
+        for offset in (0..6).step_by(2) {
+            let page = query.clone().offset(offset).run(&inventory);
+            assert_eq!(page.total_matching, 6);
+            assert!(page.items.len() <= 2);
+            paged.extend(ids(&page));
+        }
+        let all = InstanceQuery::new().sort(SortKey::Location).run(&inventory);
+        assert_eq!(paged, ids(&all));
+        assert_eq!(paged, vec![1, 4, 2, 3, 6, 5]);
+        let past_end = query.offset(10).run(&inventory);
+        assert!(past_end.items.is_empty());
+        assert_eq!(past_end.total_matching, 6);
+    }
+
+    #[test]
+    fn queries_types() {
+        let inventory = inventory();
+        let result = TypeQuery::new()
+            .name_contains("MILK")
+            .sort(TypeSortKey::Name)
+            .run(&inventory);
+        let ids = result.items.iter().map(|t| t.id.0).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 3]);
+        let result = TypeQuery::new().id(TypeId(2)).run(&inventory);
+        assert_eq!(result.items[0].name, "rice");
+        let result = TypeQuery::new().limit(1).offset(1).run(&inventory);
+        assert_eq!(result.items[0].id, TypeId(2));
+        assert_eq!(result.total_matching, 3);
+    }
+
+    #[test]
+    fn filters_types_by_attributes_and_use() {
+        let mut inventory = inventory();
+        inventory.item_types[0].ttl = Some(DAY);
+        inventory.item_types[0].minimum_quantity = 2.0;
+        inventory.item_types[1].opened_by_default = true;
+        inventory.item_types[2].minimum_quantity = 1.0;
+        inventory.item_types[0].category = Some("Food/Baking".to_string());
+        inventory.item_types[1].category = Some("Food".to_string());
+        inventory.trash(InstanceId(4));
+        inventory.trash(InstanceId(5));
+        let type_ids = |query: TypeQuery| {
+            query
+                .run(&inventory)
+                .items
+                .iter()
+                .map(|t| t.id.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(type_ids(TypeQuery::new().has_ttl(false)), vec![2, 3]);
+        assert_eq!(type_ids(TypeQuery::new().opened_by_default(true)), vec![2]);
+        assert_eq!(type_ids(TypeQuery::new().has_minimum(true)), vec![1, 3]);
+        assert_eq!(type_ids(TypeQuery::new().unused(true)), vec![3]);
+        assert_eq!(type_ids(TypeQuery::new().unused(false)), vec![1, 2]);
+        assert_eq!(type_ids(TypeQuery::new().category("food")), vec![1, 2]);
+        assert_eq!(type_ids(TypeQuery::new().category("Food/Baking")), vec![1]);
+        assert!(type_ids(TypeQuery::new().category("Foo")).is_empty());
+        assert_eq!(
+            type_ids(
+                TypeQuery::new()
+                    .has_ttl(false)
+                    .has_minimum(true)
+                    .unused(true)
+            ),
+            vec![3]
+        );
+    }
+
+    #[test]
+    fn groups_instances_with_subtotals() {
+        let inventory = inventory();
+        let instances = InstanceQuery::new().run(&inventory).items;
+        let summary = |groups: Vec<InstanceGroup<&ItemInstance>>| {
+            groups
+                .into_iter()
+                .map(|g| (g.name, g.items.iter().map(|ii| ii.id.0).collect::<Vec<_>>()))
+                .collect::<Vec<_>>()
+        };
+        let name = |n: &str| Some(n.to_string());
+
+        let groups = group_instances(&inventory, instances.clone(), GroupKey::Type);
+        assert_eq!(groups[0].subtotal.count, 2);
+        assert_eq!(groups[0].subtotal.soonest_expiry, Some(start() - DAY));
+        assert_eq!(groups[0].subtotal.quantity, 2.0 * instances[0].quantity);
+        assert_eq!(
+            summary(groups),
+            vec![
+                (name("Milk"), vec![1, 2]),
+                (name("Oat milk"), vec![4, 5]),
+                (name("rice"), vec![3, 6]),
+            ]
+        );
+
+        let groups = group_instances(&inventory, instances, GroupKey::Location);
+        assert_eq!(
+            summary(groups),
+            vec![
+                (name("Fridge"), vec![1, 4]),
+                (name("fridge door"), vec![2]),
+                (name("Pantry"), vec![3, 6]),
+                (None, vec![5]),
+            ]
+        );
+
+        let mut inventory = inventory;
+        inventory.item_instances[2].home_location = Some("Cellar".to_string());
+        let instances = InstanceQuery::new().run(&inventory).items;
+        let groups = group_instances(&inventory, instances, GroupKey::HomeLocation);
+        assert_eq!(
+            summary(groups),
+            vec![(name("Cellar"), vec![3]), (None, vec![1, 2, 4, 5, 6])]
+        );
+        assert_eq!(Subtotal::of(vec![]).soonest_expiry, None);
+    }
+}
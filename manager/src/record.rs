@@ -0,0 +1,145 @@
+//! The stable line format used by the minimal output mode.
+//!
+//! Each record is printed on its own line. Fields are separated by a single tab
+//! and always appear in the order given by the `HEADER` of the record type.
+//! Backslashes, tabs, newlines and carriage returns inside a field are escaped
+//! as `\\`, `\t`, `\n` and `\r`, so a record can never span multiple lines and
+//! splitting on tabs is always safe. Empty fields mean the value is not set.
+
+/// The character separating the fields of a record.
+pub const SEPARATOR: char = '\t';
+
+/// Something that can be printed as a single line of the minimal format.
+pub trait Record {
+    /// The names of the fields, in the order they are written.
+    const HEADER: &'static [&'static str];
+
+    /// Returns the unescaped fields of this record, in the order of `HEADER`.
+    fn to_record(&self) -> Vec<String>;
+}
+
+/// Escapes a single field so that it contains no separator or line break.
+pub fn escape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Reverts `escape`. Unknown escape sequences are kept as is.
+pub fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => out.push('\\'),
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Escapes and joins the fields into a single line, without the line break.
+pub fn join<S: AsRef<str>>(fields: &[S]) -> String {
+    fields
+        .iter()
+        .map(|f| escape(f.as_ref()))
+        .collect::<Vec<_>>()
+        .join(&SEPARATOR.to_string())
+}
+
+/// Splits a line created by `join` back into its unescaped fields.
+pub fn split(line: &str) -> Vec<String> {
+    line.split(SEPARATOR).map(unescape).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemInstance, ItemType, TypeId};
+    use std::time::Duration;
+
+    const HOSTILE: &[&str] = &[
+        "plain",
+        "semi;colon;",
+        "tab\there",
+        "new\nline",
+        "carriage\r\nreturn",
+        "back\\slash\\t",
+        "trailing\\",
+        "ünïcödé 🐐 日本語",
+        "",
+    ];
+
+    #[test]
+    fn escaped_fields_round_trip() {
+        for s in HOSTILE {
+            let escaped = escape(s);
+            assert!(!escaped.contains(SEPARATOR));
+            assert!(!escaped.contains('\n'));
+            assert!(!escaped.contains('\r'));
+            assert_eq!(unescape(&escaped), *s);
+        }
+    }
+
+    #[test]
+    fn joined_records_round_trip() {
+        let line = join(HOSTILE);
+        assert!(!line.contains('\n'));
+        assert_eq!(split(&line), HOSTILE);
+    }
+
+    #[test]
+    fn item_type_display_matches_record() {
+        for s in HOSTILE {
+            let it = ItemType {
+                name: s.to_string(),
+                ttl: Some(Duration::from_secs(3600)),
+                ..ItemType::default()
+            };
+            let line = it.to_string();
+            let fields = split(&line);
+            assert_eq!(fields.len(), crate::ItemType::HEADER.len());
+            assert_eq!(fields, it.to_record());
+            assert_eq!(fields[1], *s);
+        }
+    }
+
+    #[test]
+    fn item_instance_display_matches_record() {
+        for s in HOSTILE {
+            let ii = ItemInstance {
+                item_type: TypeId(1),
+                model: Some(s.to_string()),
+                location: Some(s.to_string()),
+                extra: Some(s.to_string()),
+                ..ItemInstance::default()
+            };
+            let line = ii.to_string();
+            let fields = split(&line);
+            assert_eq!(fields.len(), crate::ItemInstance::HEADER.len());
+            assert_eq!(fields, ii.to_record());
+            assert_eq!(fields[3], *s);
+            assert_eq!(fields[5], *s);
+            assert_eq!(fields[6], *s);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! The lookup of item instances by their external reference, like an order number.
+//!
+//! The inventory keeps an index from each reference to the positions of its item instances
+//! in `Inventory::item_instances`, built on the first lookup. The index is rebuilt when the
+//! number of item instances changed or when a position it gives is outdated, so adding and
+//! removing item instances need nothing more. Changing the `external_ref` of an item
+//! instance in place needs `Inventory::set_external_ref` or
+//! `Inventory::invalidate_ref_index`, otherwise the new reference may not be found.
+
+use crate::{InstanceId, Inventory, InventoryError, ItemInstance};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// The index of the external references, empty until it is used.
+#[derive(Default)]
+pub(crate) struct RefIndex {
+    built: Mutex<Option<Built>>,
+}
+
+struct Built {
+    /// The number of item instances when the index was built.
+    len: usize,
+    positions: HashMap<String, Vec<u32>>,
+}
+
+impl RefIndex {
+    fn build(instances: &[ItemInstance]) -> Built {
+        let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+        for (position, ii) in instances.iter().enumerate() {
+            if let Some(external_ref) = &ii.external_ref {
+                positions
+                    .entry(external_ref.clone())
+                    .or_default()
+                    .push(position as u32);
+            }
+        }
+        Built {
+            len: instances.len(),
+            positions,
+        }
+    }
+
+    /// Whether the positions of the reference still designate item instances with it.
+    fn is_current(built: &Built, instances: &[ItemInstance], external_ref: &str) -> bool {
+        built.len == instances.len()
+            && built.positions.get(external_ref).is_none_or(|positions| {
+                positions
+                    .iter()
+                    .all(|&p| instances[p as usize].external_ref.as_deref() == Some(external_ref))
+            })
+    }
+
+    fn positions(&self, instances: &[ItemInstance], external_ref: &str) -> Vec<u32> {
+        let mut built = self.built.lock().expect("The index is never poisoned");
+        let current = built
+            .as_ref()
+            .is_some_and(|b| RefIndex::is_current(b, instances, external_ref));
+        if !current {
+            *built = Some(RefIndex::build(instances));
+        }
+        built
+            .as_ref()
+            .and_then(|b| b.positions.get(external_ref).cloned())
+            .unwrap_or_default()
+    }
+
+    fn invalidate(&self) {
+        *self.built.lock().expect("The index is never poisoned") = None;
+    }
+}
+
+/// Copies of an inventory build their own index.
+impl Clone for RefIndex {
+    fn clone(&self) -> Self {
+        RefIndex::default()
+    }
+}
+
+impl fmt::Debug for RefIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RefIndex")
+    }
+}
+
+impl Inventory {
+    /// Returns the item instances whose external reference is exactly this one, including
+    /// the removed ones, in the order of `item_instances`.
+    pub fn find_by_ref(&self, external_ref: &str) -> Vec<&ItemInstance> {
+        self.ref_index
+            .positions(&self.item_instances, external_ref)
+            .into_iter()
+            .map(|p| &self.item_instances[p as usize])
+            .collect()
+    }
+
+    /// Sets or removes the external reference of an item instance, keeping the index of
+    /// `find_by_ref` up to date.
+    pub fn set_external_ref(
+        &mut self,
+        instance_id: InstanceId,
+        external_ref: Option<String>,
+    ) -> Result<(), InventoryError> {
+        let ii = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        ii.external_ref = external_ref.filter(|r| !r.is_empty());
+        self.ref_index.invalidate();
+        Ok(())
+    }
+
+    /// Drops the index of `find_by_ref`, after the external references were changed
+    /// directly in `item_instances`.
+    pub fn invalidate_ref_index(&self) {
+        self.ref_index.invalidate();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemInstance, ItemType, TypeId};
+
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            ..ItemType::default()
+        });
+        for external_ref in &[Some("PO-1"), None, Some("PO-2"), Some("PO-1")] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(1),
+                    external_ref: external_ref.map(str::to_string),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    fn ids(instances: Vec<&ItemInstance>) -> Vec<u32> {
+        instances.iter().map(|ii| ii.id.0).collect()
+    }
+
+    #[test]
+    fn references_are_found_exactly_including_removed_instances() {
+        let mut inventory = inventory();
+        inventory.trash(InstanceId(4));
+        assert_eq!(ids(inventory.find_by_ref("PO-1")), vec![1, 4]);
+        assert_eq!(ids(inventory.find_by_ref("PO-2")), vec![3]);
+        assert!(inventory.find_by_ref("PO").is_empty());
+        assert!(inventory.find_by_ref("po-1").is_empty());
+    }
+
+    #[test]
+    fn the_index_follows_the_changes_of_the_inventory() {
+        let mut inventory = inventory();
+        assert_eq!(ids(inventory.find_by_ref("PO-1")), vec![1, 4]);
+
+        inventory
+            .set_external_ref(InstanceId(2), Some("PO-2".to_string()))
+            .unwrap();
+        inventory.set_external_ref(InstanceId(3), None).unwrap();
+        assert_eq!(ids(inventory.find_by_ref("PO-2")), vec![2]);
+        assert_eq!(
+            inventory.set_external_ref(InstanceId(9), None),
+            Err(InventoryError::UnknownItemInstance)
+        );
+
+        inventory.item_instances.retain(|ii| ii.id != InstanceId(1));
+        assert_eq!(ids(inventory.find_by_ref("PO-1")), vec![4]);
+        let mut added = inventory.item_instances[0].clone();
+        added.external_ref = Some("PO-1".to_string());
+        let id = inventory.add_item_instance(added).unwrap();
+        assert_eq!(ids(inventory.find_by_ref("PO-1")), vec![4, id.0]);
+
+        // A reference moved in place is found once the index is invalidated.
+        inventory.item_instances[0].external_ref = Some("PO-3".to_string());
+        inventory.invalidate_ref_index();
+        assert_eq!(ids(inventory.find_by_ref("PO-3")), vec![2]);
+        assert!(inventory.find_by_ref("PO-2").is_empty());
+
+        let copy = inventory.clone();
+        for external_ref in &["PO-1", "PO-2", "PO-3"] {
+            let scanned = inventory
+                .item_instances
+                .iter()
+                .filter(|ii| ii.external_ref.as_deref() == Some(*external_ref))
+                .map(|ii| ii.id.0)
+                .collect::<Vec<_>>();
+            assert_eq!(ids(copy.find_by_ref(external_ref)), scanned);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn instances_without_a_reference_still_load() {
+        let ii: ItemInstance =
+            serde_json::from_str(r#"{"id": 1, "item_type": 1, "quantity": 2.0}"#).unwrap();
+        assert_eq!(ii.external_ref, None);
+        let json = serde_json::to_value(ItemInstance {
+            external_ref: Some("https://shop.example/orders/42".to_string()),
+            ..ItemInstance::default()
+        })
+        .unwrap();
+        assert_eq!(json["external_ref"], "https://shop.example/orders/42");
+        let ii: ItemInstance = serde_json::from_value(json).unwrap();
+        assert_eq!(
+            ii.external_ref.as_deref(),
+            Some("https://shop.example/orders/42")
+        );
+    }
+}
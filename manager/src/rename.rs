@@ -0,0 +1,143 @@
+//! Renaming item types without making their names ambiguous.
+//!
+//! The name of an item type is kept as it was typed, but two item types can not have names
+//! differing only by case, see `fold_case`. The names an item type had before are kept in
+//! `ItemType::former_names` so that the old name still finds it, and they are taken too.
+
+use crate::{fold_case, Inventory, InventoryError, TypeId};
+
+impl Inventory {
+    /// Returns the id of another item type whose name or one of its former names is this
+    /// name, ignoring case.
+    pub fn type_name_conflict(&self, name: &str, except: Option<TypeId>) -> Option<TypeId> {
+        let folded = fold_case(name.trim());
+        self.item_types
+            .iter()
+            .filter(|t| Some(t.id) != except)
+            .find(|t| {
+                fold_case(&t.name) == folded
+                    || t.former_names.iter().any(|n| fold_case(n) == folded)
+            })
+            .map(|t| t.id)
+    }
+
+    /// Renames an item type, keeping its previous name in its former names unless only its
+    /// case changes. Taking back a former name removes it from them.
+    /// Fails with `DuplicateTypeName` when another item type has or had the name, unless
+    /// `force`.
+    pub fn rename_type(
+        &mut self,
+        type_id: TypeId,
+        name: &str,
+        force: bool,
+    ) -> Result<(), InventoryError> {
+        let name = name.trim();
+        if !self.item_types.iter().any(|t| t.id == type_id) {
+            return Err(InventoryError::UnknownItemType);
+        }
+        if !force {
+            if let Some(conflict) = self.type_name_conflict(name, Some(type_id)) {
+                return Err(InventoryError::DuplicateTypeName { type_id: conflict });
+            }
+        }
+        let item_type = self
+            .item_types
+            .iter_mut()
+            .find(|t| t.id == type_id)
+            .ok_or(InventoryError::UnknownItemType)?;
+        let folded = fold_case(name);
+        let old = std::mem::replace(&mut item_type.name, name.to_string());
+        item_type.former_names.retain(|n| fold_case(n) != folded);
+        if fold_case(&old) != folded && !old.is_empty() {
+            item_type.former_names.push(old);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemType, MatchMode};
+
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        for name in &["Milk", "rice"] {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            });
+        }
+        inventory
+    }
+
+    #[test]
+    fn names_of_other_types_are_refused_ignoring_case() {
+        let mut inventory = inventory();
+        assert_eq!(
+            inventory.rename_type(TypeId(2), "MILK", false),
+            Err(InventoryError::DuplicateTypeName { type_id: TypeId(1) })
+        );
+        assert_eq!(inventory.item_types[1].name, "rice");
+        assert_eq!(
+            inventory.rename_type(TypeId(9), "bread", false),
+            Err(InventoryError::UnknownItemType)
+        );
+
+        // Changing only the case keeps no former name.
+        inventory.rename_type(TypeId(2), "Rice", false).unwrap();
+        assert_eq!(inventory.item_types[1].name, "Rice");
+        assert!(inventory.item_types[1].former_names.is_empty());
+
+        inventory.rename_type(TypeId(2), "milk", true).unwrap();
+        assert_eq!(inventory.item_types[1].name, "milk");
+        assert_eq!(inventory.item_types[1].former_names, vec!["Rice"]);
+    }
+
+    #[test]
+    fn former_names_find_the_type_and_are_taken() {
+        let mut inventory = inventory();
+        inventory.rename_type(TypeId(1), "Oat milk", false).unwrap();
+        inventory
+            .rename_type(TypeId(1), "Oat drink", false)
+            .unwrap();
+        assert_eq!(
+            inventory.item_types[0].former_names,
+            vec!["Milk", "Oat milk"]
+        );
+        assert_eq!(inventory.resolve_type("milk"), Ok(TypeId(1)));
+        assert_eq!(
+            inventory
+                .types_matching("oat*", MatchMode::Glob)
+                .iter()
+                .map(|t| t.id.0)
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert_eq!(
+            inventory.rename_type(TypeId(2), "oat MILK", false),
+            Err(InventoryError::DuplicateTypeName { type_id: TypeId(1) })
+        );
+
+        // A forced name wins over the former name of another item type.
+        inventory.rename_type(TypeId(2), "Milk", true).unwrap();
+        assert_eq!(inventory.resolve_type("milk"), Ok(TypeId(2)));
+    }
+
+    #[test]
+    fn a_type_can_take_back_a_former_name() {
+        let mut inventory = inventory();
+        inventory.rename_type(TypeId(1), "Oat milk", false).unwrap();
+        inventory.rename_type(TypeId(1), "milk", false).unwrap();
+        assert_eq!(inventory.item_types[0].name, "milk");
+        assert_eq!(inventory.item_types[0].former_names, vec!["Oat milk"]);
+        assert_eq!(
+            inventory.type_name_conflict("OAT MILK", None),
+            Some(TypeId(1))
+        );
+        assert_eq!(
+            inventory.type_name_conflict("oat milk", Some(TypeId(1))),
+            None
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! Finding and replacing a text in the text fields of the inventory, like a brand misspelled
+//! in the models of many item instances.
+//!
+//! The text is found as it is, matching case, or with a regular expression when the `regex`
+//! feature is enabled, whose replacement can refer to its capture groups like `$1` or
+//! `${name}`. A field of an item instance left empty by the replacement is unset, and item
+//! types are renamed like `Inventory::rename_type`.
+
+use crate::{InstanceId, InstanceQuery, Inventory, InventoryError, TypeId, TypeQuery};
+use std::fmt;
+use std::str::FromStr;
+
+/// The text fields of item instances that can be replaced in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstanceTextField {
+    Model,
+    Serial,
+    Extra,
+    Location,
+}
+
+impl FromStr for InstanceTextField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "model" => Ok(InstanceTextField::Model),
+            "serial" => Ok(InstanceTextField::Serial),
+            "extra" => Ok(InstanceTextField::Extra),
+            "location" => Ok(InstanceTextField::Location),
+            _ => Err(format!(
+                "unknown field {}, expected model, serial, extra or location",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for InstanceTextField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InstanceTextField::Model => "model",
+            InstanceTextField::Serial => "serial",
+            InstanceTextField::Extra => "extra",
+            InstanceTextField::Location => "location",
+        })
+    }
+}
+
+/// What to look for in the text fields.
+#[derive(Debug, Clone)]
+pub enum TextMatcher {
+    /// Every occurrence of the text, matching case. An empty text matches nothing.
+    Exact(String),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl TextMatcher {
+    /// Compiles a regular expression. Fails with `InvalidPattern`, whose message points at
+    /// the mistake.
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Self, InventoryError> {
+        regex::Regex::new(pattern)
+            .map(TextMatcher::Regex)
+            .map_err(|e| InventoryError::InvalidPattern {
+                message: e.to_string(),
+            })
+    }
+
+    /// Replaces every match in the text, or returns `None` when nothing matches.
+    pub fn replace(&self, text: &str, replacement: &str) -> Option<String> {
+        match self {
+            TextMatcher::Exact(from) => (!from.is_empty() && text.contains(from.as_str()))
+                .then(|| text.replace(from.as_str(), replacement)),
+            #[cfg(feature = "regex")]
+            TextMatcher::Regex(regex) => regex
+                .is_match(text)
+                .then(|| regex.replace_all(text, replacement).into_owned()),
+        }
+    }
+}
+
+impl Inventory {
+    /// Replaces the matches in a text field of the item instances matching the filter.
+    /// Returns the id, the text before and the text after of each item instance changed,
+    /// in the order of the inventory. Replacing with the same text changes nothing.
+    pub fn replace_in_instances(
+        &mut self,
+        field: InstanceTextField,
+        matcher: &TextMatcher,
+        replacement: &str,
+        filter: &InstanceQuery,
+    ) -> Vec<(InstanceId, String, String)> {
+        let ids = self
+            .item_instances
+            .iter()
+            .filter(|ii| filter.matches(self, ii))
+            .map(|ii| ii.id)
+            .collect::<Vec<_>>();
+        let mut changes = vec![];
+        for ii in self
+            .item_instances
+            .iter_mut()
+            .filter(|ii| ids.contains(&ii.id))
+        {
+            let text = match field {
+                InstanceTextField::Model => &mut ii.model,
+                InstanceTextField::Serial => &mut ii.serial,
+                InstanceTextField::Extra => &mut ii.extra,
+                InstanceTextField::Location => &mut ii.location,
+            };
+            let before = match text {
+                Some(before) => before.clone(),
+                None => continue,
+            };
+            match matcher.replace(&before, replacement) {
+                Some(after) if after != before => {
+                    *text = Some(after.clone()).filter(|t| !t.is_empty());
+                    changes.push((ii.id, before, after));
+                }
+                _ => {}
+            }
+        }
+        changes
+    }
+
+    /// Replaces the matches in the names of the item types matching the filter, renaming
+    /// them like `rename_type`. Returns the id, the name before and the name after of each
+    /// item type renamed, in the order of the inventory. Nothing is renamed when a new
+    /// name is empty, with `InvalidName`, or would be taken, with `DuplicateTypeName`.
+    pub fn replace_in_types(
+        &mut self,
+        matcher: &TextMatcher,
+        replacement: &str,
+        filter: &TypeQuery,
+    ) -> Result<Vec<(TypeId, String, String)>, InventoryError> {
+        let stats = filter.stats_for(self);
+        let changes = self
+            .item_types
+            .iter()
+            .filter(|t| filter.matches(t, &stats))
+            .filter_map(|t| {
+                let after = matcher.replace(&t.name, replacement)?;
+                (after.trim() != t.name).then(|| (t.id, t.name.clone(), after.trim().to_string()))
+            })
+            .collect::<Vec<_>>();
+        // Renamed one by one, a name freed by an item type can be taken by the next one.
+        let mut renamed = self.clone();
+        for (id, _, after) in &changes {
+            if after.is_empty() {
+                return Err(InventoryError::InvalidName);
+            }
+            renamed.rename_type(*id, after, false)?;
+        }
+        self.item_types = renamed.item_types;
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ItemInstance, ItemType};
+
+    /// Drills and saws, with models by a brand misspelled in several ways.
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        for name in ["Bosh drill", "Bosh saw", "hammer"] {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            });
+        }
+        let instances = [
+            (1, Some("Bosh PSB 1800"), Some("garage")),
+            (1, Some("bosh GSR 12V"), Some("Garage")),
+            (2, Some("Bosh PST 700"), None),
+            (3, None, Some("garage")),
+        ];
+        for (item_type, model, location) in instances {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type: TypeId(item_type),
+                    model: model.map(str::to_string),
+                    location: location.map(str::to_string),
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    #[test]
+    fn exact_matches_are_replaced_in_the_filtered_instances() {
+        let mut inventory = inventory();
+        let matcher = TextMatcher::Exact("Bosh".to_string());
+        let changes = inventory.replace_in_instances(
+            InstanceTextField::Model,
+            &matcher,
+            "Bosch",
+            &InstanceQuery::new().type_id(TypeId(1)),
+        );
+        assert_eq!(
+            changes,
+            vec![(
+                InstanceId(1),
+                "Bosh PSB 1800".to_string(),
+                "Bosch PSB 1800".to_string()
+            )]
+        );
+        assert_eq!(
+            inventory.item_instances[2].model.as_deref(),
+            Some("Bosh PST 700")
+        );
+
+        // A field emptied is unset, and the trash is left alone.
+        inventory.trash(InstanceId(4));
+        let garage = TextMatcher::Exact("garage".to_string());
+        let changes = inventory.replace_in_instances(
+            InstanceTextField::Location,
+            &garage,
+            "",
+            &InstanceQuery::new(),
+        );
+        assert_eq!(changes.len(), 1);
+        assert_eq!(inventory.item_instances[0].location, None);
+        assert_eq!(
+            inventory.item_instances[3].location.as_deref(),
+            Some("garage")
+        );
+        assert!(TextMatcher::Exact(String::new())
+            .replace("text", "x")
+            .is_none());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regexes_substitute_their_capture_groups() {
+        let mut inventory = inventory();
+        let matcher = TextMatcher::regex(r"(?i)^bosh (\w+)").unwrap();
+        let changes = inventory.replace_in_instances(
+            InstanceTextField::Model,
+            &matcher,
+            "Bosch $1",
+            &InstanceQuery::new(),
+        );
+        let after = changes.iter().map(|c| c.2.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            after,
+            vec!["Bosch PSB 1800", "Bosch GSR 12V", "Bosch PST 700"]
+        );
+
+        match TextMatcher::regex("bosh (") {
+            Err(InventoryError::InvalidPattern { message }) => {
+                assert!(message.contains("unclosed group"), "{}", message)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn item_types_are_renamed_all_or_nothing() {
+        let mut inventory = inventory();
+        let matcher = TextMatcher::Exact("Bosh".to_string());
+        let changes = inventory
+            .replace_in_types(&matcher, "Bosch", &TypeQuery::new())
+            .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(inventory.item_types[0].name, "Bosch drill");
+        assert_eq!(inventory.item_types[0].former_names, vec!["Bosh drill"]);
+
+        let saw = TextMatcher::Exact("saw".to_string());
+        assert_eq!(
+            inventory.replace_in_types(&saw, "drill", &TypeQuery::new()),
+            Err(InventoryError::DuplicateTypeName { type_id: TypeId(1) })
+        );
+        assert_eq!(
+            inventory.replace_in_types(
+                &TextMatcher::Exact("hammer".to_string()),
+                " ",
+                &TypeQuery::new()
+            ),
+            Err(InventoryError::InvalidName)
+        );
+        assert_eq!(inventory.item_types[1].name, "Bosch saw");
+        assert_eq!(inventory.item_types[2].name, "hammer");
+    }
+}
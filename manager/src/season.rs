@@ -0,0 +1,217 @@
+//! Minimum quantities that change with the time of the year, like sunscreen in summer.
+
+use crate::date;
+use crate::decimal::parse_decimal;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// The number of days of each month, in a leap year so that `02-29` is a valid day.
+const DAYS_IN_MONTH: [u8; 12] = [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+
+/// A minimum quantity that applies every year between two days, both included, written
+/// `MM-DD..MM-DD=minimum`. A range whose end comes before its start wraps over the new year,
+/// like `11-01..02-28`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SeasonRule {
+    /// The first day, as (month, day).
+    pub start_month_day: (u8, u8),
+    /// The last day, as (month, day).
+    pub end_month_day: (u8, u8),
+    pub minimum: f32,
+}
+
+impl SeasonRule {
+    /// Whether the rule applies on a day, given as (month, day).
+    pub fn contains(&self, month_day: (u8, u8)) -> bool {
+        let day = day_of_year(month_day);
+        let start = day_of_year(self.start_month_day);
+        let end = day_of_year(self.end_month_day);
+        if start <= end {
+            start <= day && day <= end
+        } else {
+            day >= start || day <= end
+        }
+    }
+
+    /// Whether the two rules have at least a day in common.
+    pub fn overlaps(&self, other: &SeasonRule) -> bool {
+        self.contains(other.start_month_day) || other.contains(self.start_month_day)
+    }
+}
+
+impl FromStr for SeasonRule {
+    type Err = String;
+
+    /// Parses `MM-DD..MM-DD=minimum`, like `05-01..09-30=2`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (range, minimum) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected MM-DD..MM-DD=quantity, got \"{}\"", s))?;
+        let (start, end) = range
+            .split_once("..")
+            .ok_or_else(|| format!("expected a range like 05-01..09-30 in \"{}\"", s))?;
+        let minimum =
+            parse_decimal(minimum).map_err(|e| format!("invalid quantity in \"{}\": {}", s, e))?;
+        if !minimum.is_finite() || minimum < 0.0 {
+            return Err(format!("invalid quantity in \"{}\": must be positive", s));
+        }
+        Ok(SeasonRule {
+            start_month_day: parse_month_day(start)?,
+            end_month_day: parse_month_day(end)?,
+            minimum,
+        })
+    }
+}
+
+impl fmt::Display for SeasonRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (start_month, start_day) = self.start_month_day;
+        let (end_month, end_day) = self.end_month_day;
+        write!(
+            f,
+            "{:02}-{:02}..{:02}-{:02}={}",
+            start_month, start_day, end_month, end_day, self.minimum
+        )
+    }
+}
+
+/// Returns the first pair of rules, by index, having a day in common.
+pub fn find_overlap(rules: &[SeasonRule]) -> Option<(usize, usize)> {
+    (0..rules.len())
+        .flat_map(|i| (i + 1..rules.len()).map(move |j| (i, j)))
+        .find(|(i, j)| rules[*i].overlaps(&rules[*j]))
+}
+
+/// The minimum of the first rule applying on the day of `now` in UTC, if any.
+pub fn seasonal_minimum(rules: &[SeasonRule], now: SystemTime) -> Option<f32> {
+    let today = month_day(now);
+    rules.iter().find(|r| r.contains(today)).map(|r| r.minimum)
+}
+
+/// The month and day of a time in UTC.
+pub fn month_day(time: SystemTime) -> (u8, u8) {
+    let (_, month, day) = date::civil_date(time);
+    (month, day)
+}
+
+/// Parses `MM-DD`, checking that the day exists in a leap year.
+fn parse_month_day(s: &str) -> Result<(u8, u8), String> {
+    let invalid = || format!("invalid day \"{}\", expected MM-DD like 05-01", s.trim());
+    let (month, day) = s.trim().split_once('-').ok_or_else(invalid)?;
+    let month = month.parse::<u8>().map_err(|_| invalid())?;
+    let day = day.parse::<u8>().map_err(|_| invalid())?;
+    if !(1..=12).contains(&month) || !(1..=DAYS_IN_MONTH[month as usize - 1]).contains(&day) {
+        return Err(invalid());
+    }
+    Ok((month, day))
+}
+
+/// The position of a day in a leap year, from 0 for January 1st.
+fn day_of_year((month, day): (u8, u8)) -> u16 {
+    let before = DAYS_IN_MONTH
+        .iter()
+        .take(month.saturating_sub(1) as usize)
+        .map(|d| *d as u16)
+        .sum::<u16>();
+    before + day as u16 - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn rule(s: &str) -> SeasonRule {
+        s.parse().unwrap()
+    }
+
+    /// Noon UTC on a day of 2021, which is not a leap year.
+    fn day_of_2021(month: u64, day: u64) -> SystemTime {
+        let days_before = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let days = 18628 + days_before[month as usize - 1] + day - 1;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(days * 86400 + 12 * 3600)
+    }
+
+    #[test]
+    fn rules_are_parsed() {
+        let summer = rule("05-01..09-30=2");
+        assert_eq!(summer.start_month_day, (5, 1));
+        assert_eq!(summer.end_month_day, (9, 30));
+        assert_eq!(summer.minimum, 2.0);
+        assert_eq!(summer.to_string(), "05-01..09-30=2");
+        assert_eq!(rule(" 2-29 .. 3-1 = 0.5").to_string(), "02-29..03-01=0.5");
+
+        for invalid in [
+            "05-01..09-30",
+            "05-01=2",
+            "05-01..09-31=2",
+            "13-01..01-01=2",
+            "00-10..01-01=2",
+            "05-00..09-30=2",
+            "02-30..03-01=2",
+            "05-01..09-30=-1",
+            "05-01..09-30=many",
+            "may..september=2",
+        ] {
+            assert!(invalid.parse::<SeasonRule>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn ranges_include_both_ends() {
+        let summer = rule("05-01..09-30=2");
+        assert!(!summer.contains((4, 30)));
+        assert!(summer.contains((5, 1)));
+        assert!(summer.contains((7, 14)));
+        assert!(summer.contains((9, 30)));
+        assert!(!summer.contains((10, 1)));
+        assert!(rule("03-03..03-03=1").contains((3, 3)));
+    }
+
+    #[test]
+    fn ranges_wrap_over_the_new_year() {
+        let winter = rule("11-15..02-28=4");
+        assert!(winter.contains((11, 15)));
+        assert!(winter.contains((12, 31)));
+        assert!(winter.contains((1, 1)));
+        assert!(winter.contains((2, 28)));
+        assert!(!winter.contains((2, 29)));
+        assert!(!winter.contains((3, 1)));
+        assert!(!winter.contains((11, 14)));
+        assert!(!winter.contains((7, 1)));
+    }
+
+    #[test]
+    fn overlaps_are_found() {
+        let rules = [
+            rule("05-01..09-30=2"),
+            rule("10-01..10-31=1"),
+            rule("11-15..02-28=4"),
+        ];
+        assert_eq!(find_overlap(&rules), None);
+        assert_eq!(find_overlap(&[]), None);
+
+        let overlapping = [rules[0], rules[2], rule("02-01..04-30=3")];
+        assert_eq!(find_overlap(&overlapping), Some((1, 2)));
+        assert!(rule("12-01..01-31=1").overlaps(&rule("11-01..02-01=1")));
+        assert!(rule("06-01..06-30=1").overlaps(&rule("01-01..12-31=1")));
+        assert!(rule("09-30..10-02=1").overlaps(&rules[0]));
+        assert!(!rule("12-31..12-31=1").overlaps(&rule("01-01..01-01=1")));
+    }
+
+    #[test]
+    fn the_rule_of_the_day_gives_the_minimum() {
+        let rules = [rule("05-01..09-30=2"), rule("11-15..02-28=0")];
+        assert_eq!(seasonal_minimum(&rules, day_of_2021(7, 14)), Some(2.0));
+        assert_eq!(seasonal_minimum(&rules, day_of_2021(9, 30)), Some(2.0));
+        assert_eq!(seasonal_minimum(&rules, day_of_2021(10, 1)), None);
+        assert_eq!(seasonal_minimum(&rules, day_of_2021(1, 1)), Some(0.0));
+        assert_eq!(seasonal_minimum(&rules, day_of_2021(3, 1)), None);
+        assert_eq!(seasonal_minimum(&[], day_of_2021(7, 14)), None);
+        assert_eq!(month_day(day_of_2021(12, 31)), (12, 31));
+    }
+}
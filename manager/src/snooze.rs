@@ -0,0 +1,176 @@
+//! Snoozing the reports about item instances kept on purpose, like vinegar past its expiry.
+//!
+//! An item instance or a whole item type is snoozed until a time, after which it is reported
+//! again without anything to do, or forever until it is unsnoozed. The reports about
+//! expiries and minimums leave out the item instances snoozed themselves or through their
+//! item type, unless asked to include them.
+
+use crate::{InstanceId, Inventory, InventoryError, ItemInstance, ItemType, TypeId};
+use std::time::SystemTime;
+
+/// How long to snooze.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Snooze {
+    Until(SystemTime),
+    Forever,
+}
+
+/// Whether the snooze fields of an item type or item instance are in effect at a time.
+fn snoozed(until: Option<SystemTime>, forever: bool, now: SystemTime) -> bool {
+    forever || until.is_some_and(|until| until > now)
+}
+
+impl ItemType {
+    /// Whether the item type is snoozed at a time, see `snooze`.
+    pub fn is_snoozed(&self, now: SystemTime) -> bool {
+        snoozed(self.snoozed_until, self.snoozed_forever, now)
+    }
+}
+
+impl ItemInstance {
+    /// Whether the item instance itself is snoozed at a time, see `Inventory::is_snoozed`
+    /// for its item type too.
+    pub fn is_snoozed(&self, now: SystemTime) -> bool {
+        snoozed(self.snoozed_until, self.snoozed_forever, now)
+    }
+}
+
+impl Inventory {
+    /// Whether the item instance or its item type is snoozed at a time.
+    pub fn is_snoozed(&self, item_instance: &ItemInstance, now: SystemTime) -> bool {
+        item_instance.is_snoozed(now)
+            || self
+                .item_types
+                .iter()
+                .find(|t| t.id == item_instance.item_type)
+                .is_some_and(|t| t.is_snoozed(now))
+    }
+
+    /// Snoozes an item instance, replacing its previous snooze. `None` unsnoozes it.
+    pub fn snooze_instance(
+        &mut self,
+        instance_id: InstanceId,
+        snooze: Option<Snooze>,
+    ) -> Result<(), InventoryError> {
+        let ii = self
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == instance_id)
+            .ok_or(InventoryError::UnknownItemInstance)?;
+        (ii.snoozed_until, ii.snoozed_forever) = fields(snooze);
+        Ok(())
+    }
+
+    /// Snoozes an item type and so all its item instances, replacing its previous snooze.
+    /// `None` unsnoozes it, leaving the snoozes of its item instances.
+    pub fn snooze_type(
+        &mut self,
+        type_id: TypeId,
+        snooze: Option<Snooze>,
+    ) -> Result<(), InventoryError> {
+        let it = self
+            .item_types
+            .iter_mut()
+            .find(|t| t.id == type_id)
+            .ok_or(InventoryError::UnknownItemType)?;
+        (it.snoozed_until, it.snoozed_forever) = fields(snooze);
+        Ok(())
+    }
+}
+
+/// The values of the `snoozed_until` and `snoozed_forever` fields.
+fn fields(snooze: Option<Snooze>) -> (Option<SystemTime>, bool) {
+    match snooze {
+        Some(Snooze::Until(until)) => (Some(until), false),
+        Some(Snooze::Forever) => (None, true),
+        None => (None, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    /// Vinegar and rice, with an item instance of each.
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        for name in ["vinegar", "rice"] {
+            let item_type = inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                ..ItemType::default()
+            });
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        inventory
+    }
+
+    fn snoozed(inventory: &Inventory, now: SystemTime) -> Vec<bool> {
+        inventory
+            .item_instances
+            .iter()
+            .map(|ii| inventory.is_snoozed(ii, now))
+            .collect()
+    }
+
+    #[test]
+    fn snoozes_until_a_time_expire_by_themselves() {
+        let mut inventory = inventory();
+        assert_eq!(snoozed(&inventory, start()), vec![false, false]);
+        inventory
+            .snooze_instance(InstanceId(2), Some(Snooze::Until(start() + DAY * 30)))
+            .unwrap();
+        assert_eq!(snoozed(&inventory, start()), vec![false, true]);
+        assert_eq!(snoozed(&inventory, start() + DAY * 30), vec![false, false]);
+        assert_eq!(
+            inventory.item_instances[1].snoozed_until,
+            Some(start() + DAY * 30)
+        );
+    }
+
+    #[test]
+    fn forever_lasts_until_unsnoozed() {
+        let mut inventory = inventory();
+        inventory
+            .snooze_type(TypeId(1), Some(Snooze::Forever))
+            .unwrap();
+        let far = start() + DAY * 36500;
+        assert_eq!(snoozed(&inventory, far), vec![true, false]);
+        assert!(inventory.item_types[0].snoozed_forever);
+        assert_eq!(inventory.item_types[0].snoozed_until, None);
+
+        // A snooze replaces the previous one, and unsnoozing the item type leaves the
+        // snoozes of its item instances.
+        inventory
+            .snooze_instance(InstanceId(1), Some(Snooze::Forever))
+            .unwrap();
+        inventory
+            .snooze_type(TypeId(1), Some(Snooze::Until(start())))
+            .unwrap();
+        assert!(!inventory.item_types[0].snoozed_forever);
+        inventory.snooze_type(TypeId(1), None).unwrap();
+        assert_eq!(snoozed(&inventory, far), vec![true, false]);
+        inventory.snooze_instance(InstanceId(1), None).unwrap();
+        assert_eq!(snoozed(&inventory, far), vec![false, false]);
+
+        assert_eq!(
+            inventory.snooze_type(TypeId(9), None),
+            Err(InventoryError::UnknownItemType)
+        );
+        assert_eq!(
+            inventory.snooze_instance(InstanceId(9), None),
+            Err(InventoryError::UnknownItemInstance)
+        );
+    }
+}
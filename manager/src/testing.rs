@@ -0,0 +1,511 @@
+//! Fake but realistic inventories, for demos, benchmarks and tests.
+//!
+//! The data only depends on the seed and on the time of the clock of the inventory: food
+//! with varied ttls and units, some expired, some below their minimum, some opened and some
+//! in the trash. The first item type and the first two item instances set every optional
+//! field, so that any inventory generated with at least one item type and two item
+//! instances exercises all of them, except `integer_only` which is set on the item types
+//! counted in pieces, like the third one.
+
+use crate::{
+    FieldDef, FieldKind, FieldValue, InstanceId, Inventory, ItemInstance, ItemType, SeasonRule,
+    TypeId, GIVEN_AWAY,
+};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The names of the item types, with their unit and their ttl in days once opened.
+const FOODS: &[(&str, Option<&str>, Option<u32>)] = &[
+    ("milk", Some("l"), Some(4)),
+    ("rice", Some("kg"), None),
+    ("eggs", Some("pieces"), None),
+    ("olive oil", Some("l"), Some(90)),
+    ("flour", Some("kg"), Some(60)),
+    ("butter", None, Some(30)),
+    ("pasta", Some("kg"), None),
+    ("yogurt", Some("pieces"), Some(3)),
+    ("coffee", Some("kg"), Some(30)),
+    ("orange juice", Some("l"), Some(7)),
+    ("tomato sauce", None, Some(5)),
+    ("cheddar", Some("kg"), Some(21)),
+    ("apples", Some("pieces"), None),
+    ("sugar", Some("kg"), None),
+    ("honey", None, None),
+    ("oat milk", Some("l"), Some(7)),
+    ("lentils", Some("kg"), None),
+    ("sourdough starter", None, None),
+    ("mustard", None, Some(180)),
+    ("canned tuna", Some("pieces"), Some(2)),
+    ("frozen peas", Some("kg"), Some(30)),
+    ("cream", Some("l"), Some(3)),
+    ("chocolate", None, Some(60)),
+    ("tea", Some("pieces"), None),
+    ("soy sauce", Some("l"), Some(365)),
+];
+
+const LOCATIONS: &[&str] = &["pantry", "fridge", "freezer", "cellar", "kitchen"];
+const CLASSES: &[&str] = &["dairy", "dry goods", "produce"];
+const CATEGORIES: &[&str] = &["Food/Dairy", "Food/Baking", "Food/Pantry", "Drinks"];
+const ICONS: &[&str] = &["🥫", "🧂", "food-can", "pantry/jar.png"];
+const VENDORS: &[&str] = &["market", "corner shop", "online"];
+const RECIPIENTS: &[&str] = &["Alex", "Sam", "the food bank"];
+const REASONS: &[&str] = &["used", "used", "expired", "broken"];
+
+/// How many item types and item instances to generate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sizes {
+    pub types: usize,
+    pub instances: usize,
+}
+
+impl Default for Sizes {
+    fn default() -> Self {
+        Sizes {
+            types: 20,
+            instances: 60,
+        }
+    }
+}
+
+/// A small deterministic random number generator, splitmix64.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 to `n` excluded.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+
+    fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+
+    /// A duration from 0 to `max` excluded, in whole hours.
+    fn hours_below(&mut self, max: Duration) -> Duration {
+        HOUR * self.below(max.as_secs() / HOUR.as_secs()) as u32
+    }
+}
+
+/// The smaller unit in which an item instance of this unit can be counted.
+fn smaller_unit(unit: &str) -> Option<(&'static str, f32)> {
+    match unit {
+        "kg" => Some(("g", 1000.0)),
+        "l" => Some(("cl", 100.0)),
+        _ => None,
+    }
+}
+
+fn item_type(rng: &mut Rng, index: usize, now: SystemTime) -> ItemType {
+    let every = index == 0;
+    let (name, unit, ttl) = FOODS[index % FOODS.len()];
+    let round = index / FOODS.len();
+    let minimum_quantity = if every || rng.chance(40) {
+        (1 + rng.below(3)) as f32
+    } else {
+        0.0
+    };
+    let allow_overdraft = every || rng.chance(10);
+    ItemType {
+        id: TypeId(0),
+        name: if round == 0 {
+            name.to_string()
+        } else {
+            format!("{} {}", name, round + 1)
+        },
+        minimum_quantity,
+        ttl: ttl.map(|days| DAY * days),
+        opened_by_default: !every && rng.chance(10),
+        auto_trash_empty: !rng.chance(15),
+        minimum_by_location: if every || rng.chance(15) {
+            vec![(rng.pick(LOCATIONS).to_string(), 1.0)]
+        } else {
+            vec![]
+        },
+        minimum_counts_sealed_only: every || rng.chance(10),
+        class: if every || rng.chance(30) {
+            Some(rng.pick(CLASSES).to_string())
+        } else {
+            None
+        },
+        deposit: if every || rng.chance(10) {
+            Some(0.25)
+        } else {
+            None
+        },
+        unit: unit.map(String::from),
+        seasonal_minimums: if every || rng.chance(10) {
+            vec![SeasonRule {
+                start_month_day: (6, 1),
+                end_month_day: (8, 31),
+                minimum: minimum_quantity + 2.0,
+            }]
+        } else {
+            vec![]
+        },
+        remind_after_open: if every || rng.chance(10) {
+            Some(DAY * 7)
+        } else {
+            None
+        },
+        allow_overdraft,
+        recount_requested_at: if every || (allow_overdraft && rng.chance(50)) {
+            Some(now - rng.hours_below(DAY * 10))
+        } else {
+            None
+        },
+        integer_only: unit == Some("pieces"),
+        category: if every || rng.chance(70) {
+            Some(rng.pick(CATEGORIES).to_string())
+        } else {
+            None
+        },
+        depreciation_years: if every || rng.chance(5) {
+            Some((2 + rng.below(8)) as f32)
+        } else {
+            None
+        },
+        icon: if every || rng.chance(30) {
+            Some(rng.pick(ICONS).to_string())
+        } else {
+            None
+        },
+        vendor: if every || rng.chance(60) {
+            Some(rng.pick(VENDORS).to_string())
+        } else {
+            None
+        },
+        former_names: if every || rng.chance(5) {
+            vec![format!("old {}", name)]
+        } else {
+            vec![]
+        },
+        auto_trash_on_expiry: every || rng.chance(5),
+        snoozed_until: if every || rng.chance(2) {
+            Some(now + rng.hours_below(DAY * 30))
+        } else {
+            None
+        },
+        snoozed_forever: every || rng.chance(2),
+        custom_fields: if every || rng.chance(10) {
+            vec![
+                FieldDef {
+                    key: "brand".to_string(),
+                    kind: FieldKind::Text,
+                },
+                FieldDef {
+                    key: "organic".to_string(),
+                    kind: FieldKind::Bool,
+                },
+            ]
+        } else {
+            vec![]
+        },
+        package_size: if every || rng.chance(20) {
+            Some((1 + rng.below(4)) as f32)
+        } else {
+            None
+        },
+    }
+}
+
+/// An item instance of an item type, with `index == 0` setting every optional field and
+/// `index == 1` being given away.
+fn item_instance(rng: &mut Rng, index: usize, it: &ItemType, now: SystemTime) -> ItemInstance {
+    let every = index == 0;
+    let added_at = now - DAY - rng.hours_below(DAY * 120);
+    let opened_at = if every || rng.chance(30) {
+        Some(added_at + rng.hours_below(now.duration_since(added_at).unwrap_or_default()))
+    } else {
+        None
+    };
+    let removed = every || index == 1 || rng.chance(15);
+    let removed_at = if removed {
+        let after = opened_at.unwrap_or(added_at);
+        Some(after + rng.hours_below(now.duration_since(after).unwrap_or_default()))
+    } else {
+        None
+    };
+
+    let mut initial_quantity = (1 + rng.below(8)) as f32 * 0.5;
+    let mut unit_override = None;
+    if let Some((unit, factor)) = it.unit.as_deref().and_then(smaller_unit) {
+        if every || rng.chance(20) {
+            unit_override = Some(unit.to_string());
+            initial_quantity = (1 + rng.below(8)) as f32 * 0.1 * factor;
+        }
+    }
+    let mut quantity = if opened_at.is_some() {
+        initial_quantity * rng.below(4) as f32 / 4.0
+    } else {
+        initial_quantity
+    };
+    if it.integer_only {
+        initial_quantity = initial_quantity.ceil();
+        quantity = quantity.floor();
+    }
+
+    let mut expires_at = if every || rng.chance(70) {
+        Some(added_at + DAY + rng.hours_below(DAY * 150))
+    } else {
+        None
+    };
+    if let (Some(opened_at), Some(ttl)) = (opened_at, it.ttl) {
+        let opened_expiry = opened_at + ttl;
+        expires_at = Some(expires_at.map_or(opened_expiry, |e| e.min(opened_expiry)));
+    }
+    let best_before_at = if every || rng.chance(20) {
+        Some(expires_at.unwrap_or(added_at + DAY * 60) - rng.hours_below(DAY * 5))
+    } else {
+        None
+    };
+
+    let location = if every || rng.chance(80) {
+        Some(rng.pick(LOCATIONS).to_string())
+    } else {
+        None
+    };
+    let home_location = if every || rng.chance(15) {
+        Some(rng.pick(LOCATIONS).to_string())
+    } else {
+        None
+    };
+    let removal_reason = match removed {
+        true if index == 1 || rng.chance(5) => Some(GIVEN_AWAY.to_string()),
+        true => Some(rng.pick(REASONS).to_string()),
+        false => None,
+    };
+    let recipient = match &removal_reason {
+        Some(reason) if reason == GIVEN_AWAY => Some(rng.pick(RECIPIENTS).to_string()),
+        _ => None,
+    };
+    let returned_at = match (removed_at, it.deposit) {
+        (Some(removed_at), Some(_)) if every || rng.chance(50) => Some((removed_at + DAY).min(now)),
+        _ => None,
+    };
+    let last_reminded_at = match (opened_at, it.remind_after_open) {
+        (Some(opened_at), Some(after)) if every || rng.chance(50) => {
+            Some((opened_at + after).min(removed_at.unwrap_or(now)))
+        }
+        _ => None,
+    };
+
+    ItemInstance {
+        id: InstanceId(0),
+        item_type: it.id,
+        quantity,
+        model: if every || rng.chance(10) {
+            Some(format!("{} classic", it.name))
+        } else {
+            None
+        },
+        serial: if every || rng.chance(10) {
+            Some(format!("SN{:06}", rng.below(1_000_000)))
+        } else {
+            None
+        },
+        extra: if every || rng.chance(10) {
+            Some("bought on sale".to_string())
+        } else {
+            None
+        },
+        location,
+        home_location,
+        value: if every || rng.chance(60) {
+            Some((50 + rng.below(2000)) as f32 / 100.0)
+        } else {
+            None
+        },
+        opened_at,
+        expires_at,
+        best_before_at,
+        added_at: Some(added_at),
+        removed_at,
+        initial_quantity: Some(initial_quantity),
+        label: None,
+        returned_at,
+        unit_override,
+        removal_reason,
+        recipient,
+        compact_issued: every || rng.chance(10),
+        last_reminded_at,
+        continuous: every || rng.chance(5),
+        external_ref: if every || rng.chance(20) {
+            Some(format!("PO-{:05}", rng.below(100_000)))
+        } else {
+            None
+        },
+        vendor: if every || rng.chance(10) {
+            Some(rng.pick(VENDORS).to_string())
+        } else {
+            None
+        },
+        snoozed_until: match expires_at {
+            Some(expires_at) if every || rng.chance(3) => Some(expires_at + DAY * 30),
+            _ => None,
+        },
+        snoozed_forever: every || rng.chance(2),
+        custom: if every || rng.chance(50) {
+            it.custom_fields
+                .iter()
+                .map(|f| {
+                    let value = match f.kind {
+                        FieldKind::Bool => FieldValue::Bool(rng.chance(50)),
+                        _ => FieldValue::Text(format!("{} house brand", rng.pick(VENDORS))),
+                    };
+                    (f.key.clone(), value)
+                })
+                .collect()
+        } else {
+            BTreeMap::new()
+        },
+    }
+}
+
+impl Inventory {
+    /// Generates an inventory from a seed, at the time of the default clock, see
+    /// `add_generated`.
+    pub fn generate(seed: u64, sizes: Sizes) -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.add_generated(seed, sizes);
+        inventory
+    }
+
+    /// Adds generated item types and item instances to the inventory, at the time of its
+    /// clock. The same seed, sizes and time always give the same data. Returns the number of
+    /// item instances added, which is zero without item types.
+    pub fn add_generated(&mut self, seed: u64, sizes: Sizes) -> usize {
+        let mut rng = Rng(seed);
+        let now = self.now();
+        let mut types = vec![];
+        for index in 0..sizes.types {
+            let mut it = item_type(&mut rng, index, now);
+            it.id = self.add_item_type(it.clone());
+            types.push(it);
+        }
+        if types.is_empty() {
+            return 0;
+        }
+        for index in 0..sizes.instances {
+            let it = if index < 2 {
+                &types[0]
+            } else {
+                rng.pick(&types)
+            };
+            let ii = item_instance(&mut rng, index, it, now);
+            self.insert_item_instance(ii)
+                .expect("The item type was just added");
+        }
+        sizes.instances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FixedClock;
+    use std::sync::Arc;
+
+    fn generated(seed: u64, sizes: Sizes) -> Inventory {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let mut inventory = Inventory::default();
+        inventory.set_clock(Arc::new(FixedClock::new(now)));
+        inventory.add_generated(seed, sizes);
+        inventory
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_inventory() {
+        let sizes = Sizes::default();
+        let (a, b) = (generated(7, sizes), generated(7, sizes));
+        assert_eq!(a.item_types, b.item_types);
+        assert_eq!(a.item_instances, b.item_instances);
+        assert_ne!(a.item_instances, generated(8, sizes).item_instances);
+        let big = generated(
+            1,
+            Sizes {
+                types: 60,
+                instances: 500,
+            },
+        );
+        assert_eq!(big.item_types.len(), 60);
+        assert_eq!(big.item_instances.len(), 500);
+        assert_eq!(big.item_types[FOODS.len()].name, "milk 2");
+        assert!(big.check_timestamps().is_empty());
+        assert!(big.item_types[2].integer_only);
+        assert!(big.fractional_quantities().is_empty());
+        let none = generated(
+            1,
+            Sizes {
+                types: 0,
+                instances: 10,
+            },
+        );
+        assert!(none.item_instances.is_empty());
+    }
+
+    #[test]
+    fn every_optional_field_is_set_sometimes() {
+        let inventory = generated(
+            3,
+            Sizes {
+                types: 1,
+                instances: 2,
+            },
+        );
+        let it = &inventory.item_types[0];
+        assert!(it.minimum_quantity > 0.0 && it.ttl.is_some());
+        assert!(!it.minimum_by_location.is_empty() && !it.seasonal_minimums.is_empty());
+        assert!(it.minimum_counts_sealed_only && it.allow_overdraft);
+        assert!(it.class.is_some() && it.deposit.is_some() && it.unit.is_some());
+        assert!(it.remind_after_open.is_some() && it.recount_requested_at.is_some());
+        assert!(it.category.is_some() && it.depreciation_years.is_some() && it.icon.is_some());
+        assert!(it.vendor.is_some() && !it.former_names.is_empty() && it.auto_trash_on_expiry);
+        assert!(it.snoozed_until.is_some() && it.snoozed_forever);
+        assert!(!it.custom_fields.is_empty() && it.package_size.is_some());
+
+        let ii = &inventory.item_instances[0];
+        assert!(ii.model.is_some() && ii.serial.is_some() && ii.extra.is_some());
+        assert!(ii.location.is_some() && ii.home_location.is_some() && ii.value.is_some());
+        assert!(ii.opened_at.is_some() && ii.expires_at.is_some());
+        assert!(ii.best_before_at.is_some() && ii.added_at.is_some());
+        assert!(ii.removed_at.is_some() && ii.initial_quantity.is_some());
+        assert!(ii.label.is_some() && ii.returned_at.is_some());
+        assert!(ii.unit_override.is_some() && ii.removal_reason.is_some());
+        assert!(ii.compact_issued && ii.last_reminded_at.is_some() && ii.continuous);
+        assert!(ii.external_ref.is_some() && ii.vendor.is_some());
+        assert!(ii.snoozed_until.is_some() && ii.snoozed_forever);
+        assert_eq!(ii.custom.len(), it.custom_fields.len());
+        assert!(inventory.item_instances[1].recipient.is_some());
+    }
+
+    #[test]
+    fn generated_inventories_look_lived_in() {
+        let inventory = generated(11, Sizes::default());
+        let now = inventory.now();
+        let instances = &inventory.item_instances;
+        assert!(instances.iter().any(|ii| ii.removed_at.is_some()));
+        assert!(instances
+            .iter()
+            .any(|ii| ii.removed_at.is_none() && ii.opened_at.is_some()));
+        assert!(instances
+            .iter()
+            .any(|ii| ii.removed_at.is_none() && ii.expires_at.is_some_and(|e| e < now)));
+        assert!(!inventory.shortages().is_empty());
+        assert!(inventory.item_types.iter().any(|it| it.ttl.is_some()));
+    }
+}
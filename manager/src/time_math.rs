@@ -0,0 +1,72 @@
+//! Arithmetic on timestamps that never panics, whatever the durations and the order of the
+//! timestamps: a ttl of absurd length, or dates in the future of each other after a clock
+//! change or an edit of the files by hand.
+
+use crate::date;
+use std::time::{Duration, SystemTime};
+
+/// The first year of the timestamps that make sense in an inventory.
+pub const EARLIEST_YEAR: i32 = 2000;
+/// The last year of the timestamps that make sense in an inventory.
+pub const LATEST_YEAR: i32 = 2200;
+
+/// `time + duration`, or `None` when it is too far in the future to be represented.
+pub fn checked_add_duration(time: SystemTime, duration: Duration) -> Option<SystemTime> {
+    time.checked_add(duration)
+}
+
+/// `time - duration`, or the earliest time that can be represented.
+pub fn saturating_sub_duration(time: SystemTime, duration: Duration) -> SystemTime {
+    time.checked_sub(duration)
+        .unwrap_or(SystemTime::UNIX_EPOCH.min(time))
+}
+
+/// The time from `earlier` to `later`, zero when `later` comes first.
+pub fn saturating_since(later: SystemTime, earlier: SystemTime) -> Duration {
+    later.duration_since(earlier).unwrap_or_default()
+}
+
+/// Whether `time` has come at `now`.
+pub fn is_past(time: SystemTime, now: SystemTime) -> bool {
+    time <= now
+}
+
+/// Whether a stored timestamp is in a year that makes sense, from `EARLIEST_YEAR` to
+/// `LATEST_YEAR`.
+pub fn is_plausible(time: SystemTime) -> bool {
+    (EARLIEST_YEAR..=LATEST_YEAR).contains(&date::civil_date(time).0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+    const FOREVER: Duration = Duration::from_secs(u64::MAX);
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    #[test]
+    fn extreme_durations_do_not_panic() {
+        assert_eq!(checked_add_duration(start(), DAY), Some(start() + DAY));
+        assert_eq!(checked_add_duration(start(), FOREVER), None);
+        assert_eq!(saturating_sub_duration(start(), DAY), start() - DAY);
+        assert!(saturating_sub_duration(start(), FOREVER) <= SystemTime::UNIX_EPOCH);
+        assert_eq!(saturating_since(start() + DAY, start()), DAY);
+        assert_eq!(saturating_since(start(), start() + DAY), Duration::ZERO);
+        assert!(is_past(start(), start()));
+        assert!(!is_past(start() + DAY, start()));
+    }
+
+    #[test]
+    fn timestamps_outside_of_2000_to_2200_are_implausible() {
+        assert!(is_plausible(start()));
+        assert!(is_plausible(date::from_civil_date(2000, 1, 1)));
+        assert!(!is_plausible(date::from_civil_date(1999, 12, 31)));
+        assert!(is_plausible(date::from_civil_date(2200, 12, 31)));
+        assert!(!is_plausible(date::from_civil_date(2201, 1, 1)));
+        assert!(!is_plausible(SystemTime::UNIX_EPOCH));
+    }
+}
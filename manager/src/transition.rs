@@ -0,0 +1,201 @@
+//! The moments item instances become expired, recorded once by a maintenance job.
+//!
+//! Whether an item instance is expired is computed from its `expires_at` whenever it is
+//! read, so nothing tells when it became expired. `inv update-state`, run by cron, looks for
+//! the item instances whose expiry passed since its previous run and records each as an
+//! `Event::Expired` of the event log, trashing those of the item types with
+//! `auto_trash_on_expiry`.
+//!
+//! A transition is the crossing of one expiry by one item instance: running twice over the
+//! same time records nothing new, while moving the expiry and crossing it again does. The
+//! time of the last run never goes back, so a clock set back doesn't scan a time again.
+
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{InstanceId, Inventory, TypeId, EXPIRED};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::SystemTime;
+
+/// An item instance whose expiry passed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ExpiryTransition {
+    pub instance_id: InstanceId,
+    pub type_id: TypeId,
+    pub expired_at: SystemTime,
+    /// Whether its item type puts it in the trash, see `Inventory::trash_expired`.
+    pub auto_trash: bool,
+}
+
+#[cfg(feature = "display")]
+impl Record for ExpiryTransition {
+    const HEADER: &'static [&'static str] = &["instance id", "type id", "expired at", "auto trash"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.instance_id.to_string(),
+            self.type_id.to_string(),
+            humantime::format_rfc3339(self.expired_at).to_string(),
+            self.auto_trash.to_string(),
+        ]
+    }
+}
+
+impl Inventory {
+    /// Returns the item instances not removed whose expiry is after `since`, when given, and
+    /// not after now, ordered by expiry. The transitions already `recorded`, as the id and
+    /// the expiry, are left out.
+    pub fn expiry_transitions(
+        &self,
+        since: Option<SystemTime>,
+        recorded: &HashSet<(InstanceId, SystemTime)>,
+    ) -> Vec<ExpiryTransition> {
+        let now = self.now();
+        let types = self.types_by_id();
+        let mut present = self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+            .collect::<Vec<_>>();
+        present.sort_by(|a, b| a.cmp_expiry(b));
+        present
+            .into_iter()
+            .filter_map(|ii| {
+                let expired_at = ii.expires_at?;
+                let crossed = expired_at <= now && since.is_none_or(|since| expired_at > since);
+                (crossed && !recorded.contains(&(ii.id, expired_at))).then(|| ExpiryTransition {
+                    instance_id: ii.id,
+                    type_id: ii.item_type,
+                    expired_at,
+                    auto_trash: types
+                        .get(&ii.item_type)
+                        .is_some_and(|t| t.auto_trash_on_expiry),
+                })
+            })
+            .collect()
+    }
+
+    /// Puts the item instances of the transitions with `auto_trash` in the trash, with the
+    /// reason `expired`.
+    pub fn trash_expired(&mut self, transitions: &[ExpiryTransition]) {
+        transitions
+            .iter()
+            .filter(|t| t.auto_trash)
+            .for_each(|t| self.trash_with_reason(t.instance_id, Some(EXPIRED)));
+    }
+}
+
+/// The time to remember as the last run: now, unless the clock went back since the
+/// previous run.
+pub fn next_run(last_run: Option<SystemTime>, now: SystemTime) -> SystemTime {
+    last_run.map_or(now, |last_run| last_run.max(now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Event, EventLog, FixedClock, ItemInstance, ItemType};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn at(hours: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000 + hours * 3600)
+    }
+
+    /// Milk trashed on expiry and rice kept, with an item instance of each expiring at the
+    /// hours given.
+    fn inventory(expiries: &[u64]) -> Inventory {
+        let mut inventory = Inventory::default();
+        let milk = inventory.add_item_type(ItemType {
+            name: "milk".to_string(),
+            auto_trash_on_expiry: true,
+            ..ItemType::default()
+        });
+        let rice = inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            ..ItemType::default()
+        });
+        for hours in expiries {
+            for item_type in [milk, rice] {
+                inventory
+                    .add_item_instance(ItemInstance {
+                        item_type,
+                        expires_at: Some(at(*hours)),
+                        ..ItemInstance::default()
+                    })
+                    .unwrap();
+            }
+        }
+        inventory
+    }
+
+    fn ids(transitions: &[ExpiryTransition]) -> Vec<u32> {
+        transitions.iter().map(|t| t.instance_id.0).collect()
+    }
+
+    /// Runs the job at a time like `inv update-state`, returning the new transitions.
+    fn run(
+        inventory: &mut Inventory,
+        log: &mut EventLog,
+        last_run: &mut Option<SystemTime>,
+        now: SystemTime,
+    ) -> Vec<ExpiryTransition> {
+        inventory.set_clock(Arc::new(FixedClock::new(now)));
+        let transitions = inventory.expiry_transitions(*last_run, &log.recorded_expiries());
+        inventory.trash_expired(&transitions);
+        log.events
+            .extend(transitions.iter().map(|t| Event::expired(t, now)));
+        *last_run = Some(next_run(*last_run, now));
+        transitions
+    }
+
+    #[test]
+    fn crossed_expiries_are_recorded_once() {
+        let mut inventory = inventory(&[2, 5, 9]);
+        let (mut log, mut last_run) = (EventLog::default(), Some(at(3)));
+
+        // Crossed before the last run, crossed since and not crossed yet.
+        let transitions = run(&mut inventory, &mut log, &mut last_run, at(6));
+        assert_eq!(ids(&transitions), vec![3, 4]);
+        assert!(transitions[0].auto_trash && !transitions[1].auto_trash);
+        assert_eq!(transitions[0].expired_at, at(5));
+        assert_eq!(inventory.item_instances[2].removed_at, Some(at(6)));
+        assert_eq!(
+            inventory.item_instances[2].removal_reason.as_deref(),
+            Some(EXPIRED)
+        );
+        assert_eq!(inventory.item_instances[3].removed_at, None);
+
+        assert!(run(&mut inventory, &mut log, &mut last_run, at(6)).is_empty());
+        assert_eq!(
+            ids(&run(&mut inventory, &mut log, &mut last_run, at(9))),
+            vec![5, 6]
+        );
+        assert_eq!(log.recorded_expiries().len(), 4);
+    }
+
+    #[test]
+    fn a_clock_set_back_records_nothing_twice() {
+        let mut inventory = inventory(&[2, 5]);
+        let (mut log, mut last_run) = (EventLog::default(), None);
+        assert_eq!(
+            ids(&run(&mut inventory, &mut log, &mut last_run, at(6))),
+            vec![1, 2, 3, 4]
+        );
+
+        assert!(run(&mut inventory, &mut log, &mut last_run, at(3)).is_empty());
+        assert_eq!(last_run, Some(at(6)));
+
+        // Without the time of the last run, the log still tells what was recorded.
+        inventory.item_instances[0].removed_at = None;
+        assert!(run(&mut inventory, &mut log, &mut None, at(7)).is_empty());
+
+        // A new expiry is crossed again.
+        inventory.item_instances[3].expires_at = Some(at(8));
+        let transitions = run(&mut inventory, &mut log, &mut last_run, at(8));
+        assert_eq!(ids(&transitions), vec![4]);
+        assert_eq!(transitions[0].expired_at, at(8));
+    }
+}
@@ -0,0 +1,72 @@
+//! Conversion of quantities between the metric units of mass and volume.
+//!
+//! Units are compared ignoring case. Other units, like `pieces`, are only compatible
+//! with themselves.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dimension {
+    Mass,
+    Volume,
+}
+
+/// The units that can be converted, with their size in milligrams or millilitres.
+const UNITS: &[(&str, Dimension, f32)] = &[
+    ("mg", Dimension::Mass, 1.0),
+    ("g", Dimension::Mass, 1000.0),
+    ("kg", Dimension::Mass, 1_000_000.0),
+    ("ml", Dimension::Volume, 1.0),
+    ("cl", Dimension::Volume, 10.0),
+    ("l", Dimension::Volume, 1000.0),
+];
+
+fn find(unit: &str) -> Option<(Dimension, f32)> {
+    UNITS
+        .iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(unit))
+        .map(|(_, dimension, size)| (*dimension, *size))
+}
+
+/// Whether the units are the same, ignoring case.
+pub fn same(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+/// Converts a quantity from a unit to another. Returns `None` when they are not the same
+/// unit and are not both known units of the same dimension.
+pub fn convert(quantity: f32, from: &str, to: &str) -> Option<f32> {
+    if same(from, to) {
+        return Some(quantity);
+    }
+    match (find(from), find(to)) {
+        (Some((from_dimension, from_size)), Some((to_dimension, to_size)))
+            if from_dimension == to_dimension =>
+        {
+            Some(quantity * from_size / to_size)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_within_a_dimension() {
+        assert_eq!(convert(500.0, "g", "kg"), Some(0.5));
+        assert_eq!(convert(2.0, "kg", "mg"), Some(2_000_000.0));
+        assert_eq!(convert(0.25, "kg", "g"), Some(250.0));
+        assert_eq!(convert(250.0, "mg", "g"), Some(0.25));
+        assert_eq!(convert(33.0, "cl", "l"), Some(0.33));
+        assert_eq!(convert(1.5, "L", "ml"), Some(1500.0));
+        assert_eq!(convert(3.0, "pieces", "Pieces"), Some(3.0));
+    }
+
+    #[test]
+    fn refuses_other_dimensions_and_unknown_units() {
+        assert_eq!(convert(1.0, "kg", "l"), None);
+        assert_eq!(convert(1.0, "ml", "g"), None);
+        assert_eq!(convert(1.0, "pieces", "g"), None);
+        assert_eq!(convert(1.0, "g", "oz"), None);
+    }
+}
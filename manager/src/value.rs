@@ -0,0 +1,173 @@
+//! What the item instances are worth now, for durable things losing value over the years.
+
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{time_math, Inventory, ItemInstance, ItemType, TypeId};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+
+/// The length of a year for depreciation, 365.25 days.
+pub const YEAR: Duration = Duration::from_secs(31_557_600);
+
+/// The value left after losing the same part of it every day for `years`, nothing after.
+/// A horizon that is not positive leaves nothing.
+pub fn depreciated(value: f32, years: f32, age: Duration) -> f32 {
+    if years.is_nan() || years <= 0.0 {
+        return 0.0;
+    }
+    let elapsed = age.as_secs_f64() / (YEAR.as_secs_f64() * years as f64);
+    (value * (1.0 - elapsed.min(1.0)) as f32).max(0.0)
+}
+
+impl ItemInstance {
+    /// The value of the item instance at `now`, depreciated if its item type has
+    /// `depreciation_years`. Item instances without `added_at` keep their whole value, as
+    /// their age is unknown. Returns `None` without a value.
+    pub fn current_value(&self, item_type: Option<&ItemType>, now: SystemTime) -> Option<f32> {
+        let value = self.value?;
+        match (item_type.and_then(|t| t.depreciation_years), self.added_at) {
+            (Some(years), Some(added_at)) => {
+                let age = time_math::saturating_since(now, added_at);
+                Some(depreciated(value, years, age))
+            }
+            _ => Some(value),
+        }
+    }
+}
+
+/// The value of the item instances of an item type.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TypeValue {
+    pub type_id: TypeId,
+    /// The number of item instances with a value.
+    pub instances: u32,
+    /// The sum of their values when they were added.
+    pub value: f32,
+    /// The sum of their values now, see `ItemInstance::current_value`.
+    pub current_value: f32,
+}
+
+#[cfg(feature = "display")]
+impl Record for TypeValue {
+    const HEADER: &'static [&'static str] = &["type id", "instances", "value", "current value"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.type_id.to_string(),
+            self.instances.to_string(),
+            self.value.to_string(),
+            self.current_value.to_string(),
+        ]
+    }
+}
+
+impl Inventory {
+    /// The value of the item instances that are not removed, per item type in ascending
+    /// order of id, at the time of the clock. Item types without a valued item instance are
+    /// left out.
+    pub fn values_by_type(&self) -> Vec<TypeValue> {
+        let now = self.now();
+        let types = self.types_by_id();
+        let mut values = BTreeMap::<TypeId, TypeValue>::new();
+        for ii in self
+            .item_instances
+            .iter()
+            .filter(|ii| ii.removed_at.is_none())
+        {
+            let item_type = types.get(&ii.item_type).copied();
+            let (value, current_value) = match (ii.value, ii.current_value(item_type, now)) {
+                (Some(value), Some(current_value)) => (value, current_value),
+                _ => continue,
+            };
+            let entry = values.entry(ii.item_type).or_insert(TypeValue {
+                type_id: ii.item_type,
+                instances: 0,
+                value: 0.0,
+                current_value: 0.0,
+            });
+            entry.instances += 1;
+            entry.value += value;
+            entry.current_value += current_value;
+        }
+        values.into_values().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FixedClock;
+    use std::sync::Arc;
+
+    #[test]
+    fn values_decrease_in_a_straight_line_to_zero() {
+        let cases: &[(f32, f32, f32)] = &[
+            (0.0, 4.0, 100.0),
+            (1.0, 4.0, 75.0),
+            (2.0, 4.0, 50.0),
+            (4.0, 4.0, 0.0),
+            (10.0, 4.0, 0.0),
+            (0.5, 0.5, 0.0),
+            (1.0, 0.0, 0.0),
+        ];
+        for (age, years, value) in cases {
+            let left = depreciated(100.0, *years, YEAR.mul_f32(*age));
+            assert!((left - value).abs() < 1e-3, "{} years of {}", age, years);
+        }
+    }
+
+    #[test]
+    fn current_values_use_the_clock() {
+        let added = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let clock = Arc::new(FixedClock::new(added));
+        let mut inventory = Inventory::default();
+        inventory.set_clock(clock.clone());
+        let drill = inventory.add_item_type(ItemType {
+            name: "drill".to_string(),
+            depreciation_years: Some(2.0),
+            ..ItemType::default()
+        });
+        let rice = inventory.add_item_type(ItemType {
+            name: "rice".to_string(),
+            ..ItemType::default()
+        });
+        for (item_type, value) in [(drill, Some(120.0)), (drill, None), (rice, Some(3.0))] {
+            inventory
+                .add_item_instance(ItemInstance {
+                    item_type,
+                    value,
+                    ..ItemInstance::default()
+                })
+                .unwrap();
+        }
+        let current = |inventory: &Inventory| {
+            inventory
+                .values_by_type()
+                .iter()
+                .map(|v| (v.type_id, v.instances, v.value, v.current_value))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            current(&inventory),
+            vec![(drill, 1, 120.0, 120.0), (rice, 1, 3.0, 3.0)]
+        );
+        clock.advance(YEAR);
+        assert_eq!(
+            current(&inventory),
+            vec![(drill, 1, 120.0, 60.0), (rice, 1, 3.0, 3.0)]
+        );
+        clock.advance(YEAR * 3);
+        assert_eq!(current(&inventory)[0].3, 0.0);
+
+        let mut unknown_age = inventory.item_instances[0].clone();
+        unknown_age.added_at = None;
+        let drill_type = inventory.item_types.first();
+        assert_eq!(
+            unknown_age.current_value(drill_type, inventory.now()),
+            Some(120.0)
+        );
+    }
+}
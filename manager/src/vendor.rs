@@ -0,0 +1,143 @@
+//! Where the item types are usually bought, to split the shopping list per store.
+
+use crate::query::none_last;
+use crate::{fold_case, Inventory, Shortage, TypeId};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+/// Items, like shortages, whose item types are bought at the same vendor.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct VendorGroup<T> {
+    /// The vendor as written for the first item type of the group, ignoring case, or `None`
+    /// for the item types without a vendor.
+    pub vendor: Option<String>,
+    pub items: Vec<T>,
+}
+
+impl Inventory {
+    /// The vendor where the item type is usually bought, if it has one.
+    pub fn vendor_of(&self, type_id: TypeId) -> Option<&str> {
+        self.item_types
+            .iter()
+            .find(|t| t.id == type_id)
+            .and_then(|t| t.vendor.as_deref())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    }
+
+    /// Whether the item type is usually bought at this vendor, ignoring case.
+    pub fn bought_at(&self, type_id: TypeId, vendor: &str) -> bool {
+        self.vendor_of(type_id)
+            .is_some_and(|v| fold_case(v) == fold_case(vendor.trim()))
+    }
+
+    /// Groups items by the vendor of their item type, given by `type_id`. The vendors are
+    /// in alphabetical order, ignoring case, followed by the item types without a vendor.
+    /// The items keep their order within a group.
+    pub fn group_by_vendor<T>(
+        &self,
+        items: Vec<T>,
+        type_id: impl Fn(&T) -> TypeId,
+    ) -> Vec<VendorGroup<T>> {
+        let mut groups: Vec<(Option<String>, VendorGroup<T>)> = vec![];
+        for item in items {
+            let vendor = self.vendor_of(type_id(&item));
+            let key = vendor.map(fold_case);
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.items.push(item),
+                None => groups.push((
+                    key,
+                    VendorGroup {
+                        vendor: vendor.map(str::to_string),
+                        items: vec![item],
+                    },
+                )),
+            }
+        }
+        groups.sort_by(|(a, _), (b, _)| none_last(a, b));
+        groups.into_iter().map(|(_, group)| group).collect()
+    }
+
+    /// The shortages, see `shortages`, grouped by vendor, see `group_by_vendor`.
+    pub fn shortages_by_vendor(&self) -> Vec<VendorGroup<Shortage>> {
+        self.group_by_vendor(self.shortages(), |s| s.type_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ItemType;
+
+    fn inventory() -> Inventory {
+        let mut inventory = Inventory::default();
+        let types = [
+            ("rice", Some("Market")),
+            ("salt", None),
+            ("milk", Some("corner shop")),
+            ("flour", Some("market ")),
+            ("oil", Some(" ")),
+        ];
+        for (name, vendor) in types {
+            inventory.add_item_type(ItemType {
+                name: name.to_string(),
+                minimum_quantity: 1.0,
+                vendor: vendor.map(str::to_string),
+                ..ItemType::default()
+            });
+        }
+        inventory
+    }
+
+    #[test]
+    fn shortages_are_grouped_by_vendor_with_the_unassigned_last() {
+        let inventory = inventory();
+        let groups = inventory
+            .shortages_by_vendor()
+            .into_iter()
+            .map(|g| (g.vendor, g.items.iter().map(|s| s.type_id.0).collect()))
+            .collect::<Vec<(Option<String>, Vec<u32>)>>();
+        assert_eq!(
+            groups,
+            vec![
+                (Some("corner shop".to_string()), vec![3]),
+                (Some("Market".to_string()), vec![1, 4]),
+                (None, vec![2, 5]),
+            ]
+        );
+    }
+
+    #[test]
+    fn types_without_a_vendor_are_bought_nowhere() {
+        let inventory = inventory();
+        assert!(inventory.bought_at(TypeId(1), "market"));
+        assert!(inventory.bought_at(TypeId(4), " MARKET"));
+        assert!(!inventory.bought_at(TypeId(2), "market"));
+        assert!(!inventory.bought_at(TypeId(5), " "));
+        assert_eq!(inventory.vendor_of(TypeId(2)), None);
+        assert_eq!(inventory.vendor_of(TypeId(5)), None);
+        assert_eq!(inventory.vendor_of(TypeId(9)), None);
+        assert!(Inventory::default().shortages_by_vendor().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn types_and_instances_without_a_vendor_still_load() {
+        let item_type: ItemType = serde_json::from_str(
+            r#"{"id": 1, "name": "rice", "minimum_quantity": 0.0, "ttl": null,
+                "opened_by_default": false}"#,
+        )
+        .unwrap();
+        assert_eq!(item_type.vendor, None);
+        let ii: crate::ItemInstance =
+            serde_json::from_str(r#"{"id": 1, "item_type": 1, "quantity": 2.0}"#).unwrap();
+        assert_eq!(ii.vendor, None);
+        let json = serde_json::to_value(ItemType {
+            vendor: Some("market".to_string()),
+            ..ItemType::default()
+        })
+        .unwrap();
+        assert_eq!(json["vendor"], "market");
+    }
+}
@@ -0,0 +1,266 @@
+//! How much of the inventory is thrown away instead of being used up.
+
+use crate::date;
+#[cfg(feature = "display")]
+use crate::Record;
+use crate::{Inventory, GIVEN_AWAY};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// The reasons for removing an item instance that the command line offers.
+pub const REMOVAL_REASONS: &[&str] = &[EXPIRED, "used", "broken", GIVEN_AWAY];
+
+/// The reason recorded for item instances trashed past their use-by date with some quantity
+/// left, when no other reason is given.
+pub const EXPIRED: &str = "expired";
+
+/// The reason reported for item instances removed without one, like those removed by older
+/// versions.
+pub const UNSPECIFIED: &str = "unspecified";
+
+/// What was removed during a month for a reason.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WasteEntry {
+    /// The month the item instances were removed in, as `YYYY-MM` in UTC.
+    pub month: String,
+    pub reason: String,
+    /// How many item instances were removed with some quantity left.
+    pub instances: u32,
+    /// The sum of the quantities left, in the units of the item instances.
+    pub quantity: f32,
+    /// The part of the value of the item instances that was left, for those with a value.
+    pub value: f32,
+}
+
+#[cfg(feature = "display")]
+impl Record for WasteEntry {
+    const HEADER: &'static [&'static str] = &["month", "reason", "instances", "quantity", "value"];
+
+    fn to_record(&self) -> Vec<String> {
+        vec![
+            self.month.clone(),
+            self.reason.clone(),
+            self.instances.to_string(),
+            self.quantity.to_string(),
+            self.value.to_string(),
+        ]
+    }
+}
+
+impl Inventory {
+    /// Sums what was left in the item instances removed during the last months, the month of
+    /// `now` included, per month and per reason. Item instances removed empty or given away
+    /// are not counted, see `given` for the latter.
+    /// Entries are ordered by month, then by reason.
+    pub fn waste_by_month(&self, now: SystemTime, months: u32) -> Vec<WasteEntry> {
+        let since = first_month(now, months);
+
+        let mut entries: Vec<WasteEntry> = vec![];
+        for ii in &self.item_instances {
+            let removed_at = match ii.removed_at {
+                Some(removed_at) if removed_at <= now && ii.quantity > 0.0 => removed_at,
+                _ => continue,
+            };
+            let month = month_of(removed_at);
+            if month < since {
+                continue;
+            }
+            let reason = ii.removal_reason.as_deref().unwrap_or(UNSPECIFIED);
+            if reason == GIVEN_AWAY {
+                continue;
+            }
+            let index = match entries
+                .iter()
+                .position(|e| e.month == month && e.reason == reason)
+            {
+                Some(index) => index,
+                None => {
+                    entries.push(WasteEntry {
+                        month,
+                        reason: reason.to_string(),
+                        instances: 0,
+                        quantity: 0.0,
+                        value: 0.0,
+                    });
+                    entries.len() - 1
+                }
+            };
+            let entry = &mut entries[index];
+            entry.instances += 1;
+            entry.quantity += ii.quantity;
+            entry.value += ii.unit_price().map(|p| p * ii.quantity).unwrap_or(0.0);
+        }
+        entries.sort_by(|a, b| (&a.month, &a.reason).cmp(&(&b.month, &b.reason)));
+        entries
+    }
+}
+
+/// The month of a time in UTC, as `YYYY-MM`.
+pub(crate) fn month_of(time: SystemTime) -> String {
+    let (year, month, _) = date::civil_date(time);
+    format!("{:04}-{:02}", year, month)
+}
+
+/// The first of the last `months` months, the month of `now` included, as `YYYY-MM`.
+pub(crate) fn first_month(now: SystemTime, months: u32) -> String {
+    let (year, month, _) = date::civil_date(now);
+    let first = i64::from(year) * 12 + i64::from(month) - 1 - i64::from(months.saturating_sub(1));
+    format!(
+        "{:04}-{:02}",
+        first.div_euclid(12),
+        first.rem_euclid(12) + 1
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FixedClock, InstanceId, InventoryError, ItemInstance, ItemType, TypeId};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// 2020-09-13, in the middle of a month.
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+    }
+
+    /// Adds an item instance worth 1.0 per unit on `added`, which is removed on `removed`
+    /// with `left` of it left.
+    fn add_removed(
+        inventory: &mut Inventory,
+        added: SystemTime,
+        removed: SystemTime,
+        left: f32,
+        reason: Option<&str>,
+    ) -> InstanceId {
+        inventory.set_clock(Arc::new(FixedClock::new(added)));
+        let id = inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                quantity: 2.0,
+                value: Some(2.0),
+                expires_at: Some(added + DAY * 5),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        inventory
+            .item_instances
+            .iter_mut()
+            .find(|ii| ii.id == id)
+            .unwrap()
+            .quantity = left;
+        inventory.set_clock(Arc::new(FixedClock::new(removed)));
+        inventory.trash_with_reason(id, reason);
+        id
+    }
+
+    fn kitchen() -> Inventory {
+        let mut inventory = Inventory::default();
+        inventory.add_item_type(ItemType {
+            name: "milk".to_string(),
+            ..ItemType::default()
+        });
+        let s = start();
+        add_removed(&mut inventory, s - DAY * 10, s - DAY, 1.5, None); // expired
+        add_removed(&mut inventory, s - DAY * 3, s - DAY, 0.5, None); // unspecified
+        add_removed(&mut inventory, s - DAY * 3, s - DAY, 0.0, Some("used")); // empty
+        add_removed(
+            &mut inventory,
+            s - DAY * 40,
+            s - DAY * 30,
+            1.0,
+            Some("broken"),
+        );
+        add_removed(&mut inventory, s - DAY * 80, s - DAY * 70, 2.0, None); // 3 months ago
+        inventory
+    }
+
+    #[test]
+    fn waste_is_summed_per_month_and_reason() {
+        let waste = kitchen().waste_by_month(start(), 2);
+        let summary = waste
+            .iter()
+            .map(|e| (e.month.as_str(), e.reason.as_str(), e.instances, e.quantity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("2020-08", "broken", 1, 1.0),
+                ("2020-09", EXPIRED, 1, 1.5),
+                ("2020-09", UNSPECIFIED, 1, 0.5),
+            ]
+        );
+        assert_eq!(waste[1].value, 1.5);
+
+        let all = kitchen().waste_by_month(start(), 6);
+        assert_eq!(all[0].month, "2020-07");
+        assert_eq!(all[0].reason, EXPIRED);
+        assert!(kitchen().waste_by_month(start() - DAY * 365, 6).is_empty());
+    }
+
+    #[test]
+    fn trashing_part_of_an_item_instance_wastes_only_that_part() {
+        let mut inventory = Inventory::default();
+        inventory.add_item_type(ItemType {
+            name: "salad".to_string(),
+            ..ItemType::default()
+        });
+        inventory.set_clock(Arc::new(FixedClock::new(start())));
+        let id = inventory
+            .add_item_instance(ItemInstance {
+                item_type: TypeId(1),
+                quantity: 4.0,
+                value: Some(8.0),
+                ..ItemInstance::default()
+            })
+            .unwrap();
+        let trashed = inventory
+            .trash_quantity_with_reason(id, 1.5, Some("broken"))
+            .unwrap();
+        assert_ne!(trashed, id);
+        let kept = inventory
+            .item_instances
+            .iter()
+            .find(|ii| ii.id == id)
+            .unwrap();
+        assert_eq!(kept.quantity, 2.5);
+        assert_eq!(kept.value, Some(5.0));
+        assert_eq!(kept.removed_at, None);
+        let waste = inventory.waste_by_month(start(), 1);
+        assert_eq!(waste.len(), 1);
+        assert_eq!(waste[0].reason, "broken");
+        assert_eq!((waste[0].instances, waste[0].quantity), (1, 1.5));
+        assert_eq!(waste[0].value, 3.0);
+
+        assert_eq!(
+            inventory.trash_quantity(id, 3.0),
+            Err(InventoryError::InvalidQuantity)
+        );
+        assert_eq!(
+            inventory.trash_quantity(id, f32::NAN),
+            Err(InventoryError::InvalidQuantity)
+        );
+        assert_eq!(
+            inventory.trash_quantity(trashed, 1.0),
+            Err(InventoryError::FinishedItemInstance)
+        );
+        assert_eq!(inventory.trash_quantity(id, 2.5), Ok(id));
+        assert_eq!(inventory.item_instances.len(), 2);
+        assert_eq!(inventory.waste_by_month(start(), 1)[0].quantity, 1.5);
+        assert_eq!(inventory.waste_by_month(start(), 1)[1].reason, UNSPECIFIED);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn old_records_have_no_reason() {
+        let json = r#"{"id": 1, "item_type": 1, "quantity": 1.0, "model": null, "serial": null,
+            "extra": null, "location": null, "value": null, "opened_at": null,
+            "expires_at": null, "added_at": null, "removed_at": null}"#;
+        let ii: crate::ItemInstance = serde_json::from_str(json).unwrap();
+        assert_eq!(ii.removal_reason, None);
+    }
+}
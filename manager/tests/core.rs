@@ -0,0 +1,46 @@
+//! The inventory without the optional features, as embedded in a web page.
+//!
+//! Run it alone with `cargo test -p inv_manager --no-default-features --test core`, the unit
+//! tests needing every feature.
+
+use inv_manager::{FixedClock, Inventory, ItemInstance, ItemType};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[test]
+fn inventory_works_without_the_optional_features() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let mut inventory = Inventory::default();
+    inventory.set_clock(Arc::new(FixedClock::new(now)));
+
+    let rice = inventory.add_item_type(ItemType {
+        name: "rice".to_string(),
+        minimum_quantity: 2.0,
+        unit: Some("kg".to_string()),
+        ..ItemType::default()
+    });
+    let bag = inventory
+        .add_item_instance(ItemInstance {
+            item_type: rice,
+            quantity: 500.0,
+            unit_override: Some("g".to_string()),
+            expires_at: Some(now + DAY * 30),
+            ..ItemInstance::default()
+        })
+        .unwrap();
+    assert_eq!(inventory.resolve_type("Rice"), Ok(rice));
+    assert_eq!(inventory.resolve_instance("rice-01"), Ok(bag));
+    assert_eq!(inventory.quantities_by_type()[&rice], 0.5);
+
+    let shortages = inventory.shortages();
+    assert_eq!(shortages.len(), 1);
+    assert_eq!(shortages[0].quantity, 0.5);
+
+    inventory.trash(bag);
+    let ii = &inventory.item_instances[0];
+    assert_eq!(ii.added_at, Some(now));
+    assert_eq!(ii.removed_at, Some(now));
+    assert_eq!(inventory.waste_by_month(now, 1)[0].month, "2020-09");
+}